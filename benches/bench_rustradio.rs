@@ -1,78 +1,139 @@
-#![feature(test)]
+/*! Benchmarks for core blocks.
+
+Run with `cargo bench`. Throughput is reported in elements per
+second, i.e. `Melem/s` is Msps of input.
+*/
+use criterion::measurement::WallTime;
+use criterion::{criterion_group, criterion_main, BenchmarkGroup, Criterion, Throughput};
 
-extern crate rustradio;
-extern crate test;
 use rustradio::block::Block;
 use rustradio::blocks::*;
-use rustradio::stream::new_streamp;
-use rustradio::Complex;
-
-use test::Bencher;
-
-#[bench]
-fn bench_fft_filter(b: &mut Bencher) {
-    let taps = rustradio::fir::low_pass_complex(1024000.0, 50000.0, 10000.0);
-    let input = {
-        let mut a = taps.clone();
-        a.resize(a.len() * 2, Complex::default());
-        a
-    };
-    let s = new_streamp();
-    let mut filter = FftFilter::new(s.clone(), &taps);
-    b.iter(|| {
-        // Empty input buffer.
-        {
-            let (i, _) = s.read_buf().unwrap();
-            let n = i.len();
-            i.consume(n);
-        }
-        // Fill input buffer.
-        {
-            let o = s.write_buf().unwrap();
-            //o.slice()[..input.len()].clone_from_slice(&input);
-            o.produce(input.len(), &[]);
-        }
-        // Empty output buffer.
-        {
-            let obind = filter.out();
-            let (out, _) = obind.read_buf().unwrap();
-            let n = out.len();
-            out.consume(n);
-        }
-        filter.work().unwrap();
+use rustradio::fir::{low_pass, low_pass_complex};
+use rustradio::stream::{new_streamp, Streamp};
+use rustradio::{Complex, Float};
+
+// Input samples per iteration.
+const N: usize = 16384;
+
+fn complex_input() -> Vec<Complex> {
+    (0..N)
+        .map(|n| Complex::from_polar(1.0, 0.1 * n as Float))
+        .collect()
+}
+
+fn float_input() -> Vec<Float> {
+    (0..N).map(|n| (0.1 * n as Float).sin()).collect()
+}
+
+// Benchmark a block with one input and one output, feeding it `input`
+// and throwing away the output on every iteration.
+fn bench_block<I: Copy, O: Copy>(
+    g: &mut BenchmarkGroup<WallTime>,
+    name: &str,
+    src: &Streamp<I>,
+    mut b: impl Block,
+    dst: Streamp<O>,
+    input: &[I],
+) {
+    g.throughput(Throughput::Elements(input.len() as u64));
+    g.bench_function(name, |bench| {
+        bench.iter(|| {
+            {
+                let mut o = src.write_buf().unwrap();
+                let n = std::cmp::min(o.len(), input.len());
+                o.fill_from_slice(&input[..n]);
+                o.produce(n, &[]);
+            }
+            b.work().unwrap();
+            let (o, _) = dst.read_buf().unwrap();
+            let n = o.len();
+            o.consume(n);
+        })
     });
 }
 
-#[bench]
-fn bench_fir_filter(b: &mut Bencher) {
-    let taps = rustradio::fir::low_pass_complex(1024000.0, 50000.0, 10000.0);
-    let input = {
-        let mut a = taps.clone();
-        a.resize(a.len() * 2, Complex::default());
-        a
-    };
-    let s = new_streamp();
-    let mut filter = FIRFilter::new(s.clone(), &taps);
-    b.iter(|| {
-        // Empty input buffer.
-        {
+fn filters(c: &mut Criterion) {
+    let mut g = c.benchmark_group("filter");
+    let samp_rate = 1_024_000.0;
+    let ctaps = low_pass_complex(samp_rate, 50_000.0, 10_000.0);
+    let ftaps = low_pass(samp_rate, 50_000.0, 10_000.0);
+    let cin = complex_input();
+    let fin = float_input();
+
+    let src = new_streamp();
+    let b = FIRFilter::new(src.clone(), &ctaps);
+    let dst = b.out();
+    bench_block(&mut g, "fir_complex", &src, b, dst, &cin);
+
+    let src = new_streamp();
+    let b = FIRFilter::new(src.clone(), &ftaps);
+    let dst = b.out();
+    bench_block(&mut g, "fir_float", &src, b, dst, &fin);
+
+    let src = new_streamp();
+    let b = FftFilter::new(src.clone(), &ctaps);
+    let dst = b.out();
+    bench_block(&mut g, "fft_complex", &src, b, dst, &cin);
+
+    let src = new_streamp();
+    let b = FftFilterFloat::new(src.clone(), &ftaps);
+    let dst = b.out();
+    bench_block(&mut g, "fft_float", &src, b, dst, &fin);
+    g.finish();
+}
+
+fn resamplers(c: &mut Criterion) {
+    let mut g = c.benchmark_group("resampler");
+    let fin = float_input();
+
+    let src = new_streamp();
+    let b = RationalResampler::new(src.clone(), 48_000, 50_000).unwrap();
+    let dst = b.out();
+    bench_block(&mut g, "rational", &src, b, dst, &fin);
+
+    let src = new_streamp();
+    let b = RationalResampler::filtered(src.clone(), 48_000, 50_000).unwrap();
+    let dst = b.out();
+    bench_block(&mut g, "rational_filtered", &src, b, dst, &fin);
+    g.finish();
+}
+
+fn demod(c: &mut Criterion) {
+    let mut g = c.benchmark_group("quadrature_demod");
+    let cin = complex_input();
+
+    let src = new_streamp();
+    let b = QuadratureDemod::new(src.clone(), 1.0);
+    let dst = b.out();
+    bench_block(&mut g, "atan2", &src, b, dst, &cin);
+
+    let src = new_streamp();
+    let b = QuadratureDemod::builder(src.clone(), 1.0)
+        .fast_atan2(true)
+        .build();
+    let dst = b.out();
+    bench_block(&mut g, "fast_atan2", &src, b, dst, &cin);
+    g.finish();
+}
+
+// Raw stream overhead: write and read back a chunk.
+fn stream(c: &mut Criterion) {
+    let mut g = c.benchmark_group("stream");
+    let cin = complex_input();
+    let s = new_streamp::<Complex>();
+    g.throughput(Throughput::Elements(N as u64));
+    g.bench_function("write_read", |bench| {
+        bench.iter(|| {
+            let mut o = s.write_buf().unwrap();
+            o.fill_from_slice(&cin);
+            o.produce(N, &[]);
             let (i, _) = s.read_buf().unwrap();
             let n = i.len();
             i.consume(n);
-        }
-        // Fill input buffer.
-        {
-            let o = s.write_buf().unwrap();
-            //o.slice()[..input.len()].clone_from_slice(&input);
-            o.produce(input.len(), &[]);
-        }
-        // Empty output buffer.
-        {
-            let obind = filter.out();
-            let (out, _) = obind.read_buf().unwrap();
-            let n = out.len();
-            out.consume(n);
-        }
-        filter.work().unwrap();
+        })
     });
+    g.finish();
 }
+
+criterion_group!(benches, filters, resamplers, demod, stream);
+criterion_main!(benches);
@@ -1,7 +1,8 @@
 /*! AX.25 1200bps Bell 202 receiver.
 
-Can be used to receive APRS over the air with RTL-SDR or from
-complex I/Q saved to a file.
+Can be used to receive APRS over the air with RTL-SDR, from complex
+I/Q saved to a file, or from the audio output of a radio connected to
+the sound card.
 
 ```no_run
 $ mkdir captured
@@ -9,8 +10,18 @@ $ ./ax25-1200-rx -r captured.c32 --samp_rate 50000 -o captured
 […]
 $ ./ax25-1200-rx --rtlsdr -o captured -v 2
 […]
+$ ./ax25-1200-rx -r captured.c32 --samp_rate 50000 --pcap aprs.pcap
+$ wireshark aprs.pcap
+$ ./ax25-1200-rx --rtlsdr --sample_rate 1000000 --aprs-is rotate.aprs2.net:14580 --callsign N0CALL-10
+$ ./ax25-1200-rx --soundcard --sample_rate 48000 -o captured
 ```
 
+With `--aprs-is` this is a complete receive-only igate.
+
+When receiving I/Q, each decoded frame is annotated with its signal
+level, SNR, and frequency offset, measured before the FM demodulator.
+They're printed with the frames, and sent with `--udp-json`.
+
 Test recordings for this code are at
 <http://wa8lmf.net/TNCtest/index.htm>. Note that track 2 should not
 be used, as it's incorrectly de-emphasized.
@@ -40,8 +51,10 @@ use structopt::StructOpt;
 
 use rustradio::blocks::*;
 use rustradio::graph::Graph;
+use rustradio::rx_meta::MeterHandle;
 use rustradio::stream::Streamp;
 use rustradio::Error;
+use rustradio::{add_block, flowgraph};
 use rustradio::{Complex, Float};
 
 #[derive(StructOpt, Debug)]
@@ -53,6 +66,36 @@ struct Opt {
     #[structopt(long = "out", short = "o", help = "Directory to write packets to")]
     output: Option<PathBuf>,
 
+    #[structopt(long = "pcap", help = "Write packets to pcap file")]
+    pcap: Option<PathBuf>,
+
+    #[structopt(long = "aprs", help = "Print packets as parsed APRS")]
+    aprs: bool,
+
+    #[structopt(long = "udp", help = "Send packets as KISS over UDP to host:port")]
+    udp: Option<String>,
+
+    #[structopt(
+        long = "udp-json",
+        help = "Send packets as JSON, with metadata, over UDP"
+    )]
+    udp_json: bool,
+
+    #[structopt(
+        long = "aprs-is",
+        help = "Igate to APRS-IS server, e.g. rotate.aprs2.net:14580"
+    )]
+    aprs_is: Option<String>,
+
+    #[structopt(long = "callsign", help = "Igate callsign, required for APRS-IS")]
+    callsign: Option<String>,
+
+    #[structopt(long = "passcode", help = "APRS-IS passcode, default calculated")]
+    passcode: Option<u16>,
+
+    #[structopt(long = "aprs-filter", help = "APRS-IS server side filter")]
+    aprs_filter: Option<String>,
+
     #[cfg(feature = "rtlsdr")]
     #[structopt(long = "freq", default_value = "144800000")]
     freq: u64,
@@ -70,6 +113,12 @@ struct Opt {
     #[structopt(long = "rtlsdr", help = "Stream I/Q from an RTLSDR")]
     rtlsdr: bool,
 
+    #[structopt(long = "soundcard", help = "Record audio from the sound card")]
+    soundcard: bool,
+
+    #[structopt(long = "soundcard-device", help = "ALSA device to record from")]
+    soundcard_device: Option<String>,
+
     #[structopt(long = "clock-file", help = "File to write clock sync data to")]
     clock_file: Option<PathBuf>,
 
@@ -89,16 +138,7 @@ struct Opt {
     symbol_max_deviation: Float,
 }
 
-macro_rules! add_block {
-    ($g:ident, $cons:expr) => {{
-        let block = Box::new($cons);
-        let prev = block.out();
-        $g.add(block);
-        prev
-    }};
-}
-
-fn get_complex_input(g: &mut Graph, opt: &Opt) -> Result<(Streamp<Complex>, f32)> {
+fn get_complex_input(g: &mut Graph, opt: &Opt) -> Result<(Streamp<Complex>, Float)> {
     if let Some(ref read) = opt.read {
         let mut b = SigMFSourceBuilder::new(read.clone());
         if let Some(s) = opt.samp_rate {
@@ -109,7 +149,7 @@ fn get_complex_input(g: &mut Graph, opt: &Opt) -> Result<(Streamp<Complex>, f32)
             .sample_rate()
             .ok_or(Error::new("SigMF file does not specify sample rate"))?;
         let prev = add_block![g, b];
-        return Ok((prev, samp_rate as f32));
+        return Ok((prev, samp_rate as Float));
     }
 
     if opt.rtlsdr {
@@ -122,7 +162,7 @@ fn get_complex_input(g: &mut Graph, opt: &Opt) -> Result<(Streamp<Complex>, f32)
 
             // Decode.
             let prev = add_block![g, RtlSdrDecode::new(prev)];
-            return Ok((prev, samp as f32));
+            return Ok((prev, samp as Float));
         }
         #[cfg(not(feature = "rtlsdr"))]
         panic!("rtlsdr feature not enabled");
@@ -130,29 +170,45 @@ fn get_complex_input(g: &mut Graph, opt: &Opt) -> Result<(Streamp<Complex>, f32)
     panic!("not read, not rtlsdr");
 }
 
-fn get_input(g: &mut Graph, opt: &Opt) -> Result<(Streamp<Float>, f32)> {
+fn get_input(g: &mut Graph, opt: &Opt) -> Result<(Streamp<Float>, Float, Option<MeterHandle>)> {
+    if opt.soundcard {
+        let mut b = AudioSource::builder(opt.samp_rate.unwrap_or(48000));
+        if let Some(dev) = &opt.soundcard_device {
+            b = b.device(dev);
+        }
+        let src = b.build()?;
+        let samp_rate = src.sample_rate() as Float;
+        let prev = add_block![g, src];
+        return Ok((prev, samp_rate, None));
+    }
     if opt.audio {
         if let Some(ref read) = &opt.read {
-            let prev = add_block![g, FileSource::new(&read, false)?];
+            let prev = add_block![g, FileSource::new(read, false)?];
             let prev = add_block![g, AuDecode::new(prev)];
             // TODO: AuDecode should be providing the bitrate.
             return Ok((
                 prev,
                 opt.samp_rate.ok_or(Error::new(
                     "audio input requires providing a sample rate, for now",
-                ))? as f32,
+                ))? as Float,
+                None,
             ));
         }
         panic!("Audio can only be read from file");
     }
 
-    let (prev, samp_rate) = get_complex_input(g, &opt)?;
+    let (prev, samp_rate) = get_complex_input(g, opt)?;
+
+    // Measure the signal, for annotating decoded frames.
+    let meter = SignalMeter::new(prev, samp_rate);
+    let handle = meter.handle();
+    let prev = add_block![g, meter];
+
     let taps = rustradio::fir::low_pass_complex(samp_rate, 20_000.0, 100.0);
-    let prev = add_block![g, FftFilter::new(prev, &taps)];
     let new_samp_rate = 50_000.0;
-    let prev = add_block![
-        g,
-        RationalResampler::new(prev, new_samp_rate as usize, samp_rate as usize)?
+    let prev = flowgraph![g, prev =>
+        FftFilter::new(prev, &taps),
+        RationalResampler::new(prev, new_samp_rate as usize, samp_rate as usize)?,
     ];
     let samp_rate = new_samp_rate;
     let prev = if opt.fast_fm {
@@ -161,7 +217,7 @@ fn get_input(g: &mut Graph, opt: &Opt) -> Result<(Streamp<Float>, f32)> {
     } else {
         add_block![g, QuadratureDemod::new(prev, 1.0)]
     };
-    Ok((prev, samp_rate))
+    Ok((prev, samp_rate, Some(handle)))
 }
 
 fn main() -> Result<()> {
@@ -176,22 +232,18 @@ fn main() -> Result<()> {
 
     let mut g = Graph::new();
 
-    let (prev, samp_rate) = get_input(&mut g, &opt)?;
-    let prev = add_block![g, Hilbert::new(prev, 65)];
-
-    // Can't use FastFM here, because it doesn't work well with
-    // preemph'd input.
-    let prev = add_block![g, QuadratureDemod::new(prev, 1.0)];
-
+    let (prev, samp_rate, meter) = get_input(&mut g, &opt)?;
     let taps = rustradio::fir::low_pass(samp_rate, 1100.0, 100.0);
-    let prev = add_block![g, FftFilterFloat::new(prev, &taps)];
-
     let freq1 = 1200.0;
     let freq2 = 2200.0;
     let center_freq = freq1 + (freq2 - freq1) / 2.0;
-    let prev = add_block![
-        g,
-        add_const(prev, -center_freq * 2.0 * std::f32::consts::PI / samp_rate)
+    let prev = flowgraph![g, prev =>
+        Hilbert::new(prev, 65),
+        // Can't use FastFM here, because it doesn't work well with
+        // preemph'd input.
+        QuadratureDemod::new(prev, 1.0),
+        FftFilterFloat::new(prev, &taps),
+        add_const(prev, -center_freq * 2.0 * std::f64::consts::PI as Float / samp_rate),
     ];
 
     /*
@@ -234,10 +286,11 @@ fn main() -> Result<()> {
     };
     g.add(Box::new(block));
 
-    let prev = add_block![g, BinarySlicer::new(prev)];
-
-    // Delay xor, aka NRZI decode.
-    let prev = add_block![g, NrziDecode::new(prev)];
+    let prev = flowgraph![g, prev =>
+        BinarySlicer::new(prev),
+        // Delay xor, aka NRZI decode.
+        NrziDecode::new(prev),
+    ];
 
     // Save bits to file.
     /*
@@ -249,11 +302,54 @@ fn main() -> Result<()> {
     )?));
      */
 
-    let mut hdlc = HdlcDeframer::new(prev, 10, 1500);
-    hdlc.set_fix_bits(opt.fix_bits);
-    let prev = add_block![g, hdlc];
-    if let Some(o) = opt.output {
+    let prev = add_block![
+        g,
+        HdlcDeframer::builder(prev).fix_bits(opt.fix_bits).build()
+    ];
+    let prev = add_block![g, ToPdu::new(prev)];
+    let prev = match meter {
+        Some(meter) => add_block![g, AnnotatePdu::new(prev, meter)],
+        None => prev,
+    };
+    if let Some(server) = opt.aprs_is {
+        let call = opt
+            .callsign
+            .ok_or(Error::new("APRS-IS requires a callsign"))?;
+        let mut dedup = PduFilter::new(prev);
+        dedup.set_dedup_window(Some(std::time::Duration::from_secs(30)));
+        dedup.set_key(Box::new(|pdu| {
+            rustradio::ax25::Frame::parse(pdu.data())
+                .map(|f| f.dedup_key())
+                .unwrap_or_else(|| pdu.data().to_vec())
+        }));
+        let prev = add_block![g, dedup];
+        g.add(Box::new(AprsIsSink::new(
+            prev,
+            &server,
+            &call,
+            opt.passcode,
+            opt.aprs_filter.as_deref(),
+        )?));
+    } else if let Some(o) = opt.udp {
+        let format = if opt.udp_json {
+            rustradio::udp_sink::Format::Json
+        } else {
+            rustradio::udp_sink::Format::Kiss(0)
+        };
+        g.add(Box::new(UdpSink::new(prev, o, format)?));
+    } else if let Some(o) = opt.pcap {
+        g.add(Box::new(PcapSink::new(
+            prev,
+            o,
+            rustradio::pcap_sink::LinkType::Ax25,
+        )?));
+    } else if let Some(o) = opt.output {
+        let prev = add_block![g, PduData::new(prev)];
         g.add(Box::new(PduWriter::new(prev, o)));
+    } else if opt.aprs {
+        let prev = add_block![g, PduData::new(prev)];
+        let prev = add_block![g, AprsDecode::new(prev)];
+        g.add(Box::new(DebugSinkNoCopy::new(prev)));
     } else {
         g.add(Box::new(DebugSinkNoCopy::new(prev)));
     }
@@ -0,0 +1,169 @@
+/*! AX.25 1200bps Bell 202 transmitter.
+
+Reads one payload per line, from a file or stdin, and sends each as an
+AX.25 UI frame, e.g. APRS. Output is audio, for the microphone input
+of a radio, or FM modulated complex I/Q for an SDR.
+
+```no_run
+$ echo '>Hello world' | ./ax25-1200-tx --src N0CALL-9 --soundcard
+$ ./ax25-1200-tx --src N0CALL-9 -r beacons.txt -o packets.au
+$ ./ax25-1200-tx --src N0CALL-9 -r beacons.txt --iq -o packets.sigmf
+```
+
+The output of `-o` can be decoded with `ax25-1200-rx`, either as audio
+(`--audio --sample_rate 48000`), or as I/Q. Its filters need some
+signal after the last frame, so use `--txtail 32` for that.
+*/
+use std::io::BufRead;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::add_block;
+use rustradio::ax25::Frame;
+use rustradio::blocks::*;
+use rustradio::file_sink::Mode;
+use rustradio::graph::Graph;
+use rustradio::stream::new_nocopy_streamp;
+use rustradio::{Error, Float};
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(long = "src", help = "Source callsign, e.g. N0CALL-9")]
+    src: String,
+
+    #[structopt(long = "dst", default_value = "APRS", help = "Destination")]
+    dst: String,
+
+    #[structopt(
+        long = "path",
+        default_value = "WIDE1-1,WIDE2-1",
+        use_delimiter = true,
+        help = "Digipeater path"
+    )]
+    path: Vec<String>,
+
+    #[structopt(short = "r", help = "Read payloads from file, one per line")]
+    read: Option<PathBuf>,
+
+    #[structopt(short = "o", help = "Write to file, .au audio or SigMF I/Q")]
+    output: Option<PathBuf>,
+
+    #[structopt(long = "iq", help = "Output FM modulated I/Q, not audio")]
+    iq: bool,
+
+    #[structopt(
+        long = "deviation",
+        default_value = "3000",
+        help = "FM deviation, in Hz"
+    )]
+    deviation: Float,
+
+    #[structopt(long = "soundcard", help = "Play audio on the sound card")]
+    soundcard: bool,
+
+    #[structopt(long = "soundcard-device", help = "ALSA device to play on")]
+    soundcard_device: Option<String>,
+
+    #[structopt(long = "sample_rate", default_value = "48000")]
+    samp_rate: u32,
+
+    #[structopt(
+        long = "txdelay",
+        default_value = "32",
+        help = "Flags before each frame"
+    )]
+    txdelay: usize,
+
+    #[structopt(long = "txtail", default_value = "8", help = "Flags after each frame")]
+    txtail: usize,
+
+    #[structopt(long = "volume", default_value = "0.5")]
+    volume: Float,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    // Read all payloads up front, so that the graph finishes once
+    // they're sent.
+    let input: Box<dyn BufRead> = match &opt.read {
+        Some(f) => Box::new(std::io::BufReader::new(std::fs::File::open(f)?)),
+        None => Box::new(std::io::stdin().lock()),
+    };
+    let path: Vec<&str> = opt.path.iter().map(|s| s.as_str()).collect();
+    let frames = new_nocopy_streamp();
+    for line in input.lines() {
+        let line = line?;
+        let frame = Frame::ui(&opt.src, &opt.dst, &path, line.as_bytes())
+            .ok_or(Error::new("invalid callsign or path"))?;
+        eprintln!("Sending {}", frame.to_tnc2());
+        frames.push(frame.encode(), &[]);
+    }
+
+    let mut g = Graph::new();
+    let samp_rate = opt.samp_rate as Float;
+    let prev = add_block![
+        g,
+        HdlcFramer::builder(frames)
+            .preamble(opt.txdelay)
+            .postamble(opt.txtail)
+            .build()
+    ];
+    let prev = add_block![g, NrziEncode::new(prev)];
+    // Full deviation for I/Q, regardless of volume.
+    let amplitude = if opt.iq { 1.0 } else { opt.volume };
+    let prev = add_block![
+        g,
+        AfskModulator::builder(prev, samp_rate)
+            .amplitude(amplitude)
+            .build()?
+    ];
+
+    if opt.iq {
+        let output = opt.output.ok_or(Error::new("I/Q output requires -o"))?;
+        let sensitivity = 2.0 * std::f64::consts::PI as Float * opt.deviation / samp_rate;
+        let prev = add_block![g, FrequencyModulator::new(prev, sensitivity)];
+        g.add(Box::new(
+            SigMFSinkBuilder::new(prev, &output.to_string_lossy())
+                .sample_rate(samp_rate as f64)
+                .description("AX.25 1200bps Bell 202")
+                .build()?,
+        ));
+    } else if let Some(output) = opt.output {
+        let prev = add_block![
+            g,
+            AuEncode::new(prev, rustradio::au::Encoding::PCM16, opt.samp_rate, 1)
+        ];
+        g.add(Box::new(FileSink::new(prev, output, Mode::Overwrite)?));
+    } else if opt.soundcard {
+        let mut b = AudioSink::builder(prev, samp_rate);
+        if let Some(dev) = &opt.soundcard_device {
+            b = b.device(dev);
+        }
+        g.add(Box::new(b.build()?));
+    } else {
+        return Err(Error::new("need -o or --soundcard").into());
+    }
+
+    let cancel = g.cancel_token();
+    ctrlc::set_handler(move || {
+        eprintln!("Received Ctrl+C!");
+        cancel.cancel();
+    })
+    .expect("Error setting Ctrl-C handler");
+    g.run()?;
+    Ok(())
+}
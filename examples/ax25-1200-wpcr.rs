@@ -14,6 +14,7 @@ use std::path::PathBuf;
 use anyhow::Result;
 use structopt::StructOpt;
 
+use rustradio::add_block;
 use rustradio::blocks::*;
 use rustradio::graph::Graph;
 use rustradio::{Error, Float};
@@ -40,15 +41,6 @@ struct Opt {
     iir_alpha: Float,
 }
 
-macro_rules! add_block {
-    ($g:ident, $cons:expr) => {{
-        let block = Box::new($cons);
-        let prev = block.out();
-        $g.add(block);
-        prev
-    }};
-}
-
 fn main() -> Result<()> {
     let opt = Opt::from_args();
     stderrlog::new()
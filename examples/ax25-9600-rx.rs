@@ -18,6 +18,7 @@ use std::path::PathBuf;
 use anyhow::Result;
 use structopt::StructOpt;
 
+use rustradio::add_block;
 use rustradio::blocks::*;
 use rustradio::graph::Graph;
 use rustradio::{Complex, Float};
@@ -65,15 +66,6 @@ struct Opt {
     symbol_max_deviation: Float,
 }
 
-macro_rules! add_block {
-    ($g:ident, $cons:expr) => {{
-        let block = Box::new($cons);
-        let prev = block.out();
-        $g.add(block);
-        prev
-    }};
-}
-
 fn main() -> Result<()> {
     let opt = Opt::from_args();
     stderrlog::new()
@@ -0,0 +1,76 @@
+/*! Bluetooth LE advertisement receiver.
+
+Decodes BLE advertising packets from a SigMF I/Q recording of an
+advertising channel, at least 2Msps.
+
+```no_run
+$ ./ble-rx -r 2402M.sigmf-data --channel 37
+```
+*/
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::add_block;
+use rustradio::blocks::*;
+use rustradio::graph::Graph;
+use rustradio::{Error, Float};
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(short = "r", help = "Read I/Q from SigMF file")]
+    read: String,
+
+    #[structopt(long = "sample_rate", help = "Override sample rate")]
+    samp_rate: Option<u32>,
+
+    #[structopt(long = "channel", default_value = "37", help = "BLE channel")]
+    channel: u8,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let mut g = Graph::new();
+
+    let mut b = SigMFSourceBuilder::new(opt.read.clone());
+    if let Some(s) = opt.samp_rate {
+        b = b.sample_rate(s as f64);
+    }
+    let b = b.build()?;
+    let samp_rate = b
+        .sample_rate()
+        .ok_or(Error::new("SigMF file does not specify sample rate"))? as Float;
+    let prev = add_block![g, b];
+
+    // LE 1M PHY is 1Mbps with 250kHz deviation.
+    let baud = 1_000_000.0;
+    let taps = rustradio::fir::low_pass_complex(samp_rate, 750_000.0, 100_000.0);
+    let prev = add_block![g, FftFilter::new(prev, &taps)];
+    let prev = add_block![g, QuadratureDemod::new(prev, 1.0)];
+    let prev = add_block![g, ZeroCrossing::new(prev, samp_rate / baud, 0.1)];
+    let prev = add_block![g, BinarySlicer::new(prev)];
+
+    let prev = add_block![g, BleDecode::new(prev, opt.channel)?];
+    g.add(Box::new(DebugSinkNoCopy::new(prev)));
+
+    let cancel = g.cancel_token();
+    ctrlc::set_handler(move || {
+        eprintln!("Received Ctrl+C!");
+        cancel.cancel();
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    g.run()?;
+    Ok(())
+}
@@ -0,0 +1,67 @@
+/*! DTMF decoder.
+
+Decodes DTMF keys from an .au audio recording, e.g. of a repeater
+input, and prints them.
+
+```no_run
+$ ./dtmf -r keys.au --sample_rate 8000
+```
+*/
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::blocks::*;
+use rustradio::graph::Graph;
+use rustradio::node::Node;
+use rustradio::Float;
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(short = "r", help = "Read audio from .au file")]
+    read: String,
+
+    #[structopt(long = "sample_rate", default_value = "8000")]
+    samp_rate: Float,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let mut g = Graph::new();
+
+    let src = g.add(Node::source(
+        FileSource::new(&opt.read, false)?,
+        FileSource::out,
+    ));
+    let au = g.add(Node::block(AuDecode::new, AuDecode::out));
+    let samp_rate = opt.samp_rate;
+    let dtmf = g.add(Node::block(
+        move |src| DtmfDecode::new(src, samp_rate),
+        DtmfDecode::out,
+    ));
+    let sink = g.add(Node::sink(DebugSinkNoCopy::new));
+    g.connect(src.output, au.input)?;
+    g.connect(au.output, dtmf.input)?;
+    g.connect(dtmf.output, sink.input)?;
+
+    let cancel = g.cancel_token();
+    ctrlc::set_handler(move || {
+        eprintln!("Received Ctrl+C!");
+        cancel.cancel();
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    g.run()?;
+    Ok(())
+}
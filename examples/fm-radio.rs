@@ -0,0 +1,156 @@
+/*!
+Broadcast FM receiver, playing on the sound card.
+
+```text
+RTL-SDR -> FreqXlatingFir -> WbfmDecode -> volume -> AudioSink
+                                  |
+                                  +-> RdsDecode -> print
+```
+
+The SDR is tuned `--offset` away from the station, to keep the DC spike
+out of the audio.
+ */
+use anyhow::Result;
+use log::warn;
+use structopt::StructOpt;
+
+use rustradio::add_block;
+use rustradio::blocks::*;
+use rustradio::file_sink::Mode;
+use rustradio::graph::Graph;
+use rustradio::wbfm::{TAU_EU, TAU_US};
+use rustradio::{Complex, Float};
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    /// Read I/Q from file, at 1.14MHz, instead of an RTL-SDR.
+    #[structopt(short = "r")]
+    filename: Option<String>,
+
+    /// Write to Au file, instead of playing.
+    #[structopt(short = "o")]
+    output: Option<std::path::PathBuf>,
+
+    /// Station frequency.
+    // Unused if rtlsdr feature not enabled.
+    #[allow(dead_code)]
+    #[structopt(long = "freq", default_value = "100000000")]
+    freq: u64,
+
+    /// Tune this far from the station.
+    #[structopt(long = "offset", default_value = "250000")]
+    offset: Float,
+
+    // Unused if rtlsdr feature not enabled.
+    #[allow(dead_code)]
+    #[structopt(long = "gain", default_value = "20")]
+    gain: i32,
+
+    /// Use the European 50µs de-emphasis, instead of 75µs.
+    #[structopt(long = "eu")]
+    eu: bool,
+
+    /// Decode and print RDS station data.
+    #[structopt(long = "rds")]
+    rds: bool,
+
+    /// ALSA audio device.
+    #[structopt(long = "audio-device")]
+    audio_device: Option<String>,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+
+    #[structopt(long = "volume", default_value = "0.5")]
+    volume: Float,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let mut g = Graph::new();
+    let samp_rate = 1_140_000.0;
+
+    let prev = if let Some(filename) = opt.filename {
+        add_block!(g, FileSource::<Complex>::new(&filename, false)?)
+    } else if !cfg!(feature = "rtlsdr") {
+        panic!("RTL SDR feature not enabled")
+    } else {
+        // RTL SDR source.
+        #[cfg(feature = "rtlsdr")]
+        {
+            let tune = (opt.freq as Float - opt.offset) as u64;
+            let src = Box::new(RtlSdrSource::new(tune, samp_rate as u32, opt.gain)?);
+            let dec = Box::new(RtlSdrDecode::new(src.out()));
+            let prev = dec.out();
+            g.add(src);
+            g.add(dec);
+            prev
+        }
+        #[cfg(not(feature = "rtlsdr"))]
+        panic!("can't happen, but must be here to compile")
+    };
+
+    // Select the station, and decimate to 228kHz, a multiple of the
+    // 19kHz pilot as RDS needs.
+    let decim = 5;
+    let taps = rustradio::fir::low_pass(samp_rate, 100_000.0, 20_000.0);
+    let prev = add_block![
+        g,
+        FreqXlatingFir::new(prev, &taps, opt.offset, samp_rate, decim)?
+    ];
+    let samp_rate = samp_rate / decim as Float;
+
+    // Demodulate to 45.6kHz audio.
+    let tau = if opt.eu { TAU_EU } else { TAU_US };
+    let mut fm = WbfmDecode::builder(prev, samp_rate)
+        .audio_decim(5)
+        .tau(tau)
+        .build()?;
+    let audio_rate = fm.audio_rate();
+    if opt.rds {
+        let rds = Box::new(RdsDecode::new(fm.out_mpx(), samp_rate)?);
+        g.add(Box::new(CallbackSink::new(rds.out(), |s| {
+            eprintln!("\n{s}")
+        })));
+        g.add(rds);
+    }
+    let prev = add_block![g, fm];
+
+    // Change volume.
+    let prev = add_block![g, MultiplyConst::new(prev, opt.volume)];
+
+    if let Some(output) = opt.output {
+        let prev = add_block![
+            g,
+            AuEncode::new(prev, rustradio::au::Encoding::PCM16, audio_rate as u32, 1)
+        ];
+        g.add(Box::new(FileSink::new(prev, output, Mode::Overwrite)?));
+    } else {
+        let mut sink = AudioSink::builder(prev, audio_rate);
+        if let Some(dev) = &opt.audio_device {
+            sink = sink.device(dev);
+        }
+        g.add(Box::new(sink.build()?));
+    }
+
+    let cancel = g.cancel_token();
+    ctrlc::set_handler(move || {
+        warn!("Got Ctrl-C");
+        eprintln!("\n");
+        cancel.cancel();
+    })
+    .expect("failed to set Ctrl-C handler");
+    let st = std::time::Instant::now();
+    g.run()?;
+    eprintln!("{}", g.generate_stats(st.elapsed()));
+    Ok(())
+}
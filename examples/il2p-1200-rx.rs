@@ -12,6 +12,7 @@ use std::path::PathBuf;
 use anyhow::Result;
 use structopt::StructOpt;
 
+use rustradio::add_block;
 use rustradio::blocks::*;
 use rustradio::graph::Graph;
 use rustradio::{Complex, Float};
@@ -38,15 +39,6 @@ struct Opt {
     symbol_max_deviation: Float,
 }
 
-macro_rules! add_block {
-    ($g:ident, $cons:expr) => {{
-        let block = Box::new($cons);
-        let prev = block.out();
-        $g.add(block);
-        prev
-    }};
-}
-
 fn main() -> Result<()> {
     let opt = Opt::from_args();
     stderrlog::new()
@@ -90,7 +82,10 @@ fn main() -> Result<()> {
     let center_freq = freq1 + (freq2 - freq1) / 2.0;
     let prev = add_block![
         g,
-        AddConst::new(prev, -center_freq * 2.0 * std::f32::consts::PI / samp_rate)
+        AddConst::new(
+            prev,
+            -center_freq * 2.0 * std::f64::consts::PI as Float / samp_rate
+        )
     ];
 
     /*
@@ -123,7 +118,7 @@ fn main() -> Result<()> {
         g,
         CorrelateAccessCodeTag::new(
             prev,
-            (&rustradio::il2p_deframer::SYNC_WORD).to_vec(),
+            rustradio::il2p_deframer::SYNC_WORD.to_vec(),
             "sync".into(),
             0,
         )
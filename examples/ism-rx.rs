@@ -0,0 +1,98 @@
+/*! ISM band sensor receiver.
+
+Decodes weather sensors, doorbells, and similar, from a SigMF I/Q
+recording centered on the signal, printing one JSON object per
+message.
+
+```no_run
+$ ./ism-rx -r 433.92M.sigmf-data
+$ ./ism-rx -r 868.3M.sigmf-data --fsk
+```
+*/
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::add_block;
+use rustradio::block::{Block, BlockRet};
+use rustradio::blocks::*;
+use rustradio::graph::Graph;
+use rustradio::ism::{IsmMessage, Modulation};
+use rustradio::stream::NoCopyStreamp;
+use rustradio::{Error, Float};
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(short = "r", help = "Read I/Q from SigMF file")]
+    read: String,
+
+    #[structopt(long = "sample_rate", help = "Override sample rate")]
+    samp_rate: Option<u32>,
+
+    #[structopt(long = "fsk", help = "Decode FSK instead of OOK")]
+    fsk: bool,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+struct JsonSink {
+    src: NoCopyStreamp<IsmMessage>,
+}
+
+impl Block for JsonSink {
+    fn block_name(&self) -> &str {
+        "JsonSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        match self.src.pop() {
+            None => Ok(BlockRet::Noop),
+            Some((m, _tags)) => {
+                println!("{m}");
+                Ok(BlockRet::Ok)
+            }
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let mut g = Graph::new();
+
+    let mut b = SigMFSourceBuilder::new(opt.read.clone());
+    if let Some(s) = opt.samp_rate {
+        b = b.sample_rate(s as f64);
+    }
+    let b = b.build()?;
+    let samp_rate = b
+        .sample_rate()
+        .ok_or(Error::new("SigMF file does not specify sample rate"))? as Float;
+    let prev = add_block![g, b];
+
+    let modulation = if opt.fsk {
+        Modulation::Fsk
+    } else {
+        Modulation::Ook
+    };
+    let prev = add_block![g, PulseSlicer::new(prev, samp_rate, modulation)];
+    let prev = add_block![g, IsmDecode::new(prev)];
+    g.add(Box::new(JsonSink { src: prev }));
+
+    let cancel = g.cancel_token();
+    ctrlc::set_handler(move || {
+        eprintln!("Received Ctrl+C!");
+        cancel.cancel();
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    g.run()?;
+    Ok(())
+}
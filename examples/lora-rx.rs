@@ -0,0 +1,90 @@
+/*! LoRa receiver.
+
+Decodes LoRa frames from a SigMF I/Q recording centered on the LoRa
+channel.
+
+```no_run
+$ ./lora-rx -r lora.sigmf-data --sf 7
+$ ./lora-rx -r lora.sigmf-data --sf 12 --ldro
+```
+*/
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::add_block;
+use rustradio::blocks::*;
+use rustradio::graph::Graph;
+use rustradio::{Error, Float};
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(short = "r", help = "Read I/Q from SigMF file")]
+    read: String,
+
+    #[structopt(long = "sample_rate", help = "Override sample rate")]
+    samp_rate: Option<u32>,
+
+    #[structopt(long = "bw", default_value = "125000", help = "LoRa bandwidth")]
+    bandwidth: u32,
+
+    #[structopt(long = "sf", default_value = "7", help = "Spreading factor")]
+    sf: u8,
+
+    #[structopt(long = "ldro", help = "Low data rate optimization")]
+    ldro: bool,
+
+    #[structopt(long = "sync_word", default_value = "18")]
+    sync_word: u8,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let mut g = Graph::new();
+
+    let mut b = SigMFSourceBuilder::new(opt.read.clone());
+    if let Some(s) = opt.samp_rate {
+        b = b.sample_rate(s as f64);
+    }
+    let b = b.build()?;
+    let samp_rate = b
+        .sample_rate()
+        .ok_or(Error::new("SigMF file does not specify sample rate"))? as Float;
+    let prev = add_block![g, b];
+
+    // Resample to one sample per chip.
+    let bw = opt.bandwidth as Float;
+    let taps = rustradio::fir::low_pass_complex(samp_rate, bw / 2.0, bw / 20.0);
+    let prev = add_block![g, FftFilter::new(prev, &taps)];
+    let prev = add_block![
+        g,
+        RationalResampler::new(prev, opt.bandwidth as usize, samp_rate as usize)?
+    ];
+
+    let mut lora = LoraDecode::new(prev, opt.sf)?;
+    lora.set_ldro(opt.ldro);
+    lora.set_sync_word(opt.sync_word);
+    let prev = add_block![g, lora];
+    g.add(Box::new(DebugSinkNoCopy::new(prev)));
+
+    let cancel = g.cancel_token();
+    ctrlc::set_handler(move || {
+        eprintln!("Received Ctrl+C!");
+        cancel.cancel();
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    g.run()?;
+    Ok(())
+}
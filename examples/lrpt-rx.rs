@@ -0,0 +1,97 @@
+/*! Meteor-M LRPT receiver.
+
+Decodes images from a SigMF I/Q recording centered on the LRPT signal,
+and writes one PNG per MSU-MR channel.
+
+```no_run
+$ mkdir images
+$ ./lrpt-rx -r meteor.sigmf-data -o images
+$ ./lrpt-rx -r meteor-m2-3.sigmf-data -o images --80k
+```
+*/
+use std::path::PathBuf;
+
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::add_block;
+use rustradio::blocks::*;
+use rustradio::graph::Graph;
+use rustradio::lrpt;
+use rustradio::{Error, Float};
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(short = "r", help = "Read I/Q from SigMF file")]
+    read: String,
+
+    #[structopt(short = "o", help = "Directory to write images to")]
+    output: PathBuf,
+
+    #[structopt(long = "sample_rate", help = "Override sample rate")]
+    samp_rate: Option<u32>,
+
+    #[structopt(long = "80k", help = "80k symbols per second interleaved mode")]
+    mode_80k: bool,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let mut g = Graph::new();
+
+    let mut b = SigMFSourceBuilder::new(opt.read.clone());
+    if let Some(s) = opt.samp_rate {
+        b = b.sample_rate(s as f64);
+    }
+    let b = b.build()?;
+    let samp_rate = b
+        .sample_rate()
+        .ok_or(Error::new("SigMF file does not specify sample rate"))? as Float;
+    let prev = add_block![g, b];
+
+    // Resample to 2 samples per symbol.
+    let symbol_rate = if opt.mode_80k {
+        lrpt::SYMBOL_RATE_80K
+    } else {
+        lrpt::SYMBOL_RATE
+    };
+    let new_samp_rate = 2.0 * symbol_rate;
+    let taps = rustradio::fir::low_pass_complex(samp_rate, symbol_rate, 10_000.0);
+    let prev = add_block![g, FftFilter::new(prev, &taps)];
+    let prev = add_block![
+        g,
+        RationalResampler::new(prev, new_samp_rate as usize, samp_rate as usize)?
+    ];
+
+    let prev = add_block![g, LrptDemod::new(prev, new_samp_rate, symbol_rate)?];
+    let prev = if opt.mode_80k {
+        add_block![g, LrptDeinterleave::new(prev)]
+    } else {
+        prev
+    };
+    let prev = add_block![g, LrptDecode::new(prev)];
+    let prev = add_block![g, CcsdsPacketizer::new(prev, lrpt::IMAGE_VCID)];
+    g.add(Box::new(LrptImageWriter::new(prev, opt.output)));
+
+    let cancel = g.cancel_token();
+    ctrlc::set_handler(move || {
+        eprintln!("Received Ctrl+C!");
+        cancel.cancel();
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    g.run()?;
+    Ok(())
+}
@@ -0,0 +1,113 @@
+/*! PSK31 receiver and transmitter.
+
+Receive from an .au audio file, e.g. recorded from an SSB receiver:
+
+```no_run
+$ ./psk31 -r recording.au --freq 1000
+```
+
+Or generate an .au file to feed to an SSB transmitter:
+
+```no_run
+$ ./psk31 --tx "CQ CQ CQ de SM0ABC SM0ABC pse k" -o tx.au --freq 1000
+```
+*/
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::add_block;
+use rustradio::blocks::*;
+use rustradio::graph::Graph;
+use rustradio::{Complex, Float};
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(short = "r", help = "Read audio from .au file")]
+    read: Option<String>,
+
+    #[structopt(long = "tx", help = "Text to transmit")]
+    tx: Option<String>,
+
+    #[structopt(short = "o", help = "Output .au file when transmitting")]
+    output: Option<std::path::PathBuf>,
+
+    #[structopt(long = "sample_rate", default_value = "8000")]
+    samp_rate: Float,
+
+    #[structopt(long = "freq", default_value = "1000", help = "Audio frequency")]
+    freq: Float,
+
+    #[structopt(long = "no_afc")]
+    no_afc: bool,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let mut g = Graph::new();
+
+    if let Some(text) = &opt.tx {
+        let output = opt
+            .output
+            .clone()
+            .ok_or(rustradio::Error::new("transmitting requires -o"))?;
+        let prev = add_block![g, Psk31Modulator::new(text, opt.samp_rate, opt.freq)];
+        let prev = add_block![
+            g,
+            MapBuilder::new(prev, |s: Complex| s.re * 0.5)
+                .name("ComplexToReal".into())
+                .build()
+        ];
+        let prev = add_block![
+            g,
+            AuEncode::new(
+                prev,
+                rustradio::au::Encoding::PCM16,
+                opt.samp_rate as u32,
+                1
+            )
+        ];
+        g.add(Box::new(FileSink::new(
+            prev,
+            output,
+            rustradio::file_sink::Mode::Overwrite,
+        )?));
+    } else {
+        let read = opt
+            .read
+            .clone()
+            .ok_or(rustradio::Error::new("need -r or --tx"))?;
+        let prev = add_block![g, FileSource::new(&read, false)?];
+        let prev = add_block![g, AuDecode::new(prev)];
+        let prev = add_block![g, Hilbert::new(prev, 65)];
+        let mut demod = Psk31Demod::new(prev, opt.samp_rate, opt.freq)?;
+        demod.set_afc(!opt.no_afc);
+        let prev = add_block![g, demod];
+        g.add(Box::new(FileSink::new(
+            prev,
+            "/dev/stdout".into(),
+            rustradio::file_sink::Mode::Append,
+        )?));
+    }
+
+    let cancel = g.cancel_token();
+    ctrlc::set_handler(move || {
+        eprintln!("Received Ctrl+C!");
+        cancel.cancel();
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    g.run()?;
+    Ok(())
+}
@@ -5,6 +5,7 @@ use anyhow::Result;
 use log::warn;
 use structopt::StructOpt;
 
+use rustradio::add_block;
 use rustradio::blocks::*;
 use rustradio::file_sink::Mode;
 use rustradio::graph::Graph;
@@ -19,13 +20,13 @@ struct Opt {
     #[structopt(short = "o")]
     output: std::path::PathBuf,
 
-    // Unused if rtlsdr feature not enabled.
-    #[allow(dead_code)]
+    /// Receive from an rtl_tcp server, e.g. raspberrypi:1234.
+    #[structopt(long = "rtl_tcp")]
+    rtl_tcp: Option<String>,
+
     #[structopt(long = "freq", default_value = "100000000")]
     freq: u64,
 
-    // Unused if rtlsdr feature not enabled.
-    #[allow(dead_code)]
     #[structopt(long = "gain", default_value = "20")]
     gain: i32,
 
@@ -34,15 +35,10 @@ struct Opt {
 
     #[structopt(long = "volume", default_value = "1.0")]
     volume: Float,
-}
 
-macro_rules! blehbleh {
-    ($g:ident, $cons:expr) => {{
-        let block = Box::new($cons);
-        let prev = block.out();
-        $g.add(block);
-        prev
-    }};
+    /// Show audio level meter.
+    #[structopt(long = "vu")]
+    vu: bool,
 }
 
 fn main() -> Result<()> {
@@ -60,7 +56,13 @@ fn main() -> Result<()> {
     let samp_rate = 1_024_000.0;
 
     let prev = if let Some(filename) = opt.filename {
-        blehbleh!(g, FileSource::<Complex>::new(&filename, false)?)
+        add_block!(g, FileSource::<Complex>::new(&filename, false)?)
+    } else if let Some(addr) = &opt.rtl_tcp {
+        let prev = add_block![
+            g,
+            RtlTcpSource::new(addr, opt.freq, samp_rate as u32, opt.gain)?
+        ];
+        add_block![g, RtlSdrDecode::new(prev)]
     } else if !cfg!(feature = "rtlsdr") {
         panic!("RTL SDR feature not enabled")
     } else {
@@ -80,38 +82,50 @@ fn main() -> Result<()> {
 
     // Filter.
     let taps = rustradio::fir::low_pass_complex(samp_rate, 100_000.0, 1000.0);
-    let prev = blehbleh![g, FftFilter::new(prev, &taps)];
+    let prev = add_block![g, FftFilter::new(prev, &taps)];
 
     // Resample.
     let new_samp_rate = 200_000.0;
-    let prev = blehbleh![
+    let prev = add_block![
         g,
         RationalResampler::new(prev, new_samp_rate as usize, samp_rate as usize)?
     ];
     let samp_rate = new_samp_rate;
 
-    // TODO: Add broadcast FM deemph.
-
     // Quad demod.
-    let prev = blehbleh![g, QuadratureDemod::new(prev, 1.0)];
+    let prev = add_block![g, QuadratureDemod::new(prev, 1.0)];
 
-    let taps = rustradio::fir::low_pass(samp_rate, 44_100.0, 500.0);
-    //let audio_filter = FIRFilter::new(prev, &taps);
-    let prev = blehbleh![g, FftFilterFloat::new(prev, &taps)];
+    // Broadcast FM deemph.
+    let prev = add_block![g, Deemph::new(prev, samp_rate, rustradio::wbfm::TAU_US)];
 
-    // Resample audio.
+    // Resample audio, with the polyphase filter doing the low pass.
     let new_samp_rate = 48_000.0;
-    let prev = blehbleh![
+    let prev = add_block![
         g,
-        RationalResampler::new(prev, new_samp_rate as usize, samp_rate as usize)?
+        RationalResampler::filtered(prev, new_samp_rate as usize, samp_rate as usize)?
     ];
     let _samp_rate = new_samp_rate;
 
     // Change volume.
-    let prev = blehbleh![g, MultiplyConst::new(prev, opt.volume)];
+    let prev = add_block![g, MultiplyConst::new(prev, opt.volume)];
+
+    // Audio level meter.
+    let prev = if opt.vu {
+        let vu = Box::new(VuMeter::new(prev, new_samp_rate));
+        let h = vu.handle();
+        let prev = vu.out();
+        g.add(vu);
+        std::thread::spawn(move || loop {
+            eprint!("\r{}", h.level().bar(50, -60.0));
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        });
+        prev
+    } else {
+        prev
+    };
 
     // Convert to .au.
-    let prev = blehbleh![
+    let prev = add_block![
         g,
         AuEncode::new(prev, rustradio::au::Encoding::PCM16, 48000, 1)
     ];
@@ -0,0 +1,102 @@
+/*! RTTY receiver.
+
+Decodes RTTY from an .au audio file, e.g. recorded from an SSB
+receiver, and writes the decoded text to stdout.
+
+```no_run
+$ ./rtty-rx -r recording.au --sample_rate 8000
+[…]
+$ ./rtty-rx -r dwd.au --sample_rate 8000 --baud 50 --shift 450
+[…]
+```
+*/
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::add_block;
+use rustradio::blocks::*;
+use rustradio::graph::Graph;
+use rustradio::rtty::{Baud, RttyDecode};
+use rustradio::Float;
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(short = "r", help = "Read audio from .au file")]
+    read: String,
+
+    #[structopt(long = "sample_rate", default_value = "8000")]
+    samp_rate: Float,
+
+    #[structopt(long = "baud", default_value = "45.45")]
+    baud: Float,
+
+    #[structopt(long = "mark", default_value = "2125", help = "Mark tone in Hz")]
+    mark: Float,
+
+    #[structopt(long = "shift", default_value = "170", help = "Shift in Hz")]
+    shift: Float,
+
+    #[structopt(long, help = "Swap mark and space")]
+    reverse: bool,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let mut g = Graph::new();
+    let samp_rate = opt.samp_rate;
+
+    let prev = add_block![g, FileSource::new(&opt.read, false)?];
+    let prev = add_block![g, AuDecode::new(prev)];
+    let prev = add_block![g, Hilbert::new(prev, 65)];
+    let prev = add_block![g, QuadratureDemod::new(prev, 1.0)];
+
+    // Mark is the lower tone, so after centering it's negative.
+    let center = opt.mark + opt.shift / 2.0;
+    let prev = add_block![
+        g,
+        add_const(
+            prev,
+            -center * 2.0 * std::f64::consts::PI as Float / samp_rate
+        )
+    ];
+    let taps = rustradio::fir::low_pass(samp_rate, opt.baud * 1.5, opt.baud);
+    let prev = add_block![g, FftFilterFloat::new(prev, &taps)];
+
+    let baud = match opt.baud {
+        b if (b - 45.45).abs() < 0.01 => Baud::B45,
+        50.0 => Baud::B50,
+        75.0 => Baud::B75,
+        b => Baud::Other(b),
+    };
+    let mut rtty = RttyDecode::new(prev, samp_rate, baud);
+    rtty.set_reverse(!opt.reverse);
+    let prev = add_block![g, rtty];
+    g.add(Box::new(FileSink::new(
+        prev,
+        "/dev/stdout".into(),
+        rustradio::file_sink::Mode::Append,
+    )?));
+
+    let cancel = g.cancel_token();
+    ctrlc::set_handler(move || {
+        eprintln!("Received Ctrl+C!");
+        cancel.cancel();
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    g.run()?;
+    println!();
+    Ok(())
+}
@@ -0,0 +1,65 @@
+/*! Run a flowgraph from a JSON file, or a GNU Radio Companion file.
+
+The file is a [GraphSpec], as saved by `DynGraph::spec()`, or by
+`to_json()` in the Python bindings. E.g.:
+
+```text
+{"blocks": [
+  {"name": "file_source_c", "params": {"filename": "in.c32"}},
+  {"name": "quadrature_demod", "inputs": [0]},
+  {"name": "file_sink_f", "inputs": [1], "params": {"filename": "out.f32"}}
+]}
+```
+
+Files ending in `.grc` are converted, for the subset of blocks
+described in [rustradio::grc].
+
+```no_run
+$ ./run_graph fm.json
+$ ./run_graph fm.grc
+```
+
+[GraphSpec]: rustradio::registry::GraphSpec
+*/
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::registry::{default_registry, DynGraph, GraphSpec};
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(help = "Flowgraph JSON or .grc file")]
+    graph: String,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let spec = if opt.graph.ends_with(".grc") {
+        rustradio::grc::load(&opt.graph)?
+    } else {
+        GraphSpec::load(&opt.graph)?
+    };
+    let mut g = DynGraph::from_spec(default_registry(), &spec)?;
+
+    let cancel = g.start()?;
+    ctrlc::set_handler(move || {
+        eprintln!("Received Ctrl+C!");
+        cancel.cancel();
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    eprintln!("{}", g.wait()?);
+    Ok(())
+}
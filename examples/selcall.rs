@@ -0,0 +1,68 @@
+/*! Selcall decoder.
+
+Decodes selective calling tone sequences from an .au audio recording
+of a PMR channel, and prints them.
+
+```no_run
+$ ./selcall -r pmr.au --sample_rate 8000 --standard zvei
+```
+*/
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::add_block;
+use rustradio::blocks::*;
+use rustradio::graph::Graph;
+use rustradio::selcall::Standard;
+use rustradio::{Error, Float};
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(short = "r", help = "Read audio from .au file")]
+    read: String,
+
+    #[structopt(long = "sample_rate", default_value = "8000")]
+    samp_rate: Float,
+
+    #[structopt(long = "standard", default_value = "ccir", help = "ccir, zvei or eea")]
+    standard: String,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let standard = match opt.standard.as_str() {
+        "ccir" => Standard::Ccir,
+        "zvei" => Standard::Zvei,
+        "eea" => Standard::Eea,
+        other => return Err(Error::new(&format!("unknown standard {other}")).into()),
+    };
+
+    let mut g = Graph::new();
+
+    let prev = add_block![g, FileSource::new(&opt.read, false)?];
+    let prev = add_block![g, AuDecode::new(prev)];
+    let prev = add_block![g, SelcallDecode::new(prev, opt.samp_rate, standard)];
+    g.add(Box::new(DebugSinkNoCopy::new(prev)));
+
+    let cancel = g.cancel_token();
+    ctrlc::set_handler(move || {
+        eprintln!("Received Ctrl+C!");
+        cancel.cancel();
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    g.run()?;
+    Ok(())
+}
@@ -1,8 +1,11 @@
 use anyhow::Result;
 use structopt::StructOpt;
 
-use rustradio::blocks::{AddConst, DebugSink, PduWriter, StreamToPdu, VectorSourceBuilder};
+use rustradio::blocks::{
+    AddConst, DebugSink, PduWriter, StreamToPdu, VectorSource, VectorSourceBuilder,
+};
 use rustradio::graph::Graph;
+use rustradio::node::Node;
 use rustradio::Complex;
 
 #[derive(StructOpt, Debug)]
@@ -15,7 +18,7 @@ struct Opt {
 fn simple_copy() -> Result<()> {
     let mut g = Graph::new();
 
-    let src = Box::new(
+    let src = g.add(Node::source(
         VectorSourceBuilder::new(vec![
             Complex::new(10.0, 0.0),
             Complex::new(-20.0, 0.0),
@@ -23,13 +26,15 @@ fn simple_copy() -> Result<()> {
         ])
         .repeat(2)
         .build(),
-    );
-    let add = Box::new(AddConst::new(src.out(), Complex::new(1.1, 2.0)));
-    let sink = Box::new(DebugSink::new(add.out()));
-
-    g.add(src);
-    g.add(add);
-    g.add(sink);
+        VectorSource::out,
+    ));
+    let add = g.add(Node::block(
+        |src| AddConst::new(src, Complex::new(1.1, 2.0)),
+        AddConst::out,
+    ));
+    let sink = g.add(Node::sink(DebugSink::new));
+    g.connect(src.output, add.input)?;
+    g.connect(add.output, sink.input)?;
 
     g.run()
 }
@@ -7,6 +7,7 @@ mod internal {
     use log::warn;
     use structopt::StructOpt;
 
+    use rustradio::add_block;
     use rustradio::blocks::*;
     use rustradio::file_sink::Mode;
     use rustradio::graph::Graph;
@@ -38,15 +39,6 @@ mod internal {
         volume: Float,
     }
 
-    macro_rules! blehbleh {
-        ($g:ident, $cons:expr) => {{
-            let block = Box::new($cons);
-            let prev = block.out();
-            $g.add(block);
-            prev
-        }};
-    }
-
     pub fn main() -> Result<()> {
         println!("soapy_fm receiver example");
         let opt = Opt::from_args();
@@ -61,7 +53,7 @@ mod internal {
         let mut g = Graph::new();
         let samp_rate = 1_024_000.0f32;
 
-        let prev = blehbleh![
+        let prev = add_block![
             g,
             SoapySdrSourceBuilder::new(opt.driver.clone(), opt.freq as f64, samp_rate as f64)
                 .igain(opt.gain as f64)
@@ -70,11 +62,11 @@ mod internal {
 
         // Filter.
         let taps = rustradio::fir::low_pass_complex(samp_rate, 100_000.0, 1000.0);
-        let prev = blehbleh![g, FftFilter::new(prev, &taps)];
+        let prev = add_block![g, FftFilter::new(prev, &taps)];
 
         // Resample.
         let new_samp_rate = 200_000.0;
-        let prev = blehbleh![
+        let prev = add_block![
             g,
             RationalResampler::new(prev, new_samp_rate as usize, samp_rate as usize)?
         ];
@@ -83,25 +75,21 @@ mod internal {
         // TODO: Add broadcast FM deemph.
 
         // Quad demod.
-        let prev = blehbleh![g, QuadratureDemod::new(prev, 1.0)];
+        let prev = add_block![g, QuadratureDemod::new(prev, 1.0)];
 
-        let taps = rustradio::fir::low_pass(samp_rate, 44_100.0, 500.0);
-        //let audio_filter = FIRFilter::new(prev, &taps);
-        let prev = blehbleh![g, FftFilterFloat::new(prev, &taps)];
-
-        // Resample audio.
+        // Resample audio, with the polyphase filter doing the low pass.
         let new_samp_rate = 48_000.0;
-        let prev = blehbleh![
+        let prev = add_block![
             g,
-            RationalResampler::new(prev, new_samp_rate as usize, samp_rate as usize)?
+            RationalResampler::filtered(prev, new_samp_rate as usize, samp_rate as usize)?
         ];
         let _samp_rate = new_samp_rate;
 
         // Change volume.
-        let prev = blehbleh![g, MultiplyConst::new(prev, opt.volume)];
+        let prev = add_block![g, MultiplyConst::new(prev, opt.volume)];
 
         // Convert to .au.
-        let prev = blehbleh![
+        let prev = add_block![
             g,
             AuEncode::new(prev, rustradio::au::Encoding::PCM16, 48000, 1)
         ];
@@ -0,0 +1,120 @@
+/*!
+Example live spectrum and waterfall display.
+
+Needs the `egui` feature, or `tui` for text mode with `--tui`:
+
+```text
+cargo run --features egui --example spectrum -- -r raw.c32 --samp-rate 1024000
+cargo run --features tui --example spectrum -- -r raw.c32 --tui
+```
+ */
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::add_block;
+use rustradio::blocks::*;
+use rustradio::mtgraph::MTGraph;
+use rustradio::{Complex, Float};
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(short = "r")]
+    filename: Option<String>,
+
+    /// Start over at the end of the file.
+    #[structopt(long = "repeat")]
+    repeat: bool,
+
+    #[structopt(long = "samp-rate", default_value = "1024000")]
+    samp_rate: Float,
+
+    #[structopt(long = "fft-size", default_value = "1024")]
+    fft_size: usize,
+
+    // Unused if rtlsdr feature not enabled.
+    #[allow(dead_code)]
+    #[structopt(long = "freq", default_value = "100000000")]
+    freq: u64,
+
+    // Unused if rtlsdr feature not enabled.
+    #[allow(dead_code)]
+    #[structopt(long = "gain", default_value = "20")]
+    gain: i32,
+
+    /// Show in the terminal, instead of a window.
+    #[structopt(long = "tui")]
+    tui: bool,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let mut g = MTGraph::new();
+    let prev = if let Some(filename) = opt.filename {
+        add_block![g, FileSource::<Complex>::new(&filename, opt.repeat)?]
+    } else if !cfg!(feature = "rtlsdr") {
+        panic!("RTL SDR feature not enabled")
+    } else {
+        // RTL SDR source.
+        #[cfg(feature = "rtlsdr")]
+        {
+            let src = Box::new(RtlSdrSource::new(opt.freq, opt.samp_rate as u32, opt.gain)?);
+            let dec = Box::new(RtlSdrDecode::new(src.out()));
+            let prev = dec.out();
+            g.add(src);
+            g.add(dec);
+            prev
+        }
+        #[cfg(not(feature = "rtlsdr"))]
+        panic!("can't happen, but must be here to compile")
+    };
+
+    let (a, b) = add_block![g, Tee::new(prev)];
+    let spectrum = Box::new(SpectrumSink::new(a, opt.samp_rate, opt.fft_size));
+    let waterfall = Box::new(WaterfallSink::new(b, opt.samp_rate, opt.fft_size));
+    let handles = (spectrum.handle(), waterfall.handle());
+    g.add(spectrum);
+    g.add(waterfall);
+
+    // The window has to own the main thread, and the terminal is
+    // simpler to handle there too.
+    let cancel = g.cancel_token();
+    let graph = std::thread::spawn(move || g.run());
+    if opt.tui {
+        #[cfg(feature = "tui")]
+        rustradio::tui::run(Some(handles.0), Some(handles.1))?;
+        #[cfg(not(feature = "tui"))]
+        {
+            drop(handles);
+            eprintln!("tui feature not enabled");
+        }
+    } else {
+        #[cfg(feature = "egui")]
+        rustradio::gui::run(
+            "rustradio spectrum",
+            vec![
+                Box::new(rustradio::spectrum_sink::SpectrumView::new(handles.0)),
+                Box::new(rustradio::waterfall_sink::WaterfallView::new(handles.1)),
+            ],
+        )?;
+        #[cfg(not(feature = "egui"))]
+        {
+            drop(handles);
+            eprintln!("egui feature not enabled, no window to show");
+        }
+    }
+    cancel.cancel();
+    graph.join().expect("graph thread panicked")?;
+    Ok(())
+}
@@ -0,0 +1,73 @@
+/*! SSTV receiver.
+
+Decodes SSTV images from an .au audio file, e.g. recorded from an SSB
+or FM receiver, and saves them as PNG.
+
+```no_run
+$ mkdir images
+$ ./sstv-rx -r recording.au --sample_rate 11025 -o images
+```
+*/
+use std::path::PathBuf;
+
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::add_block;
+use rustradio::blocks::*;
+use rustradio::graph::Graph;
+use rustradio::Float;
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(short = "r", help = "Read audio from .au file")]
+    read: String,
+
+    #[structopt(short = "o", help = "Directory to write images to")]
+    output: PathBuf,
+
+    #[structopt(long = "sample_rate", default_value = "11025")]
+    samp_rate: Float,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let mut g = Graph::new();
+    let samp_rate = opt.samp_rate;
+
+    let prev = add_block![g, FileSource::new(&opt.read, false)?];
+    let prev = add_block![g, AuDecode::new(prev)];
+    let prev = add_block![g, Hilbert::new(prev, 65)];
+
+    // Output instantaneous frequency in Hz.
+    let prev = add_block![
+        g,
+        QuadratureDemod::new(prev, samp_rate / (2.0 * std::f64::consts::PI as Float))
+    ];
+    let taps = rustradio::fir::low_pass(samp_rate, 1500.0, 300.0);
+    let prev = add_block![g, FftFilterFloat::new(prev, &taps)];
+    let prev = add_block![g, SstvDecode::new(prev, samp_rate)];
+    g.add(Box::new(SstvPngWriter::new(prev, opt.output)));
+
+    let cancel = g.cancel_token();
+    ctrlc::set_handler(move || {
+        eprintln!("Received Ctrl+C!");
+        cancel.cancel();
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    g.run()?;
+    Ok(())
+}
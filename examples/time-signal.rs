@@ -0,0 +1,83 @@
+/*! DCF77 / WWVB time signal receiver.
+
+Decodes the time from an .au audio recording of a receiver in CW or
+SSB mode, tuned so that the carrier is an audible tone.
+
+```no_run
+$ ./time-signal -r dcf77.au --sample_rate 8000
+$ ./time-signal -r wwvb.au --sample_rate 8000 --wwvb
+```
+*/
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::blocks::*;
+use rustradio::graph::Graph;
+use rustradio::node::Node;
+use rustradio::time_signal::Station;
+use rustradio::Float;
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(short = "r", help = "Read audio from .au file")]
+    read: String,
+
+    #[structopt(long = "sample_rate", default_value = "8000")]
+    samp_rate: Float,
+
+    #[structopt(long = "wwvb", help = "Decode WWVB instead of DCF77")]
+    wwvb: bool,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let mut g = Graph::new();
+    let station = if opt.wwvb {
+        Station::Wwvb
+    } else {
+        Station::Dcf77
+    };
+
+    let src = g.add(Node::source(
+        FileSource::new(&opt.read, false)?,
+        FileSource::out,
+    ));
+    let au = g.add(Node::block(AuDecode::new, AuDecode::out));
+
+    // The tone amplitude is the carrier amplitude.
+    let hilbert = g.add(Node::block(|src| Hilbert::new(src, 65), Hilbert::out));
+    let mag = g.add(Node::block(ComplexToMag2::new, ComplexToMag2::out));
+    let samp_rate = opt.samp_rate;
+    let decode = g.add(Node::block(
+        move |src| TimeSignalDecode::new(src, samp_rate, station),
+        TimeSignalDecode::out,
+    ));
+    let sink = g.add(Node::sink(DebugSinkNoCopy::new));
+    g.connect(src.output, au.input)?;
+    g.connect(au.output, hilbert.input)?;
+    g.connect(hilbert.output, mag.input)?;
+    g.connect(mag.output, decode.input)?;
+    g.connect(decode.output, sink.input)?;
+
+    let cancel = g.cancel_token();
+    ctrlc::set_handler(move || {
+        eprintln!("Received Ctrl+C!");
+        cancel.cancel();
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    g.run()?;
+    Ok(())
+}
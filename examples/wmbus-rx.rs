@@ -0,0 +1,91 @@
+/*! Wireless M-Bus receiver.
+
+Decodes utility meter frames from a SigMF I/Q recording, centered on
+the wM-Bus channel: 868.3MHz for mode S, and 868.95MHz for modes T and
+C.
+
+```no_run
+$ ./wmbus-rx -r 868.95M.sigmf-data --mode T
+$ ./wmbus-rx -r 868.3M.sigmf-data --mode S
+```
+*/
+use anyhow::Result;
+use structopt::StructOpt;
+
+use rustradio::add_block;
+use rustradio::blocks::*;
+use rustradio::graph::Graph;
+use rustradio::wmbus::Mode;
+use rustradio::{Error, Float};
+
+#[derive(StructOpt, Debug)]
+#[structopt()]
+struct Opt {
+    #[structopt(short = "r", help = "Read I/Q from SigMF file")]
+    read: String,
+
+    #[structopt(long = "sample_rate", help = "Override sample rate")]
+    samp_rate: Option<u32>,
+
+    #[structopt(long = "mode", default_value = "T", help = "Mode S, T, or C")]
+    mode: String,
+
+    #[structopt(short = "v", default_value = "0")]
+    verbose: usize,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    stderrlog::new()
+        .module(module_path!())
+        .module("rustradio")
+        .quiet(false)
+        .verbosity(opt.verbose)
+        .timestamp(stderrlog::Timestamp::Second)
+        .init()?;
+
+    let mode = match opt.mode.as_str() {
+        "S" | "s" => Mode::S,
+        "T" | "t" => Mode::T,
+        "C" | "c" => Mode::C,
+        m => return Err(Error::new(&format!("unknown mode {m}")).into()),
+    };
+
+    let mut g = Graph::new();
+
+    let mut b = SigMFSourceBuilder::new(opt.read.clone());
+    if let Some(s) = opt.samp_rate {
+        b = b.sample_rate(s as f64);
+    }
+    let b = b.build()?;
+    let samp_rate = b
+        .sample_rate()
+        .ok_or(Error::new("SigMF file does not specify sample rate"))? as Float;
+    let prev = add_block![g, b];
+
+    // Filter RF, keeping both tones.
+    let cutoff = mode.deviation() + mode.chip_rate() / 2.0;
+    let taps = rustradio::fir::low_pass_complex(samp_rate, cutoff, cutoff / 10.0);
+    let prev = add_block![g, FftFilter::new(prev, &taps)];
+    let prev = add_block![g, QuadratureDemod::new(prev, 1.0)];
+
+    // Clock recovery and slicing.
+    let prev = add_block![
+        g,
+        ZeroCrossing::new(prev, samp_rate / mode.chip_rate(), 0.1)
+    ];
+    let prev = add_block![g, BinarySlicer::new(prev)];
+
+    let prev = add_block![g, WmbusDecode::new(prev, mode)];
+    g.add(Box::new(DebugSinkNoCopy::new(prev)));
+
+    let cancel = g.cancel_token();
+    ctrlc::set_handler(move || {
+        eprintln!("Received Ctrl+C!");
+        cancel.cancel();
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    g.run()?;
+    Ok(())
+}
@@ -10,7 +10,9 @@ set -ueo pipefail
 CURRENT="$(awk '/^version/ {print $3}' Cargo.toml | sed 's/"//g')"
 NEW="$(echo $CURRENT | awk -F. '{print $1 "." $2 "." $3+1}')"
 echo "Current: '$CURRENT', New: '$NEW'"
-sed -i "s/^version = \"${CURRENT?}\"/version = \"${NEW?}\"/" Cargo.toml
+sed -i "s/^version = \"${CURRENT?}\"/version = \"${NEW?}\"/" Cargo.toml rustradio-core/Cargo.toml rustradio-macros/Cargo.toml
+sed -i "s/^rustradio-core = {version = \"${CURRENT?}\"/rustradio-core = {version = \"${NEW?}\"/" Cargo.toml
+sed -i "s/^rustradio-macros = {version = \"${CURRENT?}\"/rustradio-macros = {version = \"${NEW?}\"/" Cargo.toml
 cargo build
 git commit -a -m"Bump version to ${NEW?}"
 git tag "v${NEW?}"
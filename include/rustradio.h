@@ -0,0 +1,103 @@
+/*
+ * C API for rustradio.
+ *
+ * Build the library with the `capi` feature:
+ *
+ *   cargo rustc --release --lib --features capi --crate-type cdylib
+ *
+ * Functions returning int return 0, or a count, on success, and -1 on
+ * error. rustradio_last_error() then describes the error.
+ *
+ * See src/capi.rs for details.
+ */
+#ifndef RUSTRADIO_H
+#define RUSTRADIO_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RUSTRADIO_API_VERSION 1
+
+typedef struct Graph rustradio_graph;
+
+typedef void (*rustradio_pdu_callback)(void* user, const uint8_t* data, size_t len);
+
+/* Version of the library's API. Compare to RUSTRADIO_API_VERSION. */
+int rustradio_api_version(void);
+
+/* Last error on this thread. Valid until the next call from this thread. */
+const char* rustradio_last_error(void);
+
+/* JSON array describing all blocks. Free with rustradio_string_free(). */
+char* rustradio_blocks(void);
+void rustradio_string_free(char* s);
+
+rustradio_graph* rustradio_graph_new(void);
+
+/* Create a graph from JSON from rustradio_graph_spec(), or NULL on error. */
+rustradio_graph* rustradio_graph_from_spec(const char* json);
+
+/*
+ * Blocks, connections, and parameters of the graph as JSON, for saving.
+ * NULL on error. Free with rustradio_string_free().
+ */
+char* rustradio_graph_spec(rustradio_graph* g);
+
+/* Free a graph. A running graph is cancelled, and waited for. */
+void rustradio_graph_free(rustradio_graph* g);
+
+/*
+ * Add block `name`, connected to `n_inputs` streams in `inputs`.
+ * `params` is a JSON object, or NULL. Output stream numbers are written to
+ * `outputs`. Returns the number of outputs, or -1.
+ */
+int rustradio_graph_add(rustradio_graph* g,
+                        const char* name,
+                        const size_t* inputs,
+                        size_t n_inputs,
+                        const char* params,
+                        size_t* outputs,
+                        size_t max_outputs);
+
+/*
+ * Call `cb` with each packet in PDU stream `stream`, from the thread running
+ * the graph. `data` is only valid during the call.
+ */
+int rustradio_graph_add_pdu_callback(rustradio_graph* g,
+                                     size_t stream,
+                                     rustradio_pdu_callback cb,
+                                     void* user);
+
+/* Start running the graph in a thread of its own. */
+int rustradio_graph_start(rustradio_graph* g);
+
+/* Ask a running graph to stop. May be called from any thread. */
+int rustradio_graph_cancel(rustradio_graph* g);
+
+/*
+ * Wait for a started graph to finish. Other threads may cancel it, and set
+ * controls, while waiting.
+ */
+int rustradio_graph_wait(rustradio_graph* g);
+
+/*
+ * Set control `name` of block `block` to `value`, a JSON number, bool, or
+ * string, e.g. "-500.0". May be called from any thread, also while running.
+ */
+int rustradio_graph_set_control(rustradio_graph* g,
+                                const char* block,
+                                const char* name,
+                                const char* value);
+
+/* Start, then wait. */
+int rustradio_graph_run(rustradio_graph* g);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,320 @@
+/*! Finite impulse response filter, and tap generators.
+
+Use FftFilter if many taps are used, for better performance.
+*/
+/*
+ * TODO:
+ * * Only handles case where input, output, and tap type are all the same.
+ */
+use alloc::vec;
+use alloc::vec::Vec;
+
+// Float math. Without std it comes from libm.
+#[allow(unused_imports)]
+use num_traits::Float as _;
+
+use crate::kernels::Dot;
+use crate::{Complex, Float};
+
+/// Finite impulse response filter.
+pub struct FIR<T: Copy> {
+    taps: Vec<T>,
+}
+
+impl<T: Dot> FIR<T> {
+    /// Create new FIR.
+    pub fn new(taps: &[T]) -> Self {
+        Self {
+            taps: taps.iter().copied().rev().collect(),
+        }
+    }
+    /// Run filter once, creating one sample from the taps and an
+    /// equal number of input samples.
+    pub fn filter(&self, input: &[T]) -> T {
+        T::dot(input, &self.taps)
+    }
+
+    /// Call `filter()` multiple times, across an input range.
+    pub fn filter_n(&self, input: &[T]) -> Vec<T> {
+        let n = input.len() - self.taps.len() + 1;
+        (0..n).map(|i| self.filter(&input[i..])).collect()
+    }
+
+    /// Like `filter_n()`, but write into `out` instead of allocating.
+    ///
+    /// Returns the number of output samples written, which is the
+    /// number of input samples that can be consumed. The last
+    /// `ntaps-1` input samples are needed again for the next call.
+    pub fn filter_n_into(&self, input: &[T], out: &mut [T]) -> usize {
+        let n = core::cmp::min((input.len() + 1).saturating_sub(self.taps.len()), out.len());
+        for (i, o) in out[..n].iter_mut().enumerate() {
+            *o = self.filter(&input[i..]);
+        }
+        n
+    }
+}
+
+/// Create taps for a low pass filter as complex taps.
+pub fn low_pass_complex(samp_rate: Float, cutoff: Float, twidth: Float) -> Vec<Complex> {
+    low_pass(samp_rate, cutoff, twidth)
+        .into_iter()
+        .map(|t| Complex::new(t, 0.0))
+        .collect()
+}
+
+/// Create taps for a low pass filter.
+///
+/// TODO: this could be faster if we supported filtering a Complex by a Float.
+/// A low pass filter doesn't actually need complex taps.
+pub fn low_pass(samp_rate: Float, cutoff: Float, twidth: Float) -> Vec<Float> {
+    let pi = core::f64::consts::PI as Float;
+    let ntaps = {
+        let a: Float = 53.0; // Hamming.
+        let t = (a * samp_rate / (22.0 * twidth)) as usize;
+        if (t & 1) == 0 {
+            t + 1
+        } else {
+            t
+        }
+    };
+    let mut taps = vec![Float::default(); ntaps];
+    let window: Vec<Float> = {
+        // Hamming
+        let m = (ntaps - 1) as Float;
+        (0..ntaps)
+            .map(|n| 0.54 - 0.46 * (2.0 * pi * (n as Float) / m).cos())
+            .collect()
+    };
+    let m = (ntaps - 1) / 2;
+    let fwt0 = 2.0 * pi * cutoff / samp_rate;
+    for nm in 0..ntaps {
+        let n = nm as i64 - m as i64;
+        let nf = n as Float;
+        taps[nm] = if n == 0 {
+            fwt0 / pi * window[nm]
+        } else {
+            ((nf * fwt0).sin() / (nf * pi)) * window[nm]
+        };
+    }
+    let gain = {
+        let gain: Float = 1.0;
+        let mut fmax = taps[m];
+        for n in 1..=m {
+            fmax += 2.0 * taps[n + m];
+        }
+        gain / fmax
+    };
+    taps.into_iter().map(|t| t * gain).collect()
+}
+
+/// Create taps for a root raised cosine filter.
+///
+/// Used for pulse shaping when transmitting PSK, and as the matched
+/// filter when receiving. `alpha` is the excess bandwidth, between 0
+/// and 1. `ntaps` should be odd, and span several symbols. The taps
+/// are normalized to a DC gain of 1.
+pub fn root_raised_cosine(
+    samp_rate: Float,
+    symbol_rate: Float,
+    alpha: Float,
+    ntaps: usize,
+) -> Vec<Float> {
+    let pi = core::f64::consts::PI as Float;
+    let sps = samp_rate / symbol_rate;
+    let mid = (ntaps as Float - 1.0) / 2.0;
+    let taps: Vec<Float> = (0..ntaps)
+        .map(|i| {
+            let t = (i as Float - mid) / sps;
+            if t.abs() < 1e-6 {
+                1.0 - alpha + 4.0 * alpha / pi
+            } else if (t.abs() - 1.0 / (4.0 * alpha)).abs() < 1e-6 {
+                alpha / Float::sqrt(2.0)
+                    * ((1.0 + 2.0 / pi) * (pi / (4.0 * alpha)).sin()
+                        + (1.0 - 2.0 / pi) * (pi / (4.0 * alpha)).cos())
+            } else {
+                ((pi * t * (1.0 - alpha)).sin() + 4.0 * alpha * t * (pi * t * (1.0 + alpha)).cos())
+                    / (pi * t * (1.0 - (4.0 * alpha * t).powi(2)))
+            }
+        })
+        .collect();
+    let sum: Float = taps.iter().sum();
+    taps.into_iter().map(|t| t / sum).collect()
+}
+
+/// Create taps for a gaussian filter, as used for GMSK and GFSK.
+///
+/// `bt` is the bandwidth-time product, e.g. 0.3 for GSM and 0.5 for
+/// Bluetooth. The taps are normalized to a DC gain of 1, so that
+/// filtering the ±1 symbols before the frequency modulator keeps the
+/// deviation.
+pub fn gaussian(samp_rate: Float, symbol_rate: Float, bt: Float, ntaps: usize) -> Vec<Float> {
+    let pi = core::f64::consts::PI as Float;
+    let sps = samp_rate / symbol_rate;
+    // Standard deviation, in symbols.
+    let sigma = (2.0 as Float).ln().sqrt() / (2.0 * pi * bt);
+    let mid = (ntaps as Float - 1.0) / 2.0;
+    let taps: Vec<Float> = (0..ntaps)
+        .map(|i| {
+            let t = (i as Float - mid) / sps / sigma;
+            (-0.5 * t * t).exp()
+        })
+        .collect();
+    let sum: Float = taps.iter().sum();
+    taps.into_iter().map(|t| t / sum).collect()
+}
+
+/// Generate hilbert transformer filter.
+pub fn hilbert(ntaps: usize) -> Vec<Float> {
+    let window: Vec<Float> = {
+        let pi = core::f64::consts::PI as Float;
+        // Hamming
+        let m = (ntaps - 1) as Float;
+        (0..ntaps)
+            .map(|n| 0.54 - 0.46 * (2.0 * pi * (n as Float) / m).cos())
+            .collect()
+    };
+    let mid = (ntaps - 1) / 2;
+    let mut gain = 0.0;
+    let mut taps = vec![0.0; ntaps];
+    for i in 1..=mid {
+        if i & 1 == 1 {
+            let x = 1.0 / (i as Float);
+            taps[mid + i] = x * window[mid + i];
+            taps[mid - i] = -x * window[mid - i];
+            gain = taps[mid + i] - gain;
+        } else {
+            taps[mid + i] = 0.0;
+            taps[mid - i] = 0.0;
+        }
+    }
+    let gain = 1.0 / (2.0 * gain.abs());
+    taps.iter().map(|e| gain * *e).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::assert_almost_equal_complex;
+    use alloc::vec;
+
+    #[test]
+    fn test_complex() {
+        let input = vec![
+            Complex::new(1.0, 0.0),
+            Complex::new(2.0, 0.0),
+            Complex::new(3.0, 0.2),
+            Complex::new(4.1, 0.0),
+            Complex::new(5.0, 0.0),
+            Complex::new(6.0, 0.2),
+        ];
+        let taps = vec![
+            Complex::new(0.1, 0.0),
+            Complex::new(1.0, 0.0),
+            Complex::new(0.0, 0.2),
+        ];
+        let filter = FIR::new(&taps);
+        assert_almost_equal_complex(
+            &filter.filter_n(&input),
+            &[
+                Complex::new(2.3, 0.22),
+                Complex::new(3.41, 0.6),
+                Complex::new(4.56, 0.6),
+                Complex::new(5.6, 0.84),
+            ],
+        );
+        // Limited by output space.
+        let mut out = [Complex::default(); 3];
+        assert_eq!(filter.filter_n_into(&input, &mut out), 3);
+        assert_eq!(&out[..], &filter.filter_n(&input)[..3]);
+        // Not enough input.
+        assert_eq!(filter.filter_n_into(&input[..2], &mut out), 0);
+    }
+
+    #[test]
+    fn test_filter_generator() {
+        let taps = low_pass_complex(10000.0, 1000.0, 1000.0);
+        assert_eq!(taps.len(), 25);
+        assert_almost_equal_complex(
+            &taps,
+            &[
+                Complex::new(0.002010403, 0.0),
+                Complex::new(0.0016210203, 0.0),
+                Complex::new(7.851862e-10, 0.0),
+                Complex::new(-0.0044467063, 0.0),
+                Complex::new(-0.011685465, 0.0),
+                Complex::new(-0.018134259, 0.0),
+                Complex::new(-0.016773716, 0.0),
+                Complex::new(-3.6538055e-9, 0.0),
+                Complex::new(0.0358771, 0.0),
+                Complex::new(0.08697697, 0.0),
+                Complex::new(0.14148787, 0.0),
+                Complex::new(0.18345332, 0.0),
+                Complex::new(0.19922684, 0.0),
+                Complex::new(0.1834533, 0.0),
+                Complex::new(0.14148785, 0.0),
+                Complex::new(0.08697697, 0.0),
+                Complex::new(0.035877097, 0.0),
+                Complex::new(-3.6538053e-9, 0.0),
+                Complex::new(-0.016773716, 0.0),
+                Complex::new(-0.018134257, 0.0),
+                Complex::new(-0.011685458, 0.0),
+                Complex::new(-0.0044467044, 0.0),
+                Complex::new(7.851859e-10, 0.0),
+                Complex::new(0.0016210207, 0.0),
+                Complex::new(0.002010403, 0.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn rrc() {
+        let taps = root_raised_cosine(8.0, 1.0, 0.35, 8 * 8 + 1);
+        assert_eq!(taps.len(), 65);
+        assert!((taps.iter().sum::<Float>() - 1.0).abs() < 1e-5);
+        // Symmetric, with the peak in the middle.
+        for i in 0..32 {
+            assert!((taps[i] - taps[64 - i]).abs() < 1e-6);
+            assert!(taps[i] < taps[32]);
+        }
+
+        // Two RRC filters make a raised cosine, which is zero at
+        // every other symbol, give or take truncation.
+        let rc: Vec<Float> = (0..129)
+            .map(|n| {
+                (0..65)
+                    .filter(|&k| n >= k && n - k < 65)
+                    .map(|k| taps[k] * taps[n - k])
+                    .sum()
+            })
+            .collect();
+        for sym in 1..6 {
+            let z = rc[64 + 8 * sym] / rc[64];
+            assert!(z.abs() < 0.02, "symbol {sym}: {z}");
+        }
+
+        // Alpha 0 and 1 avoid division by zero.
+        for alpha in [0.0, 0.25, 1.0] {
+            let taps = root_raised_cosine(4.0, 1.0, alpha, 33);
+            assert!(taps.iter().all(|t| t.is_finite()), "{alpha}: {taps:?}");
+        }
+    }
+
+    #[test]
+    fn gaussian_taps() {
+        let taps = gaussian(4.0, 1.0, 0.5, 17);
+        assert_eq!(taps.len(), 17);
+        assert!((taps.iter().sum::<Float>() - 1.0).abs() < 1e-5);
+        assert!(taps.windows(2).take(8).all(|w| w[0] < w[1]));
+        assert!(taps.windows(2).skip(8).all(|w| w[0] > w[1]));
+
+        // Half power at 0.5 symbols from the center, for BT 0.5:
+        // sigma is sqrt(ln 2)/(2π·0.5) symbols.
+        let sigma = (2.0 as Float).ln().sqrt() / core::f64::consts::PI as Float;
+        let want = (-0.5 * (0.5 / sigma) * (0.5 / sigma)).exp();
+        assert!((taps[8 + 2] / taps[8] - want).abs() < 1e-5);
+
+        // Lower BT is wider.
+        let narrow = gaussian(4.0, 1.0, 0.3, 17);
+        assert!(narrow[8] < taps[8]);
+    }
+}
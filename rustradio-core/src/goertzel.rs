@@ -0,0 +1,179 @@
+/*! Goertzel tone detection.
+
+The [Goertzel algorithm] calculates the power of a single DFT bin,
+which is cheaper than a full FFT when only a few frequencies are of
+interest, such as for DTMF or selective calling tones.
+
+[ToneBank] runs a set of [Goertzel] filters over fixed size blocks of
+samples, and normalizes each tone power to the total block energy, so
+that the thresholds don't depend on signal level.
+
+[Goertzel algorithm]: https://en.wikipedia.org/wiki/Goertzel_algorithm
+*/
+use alloc::vec::Vec;
+
+// Float math. Without std it comes from libm.
+#[allow(unused_imports)]
+use num_traits::Float as _;
+
+use crate::Float;
+
+/// Single frequency Goertzel filter.
+#[derive(Debug, Clone)]
+pub struct Goertzel {
+    coeff: Float,
+    s1: Float,
+    s2: Float,
+}
+
+impl Goertzel {
+    /// Create new filter. The frequency doesn't have to be an exact
+    /// bin frequency.
+    pub fn new(samp_rate: Float, freq: Float) -> Self {
+        Self {
+            coeff: 2.0 * (2.0 * core::f64::consts::PI as Float * freq / samp_rate).cos(),
+            s1: 0.0,
+            s2: 0.0,
+        }
+    }
+
+    /// Add a sample.
+    pub fn push(&mut self, x: Float) {
+        let s = x + self.coeff * self.s1 - self.s2;
+        self.s2 = self.s1;
+        self.s1 = s;
+    }
+
+    /// Power of the tone in the samples pushed since the last reset.
+    pub fn power(&self) -> Float {
+        self.s1 * self.s1 + self.s2 * self.s2 - self.coeff * self.s1 * self.s2
+    }
+
+    /// Reset for the next block.
+    pub fn reset(&mut self) {
+        self.s1 = 0.0;
+        self.s2 = 0.0;
+    }
+}
+
+/// Result of one block in a [ToneBank].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneBlock {
+    /// Power of each tone, as a fraction of the block energy.
+    ///
+    /// A pure tone at exactly the frequency gives 1.0. Two equal
+    /// tones give 0.5 each.
+    pub powers: Vec<Float>,
+
+    /// Mean square of the samples.
+    pub energy: Float,
+}
+
+impl ToneBlock {
+    /// Index and power of the strongest tone in `range`.
+    pub fn strongest(&self, range: core::ops::Range<usize>) -> (usize, Float) {
+        let start = range.start;
+        self.powers[range]
+            .iter()
+            .enumerate()
+            .map(|(n, p)| (n + start, *p))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap()
+    }
+
+    /// Return true if all tones in `range` other than `best` are
+    /// at least `ratio` weaker than it.
+    pub fn dominates(&self, range: core::ops::Range<usize>, best: usize, ratio: Float) -> bool {
+        range
+            .filter(|n| *n != best)
+            .all(|n| self.powers[n] * ratio < self.powers[best])
+    }
+}
+
+/// Bank of Goertzel filters, run over blocks of samples.
+pub struct ToneBank {
+    filters: Vec<Goertzel>,
+    block: usize,
+    n: usize,
+    energy: Float,
+}
+
+impl ToneBank {
+    /// Create new tone bank.
+    ///
+    /// Frequency resolution is about `samp_rate / block`.
+    pub fn new(samp_rate: Float, freqs: &[Float], block: usize) -> Self {
+        Self {
+            filters: freqs.iter().map(|f| Goertzel::new(samp_rate, *f)).collect(),
+            block,
+            n: 0,
+            energy: 0.0,
+        }
+    }
+
+    /// Block size.
+    pub fn block(&self) -> usize {
+        self.block
+    }
+
+    /// Add a sample. Returns the tone powers at the end of each block.
+    pub fn push(&mut self, x: Float) -> Option<ToneBlock> {
+        self.filters.iter_mut().for_each(|f| f.push(x));
+        self.energy += x * x;
+        self.n += 1;
+        if self.n < self.block {
+            return None;
+        }
+        let scale = self.energy * self.block as Float / 2.0;
+        let powers = self
+            .filters
+            .iter()
+            .map(|f| if scale > 0.0 { f.power() / scale } else { 0.0 })
+            .collect();
+        let ret = ToneBlock {
+            powers,
+            energy: self.energy / self.block as Float,
+        };
+        self.filters.iter_mut().for_each(|f| f.reset());
+        self.energy = 0.0;
+        self.n = 0;
+        Some(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn tone(samp_rate: Float, freq: Float, n: usize) -> Vec<Float> {
+        (0..n)
+            .map(|i| {
+                0.3 * (2.0 * core::f64::consts::PI as Float * freq * i as Float / samp_rate).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn single() {
+        let mut bank = ToneBank::new(8000.0, &[697.0, 770.0, 1209.0], 205);
+        let mut got = None;
+        for x in tone(8000.0, 770.0, 205) {
+            got = bank.push(x);
+        }
+        let got = got.unwrap();
+        assert!(got.powers[1] > 0.9, "{got:?}");
+        assert!(got.powers[0] < 0.05, "{got:?}");
+        assert!(got.powers[2] < 0.01, "{got:?}");
+        assert!((got.energy - 0.045).abs() < 0.001);
+        assert_eq!(got.strongest(0..3).0, 1);
+        assert!(got.dominates(0..3, 1, 10.0));
+    }
+
+    #[test]
+    fn silence() {
+        let mut bank = ToneBank::new(8000.0, &[1000.0], 10);
+        let got = (0..10).filter_map(|_| bank.push(0.0)).next().unwrap();
+        assert_eq!(got.powers, vec![0.0]);
+    }
+}
@@ -1,7 +1,8 @@
 /*! Infinite impulse response filter
 
 */
-use std::collections::VecDeque;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
 
 use crate::Float;
 
@@ -49,7 +50,7 @@ pub struct IIRFilter<T: Copy> {
 
 impl<T> IIRFilter<T>
 where
-    T: Copy + Default + std::ops::Mul<T, Output = T> + std::ops::Add<T, Output = T>,
+    T: Copy + Default + core::ops::Mul<T, Output = T> + core::ops::Add<T, Output = T>,
 {
     /// Create new FIR.
     pub fn new(taps: &[T]) -> Self {
@@ -62,7 +63,7 @@ where
 
 impl<T> Filter<T> for IIRFilter<T>
 where
-    T: Copy + Default + std::ops::Mul<T, Output = T> + std::ops::Add<T, Output = T>,
+    T: Copy + Default + core::ops::Mul<T, Output = T> + core::ops::Add<T, Output = T>,
 {
     fn filter(&mut self, sample: T) -> T {
         let mut ret = self.taps[0] * sample;
@@ -85,7 +86,7 @@ where
 
 impl<T> CappedFilter<T> for IIRFilter<T>
 where
-    T: Copy + Default + std::ops::Mul<T, Output = T> + std::ops::Add<T, Output = T> + MinMax,
+    T: Copy + Default + core::ops::Mul<T, Output = T> + core::ops::Add<T, Output = T> + MinMax,
 {
     fn filter_capped(&mut self, sample: T, mi: T, mx: T) -> T {
         let mut ret = self.taps[0] * sample;
@@ -104,7 +105,8 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use anyhow::Result;
+
+    type Result<T> = core::result::Result<T, crate::Error>;
 
     #[test]
     fn zero_pole() -> Result<()> {
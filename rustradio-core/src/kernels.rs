@@ -0,0 +1,661 @@
+/*! Vector kernels for the inner loops of blocks, like [VOLK] in GNU Radio.
+
+On x86_64 the kernels use AVX2 and FMA if the CPU has them. With the
+`std` feature that's detected at runtime, and without it at compile
+time, e.g. with `RUSTFLAGS="-C target-cpu=native"`. On aarch64 they
+use NEON, which is always there. Everything else, and the `f64`
+feature, gets the scalar versions.
+
+Results can differ from the scalar versions in the last bits, since
+sums are added up in a different order.
+
+[VOLK]: https://www.libvolk.org/
+*/
+use core::ops::{Add, Mul};
+
+use crate::{Complex, Float};
+
+/// Types that have a dot product kernel.
+///
+/// The default implementation is a plain loop. `f32` and
+/// `Complex<f32>` use SIMD.
+pub trait Dot: Copy + Default + Mul<Self, Output = Self> + Add<Self, Output = Self> {
+    /// Sum of `a[i] * b[i]`, over the length of the shorter slice.
+    fn dot(a: &[Self], b: &[Self]) -> Self {
+        a.iter()
+            .zip(b)
+            .fold(Self::default(), |acc, (x, y)| acc + *x * *y)
+    }
+}
+
+impl Dot for f32 {
+    fn dot(a: &[f32], b: &[f32]) -> f32 {
+        let n = a.len().min(b.len());
+        simd::dot_f32(&a[..n], &b[..n]).unwrap_or_else(|| scalar::dot_f32(&a[..n], &b[..n]))
+    }
+}
+
+impl Dot for num_complex::Complex<f32> {
+    fn dot(a: &[Self], b: &[Self]) -> Self {
+        let n = a.len().min(b.len());
+        simd::dot_complex(&a[..n], &b[..n]).unwrap_or_else(|| {
+            a.iter()
+                .zip(b)
+                .fold(Self::default(), |acc, (x, y)| acc + x * y)
+        })
+    }
+}
+
+impl Dot for f64 {}
+impl Dot for num_complex::Complex<f64> {}
+
+macro_rules! dot_default {
+    ($($t:ty),*) => {
+        $(impl Dot for $t {})*
+    };
+}
+dot_default!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+/// Multiply `a` by `b`, element by element, in place.
+///
+/// Panics if `b` is shorter than `a`.
+pub fn multiply(a: &mut [Complex], b: &[Complex]) {
+    let b = &b[..a.len()];
+    #[cfg(not(feature = "f64"))]
+    if simd::multiply(a, b) {
+        return;
+    }
+    a.iter_mut().zip(b).for_each(|(x, y)| *x *= y);
+}
+
+/// Set `out[i]` to `a[i] * conj(b[i])`.
+///
+/// The angle of that is the phase difference between the two, which
+/// is how FM is demodulated.
+///
+/// Panics if `b` or `out` is shorter than `a`.
+pub fn multiply_conj(a: &[Complex], b: &[Complex], out: &mut [Complex]) {
+    let (b, out) = (&b[..a.len()], &mut out[..a.len()]);
+    #[cfg(not(feature = "f64"))]
+    if simd::multiply_conj(a, b, out) {
+        return;
+    }
+    for ((o, x), y) in out.iter_mut().zip(a).zip(b) {
+        *o = x * y.conj();
+    }
+}
+
+/// Set `out[i]` to the squared magnitude of `input[i]`.
+///
+/// Panics if `out` is shorter than `input`.
+pub fn mag2(input: &[Complex], out: &mut [Float]) {
+    let out = &mut out[..input.len()];
+    #[cfg(not(feature = "f64"))]
+    if simd::mag2(input, out) {
+        return;
+    }
+    for (o, x) in out.iter_mut().zip(input) {
+        *o = x.norm_sqr();
+    }
+}
+
+/// Convert real samples to complex, with zero imaginary part.
+///
+/// Panics if `out` is shorter than `input`.
+pub fn float_to_complex(input: &[Float], out: &mut [Complex]) {
+    let out = &mut out[..input.len()];
+    #[cfg(not(feature = "f64"))]
+    if simd::float_to_complex(input, out) {
+        return;
+    }
+    for (o, x) in out.iter_mut().zip(input) {
+        *o = Complex::new(*x, 0.0);
+    }
+}
+
+/// Take the real part of complex samples.
+///
+/// Panics if `out` is shorter than `input`.
+pub fn complex_to_real(input: &[Complex], out: &mut [Float]) {
+    let out = &mut out[..input.len()];
+    #[cfg(not(feature = "f64"))]
+    if simd::complex_to_real(input, out) {
+        return;
+    }
+    for (o, x) in out.iter_mut().zip(input) {
+        *o = x.re;
+    }
+}
+
+mod scalar {
+    /// Dot product with independent accumulators, so that the
+    /// compiler can vectorize it without reordering one long sum.
+    pub fn dot_f32(a: &[f32], b: &[f32]) -> f32 {
+        let mut acc = [0.0f32; 8];
+        let mut ca = a.chunks_exact(8);
+        let mut cb = b.chunks_exact(8);
+        for (x, y) in (&mut ca).zip(&mut cb) {
+            for i in 0..8 {
+                acc[i] += x[i] * y[i];
+            }
+        }
+        let tail: f32 = ca
+            .remainder()
+            .iter()
+            .zip(cb.remainder())
+            .map(|(x, y)| x * y)
+            .sum();
+        acc.iter().sum::<f32>() + tail
+    }
+}
+
+// The SIMD versions. Each returns None or false if it can't run on
+// this CPU, and the caller falls back to the scalar version. Slices
+// are already cut to the same length.
+#[allow(unreachable_code, unused_variables)]
+#[cfg_attr(feature = "f64", allow(dead_code))]
+mod simd {
+    type C32 = num_complex::Complex<f32>;
+
+    #[cfg(target_arch = "x86_64")]
+    fn avx2() -> bool {
+        #[cfg(feature = "std")]
+        {
+            std::is_x86_feature_detected!("avx2") && std::is_x86_feature_detected!("fma")
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            cfg!(all(target_feature = "avx2", target_feature = "fma"))
+        }
+    }
+
+    pub fn dot_f32(a: &[f32], b: &[f32]) -> Option<f32> {
+        #[cfg(target_arch = "x86_64")]
+        if avx2() {
+            // SAFETY: CPU support checked above.
+            return Some(unsafe { super::x86::dot_f32(a, b) });
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            // SAFETY: NEON is mandatory on aarch64.
+            return Some(unsafe { super::neon::dot_f32(a, b) });
+        }
+        None
+    }
+
+    pub fn dot_complex(a: &[C32], b: &[C32]) -> Option<C32> {
+        #[cfg(target_arch = "x86_64")]
+        if avx2() {
+            // SAFETY: CPU support checked above.
+            return Some(unsafe { super::x86::dot_complex(a, b) });
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            // SAFETY: NEON is mandatory on aarch64.
+            return Some(unsafe { super::neon::dot_complex(a, b) });
+        }
+        None
+    }
+
+    pub fn multiply(a: &mut [C32], b: &[C32]) -> bool {
+        #[cfg(target_arch = "x86_64")]
+        if avx2() {
+            // SAFETY: CPU support checked above.
+            unsafe { super::x86::multiply(a, b) };
+            return true;
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            // SAFETY: NEON is mandatory on aarch64.
+            unsafe { super::neon::multiply(a, b) };
+            return true;
+        }
+        false
+    }
+
+    pub fn multiply_conj(a: &[C32], b: &[C32], out: &mut [C32]) -> bool {
+        #[cfg(target_arch = "x86_64")]
+        if avx2() {
+            // SAFETY: CPU support checked above.
+            unsafe { super::x86::multiply_conj(a, b, out) };
+            return true;
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            // SAFETY: NEON is mandatory on aarch64.
+            unsafe { super::neon::multiply_conj(a, b, out) };
+            return true;
+        }
+        false
+    }
+
+    pub fn mag2(input: &[C32], out: &mut [f32]) -> bool {
+        #[cfg(target_arch = "x86_64")]
+        if avx2() {
+            // SAFETY: CPU support checked above.
+            unsafe { super::x86::mag2(input, out) };
+            return true;
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            // SAFETY: NEON is mandatory on aarch64.
+            unsafe { super::neon::mag2(input, out) };
+            return true;
+        }
+        false
+    }
+
+    pub fn float_to_complex(input: &[f32], out: &mut [C32]) -> bool {
+        #[cfg(target_arch = "x86_64")]
+        if avx2() {
+            // SAFETY: CPU support checked above.
+            unsafe { super::x86::float_to_complex(input, out) };
+            return true;
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            // SAFETY: NEON is mandatory on aarch64.
+            unsafe { super::neon::float_to_complex(input, out) };
+            return true;
+        }
+        false
+    }
+
+    pub fn complex_to_real(input: &[C32], out: &mut [f32]) -> bool {
+        #[cfg(target_arch = "x86_64")]
+        if avx2() {
+            // SAFETY: CPU support checked above.
+            unsafe { super::x86::complex_to_real(input, out) };
+            return true;
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            // SAFETY: NEON is mandatory on aarch64.
+            unsafe { super::neon::complex_to_real(input, out) };
+            return true;
+        }
+        false
+    }
+}
+
+// Complex<f32> is repr(C), so a slice of n of them is 2n f32s,
+// alternating real and imaginary.
+//
+// All functions require that the slices are of equal length.
+#[cfg(target_arch = "x86_64")]
+#[cfg_attr(feature = "f64", allow(dead_code))]
+mod x86 {
+    use core::arch::x86_64::*;
+
+    type C32 = num_complex::Complex<f32>;
+
+    // Sum of the eight lanes.
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn hsum(v: __m256) -> f32 {
+        let s = _mm_add_ps(_mm256_castps256_ps128(v), _mm256_extractf128_ps(v, 1));
+        let s = _mm_add_ps(s, _mm_movehl_ps(s, s));
+        let s = _mm_add_ss(s, _mm_shuffle_ps(s, s, 1));
+        _mm_cvtss_f32(s)
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn dot_f32(a: &[f32], b: &[f32]) -> f32 {
+        let n = a.len() / 8 * 8;
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut acc0 = _mm256_setzero_ps();
+        let mut acc1 = _mm256_setzero_ps();
+        let mut i = 0;
+        // Two accumulators, to not wait for the previous FMA.
+        while i + 16 <= n {
+            acc0 = _mm256_fmadd_ps(_mm256_loadu_ps(pa.add(i)), _mm256_loadu_ps(pb.add(i)), acc0);
+            acc1 = _mm256_fmadd_ps(
+                _mm256_loadu_ps(pa.add(i + 8)),
+                _mm256_loadu_ps(pb.add(i + 8)),
+                acc1,
+            );
+            i += 16;
+        }
+        if i < n {
+            acc0 = _mm256_fmadd_ps(_mm256_loadu_ps(pa.add(i)), _mm256_loadu_ps(pb.add(i)), acc0);
+        }
+        let tail: f32 = a[n..].iter().zip(&b[n..]).map(|(x, y)| x * y).sum();
+        hsum(_mm256_add_ps(acc0, acc1)) + tail
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn dot_complex(a: &[C32], b: &[C32]) -> C32 {
+        let n = a.len() / 4 * 4;
+        let pa = a.as_ptr() as *const f32;
+        let pb = b.as_ptr() as *const f32;
+        // [ar*br, ai*bi, …] and [ar*bi, ai*br, …].
+        let mut straight = _mm256_setzero_ps();
+        let mut crossed = _mm256_setzero_ps();
+        for i in (0..n).step_by(4) {
+            let x = _mm256_loadu_ps(pa.add(2 * i));
+            let y = _mm256_loadu_ps(pb.add(2 * i));
+            straight = _mm256_fmadd_ps(x, y, straight);
+            crossed = _mm256_fmadd_ps(x, _mm256_permute_ps(y, 0b10_11_00_01), crossed);
+        }
+        // Negate the ai*bi lanes, so both sums are plain sums.
+        let sign = _mm256_setr_ps(0.0, -0.0, 0.0, -0.0, 0.0, -0.0, 0.0, -0.0);
+        let mut ret = C32::new(hsum(_mm256_xor_ps(straight, sign)), hsum(crossed));
+        for (x, y) in a[n..].iter().zip(&b[n..]) {
+            ret += x * y;
+        }
+        ret
+    }
+
+    // (ar + ai*i)(br + bi*i), four at a time.
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn cmul(x: __m256, y: __m256) -> __m256 {
+        let yr = _mm256_moveldup_ps(y);
+        let yi = _mm256_movehdup_ps(y);
+        let xs = _mm256_permute_ps(x, 0b10_11_00_01);
+        // Even lanes ar*br - ai*bi, odd lanes ai*br + ar*bi.
+        _mm256_fmaddsub_ps(x, yr, _mm256_mul_ps(xs, yi))
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn multiply(a: &mut [C32], b: &[C32]) {
+        let n = a.len() / 4 * 4;
+        let pa = a.as_mut_ptr() as *mut f32;
+        let pb = b.as_ptr() as *const f32;
+        for i in (0..n).step_by(4) {
+            let r = cmul(
+                _mm256_loadu_ps(pa.add(2 * i)),
+                _mm256_loadu_ps(pb.add(2 * i)),
+            );
+            _mm256_storeu_ps(pa.add(2 * i), r);
+        }
+        for (x, y) in a[n..].iter_mut().zip(&b[n..]) {
+            *x *= y;
+        }
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn multiply_conj(a: &[C32], b: &[C32], out: &mut [C32]) {
+        let n = a.len() / 4 * 4;
+        let pa = a.as_ptr() as *const f32;
+        let pb = b.as_ptr() as *const f32;
+        let po = out.as_mut_ptr() as *mut f32;
+        let conj = _mm256_setr_ps(0.0, -0.0, 0.0, -0.0, 0.0, -0.0, 0.0, -0.0);
+        for i in (0..n).step_by(4) {
+            let y = _mm256_xor_ps(_mm256_loadu_ps(pb.add(2 * i)), conj);
+            let r = cmul(_mm256_loadu_ps(pa.add(2 * i)), y);
+            _mm256_storeu_ps(po.add(2 * i), r);
+        }
+        for ((o, x), y) in out[n..].iter_mut().zip(&a[n..]).zip(&b[n..]) {
+            *o = x * y.conj();
+        }
+    }
+
+    // hadd and shuffle work within 128 bit lanes, leaving complex
+    // number [0,1,4,5,2,3,6,7]. This puts them back in order.
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn unlane(v: __m256) -> __m256 {
+        _mm256_castpd_ps(_mm256_permute4x64_pd(_mm256_castps_pd(v), 0b11_01_10_00))
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn mag2(input: &[C32], out: &mut [f32]) {
+        let n = input.len() / 8 * 8;
+        let pi = input.as_ptr() as *const f32;
+        let po = out.as_mut_ptr();
+        for i in (0..n).step_by(8) {
+            let x0 = _mm256_loadu_ps(pi.add(2 * i));
+            let x1 = _mm256_loadu_ps(pi.add(2 * i + 8));
+            let s = _mm256_hadd_ps(_mm256_mul_ps(x0, x0), _mm256_mul_ps(x1, x1));
+            _mm256_storeu_ps(po.add(i), unlane(s));
+        }
+        for (o, x) in out[n..].iter_mut().zip(&input[n..]) {
+            *o = x.norm_sqr();
+        }
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn float_to_complex(input: &[f32], out: &mut [C32]) {
+        let n = input.len() / 8 * 8;
+        let pi = input.as_ptr();
+        let po = out.as_mut_ptr() as *mut f32;
+        let zero = _mm256_setzero_ps();
+        for i in (0..n).step_by(8) {
+            let x = _mm256_loadu_ps(pi.add(i));
+            // [x0,0,x1,0 | x4,0,x5,0] and [x2,0,x3,0 | x6,0,x7,0].
+            let lo = _mm256_unpacklo_ps(x, zero);
+            let hi = _mm256_unpackhi_ps(x, zero);
+            _mm256_storeu_ps(po.add(2 * i), _mm256_permute2f128_ps(lo, hi, 0x20));
+            _mm256_storeu_ps(po.add(2 * i + 8), _mm256_permute2f128_ps(lo, hi, 0x31));
+        }
+        for (o, x) in out[n..].iter_mut().zip(&input[n..]) {
+            *o = C32::new(*x, 0.0);
+        }
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn complex_to_real(input: &[C32], out: &mut [f32]) {
+        let n = input.len() / 8 * 8;
+        let pi = input.as_ptr() as *const f32;
+        let po = out.as_mut_ptr();
+        for i in (0..n).step_by(8) {
+            let x0 = _mm256_loadu_ps(pi.add(2 * i));
+            let x1 = _mm256_loadu_ps(pi.add(2 * i + 8));
+            let re = _mm256_shuffle_ps(x0, x1, 0b10_00_10_00);
+            _mm256_storeu_ps(po.add(i), unlane(re));
+        }
+        for (o, x) in out[n..].iter_mut().zip(&input[n..]) {
+            *o = x.re;
+        }
+    }
+}
+
+// Same as the x86 module. vld2q/vst2q split complex numbers into
+// separate real and imaginary vectors, so no shuffling is needed.
+#[cfg(target_arch = "aarch64")]
+#[cfg_attr(feature = "f64", allow(dead_code))]
+mod neon {
+    use core::arch::aarch64::*;
+
+    type C32 = num_complex::Complex<f32>;
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn dot_f32(a: &[f32], b: &[f32]) -> f32 {
+        let n = a.len() / 4 * 4;
+        let mut acc = vdupq_n_f32(0.0);
+        for i in (0..n).step_by(4) {
+            acc = vfmaq_f32(
+                acc,
+                vld1q_f32(a.as_ptr().add(i)),
+                vld1q_f32(b.as_ptr().add(i)),
+            );
+        }
+        let tail: f32 = a[n..].iter().zip(&b[n..]).map(|(x, y)| x * y).sum();
+        vaddvq_f32(acc) + tail
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn dot_complex(a: &[C32], b: &[C32]) -> C32 {
+        let n = a.len() / 4 * 4;
+        let pa = a.as_ptr() as *const f32;
+        let pb = b.as_ptr() as *const f32;
+        let mut re = vdupq_n_f32(0.0);
+        let mut im = vdupq_n_f32(0.0);
+        for i in (0..n).step_by(4) {
+            let x = vld2q_f32(pa.add(2 * i));
+            let y = vld2q_f32(pb.add(2 * i));
+            re = vfmaq_f32(re, x.0, y.0);
+            re = vfmsq_f32(re, x.1, y.1);
+            im = vfmaq_f32(im, x.0, y.1);
+            im = vfmaq_f32(im, x.1, y.0);
+        }
+        let mut ret = C32::new(vaddvq_f32(re), vaddvq_f32(im));
+        for (x, y) in a[n..].iter().zip(&b[n..]) {
+            ret += x * y;
+        }
+        ret
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn multiply(a: &mut [C32], b: &[C32]) {
+        let n = a.len() / 4 * 4;
+        let pa = a.as_mut_ptr() as *mut f32;
+        let pb = b.as_ptr() as *const f32;
+        for i in (0..n).step_by(4) {
+            let x = vld2q_f32(pa.add(2 * i));
+            let y = vld2q_f32(pb.add(2 * i));
+            let re = vfmsq_f32(vmulq_f32(x.0, y.0), x.1, y.1);
+            let im = vfmaq_f32(vmulq_f32(x.0, y.1), x.1, y.0);
+            vst2q_f32(pa.add(2 * i), float32x4x2_t(re, im));
+        }
+        for (x, y) in a[n..].iter_mut().zip(&b[n..]) {
+            *x *= y;
+        }
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn multiply_conj(a: &[C32], b: &[C32], out: &mut [C32]) {
+        let n = a.len() / 4 * 4;
+        let pa = a.as_ptr() as *const f32;
+        let pb = b.as_ptr() as *const f32;
+        let po = out.as_mut_ptr() as *mut f32;
+        for i in (0..n).step_by(4) {
+            let x = vld2q_f32(pa.add(2 * i));
+            let y = vld2q_f32(pb.add(2 * i));
+            let re = vfmaq_f32(vmulq_f32(x.0, y.0), x.1, y.1);
+            let im = vfmsq_f32(vmulq_f32(x.1, y.0), x.0, y.1);
+            vst2q_f32(po.add(2 * i), float32x4x2_t(re, im));
+        }
+        for ((o, x), y) in out[n..].iter_mut().zip(&a[n..]).zip(&b[n..]) {
+            *o = x * y.conj();
+        }
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn mag2(input: &[C32], out: &mut [f32]) {
+        let n = input.len() / 4 * 4;
+        let pi = input.as_ptr() as *const f32;
+        for i in (0..n).step_by(4) {
+            let x = vld2q_f32(pi.add(2 * i));
+            let m = vfmaq_f32(vmulq_f32(x.0, x.0), x.1, x.1);
+            vst1q_f32(out.as_mut_ptr().add(i), m);
+        }
+        for (o, x) in out[n..].iter_mut().zip(&input[n..]) {
+            *o = x.norm_sqr();
+        }
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn float_to_complex(input: &[f32], out: &mut [C32]) {
+        let n = input.len() / 4 * 4;
+        let po = out.as_mut_ptr() as *mut f32;
+        let zero = vdupq_n_f32(0.0);
+        for i in (0..n).step_by(4) {
+            let x = vld1q_f32(input.as_ptr().add(i));
+            vst2q_f32(po.add(2 * i), float32x4x2_t(x, zero));
+        }
+        for (o, x) in out[n..].iter_mut().zip(&input[n..]) {
+            *o = C32::new(*x, 0.0);
+        }
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn complex_to_real(input: &[C32], out: &mut [f32]) {
+        let n = input.len() / 4 * 4;
+        let pi = input.as_ptr() as *const f32;
+        for i in (0..n).step_by(4) {
+            let x = vld2q_f32(pi.add(2 * i));
+            vst1q_f32(out.as_mut_ptr().add(i), x.0);
+        }
+        for (o, x) in out[n..].iter_mut().zip(&input[n..]) {
+            *o = x.re;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    // Deterministic test data, with lengths that exercise the
+    // remainder loops.
+    fn data(n: usize, seed: u32) -> Vec<Float> {
+        let mut s = seed;
+        (0..n)
+            .map(|_| {
+                s = s.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ((s >> 16) & 0x7fff) as Float / 16384.0 - 1.0
+            })
+            .collect()
+    }
+
+    fn cdata(n: usize, seed: u32) -> Vec<Complex> {
+        let d = data(2 * n, seed);
+        d.chunks(2).map(|c| Complex::new(c[0], c[1])).collect()
+    }
+
+    fn close(a: Float, b: Float) -> bool {
+        (a - b).abs() < 1e-4 * (1.0 + b.abs())
+    }
+
+    fn cclose(a: Complex, b: Complex) -> bool {
+        close(a.re, b.re) && close(a.im, b.im)
+    }
+
+    #[test]
+    fn dot() {
+        for n in 0..70 {
+            let (a, b) = (data(n, 1), data(n, 2));
+            let want: Float = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+            let got = Float::dot(&a, &b);
+            assert!(close(got, want), "n={n}: {got} != {want}");
+
+            let (a, b) = (cdata(n, 3), cdata(n, 4));
+            let want = a
+                .iter()
+                .zip(&b)
+                .fold(Complex::default(), |acc, (x, y)| acc + x * y);
+            let got = Complex::dot(&a, &b);
+            assert!(cclose(got, want), "n={n}: {got} != {want}");
+        }
+        // Different lengths.
+        assert_eq!(Float::dot(&[1.0, 2.0, 3.0], &[2.0, 3.0]), 8.0);
+        assert_eq!(i32::dot(&[1, 2, 3], &[2, 3, 4]), 20);
+    }
+
+    #[test]
+    fn elementwise() {
+        for n in 0..40 {
+            let (a, b) = (cdata(n, 5), cdata(n, 6));
+
+            let mut got = a.clone();
+            multiply(&mut got, &b);
+            for i in 0..n {
+                assert!(cclose(got[i], a[i] * b[i]), "n={n} i={i}");
+            }
+
+            let mut got = vec![Complex::default(); n];
+            multiply_conj(&a, &b, &mut got);
+            for i in 0..n {
+                assert!(cclose(got[i], a[i] * b[i].conj()), "n={n} i={i}");
+            }
+
+            let mut got = vec![0.0; n];
+            mag2(&a, &mut got);
+            for i in 0..n {
+                assert!(close(got[i], a[i].norm_sqr()), "n={n} i={i}");
+            }
+
+            complex_to_real(&a, &mut got);
+            assert_eq!(got, a.iter().map(|c| c.re).collect::<Vec<_>>());
+
+            let f = data(n, 7);
+            let mut got = vec![Complex::default(); n];
+            float_to_complex(&f, &mut got);
+            assert_eq!(
+                got,
+                f.iter().map(|x| Complex::new(*x, 0.0)).collect::<Vec<_>>()
+            );
+        }
+    }
+}
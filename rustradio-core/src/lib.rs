@@ -0,0 +1,96 @@
+/*! Pure DSP core of [rustradio], without std.
+
+These are the filters, tone detectors, forward error correction, and
+vector kernels that don't need streams, files, threads, or hardware, so they can run
+on embedded targets like the RP2040 or STM32, with only an allocator:
+
+```text
+$ cargo build -p rustradio-core --target thumbv6m-none-eabi
+```
+
+Everything here is re-exported by rustradio at the same path, e.g.
+`rustradio::viterbi::Decoder`, where the blocks wrap them.
+
+[rustradio]: https://docs.rs/rustradio/latest/rustradio/
+*/
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+#![warn(missing_docs)]
+// Casts between Float and f32 are only unnecessary for one of the two.
+#![allow(clippy::unnecessary_cast)]
+
+extern crate alloc;
+
+use alloc::string::String;
+
+pub mod fir;
+pub mod goertzel;
+pub mod iir_filter;
+pub mod kernels;
+pub mod reed_solomon;
+pub mod viterbi;
+
+/// Float type used. f32, or f64 with the `f64` feature.
+///
+/// The `f64` feature is for things like long integrations, where f32
+/// runs out of precision. Samples in files and over the network are
+/// then f64 too, `cf64` and `rf64` in SigMF.
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+
+/// Float type used. f32, or f64 with the `f64` feature.
+///
+/// The `f64` feature is for things like long integrations, where f32
+/// runs out of precision. Samples in files and over the network are
+/// then f64 too, `cf64` and `rf64` in SigMF.
+#[cfg(feature = "f64")]
+pub type Float = f64;
+
+/// Complex (I/Q) data.
+pub type Complex = num_complex::Complex<Float>;
+
+/// Core error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// Invalid parameter.
+    BadParameter(String),
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Error::BadParameter(msg) => write!(f, "bad parameter: {msg}"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    //! Test helper functions.
+    use super::*;
+
+    /// For testing, assert that two slices are almost equal.
+    ///
+    /// Floating point numbers are almost never exactly equal.
+    pub fn assert_almost_equal_complex(left: &[Complex], right: &[Complex]) {
+        assert_eq!(
+            left.len(),
+            right.len(),
+            "\nleft: {:?}\nright: {:?}",
+            left,
+            right
+        );
+        for i in 0..left.len() {
+            let dist = (left[i] - right[i]).norm_sqr().sqrt();
+            if dist > 0.001 {
+                assert_eq!(
+                    left[i], right[i],
+                    "\nElement {i}:\nleft: {:?}\nright: {:?}",
+                    left, right
+                );
+            }
+        }
+    }
+}
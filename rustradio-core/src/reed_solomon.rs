@@ -0,0 +1,381 @@
+/*! Reed-Solomon encoder and decoder over GF(2^8).
+
+This is a straightforward Berlekamp-Massey / Chien / Forney decoder,
+parameterized the same way as [libfec], so codes from other projects
+can be plugged in by their parameters.
+
+[`ReedSolomon::ccsds()`] gives the CCSDS (255,223) code, and
+[`ReedSolomon::decode_ccsds()`] handles the dual basis representation
+that CCSDS uses on the wire.
+
+[libfec]: https://github.com/quiet/libfec
+*/
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::Error;
+
+const NN: usize = 255;
+const A0: usize = NN;
+
+// Conversion matrix between conventional and dual basis.
+const TAL: [u8; 8] = [0x8d, 0xef, 0xec, 0x86, 0xfa, 0x99, 0xaf, 0x7b];
+
+/// Reed-Solomon codec.
+pub struct ReedSolomon {
+    alpha_to: [u8; 256],
+    index_of: [usize; 256],
+    genpoly: Vec<usize>,
+    fcr: usize,
+    prim: usize,
+    iprim: usize,
+    nroots: usize,
+    to_dual: [u8; 256],
+    from_dual: [u8; 256],
+}
+
+impl ReedSolomon {
+    /// Create new codec.
+    ///
+    /// * `gfpoly`: Field generator polynomial, e.g. 0x11d.
+    /// * `fcr`: First consecutive root, in index form.
+    /// * `prim`: Primitive element used to generate roots, in index form.
+    /// * `nroots`: Number of parity symbols.
+    pub fn new(gfpoly: u16, fcr: usize, prim: usize, nroots: usize) -> Result<Self, Error> {
+        if !(1..NN).contains(&prim) || fcr >= NN || nroots >= NN {
+            return Err(Error::BadParameter(
+                "invalid Reed-Solomon parameters".to_string(),
+            ));
+        }
+        let mut alpha_to = [0u8; 256];
+        let mut index_of = [0usize; 256];
+        index_of[0] = A0;
+        let mut sr: u16 = 1;
+        for (i, alpha) in alpha_to.iter_mut().take(NN).enumerate() {
+            index_of[sr as usize] = i;
+            *alpha = sr as u8;
+            sr <<= 1;
+            if sr & 0x100 != 0 {
+                sr ^= gfpoly;
+            }
+            sr &= NN as u16;
+        }
+        if sr != 1 {
+            return Err(Error::BadParameter(
+                "Reed-Solomon field polynomial is not primitive".to_string(),
+            ));
+        }
+        let modnn = |x: usize| x % NN;
+
+        let mut genpoly = vec![0u8; nroots + 1];
+        genpoly[0] = 1;
+        let mut root = fcr * prim;
+        for i in 0..nroots {
+            genpoly[i + 1] = 1;
+            for j in (1..=i).rev() {
+                genpoly[j] = if genpoly[j] != 0 {
+                    genpoly[j - 1] ^ alpha_to[modnn(index_of[genpoly[j] as usize] + root)]
+                } else {
+                    genpoly[j - 1]
+                };
+            }
+            genpoly[0] = alpha_to[modnn(index_of[genpoly[0] as usize] + root)];
+            root += prim;
+        }
+        let genpoly = genpoly.iter().map(|g| index_of[*g as usize]).collect();
+
+        let mut iprim = 1;
+        while iprim % prim != 0 {
+            iprim += NN;
+        }
+        let iprim = iprim / prim;
+
+        let mut to_dual = [0u8; 256];
+        let mut from_dual = [0u8; 256];
+        for (i, dual) in to_dual.iter_mut().enumerate() {
+            let mut v = 0;
+            for (k, t) in TAL.iter().rev().enumerate() {
+                if i & (1 << k) != 0 {
+                    v ^= t;
+                }
+            }
+            *dual = v;
+            from_dual[v as usize] = i as u8;
+        }
+        Ok(Self {
+            alpha_to,
+            index_of,
+            genpoly,
+            fcr,
+            prim,
+            iprim,
+            nroots,
+            to_dual,
+            from_dual,
+        })
+    }
+
+    /// The CCSDS (255,223) code.
+    pub fn ccsds() -> Self {
+        Self::new(0x187, 112, 11, 32).unwrap()
+    }
+
+    /// Number of parity symbols.
+    pub fn nroots(&self) -> usize {
+        self.nroots
+    }
+
+    fn modnn(x: usize) -> usize {
+        x % NN
+    }
+
+    /// Calculate parity for data.
+    ///
+    /// Data may be shorter than 255-nroots, in which case the code is
+    /// shortened.
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        assert!(data.len() + self.nroots <= NN);
+        let nroots = self.nroots;
+        let mut parity = vec![0u8; nroots];
+        for d in data {
+            let feedback = self.index_of[(d ^ parity[0]) as usize];
+            if feedback != A0 {
+                for (j, p) in parity.iter_mut().enumerate().skip(1) {
+                    *p ^= self.alpha_to[Self::modnn(feedback + self.genpoly[nroots - j])];
+                }
+            }
+            parity.rotate_left(1);
+            parity[nroots - 1] = if feedback != A0 {
+                self.alpha_to[Self::modnn(feedback + self.genpoly[0])]
+            } else {
+                0
+            };
+        }
+        parity
+    }
+
+    /// Correct errors in a codeword (data followed by parity) in place.
+    ///
+    /// Returns the number of corrected symbols, or None if the
+    /// codeword is uncorrectable.
+    pub fn decode(&self, block: &mut [u8]) -> Option<usize> {
+        let nroots = self.nroots;
+        assert!(block.len() > nroots && block.len() <= NN);
+        let pad = NN - block.len();
+        let (alpha_to, index_of) = (&self.alpha_to, &self.index_of);
+        let modnn = Self::modnn;
+
+        // Syndromes.
+        let mut s = vec![block[0] as usize; nroots];
+        for d in &block[1..] {
+            for (i, si) in s.iter_mut().enumerate() {
+                *si = if *si == 0 {
+                    *d as usize
+                } else {
+                    (*d ^ alpha_to[modnn(index_of[*si] + (self.fcr + i) * self.prim)]) as usize
+                };
+            }
+        }
+        if s.iter().all(|x| *x == 0) {
+            return Some(0);
+        }
+        let s: Vec<usize> = s.iter().map(|x| index_of[*x]).collect();
+
+        // Berlekamp-Massey.
+        let mut lambda = vec![0u8; nroots + 1];
+        lambda[0] = 1;
+        let mut b: Vec<usize> = lambda.iter().map(|x| index_of[*x as usize]).collect();
+        let mut t = vec![0u8; nroots + 1];
+        let mut el = 0;
+        for r in 1..=nroots {
+            let mut discr_r = 0u8;
+            for i in 0..r {
+                if lambda[i] != 0 && s[r - i - 1] != A0 {
+                    discr_r ^= alpha_to[modnn(index_of[lambda[i] as usize] + s[r - i - 1])];
+                }
+            }
+            let discr_r = index_of[discr_r as usize];
+            if discr_r == A0 {
+                b.rotate_right(1);
+                b[0] = A0;
+                continue;
+            }
+            t[0] = lambda[0];
+            for i in 0..nroots {
+                t[i + 1] = if b[i] != A0 {
+                    lambda[i + 1] ^ alpha_to[modnn(discr_r + b[i])]
+                } else {
+                    lambda[i + 1]
+                };
+            }
+            if 2 * el < r {
+                el = r - el;
+                for i in 0..=nroots {
+                    b[i] = if lambda[i] == 0 {
+                        A0
+                    } else {
+                        modnn(index_of[lambda[i] as usize] + NN - discr_r)
+                    };
+                }
+            } else {
+                b.rotate_right(1);
+                b[0] = A0;
+            }
+            lambda.copy_from_slice(&t);
+        }
+
+        let lambda: Vec<usize> = lambda.iter().map(|x| index_of[*x as usize]).collect();
+        let deg_lambda = lambda.iter().rposition(|x| *x != A0).unwrap_or(0);
+        if deg_lambda == 0 {
+            return None;
+        }
+
+        // Chien search.
+        let mut reg = lambda.clone();
+        let mut root = Vec::new();
+        let mut loc = Vec::new();
+        let mut k = self.iprim - 1;
+        for i in 1..=NN {
+            let mut q = 1u8;
+            for j in (1..=deg_lambda).rev() {
+                if reg[j] != A0 {
+                    reg[j] = modnn(reg[j] + j);
+                    q ^= alpha_to[reg[j]];
+                }
+            }
+            if q == 0 {
+                root.push(i);
+                loc.push(k);
+                if root.len() == deg_lambda {
+                    break;
+                }
+            }
+            k = modnn(k + self.iprim);
+        }
+        if root.len() != deg_lambda {
+            return None;
+        }
+
+        // Error evaluator polynomial omega = s * lambda mod x^nroots.
+        let deg_omega = deg_lambda - 1;
+        let omega: Vec<usize> = (0..=deg_omega)
+            .map(|i| {
+                let mut tmp = 0u8;
+                for j in 0..=i {
+                    if s[i - j] != A0 && lambda[j] != A0 {
+                        tmp ^= alpha_to[modnn(s[i - j] + lambda[j])];
+                    }
+                }
+                index_of[tmp as usize]
+            })
+            .collect();
+
+        // Forney.
+        for (r, l) in root.iter().zip(loc.iter()) {
+            let mut num1 = 0u8;
+            for (i, o) in omega.iter().enumerate() {
+                if *o != A0 {
+                    num1 ^= alpha_to[modnn(o + i * r)];
+                }
+            }
+            let num2 = alpha_to[modnn(r * (self.fcr + NN - 1))];
+            let mut den = 0u8;
+            let mut i = deg_lambda.min(nroots - 1) & !1;
+            loop {
+                if lambda[i + 1] != A0 {
+                    den ^= alpha_to[modnn(lambda[i + 1] + i * r)];
+                }
+                if i < 2 {
+                    break;
+                }
+                i -= 2;
+            }
+            if den == 0 {
+                return None;
+            }
+            if num1 != 0 {
+                if *l < pad {
+                    return None;
+                }
+                block[l - pad] ^= alpha_to[modnn(
+                    index_of[num1 as usize] + index_of[num2 as usize] + NN - index_of[den as usize],
+                )];
+            }
+        }
+        Some(root.len())
+    }
+
+    /// Calculate parity for data in CCSDS dual basis representation.
+    pub fn encode_ccsds(&self, data: &[u8]) -> Vec<u8> {
+        let conv: Vec<u8> = data.iter().map(|d| self.from_dual[*d as usize]).collect();
+        self.encode(&conv)
+            .iter()
+            .map(|p| self.to_dual[*p as usize])
+            .collect()
+    }
+
+    /// Correct a codeword in CCSDS dual basis representation, in place.
+    pub fn decode_ccsds(&self, block: &mut [u8]) -> Option<usize> {
+        let mut conv: Vec<u8> = block.iter().map(|d| self.from_dual[*d as usize]).collect();
+        let ret = self.decode(&mut conv)?;
+        for (b, c) in block.iter_mut().zip(conv.iter()) {
+            *b = self.to_dual[*c as usize];
+        }
+        Some(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(n: usize, seed: u32) -> Vec<u8> {
+        let mut x = seed;
+        (0..n)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                (x >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ccsds_correct() {
+        let rs = ReedSolomon::ccsds();
+        for nerr in 0..=16 {
+            let mut block = data(223, nerr as u32);
+            block.extend(rs.encode_ccsds(&block));
+            let orig = block.clone();
+            for i in 0..nerr {
+                block[i * 15 + 3] ^= 0x5a;
+            }
+            assert_eq!(rs.decode_ccsds(&mut block), Some(nerr), "errors: {nerr}");
+            assert_eq!(block, orig);
+        }
+    }
+
+    #[test]
+    fn ccsds_too_many() {
+        let rs = ReedSolomon::ccsds();
+        let mut block = data(223, 1);
+        block.extend(rs.encode_ccsds(&block));
+        for i in 0..20 {
+            block[i * 11] ^= 0xff;
+        }
+        assert_eq!(rs.decode_ccsds(&mut block), None);
+    }
+
+    #[test]
+    fn shortened() -> Result<(), Error> {
+        let rs = ReedSolomon::new(0x11d, 0, 1, 8)?;
+        let mut block = data(50, 3);
+        block.extend(rs.encode(&block));
+        let orig = block.clone();
+        block[0] ^= 1;
+        block[57] ^= 0x80;
+        assert_eq!(rs.decode(&mut block), Some(2));
+        assert_eq!(block, orig);
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+/*! Convolutional encoder and soft decision Viterbi decoder.
+
+This implements the "NASA standard" rate 1/2, constraint length 7
+convolutional code, with polynomials 171 and 133 (octal). It's used by
+CCSDS, Meteor-M LRPT, and lots of other things.
+
+Soft bits are Floats, where positive means 1 and negative means 0. The
+magnitude is the confidence.
+*/
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::Float;
+
+/// Constraint length.
+pub const K: usize = 7;
+
+/// First polynomial, in the bit order where the newest bit is the LSB.
+pub const POLY_A: u8 = 0x4f;
+
+/// Second polynomial, in the bit order where the newest bit is the LSB.
+pub const POLY_B: u8 = 0x6d;
+
+const STATES: usize = 1 << (K - 1);
+
+fn parity(x: u8) -> u8 {
+    (x.count_ones() & 1) as u8
+}
+
+/// Rate 1/2 K=7 convolutional encoder.
+#[derive(Default)]
+pub struct Encoder {
+    state: u8,
+}
+
+impl Encoder {
+    /// Create new encoder, with all zero state.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encode one bit, returning the two output bits.
+    pub fn push(&mut self, bit: u8) -> [u8; 2] {
+        self.state = ((self.state << 1) | (bit & 1)) & 0x7f;
+        [parity(self.state & POLY_A), parity(self.state & POLY_B)]
+    }
+
+    /// Encode bits, returning two output bits per input bit.
+    pub fn encode(&mut self, bits: &[u8]) -> Vec<u8> {
+        bits.iter().flat_map(|b| self.push(*b)).collect()
+    }
+}
+
+/// Soft decision Viterbi decoder for the rate 1/2 K=7 code.
+pub struct Decoder {
+    // Expected output bits for (state, input bit), as +1/-1.
+    outputs: Vec<[(Float, Float); 2]>,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    /// Create new decoder.
+    pub fn new() -> Self {
+        let sign = |b: u8| if b == 1 { 1.0 } else { -1.0 };
+        let outputs = (0..STATES)
+            .map(|s| {
+                let mut r = [(0.0, 0.0); 2];
+                for (bit, out) in r.iter_mut().enumerate() {
+                    let reg = (((s as u8) << 1) | bit as u8) & 0x7f;
+                    *out = (sign(parity(reg & POLY_A)), sign(parity(reg & POLY_B)));
+                }
+                r
+            })
+            .collect();
+        Self { outputs }
+    }
+
+    /// Decode a block of soft bits (two per decoded bit).
+    ///
+    /// The encoder start state is not assumed to be known, and the
+    /// traceback starts from the best end state. Bits near the end
+    /// are therefore less reliable, so callers decoding a continuous
+    /// stream should decode with some overlap.
+    pub fn decode(&self, soft: &[Float]) -> Vec<u8> {
+        let n = soft.len() / 2;
+        let mut metrics: Vec<Float> = vec![0.0; STATES];
+        let mut next: Vec<Float> = vec![0.0; STATES];
+
+        // Decision bits: which predecessor was chosen, per state per step.
+        let mut decisions: Vec<u64> = Vec::with_capacity(n);
+        for i in 0..n {
+            let (a, b) = (soft[2 * i], soft[2 * i + 1]);
+            let mut dec = 0u64;
+            for (ns, place) in next.iter_mut().enumerate() {
+                // New state ns = ((s << 1) | bit) & 0x3f. Predecessors
+                // are s0 = ns >> 1 and s1 = (ns >> 1) | 0x20.
+                let bit = ns & 1;
+                let s0 = ns >> 1;
+                let s1 = s0 | (STATES >> 1);
+                let m = |s: usize| {
+                    let (ea, eb) = self.outputs[s][bit];
+                    metrics[s] + a * ea + b * eb
+                };
+                let (m0, m1) = (m(s0), m(s1));
+                if m1 > m0 {
+                    dec |= 1 << ns;
+                    *place = m1;
+                } else {
+                    *place = m0;
+                }
+            }
+            decisions.push(dec);
+            core::mem::swap(&mut metrics, &mut next);
+
+            // Normalize to avoid running out of float precision.
+            let max = metrics.iter().cloned().fold(Float::MIN, Float::max);
+            metrics.iter_mut().for_each(|m| *m -= max);
+        }
+
+        // Traceback.
+        let mut state = metrics
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(n, _)| n)
+            .unwrap_or(0);
+        let mut out = vec![0u8; n];
+        for i in (0..n).rev() {
+            out[i] = (state & 1) as u8;
+            let hi = (decisions[i] >> state) & 1;
+            state = (state >> 1) | ((hi as usize) << (K - 2));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(n: usize) -> Vec<u8> {
+        // Simple LCG, to not need a rand dependency.
+        let mut x: u32 = 12345;
+        (0..n)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                ((x >> 16) & 1) as u8
+            })
+            .collect()
+    }
+
+    fn to_soft(bits: &[u8]) -> Vec<Float> {
+        bits.iter()
+            .map(|b| if *b == 1 { 1.0 } else { -1.0 })
+            .collect()
+    }
+
+    #[test]
+    fn clean() {
+        let input = bits(1000);
+        let enc = Encoder::new().encode(&input);
+        let got = Decoder::new().decode(&to_soft(&enc));
+        assert_eq!(got, input);
+    }
+
+    #[test]
+    fn errors() {
+        let input = bits(2000);
+        let enc = Encoder::new().encode(&input);
+        let mut soft = to_soft(&enc);
+        // Flip every 20th coded bit, and erase every 31st.
+        for i in (0..soft.len()).step_by(20) {
+            soft[i] = -soft[i];
+        }
+        for i in (5..soft.len()).step_by(31) {
+            soft[i] = 0.0;
+        }
+        let got = Decoder::new().decode(&soft);
+        assert_eq!(got, input);
+    }
+
+    #[test]
+    fn unknown_start_state() {
+        let input = bits(600);
+        let enc = Encoder::new().encode(&input);
+        // Start decoding in the middle of the stream.
+        let got = Decoder::new().decode(&to_soft(&enc[200..]));
+        assert_eq!(got[10..], input[110..]);
+    }
+}
@@ -0,0 +1,202 @@
+/*! Derive macros for rustradio.
+
+Use them through the `rustradio` crate, where they're documented. See
+`rustradio::block`.
+*/
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Ident, LitInt, LitStr, Member};
+
+// Settings from `#[rustradio(…)]` on the struct.
+struct BlockAttrs {
+    process: Ident,
+    name: Option<LitStr>,
+    // Integer, or field with the decimation.
+    decim: Option<TokenStream2>,
+}
+
+fn block_attrs(input: &DeriveInput) -> syn::Result<BlockAttrs> {
+    let mut ret = BlockAttrs {
+        process: Ident::new("process_one", Span::call_site()),
+        name: None,
+        decim: None,
+    };
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("rustradio"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("process") {
+                ret.process = meta.value()?.parse()?;
+            } else if meta.path.is_ident("name") {
+                ret.name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("decim") {
+                let v = meta.value()?;
+                ret.decim = Some(if v.peek(LitInt) {
+                    let n: LitInt = v.parse()?;
+                    if n.base10_parse::<usize>()? == 0 {
+                        return Err(Error::new(n.span(), "decim must be at least 1"));
+                    }
+                    quote! { #n }
+                } else {
+                    let field: Ident = v.parse()?;
+                    quote! { self.#field }
+                });
+            } else {
+                return Err(meta.error("unknown rustradio attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(ret)
+}
+
+// Input fields, and output fields with their types.
+type Ports = (Vec<Member>, Vec<(Member, syn::Type)>);
+
+/// Inputs and outputs, from `#[rustradio(input)]` and
+/// `#[rustradio(output)]` on the fields.
+fn ports(input: &DeriveInput) -> syn::Result<Ports> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "Block can only be derived for structs",
+        ));
+    };
+    let mut ins = Vec::new();
+    let mut outs = Vec::new();
+    let fields: Vec<_> = match &data.fields {
+        Fields::Named(f) => f.named.iter().collect(),
+        Fields::Unnamed(f) => f.unnamed.iter().collect(),
+        Fields::Unit => Vec::new(),
+    };
+    for (n, f) in fields.into_iter().enumerate() {
+        let member = match &f.ident {
+            Some(i) => Member::Named(i.clone()),
+            None => Member::Unnamed(n.into()),
+        };
+        for attr in f.attrs.iter().filter(|a| a.path().is_ident("rustradio")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("input") {
+                    ins.push(member.clone());
+                } else if meta.path.is_ident("output") {
+                    outs.push((member.clone(), f.ty.clone()));
+                } else {
+                    return Err(meta.error("expected input or output"));
+                }
+                Ok(())
+            })?;
+        }
+    }
+    if ins.is_empty() || outs.is_empty() {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "need at least one #[rustradio(input)] and one #[rustradio(output)] field",
+        ));
+    }
+    Ok((ins, outs))
+}
+
+fn derive_block_impl(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let attrs = block_attrs(&input)?;
+    let (ins, outs) = ports(&input)?;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let name = attrs
+        .name
+        .unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let process = &attrs.process;
+
+    let in_bind: Vec<_> = (0..ins.len()).map(|n| format_ident!("in{n}")).collect();
+    let in_buf: Vec<_> = (0..ins.len()).map(|n| format_ident!("ibuf{n}")).collect();
+    let out_bind: Vec<_> = (0..outs.len()).map(|n| format_ident!("out{n}")).collect();
+    let out_buf: Vec<_> = (0..outs.len()).map(|n| format_ident!("obuf{n}")).collect();
+    let out_slice: Vec<_> = (0..outs.len())
+        .map(|n| format_ident!("oslice{n}"))
+        .collect();
+    let out_val: Vec<_> = (0..outs.len()).map(|n| format_ident!("v{n}")).collect();
+    let out_members = outs.iter().map(|(m, _)| m);
+
+    // Decimating blocks get a slice of `decim` samples per input.
+    let (decim, args) = match &attrs.decim {
+        None => (quote! { 1usize }, quote! { #(#in_buf.slice()[i]),* }),
+        Some(d) => (
+            quote! { #d },
+            quote! { #(&#in_buf.slice()[i * decim..(i + 1) * decim]),* },
+        ),
+    };
+    let results = if outs.len() == 1 {
+        quote! { #(#out_val)* }
+    } else {
+        quote! { (#(#out_val),*) }
+    };
+
+    // Convenience `out()` for the common case of one output.
+    let out_fn = match outs.as_slice() {
+        [(m, ty)] => quote! {
+            impl #impl_generics #ident #ty_generics #where_clause {
+                /// Return the output stream.
+                pub fn out(&self) -> #ty {
+                    self.#m.clone()
+                }
+            }
+        },
+        _ => quote! {},
+    };
+
+    Ok(quote! {
+        #out_fn
+
+        impl #impl_generics ::rustradio::block::Block for #ident #ty_generics #where_clause {
+            fn block_name(&self) -> &str {
+                #name
+            }
+            fn work(&mut self) -> ::std::result::Result<::rustradio::block::BlockRet, ::rustradio::Error> {
+                let decim: usize = #decim;
+                // Bindings, so that `self` can be borrowed mutably
+                // for the process function.
+                #(let #in_bind = self.#ins.clone();)*
+                #(let #out_bind = self.#out_members.clone();)*
+                let mut tags = ::std::vec::Vec::new();
+                #(
+                    let (#in_buf, t) = #in_bind.read_buf()?;
+                    if tags.is_empty() {
+                        tags = t;
+                    }
+                )*
+                #(let mut #out_buf = #out_bind.write_buf()?;)*
+                let n = [#(#in_buf.len() / decim),*, #(#out_buf.len()),*]
+                    .into_iter()
+                    .min()
+                    .unwrap();
+                if n == 0 {
+                    return Ok(::rustradio::block::BlockRet::Noop);
+                }
+                #(let #out_slice = #out_buf.slice();)*
+                for i in 0..n {
+                    let #results = self.#process(#args);
+                    #(#out_slice[i] = #out_val;)*
+                }
+                let tags: ::std::vec::Vec<_> = tags
+                    .into_iter()
+                    .filter(|t| t.pos() < n * decim)
+                    .map(|t| ::rustradio::stream::Tag::new(t.pos() / decim, t.key().into(), t.val().clone()))
+                    .collect();
+                #(#out_buf.produce(n, &tags);)*
+                #(#in_buf.consume(n * decim);)*
+                Ok(::rustradio::block::BlockRet::Ok)
+            }
+        }
+    })
+}
+
+/// Derive `rustradio::block::Block`. See the `rustradio::block` docs.
+#[proc_macro_derive(Block, attributes(rustradio))]
+pub fn derive_block(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    derive_block_impl(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
@@ -1,15 +1,18 @@
 //! Add two streams.
-use crate::block::{Block, BlockRet};
+use crate::block::Block;
 use crate::stream::{new_streamp, Streamp};
-use crate::Error;
 
-/// Adds a constant value to every sample.
+/// Adds two streams, sample by sample.
+#[derive(Block)]
 pub struct Add<T>
 where
-    T: Copy,
+    T: Copy + std::ops::Add<Output = T>,
 {
+    #[rustradio(input)]
     a: Streamp<T>,
+    #[rustradio(input)]
     b: Streamp<T>,
+    #[rustradio(output)]
     dst: Streamp<T>,
 }
 
@@ -17,7 +20,7 @@ impl<T> Add<T>
 where
     T: Copy + std::ops::Add<Output = T>,
 {
-    /// Create a new AddConst, providing the constant to be added.
+    /// Create a new Add block.
     pub fn new(a: Streamp<T>, b: Streamp<T>) -> Self {
         Self {
             a,
@@ -26,35 +29,30 @@ where
         }
     }
 
-    /// Return the output stream.
-    pub fn out(&self) -> Streamp<T> {
-        self.dst.clone()
+    fn process_one(&self, a: T, b: T) -> T {
+        a + b
     }
 }
 
-impl<T> Block for Add<T>
-where
-    T: Copy + std::ops::Add<Output = T>,
-{
-    fn block_name(&self) -> &str {
-        "Add"
-    }
-    fn work(&mut self) -> Result<BlockRet, Error> {
-        let (a, tags) = self.a.read_buf()?;
-        let (b, _tags) = self.b.read_buf()?;
-        let n = std::cmp::min(a.len(), b.len());
-        if n == 0 {
-            return Ok(BlockRet::Noop);
-        }
-        let mut o = self.dst.write_buf()?;
-        let n = std::cmp::min(n, o.len());
-        let it = a.iter().zip(b.iter()).map(|(x, y)| *x + *y);
-        for (w, samp) in o.slice().iter_mut().take(n).zip(it) {
-            *w = samp;
-        }
-        a.consume(n);
-        b.consume(n);
-        o.produce(n, &tags);
-        Ok(BlockRet::Ok)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockRet;
+    use crate::vector_source::VectorSource;
+
+    #[test]
+    fn add() -> crate::Result<()> {
+        let mut a = VectorSource::new(vec![1u32, 2, 3]);
+        let mut b = VectorSource::new(vec![10u32, 20]);
+        a.work()?;
+        b.work()?;
+        let mut add = Add::new(a.out(), b.out());
+        assert_eq!(add.block_name(), "Add");
+        add.work()?;
+        assert!(matches!(add.work()?, BlockRet::Noop));
+        let o = add.out();
+        let (res, _) = o.read_buf()?;
+        assert_eq!(res.slice(), &[11, 22]);
+        Ok(())
     }
 }
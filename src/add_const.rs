@@ -4,7 +4,7 @@ use crate::stream::{new_streamp, Streamp};
 
 /// Add const value, implemented in terms of Map.
 /// TODO: remove AddConst, below?
-pub fn add_const<T>(src: Streamp<T>, val: T) -> crate::convert::Map<T, T, impl Fn(T) -> T>
+pub fn add_const<T>(src: Streamp<T>, val: T) -> crate::convert::Map<T, T, impl FnMut(T) -> T>
 where
     T: Copy + std::ops::Add<Output = T>,
 {
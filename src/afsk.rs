@@ -0,0 +1,373 @@
+/*! AFSK modulator.
+
+Audio frequency shift keying sends bits as one of two audio tones. The
+default is Bell 202, as used by 1200bps AX.25 packet and APRS: 1200Hz
+for one (mark), and 2200Hz for zero (space).
+
+The phase is continuous across tone changes, and the bit rate doesn't
+need to divide the sample rate, so e.g. 44.1kHz works too.
+
+```
+use rustradio::blocks::{AfskModulator, HdlcFramer, NrziEncode};
+use rustradio::stream::new_nocopy_streamp;
+let frames = new_nocopy_streamp();
+let framer = HdlcFramer::new(frames);
+let nrzi = NrziEncode::new(framer.out());
+let afsk = AfskModulator::new(nrzi.out(), 48000.0)?;
+# Ok::<(), rustradio::Error>(())
+```
+*/
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, Streamp};
+use crate::{Error, Float};
+
+/// Bell 202 mark (one) frequency.
+pub const BELL202_MARK: Float = 1200.0;
+
+/// Bell 202 space (zero) frequency.
+pub const BELL202_SPACE: Float = 2200.0;
+
+/// Bell 202 bit rate.
+pub const BELL202_BAUD: Float = 1200.0;
+
+/// Builder for [AfskModulator].
+pub struct AfskModulatorBuilder {
+    src: Streamp<u8>,
+    samp_rate: Float,
+    mark: Float,
+    space: Float,
+    baud: Float,
+    amplitude: Float,
+}
+
+impl AfskModulatorBuilder {
+    /// Create new builder, for output at `samp_rate`. Defaults to Bell
+    /// 202.
+    pub fn new(src: Streamp<u8>, samp_rate: Float) -> Self {
+        Self {
+            src,
+            samp_rate,
+            mark: BELL202_MARK,
+            space: BELL202_SPACE,
+            baud: BELL202_BAUD,
+            amplitude: 1.0,
+        }
+    }
+
+    /// Set tone frequencies for one and zero.
+    pub fn tones(mut self, mark: Float, space: Float) -> Self {
+        self.mark = mark;
+        self.space = space;
+        self
+    }
+
+    /// Set bit rate.
+    pub fn baud(mut self, baud: Float) -> Self {
+        self.baud = baud;
+        self
+    }
+
+    /// Set output amplitude. Default 1.0.
+    pub fn amplitude(mut self, a: Float) -> Self {
+        self.amplitude = a;
+        self
+    }
+
+    /// Build AfskModulator block.
+    pub fn build(self) -> Result<AfskModulator, Error> {
+        let nyquist = self.samp_rate / 2.0;
+        if self.baud <= 0.0 || self.mark.max(self.space) >= nyquist || self.baud > self.samp_rate {
+            return Err(Error::BadParameter(format!(
+                "AfskModulator: can't send {} baud with tones {} and {} at sample rate {}",
+                self.baud, self.mark, self.space, self.samp_rate
+            )));
+        }
+        let step = |f: Float| 2.0 * std::f64::consts::PI * f as f64 / self.samp_rate as f64;
+        Ok(AfskModulator {
+            src: self.src,
+            dst: new_streamp(),
+            bit: None,
+            clock: 0.0,
+            phase: 0.0,
+            mark_step: step(self.mark),
+            space_step: step(self.space),
+            baud: self.baud as f64,
+            samp_rate: self.samp_rate as f64,
+            amplitude: self.amplitude,
+        })
+    }
+}
+
+/** AFSK modulator block.
+
+Takes bits (as u8), and outputs audio. Tags are moved to the first
+sample of their bit.
+
+See the [module docs](crate::afsk).
+*/
+pub struct AfskModulator {
+    src: Streamp<u8>,
+    dst: Streamp<Float>,
+    // Bit being sent.
+    bit: Option<u8>,
+    // Time into the current bit, in units where a bit is `samp_rate`
+    // long and a sample is `baud` long, to not accumulate rounding
+    // errors.
+    clock: f64,
+    phase: f64,
+    mark_step: f64,
+    space_step: f64,
+    baud: f64,
+    samp_rate: f64,
+    amplitude: Float,
+}
+
+impl AfskModulator {
+    /// Create new Bell 202 AFSK modulator.
+    pub fn new(src: Streamp<u8>, samp_rate: Float) -> Result<Self, Error> {
+        AfskModulatorBuilder::new(src, samp_rate).build()
+    }
+
+    /// Create a builder.
+    pub fn builder(src: Streamp<u8>, samp_rate: Float) -> AfskModulatorBuilder {
+        AfskModulatorBuilder::new(src, samp_rate)
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Float> {
+        self.dst.clone()
+    }
+}
+
+impl Block for AfskModulator {
+    fn block_name(&self) -> &str {
+        "AfskModulator"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (input, tags) = self.src.read_buf()?;
+        let mut o = self.dst.write_buf()?;
+        let inp = input.slice();
+        let out = o.slice();
+        let mut consumed = 0;
+        let mut produced = 0;
+        let mut otags = Vec::new();
+        while produced < out.len() {
+            let bit = match self.bit {
+                Some(b) => b,
+                None => {
+                    let Some(&b) = inp.get(consumed) else {
+                        break;
+                    };
+                    otags.extend(
+                        tags.iter()
+                            .filter(|t| t.pos() == consumed)
+                            .map(|t| t.with_pos(produced)),
+                    );
+                    consumed += 1;
+                    self.bit = Some(b);
+                    b
+                }
+            };
+            out[produced] = self.amplitude * self.phase.cos() as Float;
+            produced += 1;
+            let step = if bit != 0 {
+                self.mark_step
+            } else {
+                self.space_step
+            };
+            self.phase = (self.phase + step) % (2.0 * std::f64::consts::PI);
+            self.clock += self.baud;
+            if self.clock >= self.samp_rate {
+                self.clock -= self.samp_rate;
+                self.bit = None;
+            }
+        }
+        if produced == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        o.produce(produced, &otags);
+        input.consume(consumed);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    // Number of positive going zero crossings.
+    fn crossings(s: &[Float]) -> usize {
+        s.windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count()
+    }
+
+    #[test]
+    fn tones() -> Result<(), Error> {
+        // 12 ones and 12 zeroes is 10ms of each tone.
+        let bits: Vec<u8> = [1u8; 12].into_iter().chain([0; 12]).collect();
+        let mut b = AfskModulator::new(streamp_from_slice(&bits), 48000.0)?;
+        b.work()?;
+        let o = b.out();
+        let (out, _) = o.read_buf()?;
+        let out = out.slice();
+        assert_eq!(out.len(), 24 * 40);
+        assert_eq!(crossings(&out[..480]), 12);
+        assert_eq!(crossings(&out[480..]), 22);
+
+        // Phase continuous.
+        for w in out.windows(2) {
+            assert!((w[1] - w[0]).abs() < 0.3, "{w:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fractional_rate() -> Result<(), Error> {
+        let src = crate::stream::new_streamp();
+        let mut b = AfskModulator::new(src.clone(), 44100.0)?;
+        // Bits arriving one at a time.
+        for _ in 0..1200 {
+            let mut w = src.write_buf()?;
+            w.slice()[0] = 1;
+            w.produce(1, &[]);
+            b.work()?;
+        }
+        // Exactly one second, with 36 or 37 samples per bit.
+        let o = b.out();
+        assert_eq!(o.read_buf()?.0.len(), 44100);
+        Ok(())
+    }
+
+    #[test]
+    fn bad_params() {
+        let src = streamp_from_slice(&[0u8]);
+        assert!(AfskModulator::new(src.clone(), 4000.0).is_err());
+        assert!(AfskModulator::builder(src, 48000.0)
+            .baud(0.0)
+            .build()
+            .is_err());
+    }
+
+    #[cfg(feature = "decoders")]
+    // Send frames through the receive chain of the ax25-1200-rx
+    // example. With `noise`, the audio is sent FM modulated through a
+    // channel with that noise voltage, and signal power 1.
+    fn loopback(frames: &[Vec<u8>], noise: Option<Float>) -> Result<Vec<Vec<u8>>, Error> {
+        use crate::blocks::*;
+        use crate::stream::new_nocopy_streamp;
+
+        let samp_rate = 48000.0;
+        let src = new_nocopy_streamp();
+        for f in frames {
+            src.push(f.clone(), &[]);
+        }
+        let mut blocks: Vec<Box<dyn Block>> = Vec::new();
+        // Enough flags after to push the last frame through the
+        // filters.
+        let framer = HdlcFramer::builder(src).preamble(16).postamble(64).build();
+        let nrzi = NrziEncode::new(framer.out());
+        let afsk = AfskModulator::new(nrzi.out(), samp_rate)?;
+        let mut audio = afsk.out();
+        blocks.push(Box::new(framer));
+        blocks.push(Box::new(nrzi));
+        blocks.push(Box::new(afsk));
+        if let Some(noise) = noise {
+            // 3kHz deviation.
+            let sensitivity = 2.0 * std::f64::consts::PI as Float * 3000.0 / samp_rate;
+            let fm = FrequencyModulator::new(audio, sensitivity);
+            let chan = ChannelModel::builder(fm.out(), samp_rate)
+                .noise_voltage(noise)
+                .build();
+            // Carson bandwidth is 2·(3000+2200).
+            let taps = crate::fir::low_pass_complex(samp_rate, 5500.0, 1000.0);
+            let filter = FIRFilter::new(chan.out(), &taps);
+            let demod = QuadratureDemod::new(filter.out(), 1.0 / sensitivity);
+            audio = demod.out();
+            blocks.push(Box::new(fm));
+            blocks.push(Box::new(chan));
+            blocks.push(Box::new(filter));
+            blocks.push(Box::new(demod));
+        }
+        let hilbert = Hilbert::new(audio, 65);
+        let demod = QuadratureDemod::new(hilbert.out(), 1.0);
+        let taps = crate::fir::low_pass(samp_rate, 1100.0, 100.0);
+        let filter = FIRFilter::new(demod.out(), &taps);
+        let center = 1700.0 * 2.0 * std::f64::consts::PI as Float / samp_rate;
+        let center = AddConst::new(filter.out(), -center);
+        let sync = SymbolSync::new(
+            center.out(),
+            samp_rate / BELL202_BAUD,
+            0.5,
+            Box::new(crate::symbol_sync::TEDZeroCrossing::new()),
+            Box::new(crate::iir_filter::IIRFilter::new(&[0.5, 0.5])),
+        );
+        let slicer = BinarySlicer::new(sync.out());
+        let nrzi_dec = NrziDecode::new(slicer.out());
+        let deframer = HdlcDeframer::new(nrzi_dec.out(), 10, 1500);
+        let out = deframer.out();
+        blocks.push(Box::new(hilbert));
+        blocks.push(Box::new(demod));
+        blocks.push(Box::new(filter));
+        blocks.push(Box::new(center));
+        blocks.push(Box::new(sync));
+        blocks.push(Box::new(slicer));
+        blocks.push(Box::new(nrzi_dec));
+        blocks.push(Box::new(deframer));
+        loop {
+            let mut done = true;
+            for b in &mut blocks {
+                if matches!(b.work()?, BlockRet::Ok) {
+                    done = false;
+                }
+            }
+            if done {
+                break;
+            }
+        }
+        let mut ret = Vec::new();
+        while let Some((f, _)) = out.pop() {
+            ret.push(f);
+        }
+        Ok(ret)
+    }
+
+    #[cfg(feature = "decoders")]
+    fn frames(n: usize) -> Vec<Vec<u8>> {
+        (0..n)
+            .map(|i| {
+                let info = format!(">Test packet number {i}");
+                crate::ax25::Frame::ui("N0CALL", "APRS", &["WIDE1-1"], info.as_bytes())
+                    .unwrap()
+                    .encode()
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "decoders")]
+    #[test]
+    fn loopback_clean() -> Result<(), Error> {
+        let frames = frames(1);
+        assert_eq!(loopback(&frames, None)?, frames);
+        Ok(())
+    }
+
+    #[cfg(feature = "decoders")]
+    #[test]
+    fn loopback_snr() -> Result<(), Error> {
+        // Noise voltage, and the minimum and maximum number of frames
+        // decoded, out of 4. With signal power 1, noise voltage 0.5 is
+        // 6dB SNR over the 48kHz, or about 12dB in the FM channel.
+        // Decoding falls off quickly below that.
+        for (noise, min, max) in [(0.1, 4, 4), (0.5, 4, 4), (1.5, 0, 0)] {
+            let frames = frames(4);
+            let got = loopback(&frames, Some(noise))?;
+            assert!(
+                (min..=max).contains(&got.len()),
+                "noise {noise}: got {} frames",
+                got.len()
+            );
+            assert!(got.iter().all(|f| frames.contains(f)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,590 @@
+/*! APRS packet parser.
+
+Parses the information field of APRS packets, as received in AX.25 UI
+frames, into [AprsPacket]. Supported are:
+
+* Positions, uncompressed and compressed, with and without timestamp,
+  including course/speed and altitude.
+* Mic-E positions, as sent by many mobile radios.
+* Status reports.
+* Messages, including acks and rejects.
+
+Anything else is kept as [Payload::Other].
+
+```text
+HdlcDeframer -> AprsDecode -> (AprsPacket)
+```
+
+```
+use rustradio::aprs::{AprsPacket, Payload};
+use rustradio::ax25::Frame;
+let frame = Frame::ui("N0CALL-9", "APRS", &[], b"!5950.00N/01800.00E>088/036").unwrap();
+let p = AprsPacket::from_frame(&frame).unwrap();
+let Payload::Position(pos) = p.payload else { panic!() };
+assert_eq!(pos.course, Some(88));
+```
+
+Spec: <http://www.aprs.org/doc/APRS101.PDF>
+*/
+use log::{debug, info};
+
+use crate::ax25::Frame;
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp};
+use crate::Error;
+
+const FEET: f64 = 0.3048;
+
+/// A position, with what else position reports can carry.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct Position {
+    /// Latitude, in degrees. Positive is north.
+    pub latitude: f64,
+
+    /// Longitude, in degrees. Positive is east.
+    pub longitude: f64,
+
+    /// Symbol table, `/` or `\` or an overlay character.
+    pub symbol_table: char,
+
+    /// Symbol code.
+    pub symbol: char,
+
+    /// Timestamp as sent, e.g. `092345z`.
+    pub timestamp: Option<String>,
+
+    /// True if the station can receive messages.
+    pub messaging: bool,
+
+    /// Course, in degrees.
+    pub course: Option<u16>,
+
+    /// Speed, in knots.
+    pub speed: Option<f64>,
+
+    /// Altitude, in meters.
+    pub altitude: Option<f64>,
+
+    /// Comment, with course, speed, and altitude removed.
+    pub comment: String,
+}
+
+/// Message to another station.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct Message {
+    /// Callsign the message is for.
+    pub addressee: String,
+
+    /// Message text. Acks and rejects are `ack` and `rej` followed by
+    /// the message ID.
+    pub text: String,
+
+    /// Message ID, if the sender wants an ack.
+    pub id: Option<String>,
+}
+
+/// Decoded information field.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub enum Payload {
+    /// Position report.
+    Position(Position),
+
+    /// Mic-E position report, with its message, e.g. "En Route".
+    MicE(Position, String),
+
+    /// Status report.
+    Status(String),
+
+    /// Message.
+    Message(Message),
+
+    /// Anything else, as the raw information field.
+    Other(String),
+}
+
+impl Default for Payload {
+    fn default() -> Self {
+        Self::Other(String::new())
+    }
+}
+
+/// Parsed APRS packet.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct AprsPacket {
+    /// Source callsign, e.g. `N0CALL-9`.
+    pub src: String,
+
+    /// Destination, e.g. `APRS`.
+    pub dst: String,
+
+    /// Digipeater path, with `*` on the last one that repeated it.
+    pub path: Vec<String>,
+
+    /// The decoded information field.
+    pub payload: Payload,
+}
+
+impl AprsPacket {
+    /// Parse an AX.25 frame. Returns None if it's not an APRS frame.
+    pub fn from_frame(frame: &Frame) -> Option<Self> {
+        if !frame.is_ui() {
+            return None;
+        }
+        let last = frame.digis.iter().rposition(|d| d.h);
+        let path = frame
+            .digis
+            .iter()
+            .enumerate()
+            .map(|(n, d)| {
+                if Some(n) == last {
+                    format!("{d}*")
+                } else {
+                    d.to_string()
+                }
+            })
+            .collect();
+        Some(Self {
+            src: frame.src.to_string(),
+            dst: frame.dst.to_string(),
+            path,
+            payload: parse_info(&frame.dst.call, &frame.info),
+        })
+    }
+
+    /// Parse frame bytes, without FCS.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        Self::from_frame(&Frame::parse(data)?)
+    }
+
+    /// Return the position, for packets that have one.
+    pub fn position(&self) -> Option<&Position> {
+        match &self.payload {
+            Payload::Position(p) | Payload::MicE(p, _) => Some(p),
+            _ => None,
+        }
+    }
+}
+
+impl crate::Len for AprsPacket {
+    fn len(&self) -> usize {
+        1
+    }
+}
+
+fn latin1(b: &[u8]) -> String {
+    b.iter().map(|c| *c as char).collect()
+}
+
+/// Parse an information field. `dst` is the destination callsign,
+/// needed for Mic-E.
+pub fn parse_info(dst: &str, info: &[u8]) -> Payload {
+    let other = || Payload::Other(latin1(info));
+    let Some((&dti, rest)) = info.split_first() else {
+        return other();
+    };
+    let ret = match dti {
+        b'!' | b'=' => parse_position(rest, None, dti == b'='),
+        b'/' | b'@' => rest
+            .get(..7)
+            .and_then(|ts| parse_position(&rest[7..], Some(latin1(ts)), dti == b'@')),
+        b'`' | b'\'' | 0x1c | 0x1d => parse_mic_e(dst, rest),
+        b'>' => Some(Payload::Status(latin1(rest))),
+        b':' => parse_message(rest),
+        _ => None,
+    };
+    ret.unwrap_or_else(other)
+}
+
+// Parse e.g. "4903.50", with spaces for position ambiguity.
+fn parse_degrees(s: &[u8], deg_digits: usize) -> Option<f64> {
+    let s: String = latin1(s).replace(' ', "0");
+    let deg: f64 = s.get(..deg_digits)?.parse().ok()?;
+    let min: f64 = s.get(deg_digits..)?.parse().ok()?;
+    if !(0.0..60.0).contains(&min) {
+        return None;
+    }
+    Some(deg + min / 60.0)
+}
+
+fn base91(s: &[u8]) -> Option<u32> {
+    s.iter().try_fold(0u32, |acc, c| {
+        (33..=123).contains(c).then(|| acc * 91 + (c - 33) as u32)
+    })
+}
+
+fn parse_position(data: &[u8], timestamp: Option<String>, messaging: bool) -> Option<Payload> {
+    let first = *data.first()?;
+    let mut pos = Position {
+        timestamp,
+        messaging,
+        ..Default::default()
+    };
+    let comment = if first.is_ascii_digit() || first == b' ' {
+        // DDMM.mmN/DDDMM.mmW$
+        let d = data.get(..19)?;
+        let lat = parse_degrees(&d[..7], 2)?;
+        pos.latitude = match d[7] {
+            b'N' => lat,
+            b'S' => -lat,
+            _ => return None,
+        };
+        pos.symbol_table = d[8] as char;
+        let lon = parse_degrees(&d[9..17], 3)?;
+        pos.longitude = match d[17] {
+            b'E' => lon,
+            b'W' => -lon,
+            _ => return None,
+        };
+        pos.symbol = d[18] as char;
+        let mut comment = &data[19..];
+
+        // Course/speed.
+        if let Some(ext) = comment.get(..7) {
+            if ext[3] == b'/' {
+                let course = latin1(&ext[..3]).parse::<u16>();
+                let speed = latin1(&ext[4..]).parse::<f64>();
+                if let (Ok(c), Ok(s)) = (course, speed) {
+                    pos.course = Some(c);
+                    pos.speed = Some(s);
+                    comment = &comment[7..];
+                }
+            }
+        }
+        comment
+    } else {
+        // /YYYYXXXX$csT
+        let d = data.get(..13)?;
+        pos.symbol_table = d[0] as char;
+        pos.latitude = 90.0 - base91(&d[1..5])? as f64 / 380926.0;
+        pos.longitude = -180.0 + base91(&d[5..9])? as f64 / 190463.0;
+        pos.symbol = d[9] as char;
+        let (c, s, t) = (d[10], d[11], d[12]);
+        if c != b' ' {
+            let c = c.checked_sub(33)?;
+            let s = s.checked_sub(33)? as i32;
+            let t = t.checked_sub(33)?;
+            if t & 0x18 == 0x10 {
+                // From a GGA sentence, so it's altitude.
+                let cs = c as i32 * 91 + s;
+                pos.altitude = Some(1.002f64.powi(cs) * FEET);
+            } else if c <= 89 {
+                pos.course = Some(c as u16 * 4);
+                pos.speed = Some(1.08f64.powi(s) - 1.0);
+            }
+        }
+        &data[13..]
+    };
+    let mut comment = latin1(comment);
+    if let Some(i) = comment.find("/A=") {
+        if let Some(Ok(ft)) = comment.get(i + 3..i + 9).map(|s| s.parse::<f64>()) {
+            pos.altitude = Some(ft * FEET);
+            comment.replace_range(i..i + 9, "");
+        }
+    }
+    pos.comment = comment;
+    Some(Payload::Position(pos))
+}
+
+fn parse_mic_e(dst: &str, info: &[u8]) -> Option<Payload> {
+    let dst = dst.as_bytes();
+    if dst.len() != 6 || info.len() < 8 {
+        return None;
+    }
+
+    // Latitude digits and flags are in the destination.
+    let mut digits = Vec::with_capacity(6);
+    let mut std_bits = 0;
+    let mut custom_bits = 0;
+    for (n, c) in dst.iter().enumerate() {
+        let (digit, std, custom) = match c {
+            b'0'..=b'9' => (c - b'0', false, false),
+            b'A'..=b'J' => (c - b'A', false, true),
+            b'K' => (0, false, true),
+            b'L' => (0, false, false),
+            b'P'..=b'Y' => (c - b'P', true, false),
+            b'Z' => (0, true, false),
+            _ => return None,
+        };
+        digits.push(b'0' + digit);
+        if n < 3 {
+            std_bits = std_bits << 1 | std as u8;
+            custom_bits = custom_bits << 1 | custom as u8;
+        }
+    }
+    let flag = |n: usize| dst[n] >= b'P';
+    let lat_digits = [&digits[..4], b".", &digits[4..]].concat();
+    let lat = parse_degrees(&lat_digits, 2)?;
+    let latitude = if flag(3) { lat } else { -lat };
+
+    let b = |n: usize| info[n].checked_sub(28);
+    let mut deg = b(0)? as u16;
+    if flag(4) {
+        deg += 100;
+    }
+    if (180..=189).contains(&deg) {
+        deg -= 80;
+    } else if (190..=199).contains(&deg) {
+        deg -= 190;
+    }
+    let mut min = b(1)?;
+    if min >= 60 {
+        min -= 60;
+    }
+    let lon = deg as f64 + (min as f64 + b(2)? as f64 / 100.0) / 60.0;
+    let longitude = if flag(5) { -lon } else { lon };
+
+    let (sp, dc, se) = (b(3)? as u16, b(4)? as u16, b(5)? as u16);
+    let mut speed = sp * 10 + dc / 10;
+    if speed >= 800 {
+        speed -= 800;
+    }
+    let mut course = (dc % 10) * 100 + se;
+    if course >= 400 {
+        course -= 400;
+    }
+
+    let mut rest = &info[8..];
+    let mut altitude = None;
+    for skip in [0, 1] {
+        if rest.get(skip + 3) == Some(&b'}') {
+            if let Some(a) = base91(&rest[skip..skip + 3]) {
+                altitude = Some(a as f64 - 10000.0);
+                rest = &rest[skip + 4..];
+                break;
+            }
+        }
+    }
+
+    const STANDARD: [&str; 8] = [
+        "Emergency",
+        "Priority",
+        "Special",
+        "Committed",
+        "Returning",
+        "In Service",
+        "En Route",
+        "Off Duty",
+    ];
+    let message = if custom_bits != 0 {
+        format!("Custom-{}", 7 - (std_bits | custom_bits))
+    } else if std_bits == 0 {
+        STANDARD[0].to_string()
+    } else {
+        STANDARD[std_bits as usize].to_string()
+    };
+    let pos = Position {
+        latitude,
+        longitude,
+        symbol: info[6] as char,
+        symbol_table: info[7] as char,
+        course: Some(course),
+        speed: Some(speed as f64),
+        altitude,
+        comment: latin1(rest),
+        ..Default::default()
+    };
+    Some(Payload::MicE(pos, message))
+}
+
+fn parse_message(data: &[u8]) -> Option<Payload> {
+    if data.get(9) != Some(&b':') {
+        return None;
+    }
+    let addressee = latin1(&data[..9]).trim_end().to_string();
+    let text = latin1(&data[10..]);
+    let (text, id) = match text.rsplit_once('{') {
+        Some((t, id)) => (t.to_string(), Some(id.to_string())),
+        None => (text, None),
+    };
+    Some(Payload::Message(Message {
+        addressee,
+        text,
+        id,
+    }))
+}
+
+/** APRS decoder block.
+
+Takes AX.25 frames (without FCS), such as from
+[HdlcDeframer][crate::hdlc_deframer::HdlcDeframer], and outputs the
+APRS packets among them.
+*/
+pub struct AprsDecode {
+    src: NoCopyStreamp<Vec<u8>>,
+    dst: NoCopyStreamp<AprsPacket>,
+    decoded: usize,
+    skipped: usize,
+}
+
+impl AprsDecode {
+    /// Create new AprsDecode block.
+    pub fn new(src: NoCopyStreamp<Vec<u8>>) -> Self {
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            decoded: 0,
+            skipped: 0,
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<AprsPacket> {
+        self.dst.clone()
+    }
+}
+
+impl Drop for AprsDecode {
+    fn drop(&mut self) {
+        info!(
+            "AprsDecode: {} packets, {} other frames",
+            self.decoded, self.skipped
+        );
+    }
+}
+
+impl Block for AprsDecode {
+    fn block_name(&self) -> &str {
+        "AprsDecode"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (frame, tags) = match self.src.pop() {
+            None => return Ok(BlockRet::Noop),
+            Some(x) => x,
+        };
+        match AprsPacket::parse(&frame) {
+            Some(p) => {
+                debug!("AprsDecode: {p:?}");
+                self.decoded += 1;
+                self.dst.push(p, &tags);
+            }
+            None => self.skipped += 1,
+        }
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(dst: &str, info: &str) -> Payload {
+        parse_info(dst, info.as_bytes())
+    }
+
+    fn assert_near(got: f64, want: f64) {
+        assert!((got - want).abs() < 0.0001, "got {got} want {want}");
+    }
+
+    #[test]
+    fn uncompressed() {
+        let Payload::Position(p) = parse("APRS", "=4903.50N/07201.75W-Test /A=001234") else {
+            panic!();
+        };
+        assert_near(p.latitude, 49.0 + 3.5 / 60.0);
+        assert_near(p.longitude, -(72.0 + 1.75 / 60.0));
+        assert_eq!((p.symbol_table, p.symbol), ('/', '-'));
+        assert!(p.messaging);
+        assert_near(p.altitude.unwrap(), 1234.0 * FEET);
+        assert_eq!(p.comment, "Test ");
+
+        let Payload::Position(p) = parse("APRS", "@092345z4903.5 S\\07201.7 E>088/036") else {
+            panic!();
+        };
+        assert_eq!(p.timestamp.as_deref(), Some("092345z"));
+        assert_near(p.latitude, -(49.0 + 3.5 / 60.0));
+        assert_near(p.longitude, 72.0 + 1.7 / 60.0);
+        assert_eq!(p.course, Some(88));
+        assert_eq!(p.speed, Some(36.0));
+        assert_eq!(p.comment, "");
+    }
+
+    #[test]
+    fn compressed() {
+        // Examples from the spec.
+        let Payload::Position(p) = parse("APRS", "=/5L!!<*e7>7P[") else {
+            panic!();
+        };
+        assert_near(p.latitude, 49.5);
+        assert_near(p.longitude, -72.75);
+        assert_eq!(p.symbol, '>');
+        assert_eq!(p.course, Some(88));
+        assert!((p.speed.unwrap() - 36.2).abs() < 0.1);
+
+        let Payload::Position(p) = parse("APRS", "!/5L!!<*e7OS]S") else {
+            panic!();
+        };
+        assert!((p.altitude.unwrap() / FEET - 10004.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn mic_e() {
+        // 33°25.64'S 112°07.74'W, 20 knots, course 251.
+        let Payload::MicE(p, msg) = parse("S3R5VT", "`(#fn\"Oj/]Hello") else {
+            panic!();
+        };
+        assert_near(p.latitude, -(33.0 + 25.64 / 60.0));
+        assert_near(p.longitude, -(112.0 + 7.74 / 60.0));
+        assert_eq!(p.speed, Some(20.0));
+        assert_eq!(p.course, Some(251));
+        assert_eq!((p.symbol_table, p.symbol), ('/', 'j'));
+        assert_eq!(msg, "In Service");
+        assert_eq!(p.comment, "]Hello");
+
+        // Altitude, in meters.
+        let Payload::MicE(p, msg) = parse("S32U6T", "`(#fn\"Oj/\"4T}") else {
+            panic!();
+        };
+        assert_eq!(p.altitude, Some(61.0));
+        assert_eq!(p.comment, "");
+        assert_eq!(msg, "Returning");
+    }
+
+    #[test]
+    fn status_and_message() {
+        assert_eq!(
+            parse("APRS", ">On the air"),
+            Payload::Status("On the air".into())
+        );
+        assert_eq!(
+            parse("APRS", ":N0CALL-1 :Hello there{42"),
+            Payload::Message(Message {
+                addressee: "N0CALL-1".into(),
+                text: "Hello there".into(),
+                id: Some("42".into()),
+            })
+        );
+        assert_eq!(
+            parse("APRS", ":N0CALL   :ack42"),
+            Payload::Message(Message {
+                addressee: "N0CALL".into(),
+                text: "ack42".into(),
+                id: None,
+            })
+        );
+        for bad in ["", "T#005", "!4903.50X/07201.75W-", ":SHORT:x", "`x"] {
+            assert_eq!(parse("APRS", bad), Payload::Other(bad.into()), "{bad}");
+        }
+    }
+
+    #[test]
+    fn block() -> Result<(), Error> {
+        let src = new_nocopy_streamp();
+        let mut frame = Frame::ui("N0CALL-9", "APRS", &["WIDE1", "WIDE2-1"], b">Hi").unwrap();
+        frame.digis[0].h = true;
+        src.push(frame.encode(), &[]);
+        let mut other = frame.clone();
+        other.pid = Some(0xcc);
+        src.push(other.encode(), &[]);
+        let mut b = AprsDecode::new(src);
+        b.work()?;
+        b.work()?;
+        let o = b.out();
+        let (p, _) = o.pop().unwrap();
+        assert_eq!(p.src, "N0CALL-9");
+        assert_eq!(p.path, vec!["WIDE1*", "WIDE2-1"]);
+        assert_eq!(p.payload, Payload::Status("Hi".into()));
+        assert!(p.position().is_none());
+        assert!(o.pop().is_none());
+        Ok(())
+    }
+}
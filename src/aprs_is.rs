@@ -0,0 +1,322 @@
+/*! APRS-IS client sink.
+
+Uploads received APRS packets to [APRS-IS], making a receive-only
+igate. Takes AX.25 frames (without FCS) as PDUs, and sends them in
+TNC2 format with the igate's callsign appended to the path as
+`qAR,CALL`.
+
+Following the igate rules, packets are not gated if:
+
+* They're not UI frames with no layer 3 protocol.
+* The path contains `TCPIP`, `TCPXX`, `NOGATE` or `RFONLY`.
+* They're third party traffic (info starts with `}`).
+
+Duplicates heard via several digipeaters should be removed before this
+block, with a [PduFilter][crate::pdu_filter::PduFilter] using
+[Frame::dedup_key].
+
+```text
+HdlcDeframer -> ToPdu -> PduFilter -> AprsIsSink
+```
+
+[APRS-IS]: https://www.aprs-is.net/
+*/
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{debug, info, warn};
+
+use crate::ax25::Frame;
+use crate::block::{Block, BlockRet};
+use crate::net::{self, Backoff};
+use crate::pdu::Pdu;
+use crate::stream::NoCopyStreamp;
+use crate::Error;
+
+const NO_GATE: &[&str] = &["TCPIP", "TCPXX", "NOGATE", "RFONLY"];
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const BACKOFF_MIN: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(300);
+
+/// Calculate the APRS-IS passcode for a callsign. The SSID is ignored.
+pub fn passcode(call: &str) -> u16 {
+    let call = call.split('-').next().unwrap_or("").to_ascii_uppercase();
+    let mut hash: u16 = 0x73e2;
+    for pair in call.as_bytes().chunks(2) {
+        hash ^= (pair[0] as u16) << 8;
+        if let Some(b) = pair.get(1) {
+            hash ^= *b as u16;
+        }
+    }
+    hash & 0x7fff
+}
+
+/// Return the TNC2 line to upload for a frame, or None if it should
+/// not be gated.
+pub fn igate_line(frame: &Frame, call: &str) -> Option<String> {
+    if !frame.is_ui() || frame.info.first() == Some(&b'}') {
+        return None;
+    }
+    if frame
+        .digis
+        .iter()
+        .any(|d| NO_GATE.contains(&d.call.as_str()))
+    {
+        return None;
+    }
+    // TNC2 lines can't contain line breaks.
+    if frame.info.iter().any(|b| *b == b'\r' || *b == b'\n') {
+        return None;
+    }
+    let tnc2 = frame.to_tnc2();
+    let (head, info) = tnc2.split_once(':')?;
+    Some(format!("{head},qAR,{call}:{info}"))
+}
+
+/** APRS-IS sink block.
+
+Connects and logs in when created. If the connection is lost, it
+reconnects when the next packet arrives. After a failed connection
+attempt it waits before trying again, starting at 1s and doubling up
+to 5 minutes, and packets arriving in the meantime are dropped.
+*/
+pub struct AprsIsSink {
+    src: NoCopyStreamp<Pdu>,
+    server: String,
+    login: String,
+    call: String,
+    conn: Option<TcpStream>,
+    backoff: Backoff,
+    sent: usize,
+    skipped: usize,
+    failed: usize,
+}
+
+impl AprsIsSink {
+    /// Create new APRS-IS sink.
+    ///
+    /// * `server`: e.g. `"rotate.aprs2.net:14580"`.
+    /// * `call`: igate callsign, with SSID if any.
+    /// * `passcode`: if None, calculated from the callsign.
+    /// * `filter`: server side filter, e.g. `"r/59.8/18.0/50"`. Only
+    ///   affects what the server sends, which this block ignores.
+    pub fn new(
+        src: NoCopyStreamp<Pdu>,
+        server: &str,
+        call: &str,
+        passcode: Option<u16>,
+        filter: Option<&str>,
+    ) -> Result<Self> {
+        let call = call.to_ascii_uppercase();
+        let pass = passcode.unwrap_or_else(|| self::passcode(&call));
+        let mut login = format!(
+            "user {call} pass {pass} vers rustradio {}",
+            env!("CARGO_PKG_VERSION")
+        );
+        if let Some(filter) = filter {
+            login.push_str(&format!(" filter {filter}"));
+        }
+        login.push_str("\r\n");
+        let mut ret = Self {
+            src,
+            server: server.to_string(),
+            login,
+            call,
+            conn: None,
+            backoff: Backoff::new(BACKOFF_MIN, BACKOFF_MAX),
+            sent: 0,
+            skipped: 0,
+            failed: 0,
+        };
+        ret.connect()?;
+        Ok(ret)
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        info!("AprsIsSink: connecting to {}", self.server);
+        let ret = self.open();
+        match &ret {
+            Ok(()) => self.backoff.succeeded(),
+            Err(_) => self.backoff.failed(),
+        }
+        ret
+    }
+
+    fn open(&mut self) -> Result<()> {
+        let mut conn = net::connect(&self.server, TIMEOUT)?;
+        conn.set_read_timeout(Some(TIMEOUT))?;
+        conn.set_write_timeout(Some(TIMEOUT))?;
+        conn.write_all(self.login.as_bytes())?;
+        conn.set_nonblocking(true)?;
+        self.conn = Some(conn);
+        Ok(())
+    }
+
+    // Discard whatever the server sent, such as keepalives.
+    fn drain(&mut self) {
+        let Some(conn) = &mut self.conn else {
+            return;
+        };
+        let mut buf = [0u8; 4096];
+        loop {
+            match conn.read(&mut buf) {
+                Ok(0) => {
+                    warn!("AprsIsSink: server closed connection");
+                    self.conn = None;
+                    return;
+                }
+                Ok(n) => debug!(
+                    "AprsIsSink: server: {}",
+                    String::from_utf8_lossy(&buf[..n]).trim_end()
+                ),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    warn!("AprsIsSink: read error: {e}");
+                    self.conn = None;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn send(&mut self, line: &str) -> Result<()> {
+        if self.conn.is_none() {
+            if !self.backoff.ready() {
+                return Err(Error::new("not connected, waiting to reconnect").into());
+            }
+            self.connect()?;
+        }
+        let conn = self.conn.as_mut().unwrap();
+        conn.set_nonblocking(false)?;
+        let ret = conn.write_all(format!("{line}\r\n").as_bytes());
+        conn.set_nonblocking(true)?;
+        if let Err(e) = ret {
+            self.conn = None;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AprsIsSink {
+    fn drop(&mut self) {
+        info!(
+            "AprsIsSink: sent {}, not gated {}, failed {}",
+            self.sent, self.skipped, self.failed
+        );
+    }
+}
+
+impl Block for AprsIsSink {
+    fn block_name(&self) -> &str {
+        "AprsIsSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        self.drain();
+        let Some((pdu, _tags)) = self.src.pop() else {
+            return Ok(BlockRet::Noop);
+        };
+        let Some(line) = Frame::parse(pdu.data()).and_then(|f| igate_line(&f, &self.call)) else {
+            self.skipped += 1;
+            return Ok(BlockRet::Ok);
+        };
+        debug!("AprsIsSink: sending {line}");
+        match self.send(&line) {
+            Ok(()) => self.sent += 1,
+            Err(e) => {
+                warn!("AprsIsSink: failed to send: {e}");
+                self.failed += 1;
+            }
+        }
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ax25::tests::encode;
+    use crate::stream::new_nocopy_streamp;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    #[test]
+    fn passcodes() {
+        assert_eq!(passcode("N0CALL"), 13023);
+        assert_eq!(passcode("n0call-10"), 13023);
+    }
+
+    #[test]
+    fn gating() {
+        let f = |digis: &[(&str, bool)], info: &[u8]| {
+            let f = Frame::parse(&encode("N0CALL-9", "APRS", digis, info)).unwrap();
+            igate_line(&f, "M0GATE")
+        };
+        assert_eq!(
+            f(&[("WIDE1", true)], b">hello").unwrap(),
+            "N0CALL-9>APRS,WIDE1*,qAR,M0GATE:>hello"
+        );
+        assert!(f(&[("RFONLY", false)], b">hello").is_none());
+        assert!(f(&[], b"}M0X>APRS:>hi").is_none());
+    }
+
+    #[test]
+    fn upload() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?.to_string();
+        let src = new_nocopy_streamp();
+        let mut sink = AprsIsSink::new(src.clone(), &addr, "m0gate", None, Some("m/10"))?;
+        let (conn, _) = listener.accept()?;
+        conn.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut conn = BufReader::new(conn);
+
+        src.push(Pdu::new(encode("N0CALL", "APRS", &[], b">hi")), &[]);
+        sink.work()?;
+
+        let mut line = String::new();
+        conn.read_line(&mut line)?;
+        assert_eq!(
+            line,
+            format!(
+                "user M0GATE pass {} vers rustradio {} filter m/10\r\n",
+                passcode("M0GATE"),
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        line.clear();
+        conn.read_line(&mut line)?;
+        assert_eq!(line, "N0CALL>APRS,qAR,M0GATE:>hi\r\n");
+        Ok(())
+    }
+
+    #[test]
+    fn server_down() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?.to_string();
+        let src = new_nocopy_streamp();
+        let mut sink = AprsIsSink::new(src.clone(), &addr, "m0gate", None, None)?;
+        drop(listener.accept()?);
+        drop(listener);
+        sink.conn = None;
+
+        // First packet tries to reconnect, and fails.
+        src.push(Pdu::new(encode("N0CALL", "APRS", &[], b">hi")), &[]);
+        sink.work()?;
+        assert_eq!(sink.failed, 1);
+        assert!(!sink.backoff.ready());
+
+        // The next ones don't even try, until the backoff expires.
+        let start = std::time::Instant::now();
+        for _ in 0..10 {
+            src.push(Pdu::new(encode("N0CALL", "APRS", &[], b">hi")), &[]);
+            sink.work()?;
+        }
+        assert_eq!(sink.failed, 11);
+        assert_eq!(sink.sent, 0);
+        assert!(start.elapsed() < BACKOFF_MIN);
+        Ok(())
+    }
+}
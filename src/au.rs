@@ -10,6 +10,7 @@ It's also much simpler.
 */
 
 use anyhow::Result;
+use log::debug;
 
 use crate::block::{Block, BlockRet};
 use crate::stream::{new_streamp, Streamp};
@@ -149,8 +150,8 @@ enum DecodeState {
 
 /// .au file decoder.
 ///
-/// Currently only accepts a very narrow header format of PCM16, mono,
-/// 44100 Hz.
+/// Currently only accepts PCM16 mono. The sample rate is not checked,
+/// so it has to be known by the caller.
 pub struct AuDecode {
     src: Streamp<u8>,
     dst: Streamp<Float>,
@@ -200,8 +201,10 @@ impl Block for AuDecode {
                 let len = i.iter().take(4).copied().collect::<Vec<_>>();
                 let len = u32::from_be_bytes(len.try_into().unwrap());
                 i.consume(4);
-                assert_eq!(len, 44);
-                self.state = DecodeState::WaitingHeader(len as usize);
+                // Data offset counts from start of file, and we've
+                // already consumed 8 bytes.
+                assert!(len >= 24, "au data offset too small: {len}");
+                self.state = DecodeState::WaitingHeader(len as usize - 8);
             }
             DecodeState::WaitingHeader(len) => {
                 if i.len() < len {
@@ -212,11 +215,10 @@ impl Block for AuDecode {
                     Encoding::PCM16 as u32,
                     u32::from_be_bytes(head[4..8].try_into().unwrap())
                 );
-                assert_eq!(
-                    44100u32,
-                    u32::from_be_bytes(head[8..12].try_into().unwrap())
-                );
+                let rate = u32::from_be_bytes(head[8..12].try_into().unwrap());
+                debug!("AuDecode: sample rate {rate}");
                 assert_eq!(1u32, u32::from_be_bytes(head[12..16].try_into().unwrap()));
+                i.consume(len);
                 self.state = DecodeState::Data;
             }
             DecodeState::Data => {
@@ -0,0 +1,401 @@
+/*! Play audio on the sound card.
+
+[AudioSink] takes Float samples, and plays them on the default ALSA
+output device, by piping them to `aplay`. Any other player, or a file,
+can be given as [output](AudioSinkBuilder::output) instead, as a
+writer taking signed 16 bit little endian mono samples.
+
+```no_run
+use rustradio::blocks::{AudioSink, Map, SignalSourceComplex};
+use rustradio::graph::Graph;
+let mut g = Graph::new();
+let src = SignalSourceComplex::new(8000.0, 440.0, 0.3);
+let re = Map::new(src.out(), |s| s.re);
+let sink = AudioSink::builder(re.out(), 8000.0).build()?;
+g.add(Box::new(src));
+g.add(Box::new(re));
+g.add(Box::new(sink));
+g.run()?;
+# Ok::<(), anyhow::Error>(())
+```
+
+The sound card and the signal source run on different clocks. An
+RTL-SDR that's 50ppm off will, at 48kHz, be off by 2.4 samples per
+second, which eventually either underruns or fills any buffer. So the
+samples are queued, and resampled to the device rate with a ratio
+that's slowly adjusted to keep the queue at the configured
+[latency](AudioSinkBuilder::latency).
+
+If the queue runs dry anyway, silence is played. If it grows to twice
+the latency, the sink stops reading its input until the queue has
+drained, so that faster than realtime sources, like files, are slowed
+down to the playback rate instead of losing audio.
+*/
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use log::{debug, warn};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::Streamp;
+use crate::{Error, Float};
+
+// How much of a relative error in queue size changes the ratio.
+const GAIN: Float = 0.01;
+
+// Max relative adjustment of the ratio.
+const MAX_ADJUST: Float = 0.01;
+
+// Length of each write to the device.
+const PERIOD: Duration = Duration::from_millis(10);
+
+// Linear interpolating resampler, with a variable ratio.
+struct Resampler {
+    // Next output is at `mu` between `last` and the next input sample.
+    mu: Float,
+    last: Float,
+}
+
+impl Resampler {
+    fn new() -> Self {
+        Self { mu: 0.0, last: 0.0 }
+    }
+
+    // Resample with `step` input samples per output sample.
+    fn process(&mut self, input: &[Float], step: Float, out: &mut Vec<Float>) {
+        for &x in input {
+            while self.mu < 1.0 {
+                out.push(self.last + (x - self.last) * self.mu);
+                self.mu += step;
+            }
+            self.mu -= 1.0;
+            self.last = x;
+        }
+    }
+}
+
+// State shared with the player thread.
+struct Shared {
+    queue: Mutex<VecDeque<Float>>,
+    cond: Condvar,
+    done: AtomicBool,
+    failed: AtomicBool,
+}
+
+/// Builder for [AudioSink].
+pub struct AudioSinkBuilder {
+    src: Streamp<Float>,
+    samp_rate: Float,
+    device_rate: u32,
+    device: Option<String>,
+    latency: Duration,
+    output: Option<Box<dyn Write + Send>>,
+}
+
+impl AudioSinkBuilder {
+    /// Create new builder, for input at `samp_rate`.
+    pub fn new(src: Streamp<Float>, samp_rate: Float) -> Self {
+        Self {
+            src,
+            samp_rate,
+            device_rate: 48000,
+            device: None,
+            latency: Duration::from_millis(200),
+            output: None,
+        }
+    }
+
+    /// Set sample rate to play at. Default 48000.
+    pub fn device_rate(mut self, rate: u32) -> Self {
+        self.device_rate = rate;
+        self
+    }
+
+    /// Set ALSA device name, as given to `aplay -D`. Default is the
+    /// default device.
+    pub fn device(mut self, name: &str) -> Self {
+        self.device = Some(name.to_string());
+        self
+    }
+
+    /// Set target latency, i.e. queue size. Default 200ms.
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Write S16_LE samples at the device rate to `w`, instead of
+    /// playing them with `aplay`.
+    ///
+    /// Writes are expected to block at the pace the samples are
+    /// played.
+    pub fn output(mut self, w: Box<dyn Write + Send>) -> Self {
+        self.output = Some(w);
+        self
+    }
+
+    /// Build AudioSink block, starting playback.
+    pub fn build(self) -> Result<AudioSink> {
+        if self.samp_rate <= 0.0 || self.device_rate == 0 {
+            return Err(Error::BadParameter(format!(
+                "AudioSink rates must be positive, got {} and {}",
+                self.samp_rate, self.device_rate
+            ))
+            .into());
+        }
+        let (output, child): (Box<dyn Write + Send>, _) = match self.output {
+            Some(w) => (w, None),
+            None => {
+                let mut cmd = std::process::Command::new("aplay");
+                cmd.args(["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-r"])
+                    .arg(self.device_rate.to_string())
+                    .stdin(std::process::Stdio::piped());
+                if let Some(dev) = &self.device {
+                    cmd.arg("-D").arg(dev);
+                }
+                debug!("AudioSink: running {cmd:?}");
+                let mut child = cmd
+                    .spawn()
+                    .map_err(|e| Error::new(&format!("failed to run aplay: {e}")))?;
+                let stdin = child.stdin.take().expect("stdin is piped");
+                (Box::new(stdin), Some(child))
+            }
+        };
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(VecDeque::new()),
+            cond: Condvar::new(),
+            done: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+        });
+        let chunk = ((self.device_rate as f64 * PERIOD.as_secs_f64()) as usize).max(1);
+        let thread = {
+            let shared = shared.clone();
+            std::thread::spawn(move || play(shared, output, chunk))
+        };
+        Ok(AudioSink {
+            src: self.src,
+            shared,
+            resampler: Resampler::new(),
+            ratio: self.samp_rate / self.device_rate as Float,
+            target: ((self.device_rate as f64 * self.latency.as_secs_f64()) as usize).max(chunk),
+            thread: Some(thread),
+            child,
+        })
+    }
+}
+
+// Write queued samples to the device, `chunk` at a time, until done.
+fn play(shared: Arc<Shared>, mut out: Box<dyn Write + Send>, chunk: usize) {
+    let mut bytes = Vec::with_capacity(chunk * 2);
+    loop {
+        let deadline = Instant::now() + PERIOD;
+        let mut q = shared.queue.lock().unwrap();
+        while q.len() < chunk && !shared.done.load(Ordering::Relaxed) {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            q = shared.cond.wait_timeout(q, deadline - now).unwrap().0;
+        }
+        if q.is_empty() && shared.done.load(Ordering::Relaxed) {
+            break;
+        }
+        let n = chunk.min(q.len());
+        bytes.clear();
+        for s in q.drain(..n) {
+            let s = (s.clamp(-1.0, 1.0) * i16::MAX as Float) as i16;
+            bytes.extend(s.to_le_bytes());
+        }
+        drop(q);
+
+        // Keep the device going with silence, if there's not enough.
+        bytes.resize(chunk * 2, 0);
+        if let Err(e) = out.write_all(&bytes) {
+            warn!("AudioSink: write failed: {e}");
+            shared.failed.store(true, Ordering::Relaxed);
+            break;
+        }
+    }
+    if let Err(e) = out.flush() {
+        warn!("AudioSink: flush failed: {e}");
+    }
+}
+
+/// Play audio. See the [module docs](crate::audio_sink).
+pub struct AudioSink {
+    src: Streamp<Float>,
+    shared: Arc<Shared>,
+    resampler: Resampler,
+    ratio: Float,
+    target: usize,
+    thread: Option<std::thread::JoinHandle<()>>,
+    child: Option<std::process::Child>,
+}
+
+impl AudioSink {
+    /// Create a builder.
+    pub fn builder(src: Streamp<Float>, samp_rate: Float) -> AudioSinkBuilder {
+        AudioSinkBuilder::new(src, samp_rate)
+    }
+
+    /// Create new AudioSink, playing on the default device.
+    pub fn new(src: Streamp<Float>, samp_rate: Float) -> Result<Self> {
+        AudioSinkBuilder::new(src, samp_rate).build()
+    }
+}
+
+impl Drop for AudioSink {
+    fn drop(&mut self) {
+        // Let the player drain the queue.
+        self.shared.done.store(true, Ordering::Relaxed);
+        self.shared.cond.notify_one();
+        if let Some(t) = self.thread.take() {
+            if t.join().is_err() {
+                warn!("AudioSink: player thread panicked");
+            }
+        }
+        // The player closed the pipe, so this finishes playing.
+        if let Some(mut c) = self.child.take() {
+            if let Err(e) = c.wait() {
+                warn!("AudioSink: waiting for aplay: {e}");
+            }
+        }
+    }
+}
+
+impl Block for AudioSink {
+    fn block_name(&self) -> &str {
+        "AudioSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        if self.shared.failed.load(Ordering::Relaxed) {
+            return Err(Error::new("AudioSink: audio output failed"));
+        }
+        let (input, _tags) = self.src.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let fill = self.shared.queue.lock().unwrap().len();
+        let max = 2 * self.target;
+        if fill >= max {
+            // Let upstream wait for the player.
+            return Ok(BlockRet::Pending);
+        }
+        let err = (fill as Float - self.target as Float) / self.target as Float;
+        let step = self.ratio * (1.0 + (err * GAIN).clamp(-MAX_ADJUST, MAX_ADJUST));
+        // Only read as much as there's room for in the queue.
+        let n = std::cmp::min(input.len(), ((max - fill) as Float * step).ceil() as usize);
+        let mut out = Vec::with_capacity((n as Float / step) as usize + 1);
+        self.resampler.process(&input.slice()[..n], step, &mut out);
+        input.consume(n);
+
+        self.shared.queue.lock().unwrap().extend(out);
+        self.shared.cond.notify_one();
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    #[test]
+    fn resample() {
+        let mut r = Resampler::new();
+        let mut out = Vec::new();
+        r.process(&[0.0, 1.0, 2.0, 3.0], 0.5, &mut out);
+        assert_eq!(out, vec![0.0, 0.0, 0.0, 0.5, 1.0, 1.5, 2.0, 2.5]);
+
+        // Continues where it left off.
+        out.clear();
+        r.process(&[4.0, 5.0, 6.0, 7.0], 2.0, &mut out);
+        assert_eq!(out, vec![3.0, 5.0]);
+    }
+
+    // Writer appending to a shared Vec.
+    #[derive(Clone)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn play() -> Result<()> {
+        let cap = Capture(Arc::new(Mutex::new(Vec::new())));
+        let src = streamp_from_slice(&[0.5 as Float; 4800]);
+        {
+            let mut sink = AudioSink::builder(src, 8000.0)
+                .latency(Duration::from_secs(1))
+                .output(Box::new(cap.clone()))
+                .build()?;
+            sink.work()?;
+        }
+        let bytes = cap.0.lock().unwrap();
+        let samples: Vec<i16> = bytes
+            .chunks(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert!(samples.iter().all(|&s| (0..=16383).contains(&s)));
+
+        // 6 times as many, give or take the rate adjustment, and the
+        // start being interpolated from silence.
+        let played = samples.iter().filter(|&&s| s == 16383).count() as Float;
+        let want = 4800.0 * 6.0;
+        assert!((played - want).abs() / want <= 2.0 * MAX_ADJUST, "{played}");
+        Ok(())
+    }
+
+    #[test]
+    fn backpressure() -> Result<()> {
+        let cap = Capture(Arc::new(Mutex::new(Vec::new())));
+        let src = streamp_from_slice(&[0.5 as Float; 4800]);
+        {
+            // Queue of at most 2×4800 samples.
+            let mut sink = AudioSink::builder(src.clone(), 8000.0)
+                .latency(Duration::from_millis(100))
+                .output(Box::new(cap.clone()))
+                .build()?;
+            sink.work()?;
+            let (left, _) = src.read_buf()?;
+            assert!(!left.is_empty(), "read everything into the queue");
+            drop(left);
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while !src.read_buf()?.0.is_empty() {
+                assert!(Instant::now() < deadline, "never drained");
+                if matches!(sink.work()?, BlockRet::Pending) {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            }
+        }
+        // Nothing was dropped.
+        let bytes = cap.0.lock().unwrap();
+        let played = bytes
+            .chunks(2)
+            .filter(|b| i16::from_le_bytes([b[0], b[1]]) == 16383)
+            .count() as Float;
+        let want = 4800.0 * 6.0;
+        assert!((played - want).abs() / want <= 2.0 * MAX_ADJUST, "{played}");
+        Ok(())
+    }
+
+    #[test]
+    fn bad_rate() {
+        let src = streamp_from_slice(&[0.0 as Float]);
+        assert!(AudioSink::builder(src, 0.0)
+            .output(Box::new(std::io::sink()))
+            .build()
+            .is_err());
+    }
+}
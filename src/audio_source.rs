@@ -0,0 +1,242 @@
+/*! Capture audio from the sound card.
+
+[AudioSource] records from the default ALSA input device, by reading
+from `arecord`, so that e.g. the audio output of a radio can be decoded
+without an SDR. Any other recorder, or a file, can be given as
+[input](AudioSourceBuilder::input) instead, as a reader giving signed
+16 bit little endian samples, channels interleaved.
+
+```no_run
+use rustradio::blocks::{AudioSource, NullSink};
+let src = AudioSource::builder(48000).channels(2).build()?;
+let left = NullSink::new(src.out());
+let right = NullSink::new(src.out_channel(1));
+# Ok::<(), anyhow::Error>(())
+```
+*/
+use std::io::Read;
+
+use anyhow::Result;
+use log::{debug, warn};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, Streamp};
+use crate::{Error, Float};
+
+/// Builder for [AudioSource].
+pub struct AudioSourceBuilder {
+    samp_rate: u32,
+    channels: usize,
+    device: Option<String>,
+    input: Option<Box<dyn Read + Send>>,
+}
+
+impl AudioSourceBuilder {
+    /// Create new builder, recording at `samp_rate`.
+    pub fn new(samp_rate: u32) -> Self {
+        Self {
+            samp_rate,
+            channels: 1,
+            device: None,
+            input: None,
+        }
+    }
+
+    /// Set number of channels. Default 1.
+    pub fn channels(mut self, n: usize) -> Self {
+        self.channels = n;
+        self
+    }
+
+    /// Set ALSA device name, as given to `arecord -D`. Default is the
+    /// default device.
+    pub fn device(mut self, name: &str) -> Self {
+        self.device = Some(name.to_string());
+        self
+    }
+
+    /// Read S16_LE samples from `r`, instead of recording with
+    /// `arecord`.
+    pub fn input(mut self, r: Box<dyn Read + Send>) -> Self {
+        self.input = Some(r);
+        self
+    }
+
+    /// Build AudioSource block, starting recording.
+    pub fn build(self) -> Result<AudioSource> {
+        if self.samp_rate == 0 || self.channels == 0 {
+            return Err(Error::BadParameter(format!(
+                "AudioSource needs a sample rate and channels, got {} and {}",
+                self.samp_rate, self.channels
+            ))
+            .into());
+        }
+        let (input, child): (Box<dyn Read + Send>, _) = match self.input {
+            Some(r) => (r, None),
+            None => {
+                let mut cmd = std::process::Command::new("arecord");
+                cmd.args(["-q", "-t", "raw", "-f", "S16_LE", "-c"])
+                    .arg(self.channels.to_string())
+                    .arg("-r")
+                    .arg(self.samp_rate.to_string())
+                    .stdout(std::process::Stdio::piped());
+                if let Some(dev) = &self.device {
+                    cmd.arg("-D").arg(dev);
+                }
+                debug!("AudioSource: running {cmd:?}");
+                let mut child = cmd
+                    .spawn()
+                    .map_err(|e| Error::new(&format!("failed to run arecord: {e}")))?;
+                let stdout = child.stdout.take().expect("stdout is piped");
+                (Box::new(stdout), Some(child))
+            }
+        };
+        Ok(AudioSource {
+            input,
+            child,
+            samp_rate: self.samp_rate,
+            buf: Vec::new(),
+            dsts: (0..self.channels).map(|_| new_streamp()).collect(),
+        })
+    }
+}
+
+/// Capture audio. See the [module docs](crate::audio_source).
+pub struct AudioSource {
+    input: Box<dyn Read + Send>,
+    child: Option<std::process::Child>,
+    samp_rate: u32,
+    // Partial frame left over from last read.
+    buf: Vec<u8>,
+    dsts: Vec<Streamp<Float>>,
+}
+
+impl AudioSource {
+    /// Create a builder.
+    pub fn builder(samp_rate: u32) -> AudioSourceBuilder {
+        AudioSourceBuilder::new(samp_rate)
+    }
+
+    /// Create new AudioSource, recording one channel from the default
+    /// device.
+    pub fn new(samp_rate: u32) -> Result<Self> {
+        AudioSourceBuilder::new(samp_rate).build()
+    }
+
+    /// Return sample rate.
+    pub fn sample_rate(&self) -> u32 {
+        self.samp_rate
+    }
+
+    /// Return the output stream of the first channel.
+    pub fn out(&self) -> Streamp<Float> {
+        self.out_channel(0)
+    }
+
+    /// Return the output stream of channel `n`.
+    pub fn out_channel(&self, n: usize) -> Streamp<Float> {
+        self.dsts[n].clone()
+    }
+}
+
+impl Drop for AudioSource {
+    fn drop(&mut self) {
+        if let Some(mut c) = self.child.take() {
+            if let Err(e) = c.kill() {
+                warn!("AudioSource: killing arecord: {e}");
+            }
+            if let Err(e) = c.wait() {
+                warn!("AudioSource: waiting for arecord: {e}");
+            }
+        }
+    }
+}
+
+impl Block for AudioSource {
+    fn block_name(&self) -> &str {
+        "AudioSource"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let channels = self.dsts.len();
+        let frame = 2 * channels;
+        let dsts = self.dsts.clone();
+        let mut outs = dsts
+            .iter()
+            .map(|d| d.write_buf())
+            .collect::<Result<Vec<_>, _>>()?;
+        let room = outs.iter().map(|o| o.len()).min().unwrap_or(0);
+        if room == 0 {
+            return Ok(BlockRet::Noop);
+        }
+
+        // Blocks until there's audio.
+        let have = self.buf.len();
+        self.buf.resize(room * frame, 0);
+        let n = self
+            .input
+            .read(&mut self.buf[have..])
+            .map_err(|e| -> anyhow::Error { e.into() })?;
+        if n == 0 {
+            debug!("AudioSource: end of input");
+            return Ok(BlockRet::EOF);
+        }
+        let frames = (have + n) / frame;
+        for (ch, o) in outs.iter_mut().enumerate() {
+            let out = o.slice();
+            for (i, s) in out.iter_mut().take(frames).enumerate() {
+                let pos = i * frame + 2 * ch;
+                let v = i16::from_le_bytes([self.buf[pos], self.buf[pos + 1]]);
+                *s = v as Float / i16::MAX as Float;
+            }
+        }
+        for o in outs {
+            o.produce(frames, &[]);
+        }
+        self.buf.truncate(have + n);
+        self.buf.drain(..frames * frame);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::assert_almost_equal_float;
+
+    // Reader giving at most `n` bytes per read.
+    struct Trickle(std::io::Cursor<Vec<u8>>, usize);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.1);
+            self.0.read(&mut buf[..n])
+        }
+    }
+
+    #[test]
+    fn stereo() -> Result<()> {
+        let samples: [i16; 6] = [i16::MAX, 0, -i16::MAX, i16::MAX / 2, 0, -i16::MAX / 2];
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut src = AudioSource::builder(8000)
+            .channels(2)
+            .input(Box::new(Trickle(std::io::Cursor::new(bytes), 5)))
+            .build()?;
+        assert_eq!(src.sample_rate(), 8000);
+        // Partial frames are kept until the rest arrives.
+        while !matches!(src.work()?, BlockRet::EOF) {}
+        let left = src.out();
+        let right = src.out_channel(1);
+        assert_almost_equal_float(left.read_buf()?.0.slice(), &[1.0, -1.0, 0.0]);
+        assert_almost_equal_float(right.read_buf()?.0.slice(), &[0.0, 0.5, -0.5]);
+        Ok(())
+    }
+
+    #[test]
+    fn bad_channels() {
+        assert!(AudioSource::builder(8000)
+            .channels(0)
+            .input(Box::new(std::io::empty()))
+            .build()
+            .is_err());
+    }
+}
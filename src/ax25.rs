@@ -0,0 +1,313 @@
+/*! AX.25 frame parsing.
+
+Parses the AX.25 frames output by
+[HdlcDeframer][crate::hdlc_deframer::HdlcDeframer] (without FCS), and
+formats them in the TNC2 text format used by APRS-IS and most APRS
+software:
+
+```text
+N0CALL-9>APRS,WIDE1-1,WIDE2-1:!5950.00N/01800.00E>
+```
+
+Frames can also be created, for sending with
+[HdlcFramer][crate::hdlc_framer::HdlcFramer]:
+
+```
+use rustradio::ax25::Frame;
+let frame = Frame::ui("N0CALL-9", "APRS", &["WIDE1-1"], b">Hello").unwrap();
+assert_eq!(frame.to_tnc2(), "N0CALL-9>APRS,WIDE1-1:>Hello");
+let bytes = frame.encode();
+assert_eq!(Frame::parse(&bytes), Some(frame));
+```
+
+[AX.25]: https://www.tapr.org/pdf/AX25.2.2.pdf
+*/
+
+/// Control field of an UI frame.
+pub const CONTROL_UI: u8 = 0x03;
+
+/// PID for "no layer 3", used by APRS.
+pub const PID_NO_L3: u8 = 0xf0;
+
+const ADDR_LEN: usize = 7;
+const MAX_DIGIS: usize = 8;
+
+/// AX.25 address.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Address {
+    /// Callsign, without SSID.
+    pub call: String,
+
+    /// SSID, 0-15.
+    pub ssid: u8,
+
+    /// The "has been repeated" bit for digipeaters, or the
+    /// command/response bit for source and destination.
+    pub h: bool,
+}
+
+impl Address {
+    /// Create from text, like `N0CALL-9`.
+    ///
+    /// Returns None if the callsign is not 1-6 letters or digits, or
+    /// the SSID is not 0-15.
+    pub fn from_text(s: &str) -> Option<Self> {
+        let (call, ssid) = match s.split_once('-') {
+            Some((c, ssid)) => (c, ssid.parse::<u8>().ok().filter(|s| *s < 16)?),
+            None => (s, 0),
+        };
+        if call.is_empty() || call.len() > 6 || !call.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        Some(Self {
+            call: call.to_ascii_uppercase(),
+            ssid,
+            h: false,
+        })
+    }
+
+    /// Encode to the 7 byte on-air format, setting the end of address
+    /// bit if `last`.
+    pub fn encode(&self, last: bool) -> [u8; ADDR_LEN] {
+        let mut ret = [b' ' << 1; ADDR_LEN];
+        for (r, c) in ret.iter_mut().zip(self.call.bytes()) {
+            *r = c << 1;
+        }
+        ret[6] = 0x60 | (self.ssid << 1) | last as u8 | if self.h { 0x80 } else { 0 };
+        ret
+    }
+
+    /// Parse from the 7 byte on-air format.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < ADDR_LEN {
+            return None;
+        }
+        let mut call = String::with_capacity(6);
+        for b in &data[..6] {
+            if b & 1 != 0 {
+                return None;
+            }
+            let c = (b >> 1) as char;
+            if c == ' ' {
+                continue;
+            }
+            if !c.is_ascii_alphanumeric() {
+                return None;
+            }
+            call.push(c);
+        }
+        if call.is_empty() {
+            return None;
+        }
+        Some(Self {
+            call,
+            ssid: (data[6] >> 1) & 0x0f,
+            h: data[6] & 0x80 != 0,
+        })
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.ssid == 0 {
+            write!(f, "{}", self.call)
+        } else {
+            write!(f, "{}-{}", self.call, self.ssid)
+        }
+    }
+}
+
+/// AX.25 frame.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Frame {
+    /// Destination.
+    pub dst: Address,
+
+    /// Source.
+    pub src: Address,
+
+    /// Digipeater path.
+    pub digis: Vec<Address>,
+
+    /// Control field.
+    pub control: u8,
+
+    /// PID, for frames that have one.
+    pub pid: Option<u8>,
+
+    /// Information field.
+    pub info: Vec<u8>,
+}
+
+impl Frame {
+    /// Create an UI frame without layer 3, like APRS.
+    ///
+    /// Returns None if any address is invalid, or there are too many
+    /// digipeaters.
+    pub fn ui(src: &str, dst: &str, digis: &[&str], info: &[u8]) -> Option<Self> {
+        if digis.len() > MAX_DIGIS {
+            return None;
+        }
+        Some(Self {
+            dst: Address::from_text(dst)?,
+            src: Address::from_text(src)?,
+            digis: digis
+                .iter()
+                .map(|d| Address::from_text(d))
+                .collect::<Option<_>>()?,
+            control: CONTROL_UI,
+            pid: Some(PID_NO_L3),
+            info: info.to_vec(),
+        })
+    }
+
+    /// Encode to bytes, without FCS.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(ADDR_LEN * (2 + self.digis.len()) + 2 + self.info.len());
+        let addrs: Vec<_> = [&self.dst, &self.src]
+            .into_iter()
+            .chain(&self.digis)
+            .collect();
+        for (n, a) in addrs.iter().enumerate() {
+            v.extend(a.encode(n == addrs.len() - 1));
+        }
+        v.push(self.control);
+        v.extend(self.pid);
+        v.extend(&self.info);
+        v
+    }
+
+    /// Parse a frame, without FCS.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let mut addrs = Vec::new();
+        let mut pos = 0;
+        loop {
+            let a = data.get(pos..pos + ADDR_LEN)?;
+            addrs.push(Address::parse(a)?);
+            pos += ADDR_LEN;
+            if a[6] & 1 != 0 {
+                break;
+            }
+            if addrs.len() == 2 + MAX_DIGIS {
+                return None;
+            }
+        }
+        if addrs.len() < 2 {
+            return None;
+        }
+        let control = *data.get(pos)?;
+        pos += 1;
+        // I and UI frames have a PID.
+        let pid = if control & 1 == 0 || control & 0xef == CONTROL_UI {
+            let p = *data.get(pos)?;
+            pos += 1;
+            Some(p)
+        } else {
+            None
+        };
+        let mut addrs = addrs.into_iter();
+        Some(Self {
+            dst: addrs.next().unwrap(),
+            src: addrs.next().unwrap(),
+            digis: addrs.collect(),
+            control,
+            pid,
+            info: data[pos..].to_vec(),
+        })
+    }
+
+    /// Return true if this is an UI frame without layer 3, like APRS.
+    pub fn is_ui(&self) -> bool {
+        self.control & 0xef == CONTROL_UI && self.pid == Some(PID_NO_L3)
+    }
+
+    /// Format as TNC2, marking the last repeated digipeater with `*`.
+    ///
+    /// Non-printable info bytes are included as is, converted as
+    /// Latin-1.
+    pub fn to_tnc2(&self) -> String {
+        let mut s = format!("{}>{}", self.src, self.dst);
+        let last = self.digis.iter().rposition(|d| d.h);
+        for (n, d) in self.digis.iter().enumerate() {
+            s.push(',');
+            s.push_str(&d.to_string());
+            if Some(n) == last {
+                s.push('*');
+            }
+        }
+        s.push(':');
+        s.extend(self.info.iter().map(|b| *b as char));
+        s
+    }
+
+    /// Key for deduplication, ignoring the digipeater path.
+    pub fn dedup_key(&self) -> Vec<u8> {
+        let mut v = format!("{}>{}:", self.src, self.dst).into_bytes();
+        v.extend(&self.info);
+        v
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Encode a frame, for tests.
+    pub fn encode(src: &str, dst: &str, digis: &[(&str, bool)], info: &[u8]) -> Vec<u8> {
+        let mut addrs: Vec<(&str, bool)> = vec![(dst, false), (src, false)];
+        addrs.extend(digis);
+        let mut v = Vec::new();
+        for (n, (a, h)) in addrs.iter().enumerate() {
+            let (call, ssid) = match a.split_once('-') {
+                Some((c, s)) => (c, s.parse::<u8>().unwrap()),
+                None => (*a, 0),
+            };
+            let mut call = call.as_bytes().to_vec();
+            call.resize(6, b' ');
+            v.extend(call.iter().map(|c| c << 1));
+            let last = (n == addrs.len() - 1) as u8;
+            v.push(0x60 | (ssid << 1) | last | if *h { 0x80 } else { 0 });
+        }
+        v.extend([CONTROL_UI, PID_NO_L3]);
+        v.extend(info);
+        v
+    }
+
+    #[test]
+    fn parse() {
+        let data = encode(
+            "N0CALL-9",
+            "APRS",
+            &[("WIDE1", true), ("RELAY", true), ("WIDE2-1", false)],
+            b"!5950.00N/01800.00E>",
+        );
+        let f = Frame::parse(&data).unwrap();
+        assert!(f.is_ui());
+        assert_eq!(f.src.ssid, 9);
+        assert_eq!(
+            f.to_tnc2(),
+            "N0CALL-9>APRS,WIDE1,RELAY*,WIDE2-1:!5950.00N/01800.00E>"
+        );
+        assert_eq!(f.dedup_key(), b"N0CALL-9>APRS:!5950.00N/01800.00E>");
+        assert!(Frame::parse(&data[..10]).is_none());
+    }
+
+    #[test]
+    fn create() {
+        let f = Frame::ui("n0call-9", "APRS", &["WIDE1-1", "WIDE2-1"], b"hi").unwrap();
+        assert_eq!(
+            f.encode(),
+            encode(
+                "N0CALL-9",
+                "APRS",
+                &[("WIDE1-1", false), ("WIDE2-1", false)],
+                b"hi"
+            )
+        );
+        for bad in ["", "N0CALL7", "N0CALL-16", "N0-CALL", "N0CALL-", "N0/CALL"] {
+            assert!(Address::from_text(bad).is_none(), "{bad}");
+            assert!(Frame::ui(bad, "APRS", &[], b"").is_none(), "{bad}");
+        }
+        assert!(Frame::ui("N0CALL", "APRS", &["WIDE"; 9], b"").is_none());
+    }
+}
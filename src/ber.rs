@@ -0,0 +1,563 @@
+/*! Bit and frame error rate testing.
+
+[PrbsSource] generates a standard pseudo random bit sequence, and
+[BerSink] counts the errors in the received copy of it. Together with
+a [ChannelModel](crate::channel_model::ChannelModel), this gives BER
+curves for a modulator/demodulator pair:
+
+```text
+PrbsSource -> modulator -> ChannelModel -> demodulator -> BerSink
+```
+
+The sink needs no connection to the source. It locks on to the
+sequence by itself, whatever the delay through the chain, and also if
+the bits come out inverted, as they may after e.g. BPSK with a phase
+ambiguity. If the error rate gets too high, such as when the
+demodulator loses track, the sink drops the lock and hunts for the
+sequence again.
+
+```
+use rustradio::graph::Graph;
+use rustradio::blocks::{BerSink, PrbsSource};
+use rustradio::ber::Prbs;
+let mut src = PrbsSource::new(Prbs::prbs9());
+src.set_limit(Some(10_000));
+let sink = BerSink::new(src.out());
+let h = sink.handle();
+let mut g = Graph::new();
+g.add(Box::new(src));
+g.add(Box::new(sink));
+g.run()?;
+let r = h.reading();
+assert!(r.synced);
+assert_eq!(r.errors, 0);
+# Ok::<(), anyhow::Error>(())
+```
+*/
+use std::sync::{Arc, Mutex};
+
+use log::debug;
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, Streamp};
+use crate::Error;
+
+/// Pseudo random bit sequence, from a Fibonacci LFSR with two taps.
+///
+/// The standard sequences are those of ITU-T O.150.
+#[derive(Debug, Clone)]
+pub struct Prbs {
+    len: u8,
+    tap: u8,
+    state: u64,
+}
+
+impl Prbs {
+    /// Create new PRBS for the polynomial `x^len + x^tap + 1`.
+    ///
+    /// `seed` is the initial register value, and must not be zero.
+    pub fn new(len: u8, tap: u8, seed: u64) -> Self {
+        assert!(len > 1 && len < 64, "PRBS length must be 2-63");
+        assert!(tap > 0 && tap < len, "PRBS tap must be less than length");
+        let state = seed & Self::mask(len);
+        assert!(state != 0, "PRBS seed must not be zero");
+        Self { len, tap, state }
+    }
+
+    /// PRBS7, `x^7 + x^6 + 1`.
+    pub fn prbs7() -> Self {
+        Self::new(7, 6, 1)
+    }
+
+    /// PRBS9, `x^9 + x^5 + 1`.
+    pub fn prbs9() -> Self {
+        Self::new(9, 5, 1)
+    }
+
+    /// PRBS15, `x^15 + x^14 + 1`.
+    pub fn prbs15() -> Self {
+        Self::new(15, 14, 1)
+    }
+
+    /// PRBS23, `x^23 + x^18 + 1`.
+    pub fn prbs23() -> Self {
+        Self::new(23, 18, 1)
+    }
+
+    /// PRBS31, `x^31 + x^28 + 1`.
+    pub fn prbs31() -> Self {
+        Self::new(31, 28, 1)
+    }
+
+    /// Register length in bits, i.e. the order of the polynomial.
+    pub fn order(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Number of bits before the sequence repeats.
+    pub fn period(&self) -> u64 {
+        Self::mask(self.len)
+    }
+
+    fn mask(len: u8) -> u64 {
+        (1 << len) - 1
+    }
+
+    // The next bit, as predicted from the current register.
+    fn feedback(&self) -> u8 {
+        (((self.state >> (self.len - 1)) ^ (self.state >> (self.tap - 1))) & 1) as u8
+    }
+
+    // Shift in a bit.
+    fn push(&mut self, bit: u8) {
+        self.state = ((self.state << 1) | bit as u64) & Self::mask(self.len);
+    }
+}
+
+impl Iterator for Prbs {
+    type Item = u8;
+    fn next(&mut self) -> Option<u8> {
+        let bit = self.feedback();
+        self.push(bit);
+        Some(bit)
+    }
+}
+
+/// Generate a pseudo random bit sequence, one bit (0 or 1) per byte.
+pub struct PrbsSource {
+    dst: Streamp<u8>,
+    prbs: Prbs,
+    limit: Option<u64>,
+}
+
+impl PrbsSource {
+    /// Create new PrbsSource block.
+    pub fn new(prbs: Prbs) -> Self {
+        Self {
+            dst: new_streamp(),
+            prbs,
+            limit: None,
+        }
+    }
+
+    /// Set number of bits to generate before EOF. Default is to run
+    /// forever.
+    pub fn set_limit(&mut self, limit: Option<u64>) {
+        self.limit = limit;
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<u8> {
+        self.dst.clone()
+    }
+}
+
+impl Block for PrbsSource {
+    fn block_name(&self) -> &str {
+        "PrbsSource"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        if self.limit == Some(0) {
+            return Ok(BlockRet::EOF);
+        }
+        let obind = self.dst.clone();
+        let mut o = obind.write_buf()?;
+        let mut n = o.len();
+        if let Some(limit) = self.limit {
+            n = std::cmp::min(n as u64, limit) as usize;
+            self.limit = Some(limit - n as u64);
+        }
+        for (to, from) in o.slice()[..n].iter_mut().zip(&mut self.prbs) {
+            *to = from;
+        }
+        o.produce(n, &[]);
+        Ok(BlockRet::Ok)
+    }
+}
+
+/// Error counts from a [BerSink].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BerReading {
+    /// Bits compared.
+    pub bits: u64,
+
+    /// Bit errors.
+    pub errors: u64,
+
+    /// Complete frames compared.
+    pub frames: u64,
+
+    /// Frames with at least one bit error.
+    pub frame_errors: u64,
+
+    /// True if currently locked on to the sequence.
+    pub synced: bool,
+
+    /// True if the sequence was found inverted.
+    pub inverted: bool,
+
+    /// Number of times the lock was lost.
+    pub sync_losses: u64,
+}
+
+impl BerReading {
+    /// Bit error rate, or None if no bits were compared yet.
+    pub fn ber(&self) -> Option<f64> {
+        (self.bits > 0).then(|| self.errors as f64 / self.bits as f64)
+    }
+
+    /// Frame error rate, or None if no frames were compared yet.
+    pub fn fer(&self) -> Option<f64> {
+        (self.frames > 0).then(|| self.frame_errors as f64 / self.frames as f64)
+    }
+}
+
+impl std::fmt::Display for BerReading {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} errors in {} bits", self.errors, self.bits)?;
+        if let Some(ber) = self.ber() {
+            write!(f, " (BER {ber:.2e})")?;
+        }
+        write!(f, ", {} bad of {} frames", self.frame_errors, self.frames)?;
+        if let Some(fer) = self.fer() {
+            write!(f, " (FER {fer:.2e})")?;
+        }
+        if !self.synced {
+            write!(f, ", not synced")?;
+        }
+        Ok(())
+    }
+}
+
+/// Shared handle to the counts of a [BerSink].
+#[derive(Clone)]
+pub struct BerHandle {
+    inner: Arc<Mutex<BerReading>>,
+}
+
+impl BerHandle {
+    /// Current counts.
+    pub fn reading(&self) -> BerReading {
+        self.inner.lock().unwrap().clone()
+    }
+
+    /// Reset the counts, e.g. after changing the channel. The lock on
+    /// the sequence is kept.
+    pub fn reset(&self) {
+        let mut r = self.inner.lock().unwrap();
+        *r = BerReading {
+            synced: r.synced,
+            inverted: r.inverted,
+            ..Default::default()
+        };
+    }
+}
+
+/// Builder for [BerSink].
+pub struct BerSinkBuilder {
+    src: Streamp<u8>,
+    prbs: Prbs,
+    frame_len: usize,
+    sync_bits: usize,
+    window: usize,
+    max_window_errors: usize,
+}
+
+impl BerSinkBuilder {
+    /// Create new builder.
+    pub fn new(src: Streamp<u8>, prbs: Prbs) -> Self {
+        Self {
+            src,
+            prbs,
+            frame_len: 1000,
+            sync_bits: 64,
+            window: 1000,
+            max_window_errors: 200,
+        }
+    }
+
+    /// Set frame length in bits, for the frame error rate. Default
+    /// 1000.
+    pub fn frame_len(mut self, bits: usize) -> Self {
+        self.frame_len = bits;
+        self
+    }
+
+    /// Set number of correctly predicted bits in a row needed to
+    /// lock on to the sequence. Default 64.
+    pub fn sync_bits(mut self, bits: usize) -> Self {
+        self.sync_bits = bits;
+        self
+    }
+
+    /// Drop the lock if more than `max_errors` of `window` bits are
+    /// wrong. Default is 200 of 1000.
+    ///
+    /// A random bit stream has half the bits wrong.
+    pub fn loss_of_sync(mut self, max_errors: usize, window: usize) -> Self {
+        self.max_window_errors = max_errors;
+        self.window = window;
+        self
+    }
+
+    /// Build BerSink block.
+    pub fn build(self) -> BerSink {
+        BerSink {
+            src: self.src,
+            handle: BerHandle {
+                inner: Arc::new(Mutex::new(BerReading::default())),
+            },
+            hunt_reg: self.prbs.clone(),
+            prbs: self.prbs,
+            frame_len: self.frame_len,
+            sync_bits: self.sync_bits,
+            window: self.window,
+            max_window_errors: self.max_window_errors,
+            filled: 0,
+            run: 0,
+            frame_bit: 0,
+            frame_bad: false,
+            window_bit: 0,
+            window_errors: 0,
+        }
+    }
+}
+
+/// Count bit and frame errors in a received PRBS. See the [module
+/// docs](crate::ber).
+pub struct BerSink {
+    src: Streamp<u8>,
+    handle: BerHandle,
+    frame_len: usize,
+    sync_bits: usize,
+    window: usize,
+    max_window_errors: usize,
+
+    // Hunting. `hunt_reg` holds the latest received bits, `filled`
+    // counts how many are in there, and `run` is the number of bits
+    // in a row predicted right (positive) or inverted (negative).
+    hunt_reg: Prbs,
+    filled: usize,
+    run: isize,
+
+    // Locked. `prbs` generates the expected sequence.
+    prbs: Prbs,
+    frame_bit: usize,
+    frame_bad: bool,
+    window_bit: usize,
+    window_errors: usize,
+}
+
+impl BerSink {
+    /// Create new BerSink block, expecting PRBS9.
+    pub fn new(src: Streamp<u8>) -> Self {
+        BerSinkBuilder::new(src, Prbs::prbs9()).build()
+    }
+
+    /// Create a builder.
+    pub fn builder(src: Streamp<u8>, prbs: Prbs) -> BerSinkBuilder {
+        BerSinkBuilder::new(src, prbs)
+    }
+
+    /// Get a handle to the counts.
+    pub fn handle(&self) -> BerHandle {
+        self.handle.clone()
+    }
+
+    fn hunt(&mut self, r: &mut BerReading, bit: u8) {
+        if self.filled < self.hunt_reg.order() {
+            self.filled += 1;
+        } else if bit == self.hunt_reg.feedback() {
+            self.run = self.run.max(0) + 1;
+        } else {
+            self.run = self.run.min(0) - 1;
+        }
+        self.hunt_reg.push(bit);
+        if self.run.unsigned_abs() >= self.sync_bits {
+            r.synced = true;
+            r.inverted = self.run < 0;
+            debug!("BerSink: synced, inverted={}", r.inverted);
+            self.prbs.state = self.hunt_reg.state;
+            if r.inverted {
+                // Undo the inversion in the register, to keep
+                // generating the real sequence.
+                self.prbs.state ^= Prbs::mask(self.prbs.len);
+            }
+            self.frame_bit = 0;
+            self.frame_bad = false;
+            self.window_bit = 0;
+            self.window_errors = 0;
+        }
+    }
+
+    fn check(&mut self, r: &mut BerReading, bit: u8) {
+        let want = self.prbs.next().unwrap() ^ r.inverted as u8;
+        let err = bit != want;
+        r.bits += 1;
+        if err {
+            r.errors += 1;
+            self.frame_bad = true;
+            self.window_errors += 1;
+        }
+        self.frame_bit += 1;
+        if self.frame_bit == self.frame_len {
+            r.frames += 1;
+            if self.frame_bad {
+                r.frame_errors += 1;
+            }
+            self.frame_bit = 0;
+            self.frame_bad = false;
+        }
+        self.window_bit += 1;
+        if self.window_bit == self.window {
+            if self.window_errors > self.max_window_errors {
+                debug!("BerSink: lost sync");
+                r.synced = false;
+                r.sync_losses += 1;
+                self.filled = 0;
+                self.run = 0;
+            }
+            self.window_bit = 0;
+            self.window_errors = 0;
+        }
+    }
+}
+
+impl Block for BerSink {
+    fn block_name(&self) -> &str {
+        "BerSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (i, _) = ibind.read_buf()?;
+        let n = i.len();
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        let h = self.handle.clone();
+        let mut r = h.inner.lock().unwrap();
+        for &bit in i.iter() {
+            if r.synced {
+                self.check(&mut r, bit & 1);
+            } else {
+                self.hunt(&mut r, bit & 1);
+            }
+        }
+        i.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel_model::ChannelModel;
+    use crate::stream::streamp_from_slice;
+    use crate::{Complex, Float};
+
+    fn count(bits: &[u8], b: impl FnOnce(BerSinkBuilder) -> BerSinkBuilder) -> BerReading {
+        let src = streamp_from_slice(bits);
+        let mut sink = b(BerSink::builder(src, Prbs::prbs9())).build();
+        sink.work().unwrap();
+        sink.handle().reading()
+    }
+
+    #[test]
+    fn sequences() {
+        for (prbs, period) in [
+            (Prbs::prbs7(), 127),
+            (Prbs::prbs9(), 511),
+            (Prbs::prbs15(), 32767),
+        ] {
+            assert_eq!(prbs.period(), period);
+            let bits: Vec<u8> = prbs.take(2 * period as usize).collect();
+            let (a, b) = bits.split_at(period as usize);
+            assert_eq!(a, b);
+            // Maximal length sequences have one more one than zero.
+            let ones = a.iter().filter(|&&b| b == 1).count() as u64;
+            assert_eq!(ones, period / 2 + 1);
+        }
+    }
+
+    #[test]
+    fn source() {
+        let mut src = PrbsSource::new(Prbs::prbs7());
+        src.set_limit(Some(200));
+        assert!(matches!(src.work().unwrap(), BlockRet::Ok));
+        assert!(matches!(src.work().unwrap(), BlockRet::EOF));
+        let o = src.out();
+        let (res, _) = o.read_buf().unwrap();
+        let want: Vec<u8> = Prbs::prbs7().take(200).collect();
+        assert_eq!(res.slice(), want);
+    }
+
+    #[test]
+    fn sync() {
+        // Start anywhere in the sequence.
+        let bits: Vec<u8> = Prbs::prbs9().skip(123).take(10_000).collect();
+        let r = count(&bits, |b| b);
+        assert!(r.synced);
+        assert!(!r.inverted);
+        assert_eq!(r.errors, 0);
+        // Hunting needs 9 bits to fill the register, then 64 to lock.
+        assert_eq!(r.bits, 10_000 - 73);
+        assert_eq!(r.frames, 9);
+        assert_eq!(r.fer(), Some(0.0));
+
+        let inv: Vec<u8> = bits.iter().map(|b| b ^ 1).collect();
+        let r = count(&inv, |b| b);
+        assert!(r.synced);
+        assert!(r.inverted);
+        assert_eq!(r.errors, 0);
+    }
+
+    #[test]
+    fn errors() {
+        let mut bits: Vec<u8> = Prbs::prbs9().take(1100).collect();
+        for n in [200, 500, 501, 900] {
+            bits[n] ^= 1;
+        }
+        let r = count(&bits, |b| b.frame_len(100));
+        assert_eq!(r.bits, 1100 - 73);
+        assert_eq!(r.errors, 4);
+        assert_eq!(r.frames, 10);
+        assert_eq!(r.frame_errors, 3);
+    }
+
+    #[test]
+    fn loss_of_sync() {
+        let mut bits: Vec<u8> = Prbs::prbs9().take(1000).collect();
+        bits.extend(Prbs::prbs9().skip(300).take(1000));
+        let r = count(&bits, |b| b.loss_of_sync(10, 100));
+        assert!(r.synced);
+        assert_eq!(r.sync_losses, 1);
+        // Up to a window of garbage before noticing.
+        assert!(r.errors > 10 && r.errors < 100, "{}", r.errors);
+    }
+
+    #[test]
+    fn bpsk_awgn() {
+        // BPSK at 4dB Eb/N0 has BER 0.5*erfc(sqrt(10^0.4)) = 0.0125.
+        let bits: Vec<u8> = Prbs::prbs15().take(50_000).collect();
+        let syms: Vec<Complex> = bits
+            .iter()
+            .map(|&b| Complex::new(2.0 * b as Float - 1.0, 0.0))
+            .collect();
+        let mut chan = ChannelModel::builder(streamp_from_slice(&syms), 1.0)
+            .ebn0(4.0, 1)
+            .build();
+        chan.work().unwrap();
+        let o = chan.out();
+        let (res, _) = o.read_buf().unwrap();
+        let rx: Vec<u8> = res.iter().map(|s| (s.re > 0.0) as u8).collect();
+
+        let src = streamp_from_slice(&rx);
+        let mut sink = BerSink::builder(src, Prbs::prbs15()).build();
+        sink.work().unwrap();
+        let r = sink.handle().reading();
+        assert!(r.synced);
+        assert_eq!(r.sync_losses, 0);
+        let ber = r.ber().unwrap();
+        assert!((ber - 0.0125).abs() < 0.002, "{ber}");
+    }
+}
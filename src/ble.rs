@@ -0,0 +1,463 @@
+/*! Bluetooth Low Energy advertisement receiver.
+
+Receives BLE advertising packets on the LE 1M PHY: GFSK at 1Mbps, with
+±250kHz deviation. Demodulate the same way as other FSK in this crate:
+
+```text
+  QuadratureDemod ─→ ZeroCrossing ─→ BinarySlicer ─→ BleDecode
+```
+
+[BleDecode] looks for the advertising access address, dewhitens
+according to the channel, checks the CRC, and parses the
+[BlePacket], including the advertising data ([AdStructure]s).
+
+Advertising is on channels 37, 38, and 39. See [channel_frequency].
+
+Bits are sent LSB first, after a one byte preamble:
+
+```text
+  preamble(1) access address(4) header(2) payload(0-255) CRC(3)
+                                └──────── whitened ──────────┘
+```
+
+See the [Core spec], volume 6, part B.
+
+[Core spec]: https://www.bluetooth.com/specifications/specs/core-specification/
+*/
+use anyhow::Result;
+use log::{debug, info, trace};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp, Streamp};
+use crate::Error;
+
+/// Access address used for all advertising packets.
+pub const ADV_ACCESS_ADDRESS: u32 = 0x8e89_bed6;
+
+/// Initial CRC value for advertising packets.
+pub const ADV_CRC_INIT: u32 = 0x55_5555;
+
+// Max access address bit errors to accept.
+const MAX_AA_ERRORS: u32 = 1;
+
+/// Return the center frequency of a channel, in Hz.
+pub fn channel_frequency(channel: u8) -> Result<f64, Error> {
+    let mhz = match channel {
+        37 => 2402,
+        38 => 2426,
+        39 => 2480,
+        0..=10 => 2404 + 2 * channel as u32,
+        11..=36 => 2428 + 2 * (channel as u32 - 11),
+        _ => {
+            return Err(Error::BadParameter(format!(
+                "invalid BLE channel {channel}"
+            )))
+        }
+    };
+    Ok(mhz as f64 * 1e6)
+}
+
+/// Whiten or dewhiten data for a channel.
+pub fn whiten(channel: u8, data: &mut [u8]) {
+    let mut lfsr = channel.reverse_bits() | 2;
+    for b in data {
+        for bit in 0..8 {
+            if lfsr & 0x80 != 0 {
+                lfsr ^= 0x11;
+                *b ^= 1 << bit;
+            }
+            lfsr <<= 1;
+        }
+    }
+}
+
+/// Calculate CRC24, returned as the three bytes in sending order.
+pub fn crc24(init: u32, data: &[u8]) -> [u8; 3] {
+    let mut reg = init;
+    for b in data {
+        for bit in 0..8 {
+            let t = (reg >> 23) & 1;
+            reg = (reg << 1) & 0xff_ffff;
+            if t != ((*b >> bit) & 1) as u32 {
+                reg ^= 0x00_065b;
+            }
+        }
+    }
+    [
+        ((reg >> 16) as u8).reverse_bits(),
+        ((reg >> 8) as u8).reverse_bits(),
+        (reg as u8).reverse_bits(),
+    ]
+}
+
+/// Advertising PDU type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PduType {
+    /// Connectable and scannable undirected advertising.
+    #[default]
+    AdvInd,
+    /// Connectable directed advertising.
+    AdvDirectInd,
+    /// Non-connectable and non-scannable undirected advertising.
+    AdvNonconnInd,
+    /// Scan request.
+    ScanReq,
+    /// Scan response.
+    ScanRsp,
+    /// Connection request.
+    ConnectInd,
+    /// Scannable undirected advertising.
+    AdvScanInd,
+    /// Extended advertising.
+    AdvExtInd,
+    /// Reserved.
+    Reserved(u8),
+}
+
+impl From<u8> for PduType {
+    fn from(t: u8) -> Self {
+        match t {
+            0 => PduType::AdvInd,
+            1 => PduType::AdvDirectInd,
+            2 => PduType::AdvNonconnInd,
+            3 => PduType::ScanReq,
+            4 => PduType::ScanRsp,
+            5 => PduType::ConnectInd,
+            6 => PduType::AdvScanInd,
+            7 => PduType::AdvExtInd,
+            t => PduType::Reserved(t),
+        }
+    }
+}
+
+impl PduType {
+    /// True if the payload is AdvA followed by advertising data.
+    fn has_ad(&self) -> bool {
+        matches!(
+            self,
+            PduType::AdvInd | PduType::AdvNonconnInd | PduType::ScanRsp | PduType::AdvScanInd
+        )
+    }
+}
+
+/// One advertising data structure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdStructure {
+    /// AD type, e.g. 0x09 for complete local name.
+    pub ad_type: u8,
+
+    /// Data.
+    pub data: Vec<u8>,
+}
+
+/// Parse advertising data. Stops at the first malformed entry.
+pub fn parse_ad(mut data: &[u8]) -> Vec<AdStructure> {
+    let mut ret = Vec::new();
+    while let [len, rest @ ..] = data {
+        let len = *len as usize;
+        if len == 0 || rest.len() < len {
+            break;
+        }
+        ret.push(AdStructure {
+            ad_type: rest[0],
+            data: rest[1..len].to_vec(),
+        });
+        data = &rest[len..];
+    }
+    ret
+}
+
+/// A received advertising channel packet.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BlePacket {
+    /// Channel received on.
+    pub channel: u8,
+
+    /// PDU type.
+    pub pdu_type: PduType,
+
+    /// Channel selection algorithm 2 supported.
+    pub ch_sel: bool,
+
+    /// Transmitter address is random.
+    pub tx_add: bool,
+
+    /// Receiver address is random.
+    pub rx_add: bool,
+
+    /// Raw payload, after the header.
+    pub payload: Vec<u8>,
+}
+
+impl BlePacket {
+    /// Parse a dewhitened PDU, without CRC.
+    pub fn parse(channel: u8, pdu: &[u8]) -> Option<Self> {
+        let (hdr, payload) = pdu.split_at_checked(2)?;
+        if payload.len() != hdr[1] as usize {
+            return None;
+        }
+        Some(Self {
+            channel,
+            pdu_type: PduType::from(hdr[0] & 0x0f),
+            ch_sel: hdr[0] & 0x20 != 0,
+            tx_add: hdr[0] & 0x40 != 0,
+            rx_add: hdr[0] & 0x80 != 0,
+            payload: payload.to_vec(),
+        })
+    }
+
+    /// Advertiser address, as usually written, e.g. "C0:FF:EE:00:11:22".
+    pub fn adv_address(&self) -> Option<String> {
+        if self.pdu_type == PduType::AdvExtInd || self.payload.len() < 6 {
+            return None;
+        }
+        // Scan requests and connect requests have the scanner first.
+        let off = match self.pdu_type {
+            PduType::ScanReq | PduType::ConnectInd => 6,
+            _ => 0,
+        };
+        let a = self.payload.get(off..off + 6)?;
+        Some(
+            a.iter()
+                .rev()
+                .map(|b| format!("{b:02X}"))
+                .collect::<Vec<_>>()
+                .join(":"),
+        )
+    }
+
+    /// Advertising data, for PDU types that have it.
+    pub fn ad(&self) -> Vec<AdStructure> {
+        if !self.pdu_type.has_ad() || self.payload.len() < 6 {
+            return Vec::new();
+        }
+        parse_ad(&self.payload[6..])
+    }
+
+    /// Local name, complete or shortened, if advertised.
+    pub fn local_name(&self) -> Option<String> {
+        self.ad()
+            .into_iter()
+            .find(|a| a.ad_type == 0x09 || a.ad_type == 0x08)
+            .map(|a| String::from_utf8_lossy(&a.data).to_string())
+    }
+
+    /// Manufacturer specific data, as company ID and data.
+    pub fn manufacturer_data(&self) -> Option<(u16, Vec<u8>)> {
+        self.ad()
+            .into_iter()
+            .find(|a| a.ad_type == 0xff && a.data.len() >= 2)
+            .map(|a| {
+                (
+                    u16::from_le_bytes([a.data[0], a.data[1]]),
+                    a.data[2..].to_vec(),
+                )
+            })
+    }
+}
+
+impl crate::Len for BlePacket {
+    fn len(&self) -> usize {
+        self.payload.len()
+    }
+}
+
+/** BLE advertising channel decoder.
+
+Input is one bit per sample, as 0 or 1.
+*/
+pub struct BleDecode {
+    src: Streamp<u8>,
+    dst: NoCopyStreamp<BlePacket>,
+    channel: u8,
+    shift: u32,
+    // Bytes being received, and bit count of the last one.
+    packet: Option<(Vec<u8>, usize)>,
+    packets: usize,
+    crc_errors: usize,
+}
+
+impl BleDecode {
+    /// Create new decoder for a channel.
+    pub fn new(src: Streamp<u8>, channel: u8) -> Result<Self, Error> {
+        channel_frequency(channel)?;
+        Ok(Self {
+            src,
+            dst: new_nocopy_streamp(),
+            channel,
+            shift: 0,
+            packet: None,
+            packets: 0,
+            crc_errors: 0,
+        })
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<BlePacket> {
+        self.dst.clone()
+    }
+
+    fn process_one(&mut self, bit: u8) -> Option<BlePacket> {
+        let bit = bit & 1;
+        let Some((bytes, nbits)) = &mut self.packet else {
+            // LSB first, so shift in from the top.
+            self.shift = (self.shift >> 1) | ((bit as u32) << 31);
+            if (self.shift ^ ADV_ACCESS_ADDRESS).count_ones() <= MAX_AA_ERRORS {
+                trace!("BleDecode: access address");
+                self.packet = Some((Vec::new(), 8));
+            }
+            return None;
+        };
+        if *nbits == 8 {
+            bytes.push(0);
+            *nbits = 0;
+        }
+        *bytes.last_mut().unwrap() |= bit << *nbits;
+        *nbits += 1;
+        if *nbits < 8 || bytes.len() < 2 {
+            return None;
+        }
+        let mut hdr = [bytes[0], bytes[1]];
+        whiten(self.channel, &mut hdr);
+        let total = 2 + hdr[1] as usize + 3;
+        if bytes.len() < total {
+            return None;
+        }
+        let (mut pdu, _) = self.packet.take().unwrap();
+        whiten(self.channel, &mut pdu);
+        let (data, crc) = pdu.split_at(total - 3);
+        if crc24(ADV_CRC_INIT, data) != crc {
+            debug!("BleDecode: CRC error");
+            self.crc_errors += 1;
+            return None;
+        }
+        BlePacket::parse(self.channel, data)
+    }
+}
+
+impl Drop for BleDecode {
+    fn drop(&mut self) {
+        info!(
+            "BleDecode: {} packets, {} CRC errors",
+            self.packets, self.crc_errors
+        );
+    }
+}
+
+impl Block for BleDecode {
+    fn block_name(&self) -> &str {
+        "BleDecode"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        for bit in input.iter() {
+            if let Some(p) = self.process_one(*bit) {
+                debug!(
+                    "BleDecode: {:?} from {}",
+                    p.pdu_type,
+                    p.adv_address().unwrap_or_default()
+                );
+                self.packets += 1;
+                self.dst.push(p, &[]);
+            }
+        }
+        let n = input.len();
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    // ADV_NONCONN_IND with a name and manufacturer data.
+    fn pdu() -> Vec<u8> {
+        let mut payload = vec![0x22, 0x11, 0x00, 0xee, 0xff, 0xc0];
+        payload.extend([6, 0x09, b'r', b'u', b's', b't', b'y']);
+        payload.extend([5, 0xff, 0x4c, 0x00, 0x12, 0x34]);
+        let mut pdu = vec![0x42, payload.len() as u8];
+        pdu.extend(payload);
+        pdu
+    }
+
+    fn air_bits(channel: u8, pdu: &[u8]) -> Vec<u8> {
+        let mut data = pdu.to_vec();
+        data.extend(crc24(ADV_CRC_INIT, pdu));
+        whiten(channel, &mut data);
+        let mut bytes = vec![0x11, 0x22, 0xaa];
+        bytes.extend(ADV_ACCESS_ADDRESS.to_le_bytes());
+        bytes.extend(data);
+        bytes.push(0x55);
+        bytes
+            .iter()
+            .flat_map(|b| (0..8).map(move |n| (b >> n) & 1))
+            .collect()
+    }
+
+    fn decode(channel: u8, bits: &[u8]) -> Result<Vec<BlePacket>> {
+        let mut b = BleDecode::new(streamp_from_slice(bits), channel)?;
+        b.work()?;
+        let out = b.out();
+        let mut ret = Vec::new();
+        while let Some((p, _)) = out.pop() {
+            ret.push(p);
+        }
+        Ok(ret)
+    }
+
+    #[test]
+    fn whitening() {
+        let mut data: Vec<u8> = (0..50).collect();
+        whiten(37, &mut data);
+        assert_ne!(data, (0..50).collect::<Vec<u8>>());
+        whiten(37, &mut data);
+        assert_eq!(data, (0..50).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn advertisement() -> Result<()> {
+        for channel in [37, 38, 39] {
+            let got = decode(channel, &air_bits(channel, &pdu()))?;
+            assert_eq!(got.len(), 1);
+            let p = &got[0];
+            assert_eq!(p.pdu_type, PduType::AdvNonconnInd);
+            assert!(p.tx_add);
+            assert_eq!(p.adv_address().unwrap(), "C0:FF:EE:00:11:22");
+            assert_eq!(p.local_name().unwrap(), "rusty");
+            assert_eq!(p.manufacturer_data().unwrap(), (0x004c, vec![0x12, 0x34]));
+            assert_eq!(p.ad().len(), 2);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> Result<()> {
+        // One access address bit error is fine.
+        let mut bits = air_bits(37, &pdu());
+        bits[30] ^= 1;
+        assert_eq!(decode(37, &bits)?.len(), 1);
+
+        // Payload bit error fails CRC.
+        let mut bits = air_bits(37, &pdu());
+        bits[100] ^= 1;
+        assert!(decode(37, &bits)?.is_empty());
+
+        // Wrong channel dewhitens wrong.
+        assert!(decode(38, &air_bits(37, &pdu()))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn channels() {
+        assert_eq!(channel_frequency(37).unwrap(), 2402e6);
+        assert_eq!(channel_frequency(0).unwrap(), 2404e6);
+        assert_eq!(channel_frequency(36).unwrap(), 2478e6);
+        assert!(channel_frequency(40).is_err());
+    }
+}
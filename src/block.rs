@@ -3,12 +3,59 @@
 Blocks are the main buildingblocks of rustradio. They each do one
 thing, and you connect them together with streams to process the data.
 
+# Deriving Block
+
+Blocks that take one sample (or a fixed number of samples) from each
+input, and produce one sample for each output, can derive `Block`
+instead of writing `work()`. Mark stream fields with
+`#[rustradio(input)]` and `#[rustradio(output)]`, and implement
+`process_one`. It gets one sample per input, in field order, and
+returns one sample, or a tuple with one sample per output.
+
+Each call to `work()` processes as many samples as are available on
+every input and fit in every output, i.e. the minimum of them. The
+rest stays in the longer inputs until the others catch up.
+
+Tags are copied from the first input. If there's only one output, an
+`out()` method is also generated.
+
+Struct attributes:
+* `#[rustradio(process = f)]`: call `f` instead of `process_one`.
+* `#[rustradio(name = "X")]`: block name. Defaults to the struct name.
+* `#[rustradio(decim = N)]`: process `N` samples per input at a time,
+  passed as slices. `N` is an integer, or the name of a `usize` field.
+
+The generated impls copy the struct's generics and where clause, so
+any bounds that `process_one` needs must be on the struct.
+
+```
+use rustradio::block::Block;
+use rustradio::stream::{new_streamp, Streamp};
+
+#[derive(Block)]
+#[rustradio(decim = 2)]
+struct MaxPair {
+    #[rustradio(input)]
+    src: Streamp<u8>,
+    #[rustradio(output)]
+    dst: Streamp<u8>,
+}
+
+impl MaxPair {
+    fn process_one(&mut self, s: &[u8]) -> u8 {
+        s[0].max(s[1])
+    }
+}
+```
 */
 
 use anyhow::Result;
 
+use crate::control::Control;
 use crate::Error;
 
+pub use rustradio_macros::Block;
+
 /** Return type for all blocks.
 
 This will let the scheduler know if more data could come out of this block, or if
@@ -58,22 +105,111 @@ pub trait Block {
 
     /** Block work function
 
-    # Args
-    * `r`: Object representing all input streams to read from.
-    * `w`: Object representing all output streams to write to.
-
-    A pure Source block will not use `r`, and a pure Sink block won't
-    use `w`.
+    Blocks get their input streams when constructed, and own their
+    output streams, so `work()` takes no arguments. A pure Source
+    block has no input stream, and a pure Sink block no output
+    stream.
 
-    Consuming data from `r` involves first reading it, and then
-    "consuming" from the stream. If a `consume()` (or `clear()`) is
-    not called on the stream, the same data will continue to be read
-    forever.
+    Consuming data from an input stream involves first reading it
+    with `read_buf()`, and then calling `consume()` on the returned
+    buffer. If `consume()` is not called, the same data will continue
+    to be read forever.
 
-    Writing data to streams in `w` only involves calling `.write()` on
-    the stream.
+    Writing data involves getting a buffer with `write_buf()` on an
+    output stream, filling it, and calling `produce()`.
      */
     fn work(&mut self) -> Result<BlockRet, Error>;
+
+    /** How many items this block wants per `work()` call.
+
+    Graphs use this when the block is added with
+    [Graph::add](crate::graph::Graph::add) or
+    [MTGraph::add](crate::mtgraph::MTGraph::add). The default is no
+    preference. See [WorkHints].
+     */
+    fn work_hints(&self) -> WorkHints {
+        WorkHints::default()
+    }
+
+    /** Parameters that can be changed while the graph is running.
+
+    Graphs collect these with
+    [Graph::controls](crate::graph::Graph::controls), which has to be
+    called before `run()`. The default is none. See
+    [crate::control].
+     */
+    fn controls(&self) -> Vec<Control> {
+        Vec::new()
+    }
+}
+
+/** Hints to the scheduler about how many items to hand a block per
+`work()` call.
+
+Heavy blocks can ask for a minimum, to amortize their per-call
+overhead over large chunks. Latency critical blocks, like audio output
+or PTT control, can ask for a maximum, so that they get their input
+in small chunks instead of whatever has piled up.
+
+The scheduler applies these by limiting what the block sees from
+`read_buf()` and `write_buf()`:
+* With a minimum, an input stream looks empty until it has at least
+  that many items. If the input stops growing, e.g. at end of file,
+  the rest is handed over anyway.
+* With a maximum, each `read_buf()` and `write_buf()` returns at most
+  that many items. A block that needs more than that per call, like
+  [FftFilter](crate::blocks::FftFilter) needing a whole segment, will
+  never run.
+
+```
+use rustradio::block::WorkHints;
+use rustradio::blocks::{ConstantSource, NullSink};
+use rustradio::graph::Graph;
+let src = ConstantSource::new(1.0f32);
+let sink = NullSink::new(src.out());
+let mut g = Graph::new();
+g.add(Box::new(src));
+g.add_with_hints(Box::new(sink), WorkHints::new().max_items(256));
+```
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorkHints {
+    /// Don't call the block with fewer than this many input items.
+    pub min_items: Option<usize>,
+
+    /// Don't hand the block more than this many items per buffer.
+    pub max_items: Option<usize>,
+}
+
+impl WorkHints {
+    /// Create hints with no preference.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set minimum items.
+    pub fn min_items(mut self, n: usize) -> Self {
+        self.min_items = Some(n);
+        self
+    }
+
+    /// Set maximum items.
+    pub fn max_items(mut self, n: usize) -> Self {
+        self.max_items = Some(n);
+        self
+    }
+}
+
+/** One-to-one blocks that can transform samples in place.
+
+Chains of such blocks can then be run as one
+[InPlaceChain](crate::in_place::InPlaceChain), copying the samples
+only once for the whole chain. Blocks made with
+[map_block_macro_v2](crate::map_block_macro_v2) implement it.
+*/
+pub trait InPlace<T> {
+    /// Transform the samples in place.
+    fn process_in_place(&mut self, s: &mut [T]);
 }
 
 /** Macro to make it easier to write one-for-one blocks.
@@ -123,6 +259,16 @@ macro_rules! map_block_macro_v2 {
                 self.dst.clone()
             }
         }
+        impl<T> $crate::block::InPlace<T> for $name
+        where
+            T: Copy $(+$tr)*,
+        {
+            fn process_in_place(&mut self, s: &mut [T]) {
+                for x in s.iter_mut() {
+                    *x = self.process_one(x);
+                }
+            }
+        }
         impl<T> $crate::block::Block for $name
         where
             T: Copy $(+$tr)*,
@@ -274,3 +420,72 @@ macro_rules! map_block_convert_tag_macro {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::{new_streamp, Streamp, Tag, TagValue};
+    use crate::vector_source::VectorSource;
+
+    #[derive(Block)]
+    #[rustradio(process = split, name = "Splitter", decim = decim)]
+    struct Splitter {
+        decim: usize,
+        #[rustradio(input)]
+        src: Streamp<u8>,
+        #[rustradio(output)]
+        sum: Streamp<u32>,
+        #[rustradio(output)]
+        first: Streamp<u8>,
+    }
+
+    impl Splitter {
+        fn split(&mut self, s: &[u8]) -> (u32, u8) {
+            (s.iter().map(|&x| x as u32).sum(), s[0])
+        }
+    }
+
+    #[test]
+    fn derive() -> Result<()> {
+        let mut src = VectorSource::new(vec![1u8, 2, 3, 4, 5, 6, 7]);
+        src.work()?;
+        let mut b = Splitter {
+            decim: 3,
+            src: src.out(),
+            sum: new_streamp(),
+            first: new_streamp(),
+        };
+        assert_eq!(b.block_name(), "Splitter");
+        assert!(matches!(b.work()?, BlockRet::Ok));
+        assert!(matches!(b.work()?, BlockRet::Noop));
+        let (sum, tags) = b.sum.read_buf()?;
+        assert_eq!(sum.slice(), &[6, 15]);
+        assert!(tags.contains(&Tag::new(
+            0,
+            "VectorSource::first".into(),
+            TagValue::Bool(true)
+        )));
+        let (first, _) = b.first.read_buf()?;
+        assert_eq!(first.slice(), &[1, 4]);
+        // Leftover sample.
+        assert_eq!(src.out().read_buf()?.0.len(), 1);
+        Ok(())
+    }
+    #[test]
+    fn tags_through_map_blocks() -> Result<()> {
+        use crate::blocks::{AddConst, MultiplyConst};
+        use crate::stream::{TAG_BURST_END, TAG_BURST_START};
+        let src = new_streamp();
+        let tags = vec![
+            Tag::new(2, TAG_BURST_START.into(), TagValue::Bool(true)),
+            Tag::new(8, TAG_BURST_END.into(), TagValue::Bool(true)),
+        ];
+        src.write_buf()?.produce(10, &tags);
+        let mut add = AddConst::new(src, 1.0);
+        let mut mul = MultiplyConst::new(add.out(), 2.0);
+        add.work()?;
+        mul.work()?;
+        assert_eq!(mul.out().read_buf()?.1, tags);
+        Ok(())
+    }
+}
@@ -1,49 +1,144 @@
 //! Convenient mod collecting all standard library blocks for import.
 pub use crate::add::Add;
 pub use crate::add_const::{add_const, AddConst};
+pub use crate::afsk::{AfskModulator, AfskModulatorBuilder};
+#[cfg(feature = "decoders")]
+pub use crate::aprs::AprsDecode;
+#[cfg(feature = "decoders")]
+pub use crate::aprs_is::AprsIsSink;
 pub use crate::au::{AuDecode, AuEncode};
+pub use crate::audio_sink::{AudioSink, AudioSinkBuilder};
+pub use crate::audio_source::{AudioSource, AudioSourceBuilder};
+pub use crate::ber::{BerSink, BerSinkBuilder, PrbsSource};
 pub use crate::binary_slicer::BinarySlicer;
+#[cfg(feature = "decoders")]
+pub use crate::ble::BleDecode;
+#[cfg(feature = "json")]
+pub use crate::burst_file_sink::{BurstFileSink, BurstFileSinkBuilder};
 pub use crate::burst_tagger::BurstTagger;
-pub use crate::complex_to_mag2::ComplexToMag2;
+pub use crate::callback_sink::CallbackSink;
+#[cfg(feature = "decoders")]
+pub use crate::ccsds::CcsdsPacketizer;
+pub use crate::channel_model::{ChannelModel, ChannelModelBuilder};
+pub use crate::channelizer::{Channelizer, ChannelizerBuilder};
+pub use crate::complex_to_mag2::{ComplexToMag2, ComplexToMagSq};
 pub use crate::constant_source::ConstantSource;
-pub use crate::convert::{FloatToComplex, MapBuilder};
+pub use crate::constellation_sink::ConstellationSink;
+pub use crate::convert::{
+    ComplexToArg, ComplexToImag, ComplexToInterleavedI16, ComplexToMag, ComplexToReal, Filter,
+    FilterMap, FloatToComplex, FloatToI16, I16ToFloat, InterleavedI16ToComplex,
+    InterleavedI8ToComplex, Map, MapBuilder, RealImagToComplex, U8ToFloat,
+};
 pub use crate::correlate_access_code::{CorrelateAccessCode, CorrelateAccessCodeTag};
+#[cfg(all(feature = "fft", feature = "json"))]
+pub use crate::dashboard::{Dashboard, Probe};
+pub use crate::dc_blocker::DcBlocker;
 pub use crate::debug_sink::{DebugFilter, DebugSink, DebugSinkNoCopy};
 pub use crate::delay::Delay;
 pub use crate::descrambler::Descrambler;
+pub use crate::divide::Divide;
+#[cfg(feature = "sgp4")]
+pub use crate::doppler::{Doppler, DopplerBuilder};
+#[cfg(feature = "decoders")]
+pub use crate::dtmf::DtmfDecode;
+#[cfg(feature = "fft")]
 pub use crate::fft_filter::FftFilter;
+#[cfg(feature = "fft")]
 pub use crate::fft_filter::FftFilterFloat;
 pub use crate::file_sink::{FileSink, NoCopyFileSink};
 pub use crate::file_source::FileSource;
 pub use crate::fir::FIRFilter;
-pub use crate::hdlc_deframer::HdlcDeframer;
+#[cfg(feature = "fft")]
+pub use crate::freq_offset::{FreqOffset, FreqOffsetBuilder};
+pub use crate::freq_xlating_fir::FreqXlatingFir;
+pub use crate::frequency_modulator::FrequencyModulator;
+pub use crate::hdlc_deframer::{HdlcDeframer, HdlcDeframerBuilder};
+pub use crate::hdlc_framer::{HdlcFramer, HdlcFramerBuilder};
 pub use crate::hilbert::Hilbert;
+#[cfg(all(feature = "fft", feature = "json"))]
+pub use crate::histogram_probe::HistogramProbe;
 pub use crate::il2p_deframer::Il2pDeframer;
+pub use crate::in_place::InPlaceChain;
+pub use crate::iq_balance::IqBalance;
+#[cfg(all(feature = "decoders", feature = "json"))]
+pub use crate::ism::{IsmDecode, PulseSlicer};
+#[cfg(all(feature = "decoders", feature = "fft"))]
+pub use crate::lora::LoraDecode;
+#[cfg(all(feature = "decoders", feature = "png"))]
+pub use crate::lrpt::{LrptDecode, LrptDeinterleave, LrptDemod, LrptImageWriter};
+#[cfg(feature = "decoders")]
+pub use crate::morse::{MorseKeyer, MorseKeyerBuilder};
+pub use crate::mqtt_sink::{MqttSink, MqttSinkBuilder};
+pub use crate::multiply::Multiply;
 pub use crate::multiply_const::MultiplyConst;
-pub use crate::nrzi::NrziDecode;
+pub use crate::noise_source::{NoiseSource, NoiseSourceBuilder};
+pub use crate::nrzi::{NrziDecode, NrziEncode};
 pub use crate::null_sink::NullSink;
+pub use crate::pcap_sink::PcapSink;
+pub use crate::pdu::{PduData, ToPdu};
+pub use crate::pdu_filter::PduFilter;
+pub use crate::pdu_queue::PduQueue;
+pub use crate::pdu_to_stream::PduToStream;
 pub use crate::pdu_writer::PduWriter;
-pub use crate::quadrature_demod::{FastFM, QuadratureDemod};
+pub use crate::pll::{CostasLoop, CostasLoopBuilder, Pll, PllBuilder};
+pub use crate::power_meter::{PowerMeter, PowerMeterBuilder};
+pub use crate::power_squelch::{PowerSquelch, PowerSquelchBuilder, PowerSquelchHandle};
+#[cfg(feature = "decoders")]
+pub use crate::psk31::{Psk31Demod, Psk31Modulator};
+pub use crate::push_source::PushSource;
+pub use crate::quadrature_demod::{FastFM, QuadratureDemod, QuadratureDemodBuilder};
 pub use crate::rational_resampler::RationalResampler;
+#[cfg(feature = "decoders")]
+pub use crate::rds::RdsDecode;
+pub use crate::rotator::{Rotator, RotatorHandle};
+pub use crate::rtl_tcp_source::{RtlTcpHandle, RtlTcpSource, RtlTcpSourceBuilder};
 pub use crate::rtlsdr_decode::RtlSdrDecode;
-pub use crate::sigmf::SigMFSourceBuilder;
-pub use crate::signal_source::SignalSourceComplex;
+#[cfg(feature = "decoders")]
+pub use crate::rtty::RttyDecode;
+pub use crate::rx_meta::{AnnotatePdu, SignalMeter};
+#[cfg(feature = "decoders")]
+pub use crate::selcall::SelcallDecode;
+#[cfg(feature = "json")]
+pub use crate::sigmf::{SigMFSink, SigMFSinkBuilder, SigMFSource, SigMFSourceBuilder};
+pub use crate::signal_source::{SignalSource, SignalSourceBuilder, SignalSourceComplex};
 pub use crate::single_pole_iir_filter::SinglePoleIIRFilter;
 pub use crate::skip::Skip;
-pub use crate::stream_to_pdu::StreamToPdu;
+#[cfg(all(feature = "fft", feature = "png"))]
+pub use crate::spectrogram_sink::SpectrogramSink;
+#[cfg(feature = "fft")]
+pub use crate::spectrum_sink::SpectrumSink;
+#[cfg(all(feature = "decoders", feature = "png"))]
+pub use crate::sstv::{SstvDecode, SstvPngWriter};
+pub use crate::stream_to_pdu::{StreamToPdu, TagToPdu};
+pub use crate::subtract::Subtract;
 pub use crate::symbol_sync::SymbolSync;
+pub use crate::tcp_pdu::{TcpPduSink, TcpPduSource};
 pub use crate::tcp_source::TcpSource;
 pub use crate::tee::Tee;
+#[cfg(feature = "decoders")]
+pub use crate::time_signal::TimeSignalDecode;
 pub use crate::to_text::ToText;
+pub use crate::udp_sink::UdpSink;
 pub use crate::vec_to_stream::VecToStream;
+pub use crate::vector_sink::VectorSink;
 pub use crate::vector_source::{VectorSource, VectorSourceBuilder};
+pub use crate::vu_meter::VuMeter;
+#[cfg(feature = "fft")]
+pub use crate::waterfall_sink::WaterfallSink;
+pub use crate::wbfm::{Deemph, WbfmDecode, WbfmDecodeBuilder};
+#[cfg(feature = "decoders")]
+pub use crate::wmbus::WmbusDecode;
+#[cfg(feature = "fft")]
 pub use crate::wpcr::{Midpointer, Wpcr, WpcrBuilder};
 pub use crate::xor::Xor;
 pub use crate::xor_const::XorConst;
 pub use crate::zero_crossing::ZeroCrossing;
 
 #[cfg(feature = "rtlsdr")]
-pub use crate::rtlsdr_source::RtlSdrSource;
+pub use crate::rtlsdr_source::{RtlSdrHandle, RtlSdrSource, RtlSdrSourceBuilder};
 
 #[cfg(feature = "soapysdr")]
 pub use crate::soapysdr_source::{SoapySdrSource, SoapySdrSourceBuilder};
+
+#[cfg(feature = "zeromq")]
+pub use crate::zmq::{ZmqPubSink, ZmqSubSource, ZmqSubSourceBuilder};
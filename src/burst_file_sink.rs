@@ -0,0 +1,389 @@
+/*! Write each burst to its own file.
+
+For scanner style capture of intermittent signals, like APRS or
+pagers. Bursts are marked with the common burst tags,
+[TAG_BURST_START] on the first sample and [TAG_BURST_END] on the last,
+like from [PowerSquelch][crate::power_squelch::PowerSquelch]. Samples
+outside of bursts are discarded.
+
+```text
+   Source -> PowerSquelch -> BurstFileSink -> dir/1700000000123456.sigmf-data
+                                              dir/1700000000123456.sigmf-meta
+                                              dir/1700000004000000.sigmf-data
+                                              …
+```
+
+Files are named after when the burst started, in microseconds since
+the epoch, like [PduWriter][crate::pdu_writer::PduWriter]. Raw files
+have no extension. SigMF recordings also get the start time, and an
+annotation covering the burst.
+
+```no_run
+use rustradio::blocks::{BurstFileSink, ConstantSource, PowerSquelch};
+use rustradio::burst_file_sink::BurstFormat;
+use rustradio::Complex;
+let src = ConstantSource::new(Complex::new(1.0, 0.0));
+let sq = PowerSquelch::builder(src.out(), -40.0)
+    .release(48_000)
+    .gate(true)
+    .build();
+let sink = BurstFileSink::builder(sq.out(), "bursts".into())
+    .format(BurstFormat::SigMF)
+    .sample_rate(48_000.0)
+    .frequency(144_800_000.0)
+    .build();
+```
+*/
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use log::{debug, error, info};
+
+use crate::block::{Block, BlockRet};
+use crate::sigmf::{SigMFSink, Type};
+use crate::stream::{new_streamp, Streamp, Tag, TagValue, TAG_BURST_END, TAG_BURST_START};
+use crate::{Error, Sample};
+
+/// File format for [BurstFileSink].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum BurstFormat {
+    /// Raw samples, in the same format as
+    /// [FileSink][crate::file_sink::FileSink].
+    #[default]
+    Raw,
+
+    /// SigMF recording, data and meta file.
+    SigMF,
+}
+
+/// Builder for [BurstFileSink].
+pub struct BurstFileSinkBuilder<T> {
+    src: Streamp<T>,
+    dir: PathBuf,
+    format: BurstFormat,
+    sample_rate: Option<f64>,
+    frequency: Option<f64>,
+    description: Option<String>,
+}
+
+impl<T> BurstFileSinkBuilder<T> {
+    /// Create new builder, writing files in `dir`.
+    pub fn new(src: Streamp<T>, dir: PathBuf) -> Self {
+        Self {
+            src,
+            dir,
+            format: BurstFormat::default(),
+            sample_rate: None,
+            frequency: None,
+            description: None,
+        }
+    }
+
+    /// Set file format. Default raw.
+    pub fn format(mut self, format: BurstFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set sample rate, for SigMF.
+    pub fn sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = Some(rate);
+        self
+    }
+
+    /// Set center frequency, for SigMF.
+    pub fn frequency(mut self, freq: f64) -> Self {
+        self.frequency = Some(freq);
+        self
+    }
+
+    /// Set description, for SigMF.
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Build BurstFileSink.
+    pub fn build(self) -> BurstFileSink<T>
+    where
+        T: Copy,
+    {
+        BurstFileSink {
+            src: self.src,
+            dir: self.dir,
+            format: self.format,
+            sample_rate: self.sample_rate,
+            frequency: self.frequency,
+            description: self.description,
+            writer: None,
+            last_name: 0,
+            files_written: 0,
+        }
+    }
+}
+
+// The file of the current burst.
+enum Writer<T: Copy> {
+    Raw(BufWriter<std::fs::File>),
+    SigMF {
+        src: Streamp<T>,
+        sink: Box<SigMFSink<T>>,
+    },
+}
+
+/// Write each burst to its own file. See the [module
+/// docs][crate::burst_file_sink].
+pub struct BurstFileSink<T: Copy> {
+    src: Streamp<T>,
+    dir: PathBuf,
+    format: BurstFormat,
+    sample_rate: Option<f64>,
+    frequency: Option<f64>,
+    description: Option<String>,
+    writer: Option<Writer<T>>,
+    // Microseconds in the last file name, to keep them unique.
+    last_name: u128,
+    files_written: usize,
+}
+
+impl<T: Copy> BurstFileSink<T> {
+    /// Create new builder.
+    pub fn builder(src: Streamp<T>, dir: PathBuf) -> BurstFileSinkBuilder<T> {
+        BurstFileSinkBuilder::new(src, dir)
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        match self.writer.take() {
+            None => return Ok(()),
+            Some(Writer::Raw(mut f)) => f.flush()?,
+            Some(Writer::SigMF { mut sink, .. }) => {
+                let n = sink.samples();
+                sink.annotate(0, Some(n), "burst");
+                sink.close()?;
+            }
+        }
+        self.files_written += 1;
+        Ok(())
+    }
+}
+
+impl<T: Copy> Drop for BurstFileSink<T> {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            error!("BurstFileSink: failed to close burst: {e}");
+        }
+        info!("BurstFileSink: wrote {}", self.files_written);
+    }
+}
+
+impl<T> BurstFileSink<T>
+where
+    T: Sample<Type = T> + Copy + std::fmt::Debug + Type,
+{
+    fn open(&mut self) -> Result<(), Error> {
+        self.close()?;
+        let now = SystemTime::now();
+        let micros = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros()
+            .max(self.last_name + 1);
+        self.last_name = micros;
+        self.writer = Some(match self.format {
+            BurstFormat::Raw => {
+                let full = self.dir.join(micros.to_string());
+                debug!("BurstFileSink: opening {full:?}");
+                Writer::Raw(BufWriter::new(std::fs::File::create(full)?))
+            }
+            BurstFormat::SigMF => {
+                let full = self.dir.join(format!("{micros}.sigmf"));
+                debug!("BurstFileSink: opening {full:?}");
+                let src = new_streamp();
+                let mut b = SigMFSink::builder(src.clone(), &full.to_string_lossy()).datetime(now);
+                if let Some(r) = self.sample_rate {
+                    b = b.sample_rate(r);
+                }
+                if let Some(f) = self.frequency {
+                    b = b.frequency(f);
+                }
+                if let Some(d) = &self.description {
+                    b = b.description(d);
+                }
+                Writer::SigMF {
+                    src,
+                    sink: Box::new(b.build()?),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[T], tags: &[Tag]) -> Result<(), Error> {
+        match &mut self.writer {
+            None => {}
+            Some(Writer::Raw(f)) => {
+                for s in samples {
+                    f.write_all(&s.serialize())?;
+                }
+            }
+            Some(Writer::SigMF { src, sink }) => {
+                let mut done = 0;
+                while done < samples.len() {
+                    let n = {
+                        let mut o = src.write_buf()?;
+                        let n = std::cmp::min(o.len(), samples.len() - done);
+                        o.fill_from_slice(&samples[done..done + n]);
+                        let tags: Vec<_> = tags
+                            .iter()
+                            .filter(|t| (done..done + n).contains(&t.pos()))
+                            .map(|t| t.with_pos(t.pos() - done))
+                            .collect();
+                        o.produce(n, &tags);
+                        n
+                    };
+                    sink.work()?;
+                    done += n;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T> Block for BurstFileSink<T>
+where
+    T: Sample<Type = T> + Copy + std::fmt::Debug + Type,
+{
+    fn block_name(&self) -> &str {
+        "BurstFileSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        // Clone, so that `self` can be borrowed mutably for writing.
+        let src = self.src.clone();
+        let (i, tags) = src.read_buf()?;
+        let n = i.len();
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        // Starts before ends on the same sample.
+        let mut bursts: Vec<_> = tags
+            .iter()
+            .filter(|t| t.val() == &TagValue::Bool(true))
+            .filter_map(|t| match t.key() {
+                TAG_BURST_START => Some((t.pos(), false)),
+                TAG_BURST_END => Some((t.pos(), true)),
+                _ => None,
+            })
+            .collect();
+        bursts.sort();
+        let mut pos = 0;
+        for (tpos, end) in bursts {
+            if end {
+                self.write(&i.slice()[pos..=tpos], &segment_tags(&tags, pos, tpos + 1))?;
+                self.close()?;
+                pos = tpos + 1;
+            } else {
+                self.write(&i.slice()[pos..tpos], &segment_tags(&tags, pos, tpos))?;
+                self.open()?;
+                pos = tpos;
+            }
+        }
+        self.write(&i.slice()[pos..], &segment_tags(&tags, pos, n))?;
+        i.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+// Tags in `start..end`, relative to `start`.
+fn segment_tags(tags: &[Tag], start: usize, end: usize) -> Vec<Tag> {
+    tags.iter()
+        .filter(|t| (start..end).contains(&t.pos()))
+        .map(|t| t.with_pos(t.pos() - start))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Complex, Float};
+
+    fn input() -> crate::Result<Streamp<Complex>> {
+        let src = new_streamp();
+        let mut o = src.write_buf()?;
+        let v: Vec<_> = (0..20).map(|n| Complex::new(n as Float, 0.0)).collect();
+        o.fill_from_slice(&v);
+        let tag = |pos, key: &str| Tag::new(pos, key.into(), TagValue::Bool(true));
+        o.produce(
+            20,
+            &[
+                tag(2, TAG_BURST_START),
+                tag(4, TAG_BURST_END),
+                tag(10, TAG_BURST_START),
+                tag(12, TAG_BURST_END),
+                tag(18, TAG_BURST_START),
+            ],
+        );
+        Ok(src)
+    }
+
+    // Files in `dir`, sorted by name.
+    fn files(dir: &std::path::Path) -> crate::Result<Vec<PathBuf>> {
+        let mut v: Vec<_> = std::fs::read_dir(dir)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<Result<_, _>>()?;
+        v.sort();
+        Ok(v)
+    }
+
+    fn parse(data: &[u8]) -> Vec<Float> {
+        data.chunks_exact(Complex::size())
+            .map(|c| Complex::parse(c).unwrap().re)
+            .collect()
+    }
+
+    #[test]
+    fn raw() -> crate::Result<()> {
+        let tmpd = tempfile::tempdir()?;
+        {
+            let mut sink = BurstFileSink::builder(input()?, tmpd.path().into()).build();
+            sink.work()?;
+        }
+        let files = files(tmpd.path())?;
+        assert_eq!(files.len(), 3);
+        assert_eq!(parse(&std::fs::read(&files[0])?), &[2.0, 3.0, 4.0]);
+        assert_eq!(parse(&std::fs::read(&files[1])?), &[10.0, 11.0, 12.0]);
+        // Closed when the sink was dropped.
+        assert_eq!(parse(&std::fs::read(&files[2])?), &[18.0, 19.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn sigmf() -> crate::Result<()> {
+        let tmpd = tempfile::tempdir()?;
+        let mut sink = BurstFileSink::builder(input()?, tmpd.path().into())
+            .format(BurstFormat::SigMF)
+            .sample_rate(1000.0)
+            .frequency(144_800_000.0)
+            .build();
+        sink.work()?;
+        let files = files(tmpd.path())?;
+        // Two done, and the data file of the third.
+        assert_eq!(files.len(), 5);
+        let name = files[0].to_string_lossy().to_string();
+        let base = name.strip_suffix(".sigmf-data").unwrap();
+        assert_eq!(parse(&std::fs::read(&files[0])?), &[2.0, 3.0, 4.0]);
+        let meta: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(format!("{base}.sigmf-meta"))?)?;
+        assert_eq!(meta["global"]["core:sample_rate"], 1000.0);
+        assert_eq!(meta["captures"][0]["core:frequency"], 144_800_000.0);
+        assert!(meta["captures"][0]["core:datetime"]
+            .as_str()
+            .unwrap()
+            .ends_with('Z'));
+        assert_eq!(meta["annotations"][0]["core:sample_start"], 0);
+        assert_eq!(meta["annotations"][0]["core:sample_count"], 3);
+        assert_eq!(meta["annotations"][0]["core:label"], "burst");
+        Ok(())
+    }
+}
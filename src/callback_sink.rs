@@ -0,0 +1,68 @@
+//! Call a function for each packet.
+use crate::block::{Block, BlockRet};
+use crate::stream::NoCopyStreamp;
+use crate::Error;
+
+/** Call a function for each packet, or other item, of a stream.
+
+This is for handing packets to code outside the graph, such as an
+application embedding rustradio. The function is called from the
+thread running the graph.
+
+```
+use rustradio::blocks::CallbackSink;
+# use rustradio::stream::new_nocopy_streamp;
+# let src = new_nocopy_streamp::<Vec<u8>>();
+let sink = CallbackSink::new(src, |pdu: Vec<u8>| println!("Got {} bytes", pdu.len()));
+```
+*/
+pub struct CallbackSink<T> {
+    src: NoCopyStreamp<T>,
+    f: Box<dyn FnMut(T) + Send>,
+}
+
+impl<T> CallbackSink<T> {
+    /// Create new CallbackSink.
+    pub fn new(src: NoCopyStreamp<T>, f: impl FnMut(T) + Send + 'static) -> Self {
+        Self {
+            src,
+            f: Box::new(f),
+        }
+    }
+}
+
+impl<T> Block for CallbackSink<T> {
+    fn block_name(&self) -> &str {
+        "CallbackSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (v, _tags) = match self.src.pop() {
+            None => return Ok(BlockRet::Noop),
+            Some(x) => x,
+        };
+        (self.f)(v);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::new_nocopy_streamp;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn calls() -> Result<(), Error> {
+        let src = new_nocopy_streamp();
+        let got = Arc::new(Mutex::new(Vec::new()));
+        let g2 = got.clone();
+        let mut b = CallbackSink::new(src.clone(), move |v: Vec<u8>| g2.lock().unwrap().push(v));
+        src.push(vec![1, 2], &[]);
+        src.push(vec![3], &[]);
+        assert!(matches!(b.work()?, BlockRet::Ok));
+        assert!(matches!(b.work()?, BlockRet::Ok));
+        assert!(matches!(b.work()?, BlockRet::Noop));
+        assert_eq!(*got.lock().unwrap(), vec![vec![1, 2], vec![3]]);
+        Ok(())
+    }
+}
@@ -0,0 +1,638 @@
+/*! C API, for embedding rustradio in C, C++, and other languages.
+
+Only available with the `capi` feature. The declarations are in
+`include/rustradio.h`. Build a shared or static library with:
+
+```text
+$ cargo rustc --release --lib --features capi --crate-type cdylib
+$ cargo rustc --release --lib --features capi --crate-type staticlib
+```
+
+Graphs are built from [registry][crate::registry] blocks, by name,
+with parameters as a JSON object. Streams are numbered, and
+`rustradio_graph_add()` returns the numbers of the new block's output
+streams, to connect to the next block:
+
+```text
+rustradio_graph* g = rustradio_graph_new();
+size_t s;
+rustradio_graph_add(g, "file_source_b", NULL, 0,
+                    "{\"filename\": \"bits.u8\"}", &s, 1);
+rustradio_graph_add(g, "hdlc_deframer", &s, 1, NULL, &s, 1);
+rustradio_graph_add_pdu_callback(g, s, on_packet, app);
+rustradio_graph_run(g);
+rustradio_graph_free(g);
+```
+
+Functions returning `int` return 0, or a count, on success, and -1 on
+error. `rustradio_last_error()` then describes the error.
+
+`rustradio_graph_cancel()` and `rustradio_graph_set_control()` may be
+called from any thread, also while another thread is in
+`rustradio_graph_wait()`. Callbacks are called from the thread running
+the graph.
+
+Block parameters that can change while running, like the frequency of
+a source, are [controls][crate::control], set by block and control
+name. The value is JSON, e.g. `"-500.0"` or `"true"`:
+
+```text
+rustradio_graph_set_control(g, "Rotator", "freq", "-500.0");
+```
+
+The API is versioned by [RUSTRADIO_API_VERSION]. Existing functions
+keep their signatures and behavior within a version.
+*/
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::panic::AssertUnwindSafe;
+use std::sync::Mutex;
+
+use crate::blocks::CallbackSink;
+use crate::graph::CancellationToken;
+use crate::registry::{default_registry, join, AnyStream, Created, DynGraph, GraphSpec, Params};
+use crate::stream::TagValue;
+use crate::Error;
+
+/// Version of the C API.
+pub const RUSTRADIO_API_VERSION: c_int = 1;
+
+/// Graph, as seen from C.
+pub struct Graph {
+    graph: Mutex<DynGraph>,
+    // Separate, so that cancel doesn't wait for wait().
+    cancel: Mutex<Option<CancellationToken>>,
+}
+
+/// Callback for PDUs.
+pub type PduCallback = extern "C" fn(user: *mut c_void, data: *const u8, len: usize);
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_error(msg: &str) {
+    let msg = CString::new(msg.replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = msg);
+}
+
+// Run f, turning errors and panics into -1 and a last error.
+fn status(f: impl FnOnce() -> Result<c_int, Error>) -> c_int {
+    match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(v)) => v,
+        Ok(Err(e)) => {
+            set_error(&e.to_string());
+            -1
+        }
+        Err(_) => {
+            set_error("panic");
+            -1
+        }
+    }
+}
+
+unsafe fn graph<'a>(g: *const Graph) -> Result<&'a Graph, Error> {
+    g.as_ref()
+        .ok_or_else(|| Error::BadParameter("graph is NULL".into()))
+}
+
+unsafe fn string(s: *const c_char, what: &str) -> Result<String, Error> {
+    if s.is_null() {
+        return Err(Error::BadParameter(format!("{what} is NULL")));
+    }
+    Ok(CStr::from_ptr(s)
+        .to_str()
+        .map_err(|e| Error::BadParameter(format!("{what}: {e}")))?
+        .to_string())
+}
+
+/// Return [RUSTRADIO_API_VERSION].
+#[no_mangle]
+pub extern "C" fn rustradio_api_version() -> c_int {
+    RUSTRADIO_API_VERSION
+}
+
+/// Description of the last error on this thread.
+///
+/// Valid until the next call into the library from this thread.
+#[no_mangle]
+pub extern "C" fn rustradio_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ptr())
+}
+
+/// JSON array describing all blocks, or NULL on error.
+///
+/// Free with [rustradio_string_free].
+#[no_mangle]
+pub extern "C" fn rustradio_blocks() -> *mut c_char {
+    let mut ret = std::ptr::null_mut();
+    status(|| {
+        let reg = default_registry();
+        let v: Vec<_> = reg.names().into_iter().map(|n| reg.info(n)).collect();
+        let json = serde_json::to_string(&v).map_err(|e| Error::new(&e.to_string()))?;
+        ret = c_string(json);
+        Ok(0)
+    });
+    ret
+}
+
+fn c_string(s: String) -> *mut c_char {
+    match CString::new(s) {
+        Ok(s) => s.into_raw(),
+        Err(e) => {
+            set_error(&e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Free a string returned by the library.
+///
+/// # Safety
+///
+/// `s` must be NULL, or returned by the library and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn rustradio_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Create a new, empty graph.
+#[no_mangle]
+pub extern "C" fn rustradio_graph_new() -> *mut Graph {
+    Box::into_raw(Box::new(Graph {
+        graph: Mutex::new(DynGraph::new(default_registry())),
+        cancel: Mutex::new(None),
+    }))
+}
+
+/// Create a graph from JSON saved by [rustradio_graph_spec], or NULL
+/// on error.
+///
+/// # Safety
+///
+/// `json` must be a C string.
+#[no_mangle]
+pub unsafe extern "C" fn rustradio_graph_from_spec(json: *const c_char) -> *mut Graph {
+    let mut ret = std::ptr::null_mut();
+    status(|| {
+        let spec = GraphSpec::from_json(&string(json, "json")?)?;
+        let graph = DynGraph::from_spec(default_registry(), &spec)?;
+        ret = Box::into_raw(Box::new(Graph {
+            graph: Mutex::new(graph),
+            cancel: Mutex::new(None),
+        }));
+        Ok(0)
+    });
+    ret
+}
+
+/// Blocks, connections, and parameters of the graph as JSON, or NULL
+/// on error.
+///
+/// Free with [rustradio_string_free].
+///
+/// # Safety
+///
+/// `g` must be a valid graph.
+#[no_mangle]
+pub unsafe extern "C" fn rustradio_graph_spec(g: *mut Graph) -> *mut c_char {
+    let mut ret = std::ptr::null_mut();
+    status(|| {
+        let spec = graph(g)?.graph.lock().unwrap().spec()?;
+        ret = c_string(spec.to_json());
+        Ok(0)
+    });
+    ret
+}
+
+/// Free a graph. A running graph is cancelled, and waited for.
+///
+/// # Safety
+///
+/// `g` must be NULL, or from [rustradio_graph_new] and not yet freed.
+/// No other thread may use it.
+#[no_mangle]
+pub unsafe extern "C" fn rustradio_graph_free(g: *mut Graph) {
+    if g.is_null() {
+        return;
+    }
+    let g = Box::from_raw(g);
+    let mut graph = g.graph.into_inner().unwrap_or_else(|e| e.into_inner());
+    if graph.started() && !graph.finished() {
+        graph.cancel();
+    }
+    let _ = graph.wait();
+}
+
+/// Add block `name`, connected to `n_inputs` streams in `inputs`.
+///
+/// `params` is a JSON object, or NULL for no parameters. The numbers
+/// of the output streams are written to `outputs`, which has room for
+/// `max_outputs`.
+///
+/// Returns the number of output streams, or -1 on error.
+///
+/// # Safety
+///
+/// `g` must be a valid graph, `name` a C string, `params` NULL or a C
+/// string, `inputs` valid for `n_inputs` reads, and `outputs` valid
+/// for `max_outputs` writes.
+#[no_mangle]
+pub unsafe extern "C" fn rustradio_graph_add(
+    g: *mut Graph,
+    name: *const c_char,
+    inputs: *const usize,
+    n_inputs: usize,
+    params: *const c_char,
+    outputs: *mut usize,
+    max_outputs: usize,
+) -> c_int {
+    status(|| {
+        let g = graph(g)?;
+        let name = string(name, "name")?;
+        let params: Params = match params.is_null() {
+            true => Params::new(),
+            false => serde_json::from_str(&string(params, "params")?)
+                .map_err(|e| Error::BadParameter(format!("params: {e}")))?,
+        };
+        let inputs = match n_inputs {
+            0 => &[][..],
+            n => std::slice::from_raw_parts(inputs, n),
+        };
+        let mut graph = g.graph.lock().unwrap();
+        let info = default_registry()
+            .info(&name)
+            .ok_or_else(|| Error::BadParameter(format!("unknown block {name}")))?;
+        if info.outputs.len() > max_outputs {
+            return Err(Error::BadParameter(format!(
+                "{name} has {} outputs, room for {max_outputs}",
+                info.outputs.len()
+            )));
+        }
+        let outs = graph.add(&name, inputs, &params)?;
+        for (n, id) in outs.iter().enumerate() {
+            *outputs.add(n) = *id;
+        }
+        Ok(outs.len() as c_int)
+    })
+}
+
+// User data, passed back to the callback.
+struct UserData(*mut c_void);
+
+// SAFETY: The caller of rustradio_graph_add_pdu_callback promises that
+// the callback can be called with it from another thread.
+unsafe impl Send for UserData {}
+
+impl UserData {
+    // A method, so that closures capture the whole Send struct.
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+/// Call `cb` with each packet in PDU stream `stream`.
+///
+/// # Safety
+///
+/// `g` must be a valid graph. `cb` must be safe to call, with `user`,
+/// from the thread running the graph, until the graph is freed. The
+/// data pointer is only valid during the call.
+#[no_mangle]
+pub unsafe extern "C" fn rustradio_graph_add_pdu_callback(
+    g: *mut Graph,
+    stream: usize,
+    cb: Option<PduCallback>,
+    user: *mut c_void,
+) -> c_int {
+    status(|| {
+        let g = graph(g)?;
+        let cb = cb.ok_or_else(|| Error::BadParameter("callback is NULL".into()))?;
+        let mut graph = g.graph.lock().unwrap();
+        if graph.kind(stream) != Some(crate::registry::StreamKind::Pdu) {
+            return Err(Error::TypeMismatch(format!(
+                "stream {stream} is not a PDU stream"
+            )));
+        }
+        let AnyStream::Pdu(src) = graph.take(stream)? else {
+            unreachable!();
+        };
+        let user = UserData(user);
+        graph.push(Created {
+            block: Box::new(CallbackSink::new(src, move |pdu: Vec<u8>| {
+                cb(user.get(), pdu.as_ptr(), pdu.len())
+            })),
+            outputs: vec![],
+        });
+        Ok(0)
+    })
+}
+
+/// Start running the graph, in a thread of its own.
+///
+/// # Safety
+///
+/// `g` must be a valid graph.
+#[no_mangle]
+pub unsafe extern "C" fn rustradio_graph_start(g: *mut Graph) -> c_int {
+    status(|| {
+        let g = graph(g)?;
+        let cancel = g.graph.lock().unwrap().start()?;
+        *g.cancel.lock().unwrap() = Some(cancel);
+        Ok(0)
+    })
+}
+
+/// Ask a running graph to stop. May be called from any thread. Does
+/// nothing if the graph isn't running.
+///
+/// # Safety
+///
+/// `g` must be a valid graph.
+#[no_mangle]
+pub unsafe extern "C" fn rustradio_graph_cancel(g: *mut Graph) -> c_int {
+    status(|| {
+        let g = graph(g)?;
+        let cancel = g
+            .cancel
+            .lock()
+            .map_err(|_| Error::new("graph lock poisoned"))?;
+        if let Some(c) = &*cancel {
+            c.cancel();
+        }
+        Ok(0)
+    })
+}
+
+/// Wait for a started graph to finish.
+///
+/// The graph isn't locked while waiting, so other threads can cancel
+/// it and set controls.
+///
+/// # Safety
+///
+/// `g` must be a valid graph.
+#[no_mangle]
+pub unsafe extern "C" fn rustradio_graph_wait(g: *mut Graph) -> c_int {
+    status(|| {
+        let g = graph(g)?;
+        let thread = g.graph.lock().unwrap().take_thread()?;
+        join(thread)?;
+        Ok(0)
+    })
+}
+
+// Control value from JSON.
+fn tag_value(json: &str) -> Result<TagValue, Error> {
+    let v: serde_json::Value =
+        serde_json::from_str(json).map_err(|e| Error::BadParameter(format!("value: {e}")))?;
+    Ok(match v {
+        serde_json::Value::Bool(b) => TagValue::Bool(b),
+        serde_json::Value::String(s) => TagValue::String(s),
+        serde_json::Value::Number(n) => match n.as_u64() {
+            Some(u) => TagValue::U64(u),
+            None => TagValue::Float(n.as_f64().unwrap_or_default() as crate::Float),
+        },
+        _ => return Err(Error::BadParameter(format!("bad control value {json}"))),
+    })
+}
+
+/// Set control `name` of block `block` to `value`, a JSON number,
+/// bool, or string. May be called from any thread, also while
+/// running.
+///
+/// # Safety
+///
+/// `g` must be a valid graph, and `block`, `name`, and `value` C
+/// strings.
+#[no_mangle]
+pub unsafe extern "C" fn rustradio_graph_set_control(
+    g: *mut Graph,
+    block: *const c_char,
+    name: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    status(|| {
+        let g = graph(g)?;
+        let block = string(block, "block")?;
+        let name = string(name, "name")?;
+        let value = tag_value(&string(value, "value")?)?;
+        let controls = g.graph.lock().unwrap().controls();
+        controls.set(&block, &name, value)?;
+        Ok(0)
+    })
+}
+
+/// Run the graph until done. Same as start, then wait.
+///
+/// # Safety
+///
+/// `g` must be a valid graph.
+#[no_mangle]
+pub unsafe extern "C" fn rustradio_graph_run(g: *mut Graph) -> c_int {
+    match rustradio_graph_start(g) {
+        0 => rustradio_graph_wait(g),
+        e => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(rustradio_last_error()) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    extern "C" fn collect(user: *mut c_void, data: *const u8, len: usize) {
+        let v = unsafe { &*(user as *const Mutex<Vec<Vec<u8>>>) };
+        v.lock()
+            .unwrap()
+            .push(unsafe { std::slice::from_raw_parts(data, len) }.to_vec());
+    }
+
+    #[test]
+    fn pdus() {
+        let bits: Vec<String> = "01111110010101011110000001111110"
+            .chars()
+            .map(|c| c.to_string())
+            .collect();
+        let params = CString::new(format!(r#"{{"data": [{}]}}"#, bits.join(","))).unwrap();
+        let got: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+        unsafe {
+            let g = rustradio_graph_new();
+            let mut s = [0usize; 2];
+            let name = CString::new("vector_source_b").unwrap();
+            let n = rustradio_graph_add(
+                g,
+                name.as_ptr(),
+                std::ptr::null(),
+                0,
+                params.as_ptr(),
+                s.as_mut_ptr(),
+                2,
+            );
+            assert_eq!(n, 1);
+
+            // Errors.
+            let bad = CString::new("quadrature_demod").unwrap();
+            let n = rustradio_graph_add(
+                g,
+                bad.as_ptr(),
+                s.as_ptr(),
+                1,
+                std::ptr::null(),
+                s.as_mut_ptr(),
+                2,
+            );
+            assert_eq!(n, -1);
+            assert!(last_error().contains("takes inputs"), "{}", last_error());
+            let bad = CString::new("{").unwrap();
+            let n = rustradio_graph_add(
+                g,
+                name.as_ptr(),
+                std::ptr::null(),
+                0,
+                bad.as_ptr(),
+                s.as_mut_ptr(),
+                2,
+            );
+            assert_eq!(n, -1);
+            assert!(last_error().contains("params"), "{}", last_error());
+            assert_eq!(
+                rustradio_graph_add_pdu_callback(g, s[0], Some(collect), std::ptr::null_mut()),
+                -1
+            );
+
+            let name = CString::new("hdlc_deframer").unwrap();
+            let p = CString::new(r#"{"min_size": 1, "checksum": false}"#).unwrap();
+            let n = rustradio_graph_add(
+                g,
+                name.as_ptr(),
+                s.as_ptr(),
+                1,
+                p.as_ptr(),
+                s.as_mut_ptr(),
+                1,
+            );
+            assert_eq!(n, 1, "{}", last_error());
+            let user = &got as *const _ as *mut c_void;
+            assert_eq!(
+                rustradio_graph_add_pdu_callback(g, s[0], Some(collect), user),
+                0
+            );
+
+            // Save and restore.
+            let json = rustradio_graph_spec(g);
+            assert!(!json.is_null(), "{}", last_error());
+            let g2 = rustradio_graph_from_spec(json);
+            rustradio_string_free(json);
+            assert!(!g2.is_null(), "{}", last_error());
+            rustradio_graph_free(g2);
+            let bad = CString::new("[]").unwrap();
+            assert!(rustradio_graph_from_spec(bad.as_ptr()).is_null());
+            assert!(last_error().contains("graph spec"), "{}", last_error());
+
+            assert_eq!(rustradio_graph_run(g), 0, "{}", last_error());
+            assert_eq!(rustradio_graph_run(g), -1);
+            assert_eq!(rustradio_graph_cancel(g), 0);
+            assert_eq!(rustradio_graph_cancel(std::ptr::null_mut()), -1);
+            rustradio_graph_free(g);
+        }
+        assert_eq!(*got.lock().unwrap(), vec![vec![0xaa, 0x07]]);
+    }
+
+    fn add(g: *mut Graph, name: &str, inputs: &[usize], params: &str) -> usize {
+        let name = CString::new(name).unwrap();
+        let params = CString::new(params).unwrap();
+        let mut out = 0;
+        let n = unsafe {
+            rustradio_graph_add(
+                g,
+                name.as_ptr(),
+                inputs.as_ptr(),
+                inputs.len(),
+                params.as_ptr(),
+                &mut out,
+                1,
+            )
+        };
+        assert!(n >= 0, "{}", last_error());
+        out
+    }
+
+    fn set_control(g: *mut Graph, block: &str, name: &str, value: &str) -> c_int {
+        let block = CString::new(block).unwrap();
+        let name = CString::new(name).unwrap();
+        let value = CString::new(value).unwrap();
+        unsafe { rustradio_graph_set_control(g, block.as_ptr(), name.as_ptr(), value.as_ptr()) }
+    }
+
+    #[test]
+    fn controls() {
+        let g = rustradio_graph_new();
+        let s = add(
+            g,
+            "signal_source",
+            &[],
+            r#"{"samp_rate": 48000.0, "freq": 1000.0}"#,
+        );
+        let s = add(
+            g,
+            "rotator",
+            &[s],
+            r#"{"samp_rate": 48000.0, "freq": 100.0}"#,
+        );
+        add(g, "null_sink_c", &[s], "{}");
+        assert_eq!(
+            set_control(g, "Rotator", "freq", "200"),
+            0,
+            "{}",
+            last_error()
+        );
+        assert_eq!(unsafe { rustradio_graph_start(g) }, 0, "{}", last_error());
+
+        // Controls can be set, and the graph cancelled, while another
+        // thread waits.
+        let addr = g as usize;
+        let waiter =
+            std::thread::spawn(move || unsafe { rustradio_graph_wait(addr as *mut Graph) });
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(
+            set_control(g, "Rotator", "freq", "-500.5"),
+            0,
+            "{}",
+            last_error()
+        );
+        assert_eq!(set_control(g, "Rotator", "gain", "1"), -1);
+        assert!(last_error().contains("no control"), "{}", last_error());
+        assert_eq!(set_control(g, "Rotator", "freq", "true"), -1);
+        assert_eq!(set_control(g, "Rotator", "freq", "{"), -1);
+        assert!(last_error().contains("value"), "{}", last_error());
+        assert_eq!(unsafe { rustradio_graph_cancel(g) }, 0);
+        assert_eq!(waiter.join().unwrap(), 0);
+        unsafe { rustradio_graph_free(g) };
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(rustradio_api_version(), RUSTRADIO_API_VERSION);
+        let s = rustradio_blocks();
+        let json = unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string();
+        unsafe { rustradio_string_free(s) };
+        let v: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let hdlc = v
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|b| b["name"] == "hdlc_deframer")
+            .unwrap();
+        assert_eq!(hdlc["inputs"], serde_json::json!(["u8"]));
+        assert_eq!(hdlc["outputs"], serde_json::json!(["pdu"]));
+    }
+}
@@ -0,0 +1,339 @@
+/*! CCSDS transfer frame and space packet handling.
+
+This is the framing shared by many satellites, including Meteor-M LRPT:
+
+* CADU: 32 bit attached sync marker (ASM), followed by a randomized,
+  Reed-Solomon protected transfer frame.
+* VCDU: The transfer frame. A 6 byte header (spacecraft ID, virtual
+  channel ID, and counter), and an M_PDU data zone.
+* M_PDU: 2 byte header pointing to the first packet start, followed by
+  a zone of space packets, which may span VCDUs.
+* Space packet: 6 byte header (APID, sequence count, length), and
+  payload.
+
+[CcsdsPacketizer] takes VCDUs and outputs reassembled space packets.
+
+Reference: [CCSDS 732.0-B][ccsds].
+
+[ccsds]: https://public.ccsds.org/Pubs/732x0b4.pdf
+*/
+use anyhow::Result;
+use log::{debug, info, trace};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp};
+use crate::Error;
+
+/// Attached sync marker.
+pub const ASM: u32 = 0x1acffc1d;
+
+/// Length of VCDU primary header.
+pub const VCDU_HEADER_LEN: usize = 6;
+
+/// Length of M_PDU header.
+pub const MPDU_HEADER_LEN: usize = 2;
+
+/// Length of space packet primary header.
+pub const PACKET_HEADER_LEN: usize = 6;
+
+/// APID used for idle (fill) packets.
+pub const IDLE_APID: u16 = 0x7ff;
+
+// First header pointer value meaning no packet starts in this VCDU.
+const NO_PACKET_START: usize = 0x7ff;
+
+/// Generate the CCSDS pseudo-noise sequence used for randomization.
+///
+/// The polynomial is x^8+x^7+x^5+x^3+1, and the register starts as all
+/// ones.
+pub fn pn_sequence(len: usize) -> Vec<u8> {
+    let mut sr: u8 = 0xff;
+    (0..len)
+        .map(|_| {
+            let mut b = 0;
+            for _ in 0..8 {
+                b = (b << 1) | (sr >> 7);
+                sr = (sr << 1) | ((sr & 0x95).count_ones() & 1) as u8;
+            }
+            b
+        })
+        .collect()
+}
+
+/// XOR data with the PN sequence.
+///
+/// Randomization and derandomization are the same operation.
+pub fn derandomize(data: &mut [u8], pn: &[u8]) {
+    data.iter_mut().zip(pn.iter()).for_each(|(d, p)| *d ^= p);
+}
+
+/// VCDU primary header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VcduHeader {
+    /// Spacecraft ID.
+    pub scid: u8,
+
+    /// Virtual channel ID.
+    pub vcid: u8,
+
+    /// Frame counter, 24 bits.
+    pub counter: u32,
+}
+
+impl VcduHeader {
+    /// Parse header from start of VCDU.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < VCDU_HEADER_LEN {
+            return None;
+        }
+        Some(Self {
+            scid: (data[0] << 2) | (data[1] >> 6),
+            vcid: data[1] & 0x3f,
+            counter: u32::from_be_bytes([0, data[2], data[3], data[4]]),
+        })
+    }
+}
+
+/// Space packet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    /// Application process ID.
+    pub apid: u16,
+
+    /// Sequence flags. 3 means unsegmented.
+    pub seq_flags: u8,
+
+    /// Sequence count, 14 bits.
+    pub seq: u16,
+
+    /// Secondary header flag.
+    pub sec_hdr: bool,
+
+    /// Payload, including secondary header if present.
+    pub data: Vec<u8>,
+}
+
+impl Packet {
+    /// Parse a complete packet, including primary header.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let len = Self::total_len(data)?;
+        if data.len() < len {
+            return None;
+        }
+        Some(Self {
+            apid: u16::from_be_bytes([data[0] & 7, data[1]]),
+            sec_hdr: data[0] & 8 != 0,
+            seq_flags: data[2] >> 6,
+            seq: u16::from_be_bytes([data[2] & 0x3f, data[3]]),
+            data: data[PACKET_HEADER_LEN..len].to_vec(),
+        })
+    }
+
+    /// Total length of packet, including header, if header is
+    /// available.
+    pub fn total_len(data: &[u8]) -> Option<usize> {
+        if data.len() < PACKET_HEADER_LEN {
+            return None;
+        }
+        Some(u16::from_be_bytes([data[4], data[5]]) as usize + 1 + PACKET_HEADER_LEN)
+    }
+}
+
+impl crate::Len for Packet {
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// Reassemble space packets from a sequence of VCDUs from the same
+/// virtual channel.
+#[derive(Default)]
+pub struct PacketExtractor {
+    pending: Vec<u8>,
+    synced: bool,
+    last_counter: Option<u32>,
+}
+
+impl PacketExtractor {
+    /// Create new packet extractor.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one VCDU, returning any packets completed by it.
+    pub fn push(&mut self, vcdu: &[u8]) -> Vec<Packet> {
+        let Some(hdr) = VcduHeader::parse(vcdu) else {
+            return vec![];
+        };
+        if vcdu.len() < VCDU_HEADER_LEN + MPDU_HEADER_LEN {
+            return vec![];
+        }
+        if let Some(last) = self.last_counter {
+            if hdr.counter != (last + 1) & 0xffffff {
+                debug!(
+                    "CCSDS: lost VCDUs between {last} and {}, dropping partial packet",
+                    hdr.counter
+                );
+                self.synced = false;
+            }
+        }
+        self.last_counter = Some(hdr.counter);
+
+        let mpdu = &vcdu[VCDU_HEADER_LEN..];
+        let fhp = (u16::from_be_bytes([mpdu[0], mpdu[1]]) & 0x7ff) as usize;
+        let zone = &mpdu[MPDU_HEADER_LEN..];
+        if !self.synced {
+            self.pending.clear();
+            if fhp == NO_PACKET_START || fhp >= zone.len() {
+                return vec![];
+            }
+            self.pending.extend(&zone[fhp..]);
+            self.synced = true;
+        } else {
+            self.pending.extend(zone);
+        }
+
+        let mut ret = Vec::new();
+        while let Some(len) = Packet::total_len(&self.pending) {
+            if self.pending.len() < len {
+                break;
+            }
+            if let Some(p) = Packet::parse(&self.pending) {
+                trace!("CCSDS: packet APID {} len {}", p.apid, p.data.len());
+                ret.push(p);
+            }
+            self.pending.drain(..len);
+        }
+        ret
+    }
+}
+
+/// Block turning VCDUs into space packets.
+///
+/// Only VCDUs for the selected virtual channel are used. Idle packets
+/// are dropped.
+pub struct CcsdsPacketizer {
+    src: NoCopyStreamp<Vec<u8>>,
+    dst: NoCopyStreamp<Packet>,
+    vcid: u8,
+    extractor: PacketExtractor,
+    packets: usize,
+}
+
+impl CcsdsPacketizer {
+    /// Create new packetizer for virtual channel `vcid`.
+    pub fn new(src: NoCopyStreamp<Vec<u8>>, vcid: u8) -> Self {
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            vcid,
+            extractor: PacketExtractor::new(),
+            packets: 0,
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<Packet> {
+        self.dst.clone()
+    }
+}
+
+impl Drop for CcsdsPacketizer {
+    fn drop(&mut self) {
+        info!("CcsdsPacketizer: {} packets", self.packets);
+    }
+}
+
+impl Block for CcsdsPacketizer {
+    fn block_name(&self) -> &str {
+        "CcsdsPacketizer"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let vcdu = match self.src.pop() {
+            None => return Ok(BlockRet::Noop),
+            Some((x, _tags)) => x,
+        };
+        match VcduHeader::parse(&vcdu) {
+            Some(h) if h.vcid == self.vcid => {}
+            _ => return Ok(BlockRet::Ok),
+        }
+        for p in self.extractor.push(&vcdu) {
+            if p.apid == IDLE_APID {
+                continue;
+            }
+            self.packets += 1;
+            self.dst.push(p, &[]);
+        }
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pn() {
+        assert_eq!(
+            pn_sequence(8),
+            vec![0xff, 0x48, 0x0e, 0xc0, 0x9a, 0x0d, 0x70, 0xbc]
+        );
+    }
+
+    fn packet(apid: u16, seq: u16, len: usize) -> Vec<u8> {
+        let mut p = vec![
+            (apid >> 8) as u8,
+            apid as u8,
+            0xc0 | (seq >> 8) as u8,
+            seq as u8,
+            ((len - 1) >> 8) as u8,
+            (len - 1) as u8,
+        ];
+        p.extend((0..len).map(|n| n as u8));
+        p
+    }
+
+    fn vcdus(stream: &[u8], first_start: usize, zone: usize) -> Vec<Vec<u8>> {
+        stream
+            .chunks(zone)
+            .enumerate()
+            .map(|(n, chunk)| {
+                let fhp: u16 = if n == 0 { first_start as u16 } else { 0x7ff };
+                let mut v = vec![0x40, 0x05, 0, 0, n as u8, 0];
+                v.extend(fhp.to_be_bytes());
+                v.extend(chunk);
+                v.resize(VCDU_HEADER_LEN + MPDU_HEADER_LEN + zone, 0);
+                v
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reassemble() {
+        // Junk, then three packets spanning several VCDUs.
+        let mut stream = vec![0xaa; 5];
+        stream.extend(packet(64, 1, 100));
+        stream.extend(packet(65, 2, 3));
+        stream.extend(packet(64, 3, 50));
+        let mut ex = PacketExtractor::new();
+        let got: Vec<_> = vcdus(&stream, 5, 44)
+            .iter()
+            .flat_map(|v| ex.push(v))
+            .collect();
+        assert_eq!(got.len(), 3);
+        assert_eq!(got[0].apid, 64);
+        assert_eq!(got[0].seq, 1);
+        assert_eq!(got[0].data.len(), 100);
+        assert_eq!(got[1].apid, 65);
+        assert_eq!(got[1].data, vec![0, 1, 2]);
+        assert_eq!(got[2].seq, 3);
+    }
+
+    #[test]
+    fn vcdu_header() {
+        let h = VcduHeader::parse(&[0x40, 0x45, 0x01, 0x02, 0x03, 0x00]).unwrap();
+        assert_eq!(h.scid, 0x01);
+        assert_eq!(h.vcid, 5);
+        assert_eq!(h.counter, 0x010203);
+    }
+}
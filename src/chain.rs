@@ -0,0 +1,399 @@
+/*! Build linear chains of blocks with method calls.
+
+A [Chain] is a stream, and the graph it's in. Each method adds a block
+reading the stream, and returns a chain of the block's output, so
+the code reads like the signal path:
+
+```
+use rustradio::blocks::VectorSource;
+use rustradio::chain::ChainGraph;
+use rustradio::graph::Graph;
+use rustradio::Complex;
+
+let mut g = Graph::new();
+let taps = rustradio::fir::low_pass_complex(50_000.0, 10_000.0, 1_000.0);
+g.source(VectorSource::new(vec![Complex::new(1.0, 0.0); 1000]), VectorSource::out)
+    .fir_filter(&taps)
+    .quadrature_demod(1.0)
+    .add_const(-0.1)
+    .null_sink();
+g.run()?;
+# Ok::<(), anyhow::Error>(())
+```
+
+For blocks without a method, use [Chain::then]. For branches, use
+[Chain::tee], and start a new chain from the second stream with
+[ChainGraph::chain].
+
+Consecutive one-to-one blocks that can run in place, like `add_const`
+and `multiply_const`, are run as one
+[InPlaceChain](crate::in_place::InPlaceChain), since nothing else can
+read the streams between them. See [Chain::then_in_place].
+*/
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::block::{Block, InPlace};
+use crate::blocks::*;
+use crate::file_sink::Mode;
+use crate::graph::Graph;
+use crate::in_place::{InPlaceChain, InPlaceStages};
+use crate::mtgraph::MTGraph;
+use crate::stream::Streamp;
+use crate::vector_sink::VectorSinkHandle;
+use crate::{Complex, Float, Sample};
+
+/// Graphs that chains can add blocks to.
+pub trait ChainGraph: Sized {
+    /// Add a block to the graph.
+    fn add_block(&mut self, b: Box<dyn Block + Send>);
+
+    /// Start a chain reading from `s`.
+    fn chain<T: Copy>(&mut self, s: Streamp<T>) -> Chain<'_, Self, T> {
+        Chain {
+            g: self,
+            s,
+            fused: None,
+        }
+    }
+
+    /// Add a source block, and start a chain from its output.
+    fn source<T, B>(&mut self, b: B, out: impl FnOnce(&B) -> Streamp<T>) -> Chain<'_, Self, T>
+    where
+        T: Copy,
+        B: Block + Send + 'static,
+    {
+        let s = out(&b);
+        self.add_block(Box::new(b));
+        self.chain(s)
+    }
+}
+
+impl ChainGraph for Graph {
+    fn add_block(&mut self, b: Box<dyn Block + Send>) {
+        self.add(b);
+    }
+}
+
+impl ChainGraph for MTGraph {
+    fn add_block(&mut self, b: Box<dyn Block + Send>) {
+        self.add(b);
+    }
+}
+
+/// A stream in a graph, to add blocks to. See the [module
+/// docs](crate::chain).
+pub struct Chain<'g, G: ChainGraph, T> {
+    g: &'g mut G,
+    s: Streamp<T>,
+
+    // If `s` is the output of an InPlaceChain, its list of blocks.
+    fused: Option<InPlaceStages<T>>,
+}
+
+impl<'g, G: ChainGraph, T: Copy + Send + Sync + 'static> Chain<'g, G, T> {
+    /// The stream at the end of the chain, to connect other blocks
+    /// to.
+    pub fn out(self) -> Streamp<T> {
+        self.s
+    }
+
+    /// Add any block. `f` creates it from the stream, and `out` gets
+    /// its output stream.
+    ///
+    /// ```
+    /// # use rustradio::blocks::{Delay, VectorSource};
+    /// # use rustradio::chain::ChainGraph;
+    /// # let mut g = rustradio::graph::Graph::new();
+    /// # let src = VectorSource::<u8>::new(vec![]);
+    /// # let s = src.out();
+    /// g.chain(s).then(|s| Delay::new(s, 10), Delay::out).null_sink();
+    /// ```
+    pub fn then<U: Copy, B>(
+        self,
+        f: impl FnOnce(Streamp<T>) -> B,
+        out: impl FnOnce(&B) -> Streamp<U>,
+    ) -> Chain<'g, G, U>
+    where
+        B: Block + Send + 'static,
+    {
+        let b = f(self.s);
+        let s = out(&b);
+        self.g.add_block(Box::new(b));
+        Chain {
+            g: self.g,
+            s,
+            fused: None,
+        }
+    }
+
+    /// Add a one-to-one block that can run in place. Consecutive such
+    /// blocks are run in one InPlaceChain, so the samples are only
+    /// copied once.
+    ///
+    /// `f` creates the block from the stream, but the block's streams
+    /// are not used.
+    pub fn then_in_place<B>(mut self, f: impl FnOnce(Streamp<T>) -> B) -> Self
+    where
+        B: InPlace<T> + Send + 'static,
+    {
+        let stages = match &self.fused {
+            Some(stages) => stages.clone(),
+            None => {
+                let b = InPlaceChain::new(self.s.clone());
+                let stages = b.stages();
+                self.s = b.out();
+                self.g.add_block(Box::new(b));
+                self.fused = Some(stages.clone());
+                stages
+            }
+        };
+        let b = f(self.s.clone());
+        stages.lock().unwrap().push(Box::new(b));
+        self
+    }
+
+    /// Call `f` on every sample.
+    pub fn map<U, F>(self, f: F) -> Chain<'g, G, U>
+    where
+        U: Copy + Send + Sync + 'static,
+        F: Fn(T) -> U + Send + 'static,
+    {
+        self.then(|s| Map::new(s, f), Map::out)
+    }
+
+    /// Drop samples for which `f` returns false.
+    pub fn filter<F>(self, f: F) -> Self
+    where
+        F: Fn(&T) -> bool + Send + 'static,
+    {
+        self.then(|s| Filter::new(s, f), Filter::out)
+    }
+
+    /// Delay by `delay` samples.
+    pub fn delay(self, delay: usize) -> Self
+    where
+        T: Default,
+    {
+        self.then(|s| Delay::new(s, delay), Delay::out)
+    }
+
+    /// Drop the first `skip` samples.
+    pub fn skip(self, skip: usize) -> Self
+    where
+        T: std::fmt::Debug,
+    {
+        self.then(|s| Skip::new(s, skip), Skip::out)
+    }
+
+    /// Resample by `interp`/`deci`.
+    pub fn rational_resampler(self, interp: usize, deci: usize) -> Result<Self> {
+        let b = RationalResampler::new(self.s.clone(), interp, deci)?;
+        Ok(self.then(|_| b, RationalResampler::out))
+    }
+
+    /// Copy the stream. Returns this chain, and the copy.
+    pub fn tee(self) -> (Self, Streamp<T>) {
+        let b = Tee::new(self.s.clone());
+        let (a, c) = b.out();
+        self.g.add_block(Box::new(b));
+        (
+            Chain {
+                g: self.g,
+                s: a,
+                fused: None,
+            },
+            c,
+        )
+    }
+
+    /// End the chain, discarding the samples.
+    pub fn null_sink(self)
+    where
+        T: Default,
+    {
+        self.g.add_block(Box::new(NullSink::new(self.s)));
+    }
+
+    /// End the chain, printing the samples.
+    pub fn debug_sink(self)
+    where
+        T: std::fmt::Debug + Default,
+    {
+        self.g.add_block(Box::new(DebugSink::new(self.s)));
+    }
+
+    /// End the chain, writing the samples to a file.
+    pub fn file_sink(self, filename: impl Into<PathBuf>, mode: Mode) -> Result<()>
+    where
+        T: Sample<Type = T> + std::fmt::Debug + Default,
+    {
+        let b = FileSink::new(self.s, filename.into(), mode)?;
+        self.g.add_block(Box::new(b));
+        Ok(())
+    }
+
+    /// End the chain, collecting the samples in memory.
+    pub fn vector_sink(self) -> VectorSinkHandle<T> {
+        let b = VectorSink::new(self.s);
+        let h = b.handle();
+        self.g.add_block(Box::new(b));
+        h
+    }
+
+    /// Add a constant.
+    pub fn add_const(self, val: T) -> Self
+    where
+        T: std::ops::Add<Output = T>,
+    {
+        self.then_in_place(|s| AddConst::new(s, val))
+    }
+
+    /// Multiply by a constant.
+    pub fn multiply_const(self, val: T) -> Self
+    where
+        T: std::ops::Mul<Output = T>,
+    {
+        self.then_in_place(|s| MultiplyConst::new(s, val))
+    }
+
+    /// FIR filter.
+    pub fn fir_filter(self, taps: &[T]) -> Self
+    where
+        T: crate::kernels::Dot,
+    {
+        self.then(|s| FIRFilter::new(s, taps), FIRFilter::out)
+    }
+}
+
+impl<'g, G: ChainGraph> Chain<'g, G, Complex> {
+    /// FIR filter, using FFT.
+    #[cfg(feature = "fft")]
+    pub fn fft_filter(self, taps: &[Complex]) -> Self {
+        self.then(|s| FftFilter::new(s, taps), FftFilter::out)
+    }
+
+    /// Remove DC offset.
+    pub fn dc_blocker(self, alpha: Float) -> Result<Self> {
+        let b = DcBlocker::new(self.s.clone(), alpha)?;
+        Ok(self.then(|_| b, DcBlocker::out))
+    }
+
+    /// Correct I/Q imbalance.
+    pub fn iq_balance(self, alpha: Float) -> Result<Self> {
+        let b = IqBalance::new(self.s.clone(), alpha)?;
+        Ok(self.then(|_| b, IqBalance::out))
+    }
+
+    /// Frequency shift by `freq` Hz.
+    pub fn rotator(self, samp_rate: Float, freq: Float) -> Self {
+        self.then(|s| Rotator::new(s, samp_rate, freq), Rotator::out)
+    }
+
+    /// FM demodulate.
+    pub fn quadrature_demod(self, gain: Float) -> Chain<'g, G, Float> {
+        self.then(|s| QuadratureDemod::new(s, gain), QuadratureDemod::out)
+    }
+
+    /// FM demodulate, faster but less exact.
+    pub fn fast_fm(self) -> Chain<'g, G, Float> {
+        self.then(FastFM::new, FastFM::out)
+    }
+
+    /// Squared magnitude.
+    pub fn complex_to_mag2(self) -> Chain<'g, G, Float> {
+        self.then(ComplexToMag2::new, ComplexToMag2::out)
+    }
+
+    /// Magnitude.
+    pub fn complex_to_mag(self) -> Chain<'g, G, Float> {
+        self.then(ComplexToMag::new, ComplexToMag::out)
+    }
+
+    /// Phase angle.
+    pub fn complex_to_arg(self) -> Chain<'g, G, Float> {
+        self.then(ComplexToArg::new, ComplexToArg::out)
+    }
+
+    /// Real part.
+    pub fn complex_to_real(self) -> Chain<'g, G, Float> {
+        self.then(ComplexToReal::new, ComplexToReal::out)
+    }
+
+    /// Imaginary part.
+    pub fn complex_to_imag(self) -> Chain<'g, G, Float> {
+        self.then(ComplexToImag::new, ComplexToImag::out)
+    }
+}
+
+impl<'g, G: ChainGraph> Chain<'g, G, Float> {
+    /// FIR filter, using FFT.
+    #[cfg(feature = "fft")]
+    pub fn fft_filter(self, taps: &[Float]) -> Self {
+        self.then(|s| FftFilterFloat::new(s, taps), FftFilterFloat::out)
+    }
+
+    /// Positive to 1, else 0.
+    pub fn binary_slicer(self) -> Chain<'g, G, u8> {
+        self.then(BinarySlicer::new, BinarySlicer::out)
+    }
+
+    /// Hilbert transform, real to complex.
+    pub fn hilbert(self, ntaps: usize) -> Chain<'g, G, Complex> {
+        self.then(|s| Hilbert::new(s, ntaps), Hilbert::out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain() -> Result<()> {
+        let mut g = Graph::new();
+        let (a, b) = g
+            .source(VectorSource::new(vec![1.0, -2.0, 3.0]), VectorSource::out)
+            .multiply_const(2.0)
+            .add_const(1.0)
+            .tee();
+        let a = a.map(|x| x as i32).filter(|x| *x > 0).vector_sink();
+        let b = g.chain(b).binary_slicer().vector_sink();
+        g.run()?;
+        assert_eq!(a.data(), vec![3, 7]);
+        assert_eq!(b.data(), vec![1, 0, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn in_place() -> Result<()> {
+        let mut g = Graph::new();
+        let out = g
+            .source(VectorSource::new(vec![1, 2, 3]), VectorSource::out)
+            .add_const(1)
+            .multiply_const(10)
+            .add_const(2)
+            .delay(1)
+            .multiply_const(2)
+            .vector_sink();
+        g.run()?;
+        assert_eq!(out.data(), vec![0, 44, 64, 84]);
+        let stats = g.generate_stats(std::time::Duration::from_secs(1));
+        assert_eq!(stats.matches("InPlaceChain").count(), 2, "{stats}");
+        assert!(!stats.contains("AddConst"), "{stats}");
+        Ok(())
+    }
+
+    #[test]
+    fn mtgraph() -> Result<()> {
+        let mut g = MTGraph::new();
+        let out = g
+            .source(VectorSource::new(vec![1u8, 2, 3]), VectorSource::out)
+            .delay(1)
+            .skip(1)
+            .vector_sink();
+        g.run()?;
+        assert_eq!(out.data(), vec![1, 2, 3]);
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+/*! Channel model, for simulating a radio channel.
+
+Modems can be developed and regression tested entirely in software by
+putting a [ChannelModel] between the modulator and the demodulator:
+
+```text
+   TX chain -> ChannelModel -> RX chain
+```
+
+The impairments are applied in this order:
+1. Timing offset: the receiver's sample clock runs at a different
+   rate.
+2. Multipath: the signal arrives via several paths, with different
+   delays and gains.
+3. Frequency offset: the receiver is not tuned exactly right.
+4. Noise: additive white gaussian noise.
+
+The noise is pseudo random, from a fixed seed by default, so that
+tests are repeatable. For noise on its own, e.g. for real valued
+signals, use [NoiseSource][crate::noise_source::NoiseSource].
+
+```
+use rustradio::blocks::{ChannelModel, ConstantSource, NullSink};
+use rustradio::Complex;
+let src = ConstantSource::new(Complex::new(1.0, 0.0));
+let chan = ChannelModel::builder(src.out(), 48_000.0)
+    .ebn0(10.0, 8)
+    .freq_offset(100.0)
+    .timing_offset(1.0001)
+    .multipath(&[Complex::new(1.0, 0.0), Complex::new(0.0, 0.2)])
+    .build();
+let sink = NullSink::new(chan.out());
+```
+*/
+use crate::block::{Block, BlockRet};
+use crate::noise_source::Gaussian;
+use crate::stream::{new_streamp, Streamp, Tag};
+use crate::{Complex, Error, Float};
+
+/// Builder for [ChannelModel].
+pub struct ChannelModelBuilder {
+    src: Streamp<Complex>,
+    samp_rate: Float,
+    noise_voltage: Float,
+    freq_offset: Float,
+    timing_offset: f64,
+    taps: Vec<Complex>,
+    seed: u64,
+}
+
+impl ChannelModelBuilder {
+    /// Create new builder, for a channel with no impairments.
+    pub fn new(src: Streamp<Complex>, samp_rate: Float) -> Self {
+        Self {
+            src,
+            samp_rate,
+            noise_voltage: 0.0,
+            freq_offset: 0.0,
+            timing_offset: 1.0,
+            taps: vec![Complex::new(1.0, 0.0)],
+            seed: 1,
+        }
+    }
+
+    /// Set noise RMS amplitude. Default 0.
+    ///
+    /// The noise power per sample is the square of this, split
+    /// equally between I and Q.
+    pub fn noise_voltage(mut self, v: Float) -> Self {
+        self.noise_voltage = v;
+        self
+    }
+
+    /// Set noise level from Eb/N0 in dB, assuming signal power 1 and
+    /// `samples_per_bit` samples per bit.
+    pub fn ebn0(self, db: Float, samples_per_bit: usize) -> Self {
+        let ebn0 = (10.0 as Float).powf(db / 10.0);
+        self.noise_voltage((samples_per_bit as Float / ebn0).sqrt())
+    }
+
+    /// Set frequency offset in Hz. Default 0.
+    pub fn freq_offset(mut self, hz: Float) -> Self {
+        self.freq_offset = hz;
+        self
+    }
+
+    /// Set ratio of transmitter to receiver sample clock. Default 1.0.
+    ///
+    /// E.g. 1.0001 means the receiver clock is 100ppm slow, and each
+    /// output sample advances 1.0001 input samples. Samples in between
+    /// are linearly interpolated.
+    pub fn timing_offset(mut self, ratio: f64) -> Self {
+        self.timing_offset = ratio;
+        self
+    }
+
+    /// Set multipath taps. Default is a single path with gain 1.
+    ///
+    /// Tap `n` is the gain of the path delayed `n` samples.
+    pub fn multipath(mut self, taps: &[Complex]) -> Self {
+        assert!(!taps.is_empty(), "multipath needs at least one tap");
+        self.taps = taps.to_vec();
+        self
+    }
+
+    /// Set seed for the noise. Default 1.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Build ChannelModel block.
+    pub fn build(self) -> ChannelModel {
+        let w = 2.0 * std::f64::consts::PI as Float * self.freq_offset / self.samp_rate;
+        ChannelModel {
+            src: self.src,
+            dst: new_streamp(),
+            noise: Gaussian::new(self.seed),
+            sigma: self.noise_voltage / (2.0 as Float).sqrt(),
+            phase: Complex::new(1.0, 0.0),
+            step: Complex::from_polar(1.0, w),
+            ratio: self.timing_offset,
+            mu: 0.0,
+            last: Complex::default(),
+            history: vec![Complex::default(); self.taps.len()],
+            hpos: 0,
+            taps: self.taps,
+        }
+    }
+}
+
+/// Channel model block. See the [module docs](crate::channel_model).
+pub struct ChannelModel {
+    src: Streamp<Complex>,
+    dst: Streamp<Complex>,
+    noise: Gaussian,
+    sigma: Float,
+
+    // Frequency offset oscillator.
+    phase: Complex,
+    step: Complex,
+
+    // Timing offset. Next output is at `mu` between `last` and the
+    // next input sample.
+    ratio: f64,
+    mu: f64,
+    last: Complex,
+
+    // Multipath. `history` is a ring buffer of the latest samples,
+    // with the next one to be written at `hpos`.
+    taps: Vec<Complex>,
+    history: Vec<Complex>,
+    hpos: usize,
+}
+
+impl ChannelModel {
+    /// Create a builder.
+    pub fn builder(src: Streamp<Complex>, samp_rate: Float) -> ChannelModelBuilder {
+        ChannelModelBuilder::new(src, samp_rate)
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Complex> {
+        self.dst.clone()
+    }
+
+    // Apply everything after the timing offset.
+    fn process(&mut self, s: Complex) -> Complex {
+        let ntaps = self.taps.len();
+        self.history[self.hpos] = s;
+        let mut acc = Complex::default();
+        for (n, t) in self.taps.iter().enumerate() {
+            acc += t * self.history[(self.hpos + ntaps - n) % ntaps];
+        }
+        self.hpos = (self.hpos + 1) % ntaps;
+
+        let mut s = acc * self.phase;
+        self.phase *= self.step;
+
+        if self.sigma > 0.0 {
+            let (i, q) = self.noise.pair();
+            s += Complex::new(i, q) * self.sigma;
+        }
+        s
+    }
+}
+
+impl Block for ChannelModel {
+    fn block_name(&self) -> &str {
+        "ChannelModel"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let obind = self.dst.clone();
+        let mut o = obind.write_buf()?;
+        let out = o.slice();
+        let mut produced = 0;
+        let mut consumed = 0;
+        let mut otags = Vec::new();
+        'outer: for &x in input.iter() {
+            if self.ratio == 1.0 {
+                if produced == out.len() {
+                    break;
+                }
+                out[produced] = self.process(x);
+                produced += 1;
+            } else {
+                // Output samples between `last` and `x`.
+                while self.mu < 1.0 {
+                    if produced == out.len() {
+                        break 'outer;
+                    }
+                    let s = self.last + (x - self.last) * self.mu as Float;
+                    out[produced] = self.process(s);
+                    produced += 1;
+                    self.mu += self.ratio;
+                }
+                self.mu -= 1.0;
+                self.last = x;
+            }
+            otags.extend(
+                tags.iter()
+                    .filter(|t| t.pos() == consumed)
+                    .map(|t| Tag::new(produced, t.key().into(), t.val().clone())),
+            );
+            consumed += 1;
+        }
+        // Keep rounding errors from growing the amplitude.
+        self.phase /= self.phase.norm();
+        otags.retain(|t| t.pos() < produced);
+        o.produce(produced, &otags);
+        input.consume(consumed);
+        if consumed == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+    use crate::tests::assert_almost_equal_complex;
+
+    fn run(
+        input: &[Complex],
+        b: impl FnOnce(ChannelModelBuilder) -> ChannelModelBuilder,
+    ) -> Vec<Complex> {
+        let src = streamp_from_slice(input);
+        let mut chan = b(ChannelModel::builder(src, 48_000.0)).build();
+        chan.work().unwrap();
+        let o = chan.out();
+        let (res, _) = o.read_buf().unwrap();
+        res.slice().to_vec()
+    }
+
+    #[test]
+    fn passthrough() {
+        let input: Vec<Complex> = (0..100).map(|n| Complex::new(n as Float, -1.0)).collect();
+        assert_eq!(run(&input, |b| b), input);
+    }
+
+    #[test]
+    fn noise() {
+        let zeros = vec![Complex::default(); 50_000];
+        let res = run(&zeros, |b| b.noise_voltage(0.5));
+        let n = res.len() as Float;
+        let mean = res.iter().sum::<Complex>() / n;
+        let power_i = res.iter().map(|s| s.re * s.re).sum::<Float>() / n;
+        let power_q = res.iter().map(|s| s.im * s.im).sum::<Float>() / n;
+        assert!(mean.norm() < 0.01, "{mean}");
+        assert!((power_i - 0.125).abs() < 0.005, "{power_i}");
+        assert!((power_q - 0.125).abs() < 0.005, "{power_q}");
+
+        // Repeatable, unless the seed changes.
+        assert_eq!(run(&zeros[..100], |b| b.noise_voltage(0.5)), res[..100]);
+        assert_ne!(
+            run(&zeros[..100], |b| b.noise_voltage(0.5).seed(2)),
+            res[..100]
+        );
+
+        // 8 samples per bit at 6dB Eb/N0 is noise power 2.
+        let res = run(&zeros, |b| b.ebn0(6.0, 8));
+        let power = res.iter().map(|s| s.norm_sqr()).sum::<Float>() / n;
+        assert!((power - 2.0).abs() < 0.05, "{power}");
+    }
+
+    #[test]
+    fn freq_offset() {
+        let res = run(&[Complex::new(1.0, 0.0); 1000], |b| b.freq_offset(-1200.0));
+        let want = -2.0 * std::f64::consts::PI as Float * 1200.0 / 48_000.0;
+        for w in res.windows(2) {
+            let got = (w[1] * w[0].conj()).arg();
+            assert!((got - want).abs() < 1e-4, "want {want} got {got}");
+        }
+    }
+
+    #[test]
+    fn multipath() {
+        let mut input = vec![Complex::default(); 10];
+        input[2] = Complex::new(1.0, 0.0);
+        let taps = [
+            Complex::new(1.0, 0.0),
+            Complex::new(0.0, 0.0),
+            Complex::new(0.0, 0.5),
+        ];
+        let res = run(&input, |b| b.multipath(&taps));
+        let mut want = vec![Complex::default(); 10];
+        want[2..5].copy_from_slice(&taps);
+        assert_almost_equal_complex(&res, &want);
+    }
+
+    #[test]
+    fn timing_offset() {
+        let ramp: Vec<Complex> = (0..100).map(|n| Complex::new(n as Float, 0.0)).collect();
+
+        // Twice the samples, interpolated. The first two are before
+        // the first input sample.
+        let res = run(&ramp, |b| b.timing_offset(0.5));
+        assert_eq!(res.len(), 200);
+        let want: Vec<Complex> = (0..200)
+            .map(|n| Complex::new((n.max(2) - 2) as Float / 2.0, 0.0))
+            .collect();
+        assert_almost_equal_complex(&res, &want);
+
+        // Slightly fast clock drops a sample now and then.
+        let res = run(&ramp, |b| b.timing_offset(1.05));
+        assert_eq!(res.len(), 96);
+        assert_almost_equal_complex(&res[90..91], &[Complex::new(93.5, 0.0)]);
+    }
+}
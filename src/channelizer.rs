@@ -0,0 +1,269 @@
+/*! Split a wideband stream into narrowband channels.
+
+E.g. to listen to 16 APRS channels at once from one SDR. Each channel
+is shifted to baseband, low pass filtered, and decimated, independent
+of the others.
+
+This is a bank of frequency translating FIR filters, not a polyphase
+channelizer, so CPU use scales with number of channels times number of
+taps. With the `rayon` feature the channels can be processed in
+parallel, using all cores. See [ChannelizerBuilder::parallel].
+
+```
+use rustradio::blocks::{Channelizer, ConstantSource, NullSink};
+use rustradio::fir::low_pass_complex;
+use rustradio::graph::Graph;
+use rustradio::Complex;
+
+let samp_rate = 1_024_000.0;
+let src = ConstantSource::new(Complex::new(0.0, 0.0));
+let taps = low_pass_complex(samp_rate, 10_000.0, 5_000.0);
+let chan = Channelizer::builder(src.out(), samp_rate, &taps, 32)
+    .channel(-25_000.0)
+    .channel(0.0)
+    .channel(25_000.0)
+    .build();
+let mut g = Graph::new();
+for out in chan.outs() {
+    g.add(Box::new(NullSink::new(out)));
+}
+g.add(Box::new(src));
+g.add(Box::new(chan));
+```
+*/
+use log::trace;
+
+use crate::block::{Block, BlockRet};
+use crate::fir::FIR;
+use crate::stream::{new_streamp, Streamp};
+use crate::{Complex, Error, Float};
+
+// State for one channel.
+struct Channel {
+    // Frequency shift, as oscillator and per sample rotation.
+    phase: Complex,
+    step: Complex,
+    fir: FIR<Complex>,
+    ntaps: usize,
+
+    // Shifted samples not yet fully used by the filter.
+    buf: Vec<Complex>,
+
+    // Where in `buf` the next output sample starts.
+    pos: usize,
+
+    // Output of the last call to `process()`.
+    out: Vec<Complex>,
+}
+
+impl Channel {
+    fn new(freq: Float, samp_rate: Float, taps: &[Complex]) -> Self {
+        let w = -2.0 * std::f64::consts::PI as Float * freq / samp_rate;
+        Self {
+            phase: Complex::new(1.0, 0.0),
+            step: Complex::from_polar(1.0, w),
+            fir: FIR::new(taps),
+            ntaps: taps.len(),
+            buf: Vec::new(),
+            pos: 0,
+            out: Vec::new(),
+        }
+    }
+
+    fn process(&mut self, input: &[Complex], decim: usize) {
+        for s in input {
+            self.buf.push(s * self.phase);
+            self.phase *= self.step;
+        }
+        // Keep rounding errors from growing the amplitude.
+        self.phase /= self.phase.norm();
+
+        self.out.clear();
+        while self.pos + self.ntaps <= self.buf.len() {
+            self.out.push(self.fir.filter(&self.buf[self.pos..]));
+            self.pos += decim;
+        }
+        let used = std::cmp::min(self.pos, self.buf.len());
+        self.buf.drain(..used);
+        self.pos -= used;
+    }
+}
+
+/// Builder for [Channelizer].
+pub struct ChannelizerBuilder {
+    src: Streamp<Complex>,
+    samp_rate: Float,
+    taps: Vec<Complex>,
+    decim: usize,
+    freqs: Vec<Float>,
+    parallel: bool,
+}
+
+impl ChannelizerBuilder {
+    /// Create new builder.
+    ///
+    /// `taps` is the low pass filter applied to each channel after
+    /// shifting it to baseband. The output sample rate is
+    /// `samp_rate / decim`.
+    pub fn new(src: Streamp<Complex>, samp_rate: Float, taps: &[Complex], decim: usize) -> Self {
+        Self {
+            src,
+            samp_rate,
+            taps: taps.to_vec(),
+            decim,
+            freqs: Vec::new(),
+            parallel: false,
+        }
+    }
+
+    /// Add a channel, at the given offset in Hz from the center of
+    /// the input.
+    pub fn channel(mut self, freq: Float) -> Self {
+        self.freqs.push(freq);
+        self
+    }
+
+    /// Process the channels in parallel, on the rayon thread pool.
+    ///
+    /// Worth it with many channels or many taps. With few it's
+    /// slower, from the overhead of handing out the work.
+    #[cfg(feature = "rayon")]
+    pub fn parallel(mut self, v: bool) -> Self {
+        self.parallel = v;
+        self
+    }
+
+    /// Build Channelizer.
+    pub fn build(self) -> Channelizer {
+        Channelizer {
+            channels: self
+                .freqs
+                .iter()
+                .map(|f| Channel::new(*f, self.samp_rate, &self.taps))
+                .collect(),
+            dsts: self.freqs.iter().map(|_| new_streamp()).collect(),
+            decim: std::cmp::max(self.decim, 1),
+            parallel: self.parallel,
+            src: self.src,
+        }
+    }
+}
+
+/// Split a wideband stream into narrowband channels. See the [module
+/// docs](crate::channelizer).
+pub struct Channelizer {
+    src: Streamp<Complex>,
+    dsts: Vec<Streamp<Complex>>,
+    channels: Vec<Channel>,
+    decim: usize,
+    parallel: bool,
+}
+
+impl Channelizer {
+    /// Create a builder.
+    pub fn builder(
+        src: Streamp<Complex>,
+        samp_rate: Float,
+        taps: &[Complex],
+        decim: usize,
+    ) -> ChannelizerBuilder {
+        ChannelizerBuilder::new(src, samp_rate, taps, decim)
+    }
+
+    /// Return the output streams, in the order the channels were
+    /// added.
+    pub fn outs(&self) -> Vec<Streamp<Complex>> {
+        self.dsts.clone()
+    }
+}
+
+impl Block for Channelizer {
+    fn block_name(&self) -> &str {
+        "Channelizer"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (input, _tags) = self.src.read_buf()?;
+        let outs = self
+            .dsts
+            .iter()
+            .map(|d| d.write_buf())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Leaving one output sample of room per channel guarantees
+        // that the output fits, however the filter history lines up.
+        let space = outs.iter().map(|o| o.len()).min().unwrap_or(0);
+        let n = std::cmp::min(input.len(), space.saturating_sub(1) * self.decim);
+        if n == 0 {
+            trace!("Channelizer: no input or no output space");
+            return Ok(BlockRet::Noop);
+        }
+        let samples = &input.slice()[..n];
+        let decim = self.decim;
+
+        #[cfg(feature = "rayon")]
+        if self.parallel {
+            use rayon::prelude::*;
+            self.channels
+                .par_iter_mut()
+                .for_each(|c| c.process(samples, decim));
+        }
+        if !self.parallel {
+            for c in &mut self.channels {
+                c.process(samples, decim);
+            }
+        }
+
+        for (c, mut o) in self.channels.iter().zip(outs) {
+            o.fill_from_slice(&c.out);
+            o.produce(c.out.len(), &[]);
+        }
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::SignalSourceComplex;
+
+    fn run(
+        f: impl FnOnce(ChannelizerBuilder) -> ChannelizerBuilder,
+    ) -> Result<Vec<Vec<Complex>>, Error> {
+        let samp_rate = 8_000.0;
+        let mut src = SignalSourceComplex::new(samp_rate, 1000.0, 1.0);
+        let taps = crate::fir::low_pass_complex(samp_rate, 200.0, 100.0);
+        let b = Channelizer::builder(src.out(), samp_rate, &taps, 4)
+            .channel(1000.0)
+            .channel(-2000.0);
+        let mut chan = f(b).build();
+        for _ in 0..10 {
+            src.work()?;
+            chan.work()?;
+        }
+        chan.outs()
+            .iter()
+            .map(|o| Ok(o.read_buf()?.0.slice().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn channels() -> Result<(), Error> {
+        let outs = run(|b| b)?;
+        assert_eq!(outs.len(), 2);
+        assert!(outs[0].len() > 1000, "got {}", outs[0].len());
+        assert_eq!(outs[0].len(), outs[1].len());
+        // Skip the filter warming up.
+        let skip = 200;
+        for s in &outs[0][skip..] {
+            assert!((s.norm() - 1.0).abs() < 0.01, "got {s}");
+        }
+        for s in &outs[1][skip..] {
+            assert!(s.norm() < 0.01, "got {s}");
+        }
+        // Parallel gives exactly the same result.
+        #[cfg(feature = "rayon")]
+        assert_eq!(run(|b| b.parallel(true))?, outs);
+        Ok(())
+    }
+}
@@ -1,18 +1,38 @@
 //! Test implementation of circular buffers.
 //! Full of unsafe. Full of ugly code.
+//!
+//! The buffer is mapped twice, back to back, so that reads and writes
+//! never have to wrap. On WASM there's no mmap, so there the second
+//! half is a plain copy, kept up to date on every write.
+//!
+//! Since the second mapping has to start on a page boundary, buffer
+//! sizes are rounded up to a multiple of the page size.
+//!
+//! Access goes through a [BufferReader] or [BufferWriter], returned by
+//! [Buffer::read_buf] and [Buffer::write_buf]. There can be only one
+//! of each at a time. Their `consume` and `produce` take the handle by
+//! value, and only up to what the handle's slice covered, so a block
+//! can't consume samples it hasn't seen, or publish samples it hasn't
+//! written. Dropping a handle without calling them does nothing.
 
 use std::collections::BTreeMap;
+#[cfg(not(target_family = "wasm"))]
 use std::os::fd::AsRawFd;
 use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
-use libc::{c_int, c_uchar, c_void, off_t, size_t};
-use libc::{MAP_FAILED, MAP_SHARED, PROT_READ, PROT_WRITE};
+#[cfg(not(target_family = "wasm"))]
+use libc::{c_int, c_void, off_t, size_t};
+#[cfg(not(target_family = "wasm"))]
+use libc::{
+    MAP_ANONYMOUS, MAP_FAILED, MAP_FIXED, MAP_PRIVATE, MAP_SHARED, PROT_NONE, PROT_READ, PROT_WRITE,
+};
 use log::{debug, trace};
 
 use crate::stream::{Tag, TagPos};
 use crate::Error;
 
+#[cfg(not(target_family = "wasm"))]
 extern "C" {
     fn mmap(
         addr: *const c_void,
@@ -25,76 +45,156 @@ extern "C" {
     fn munmap(addr: *const c_void, length: size_t) -> c_int;
 }
 
+/// Size of a memory page, which buffer sizes are a multiple of.
+#[cfg(not(target_family = "wasm"))]
+pub fn page_size() -> usize {
+    let n = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if n > 0 {
+        n as usize
+    } else {
+        4096
+    }
+}
+
+/// Size of a memory page, which buffer sizes are a multiple of.
+#[cfg(target_family = "wasm")]
+pub fn page_size() -> usize {
+    4096
+}
+
+fn gcd(mut a: usize, mut b: usize) -> usize {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
 /// Circular buffer dealing in bytes.
 #[derive(Debug)]
 pub struct Circ {
-    buf: *mut c_uchar,
+    buf: *mut u8,
     len: usize,
 }
 
+// File backing the buffer. An anonymous memfd where there is one, so
+// that nothing touches the filesystem, else an unlinked tempfile.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn backing_file() -> Result<std::fs::File> {
+    use std::os::fd::FromRawFd;
+    let fd = unsafe { libc::memfd_create(c"rustradio-circ".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        debug!(
+            "memfd_create() failed, falling back to tempfile: {}",
+            std::io::Error::last_os_error()
+        );
+        return Ok(tempfile::tempfile()?);
+    }
+    Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+}
+
+#[cfg(all(
+    not(target_family = "wasm"),
+    not(any(target_os = "linux", target_os = "android"))
+))]
+fn backing_file() -> Result<std::fs::File> {
+    Ok(tempfile::tempfile()?)
+}
+
 impl Circ {
+    #[cfg(not(target_family = "wasm"))]
     fn create(size: usize) -> Result<Self> {
         let len = size;
         let len2 = len * 2;
-        let f = tempfile::tempfile()?;
-        f.set_len(len2 as u64)?;
+        // The file is closed when `f` goes out of scope. The mappings
+        // keep the memory alive until Drop.
+        let f = backing_file()?;
+        f.set_len(len as u64)?;
         let fd = f.as_raw_fd();
 
-        // Map first.
-        let buf = {
-            let buf = unsafe {
+        // Reserve address space for both halves.
+        let buf = unsafe {
+            mmap(
+                std::ptr::null::<c_void>(),
+                len2 as size_t,
+                PROT_NONE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if buf == MAP_FAILED {
+            return Err(Error::new("Initial mmap() failed").into());
+        }
+        let buf = buf as *mut u8;
+
+        // Map the file over both halves. MAP_FIXED replaces the
+        // reservation, so they're guaranteed to end up back to back.
+        for half in [0, len] {
+            let addr = unsafe { buf.add(half) } as *const c_void;
+            let got = unsafe {
                 mmap(
-                    std::ptr::null::<c_void>(),
-                    len2 as size_t,
+                    addr,
+                    len as size_t,
                     PROT_READ | PROT_WRITE,
-                    MAP_SHARED, // flags
-                    fd,         // fd
-                    0,          // offset
+                    MAP_SHARED | MAP_FIXED,
+                    fd,
+                    0,
                 )
             };
-            if buf == MAP_FAILED {
-                return Err(Error::new("Initial mmap() failed").into());
+            if got == MAP_FAILED || !std::ptr::eq(got, addr) {
+                let rc = unsafe { munmap(buf as *const c_void, len2) };
+                if rc != 0 {
+                    panic!("munmap() failed on buffer that we *definitely* allocated. Something is seriously broken!");
+                }
+                return Err(Error::new("mmap() of circular buffer half failed").into());
             }
-            buf as *mut c_uchar
-        };
-        let second = (buf as libc::uintptr_t + len as libc::uintptr_t) as *const c_void;
-
-        // Unmap second half.
-        let rc = unsafe { munmap(second, len) };
-        if rc != 0 {
-            panic!("munmap() failed on second half of circular buffer");
         }
+        Ok(Self { len: len2, buf })
+    }
 
-        // Re-map second half.
-        let buf2 = unsafe {
-            mmap(
-                second,
-                len as size_t,
-                PROT_READ | PROT_WRITE,
-                MAP_SHARED, // flags
-                fd,         // fd
-                0,          // offset
-            )
-        };
-        if buf2 == MAP_FAILED {
-            return Err(Error::new("second mmap did not succeed").into());
-        }
-        if buf2 as *const c_void != second {
-            let rc = unsafe { munmap(buf as *const c_void, len) };
-            if rc != 0 {
-                panic!("munmap() failed on buffer that we *definitely* allocated. Something is seriously broken!");
-            }
-            return Err(Error::new("second mmap did not end up where we wanted it").into());
+    #[cfg(target_family = "wasm")]
+    fn create(size: usize) -> Result<Self> {
+        let len2 = size * 2;
+        let layout = std::alloc::Layout::from_size_align(len2, 4096)?;
+        let buf = unsafe { std::alloc::alloc_zeroed(layout) };
+        if buf.is_null() {
+            return Err(Error::new("failed to allocate circular buffer").into());
         }
         Ok(Self { len: len2, buf })
     }
 
-    /// Create a new circular buffer.
+    /// Copy `len` bytes written at `start` to the other half.
     ///
-    /// TODO:
-    /// * don't leak memory on error.
-    /// * release memory on drop.
+    /// Only needed on WASM. Elsewhere the halves are the same memory.
+    #[cfg(target_family = "wasm")]
+    fn mirror(&self, start: usize, len: usize) {
+        let half = self.len / 2;
+        let end = start + len;
+        assert!(len <= half && start < half);
+        unsafe {
+            // Part in the first half.
+            let n = end.min(half) - start;
+            std::ptr::copy_nonoverlapping(self.buf.add(start), self.buf.add(start + half), n);
+            // Part that went into the second half.
+            if end > half {
+                std::ptr::copy_nonoverlapping(self.buf.add(half), self.buf, end - half);
+            }
+        }
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    fn mirror(&self, _start: usize, _len: usize) {}
+
+    /// Create a new circular buffer of `size` bytes, which must be a
+    /// multiple of the [page size](page_size).
     pub fn new(size: usize) -> Result<Self> {
+        if size == 0 || !size.is_multiple_of(page_size()) {
+            return Err(Error::BadParameter(format!(
+                "circular buffer size {size} is not a multiple of the page size {}",
+                page_size()
+            ))
+            .into());
+        }
         for attempt in 0..10 {
             trace!("Creating circular buffer, attempt {attempt}");
             match Circ::create(size) {
@@ -125,7 +225,7 @@ impl Circ {
     // a strange optimization, but let's hope not. :-)
     #[allow(clippy::mut_from_ref)]
     fn full_buffer<T>(&self, start: usize, end: usize) -> &mut [T] {
-        assert!(self.len % std::mem::size_of::<T>() == 0);
+        assert!(self.len.is_multiple_of(std::mem::size_of::<T>()));
         let buf = unsafe {
             std::slice::from_raw_parts_mut(self.buf as *mut T, self.len / std::mem::size_of::<T>())
         };
@@ -133,6 +233,23 @@ impl Circ {
     }
 }
 
+impl Drop for Circ {
+    #[cfg(not(target_family = "wasm"))]
+    fn drop(&mut self) {
+        // Both halves in one call, since they're adjacent.
+        let rc = unsafe { munmap(self.buf as *const c_void, self.len) };
+        if rc != 0 {
+            panic!("munmap() failed on circular buffer");
+        }
+    }
+
+    #[cfg(target_family = "wasm")]
+    fn drop(&mut self) {
+        let layout = std::alloc::Layout::from_size_align(self.len, 4096).unwrap();
+        unsafe { std::alloc::dealloc(self.buf, layout) };
+    }
+}
+
 unsafe impl Send for Circ {}
 unsafe impl Sync for Circ {}
 
@@ -199,8 +316,17 @@ impl<'a, T: Copy> BufferReader<'a, T> {
     }
 
     /// We're done with the buffer. Consume `n` samples.
+    ///
+    /// Panics if `n` is more than the slice, since the rest hasn't
+    /// been seen by the block.
     pub fn consume(self, n: usize) {
+        assert!(
+            n <= self.slice.len(),
+            "trying to consume {n}, but was only given {}",
+            self.slice.len()
+        );
         self.parent.consume(n);
+        crate::stream::count(|c| c.items_in += n as u64);
     }
 
     /// len convenience function.
@@ -212,6 +338,11 @@ impl<'a, T: Copy> BufferReader<'a, T> {
     pub fn is_empty(&self) -> bool {
         self.slice.is_empty()
     }
+
+    /// Only hand out the first `n` samples.
+    pub(crate) fn truncate(&mut self, n: usize) {
+        self.slice = &self.slice[..n];
+    }
 }
 
 impl<T: Copy> std::ops::Index<usize> for BufferReader<'_, T> {
@@ -260,9 +391,18 @@ impl<'a, T: Copy> BufferWriter<'a, T> {
 
     /// Having written into the write buffer, now tell the buffer
     /// we're done. Also here are the tags, with positions relative to
-    /// start of buffer.
+    /// start of buffer. Tags at or past `n` are dropped.
+    ///
+    /// Panics if `n` is more than the slice, since the rest hasn't
+    /// been written by the block.
     pub fn produce(self, n: usize, tags: &[Tag]) {
+        assert!(
+            n <= self.slice.len(),
+            "trying to produce {n}, but was only given room for {}",
+            self.slice.len()
+        );
         self.parent.produce(n, tags);
+        crate::stream::count(|c| c.items_out += n as u64);
     }
 
     /// len convenience function.
@@ -274,6 +414,12 @@ impl<'a, T: Copy> BufferWriter<'a, T> {
     pub fn is_empty(&self) -> bool {
         self.slice.is_empty()
     }
+
+    /// Only hand out the first `n` samples.
+    pub(crate) fn truncate(&mut self, n: usize) {
+        let slice = std::mem::take(&mut self.slice);
+        self.slice = &mut slice[..n];
+    }
 }
 
 impl<T: Copy> Drop for BufferWriter<'_, T> {
@@ -292,8 +438,16 @@ pub struct Buffer<T> {
 }
 
 impl<T> Buffer<T> {
-    /// Create a new Buffer.
+    /// Create a new Buffer of at least `size` bytes.
+    ///
+    /// The size is rounded up to a whole number of pages, that's also
+    /// a whole number of items. [total_size](Self::total_size) gives
+    /// the actual capacity.
     pub fn new(size: usize) -> Result<Self> {
+        let member = std::mem::size_of::<T>().max(1);
+        let page = page_size();
+        let unit = page / gcd(page, member) * member;
+        let size = size.max(1).div_ceil(unit) * unit;
         Ok(Self {
             state: Arc::new(Mutex::new(BufferState {
                 read_borrow: false,
@@ -311,8 +465,8 @@ impl<T> Buffer<T> {
         })
     }
 
-    /// Return length of buffer, ignoring how much is in use, and the
-    /// double buffer.
+    /// Return length of buffer in items, ignoring how much is in use,
+    /// and the double buffer.
     pub fn total_size(&self) -> usize {
         self.circ.total_size() / self.member_size
     }
@@ -375,11 +529,14 @@ impl<T: Copy> Buffer<T> {
             s.write_capacity(),
             n
         );
-        for tag in tags {
+        // Tags past what's produced would end up on later samples.
+        for tag in tags.iter().filter(|t| t.pos() < n) {
             let pos = (tag.pos() + s.wpos) % s.capacity();
             let tag = Tag::new(pos, tag.key().into(), tag.val().clone());
             s.tags.entry(pos).or_default().push(tag);
         }
+        self.circ
+            .mirror(s.wpos * self.member_size, n * self.member_size);
         s.wpos = (s.wpos + n) % s.capacity();
         s.used += n;
     }
@@ -399,7 +556,7 @@ impl<T: Copy> Buffer<T> {
     }
 
     /// Get the read slice.
-    pub fn read_buf(&self) -> Result<(BufferReader<T>, Vec<Tag>)> {
+    pub fn read_buf(&self) -> Result<(BufferReader<'_, T>, Vec<Tag>)> {
         let mut s = self.state.lock().unwrap();
         if s.read_borrow {
             return Err(Error::new("read buf already borrowed").into());
@@ -407,41 +564,28 @@ impl<T: Copy> Buffer<T> {
         s.read_borrow = true;
         let (start, end) = s.read_range();
         let buf = self.circ.full_buffer::<T>(start, end);
-        let mut tags = Vec::new();
-
-        // TODO: range scan the tags.
-        for (n, ts) in &s.tags {
-            let modded_n: usize = *n % s.capacity();
-            if end < s.capacity() && start < s.capacity() {
-                // Start and end are both in first half.
-                if modded_n < start || modded_n > end {
-                    continue;
-                }
-            } else {
-                // Start and end can't both be in the second half, and
-                // end has to be higher than start.
-                assert!(start < s.capacity());
-                if modded_n > (end % s.capacity()) && modded_n < start {
-                    continue;
-                }
-            }
-            for tag in ts {
-                tags.push(Tag::new(
-                    (tag.pos() + s.capacity() - start) % s.capacity(),
-                    tag.key().into(),
-                    tag.val().clone(),
-                ));
-            }
-        }
-        tags.sort_by_key(|a| a.pos());
+        // Tags are stored by position in the first half, so a range
+        // that continues into the second half is two ranges.
+        let cap = s.capacity();
+        let ranges = if end <= cap {
+            [(start, end), (0, 0)]
+        } else {
+            [(start, cap), (0, end - cap)]
+        };
+        let tags = ranges
+            .into_iter()
+            .flat_map(|(a, b)| s.tags.range(a..b))
+            .flat_map(|(_, ts)| ts)
+            .map(|tag| tag.with_pos((tag.pos() + cap - start) % cap))
+            .collect();
         Ok((
-            BufferReader::new(unsafe { std::mem::transmute(buf) }, self),
+            BufferReader::new(unsafe { std::mem::transmute::<&mut [T], &[T]>(buf) }, self),
             tags,
         ))
     }
 
     /// Get the write slice.
-    pub fn write_buf(&self) -> Result<BufferWriter<T>> {
+    pub fn write_buf(&self) -> Result<BufferWriter<'_, T>> {
         let mut s = self.state.lock().unwrap();
         if s.write_borrow {
             return Err(Error::new("write buf already borrowed").into());
@@ -449,7 +593,10 @@ impl<T: Copy> Buffer<T> {
         s.write_borrow = true;
         let (start, end) = s.write_range();
         let buf = self.circ.full_buffer::<T>(start, end);
-        Ok(BufferWriter::new(unsafe { std::mem::transmute(buf) }, self))
+        Ok(BufferWriter::new(
+            unsafe { std::mem::transmute::<&mut [T], &mut [T]>(buf) },
+            self,
+        ))
     }
 }
 
@@ -457,7 +604,6 @@ impl<T: Copy> Buffer<T> {
 mod tests {
     use super::*;
     use crate::stream::TagValue;
-    use crate::Float;
 
     #[test]
     pub fn test_no_double() -> Result<()> {
@@ -616,6 +762,121 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    pub fn sizes() -> Result<()> {
+        // Rounded up to whole pages.
+        assert_eq!(Buffer::<u8>::new(1)?.total_size(), 4096);
+        assert_eq!(Buffer::<u8>::new(4097)?.total_size(), 8192);
+        assert_eq!(Buffer::<u32>::new(10_000)?.total_size(), 3072);
+
+        // Items that don't fit evenly in a page.
+        let b = Buffer::<[u8; 24]>::new(10_000)?;
+        assert_eq!(b.total_size(), 512);
+
+        // Big, and wrapping around.
+        let b = Buffer::<u32>::new(8 << 20)?;
+        let n = b.total_size();
+        assert_eq!(n, 2 << 20);
+        b.write_buf()?.produce(n - 10, &[]);
+        b.consume(n - 10);
+        {
+            let mut w = b.write_buf()?;
+            assert_eq!(w.len(), n);
+            for (i, v) in w.slice()[..20].iter_mut().enumerate() {
+                *v = i as u32;
+            }
+            w.produce(20, &[]);
+        }
+        let (r, _) = b.read_buf()?;
+        assert_eq!(r.slice(), (0..20).collect::<Vec<u32>>());
+        Ok(())
+    }
+
+    // Would run out of address space, or file descriptors, if
+    // buffers were leaked.
+    #[test]
+    pub fn create_and_drop_many() -> Result<()> {
+        for _ in 0..5000 {
+            let b: Buffer<u8> = Buffer::new(64 << 20)?;
+            b.write_buf()?.produce(1, &[]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    pub fn tags_past_produced() -> Result<()> {
+        let b: Buffer<u8> = Buffer::new(4096)?;
+        b.write_buf()?.produce(
+            10,
+            &[
+                Tag::new(9, "last".into(), TagValue::Bool(true)),
+                Tag::new(10, "next".into(), TagValue::Bool(true)),
+            ],
+        );
+        assert_eq!(
+            b.read_buf()?.1,
+            vec![Tag::new(9, "last".into(), TagValue::Bool(true))]
+        );
+
+        // Passing the same tag on again, for the next sample, doesn't
+        // duplicate it.
+        b.write_buf()?
+            .produce(1, &[Tag::new(0, "next".into(), TagValue::Bool(true))]);
+        assert_eq!(
+            b.read_buf()?.1,
+            vec![
+                Tag::new(9, "last".into(), TagValue::Bool(true)),
+                Tag::new(10, "next".into(), TagValue::Bool(true))
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn tags_wrapping() -> Result<()> {
+        let b: Buffer<u8> = Buffer::new(4096)?;
+        b.write_buf()?.produce(4000, &[]);
+        b.read_buf()?.0.consume(4000);
+        b.write_buf()?.produce(
+            200,
+            &[
+                Tag::new(0, "first".into(), TagValue::Bool(true)),
+                Tag::new(95, "before".into(), TagValue::Bool(true)),
+                Tag::new(96, "after".into(), TagValue::Bool(true)),
+            ],
+        );
+        let (r, tags) = b.read_buf()?;
+        assert_eq!(r.len(), 200);
+        assert_eq!(
+            tags,
+            vec![
+                Tag::new(0, "first".into(), TagValue::Bool(true)),
+                Tag::new(95, "before".into(), TagValue::Bool(true)),
+                Tag::new(96, "after".into(), TagValue::Bool(true)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "trying to produce 11")]
+    pub fn produce_past_truncated() {
+        let b: Buffer<u8> = Buffer::new(4096).unwrap();
+        let mut w = b.write_buf().unwrap();
+        w.truncate(10);
+        w.produce(11, &[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "trying to consume 11")]
+    pub fn consume_past_truncated() {
+        let b: Buffer<u8> = Buffer::new(4096).unwrap();
+        b.write_buf().unwrap().produce(20, &[]);
+        let (mut r, _) = b.read_buf().unwrap();
+        r.truncate(10);
+        r.consume(11);
+    }
+
     #[test]
     pub fn exact_overflow() -> Result<()> {
         let b: Buffer<u8> = Buffer::new(4096)?;
@@ -638,7 +899,7 @@ mod tests {
 
     #[test]
     pub fn test_float() -> Result<()> {
-        let b: Buffer<Float> = Buffer::new(4096)?;
+        let b: Buffer<f32> = Buffer::new(4096)?;
 
         // Initial.
         assert!(b.read_buf()?.0.is_empty());
@@ -663,12 +924,12 @@ mod tests {
             let n = 1000;
             let mut wb = b.write_buf()?;
             for i in 0..n {
-                wb.slice()[i] = i as Float;
+                wb.slice()[i] = i as f32;
             }
             wb.produce(n, &[]);
             assert_eq!(b.read_buf()?.0.len(), n);
             for i in 0..n {
-                assert_eq!(b.read_buf()?.0.slice()[i], i as Float);
+                assert_eq!(b.read_buf()?.0.slice()[i], i as f32);
             }
             assert_eq!(b.write_buf()?.len(), 24);
         }
@@ -679,12 +940,12 @@ mod tests {
             let n = 100;
             let mut wb = b.write_buf()?;
             for i in 0..n {
-                wb.slice()[i] = (n - i) as Float;
+                wb.slice()[i] = (n - i) as f32;
             }
             wb.produce(n, &[]);
             assert_eq!(b.read_buf()?.0.len(), n);
             for i in 0..n {
-                assert_eq!(b.read_buf()?.0.slice()[i], (n - i) as Float);
+                assert_eq!(b.read_buf()?.0.slice()[i], (n - i) as f32);
             }
         }
         assert_eq!(b.read_buf()?.0.len(), 100);
@@ -1,8 +1,7 @@
 //! Convert Complex numbers to square of their magnitude.
-use anyhow::Result;
-
+use crate::block::{Block, BlockRet};
 use crate::stream::{new_streamp, Streamp};
-use crate::{map_block_convert_macro, Complex, Float};
+use crate::{kernels, Complex, Error, Float};
 
 /// Convert Complex numbers to square of their magnitude.
 pub struct ComplexToMag2 {
@@ -18,9 +17,30 @@ impl ComplexToMag2 {
             dst: new_streamp(),
         }
     }
-    fn process_one(&self, sample: Complex) -> Float {
-        sample.norm_sqr()
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Float> {
+        self.dst.clone()
+    }
+}
+
+impl Block for ComplexToMag2 {
+    fn block_name(&self) -> &str {
+        "ComplexToMag2"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (i, tags) = self.src.read_buf()?;
+        let mut o = self.dst.write_buf()?;
+        let n = std::cmp::min(i.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        kernels::mag2(&i.slice()[..n], o.slice());
+        o.produce(n, &tags);
+        i.consume(n);
+        Ok(BlockRet::Ok)
     }
 }
 
-map_block_convert_macro![ComplexToMag2, Float];
+/// Square of the magnitude. Same as [ComplexToMag2].
+pub type ComplexToMagSq = ComplexToMag2;
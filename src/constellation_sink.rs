@@ -0,0 +1,367 @@
+/*! Live constellation diagram.
+
+[ConstellationSink] takes symbols, e.g. the output of a
+[SymbolSync][crate::symbol_sync::SymbolSync] on a Complex stream, and
+accumulates them into a 2D histogram with persistence: old symbols
+fade out as new ones arrive. With the `egui` feature enabled,
+[ConstellationView] shows it, in the [gui][crate::gui] window
+framework.
+
+```text
+… -> SymbolSync -> ConstellationSink
+```
+
+By default the scale follows the average symbol magnitude, so the
+constellation fills the plot whatever the gain.
+*/
+use std::sync::{Arc, Mutex};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::Streamp;
+use crate::{Complex, Error, Float};
+
+const DEFAULT_SIZE: usize = 256;
+const DEFAULT_DECAY: Float = 1000.0;
+
+// How much bigger than the average magnitude the auto range is.
+const AUTO_RANGE_FACTOR: Float = 2.0;
+
+// Weight of each symbol in the auto range average.
+const AUTO_RANGE_ALPHA: Float = 0.001;
+
+struct Shared {
+    grid: Vec<Float>,
+    size: usize,
+    range: Float,
+    auto_range: bool,
+    decay: Float,
+    count: u64,
+}
+
+/// Handle for reading the constellation, and changing settings, from
+/// another thread.
+#[derive(Clone)]
+pub struct ConstellationHandle {
+    h: Arc<Mutex<Shared>>,
+}
+
+impl ConstellationHandle {
+    /// Get the histogram, `size` by `size` cells, row by row from the
+    /// top (max Q) left (min I), normalized so the highest cell is 1.
+    pub fn grid(&self) -> (Vec<Float>, usize) {
+        let h = self.h.lock().unwrap();
+        let max = h.grid.iter().cloned().fold(0.0, Float::max);
+        let grid = if max > 0.0 {
+            h.grid.iter().map(|v| v / max).collect()
+        } else {
+            h.grid.clone()
+        };
+        (grid, h.size)
+    }
+
+    /// Number of symbols seen.
+    pub fn count(&self) -> u64 {
+        self.h.lock().unwrap().count
+    }
+
+    /// Get the plot range. The plot goes from -range to +range on both
+    /// axes.
+    pub fn range(&self) -> Float {
+        self.h.lock().unwrap().range
+    }
+
+    /// Set a fixed plot range, or None to follow the signal.
+    pub fn set_range(&self, range: Option<Float>) {
+        let mut h = self.h.lock().unwrap();
+        match range {
+            Some(r) => {
+                h.range = r.max(Float::MIN_POSITIVE);
+                h.auto_range = false;
+            }
+            None => h.auto_range = true,
+        }
+        h.grid.fill(0.0);
+    }
+
+    /// Return true if the range follows the signal.
+    pub fn auto_range(&self) -> bool {
+        self.h.lock().unwrap().auto_range
+    }
+
+    /// Get the persistence, in symbols.
+    pub fn decay(&self) -> Float {
+        self.h.lock().unwrap().decay
+    }
+
+    /// Set the persistence: the number of symbols after which an old
+    /// symbol has faded to 1/e.
+    pub fn set_decay(&self, symbols: Float) {
+        self.h.lock().unwrap().decay = symbols.max(1.0);
+    }
+
+    /// Clear the histogram.
+    pub fn clear(&self) {
+        self.h.lock().unwrap().grid.fill(0.0);
+    }
+}
+
+/// Constellation sink block.
+pub struct ConstellationSink {
+    src: Streamp<Complex>,
+    avg_mag: Option<Float>,
+    handle: ConstellationHandle,
+}
+
+impl ConstellationSink {
+    /// Create new ConstellationSink block.
+    pub fn new(src: Streamp<Complex>) -> Self {
+        Self {
+            src,
+            avg_mag: None,
+            handle: ConstellationHandle {
+                h: Arc::new(Mutex::new(Shared {
+                    grid: vec![0.0; DEFAULT_SIZE * DEFAULT_SIZE],
+                    size: DEFAULT_SIZE,
+                    range: 1.5,
+                    auto_range: true,
+                    decay: DEFAULT_DECAY,
+                    count: 0,
+                })),
+            },
+        }
+    }
+
+    /// Return a handle for reading the constellation.
+    pub fn handle(&self) -> ConstellationHandle {
+        self.handle.clone()
+    }
+}
+
+impl Block for ConstellationSink {
+    fn block_name(&self) -> &str {
+        "ConstellationSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        let n = input.len();
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        let mut h = self.handle.h.lock().unwrap();
+        if h.auto_range {
+            let mut avg = self.avg_mag.unwrap_or(input.slice()[0].norm());
+            for s in input.iter() {
+                avg += AUTO_RANGE_ALPHA * (s.norm() - avg);
+            }
+            self.avg_mag = Some(avg);
+            if avg > 0.0 {
+                h.range = avg * AUTO_RANGE_FACTOR;
+            }
+        }
+        let fade = (-(n as Float) / h.decay).exp();
+        h.grid.iter_mut().for_each(|v| *v *= fade);
+        let (size, range) = (h.size, h.range);
+        let cell = |v: Float| {
+            let c = ((v / range + 1.0) / 2.0 * size as Float).floor();
+            (c >= 0.0 && c < size as Float).then_some(c as usize)
+        };
+        for s in input.iter() {
+            if let (Some(x), Some(y)) = (cell(s.re), cell(-s.im)) {
+                h.grid[y * size + x] += 1.0;
+            }
+        }
+        h.count += n as u64;
+        drop(h);
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(feature = "egui")]
+mod view {
+    use super::*;
+    use crate::gui::{View, GRID_COLOR};
+    use crate::waterfall_sink::colormap;
+    use eframe::egui;
+    use egui::{Color32, ColorImage, Pos2, Rect, Sense, Stroke, TextureHandle};
+
+    /// Constellation display, for [crate::gui::run].
+    pub struct ConstellationView {
+        handle: ConstellationHandle,
+        texture: Option<TextureHandle>,
+        marker: Option<Complex>,
+    }
+
+    impl ConstellationView {
+        /// Create new constellation view.
+        pub fn new(handle: ConstellationHandle) -> Self {
+            Self {
+                handle,
+                texture: None,
+                marker: None,
+            }
+        }
+    }
+
+    impl View for ConstellationView {
+        fn name(&self) -> &str {
+            "Constellation"
+        }
+
+        fn controls(&mut self, ui: &mut egui::Ui) {
+            let mut decay = self.handle.decay();
+            if ui
+                .add(
+                    egui::Slider::new(&mut decay, 10.0..=100000.0)
+                        .logarithmic(true)
+                        .text("Persistence"),
+                )
+                .changed()
+            {
+                self.handle.set_decay(decay);
+            }
+            let mut auto = self.handle.auto_range();
+            if ui.checkbox(&mut auto, "Auto range").changed() {
+                self.handle
+                    .set_range((!auto).then_some(self.handle.range()));
+            }
+            let mut range = self.handle.range();
+            if ui
+                .add_enabled(
+                    !auto,
+                    egui::Slider::new(&mut range, 0.001..=10.0)
+                        .logarithmic(true)
+                        .text("Range"),
+                )
+                .changed()
+            {
+                self.handle.set_range(Some(range));
+            }
+            if ui.button("Clear").clicked() {
+                self.handle.clear();
+            }
+        }
+
+        fn show(&mut self, ui: &mut egui::Ui) {
+            let (grid, size) = self.handle.grid();
+            let mut img = ColorImage::new([size, size], Color32::BLACK);
+            for (p, v) in img.pixels.iter_mut().zip(&grid) {
+                // Square root, to make rare symbols visible.
+                let [r, g, b] = colormap(v.sqrt());
+                *p = Color32::from_rgb(r, g, b);
+            }
+            match &mut self.texture {
+                Some(t) => t.set(img, egui::TextureOptions::NEAREST),
+                None => {
+                    self.texture = Some(ui.ctx().load_texture(
+                        "constellation",
+                        img,
+                        egui::TextureOptions::NEAREST,
+                    ))
+                }
+            }
+
+            // Keep it square.
+            let avail = ui.available_size();
+            let side = avail.x.min(avail.y);
+            let (resp, painter) = ui.allocate_painter(egui::vec2(side, side), Sense::click());
+            let rect = resp.rect;
+            let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+            if let Some(t) = &self.texture {
+                painter.image(t.id(), rect, uv, Color32::WHITE);
+            }
+            let c = rect.center();
+            painter.hline(rect.x_range(), c.y, Stroke::new(1.0, GRID_COLOR));
+            painter.vline(c.x, rect.y_range(), Stroke::new(1.0, GRID_COLOR));
+
+            let range = self.handle.range();
+            painter.text(
+                rect.right_top() + egui::vec2(-2.0, 2.0),
+                egui::Align2::RIGHT_TOP,
+                format!("±{range:.3}"),
+                egui::FontId::monospace(10.0),
+                Color32::GRAY,
+            );
+
+            // Marker.
+            if let Some(pos) = resp.interact_pointer_pos() {
+                let re = ((pos.x - c.x) / (side / 2.0)) as Float * range;
+                let im = ((c.y - pos.y) / (side / 2.0)) as Float * range;
+                self.marker = Some(Complex::new(re, im));
+            }
+            if let Some(m) = self.marker {
+                let pos = c + egui::vec2(m.re as f32, -m.im as f32) * (side / 2.0 / range as f32);
+                painter.circle_stroke(pos, 4.0, Stroke::new(1.0, Color32::WHITE));
+                painter.text(
+                    pos + egui::vec2(6.0, 0.0),
+                    egui::Align2::LEFT_CENTER,
+                    format!(
+                        "{:.3}{:+.3}j\n{:.3} ∠{:.1}°",
+                        m.re,
+                        m.im,
+                        m.norm(),
+                        m.arg().to_degrees()
+                    ),
+                    egui::FontId::monospace(12.0),
+                    Color32::WHITE,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(feature = "egui")]
+pub use view::ConstellationView;
+
+/// Open a window showing the constellation, and run until it's closed.
+///
+/// Must be called from the main thread.
+#[cfg(feature = "egui")]
+pub fn run_window(handle: ConstellationHandle, title: &str) -> anyhow::Result<()> {
+    crate::gui::run(title, vec![Box::new(ConstellationView::new(handle))])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::VectorSource;
+
+    #[test]
+    fn qpsk() -> anyhow::Result<()> {
+        let syms: Vec<Complex> = (0..400)
+            .map(|n| {
+                let a = std::f64::consts::FRAC_PI_4 as Float
+                    + std::f64::consts::FRAC_PI_2 as Float * (n % 4) as Float;
+                Complex::from_polar(0.5, a)
+            })
+            .collect();
+        let mut src = VectorSource::new(syms);
+        let mut sink = ConstellationSink::new(src.out());
+        let h = sink.handle();
+        src.work()?;
+        sink.work()?;
+        assert_eq!(h.count(), 400);
+        assert!((h.range() - 1.0).abs() < 0.001, "{}", h.range());
+
+        let (grid, size) = h.grid();
+        let mut hot: Vec<(usize, usize)> = grid
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| **v > 0.5)
+            .map(|(i, _)| (i % size, i / size))
+            .collect();
+        hot.sort();
+        // 0.5/√2 ≈ 0.354, so cell 128 ± 0.354*128.
+        assert_eq!(hot, vec![(82, 82), (82, 173), (173, 82), (173, 173)]);
+
+        // Fixed range, with the symbols outside the plot.
+        h.set_range(Some(0.1));
+        src.set_repeat(crate::vector_source::Repeat::Finite(2));
+        src.work()?;
+        sink.work()?;
+        assert!(h.grid().0.iter().all(|v| *v == 0.0));
+        assert_eq!(h.range(), 0.1);
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+/*! Change block parameters while the graph is running.
+
+Blocks with settings that make sense to change on the fly, like the
+frequency of an SDR source or the threshold of a squelch, expose them
+as named [Control]s, through [Block::controls]. Get them all from the
+graph with [Graph::controls](crate::graph::Graph::controls) before
+calling `run()`, and set them from any thread.
+
+```text
+   Graph::controls() -> Controls -> set("Rotator", "freq", …)
+                                 -> get("PowerSquelch", "threshold")
+```
+
+Values are [TagValue]s. Numeric controls accept both `Float` and
+`U64`, as long as the value fits.
+
+```
+use rustradio::blocks::{ConstantSource, NullSink, Rotator};
+use rustradio::graph::Graph;
+use rustradio::stream::TagValue;
+use rustradio::Complex;
+
+let src = ConstantSource::new(Complex::new(1.0, 0.0));
+let rot = Rotator::new(src.out(), 48000.0, 1000.0);
+let sink = NullSink::new(rot.out());
+let mut g = Graph::new();
+g.add(Box::new(src));
+g.add(Box::new(rot));
+g.add(Box::new(sink));
+
+let controls = g.controls();
+let cancel = g.cancel_token();
+std::thread::spawn(move || {
+    controls.set("Rotator", "freq", TagValue::Float(-500.0)).unwrap();
+    cancel.cancel();
+});
+g.run()?;
+# Ok::<(), anyhow::Error>(())
+```
+
+Blocks usually implement their controls with the same shared state as
+their own handle, like [RotatorHandle](crate::rotator::RotatorHandle),
+so the handle and the control can be used interchangeably.
+*/
+use std::sync::Arc;
+
+use crate::block::Block;
+use crate::stream::TagValue;
+use crate::{Error, Float};
+
+type Getter = dyn Fn() -> TagValue + Send + Sync;
+type Setter = dyn Fn(&TagValue) -> Result<(), Error> + Send + Sync;
+
+/// A named block parameter that can be changed while running.
+///
+/// Cheap to clone, and all clones control the same parameter.
+#[derive(Clone)]
+pub struct Control {
+    name: String,
+    get: Arc<Getter>,
+    set: Arc<Setter>,
+}
+
+impl std::fmt::Debug for Control {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Control")
+            .field("name", &self.name)
+            .field("value", &self.get())
+            .finish()
+    }
+}
+
+impl Control {
+    /// Create a control of any type. `set` should reject values of the
+    /// wrong type.
+    pub fn new(
+        name: &str,
+        get: impl Fn() -> TagValue + Send + Sync + 'static,
+        set: impl Fn(&TagValue) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            get: Arc::new(get),
+            set: Arc::new(set),
+        }
+    }
+
+    /// Create a float control.
+    pub fn float(
+        name: &str,
+        get: impl Fn() -> Float + Send + Sync + 'static,
+        set: impl Fn(Float) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        let n = name.to_string();
+        Self::new(
+            name,
+            move || TagValue::Float(get()),
+            move |v| match v {
+                TagValue::Float(f) => set(*f),
+                TagValue::U64(u) => set(*u as Float),
+                _ => Err(mismatch(&n, "a number", v)),
+            },
+        )
+    }
+
+    /// Create an unsigned integer control.
+    pub fn u64(
+        name: &str,
+        get: impl Fn() -> u64 + Send + Sync + 'static,
+        set: impl Fn(u64) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        let n = name.to_string();
+        Self::new(
+            name,
+            move || TagValue::U64(get()),
+            move |v| match v {
+                TagValue::U64(u) => set(*u),
+                TagValue::Float(f) if *f >= 0.0 && f.fract() == 0.0 => set(*f as u64),
+                _ => Err(mismatch(&n, "an unsigned integer", v)),
+            },
+        )
+    }
+
+    /// Create a bool control.
+    pub fn bool(
+        name: &str,
+        get: impl Fn() -> bool + Send + Sync + 'static,
+        set: impl Fn(bool) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        let n = name.to_string();
+        Self::new(
+            name,
+            move || TagValue::Bool(get()),
+            move |v| match v {
+                TagValue::Bool(b) => set(*b),
+                _ => Err(mismatch(&n, "a bool", v)),
+            },
+        )
+    }
+
+    /// Name of the parameter.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Current value.
+    pub fn get(&self) -> TagValue {
+        (self.get)()
+    }
+
+    /// Set a new value. The block uses it from its next `work()` call.
+    pub fn set(&self, value: TagValue) -> Result<(), Error> {
+        (self.set)(&value)
+    }
+}
+
+fn mismatch(name: &str, want: &str, got: &TagValue) -> Error {
+    Error::TypeMismatch(format!("control {name} wants {want}, got {got:?}"))
+}
+
+/// The controls of all blocks in a graph.
+///
+/// Cheap to clone, and can be sent to other threads.
+#[derive(Clone, Default, Debug)]
+pub struct Controls {
+    // Block name, and control.
+    controls: Vec<(String, Control)>,
+}
+
+impl Controls {
+    /// Create an empty set of controls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the controls of a block.
+    pub fn add_block(&mut self, b: &dyn Block) {
+        let name = b.block_name().to_string();
+        self.controls
+            .extend(b.controls().into_iter().map(|c| (name.clone(), c)));
+    }
+
+    /// Find a control. If several blocks have the same name, the
+    /// first one added.
+    pub fn get(&self, block: &str, name: &str) -> Option<&Control> {
+        self.controls
+            .iter()
+            .find(|(b, c)| b == block && c.name() == name)
+            .map(|(_, c)| c)
+    }
+
+    /// Set a control.
+    pub fn set(&self, block: &str, name: &str, value: TagValue) -> Result<(), Error> {
+        self.get(block, name)
+            .ok_or_else(|| Error::BadParameter(format!("no control {block}.{name}")))?
+            .set(value)
+    }
+
+    /// All controls, with the name of their block.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Control)> {
+        self.controls.iter().map(|(b, c)| (b.as_str(), c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{PowerSquelch, Rotator, VectorSource};
+    use crate::graph::Graph;
+    use crate::mtgraph::MTGraph;
+    use crate::Complex;
+    use std::sync::Mutex;
+
+    #[test]
+    fn types() -> crate::Result<()> {
+        let v = Arc::new(Mutex::new(100));
+        let (g, s) = (v.clone(), v.clone());
+        let c = Control::u64(
+            "freq",
+            move || *g.lock().unwrap(),
+            move |u| {
+                *s.lock().unwrap() = u;
+                Ok(())
+            },
+        );
+        assert_eq!(c.name(), "freq");
+        assert_eq!(c.get(), TagValue::U64(100));
+        c.set(TagValue::Float(200.0))?;
+        assert_eq!(c.get(), TagValue::U64(200));
+        assert!(c.set(TagValue::Float(-1.0)).is_err());
+        assert!(c.set(TagValue::Float(1.5)).is_err());
+        assert!(c.set(TagValue::Bool(true)).is_err());
+        assert_eq!(*v.lock().unwrap(), 200);
+
+        let v = Arc::new(Mutex::new(1.0));
+        let (g, s) = (v.clone(), v.clone());
+        let c = Control::float(
+            "gain",
+            move || *g.lock().unwrap(),
+            move |f| {
+                *s.lock().unwrap() = f;
+                Ok(())
+            },
+        );
+        c.clone().set(TagValue::U64(3))?;
+        assert_eq!(c.get(), TagValue::Float(3.0));
+
+        let c = Control::bool("agc", || false, |_| Err(Error::new("device gone")));
+        assert!(c.set(TagValue::Bool(true)).is_err());
+        assert!(c.set(TagValue::U64(1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn graph() -> crate::Result<()> {
+        let src = VectorSource::new(vec![Complex::new(1.0, 0.0); 10]);
+        let rot = Rotator::new(src.out(), 48000.0, 1000.0);
+        let rh = rot.handle();
+        let sq = PowerSquelch::new(rot.out(), -20.0);
+        let sh = sq.handle();
+        let mut g = Graph::new();
+        g.add(Box::new(src));
+        g.add(Box::new(rot));
+        g.add(Box::new(sq));
+
+        let controls = g.controls();
+        let names: Vec<_> = controls
+            .iter()
+            .map(|(b, c)| format!("{b}.{}", c.name()))
+            .collect();
+        assert_eq!(names, ["Rotator.freq", "PowerSquelch.threshold"]);
+        std::thread::spawn(move || controls.set("Rotator", "freq", TagValue::Float(-500.0)))
+            .join()
+            .unwrap()?;
+        g.controls()
+            .set("PowerSquelch", "threshold", TagValue::Float(-30.0))?;
+        assert!(g
+            .controls()
+            .set("Rotator", "gain", TagValue::U64(1))
+            .is_err());
+        g.run()?;
+        assert_eq!(rh.freq(), -500.0);
+        assert_eq!(sh.threshold(), -30.0);
+        Ok(())
+    }
+
+    #[test]
+    fn mtgraph() -> crate::Result<()> {
+        let src = VectorSource::new(vec![Complex::new(1.0, 0.0); 10]);
+        let rot = Rotator::new(src.out(), 48000.0, 1000.0);
+        let rh = rot.handle();
+        let mut g = MTGraph::new();
+        g.add(Box::new(src));
+        g.add(Box::new(rot));
+        g.controls().set("Rotator", "freq", TagValue::U64(10))?;
+        assert_eq!(rh.freq(), 10.0);
+        Ok(())
+    }
+}
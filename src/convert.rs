@@ -1,15 +1,33 @@
-//! Blocks for converting from one type to another.
+/*! Blocks for converting from one type to another, and closure based
+map and filter blocks.
+
+The integer conversions are for raw captures from other SDR tools,
+read with [FileSource][crate::file_source::FileSource]. E.g. a file of
+interleaved 16 bit I/Q:
+
+```no_run
+use rustradio::blocks::{FileSource, InterleavedI16ToComplex};
+
+let src = FileSource::<i16>::new("capture.cs16", false)?;
+let iq = InterleavedI16ToComplex::new(src.out(), 32768.0);
+# Ok::<(), anyhow::Error>(())
+```
+
+Like in GNU Radio, integer to float conversions divide by `scale`,
+and float to integer conversions multiply by it. Float to integer
+rounds, and saturates at the limits of the integer type.
+*/
 use anyhow::Result;
 
 use crate::block::{Block, BlockRet};
-use crate::stream::{new_streamp, Streamp};
+use crate::stream::{new_streamp, Streamp, Tag};
 use crate::Error;
 use crate::{Complex, Float};
 
 /// Builder for Map.
 pub struct MapBuilder<In, Out, F>
 where
-    F: Fn(In) -> Out,
+    F: FnMut(In) -> Out,
 {
     map: F,
     name: String,
@@ -18,7 +36,7 @@ where
 
 impl<In, Out, F> MapBuilder<In, Out, F>
 where
-    F: Fn(In) -> Out,
+    F: FnMut(In) -> Out,
 {
     /// Create new MapBuilder.
     pub fn new(src: Streamp<In>, map: F) -> Self {
@@ -35,14 +53,35 @@ where
     }
     /// Build Map.
     pub fn build(self) -> Map<In, Out, F> {
-        Map::new(self.name, self.src, self.map)
+        let mut b = Map::new(self.src, self.map);
+        b.name = self.name;
+        b
     }
 }
 
-/// Arbitrary mapping
+/** Arbitrary mapping.
+
+The closure may keep state between samples.
+
+```
+use rustradio::blocks::{Map, VectorSource};
+use rustradio::Complex;
+
+let src = VectorSource::new(vec![Complex::new(1.0, 2.0)]);
+let b = Map::new(src.out(), |x: Complex| x.norm_sqr());
+
+// Running sum.
+let src = VectorSource::new(vec![1.0, 2.0, 3.0]);
+let mut sum = 0.0;
+let b = Map::new(src.out(), move |x: f32| {
+    sum += x;
+    sum
+});
+```
+*/
 pub struct Map<In, Out, F>
 where
-    F: Fn(In) -> Out,
+    F: FnMut(In) -> Out,
 {
     name: String,
     map: F,
@@ -52,19 +91,18 @@ where
 
 impl<In, Out, F> Map<In, Out, F>
 where
-    F: Fn(In) -> Out,
+    F: FnMut(In) -> Out,
 {
     /// Return the output stream.
     pub fn out(&self) -> Streamp<Out> {
         self.dst.clone()
     }
-    /// Create new FloatToU32, scaled.
+    /// Create new Map block, calling `map` on every sample.
     ///
-    /// Return value is the input multiplied by the scale. E.g. with a
-    /// scale of 100.0, the input 0.123 becomes 12.
-    fn new(name: String, src: Streamp<In>, map: F) -> Self {
+    /// Use [MapBuilder] to also set the block name.
+    pub fn new(src: Streamp<In>, map: F) -> Self {
         Self {
-            name,
+            name: "Map".into(),
             map,
             src,
             dst: new_streamp(),
@@ -82,7 +120,7 @@ impl<In, Out, F> Block for Map<In, Out, F>
 where
     In: Copy,
     Out: Copy,
-    F: Fn(In) -> Out,
+    F: FnMut(In) -> Out,
 {
     fn block_name(&self) -> &str {
         &self.name
@@ -115,6 +153,264 @@ where
     }
 }
 
+/** Drop samples for which a predicate returns false.
+
+Tags on dropped samples are dropped too.
+
+```
+use rustradio::blocks::{Filter, VectorSource};
+
+let src = VectorSource::new(vec![1.0, -1.0, 2.0]);
+let b = Filter::new(src.out(), |x: &f32| *x > 0.0);
+```
+*/
+pub struct Filter<T, F>
+where
+    F: FnMut(&T) -> bool,
+{
+    filter: F,
+    src: Streamp<T>,
+    dst: Streamp<T>,
+}
+
+impl<T, F> Filter<T, F>
+where
+    F: FnMut(&T) -> bool,
+{
+    /// Create new Filter block, keeping samples where `filter` returns true.
+    pub fn new(src: Streamp<T>, filter: F) -> Self {
+        Self {
+            filter,
+            src,
+            dst: new_streamp(),
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<T> {
+        self.dst.clone()
+    }
+}
+
+impl<T, F> Block for Filter<T, F>
+where
+    T: Copy,
+    F: FnMut(&T) -> bool,
+{
+    fn block_name(&self) -> &str {
+        "Filter"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (i, tags) = self.src.read_buf()?;
+        if i.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let mut o = self.dst.write_buf()?;
+        if o.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+
+        // Every input sample may be kept, so don't read more than fits.
+        let n = std::cmp::min(i.len(), o.len());
+        let mut otags = Vec::new();
+        let mut opos = 0;
+        let oslice = o.slice();
+        for (pos, sample) in i.iter().take(n).enumerate() {
+            if !(self.filter)(sample) {
+                continue;
+            }
+            otags.extend(
+                tags.iter()
+                    .filter(|t| t.pos() == pos)
+                    .map(|t| Tag::new(opos, t.key().into(), t.val().clone())),
+            );
+            oslice[opos] = *sample;
+            opos += 1;
+        }
+        i.consume(n);
+        o.produce(opos, &otags);
+        Ok(BlockRet::Ok)
+    }
+}
+
+/** Map samples, dropping those for which the closure returns None.
+
+Tags on dropped samples are dropped too.
+
+```
+use rustradio::blocks::{FilterMap, VectorSource};
+
+// Bytes to ASCII digit values, skipping anything else.
+let src = VectorSource::new(b"1a2".to_vec());
+let b = FilterMap::new(src.out(), |x: u8| (x as char).to_digit(10).map(|d| d as u8));
+```
+*/
+pub struct FilterMap<In, Out, F>
+where
+    F: FnMut(In) -> Option<Out>,
+{
+    map: F,
+    src: Streamp<In>,
+    dst: Streamp<Out>,
+}
+
+impl<In, Out, F> FilterMap<In, Out, F>
+where
+    F: FnMut(In) -> Option<Out>,
+{
+    /// Create new FilterMap block.
+    pub fn new(src: Streamp<In>, map: F) -> Self {
+        Self {
+            map,
+            src,
+            dst: new_streamp(),
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Out> {
+        self.dst.clone()
+    }
+}
+
+impl<In, Out, F> Block for FilterMap<In, Out, F>
+where
+    In: Copy,
+    Out: Copy,
+    F: FnMut(In) -> Option<Out>,
+{
+    fn block_name(&self) -> &str {
+        "FilterMap"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (i, tags) = self.src.read_buf()?;
+        if i.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let mut o = self.dst.write_buf()?;
+        if o.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+
+        // Every input sample may be kept, so don't read more than fits.
+        let n = std::cmp::min(i.len(), o.len());
+        let mut otags = Vec::new();
+        let mut opos = 0;
+        let oslice = o.slice();
+        for (pos, sample) in i.iter().take(n).enumerate() {
+            let Some(v) = (self.map)(*sample) else {
+                continue;
+            };
+            otags.extend(
+                tags.iter()
+                    .filter(|t| t.pos() == pos)
+                    .map(|t| Tag::new(opos, t.key().into(), t.val().clone())),
+            );
+            oslice[opos] = v;
+            opos += 1;
+        }
+        i.consume(n);
+        o.produce(opos, &otags);
+        Ok(BlockRet::Ok)
+    }
+}
+
+/// Real part of complex numbers.
+#[derive(Block)]
+pub struct ComplexToReal {
+    #[rustradio(input)]
+    src: Streamp<Complex>,
+    #[rustradio(output)]
+    dst: Streamp<Float>,
+}
+
+impl ComplexToReal {
+    /// Create new block.
+    pub fn new(src: Streamp<Complex>) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+        }
+    }
+
+    fn process_one(&self, x: Complex) -> Float {
+        x.re
+    }
+}
+
+/// Imaginary part of complex numbers.
+#[derive(Block)]
+pub struct ComplexToImag {
+    #[rustradio(input)]
+    src: Streamp<Complex>,
+    #[rustradio(output)]
+    dst: Streamp<Float>,
+}
+
+impl ComplexToImag {
+    /// Create new block.
+    pub fn new(src: Streamp<Complex>) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+        }
+    }
+
+    fn process_one(&self, x: Complex) -> Float {
+        x.im
+    }
+}
+
+/// Magnitude of complex numbers.
+///
+/// For AM demodulation. If the square is good enough, like for power
+/// or comparisons, [`ComplexToMag2`][crate::complex_to_mag2::ComplexToMag2]
+/// is cheaper.
+#[derive(Block)]
+pub struct ComplexToMag {
+    #[rustradio(input)]
+    src: Streamp<Complex>,
+    #[rustradio(output)]
+    dst: Streamp<Float>,
+}
+
+impl ComplexToMag {
+    /// Create new block.
+    pub fn new(src: Streamp<Complex>) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+        }
+    }
+
+    fn process_one(&self, x: Complex) -> Float {
+        x.norm()
+    }
+}
+
+/// Phase angle of complex numbers, in radians from -π to π.
+#[derive(Block)]
+pub struct ComplexToArg {
+    #[rustradio(input)]
+    src: Streamp<Complex>,
+    #[rustradio(output)]
+    dst: Streamp<Float>,
+}
+
+impl ComplexToArg {
+    /// Create new block.
+    pub fn new(src: Streamp<Complex>) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+        }
+    }
+
+    fn process_one(&self, x: Complex) -> Float {
+        x.arg()
+    }
+}
+
 /// Convert floats to complex.
 pub struct FloatToComplex {
     re: Streamp<Float>,
@@ -163,3 +459,384 @@ impl Block for FloatToComplex {
         Ok(BlockRet::Ok)
     }
 }
+
+/// Convert bytes to floats, without scaling.
+#[derive(Block)]
+pub struct U8ToFloat {
+    #[rustradio(input)]
+    src: Streamp<u8>,
+    #[rustradio(output)]
+    dst: Streamp<Float>,
+}
+
+impl U8ToFloat {
+    /// Create new block.
+    pub fn new(src: Streamp<u8>) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+        }
+    }
+
+    fn process_one(&self, x: u8) -> Float {
+        x as Float
+    }
+}
+
+/// Convert 16 bit integers to floats, divided by `scale`.
+#[derive(Block)]
+pub struct I16ToFloat {
+    #[rustradio(input)]
+    src: Streamp<i16>,
+    #[rustradio(output)]
+    dst: Streamp<Float>,
+    scale: Float,
+}
+
+impl I16ToFloat {
+    /// Create new block. A scale of 32768 gives -1 to 1.
+    pub fn new(src: Streamp<i16>, scale: Float) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            scale,
+        }
+    }
+
+    fn process_one(&self, x: i16) -> Float {
+        x as Float / self.scale
+    }
+}
+
+/// Convert floats to 16 bit integers, multiplied by `scale`.
+#[derive(Block)]
+pub struct FloatToI16 {
+    #[rustradio(input)]
+    src: Streamp<Float>,
+    #[rustradio(output)]
+    dst: Streamp<i16>,
+    scale: Float,
+}
+
+impl FloatToI16 {
+    /// Create new block. A scale of 32767 maps -1 to 1 onto the full
+    /// range.
+    pub fn new(src: Streamp<Float>, scale: Float) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            scale,
+        }
+    }
+
+    fn process_one(&self, x: Float) -> i16 {
+        // `as` saturates.
+        (x * self.scale).round() as i16
+    }
+}
+
+/// Convert interleaved 8 bit I/Q, like from HackRF, to complex,
+/// divided by `scale`.
+#[derive(Block)]
+#[rustradio(decim = 2)]
+pub struct InterleavedI8ToComplex {
+    #[rustradio(input)]
+    src: Streamp<i8>,
+    #[rustradio(output)]
+    dst: Streamp<Complex>,
+    scale: Float,
+}
+
+impl InterleavedI8ToComplex {
+    /// Create new block. A scale of 128 gives -1 to 1.
+    pub fn new(src: Streamp<i8>, scale: Float) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            scale,
+        }
+    }
+
+    fn process_one(&self, s: &[i8]) -> Complex {
+        Complex::new(s[0] as Float, s[1] as Float) / self.scale
+    }
+}
+
+/// Convert interleaved 16 bit I/Q to complex, divided by `scale`.
+#[derive(Block)]
+#[rustradio(decim = 2)]
+pub struct InterleavedI16ToComplex {
+    #[rustradio(input)]
+    src: Streamp<i16>,
+    #[rustradio(output)]
+    dst: Streamp<Complex>,
+    scale: Float,
+}
+
+impl InterleavedI16ToComplex {
+    /// Create new block. A scale of 32768 gives -1 to 1.
+    pub fn new(src: Streamp<i16>, scale: Float) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            scale,
+        }
+    }
+
+    fn process_one(&self, s: &[i16]) -> Complex {
+        Complex::new(s[0] as Float, s[1] as Float) / self.scale
+    }
+}
+
+/// Convert complex to interleaved 16 bit I/Q, multiplied by `scale`.
+pub struct ComplexToInterleavedI16 {
+    src: Streamp<Complex>,
+    dst: Streamp<i16>,
+    scale: Float,
+}
+
+impl ComplexToInterleavedI16 {
+    /// Create new block. A scale of 32767 maps -1 to 1 onto the full
+    /// range.
+    pub fn new(src: Streamp<Complex>, scale: Float) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            scale,
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<i16> {
+        self.dst.clone()
+    }
+}
+
+impl Block for ComplexToInterleavedI16 {
+    fn block_name(&self) -> &str {
+        "ComplexToInterleavedI16"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (i, tags) = self.src.read_buf()?;
+        let mut o = self.dst.write_buf()?;
+        let n = std::cmp::min(i.len(), o.len() / 2);
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        let scale = self.scale;
+        o.fill_from_iter(
+            i.iter()
+                .take(n)
+                .flat_map(|c| [(c.re * scale).round() as i16, (c.im * scale).round() as i16]),
+        );
+        let tags: Vec<_> = tags
+            .into_iter()
+            .filter(|t| t.pos() < n)
+            .map(|t| t.with_pos(t.pos() * 2))
+            .collect();
+        i.consume(n);
+        o.produce(n * 2, &tags);
+        Ok(BlockRet::Ok)
+    }
+}
+
+/// Combine real and imaginary streams to complex. Same as
+/// [FloatToComplex].
+pub type RealImagToComplex = FloatToComplex;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::{streamp_from_slice, TagValue};
+    use crate::tests::{assert_almost_equal_complex, assert_almost_equal_float};
+
+    #[test]
+    fn decompose() -> Result<()> {
+        let input = [
+            Complex::new(3.0, 4.0),
+            Complex::new(0.0, -2.0),
+            Complex::new(-1.0, 0.0),
+        ];
+        let mut re = ComplexToReal::new(streamp_from_slice(&input));
+        let mut im = ComplexToImag::new(streamp_from_slice(&input));
+        let mut mag = ComplexToMag::new(streamp_from_slice(&input));
+        let mut arg = ComplexToArg::new(streamp_from_slice(&input));
+        re.work()?;
+        im.work()?;
+        mag.work()?;
+        arg.work()?;
+        assert_eq!(re.out().read_buf()?.0.slice(), &[3.0, 0.0, -1.0]);
+        assert_eq!(im.out().read_buf()?.0.slice(), &[4.0, -2.0, 0.0]);
+        assert_almost_equal_float(mag.out().read_buf()?.0.slice(), &[5.0, 2.0, 1.0]);
+        let pi = std::f64::consts::PI as Float;
+        assert_almost_equal_float(
+            arg.out().read_buf()?.0.slice(),
+            &[(4.0 as Float).atan2(3.0), -pi / 2.0, pi],
+        );
+
+        // And back again.
+        let mut c = RealImagToComplex::new(re.out(), im.out());
+        c.work()?;
+        assert_almost_equal_complex(c.out().read_buf()?.0.slice(), &input);
+        Ok(())
+    }
+
+    #[test]
+    fn ints() -> Result<()> {
+        let mut b = U8ToFloat::new(streamp_from_slice(&[0u8, 1, 255]));
+        b.work()?;
+        assert_eq!(b.out().read_buf()?.0.slice(), &[0.0, 1.0, 255.0]);
+
+        let mut b = I16ToFloat::new(streamp_from_slice(&[-32768i16, 0, 16384]), 32768.0);
+        b.work()?;
+        assert_eq!(b.out().read_buf()?.0.slice(), &[-1.0, 0.0, 0.5]);
+
+        let mut b = FloatToI16::new(streamp_from_slice(&[-2.0, -0.5, 0.0, 0.25, 2.0]), 32767.0);
+        b.work()?;
+        assert_eq!(
+            b.out().read_buf()?.0.slice(),
+            &[-32768, -16384, 0, 8192, 32767]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn interleaved() -> Result<()> {
+        let src = new_streamp();
+        {
+            let mut o = src.write_buf()?;
+            o.fill_from_slice(&[1i16, 2, 3, 4, 5]);
+            o.produce(5, &[Tag::new(2, "tag".into(), TagValue::Bool(true))]);
+        }
+        let mut b = InterleavedI16ToComplex::new(src.clone(), 2.0);
+        b.work()?;
+        let o = b.out();
+        let (res, tags) = o.read_buf()?;
+        assert_eq!(
+            res.slice(),
+            &[Complex::new(0.5, 1.0), Complex::new(1.5, 2.0)]
+        );
+        assert_eq!(tags, vec![Tag::new(1, "tag".into(), TagValue::Bool(true))]);
+        drop(res);
+        // Half a sample left.
+        assert_eq!(src.read_buf()?.0.slice(), &[5]);
+
+        let mut b = ComplexToInterleavedI16::new(o, 2.0);
+        b.work()?;
+        let o = b.out();
+        let (res, tags) = o.read_buf()?;
+        assert_eq!(res.slice(), &[1, 2, 3, 4]);
+        assert_eq!(tags, vec![Tag::new(2, "tag".into(), TagValue::Bool(true))]);
+
+        let mut b = InterleavedI8ToComplex::new(streamp_from_slice(&[-128i8, 127]), 128.0);
+        b.work()?;
+        assert_eq!(
+            b.out().read_buf()?.0.slice(),
+            &[Complex::new(-1.0, 127.0 / 128.0)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn from_file() -> Result<()> {
+        let tmpd = tempfile::tempdir()?;
+        let path = tmpd.path().join("capture.cs16");
+        let data: Vec<u8> = [100i16, -200, 300, -400]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        std::fs::write(&path, data)?;
+        let mut src = crate::file_source::FileSource::<i16>::new(path.to_str().unwrap(), false)?;
+        let mut b = InterleavedI16ToComplex::new(src.out(), 100.0);
+        src.work()?;
+        b.work()?;
+        assert_eq!(
+            b.out().read_buf()?.0.slice(),
+            &[Complex::new(1.0, -2.0), Complex::new(3.0, -4.0)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn map() -> Result<()> {
+        let src = streamp_from_slice(&[Complex::new(1.0, 2.0), Complex::new(0.0, -3.0)]);
+        let mut b = Map::new(src, |x: Complex| x.norm_sqr());
+        assert_eq!(b.block_name(), "Map");
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        assert_eq!(res.slice(), &[5.0, 9.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn map_state() -> Result<()> {
+        let src = streamp_from_slice(&[1u32, 2, 3, 4]);
+        let mut sum = 0;
+        let mut b = MapBuilder::new(src, move |x: u32| {
+            sum += x;
+            sum
+        })
+        .name("Sum".into())
+        .build();
+        assert_eq!(b.block_name(), "Sum");
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        assert_eq!(res.slice(), &[1, 3, 6, 10]);
+        Ok(())
+    }
+
+    #[test]
+    fn filter_map() -> Result<()> {
+        let src = new_streamp();
+        {
+            let mut o = src.write_buf()?;
+            o.fill_from_slice(b"1a23");
+            o.produce(
+                4,
+                &[
+                    Tag::new(1, "dropped".into(), TagValue::Bool(true)),
+                    Tag::new(2, "dropped".into(), TagValue::Bool(true)),
+                    Tag::new(3, "kept".into(), TagValue::Bool(true)),
+                ],
+            );
+        }
+        // Every other digit.
+        let mut n = 0;
+        let mut b = FilterMap::new(src, |x: u8| {
+            let d = (x as char).to_digit(10)?;
+            n += 1;
+            (n % 2 == 1).then_some(d as Float)
+        });
+        b.work()?;
+        let o = b.out();
+        let (res, tags) = o.read_buf()?;
+        assert_eq!(res.slice(), &[1.0, 3.0]);
+        assert_eq!(tags, vec![Tag::new(1, "kept".into(), TagValue::Bool(true))]);
+        Ok(())
+    }
+
+    #[test]
+    fn filter() -> Result<()> {
+        let src = new_streamp();
+        {
+            let mut o = src.write_buf()?;
+            o.fill_from_slice(&[1u8, 2, 3, 4, 5]);
+            o.produce(
+                5,
+                &[
+                    Tag::new(0, "dropped".into(), TagValue::Bool(true)),
+                    Tag::new(3, "kept".into(), TagValue::Bool(true)),
+                ],
+            );
+        }
+        let mut b = Filter::new(src, |x: &u8| x.is_multiple_of(2));
+        b.work()?;
+        let o = b.out();
+        let (res, tags) = o.read_buf()?;
+        assert_eq!(res.slice(), &[2, 4]);
+        assert_eq!(tags, vec![Tag::new(1, "kept".into(), TagValue::Bool(true))]);
+        Ok(())
+    }
+}
@@ -0,0 +1,651 @@
+/*! Live dashboard over WebSocket.
+
+[Dashboard] is a small HTTP and [WebSocket] server, so a browser can
+be the receiver's front panel. It serves a built-in page at `/`, and
+streams to every client connected to `/ws`:
+
+| Message                   | Content                                     |
+|---------------------------|---------------------------------------------|
+| `{"type":"spectrum",…}`   | Text. Sample rate, frequency range, number of bins, and if there's peak hold |
+| binary                    | Follows the spectrum header: little endian f32 dB values, averaged then (with peak hold) peak |
+| `{"type":"probes",…}`     | Text. Probe values, by name                 |
+| `{"type":"stats",…}`      | Text. [Stats][crate::graph::Stats] of the running graph |
+
+Probe values are set with a [DashboardHandle], either directly or with
+the [Probe] block, which passes a Float stream through and publishes
+its average.
+
+```no_run
+use rustradio::blocks::{ConstantSource, Dashboard, NullSink, Probe, SpectrumSink};
+use rustradio::graph::Graph;
+use rustradio::{Complex, Float};
+
+let mut g = Graph::new();
+let mut dash = Dashboard::new("[::]:8080")?;
+let src = Box::new(ConstantSource::new(Complex::new(1.0, 0.0)));
+let spectrum = Box::new(SpectrumSink::new(src.out(), 1_024_000.0, 1024));
+dash.set_spectrum(spectrum.handle());
+dash.set_stats(g.stats_handle());
+dash.handle().set_probe("frequency", 144_800_000);
+g.add(src);
+g.add(spectrum);
+g.add(Box::new(dash));
+g.run()?;
+# Ok::<(), anyhow::Error>(())
+```
+
+The server is minimal: no TLS, no authentication, and it ignores
+anything clients send other than close and ping. Put it behind a
+reverse proxy if it needs to be reachable from outside.
+
+[WebSocket]: https://www.rfc-editor.org/rfc/rfc6455
+*/
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use log::{debug, info};
+
+use crate::block::{Block, BlockRet};
+use crate::graph::StatsHandle;
+use crate::spectrum_sink::SpectrumHandle;
+use crate::stream::{new_streamp, Streamp};
+use crate::{Error, Float};
+
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(100);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_REQUEST: usize = 16384;
+
+const INDEX_HTML: &str = include_str!("dashboard.html");
+
+/// SHA-1, only used for the WebSocket handshake.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend((data.len() as u64 * 8).to_be_bytes());
+    for chunk in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, b) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes(b.try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*wi);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, t);
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+    let mut out = [0u8; 20];
+    for (o, v) in out.chunks_mut(4).zip(h) {
+        o.copy_from_slice(&v.to_be_bytes());
+    }
+    out
+}
+
+fn base64(data: &[u8]) -> String {
+    const T: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut s = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(T[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+/// Return the `Sec-WebSocket-Accept` value for a key.
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{key}{WS_GUID}").as_bytes()))
+}
+
+/// Encode a WebSocket frame, as sent by the server (not masked).
+pub fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut v = vec![0x80 | opcode];
+    match payload.len() {
+        n if n < 126 => v.push(n as u8),
+        n if n < 65536 => {
+            v.push(126);
+            v.extend((n as u16).to_be_bytes());
+        }
+        n => {
+            v.push(127);
+            v.extend((n as u64).to_be_bytes());
+        }
+    }
+    v.extend(payload);
+    v
+}
+
+/// Decode a WebSocket frame from the start of `buf`, unmasking if
+/// needed. Returns opcode, payload and the number of bytes used, or
+/// None if the frame isn't complete.
+pub fn decode_frame(buf: &[u8]) -> Option<(u8, Vec<u8>, usize)> {
+    let opcode = buf.first()? & 0x0f;
+    let b1 = *buf.get(1)?;
+    let mut pos = 2;
+    let len = match b1 & 0x7f {
+        126 => {
+            pos = 4;
+            u16::from_be_bytes(buf.get(2..4)?.try_into().ok()?) as usize
+        }
+        127 => {
+            pos = 10;
+            u64::from_be_bytes(buf.get(2..10)?.try_into().ok()?) as usize
+        }
+        n => n as usize,
+    };
+    let mask: Option<[u8; 4]> = if b1 & 0x80 != 0 {
+        let m = buf.get(pos..pos + 4)?.try_into().ok()?;
+        pos += 4;
+        Some(m)
+    } else {
+        None
+    };
+    let data = buf.get(pos..pos.checked_add(len)?)?;
+    let payload = match mask {
+        Some(m) => data
+            .iter()
+            .zip(m.iter().cycle())
+            .map(|(b, m)| b ^ m)
+            .collect(),
+        None => data.to_vec(),
+    };
+    Some((opcode, payload, pos + len))
+}
+
+/// Handle for setting probe values, from any thread.
+#[derive(Clone, Default)]
+pub struct DashboardHandle {
+    probes: Arc<Mutex<BTreeMap<String, serde_json::Value>>>,
+}
+
+impl DashboardHandle {
+    /// Set a probe value, shown on the dashboard.
+    pub fn set_probe<V: Into<serde_json::Value>>(&self, name: &str, value: V) {
+        self.probes
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), value.into());
+    }
+
+    /// Remove a probe.
+    pub fn remove_probe(&self, name: &str) {
+        self.probes.lock().unwrap().remove(name);
+    }
+}
+
+enum State {
+    Http,
+    WebSocket,
+}
+
+struct Client {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    state: State,
+}
+
+impl Client {
+    fn send(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.stream.set_nonblocking(false)?;
+        let ret = self.stream.write_all(data);
+        self.stream.set_nonblocking(true)?;
+        ret
+    }
+
+    // Handle a complete HTTP request. Returns false to close.
+    fn request(&mut self, html: &str) -> Result<bool> {
+        let Some(end) = self.buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+            if self.buf.len() > MAX_REQUEST {
+                return Err(Error::new("request too long").into());
+            }
+            return Ok(true);
+        };
+        let req = String::from_utf8_lossy(&self.buf[..end]).to_string();
+        self.buf.drain(..end + 4);
+        let mut lines = req.split("\r\n");
+        let path = lines
+            .next()
+            .and_then(|l| l.split(' ').nth(1))
+            .unwrap_or("")
+            .to_string();
+        let headers: BTreeMap<String, String> = lines
+            .filter_map(|l| l.split_once(':'))
+            .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+            .collect();
+        debug!("Dashboard: request for {path}");
+        match path.as_str() {
+            "/" => {
+                self.send(
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{html}",
+                        html.len()
+                    )
+                    .as_bytes(),
+                )?;
+                Ok(false)
+            }
+            "/ws" => {
+                let upgrade = headers
+                    .get("upgrade")
+                    .map(|v| v.eq_ignore_ascii_case("websocket"))
+                    .unwrap_or(false);
+                let Some(key) = headers.get("sec-websocket-key").filter(|_| upgrade) else {
+                    self.send(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")?;
+                    return Ok(false);
+                };
+                self.send(
+                    format!(
+                        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                        accept_key(key)
+                    )
+                    .as_bytes(),
+                )?;
+                info!("Dashboard: WebSocket client connected");
+                self.state = State::WebSocket;
+                Ok(true)
+            }
+            _ => {
+                self.send(
+                    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )?;
+                Ok(false)
+            }
+        }
+    }
+
+    // Handle frames from the client. Returns false to close.
+    fn frames(&mut self) -> Result<bool> {
+        while let Some((opcode, payload, used)) = decode_frame(&self.buf) {
+            self.buf.drain(..used);
+            match opcode {
+                OP_CLOSE => {
+                    let _ = self.send(&encode_frame(OP_CLOSE, &payload));
+                    return Ok(false);
+                }
+                OP_PING => self.send(&encode_frame(OP_PONG, &payload))?,
+                _ => {}
+            }
+        }
+        if self.buf.len() > MAX_REQUEST {
+            return Err(Error::new("client frame too long").into());
+        }
+        Ok(true)
+    }
+
+    // Read and handle what the client sent. Returns false to close.
+    fn poll(&mut self, html: &str) -> Result<bool> {
+        let mut tmp = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut tmp) {
+                Ok(0) => return Ok(false),
+                Ok(n) => self.buf.extend(&tmp[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if let State::Http = self.state {
+            if !self.request(html)? {
+                return Ok(false);
+            }
+        }
+        match self.state {
+            State::Http => Ok(true),
+            State::WebSocket => self.frames(),
+        }
+    }
+}
+
+/// Dashboard server block.
+pub struct Dashboard {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    html: String,
+    spectrum: Option<SpectrumHandle>,
+    spectrum_count: u64,
+    stats: Option<StatsHandle>,
+    handle: DashboardHandle,
+    interval: Duration,
+    last: Instant,
+}
+
+impl Dashboard {
+    /// Create new dashboard, listening on `addr`, e.g. `"[::]:8080"`.
+    pub fn new<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        info!("Dashboard: listening on http://{}/", listener.local_addr()?);
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+            html: INDEX_HTML.to_string(),
+            spectrum: None,
+            spectrum_count: 0,
+            stats: None,
+            handle: DashboardHandle::default(),
+            interval: DEFAULT_INTERVAL,
+            last: Instant::now(),
+        })
+    }
+
+    /// Address being listened on.
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Stream this spectrum.
+    pub fn set_spectrum(&mut self, h: SpectrumHandle) {
+        self.spectrum = Some(h);
+    }
+
+    /// Stream stats from this graph.
+    pub fn set_stats(&mut self, h: StatsHandle) {
+        self.stats = Some(h);
+    }
+
+    /// Set how often updates are sent. Default 100ms.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Replace the built-in page served at `/`.
+    pub fn set_html(&mut self, html: String) {
+        self.html = html;
+    }
+
+    /// Return a handle for setting probe values.
+    pub fn handle(&self) -> DashboardHandle {
+        self.handle.clone()
+    }
+
+    // Build the update frames.
+    fn updates(&mut self) -> Vec<Vec<u8>> {
+        let mut ret = Vec::new();
+        if let Some(h) = &self.spectrum {
+            if h.count() != self.spectrum_count {
+                let s = h.spectrum();
+                self.spectrum_count = s.count;
+                let header = serde_json::json!({
+                    "type": "spectrum",
+                    "samp_rate": s.samp_rate,
+                    "min_freq": s.min_freq(),
+                    "max_freq": s.max_freq(),
+                    "bins": s.avg.len(),
+                    "peak": !s.peak.is_empty(),
+                });
+                ret.push(encode_frame(OP_TEXT, header.to_string().as_bytes()));
+                let data: Vec<u8> = s
+                    .avg
+                    .iter()
+                    .chain(&s.peak)
+                    .flat_map(|v| (*v as f32).to_le_bytes())
+                    .collect();
+                ret.push(encode_frame(OP_BINARY, &data));
+            }
+        }
+        let probes = self.handle.probes.lock().unwrap().clone();
+        if !probes.is_empty() {
+            let msg = serde_json::json!({"type": "probes", "values": probes});
+            ret.push(encode_frame(OP_TEXT, msg.to_string().as_bytes()));
+        }
+        if let Some(h) = &self.stats {
+            let mut msg = serde_json::to_value(h.get()).unwrap_or_default();
+            msg["type"] = "stats".into();
+            ret.push(encode_frame(OP_TEXT, msg.to_string().as_bytes()));
+        }
+        ret
+    }
+}
+
+impl Block for Dashboard {
+    fn block_name(&self) -> &str {
+        "Dashboard"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    debug!("Dashboard: connection from {addr}");
+                    stream.set_nonblocking(true)?;
+                    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                    self.clients.push(Client {
+                        stream,
+                        buf: Vec::new(),
+                        state: State::Http,
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let html = &self.html;
+        self.clients.retain_mut(|c| match c.poll(html) {
+            Ok(keep) => keep,
+            Err(e) => {
+                debug!("Dashboard: dropping client: {e}");
+                false
+            }
+        });
+
+        let ws = self
+            .clients
+            .iter()
+            .any(|c| matches!(c.state, State::WebSocket));
+        if !ws || self.last.elapsed() < self.interval {
+            return Ok(BlockRet::Noop);
+        }
+        self.last = Instant::now();
+        let frames = self.updates();
+        self.clients.retain_mut(|c| {
+            if let State::Http = c.state {
+                return true;
+            }
+            match frames.iter().try_for_each(|f| c.send(f)) {
+                Ok(()) => true,
+                Err(e) => {
+                    info!("Dashboard: dropping client: {e}");
+                    false
+                }
+            }
+        });
+        Ok(BlockRet::Noop)
+    }
+}
+
+/** Publish the average of a Float stream as a dashboard probe.
+
+Passes the stream through unchanged.
+*/
+pub struct Probe {
+    src: Streamp<Float>,
+    dst: Streamp<Float>,
+    name: String,
+    handle: DashboardHandle,
+}
+
+impl Probe {
+    /// Create new Probe block.
+    pub fn new(src: Streamp<Float>, name: &str, handle: DashboardHandle) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            name: name.to_string(),
+            handle,
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Float> {
+        self.dst.clone()
+    }
+}
+
+impl Block for Probe {
+    fn block_name(&self) -> &str {
+        "Probe"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (input, tags) = self.src.read_buf()?;
+        let obind = self.dst.clone();
+        let mut o = obind.write_buf()?;
+        let n = std::cmp::min(input.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        let data = &input.slice()[..n];
+        o.fill_from_slice(data);
+        o.produce(n, &tags);
+        let avg = data.iter().sum::<Float>() / n as Float;
+        self.handle.set_probe(&self.name, avg);
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::VectorSource;
+    use crate::spectrum_sink::SpectrumSink;
+    use crate::Complex;
+
+    #[test]
+    fn handshake() {
+        // From RFC 6455.
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    #[test]
+    fn frames() {
+        for len in [0, 5, 200, 70000] {
+            let payload: Vec<u8> = (0..len).map(|n| n as u8).collect();
+            let f = encode_frame(OP_BINARY, &payload);
+            let (op, got, used) = decode_frame(&f).unwrap();
+            assert_eq!((op, used), (OP_BINARY, f.len()));
+            assert_eq!(got, payload);
+            assert!(decode_frame(&f[..f.len() - 1]).is_none() || len == 0);
+        }
+        // Masked "Hello", from RFC 6455.
+        let f = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        assert_eq!(decode_frame(&f).unwrap(), (OP_TEXT, b"Hello".to_vec(), 11));
+    }
+
+    // Call work() until the client has something to read.
+    fn read_some(dash: &mut Dashboard, client: &mut TcpStream, buf: &mut Vec<u8>) -> Result<()> {
+        client.set_nonblocking(true)?;
+        let mut tmp = [0u8; 65536];
+        for _ in 0..500 {
+            dash.work()?;
+            match client.read(&mut tmp) {
+                Ok(n) => {
+                    buf.extend(&tmp[..n]);
+                    return Ok(());
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("nothing received");
+    }
+
+    #[test]
+    fn serve() -> Result<()> {
+        let mut dash = Dashboard::new("127.0.0.1:0")?;
+        let mut client = TcpStream::connect(dash.local_addr()?)?;
+        client.write_all(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n")?;
+        let mut buf = Vec::new();
+        while !buf.ends_with(b"</html>\n") {
+            read_some(&mut dash, &mut client, &mut buf)?;
+        }
+        assert!(buf.starts_with(b"HTTP/1.1 200 OK\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn stream() -> Result<()> {
+        let mut src = VectorSource::new(vec![Complex::new(1.0, 0.0); 64]);
+        let mut spectrum = SpectrumSink::new(src.out(), 1000.0, 64);
+        src.work()?;
+        spectrum.work()?;
+
+        let mut dash = Dashboard::new("127.0.0.1:0")?;
+        dash.set_spectrum(spectrum.handle());
+        dash.set_interval(Duration::ZERO);
+        dash.handle().set_probe("snr", 12.5);
+        let mut client = TcpStream::connect(dash.local_addr()?)?;
+        client.write_all(
+            b"GET /ws HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+        )?;
+        let mut buf = Vec::new();
+        let mut msgs = Vec::new();
+        while msgs.len() < 3 {
+            read_some(&mut dash, &mut client, &mut buf)?;
+            if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                let resp = String::from_utf8_lossy(&buf[..end]).to_string();
+                assert!(resp.starts_with("HTTP/1.1 101"), "{resp}");
+                assert!(resp.contains("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="), "{resp}");
+                buf.drain(..end + 4);
+            }
+            while let Some((op, payload, used)) = decode_frame(&buf) {
+                buf.drain(..used);
+                msgs.push((op, payload));
+            }
+        }
+        let header: serde_json::Value = serde_json::from_slice(&msgs[0].1)?;
+        assert_eq!(header["type"], "spectrum");
+        assert_eq!(header["bins"], 64);
+        assert_eq!(msgs[1].0, OP_BINARY);
+        assert_eq!(msgs[1].1.len(), 64 * 4 * 2);
+        let probes: serde_json::Value = serde_json::from_slice(&msgs[2].1)?;
+        assert_eq!(probes["values"]["snr"], 12.5);
+        Ok(())
+    }
+}
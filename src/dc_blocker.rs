@@ -0,0 +1,120 @@
+/*! Remove DC offset.
+
+Cheap SDRs like the RTL-SDR have a spike at the center frequency,
+from DC offset in their I/Q samples. [DcBlocker] tracks the mean with
+a single pole IIR filter, and subtracts it:
+
+```text
+mean = mean + alpha * (x - mean)
+y = x - mean
+```
+
+Smaller `alpha` gives a narrower notch, but takes longer to settle. A
+time constant of about `1/alpha` samples.
+
+```
+use rustradio::blocks::{ConstantSource, DcBlocker};
+use rustradio::Complex;
+let src = ConstantSource::new(Complex::new(0.1, -0.05));
+let dc = DcBlocker::new(src.out(), 0.0001)?;
+# Ok::<(), rustradio::Error>(())
+```
+*/
+use crate::block::Block;
+use crate::stream::{new_streamp, Streamp};
+use crate::{Error, Float};
+
+/// Remove DC offset. See the [module docs][crate::dc_blocker].
+#[derive(Block)]
+pub struct DcBlocker<T>
+where
+    T: Copy
+        + Default
+        + std::ops::Add<Output = T>
+        + std::ops::Sub<Output = T>
+        + std::ops::Mul<Float, Output = T>,
+{
+    #[rustradio(input)]
+    src: Streamp<T>,
+    #[rustradio(output)]
+    dst: Streamp<T>,
+    alpha: Float,
+    mean: T,
+}
+
+impl<T> DcBlocker<T>
+where
+    T: Copy
+        + Default
+        + std::ops::Add<Output = T>
+        + std::ops::Sub<Output = T>
+        + std::ops::Mul<Float, Output = T>,
+{
+    /// Create new DcBlocker. `alpha` must be more than 0, and at most 1.
+    pub fn new(src: Streamp<T>, alpha: Float) -> Result<Self, Error> {
+        if !(alpha > 0.0 && alpha <= 1.0) {
+            return Err(Error::BadParameter(format!(
+                "DcBlocker alpha must be in (0, 1], got {alpha}"
+            )));
+        }
+        Ok(Self {
+            src,
+            dst: new_streamp(),
+            alpha,
+            mean: T::default(),
+        })
+    }
+
+    fn process_one(&mut self, x: T) -> T {
+        self.mean = self.mean + (x - self.mean) * self.alpha;
+        x - self.mean
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+    use crate::Complex;
+
+    #[test]
+    fn float() -> crate::Result<()> {
+        let input: Vec<Float> = (0..5000).map(|n| 0.5 + (n as Float * 0.3).sin()).collect();
+        let mut b = DcBlocker::new(streamp_from_slice(&input), 0.01)?;
+        assert_eq!(b.block_name(), "DcBlocker");
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        let tail = &res.slice()[4000..];
+        let mean = tail.iter().sum::<Float>() / tail.len() as Float;
+        assert!(mean.abs() < 0.01, "{mean}");
+        // The tone is still there.
+        let max = tail.iter().fold(0.0 as Float, |a, b| a.max(b.abs()));
+        assert!((max - 1.0).abs() < 0.05, "{max}");
+        Ok(())
+    }
+
+    #[test]
+    fn complex() -> crate::Result<()> {
+        let dc = Complex::new(0.2, -0.3);
+        let input: Vec<Complex> = (0..5000)
+            .map(|n| dc + Complex::from_polar(0.5, n as Float * 0.2))
+            .collect();
+        let mut b = DcBlocker::new(streamp_from_slice(&input), 0.01)?;
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        let tail = &res.slice()[4000..];
+        let mean = tail.iter().sum::<Complex>() / tail.len() as Float;
+        assert!(mean.norm() < 0.01, "{mean}");
+        Ok(())
+    }
+
+    #[test]
+    fn bad_alpha() {
+        let src = streamp_from_slice(&[0.0 as Float]);
+        assert!(DcBlocker::new(src.clone(), 0.0).is_err());
+        assert!(DcBlocker::new(src.clone(), 1.5).is_err());
+        assert!(DcBlocker::new(src, Float::NAN).is_err());
+    }
+}
@@ -0,0 +1,61 @@
+//! Divide two streams.
+use crate::block::Block;
+use crate::stream::{new_streamp, Streamp};
+
+/// Divides one stream by another, sample by sample.
+///
+/// Output is `a / b`. Division by zero is whatever `T` does: infinity
+/// or NaN for floats, and a panic for integers.
+#[derive(Block)]
+pub struct Divide<T>
+where
+    T: Copy + std::ops::Div<Output = T>,
+{
+    #[rustradio(input)]
+    a: Streamp<T>,
+    #[rustradio(input)]
+    b: Streamp<T>,
+    #[rustradio(output)]
+    dst: Streamp<T>,
+}
+
+impl<T> Divide<T>
+where
+    T: Copy + std::ops::Div<Output = T>,
+{
+    /// Create a new Divide block.
+    pub fn new(a: Streamp<T>, b: Streamp<T>) -> Self {
+        Self {
+            a,
+            b,
+            dst: new_streamp(),
+        }
+    }
+
+    fn process_one(&self, a: T, b: T) -> T {
+        a / b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockRet;
+    use crate::stream::streamp_from_slice;
+    use crate::Float;
+
+    #[test]
+    fn divide() -> crate::Result<()> {
+        let a = streamp_from_slice(&[10.0 as Float, 9.0, 1.0]);
+        let b = streamp_from_slice(&[2.0 as Float, 3.0, 0.0, 7.0]);
+        let mut div = Divide::new(a.clone(), b.clone());
+        assert_eq!(div.block_name(), "Divide");
+        div.work()?;
+        assert!(matches!(div.work()?, BlockRet::Noop));
+        let o = div.out();
+        let (res, _) = o.read_buf()?;
+        assert_eq!(res.slice(), &[5.0, 3.0, Float::INFINITY]);
+        assert_eq!(b.read_buf()?.0.slice(), &[7.0]);
+        Ok(())
+    }
+}
@@ -0,0 +1,527 @@
+/*! Satellite Doppler correction.
+
+A satellite in low earth orbit moves at about 7.5km/s, which shifts a
+137MHz downlink by up to ±3.5kHz, and a 435MHz one by ±10kHz, sweeping
+through zero during the pass. [Doppler] predicts the shift from the
+satellite's TLE and the observer location, using [SGP4][sgp4], and
+shifts the stream back, so that the downlink stays centered for the
+demodulator.
+
+```text
+   SDR -> Doppler -> demodulator
+             \_ on_update() -> rotator, retune
+```
+
+[DopplerBuilder::on_update] is called with the [Look] angles and
+Doppler shift on every update, e.g. to point a rotator. To retune the
+SDR instead of shifting in software, turn off the shifting with
+[DopplerBuilder::correct].
+
+The observer location can be changed while running, e.g. from a GPS,
+with [DopplerHandle::set_observer].
+
+Time is taken from the sample count, starting at the wall clock time
+of the first `work()` call, or at [DopplerBuilder::start_time] for
+recordings.
+
+```
+use rustradio::blocks::{ConstantSource, Doppler, NullSink};
+use rustradio::doppler::Observer;
+use rustradio::Complex;
+let tle = "ISS (ZARYA)
+1 25544U 98067A   08264.51782528 -.00002182  00000-0 -11606-4 0  2927
+2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391563537";
+let src = ConstantSource::new(Complex::new(0.0, 0.0));
+let doppler = Doppler::builder(
+    src.out(),
+    48_000.0,
+    tle,
+    145_800_000.0,
+    Observer::new(51.5, -0.1, 20.0),
+)?
+.on_update(|look| println!("az {:.0} el {:.0}", look.azimuth, look.elevation))
+.build();
+let sink = NullSink::new(doppler.out());
+# Ok::<(), rustradio::Error>(())
+```
+
+[sgp4]: https://en.wikipedia.org/wiki/Simplified_perturbations_models
+*/
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use log::debug;
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, Streamp, Tag, TagValue};
+use crate::{Complex, Error, Float};
+
+const SPEED_OF_LIGHT: f64 = 299_792.458; // km/s.
+const EARTH_ROTATION: f64 = 7.292_115_146_706_979e-5; // rad/s.
+const WGS84_A: f64 = 6378.137; // km.
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+
+// 2000-01-01 12:00 UTC, as used by SGP4 for epochs.
+const J2000_UNIX: f64 = 946_728_000.0;
+const SECONDS_PER_YEAR: f64 = 86400.0 * 365.25;
+
+const DEFAULT_UPDATE: Duration = Duration::from_millis(100);
+
+/// Tag key for the Doppler shift in Hz, added at every update.
+pub const TAG_DOPPLER: &str = "doppler";
+
+/// Observer location.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Observer {
+    /// Latitude in degrees, north positive.
+    pub lat: f64,
+
+    /// Longitude in degrees, east positive.
+    pub lon: f64,
+
+    /// Altitude above the WGS84 ellipsoid in meters.
+    pub alt: f64,
+}
+
+impl Observer {
+    /// Create new observer location.
+    pub fn new(lat: f64, lon: f64, alt: f64) -> Self {
+        Self { lat, lon, alt }
+    }
+
+    // Position in earth fixed coordinates, in km.
+    fn ecef(&self) -> [f64; 3] {
+        let (lat, lon) = (self.lat.to_radians(), self.lon.to_radians());
+        let alt = self.alt / 1000.0;
+        let e2 = WGS84_F * (2.0 - WGS84_F);
+        let n = WGS84_A / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+        [
+            (n + alt) * lat.cos() * lon.cos(),
+            (n + alt) * lat.cos() * lon.sin(),
+            (n * (1.0 - e2) + alt) * lat.sin(),
+        ]
+    }
+}
+
+/// Where a satellite is, as seen from the observer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Look {
+    /// Azimuth in degrees, clockwise from north.
+    pub azimuth: f64,
+
+    /// Elevation in degrees above the horizon. Negative when below.
+    pub elevation: f64,
+
+    /// Distance in km.
+    pub range: f64,
+
+    /// Rate of change of distance, in km/s. Positive when moving
+    /// away.
+    pub range_rate: f64,
+
+    /// Doppler shift of the downlink frequency, in Hz.
+    pub doppler: f64,
+
+    /// Downlink frequency as received, in Hz. What to tune to.
+    pub freq: f64,
+}
+
+/// A satellite, from its TLE.
+pub struct Satellite {
+    elements: sgp4::Elements,
+    constants: sgp4::Constants,
+}
+
+impl Satellite {
+    /// Parse a TLE. Either the two element lines, or a name line and
+    /// the two element lines.
+    pub fn from_tle(tle: &str) -> Result<Self, Error> {
+        let lines: Vec<&str> = tle
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect();
+        let (name, l1, l2) = match lines[..] {
+            [l1, l2] => (None, l1, l2),
+            [name, l1, l2] => (Some(name.to_string()), l1, l2),
+            _ => {
+                return Err(Error::BadParameter(format!(
+                    "TLE must be 2 or 3 lines, got {}",
+                    lines.len()
+                )))
+            }
+        };
+        let elements = sgp4::Elements::from_tle(name, l1.as_bytes(), l2.as_bytes())
+            .map_err(|e| Error::BadParameter(format!("bad TLE: {e}")))?;
+        let constants = sgp4::Constants::from_elements(&elements)
+            .map_err(|e| Error::BadParameter(format!("bad TLE elements: {e}")))?;
+        Ok(Self {
+            elements,
+            constants,
+        })
+    }
+
+    /// Satellite name, if the TLE had one.
+    pub fn name(&self) -> Option<&str> {
+        self.elements.object_name.as_deref()
+    }
+
+    /// Look angles and Doppler shift of `freq` at a time, given as
+    /// seconds since the unix epoch.
+    pub fn look(&self, observer: &Observer, unix_time: f64, freq: f64) -> Result<Look, Error> {
+        let years = (unix_time - J2000_UNIX) / SECONDS_PER_YEAR;
+        let minutes = (years - self.elements.epoch()) * SECONDS_PER_YEAR / 60.0;
+        let p = self
+            .constants
+            .propagate(sgp4::MinutesSinceEpoch(minutes))
+            .map_err(|e| Error::new(&format!("SGP4 propagation failed: {e}")))?;
+
+        // TEME to earth fixed, ignoring polar motion.
+        let theta = sgp4::iau_epoch_to_sidereal_time(years);
+        let (s, c) = theta.sin_cos();
+        let [x, y, z] = p.position;
+        let r = [c * x + s * y, -s * x + c * y, z];
+        let [vx, vy, vz] = p.velocity;
+        let v = [
+            c * vx + s * vy + EARTH_ROTATION * r[1],
+            -s * vx + c * vy - EARTH_ROTATION * r[0],
+            vz,
+        ];
+
+        let o = observer.ecef();
+        let d = [r[0] - o[0], r[1] - o[1], r[2] - o[2]];
+        let range = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+        let range_rate = (d[0] * v[0] + d[1] * v[1] + d[2] * v[2]) / range;
+
+        // East, north, up.
+        let (slat, clat) = observer.lat.to_radians().sin_cos();
+        let (slon, clon) = observer.lon.to_radians().sin_cos();
+        let e = -slon * d[0] + clon * d[1];
+        let n = -slat * clon * d[0] - slat * slon * d[1] + clat * d[2];
+        let u = clat * clon * d[0] + clat * slon * d[1] + slat * d[2];
+
+        let doppler = -freq * range_rate / SPEED_OF_LIGHT;
+        Ok(Look {
+            azimuth: e.atan2(n).to_degrees().rem_euclid(360.0),
+            elevation: (u / range).clamp(-1.0, 1.0).asin().to_degrees(),
+            range,
+            range_rate,
+            doppler,
+            freq: freq + doppler,
+        })
+    }
+}
+
+fn unix_time(t: SystemTime) -> f64 {
+    t.duration_since(SystemTime::UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs_f64()
+}
+
+#[derive(Default)]
+struct Shared {
+    observer: Option<Observer>,
+    look: Option<Look>,
+}
+
+/// Shared handle to a [Doppler] block.
+#[derive(Clone)]
+pub struct DopplerHandle {
+    inner: Arc<Mutex<Shared>>,
+}
+
+impl DopplerHandle {
+    /// Latest look angles and Doppler shift, or None before the first
+    /// update.
+    pub fn look(&self) -> Option<Look> {
+        self.inner.lock().unwrap().look.clone()
+    }
+
+    /// Change observer location, e.g. from a GPS. Used from the next
+    /// update.
+    pub fn set_observer(&self, observer: Observer) {
+        self.inner.lock().unwrap().observer = Some(observer);
+    }
+}
+
+type UpdateCallback = Box<dyn FnMut(&Look) + Send>;
+
+/// Builder for [Doppler].
+pub struct DopplerBuilder {
+    block: Doppler,
+}
+
+impl DopplerBuilder {
+    /// Create new builder.
+    ///
+    /// `freq` is the nominal downlink frequency in Hz, and `tle` the
+    /// satellite's two line elements, optionally with a name line
+    /// first.
+    pub fn new(
+        src: Streamp<Complex>,
+        samp_rate: Float,
+        tle: &str,
+        freq: f64,
+        observer: Observer,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            block: Doppler {
+                src,
+                dst: new_streamp(),
+                sat: Satellite::from_tle(tle)?,
+                samp_rate,
+                freq,
+                observer,
+                handle: DopplerHandle {
+                    inner: Arc::new(Mutex::new(Shared::default())),
+                },
+                start: None,
+                samples: 0,
+                update: (DEFAULT_UPDATE.as_secs_f64() * samp_rate as f64) as u64,
+                next_update: 0,
+                correct: true,
+                on_update: None,
+                phase: Complex::new(1.0, 0.0),
+                step: Complex::new(1.0, 0.0),
+            },
+        })
+    }
+
+    /// Time of the first sample. Default is the wall clock time of
+    /// the first `work()` call.
+    pub fn start_time(mut self, t: SystemTime) -> Self {
+        self.block.start = Some(t);
+        self
+    }
+
+    /// How often to recalculate the Doppler shift, in stream time.
+    /// Default 100ms.
+    pub fn update_interval(mut self, d: Duration) -> Self {
+        self.block.update = ((d.as_secs_f64() * self.block.samp_rate as f64) as u64).max(1);
+        self
+    }
+
+    /// Shift the stream to cancel the Doppler shift. Default true.
+    ///
+    /// Turn off if retuning the SDR from [DopplerBuilder::on_update]
+    /// instead.
+    pub fn correct(mut self, v: bool) -> Self {
+        self.block.correct = v;
+        self
+    }
+
+    /// Call `f` on every update, e.g. to point a rotator, or retune
+    /// the SDR to [Look::freq].
+    pub fn on_update(mut self, f: impl FnMut(&Look) + Send + 'static) -> Self {
+        self.block.on_update = Some(Box::new(f));
+        self
+    }
+
+    /// Build Doppler block.
+    pub fn build(self) -> Doppler {
+        self.block
+    }
+}
+
+/// Doppler correction block. See the [module docs](crate::doppler).
+pub struct Doppler {
+    src: Streamp<Complex>,
+    dst: Streamp<Complex>,
+    sat: Satellite,
+    samp_rate: Float,
+    freq: f64,
+    observer: Observer,
+    handle: DopplerHandle,
+    correct: bool,
+    on_update: Option<UpdateCallback>,
+
+    // Stream time.
+    start: Option<SystemTime>,
+    samples: u64,
+
+    // Samples between updates, and sample count of the next one.
+    update: u64,
+    next_update: u64,
+
+    // Oscillator for the correction.
+    phase: Complex,
+    step: Complex,
+}
+
+impl Doppler {
+    /// Create a builder.
+    pub fn builder(
+        src: Streamp<Complex>,
+        samp_rate: Float,
+        tle: &str,
+        freq: f64,
+        observer: Observer,
+    ) -> Result<DopplerBuilder, Error> {
+        DopplerBuilder::new(src, samp_rate, tle, freq, observer)
+    }
+
+    /// Get a handle, for reading the latest look angles or changing
+    /// the observer location.
+    pub fn handle(&self) -> DopplerHandle {
+        self.handle.clone()
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Complex> {
+        self.dst.clone()
+    }
+
+    fn update(&mut self) -> Result<Look, Error> {
+        if let Some(o) = self.handle.inner.lock().unwrap().observer.take() {
+            self.observer = o;
+        }
+        let start = *self.start.get_or_insert_with(SystemTime::now);
+        let t = unix_time(start) + self.samples as f64 / self.samp_rate as f64;
+        let look = self.sat.look(&self.observer, t, self.freq)?;
+        debug!("Doppler: {look:?}");
+        let w = -2.0 * std::f64::consts::PI * look.doppler / self.samp_rate as f64;
+        self.step = Complex::from_polar(1.0, w as Float);
+        // Keep rounding errors from growing the amplitude.
+        self.phase /= self.phase.norm();
+        if let Some(f) = &mut self.on_update {
+            f(&look);
+        }
+        self.handle.inner.lock().unwrap().look = Some(look.clone());
+        Ok(look)
+    }
+}
+
+impl Block for Doppler {
+    fn block_name(&self) -> &str {
+        "Doppler"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let obind = self.dst.clone();
+        let mut o = obind.write_buf()?;
+        let n = std::cmp::min(input.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::Ok);
+        }
+        let mut otags: Vec<Tag> = tags.into_iter().filter(|t| t.pos() < n).collect();
+        let out = o.slice();
+        for (i, (s, d)) in input.iter().zip(out.iter_mut()).take(n).enumerate() {
+            if self.samples == self.next_update {
+                let look = self.update()?;
+                otags.push(Tag::new(
+                    i,
+                    TAG_DOPPLER.into(),
+                    TagValue::Float(look.doppler as Float),
+                ));
+                self.next_update += self.update;
+            }
+            *d = if self.correct { s * self.phase } else { *s };
+            self.phase *= self.step;
+            self.samples += 1;
+        }
+        o.produce(n, &otags);
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    // ISS, from the sgp4 crate docs. Epoch 2008-09-20 12:25:40 UTC.
+    const TLE: &str = "ISS (ZARYA)
+1 25544U 98067A   08264.51782528 -.00002182  00000-0 -11606-4 0  2927
+2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391563537";
+    const EPOCH: f64 = 1_221_913_540.0;
+
+    #[test]
+    fn look() -> Result<(), Error> {
+        let sat = Satellite::from_tle(TLE)?;
+        assert_eq!(sat.name(), Some("ISS (ZARYA)"));
+        let freq = 145.8e6;
+        let t = EPOCH + 1000.0;
+
+        // Straight below the satellite.
+        // Find it by moving the observer towards the satellite.
+        let below = {
+            let mut o = Observer::new(0.0, 0.0, 0.0);
+            for _ in 0..500 {
+                let l = sat.look(&o, t, freq)?;
+                // Elevation changes much faster than the ground
+                // position, for a satellite this low.
+                let step = (90.0 - l.elevation) / 20.0;
+                let (s, c) = l.azimuth.to_radians().sin_cos();
+                o.lat += c * step;
+                o.lon += s * step / o.lat.to_radians().cos();
+            }
+            o
+        };
+        let l = sat.look(&below, t, freq)?;
+        assert!(l.elevation > 89.9, "{l:?}");
+        // ISS is about 350km up in 2008.
+        assert!((300.0..450.0).contains(&l.range), "{l:?}");
+
+        // From the side, the Doppler shift is the rate of change of
+        // the distance.
+        let obs = Observer::new(below.lat + 10.0, below.lon + 5.0, 100.0);
+        let l = sat.look(&obs, t, freq)?;
+        let l0 = sat.look(&obs, t - 0.5, freq)?;
+        let l1 = sat.look(&obs, t + 0.5, freq)?;
+        let rate = l1.range - l0.range;
+        assert!((l.range_rate - rate).abs() < 0.001, "{l:?} {rate}");
+        assert!(l.range_rate.abs() > 1.0, "{l:?}");
+        assert!(
+            (l.doppler + freq * rate / SPEED_OF_LIGHT).abs() < 1.0,
+            "{l:?}"
+        );
+        assert!((l.freq - freq - l.doppler).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn bad_tle() {
+        assert!(Satellite::from_tle("hello").is_err());
+        assert!(Satellite::from_tle("1 foo\n2 bar").is_err());
+    }
+
+    #[test]
+    fn block() -> Result<(), Error> {
+        let samp_rate = 48_000.0;
+        let src = streamp_from_slice(&vec![Complex::new(1.0, 0.0); 10_000]);
+        let obs = Observer::new(40.0, -100.0, 0.0);
+        let looks = Arc::new(Mutex::new(Vec::new()));
+        let looks2 = looks.clone();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs_f64(EPOCH + 1200.0);
+        let mut b = Doppler::builder(src, samp_rate, TLE, 145.8e6, obs)?
+            .start_time(start)
+            .update_interval(Duration::from_millis(50))
+            .on_update(move |l| looks2.lock().unwrap().push(l.clone()))
+            .build();
+        b.work()?;
+        let looks = looks.lock().unwrap();
+        // 10000 samples is just over 4 updates at 2400 samples each.
+        assert_eq!(looks.len(), 5);
+        assert_eq!(b.handle().look().as_ref(), looks.last());
+
+        // DC is shifted to minus the Doppler shift.
+        let o = b.out();
+        let (res, tags) = o.read_buf()?;
+        assert_eq!(res.len(), 10_000);
+        let want = -2.0 * std::f64::consts::PI * looks[0].doppler / samp_rate as f64;
+        let got = (res[1] * res[0].conj()).arg() as f64;
+        assert!((got - want).abs() < 1e-4, "want {want} got {got}");
+        assert_eq!(tags.iter().filter(|t| t.key() == TAG_DOPPLER).count(), 5);
+        assert!(tags.contains(&Tag::new(
+            2400,
+            TAG_DOPPLER.into(),
+            TagValue::Float(looks[1].doppler as Float)
+        )));
+        Ok(())
+    }
+}
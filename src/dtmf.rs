@@ -0,0 +1,256 @@
+/*! DTMF decoder.
+
+[DTMF] is the tone dialing used by phones, and by radio repeaters for
+remote control. Each key is a pair of one low ("row") and one high
+("column") tone:
+
+```text
+          1209  1336  1477  1633
+    697     1     2     3     A
+    770     4     5     6     B
+    852     7     8     9     C
+    941     *     0     #     D
+```
+
+[DtmfDecode] runs a [ToneBank] over blocks of 25.6ms, and accepts a
+block as a key if:
+
+* Most of the energy is in one row and one column tone.
+* The column tone is at most 4dB stronger, and at most 8dB weaker,
+  than the row tone (the "twist").
+* The other row and column tones are at least 8dB weaker.
+
+A [DtmfEvent] is output when a key is released, if it was held for at
+least two blocks (about 50ms).
+
+[DTMF]: https://en.wikipedia.org/wiki/Dual-tone_multi-frequency_signaling
+*/
+use anyhow::Result;
+use log::{debug, info};
+
+use crate::block::{Block, BlockRet};
+use crate::goertzel::{ToneBank, ToneBlock};
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp, Streamp};
+use crate::{Error, Float};
+
+/// Row tone frequencies.
+pub const ROWS: [Float; 4] = [697.0, 770.0, 852.0, 941.0];
+
+/// Column tone frequencies.
+pub const COLS: [Float; 4] = [1209.0, 1336.0, 1477.0, 1633.0];
+
+const KEYS: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+// Block length in seconds.
+const BLOCK_SECONDS: Float = 0.0256;
+
+// Minimum fraction of block energy in the two tones.
+const MIN_FRACTION: Float = 0.5;
+
+// Twist limits, as column to row power ratio.
+const MAX_TWIST: Float = 2.512; // +4dB
+const MIN_TWIST: Float = 0.158; // -8dB
+
+// Other tones must be this much weaker (8dB).
+const MIN_DOMINANCE: Float = 6.3;
+
+// Blocks with lower mean square are silence.
+const MIN_ENERGY: Float = 1e-6;
+
+// Blocks a key must be held.
+const MIN_BLOCKS: usize = 2;
+
+/// A DTMF key press.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DtmfEvent {
+    /// Key: 0-9, A-D, * or #.
+    pub digit: char,
+
+    /// How long the key was held, in seconds.
+    pub duration: Float,
+}
+
+/// Return which key a block is, if any.
+pub fn detect(b: &ToneBlock) -> Option<char> {
+    if b.energy < MIN_ENERGY {
+        return None;
+    }
+    let (r, rp) = b.strongest(0..4);
+    let (c, cp) = b.strongest(4..8);
+    let twist = cp / rp;
+    if rp + cp < MIN_FRACTION || !(MIN_TWIST..=MAX_TWIST).contains(&twist) {
+        return None;
+    }
+    if !b.dominates(0..4, r, MIN_DOMINANCE) || !b.dominates(4..8, c, MIN_DOMINANCE) {
+        return None;
+    }
+    Some(KEYS[r][c - 4])
+}
+
+/** DTMF decoder block.
+
+Input is audio. Output is one event per key press.
+*/
+pub struct DtmfDecode {
+    src: Streamp<Float>,
+    dst: NoCopyStreamp<DtmfEvent>,
+    bank: ToneBank,
+    block_seconds: Float,
+    current: Option<(char, usize)>,
+    events: usize,
+}
+
+impl DtmfDecode {
+    /// Create new DTMF decoder.
+    pub fn new(src: Streamp<Float>, samp_rate: Float) -> Self {
+        let block = (samp_rate * BLOCK_SECONDS).round() as usize;
+        let freqs: Vec<Float> = ROWS.iter().chain(COLS.iter()).copied().collect();
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            bank: ToneBank::new(samp_rate, &freqs, block),
+            block_seconds: block as Float / samp_rate,
+            current: None,
+            events: 0,
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<DtmfEvent> {
+        self.dst.clone()
+    }
+
+    fn process_block(&mut self, b: &ToneBlock) -> Option<DtmfEvent> {
+        let key = detect(b);
+        match self.current {
+            Some((k, n)) if Some(k) == key => {
+                self.current = Some((k, n + 1));
+                None
+            }
+            prev => {
+                self.current = key.map(|k| (k, 1));
+                let (k, n) = prev?;
+                (n >= MIN_BLOCKS).then_some(DtmfEvent {
+                    digit: k,
+                    duration: n as Float * self.block_seconds,
+                })
+            }
+        }
+    }
+}
+
+impl Drop for DtmfDecode {
+    fn drop(&mut self) {
+        info!("DtmfDecode: {} keys", self.events);
+    }
+}
+
+impl Block for DtmfDecode {
+    fn block_name(&self) -> &str {
+        "DtmfDecode"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        for x in input.iter() {
+            let Some(b) = self.bank.push(*x) else {
+                continue;
+            };
+            if let Some(ev) = self.process_block(&b) {
+                debug!("DtmfDecode: {} for {:.3}s", ev.digit, ev.duration);
+                self.events += 1;
+                self.dst.push(ev, &[]);
+            }
+        }
+        let n = input.len();
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    const SAMP_RATE: Float = 8000.0;
+
+    fn key(k: char) -> (Float, Float) {
+        for (r, row) in KEYS.iter().enumerate() {
+            if let Some(c) = row.iter().position(|x| *x == k) {
+                return (ROWS[r], COLS[c]);
+            }
+        }
+        panic!("bad key {k}");
+    }
+
+    fn dial(keys: &str, on: Float, off: Float, col_gain: Float) -> Vec<Float> {
+        let mut out = Vec::new();
+        let mut x: u32 = 1;
+        let mut noise = move || {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            ((x >> 16) % 1000) as Float / 1000.0 - 0.5
+        };
+        for k in keys.chars() {
+            let (fr, fc) = key(k);
+            for i in 0..(on * SAMP_RATE) as usize {
+                let t = i as Float / SAMP_RATE;
+                let w = 2.0 * std::f64::consts::PI as Float * t;
+                out.push(0.2 * (w * fr).sin() + 0.2 * col_gain * (w * fc).sin() + 0.02 * noise());
+            }
+            for _ in 0..(off * SAMP_RATE) as usize {
+                out.push(0.02 * noise());
+            }
+        }
+        out
+    }
+
+    fn decode(samples: &[Float]) -> Result<Vec<DtmfEvent>> {
+        let mut b = DtmfDecode::new(streamp_from_slice(samples), SAMP_RATE);
+        b.work()?;
+        let out = b.out();
+        let mut ret = Vec::new();
+        while let Some((e, _)) = out.pop() {
+            ret.push(e);
+        }
+        Ok(ret)
+    }
+
+    #[test]
+    fn all_keys() -> Result<()> {
+        let keys = "123A456B789C*0#D";
+        let got = decode(&dial(keys, 0.08, 0.06, 1.0))?;
+        let digits: String = got.iter().map(|e| e.digit).collect();
+        assert_eq!(digits, keys);
+        for e in got {
+            assert!(e.duration > 0.05 && e.duration < 0.11, "{e:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn repeated_key() -> Result<()> {
+        let got = decode(&dial("55", 0.1, 0.05, 1.0))?;
+        assert_eq!(got.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects() -> Result<()> {
+        // Too short.
+        assert!(decode(&dial("1", 0.03, 0.1, 1.0))?.is_empty());
+        // Too much twist.
+        assert!(decode(&dial("1", 0.1, 0.1, 2.5))?.is_empty());
+        // Within twist limits.
+        assert_eq!(decode(&dial("1", 0.1, 0.1, 1.4))?.len(), 1);
+        Ok(())
+    }
+}
@@ -25,7 +25,7 @@ let sink = Box::new(NullSink::new(fft.out()));
 
 ## Further reading:
 * <https://en.wikipedia.org/wiki/Fast_Fourier_transform>
-* <https://en.wikipedia.org/wiki/Overlap%E2%80%93add_method>
+* <https://en.wikipedia.org/wiki/Overlap%E2%80%93save_method>
 */
 use std::sync::Arc;
 
@@ -34,38 +34,39 @@ use log::trace;
 use rustfft::FftPlanner;
 
 use crate::block::{Block, BlockRet};
-use crate::stream::{new_streamp, Streamp};
+use crate::kernels;
+use crate::stream::{new_streamp, Streamp, Tag};
 use crate::{Complex, Error, Float};
 
-/// FFT filter. Like a FIR filter, but more efficient when there are many taps.
-pub struct FftFilter {
-    buf: Vec<Complex>,
-    taps_fft: Vec<Complex>,
-    nsamples: usize,
-    fft_size: usize,
-    tail: Vec<Complex>,
+/// Overlap-save FFT convolution.
+///
+/// Everything is allocated at construction, so filtering a segment
+/// doesn't allocate.
+struct OverlapSave {
     fft: Arc<dyn rustfft::Fft<Float>>,
     ifft: Arc<dyn rustfft::Fft<Float>>,
-    src: Streamp<Complex>,
-    dst: Streamp<Complex>,
-}
+    taps_fft: Vec<Complex>,
 
-impl FftFilter {
-    fn calc_fft_size(from: usize) -> usize {
-        let mut n = 1;
-        while n < from {
-            n <<= 1;
-        }
-        2 * n
-    }
+    // Number of samples kept from the previous segment.
+    history: usize,
 
-    /// Create new FftFilter, given filter taps.
-    pub fn new(src: Streamp<Complex>, taps: &[Complex]) -> Self {
-        // Set up FFT / batch size.
+    // Number of new samples per segment.
+    nsamples: usize,
+
+    // Input. The history, followed by the new samples.
+    buf: Vec<Complex>,
+
+    // FFT of buf is done in here, since buf needs to keep the input.
+    work: Vec<Complex>,
+    scratch: Vec<Complex>,
+}
+
+impl OverlapSave {
+    fn new(taps: &[Complex]) -> Self {
         let fft_size = Self::calc_fft_size(taps.len());
-        let nsamples = fft_size - taps.len();
+        let history = taps.len().saturating_sub(1);
 
-        // Create FFT planners.
+        // Create FFT plans.
         let mut planner = FftPlanner::new();
         let fft = planner.plan_fft_forward(fft_size);
         let ifft = planner.plan_fft_inverse(fft_size);
@@ -82,17 +83,73 @@ impl FftFilter {
             let f = 1.0 / taps_fft.len() as Float;
             taps_fft.iter_mut().for_each(|s: &mut Complex| *s *= f);
         }
+        let scratch_len = std::cmp::max(
+            fft.get_inplace_scratch_len(),
+            ifft.get_inplace_scratch_len(),
+        );
+        Self {
+            fft,
+            ifft,
+            taps_fft,
+            history,
+            nsamples: fft_size - history,
+            buf: vec![Complex::default(); fft_size],
+            work: vec![Complex::default(); fft_size],
+            scratch: vec![Complex::default(); scratch_len],
+        }
+    }
 
+    fn calc_fft_size(from: usize) -> usize {
+        let mut n = 1;
+        while n < from {
+            n <<= 1;
+        }
+        2 * n
+    }
+
+    /// Where to put the next `nsamples` input samples.
+    fn input(&mut self) -> &mut [Complex] {
+        &mut self.buf[self.history..]
+    }
+
+    /// Filter the samples in `input()`, returning `nsamples` output
+    /// samples.
+    fn run(&mut self) -> &[Complex] {
+        self.work.copy_from_slice(&self.buf);
+        self.fft
+            .process_with_scratch(&mut self.work, &mut self.scratch);
+        kernels::multiply(&mut self.work, &self.taps_fft);
+        self.ifft
+            .process_with_scratch(&mut self.work, &mut self.scratch);
+
+        // Keep the end of the input as history for the next segment.
+        self.buf.copy_within(self.nsamples.., 0);
+
+        // The first `history` samples wrapped around, and are garbage.
+        &self.work[self.history..]
+    }
+}
+
+// Tags in the segment just filtered. Output positions are the same as
+// input positions.
+fn segment_tags(tags: Vec<Tag>, n: usize) -> Vec<Tag> {
+    tags.into_iter().filter(|t| t.pos() < n).collect()
+}
+
+/// FFT filter. Like a FIR filter, but more efficient when there are many taps.
+pub struct FftFilter {
+    os: OverlapSave,
+    src: Streamp<Complex>,
+    dst: Streamp<Complex>,
+}
+
+impl FftFilter {
+    /// Create new FftFilter, given filter taps.
+    pub fn new(src: Streamp<Complex>, taps: &[Complex]) -> Self {
         Self {
             src,
             dst: new_streamp(),
-            fft_size,
-            taps_fft,
-            tail: vec![Complex::default(); taps.len()],
-            fft,
-            ifft,
-            buf: Vec::with_capacity(fft_size),
-            nsamples,
+            os: OverlapSave::new(taps),
         }
     }
     /// Return the output stream.
@@ -106,78 +163,23 @@ impl Block for FftFilter {
         "FftFilter"
     }
     fn work(&mut self) -> Result<BlockRet, Error> {
+        let n = self.os.nsamples;
         let mut produced = false;
         loop {
-            let (input, _tags) = self.src.read_buf()?;
+            let (input, tags) = self.src.read_buf()?;
             let mut o = self.dst.write_buf()?;
-
-            if self.nsamples > o.len() {
-                trace!(
-                    "FftFilter: Need {} output space, only have {}",
-                    self.nsamples,
-                    o.len()
-                );
+            if n > o.len() {
+                trace!("FftFilter: Need {} output space, only have {}", n, o.len());
                 break;
             }
-            // Read so that self.buf contains exactly self.nsamples samples.
-            //
-            // Yes, this part is weird. It evolved into this, but any
-            // cleanup I do to the next few lines just made it slower,
-            // even though I removed needless logic.
-            //
-            // E.g.:
-            // * self.buf.len() is *always* empty here, so that's a
-            //   needless subtraction.
-            // * We break if input available less than nsamples, so
-            //   why not just compare that?
-            // * Why do we even have self.buf? It's cleared on every round.
-            //   (well, that means no heap allocation, sure)
-            //
-            // Why are these things not fixed: Because then it's
-            // slower, for some reason. At least as of 2023-10-07, on
-            // amd64, with Rust 1.7.1.
-            let add = std::cmp::min(input.len(), self.nsamples - self.buf.len());
-            if add < self.nsamples {
+            if n > input.len() {
                 break;
             }
-            self.buf.extend(input.iter().take(add).copied());
-            input.consume(add);
-
-            // Run FFT.
-            self.buf.resize(self.fft_size, Complex::default());
-            self.fft.process(&mut self.buf);
-
-            // Filter by array multiplication.
-            //
-            // TODO: check if this can be done faster by using volk.
-            let mut filtered: Vec<Complex> = self
-                .buf
-                .iter()
-                .zip(self.taps_fft.iter())
-                .map(|(x, y)| x * y)
-                .collect::<Vec<Complex>>();
-
-            // IFFT back to the time domain.
-            self.ifft.process(&mut filtered);
-
-            // Add overlapping tail.
-            for (i, t) in self.tail.iter().enumerate() {
-                filtered[i] += t;
-            }
-
-            // Output.
-            // TODO: needless copy.
-            o.fill_from_slice(&filtered[..self.nsamples]);
-            o.produce(self.nsamples, &[]);
+            self.os.input().copy_from_slice(&input.slice()[..n]);
+            o.fill_from_slice(self.os.run());
+            o.produce(n, &segment_tags(tags, n));
+            input.consume(n);
             produced = true;
-
-            // Stash tail.
-            for i in 0..self.tail.len() {
-                self.tail[i] = filtered[self.nsamples + i];
-            }
-
-            // Clear buffer. Per above performance comment.
-            self.buf.clear();
         }
         if produced {
             Ok(BlockRet::Ok)
@@ -191,31 +193,22 @@ impl Block for FftFilter {
 ///
 /// Works just like [FftFilter], but for Float input, output, and taps.
 ///
-/// In fact, the current implementation of FftFilterFloat is just
-/// FftFilter hiding under a trenchcoat. Counter intuitively
-/// therefore, this Float version of the FftFilter has a little worse
-/// performance than the Complex filter.
+/// The filtering is still done on complex numbers, so it's not
+/// faster than FftFilter.
 pub struct FftFilterFloat {
-    complex: FftFilter,
+    os: OverlapSave,
     src: Streamp<Float>,
     dst: Streamp<Float>,
-    inner_in: Streamp<Complex>,
-    inner_out: Streamp<Complex>,
 }
 
 impl FftFilterFloat {
     /// Create a new FftFilterFloat block.
     pub fn new(src: Streamp<Float>, taps: &[Float]) -> Self {
         let ctaps: Vec<Complex> = taps.iter().copied().map(|f| Complex::new(f, 0.0)).collect();
-        let inner_in = new_streamp();
-        let complex = FftFilter::new(inner_in.clone(), &ctaps);
-        let inner_out = complex.out();
         Self {
             src,
             dst: new_streamp(),
-            complex,
-            inner_in,
-            inner_out,
+            os: OverlapSave::new(&ctaps),
         }
     }
     /// Return the output stream.
@@ -229,33 +222,25 @@ impl Block for FftFilterFloat {
         "FftFilterFloat"
     }
     fn work(&mut self) -> Result<BlockRet, Error> {
-        // Convert input to Complex.
-        {
-            let (outer_in, tags) = self.src.read_buf()?;
-            let mut inner_to = self.inner_in.write_buf()?;
-            let n = std::cmp::min(outer_in.len(), inner_to.len());
-            for (i, samp) in outer_in.iter().take(n).enumerate() {
-                inner_to.slice()[i] = Complex::new(*samp, 0.0);
+        let n = self.os.nsamples;
+        let mut produced = false;
+        loop {
+            let (input, tags) = self.src.read_buf()?;
+            let mut o = self.dst.write_buf()?;
+            if n > input.len() || n > o.len() {
+                break;
             }
-            inner_to.produce(n, &tags);
-            outer_in.consume(n);
+            kernels::float_to_complex(&input.slice()[..n], self.os.input());
+            kernels::complex_to_real(self.os.run(), o.slice());
+            o.produce(n, &segment_tags(tags, n));
+            input.consume(n);
+            produced = true;
         }
-
-        // Run Complex FftFilter.
-        let ret = self.complex.work()?;
-
-        // Replicate stream write.
-        {
-            let (inner_from, tags) = self.inner_out.read_buf()?;
-            let mut outer_to = self.dst.write_buf()?;
-            let n = std::cmp::min(inner_from.len(), outer_to.len());
-            for (i, samp) in inner_from.iter().take(n).enumerate() {
-                outer_to.slice()[i] = samp.re;
-            }
-            inner_from.consume(n);
-            outer_to.produce(n, &tags);
+        if produced {
+            Ok(BlockRet::Ok)
+        } else {
+            Ok(BlockRet::Noop)
         }
-        Ok(ret)
     }
 }
 
@@ -335,6 +320,42 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn same_as_fir() -> Result<()> {
+        use crate::fir::FIR;
+        use crate::stream::TagValue;
+        use crate::tests::assert_almost_equal_float;
+
+        let taps = [0.1, -0.3, 0.5, 0.2, 0.05];
+        let input: Vec<Float> = (0..100).map(|n| ((n * 7) % 13) as Float - 6.0).collect();
+        let want = {
+            let mut padded = vec![0.0; taps.len() - 1];
+            padded.extend(&input);
+            FIR::new(&taps).filter_n(&padded)
+        };
+
+        let src = new_streamp();
+        let mut b = FftFilterFloat::new(src.clone(), &taps);
+        // Write in small chunks, so that segments span writes.
+        for (i, chunk) in input.chunks(7).enumerate() {
+            let mut w = src.write_buf()?;
+            w.fill_from_slice(chunk);
+            w.produce(
+                chunk.len(),
+                &[Tag::new(0, "chunk".into(), TagValue::U64(i as u64))],
+            );
+            b.work()?;
+        }
+        let o = b.out();
+        let (res, tags) = o.read_buf()?;
+        // 16 point FFT, minus 4 history, is 12 samples per segment.
+        assert_eq!(res.len(), 96);
+        assert_almost_equal_float(res.slice(), &want[..96]);
+        assert_eq!(tags.len(), 96 / 7 + 1);
+        assert!(tags.contains(&Tag::new(91, "chunk".into(), TagValue::U64(13))));
+        Ok(())
+    }
+
     #[allow(dead_code)]
     fn write_vec(filename: &str, v: &[Complex]) -> Result<()> {
         use std::io::BufWriter;
@@ -129,7 +129,8 @@ where
     }
 }
 
-#[cfg(test)]
+// Checks the f32 layout.
+#[cfg(all(test, not(feature = "f64")))]
 mod tests {
     use super::*;
     use crate::stream::streamp_from_slice;
@@ -100,7 +100,8 @@ where
     }
 }
 
-#[cfg(test)]
+// Checks the f32 layout.
+#[cfg(all(test, not(feature = "f64")))]
 mod tests {
     use super::*;
     use crate::{Complex, Float};
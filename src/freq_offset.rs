@@ -0,0 +1,377 @@
+/*! Carrier frequency offset estimator.
+
+Cheap SDR dongles are often tens of PPM off, which at 433MHz is over
+10kHz. [FreqOffset] passes a stream through unchanged, and estimates
+how far from center the strongest carrier is, so that the receiver can
+report or correct the error.
+
+```text
+   Source -> FreqOffset -> …
+                 \_ FreqOffsetHandle -> reading()
+                 \_ estimates() -> Float stream of offsets in Hz
+```
+
+The estimate is the peak of an averaged FFT, interpolated between
+bins. For modulated signals without a carrier, raising the signal to
+the power of the modulation order first removes the modulation, e.g.
+order 2 for BPSK, and 4 for QPSK. See [FreqOffsetBuilder::order].
+
+With the center frequency set, the offset is also given in PPM. If the
+signal is known to be exactly on frequency, e.g. a calibration
+transmitter, then that's the tuning error of the SDR.
+
+```
+use rustradio::blocks::{ConstantSource, FreqOffset, NullSink};
+use rustradio::Complex;
+let src = ConstantSource::new(Complex::new(1.0, 0.0));
+let fo = FreqOffset::builder(src.out(), 1_024_000.0)
+    .center_freq(433_920_000.0)
+    .build();
+let handle = fo.handle();
+let sink = NullSink::new(fo.out());
+// Run graph, then:
+if let Some(r) = handle.reading() {
+    println!("Off by {:.0} Hz", r.hz);
+}
+```
+*/
+use std::sync::{Arc, Mutex};
+
+use log::debug;
+use rustfft::{Fft, FftPlanner};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, Streamp};
+use crate::{Complex, Error, Float};
+
+const DEFAULT_FFT_SIZE: usize = 4096;
+const DEFAULT_AVERAGES: usize = 8;
+
+/// A frequency offset estimate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreqOffsetReading {
+    /// Offset from center, in Hz.
+    pub hz: Float,
+
+    /// Offset relative to center frequency, in parts per million, if
+    /// center frequency is set.
+    pub ppm: Option<Float>,
+
+    /// Peak power above the average of the spectrum, in dB. Low
+    /// values mean the estimate is probably just noise.
+    pub snr: Float,
+}
+
+/// Shared handle to the readings of a [FreqOffset].
+#[derive(Clone, Default)]
+pub struct FreqOffsetHandle {
+    inner: Arc<Mutex<Option<FreqOffsetReading>>>,
+}
+
+impl FreqOffsetHandle {
+    /// Latest estimate, or None before the first one is done.
+    pub fn reading(&self) -> Option<FreqOffsetReading> {
+        self.inner.lock().unwrap().clone()
+    }
+}
+
+/// Builder for [FreqOffset].
+pub struct FreqOffsetBuilder {
+    src: Streamp<Complex>,
+    samp_rate: Float,
+    fft_size: usize,
+    averages: usize,
+    order: u32,
+    center_freq: Option<Float>,
+}
+
+impl FreqOffsetBuilder {
+    /// Create new builder.
+    pub fn new(src: Streamp<Complex>, samp_rate: Float) -> Self {
+        Self {
+            src,
+            samp_rate,
+            fft_size: DEFAULT_FFT_SIZE,
+            averages: DEFAULT_AVERAGES,
+            order: 1,
+            center_freq: None,
+        }
+    }
+
+    /// Set FFT size. Default 4096.
+    ///
+    /// Larger sizes give finer resolution, but take longer to fill.
+    pub fn fft_size(mut self, n: usize) -> Self {
+        self.fft_size = n;
+        self
+    }
+
+    /// Set number of FFTs averaged per estimate. Default 8.
+    pub fn averages(mut self, n: usize) -> Self {
+        self.averages = n;
+        self
+    }
+
+    /// Raise the signal to this power before the FFT. Default 1.
+    ///
+    /// Use the modulation order for PSK signals, which have no
+    /// carrier to find. This also multiplies the noise, and divides
+    /// the range of offsets that can be measured.
+    pub fn order(mut self, m: u32) -> Self {
+        self.order = m;
+        self
+    }
+
+    /// Set center frequency, for getting the offset in PPM.
+    pub fn center_freq(mut self, f: Float) -> Self {
+        self.center_freq = Some(f);
+        self
+    }
+
+    /// Build FreqOffset block.
+    pub fn build(self) -> FreqOffset {
+        let fft_size = std::cmp::max(self.fft_size, 4);
+        let fft = FftPlanner::new().plan_fft_forward(fft_size);
+        let pi = std::f64::consts::PI as Float;
+        FreqOffset {
+            window: (0..fft_size)
+                .map(|n| 0.5 - 0.5 * (2.0 * pi * n as Float / fft_size as Float).cos())
+                .collect(),
+            scratch: vec![Complex::default(); fft.get_inplace_scratch_len()],
+            buf: Vec::with_capacity(fft_size),
+            acc: vec![0.0; fft_size],
+            nacc: 0,
+            fft,
+            src: self.src,
+            dst: new_streamp(),
+            estimates: new_streamp(),
+            handle: FreqOffsetHandle::default(),
+            samp_rate: self.samp_rate,
+            fft_size,
+            averages: std::cmp::max(self.averages, 1),
+            order: std::cmp::max(self.order, 1),
+            center_freq: self.center_freq,
+        }
+    }
+}
+
+/** Frequency offset estimator block.
+
+Passes the stream through as is. See the [module
+docs](crate::freq_offset).
+*/
+pub struct FreqOffset {
+    src: Streamp<Complex>,
+    dst: Streamp<Complex>,
+    estimates: Streamp<Float>,
+    handle: FreqOffsetHandle,
+    fft: Arc<dyn Fft<Float>>,
+    samp_rate: Float,
+    fft_size: usize,
+    averages: usize,
+    order: u32,
+    center_freq: Option<Float>,
+
+    // Hann window.
+    window: Vec<Float>,
+    scratch: Vec<Complex>,
+
+    // Samples for the next FFT.
+    buf: Vec<Complex>,
+
+    // Summed power spectrum of `nacc` FFTs.
+    acc: Vec<Float>,
+    nacc: usize,
+}
+
+impl FreqOffset {
+    /// Create new FreqOffset block, with default settings.
+    pub fn new(src: Streamp<Complex>, samp_rate: Float) -> Self {
+        FreqOffsetBuilder::new(src, samp_rate).build()
+    }
+
+    /// Create a builder.
+    pub fn builder(src: Streamp<Complex>, samp_rate: Float) -> FreqOffsetBuilder {
+        FreqOffsetBuilder::new(src, samp_rate)
+    }
+
+    /// Get a handle to the readings.
+    pub fn handle(&self) -> FreqOffsetHandle {
+        self.handle.clone()
+    }
+
+    /// Return the output stream, the same as the input.
+    pub fn out(&self) -> Streamp<Complex> {
+        self.dst.clone()
+    }
+
+    /// Return the stream of estimates, in Hz. One per
+    /// `fft_size * averages` input samples.
+    pub fn estimates(&self) -> Streamp<Float> {
+        self.estimates.clone()
+    }
+
+    // Add the spectrum of `buf` to the average.
+    fn fft_done(&mut self) {
+        for (s, w) in self.buf.iter_mut().zip(&self.window) {
+            *s = s.powu(self.order) * w;
+        }
+        self.fft
+            .process_with_scratch(&mut self.buf, &mut self.scratch);
+        for (a, s) in self.acc.iter_mut().zip(&self.buf) {
+            *a += s.norm_sqr();
+        }
+        self.buf.clear();
+        self.nacc += 1;
+        if self.nacc >= self.averages {
+            self.estimate();
+        }
+    }
+
+    fn estimate(&mut self) {
+        let n = self.fft_size;
+        let (peak, _) = self
+            .acc
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap(); // unwrap: fft_size is never zero.
+
+        // Fit a parabola to the log power around the peak. For a Hann
+        // window that's a good approximation of the peak shape.
+        let at = |i: usize| self.acc[i % n].max(1e-30).ln();
+        let (a, b, c) = (at(peak + n - 1), at(peak), at(peak + 1));
+        let denom = a - 2.0 * b + c;
+        let delta = if denom.abs() > 1e-20 {
+            (0.5 * (a - c) / denom).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        let bin = if peak < n / 2 {
+            peak as Float
+        } else {
+            peak as Float - n as Float
+        } + delta;
+        let hz = bin * self.samp_rate / n as Float / self.order as Float;
+
+        let mean = self.acc.iter().sum::<Float>() / n as Float;
+        let snr = 10.0 * (self.acc[peak] / mean.max(1e-30)).log10();
+        let reading = FreqOffsetReading {
+            hz,
+            ppm: self.center_freq.map(|f| hz / f * 1e6),
+            snr,
+        };
+        debug!("FreqOffset: {reading:?}");
+        *self.handle.inner.lock().unwrap() = Some(reading);
+        if let Ok(mut o) = self.estimates.write_buf() {
+            if !o.is_empty() {
+                o.slice()[0] = hz;
+                o.produce(1, &[]);
+            }
+        }
+        self.acc.iter_mut().for_each(|a| *a = 0.0);
+        self.nacc = 0;
+    }
+}
+
+impl Block for FreqOffset {
+    fn block_name(&self) -> &str {
+        "FreqOffset"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, mut tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let obind = self.dst.clone();
+        let mut o = obind.write_buf()?;
+        let n = std::cmp::min(input.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::Ok);
+        }
+        let mut rest = &input.slice()[..n];
+        while !rest.is_empty() {
+            let take = std::cmp::min(rest.len(), self.fft_size - self.buf.len());
+            self.buf.extend_from_slice(&rest[..take]);
+            rest = &rest[take..];
+            if self.buf.len() == self.fft_size {
+                self.fft_done();
+            }
+        }
+        o.fill_from_slice(&input.slice()[..n]);
+        tags.retain(|t| t.pos() < n);
+        o.produce(n, &tags);
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    fn tone(samp_rate: Float, freq: Float, len: usize) -> Vec<Complex> {
+        let pi = std::f64::consts::PI as Float;
+        (0..len)
+            .map(|n| Complex::from_polar(1.0, 2.0 * pi * freq * n as Float / samp_rate))
+            .collect()
+    }
+
+    fn run(b: FreqOffsetBuilder) -> Result<(FreqOffset, FreqOffsetReading), Error> {
+        let mut b = b.build();
+        b.work()?;
+        let r = b.handle().reading().unwrap();
+        Ok((b, r))
+    }
+
+    #[test]
+    fn tone_offset() -> Result<(), Error> {
+        let samp_rate = 48_000.0;
+        for freq in [1234.5, -7000.0, 0.0, 20_001.0] {
+            let src = streamp_from_slice(&tone(samp_rate, freq, 4096 * 8));
+            let (b, r) = run(FreqOffset::builder(src, samp_rate).center_freq(100e6))?;
+            assert!((r.hz - freq).abs() < 1.0, "want {freq}, got {r:?}");
+            assert!((r.ppm.unwrap() - freq / 100.0).abs() < 0.01, "{r:?}");
+            assert!(r.snr > 30.0, "{r:?}");
+
+            // Passed through, and one estimate.
+            assert_eq!(b.out().read_buf()?.0.len(), 4096 * 8);
+            assert_eq!(b.estimates().read_buf()?.0.slice(), &[r.hz]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn bpsk() -> Result<(), Error> {
+        let samp_rate = 48_000.0;
+        let freq = 512.0;
+        let mut data = tone(samp_rate, freq, 2048 * 4);
+        // Flip phase every 10 samples, pseudo randomly.
+        let mut lfsr = 0xace1u16;
+        for chunk in data.chunks_mut(10) {
+            lfsr = (lfsr >> 1) ^ (0u16.wrapping_sub(lfsr & 1) & 0xb400);
+            if lfsr & 1 == 1 {
+                chunk.iter_mut().for_each(|s| *s = -*s);
+            }
+        }
+        let src = streamp_from_slice(&data);
+        let b = FreqOffset::builder(src, samp_rate)
+            .fft_size(2048)
+            .averages(4)
+            .order(2);
+        let (_, r) = run(b)?;
+        assert!((r.hz - freq).abs() < 2.0, "got {r:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn not_enough() -> Result<(), Error> {
+        let src = streamp_from_slice(&tone(1000.0, 10.0, 4096 * 8 - 1));
+        let mut b = FreqOffset::new(src, 1000.0);
+        b.work()?;
+        assert_eq!(b.handle().reading(), None);
+        Ok(())
+    }
+}
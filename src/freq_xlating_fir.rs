@@ -0,0 +1,197 @@
+/*! Frequency translating FIR filter.
+
+Selecting a channel from a wideband capture takes a frequency shift,
+a low pass filter, and decimation. [FreqXlatingFir] does all three in
+one pass: the low pass taps are turned into band pass taps centered
+on the channel, only the outputs kept after decimation are computed,
+and those are then shifted down to 0Hz.
+
+```
+use rustradio::blocks::{FreqXlatingFir, NullSink};
+use rustradio::stream::new_streamp;
+let samp_rate = 1_024_000.0;
+let src = new_streamp();
+// Channel 100kHz above center, 25kHz wide, at 1/16 the sample rate.
+let taps = rustradio::fir::low_pass(samp_rate, 12_500.0, 5_000.0);
+let xlate = FreqXlatingFir::new(src, &taps, 100_000.0, samp_rate, 16)?;
+let sink = NullSink::new(xlate.out());
+# Ok::<(), rustradio::Error>(())
+```
+*/
+use crate::block::{Block, BlockRet};
+use crate::fir::FIR;
+use crate::stream::{new_streamp, Streamp};
+use crate::{Complex, Error, Float};
+
+/// Frequency translating FIR filter block.
+///
+/// See the [module docs](crate::freq_xlating_fir).
+pub struct FreqXlatingFir {
+    src: Streamp<Complex>,
+    dst: Streamp<Complex>,
+    fir: FIR<Complex>,
+    ntaps: usize,
+    decim: usize,
+    // Shift of the next output, and how much it changes per output.
+    rot: Complex,
+    step: Complex,
+}
+
+impl FreqXlatingFir {
+    /// Create new block.
+    ///
+    /// `taps` are for a low pass filter at the input sample rate,
+    /// `center_freq` is the frequency of the channel relative to the
+    /// input center, and every `decim`th filtered sample is output.
+    pub fn new(
+        src: Streamp<Complex>,
+        taps: &[Float],
+        center_freq: Float,
+        samp_rate: Float,
+        decim: usize,
+    ) -> Result<Self, Error> {
+        if taps.is_empty() || decim == 0 {
+            return Err(Error::BadParameter(format!(
+                "FreqXlatingFir needs taps and decimation, got {} and {decim}",
+                taps.len()
+            )));
+        }
+        let w = 2.0 * std::f64::consts::PI as Float * center_freq / samp_rate;
+        let bandpass: Vec<Complex> = taps
+            .iter()
+            .enumerate()
+            .map(|(k, t)| Complex::from_polar(*t, w * k as Float))
+            .collect();
+        let ntaps = taps.len();
+        Ok(Self {
+            src,
+            dst: new_streamp(),
+            fir: FIR::new(&bandpass),
+            ntaps,
+            decim,
+            // The first output is at input sample ntaps-1.
+            rot: Complex::from_polar(1.0, -w * (ntaps - 1) as Float),
+            step: Complex::from_polar(1.0, -w * decim as Float),
+        })
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Complex> {
+        self.dst.clone()
+    }
+}
+
+impl Block for FreqXlatingFir {
+    fn block_name(&self) -> &str {
+        "FreqXlatingFir"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (input, tags) = self.src.read_buf()?;
+        if input.len() < self.ntaps {
+            return Ok(BlockRet::Noop);
+        }
+        let mut o = self.dst.write_buf()?;
+        let n = ((input.len() - self.ntaps) / self.decim + 1).min(o.len());
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        let inp = input.slice();
+        for (i, out) in o.slice()[..n].iter_mut().enumerate() {
+            let pos = i * self.decim;
+            *out = self.fir.filter(&inp[pos..pos + self.ntaps]) * self.rot;
+            self.rot *= self.step;
+        }
+        // Keep rounding errors from growing the amplitude.
+        self.rot /= self.rot.norm();
+        let otags: Vec<_> = tags
+            .iter()
+            .filter(|t| t.pos() < n * self.decim)
+            .map(|t| t.with_pos(t.pos() / self.decim))
+            .collect();
+        o.produce(n, &otags);
+        // The last ntaps-1 samples are needed again.
+        input.consume(n * self.decim);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+    use crate::tests::assert_almost_equal_complex;
+
+    #[test]
+    fn same_as_separate_blocks() -> Result<(), Error> {
+        let samp_rate = 48_000.0;
+        let w = |f: Float| 2.0 * std::f64::consts::PI as Float * f / samp_rate;
+
+        // Tones at 10kHz and 10.5kHz, of which the latter is wanted.
+        let input: Vec<Complex> = (0..4000)
+            .map(|n| {
+                Complex::from_polar(1.0, w(10_000.0) * n as Float)
+                    + Complex::from_polar(0.5, w(10_500.0) * n as Float)
+            })
+            .collect();
+        let taps = crate::fir::low_pass(samp_rate, 200.0, 200.0);
+        let ntaps = taps.len();
+        let decim = 8;
+        let mut b = FreqXlatingFir::new(
+            streamp_from_slice(&input),
+            &taps,
+            10_500.0,
+            samp_rate,
+            decim,
+        )?;
+        b.work()?;
+        let o = b.out();
+        let (out, _) = o.read_buf()?;
+        assert_eq!(out.len(), (input.len() - ntaps) / decim + 1);
+
+        // Mix, filter, decimate.
+        let mixed: Vec<Complex> = input
+            .iter()
+            .enumerate()
+            .map(|(n, s)| s * Complex::from_polar(1.0, -w(10_500.0) * n as Float))
+            .collect();
+        let ctaps: Vec<Complex> = taps.iter().map(|t| Complex::new(*t, 0.0)).collect();
+        let want: Vec<Complex> = FIR::new(&ctaps)
+            .filter_n(&mixed)
+            .into_iter()
+            .step_by(decim)
+            .collect();
+        assert_almost_equal_complex(out.slice(), &want);
+
+        // The wanted tone is now at DC, and the other filtered out.
+        for s in &out.slice()[out.len() / 2..] {
+            assert!((s - Complex::new(0.5, 0.0)).norm() < 0.01, "{s}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn history() -> Result<(), Error> {
+        // Output is the same when input arrives in pieces.
+        let input: Vec<Complex> = (0..100).map(|n| Complex::new(n as Float, 1.0)).collect();
+        let taps = [0.5, 0.25, 0.125, 0.125];
+        let whole = {
+            let mut b = FreqXlatingFir::new(streamp_from_slice(&input), &taps, 100.0, 1000.0, 3)?;
+            b.work()?;
+            let o = b.out();
+            let v = o.read_buf()?.0.slice().to_vec();
+            v
+        };
+        let src = crate::stream::new_streamp();
+        let mut b = FreqXlatingFir::new(src.clone(), &taps, 100.0, 1000.0, 3)?;
+        for chunk in input.chunks(7) {
+            let mut w = src.write_buf()?;
+            w.fill_from_slice(chunk);
+            w.produce(chunk.len(), &[]);
+            b.work()?;
+        }
+        let o = b.out();
+        assert_almost_equal_complex(o.read_buf()?.0.slice(), &whole);
+        assert!(FreqXlatingFir::new(src, &taps, 100.0, 1000.0, 0).is_err());
+        Ok(())
+    }
+}
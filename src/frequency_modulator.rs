@@ -0,0 +1,77 @@
+/*! Frequency modulator.
+
+The opposite of [QuadratureDemod][crate::quadrature_demod::QuadratureDemod]:
+each input sample sets how far, in radians, the output vector turns
+until the next sample.
+
+For a peak deviation of `dev` Hz for input in [-1, 1], use a
+sensitivity of `2π·dev/samp_rate`.
+
+```
+use rustradio::blocks::{FrequencyModulator, QuadratureDemod};
+use rustradio::stream::new_streamp;
+let sensitivity = 2.0 * std::f64::consts::PI as rustradio::Float * 3000.0 / 48000.0;
+let src = new_streamp();
+let fm = FrequencyModulator::new(src, sensitivity);
+// Gets back the input.
+let demod = QuadratureDemod::new(fm.out(), 1.0 / sensitivity);
+```
+*/
+use crate::stream::{new_streamp, Streamp};
+use crate::{map_block_convert_macro, Complex, Float};
+
+/// Frequency modulator block.
+pub struct FrequencyModulator {
+    sensitivity: f64,
+    phase: f64,
+    src: Streamp<Float>,
+    dst: Streamp<Complex>,
+}
+
+impl FrequencyModulator {
+    /// Create new FrequencyModulator block.
+    ///
+    /// Sensitivity is radians per sample for an input of 1.0.
+    pub fn new(src: Streamp<Float>, sensitivity: Float) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            sensitivity: sensitivity as f64,
+            phase: 0.0,
+        }
+    }
+
+    fn process_one(&mut self, s: Float) -> Complex {
+        self.phase = (self.phase + self.sensitivity * s as f64) % (2.0 * std::f64::consts::PI);
+        Complex::new(self.phase.cos() as Float, self.phase.sin() as Float)
+    }
+}
+map_block_convert_macro![FrequencyModulator, Complex];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::blocks::QuadratureDemod;
+    use crate::stream::streamp_from_slice;
+    use crate::tests::assert_almost_equal_float;
+
+    #[test]
+    fn roundtrip() -> crate::Result<()> {
+        let input: Vec<Float> = (0..1000).map(|n| (n as Float * 0.01).sin()).collect();
+        let sensitivity = 0.5;
+        let mut fm = FrequencyModulator::new(streamp_from_slice(&input), sensitivity);
+        fm.work()?;
+        let o = fm.out();
+        let (out, _) = o.read_buf()?;
+        assert!(out.iter().all(|s| (s.norm() - 1.0).abs() < 0.0001));
+        drop(out);
+        let mut demod = QuadratureDemod::new(fm.out(), 1.0 / sensitivity);
+        demod.work()?;
+        let o = demod.out();
+        let (out, _) = o.read_buf()?;
+        // The first sample is relative to the zero vector.
+        assert_almost_equal_float(&out.slice()[1..], &input[1..]);
+        Ok(())
+    }
+}
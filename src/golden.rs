@@ -0,0 +1,490 @@
+/*! Golden test vectors: run decoders against recorded signals.
+
+Unit tests with synthetic signals only get you so far. Real captures
+have fading, interference, and off-frequency transmitters, and a DSP
+change that looks harmless can lose packets there. A [GoldenCase] runs
+a decoder chain against a [SigMF](crate::sigmf) recording, and checks
+what comes out:
+
+```no_run
+use rustradio::blocks::*;
+use rustradio::golden::GoldenCase;
+use rustradio::Float;
+
+GoldenCase::new("ax25-1200", "aprs-1200.sigmf", |g, src, samp_rate| {
+    // Decoder chain from `src` to a stream of packets.
+    # let bits: rustradio::stream::Streamp<u8> = rustradio::stream::new_streamp();
+    let deframer = HdlcDeframer::new(bits, 10, 1500);
+    let out = deframer.out();
+    g.add(Box::new(deframer));
+    Ok(out)
+})
+.min_count(900)
+.contains(b"N0CALL>APRS:hello".to_vec())
+.check();
+```
+
+Recordings are looked for in `testdata/golden`, or the directory
+named by `$RUSTRADIO_GOLDEN_DIR`. Recordings too big to bundle can be
+given a download [url](GoldenCase::url), and are then downloaded (with
+`curl`) if `$RUSTRADIO_GOLDEN_DOWNLOAD` is set.
+
+A missing recording skips the case, so that a plain `cargo test`
+works offline. Set `$RUSTRADIO_GOLDEN_REQUIRE` to fail instead.
+
+The environment is read when the case is created. To not depend on
+it, e.g. in tests, pass a [Config] to [GoldenCase::config].
+
+`testdata/golden/ax25-1200.sigmf` is bundled, for the AX.25 1200 case
+in this module's tests. It has three APRS frames at different levels,
+off frequency and with noise, but is generated with the modulator and
+[ChannelModel](crate::channel_model::ChannelModel), not captured over
+the air. Real captures are best added with a [url](GoldenCase::url),
+rather than bundled.
+*/
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use log::info;
+
+use crate::block::{Block, BlockRet};
+use crate::graph::Graph;
+use crate::sigmf::SigMFSource;
+use crate::stream::{NoCopyStreamp, Streamp};
+use crate::{Complex, Error, Float};
+
+/// Environment variable overriding where recordings are.
+pub const ENV_DIR: &str = "RUSTRADIO_GOLDEN_DIR";
+
+/// Environment variable allowing missing recordings to be downloaded.
+pub const ENV_DOWNLOAD: &str = "RUSTRADIO_GOLDEN_DOWNLOAD";
+
+/// Environment variable making missing recordings an error.
+pub const ENV_REQUIRE: &str = "RUSTRADIO_GOLDEN_REQUIRE";
+
+/// Directory recordings are read from.
+pub fn dir() -> PathBuf {
+    std::env::var_os(ENV_DIR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata/golden"))
+}
+
+/// Where recordings are, and what to do if one is missing.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Directory recordings are read from, and downloaded to.
+    pub dir: PathBuf,
+
+    /// Download missing recordings that have a [url](GoldenCase::url).
+    pub download: bool,
+
+    /// Fail, instead of skipping, if a recording is missing.
+    pub require: bool,
+}
+
+impl Config {
+    /// Config from [ENV_DIR], [ENV_DOWNLOAD], and [ENV_REQUIRE].
+    pub fn from_env() -> Self {
+        Self {
+            dir: dir(),
+            download: std::env::var_os(ENV_DOWNLOAD).is_some(),
+            require: std::env::var_os(ENV_REQUIRE).is_some(),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+type Decoder<T> = Box<dyn FnOnce(&mut Graph, Streamp<Complex>, Float) -> Result<NoCopyStreamp<T>>>;
+
+/// A decoder chain, the recording to run it on, and what it should
+/// decode. See the [module docs](crate::golden).
+pub struct GoldenCase<T> {
+    name: String,
+    recording: String,
+    url: Option<String>,
+    config: Config,
+    decoder: Decoder<T>,
+    count: Option<usize>,
+    min_count: Option<usize>,
+    contains: Vec<T>,
+}
+
+impl<T: PartialEq + std::fmt::Debug + 'static> GoldenCase<T> {
+    /// Create new case.
+    ///
+    /// `recording` is the SigMF base name, i.e. without `-meta` and
+    /// `-data`, relative to the [recordings directory](dir).
+    /// `decoder` adds blocks to the graph, from the recording's
+    /// samples and sample rate to a stream of decoded items.
+    pub fn new<F>(name: &str, recording: &str, decoder: F) -> Self
+    where
+        F: FnOnce(&mut Graph, Streamp<Complex>, Float) -> Result<NoCopyStreamp<T>> + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            recording: recording.to_string(),
+            url: None,
+            config: Config::from_env(),
+            decoder: Box::new(decoder),
+            count: None,
+            min_count: None,
+            contains: Vec::new(),
+        }
+    }
+
+    /// Set base URL to download the recording from, if missing.
+    /// `-meta` and `-data` are appended.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_string());
+        self
+    }
+
+    /// Set config. Default [from the environment](Config::from_env).
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Expect exactly `n` items.
+    pub fn count(mut self, n: usize) -> Self {
+        self.count = Some(n);
+        self
+    }
+
+    /// Expect at least `n` items.
+    ///
+    /// Better than [count](Self::count) for regression testing,
+    /// since decoding more is an improvement.
+    pub fn min_count(mut self, n: usize) -> Self {
+        self.min_count = Some(n);
+        self
+    }
+
+    /// Expect `item` to be among the decoded items.
+    pub fn contains(mut self, item: T) -> Self {
+        self.contains.push(item);
+        self
+    }
+
+    // Find the recording, downloading it if allowed. None if missing.
+    fn find(&self) -> Result<Option<PathBuf>> {
+        let dir = &self.config.dir;
+        let base = dir.join(&self.recording);
+        if dir.join(format!("{}-meta", self.recording)).exists() {
+            return Ok(Some(base));
+        }
+        let Some(url) = &self.url else {
+            return Ok(None);
+        };
+        if !self.config.download {
+            return Ok(None);
+        }
+        if let Some(parent) = base.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Data first, so that an interrupted download is retried.
+        for ext in ["data", "meta"] {
+            let u = format!("{url}-{ext}");
+            let out = dir.join(format!("{}-{ext}", self.recording));
+            info!("golden: downloading {u}");
+            let status = std::process::Command::new("curl")
+                .arg("-fsSL")
+                .arg("-o")
+                .arg(&out)
+                .arg(&u)
+                .status()?;
+            if !status.success() {
+                return Err(Error::new(&format!("downloading {u} failed: {status}")).into());
+            }
+        }
+        Ok(Some(base))
+    }
+
+    /// Run the case. Returns the decoded items, or None if the
+    /// recording is missing and not required.
+    pub fn run(self) -> Result<Option<Vec<T>>> {
+        let Some(path) = self.find()? else {
+            if self.config.require {
+                return Err(Error::new(&format!(
+                    "golden {}: recording {} missing from {}",
+                    self.name,
+                    self.recording,
+                    self.config.dir.display()
+                ))
+                .into());
+            }
+            return Ok(None);
+        };
+        let src = SigMFSource::<Complex>::new(&path.to_string_lossy(), None)?;
+        let samp_rate = src.sample_rate().ok_or(Error::new(&format!(
+            "golden {}: recording has no sample rate",
+            self.name
+        )))? as Float;
+        let mut g = Graph::new();
+        let prev = src.out();
+        g.add(Box::new(src));
+        let out = (self.decoder)(&mut g, prev, samp_rate)?;
+        let sink = Collect::new(out);
+        let items = sink.items.clone();
+        g.add(Box::new(sink));
+        g.run()?;
+        let items = std::mem::take(&mut *items.lock().unwrap());
+
+        let fail = |msg: String| -> Result<Option<Vec<T>>> {
+            Err(Error::new(&format!("golden {}: {msg}", self.name)).into())
+        };
+        if let Some(n) = self.count {
+            if items.len() != n {
+                return fail(format!("decoded {} items, want {n}", items.len()));
+            }
+        }
+        if let Some(n) = self.min_count {
+            if items.len() < n {
+                return fail(format!("decoded {} items, want at least {n}", items.len()));
+            }
+        }
+        for want in &self.contains {
+            if !items.contains(want) {
+                return fail(format!("{want:?} not decoded"));
+            }
+        }
+        Ok(Some(items))
+    }
+
+    /// Run the case, panicking if it fails. For use in tests.
+    pub fn check(self) {
+        let name = self.name.clone();
+        match self.run() {
+            Ok(Some(items)) => info!("golden {name}: {} items OK", items.len()),
+            Ok(None) => eprintln!("golden {name}: recording missing, skipped"),
+            Err(e) => panic!("{e}"),
+        }
+    }
+}
+
+// Collect all items from a stream.
+struct Collect<T> {
+    src: NoCopyStreamp<T>,
+    items: Arc<Mutex<Vec<T>>>,
+}
+
+impl<T> Collect<T> {
+    fn new(src: NoCopyStreamp<T>) -> Self {
+        Self {
+            src,
+            items: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl<T> Block for Collect<T> {
+    fn block_name(&self) -> &str {
+        "GoldenCollect"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let mut ret = BlockRet::Noop;
+        while let Some((item, _tags)) = self.src.pop() {
+            self.items.lock().unwrap().push(item);
+            ret = BlockRet::Ok;
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{BinarySlicer, HdlcDeframer, Map};
+
+    fn hdlc(g: &mut Graph, src: Streamp<Complex>, _: Float) -> Result<NoCopyStreamp<Vec<u8>>> {
+        let re = Map::new(src, |s: Complex| s.re);
+        let slicer = BinarySlicer::new(re.out());
+        let mut deframer = HdlcDeframer::new(slicer.out(), 1, 10);
+        deframer.set_checksum(false);
+        let out = deframer.out();
+        g.add(Box::new(re));
+        g.add(Box::new(slicer));
+        g.add(Box::new(deframer));
+        Ok(out)
+    }
+
+    // Write two HDLC frames as a recording, one sample per bit.
+    fn write_recording(dir: &std::path::Path) -> Result<()> {
+        let bits = "01111110010101011110000001111110010101011010101001111110";
+        let mut data = Vec::new();
+        for ch in bits.chars() {
+            let s: Float = if ch == '1' { 1.0 } else { -1.0 };
+            data.extend_from_slice(&s.to_le_bytes());
+            data.extend_from_slice(&(0.0 as Float).to_le_bytes());
+        }
+        std::fs::write(dir.join("hdlc.sigmf-data"), data)?;
+        crate::sigmf::write(
+            &dir.join("hdlc.sigmf-meta").to_string_lossy(),
+            9600.0,
+            144_800_000.0,
+        )
+    }
+
+    #[test]
+    fn golden() -> Result<()> {
+        let tmpd = tempfile::tempdir()?;
+        let config = |require| Config {
+            dir: tmpd.path().to_path_buf(),
+            download: false,
+            require,
+        };
+        let case = || GoldenCase::new("hdlc", "hdlc.sigmf", hdlc).config(config(false));
+
+        // Missing.
+        assert!(case().run()?.is_none());
+        assert!(case().config(config(true)).run().is_err());
+
+        write_recording(tmpd.path())?;
+        let got = case().count(2).contains(vec![0xaa, 0x55]).run()?.unwrap();
+        assert_eq!(got, vec![vec![0xaa, 0x07], vec![0xaa, 0x55]]);
+        assert!(case().min_count(3).run().is_err());
+        assert!(case().contains(vec![1, 2]).run().is_err());
+        case().config(config(true)).min_count(2).check();
+        Ok(())
+    }
+
+    #[cfg(all(feature = "decoders", feature = "fft"))]
+    mod ax25 {
+        use super::*;
+        use crate::afsk::{BELL202_BAUD, BELL202_MARK, BELL202_SPACE};
+        use crate::ax25::Frame;
+        use crate::blocks::*;
+        use crate::sigmf::{DataType, Format};
+        use crate::stream::new_nocopy_streamp;
+        use crate::{add_block, flowgraph};
+
+        const SAMP_RATE: Float = 25_000.0;
+
+        // Frames in the recording, and their amplitude.
+        fn frames() -> Vec<(Frame, Float)> {
+            [
+                (
+                    "N0CALL-9",
+                    &["WIDE1-1", "WIDE2-1"][..],
+                    "!5950.00N/01800.00E>mobile",
+                    0.4,
+                ),
+                ("N0CALL-10", &["WIDE2-1"], ">rustradio golden test", 0.2),
+                ("M0XYZ", &[], "=5130.00N/00010.00W-weak station", 0.1),
+            ]
+            .into_iter()
+            .map(|(src, digis, info, amp)| {
+                (Frame::ui(src, "APRS", digis, info.as_bytes()).unwrap(), amp)
+            })
+            .collect()
+        }
+
+        // The receive chain of examples/ax25-1200-rx.
+        fn rx(
+            g: &mut Graph,
+            src: Streamp<Complex>,
+            samp_rate: Float,
+        ) -> Result<NoCopyStreamp<Vec<u8>>> {
+            let prev = src;
+            let taps = crate::fir::low_pass_complex(samp_rate, samp_rate / 2.5, 100.0);
+            let taps2 = crate::fir::low_pass(50_000.0, 1100.0, 100.0);
+            let center = (BELL202_MARK + BELL202_SPACE) / 2.0;
+            let prev = flowgraph![g, prev =>
+                FftFilter::new(prev, &taps),
+                RationalResampler::new(prev, 50_000, samp_rate as usize)?,
+                QuadratureDemod::new(prev, 1.0),
+                Hilbert::new(prev, 65),
+                QuadratureDemod::new(prev, 1.0),
+                FftFilterFloat::new(prev, &taps2),
+                AddConst::new(prev, -center * 2.0 * std::f64::consts::PI as Float / 50_000.0),
+                SymbolSync::new(
+                    prev,
+                    50_000.0 / BELL202_BAUD,
+                    0.5,
+                    Box::new(crate::symbol_sync::TEDZeroCrossing::new()),
+                    Box::new(crate::iir_filter::IIRFilter::new(&[0.5, 0.5])),
+                ),
+                BinarySlicer::new(prev),
+                NrziDecode::new(prev),
+                HdlcDeframer::new(prev, 10, 1500),
+            ];
+            Ok(prev)
+        }
+
+        #[test]
+        fn ax25_1200() {
+            let frames = frames();
+            let mut case = GoldenCase::new("ax25-1200", "ax25-1200.sigmf", rx).count(frames.len());
+            for (f, _) in frames {
+                case = case.contains(f.encode());
+            }
+            case.check();
+        }
+
+        // FM modulated frame, with silence after.
+        fn modulate(frame: &Frame) -> Result<Vec<Complex>> {
+            let frames = new_nocopy_streamp();
+            frames.push(frame.encode(), &[]);
+            let mut g = Graph::new();
+            let prev = frames;
+            let prev = flowgraph![g, prev =>
+                HdlcFramer::builder(prev).preamble(32).postamble(32).build(),
+                NrziEncode::new(prev),
+                AfskModulator::new(prev, SAMP_RATE)?,
+                FrequencyModulator::new(prev, 2.0 * std::f64::consts::PI as Float * 3000.0 / SAMP_RATE),
+            ];
+            let sink = VectorSink::new(prev);
+            let h = sink.handle();
+            g.add(Box::new(sink));
+            g.run()?;
+            let mut ret = h.data();
+            ret.resize(ret.len() + SAMP_RATE as usize / 5, Complex::default());
+            Ok(ret)
+        }
+
+        // Regenerate the bundled recording, with:
+        //
+        //   cargo test --lib make_ax25_1200 -- --ignored
+        //
+        // Frames at three levels, 2kHz off frequency, with noise, and
+        // quantized to 8 bits like from an RTL-SDR.
+        #[test]
+        #[ignore]
+        fn make_ax25_1200() -> Result<()> {
+            let mut iq = vec![Complex::default(); SAMP_RATE as usize / 5];
+            for (f, amp) in frames() {
+                iq.extend(modulate(&f)?.into_iter().map(|s| s * amp));
+            }
+            let mut g = Graph::new();
+            let prev = add_block![g, VectorSource::new(iq)];
+            let prev = add_block![
+                g,
+                ChannelModel::builder(prev, SAMP_RATE)
+                    .noise_voltage(0.01)
+                    .freq_offset(2000.0)
+                    .build()
+            ];
+            let out = dir().join("ax25-1200.sigmf");
+            for ext in ["data", "meta"] {
+                let _ = std::fs::remove_file(dir().join(format!("ax25-1200.sigmf-{ext}")));
+            }
+            g.add(Box::new(
+                SigMFSinkBuilder::new(prev, &out.to_string_lossy())
+                    .sample_rate(SAMP_RATE as f64)
+                    .frequency(144_800_000.0)
+                    .datetime(std::time::SystemTime::UNIX_EPOCH)
+                    .datatype(DataType::new(Format::U8, true, false))
+                    .description("AX.25 1200 golden test: three APRS frames")
+                    .build()?,
+            ));
+            g.run()?;
+            Ok(())
+        }
+    }
+}
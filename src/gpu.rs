@@ -0,0 +1,322 @@
+/*! GPU rendering for live displays.
+
+Only available with the `gpu` feature.
+
+The CPU waterfall in [WaterfallView][crate::waterfall_sink::WaterfallView]
+colors every pixel of the history each time a row arrives, which at
+large FFT sizes and row rates takes a core of its own. [GpuWaterfall]
+instead keeps the history in a texture on the GPU, as dB values in a
+ring buffer. Each row is uploaded once, and the color map and dB range
+are applied by a shader when drawing, so CPU use no longer grows with
+FFT size × history, and changing the dB range costs nothing.
+
+This uses OpenGL through glow, since that's the renderer eframe is
+built with here. wgpu can't be used: every wgpu version needs a newer
+termcolor than the one stderrlog, used by the examples, is pinned to.
+
+Float textures need OpenGL 3.1 or OpenGL ES 3.0. On anything older, or
+if the FFT is wider than the maximum texture size,
+[GpuWaterfall::failed] becomes true, and the view goes back to drawing
+on the CPU.
+*/
+use std::sync::{Arc, Mutex};
+
+use eframe::egui;
+use eframe::egui_glow::{self, ShaderVersion};
+use eframe::glow::{self, HasContext};
+use log::{debug, warn};
+
+use crate::Float;
+
+const VERTEX_SHADER: &str = r#"
+out vec2 v_pos;
+void main() {
+    // Triangle strip covering the viewport.
+    vec2 pos = vec2(float(gl_VertexID & 1), float(gl_VertexID >> 1));
+    // x: 0 left to 1 right. y: 0 top (newest) to 1 bottom (oldest).
+    v_pos = vec2(pos.x, 1.0 - pos.y);
+    gl_Position = vec4(pos * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+// Same color map as crate::waterfall_sink::colormap.
+const FRAGMENT_SHADER: &str = r#"
+uniform sampler2D u_db;
+uniform float u_head;
+uniform float u_rows;
+uniform float u_history;
+uniform float u_height;
+uniform float u_min_db;
+uniform float u_max_db;
+in vec2 v_pos;
+out vec4 out_color;
+
+vec3 colormap(float v) {
+    vec3 stops[6] = vec3[6](
+        vec3(0.0, 0.0, 0.0),
+        vec3(0.0, 0.0, 160.0),
+        vec3(0.0, 200.0, 255.0),
+        vec3(255.0, 255.0, 0.0),
+        vec3(255.0, 0.0, 0.0),
+        vec3(255.0, 255.0, 255.0));
+    float pos = clamp(v, 0.0, 1.0) * 5.0;
+    int i = min(int(pos), 4);
+    return mix(stops[i], stops[i + 1], pos - float(i)) / 255.0;
+}
+
+void main() {
+    float age = floor(v_pos.y * u_history);
+    if (age >= u_rows) {
+        out_color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+    float row = mod(u_head - 1.0 - age + u_height, u_height);
+    float db = texture(u_db, vec2(v_pos.x, (row + 0.5) / u_height)).r;
+    out_color = vec4(colormap((db - u_min_db) / (u_max_db - u_min_db)), 1.0);
+}
+"#;
+
+// GL objects, created on first paint.
+struct Gl {
+    program: glow::Program,
+    vao: glow::VertexArray,
+    texture: Option<glow::Texture>,
+    max_size: usize,
+}
+
+impl Gl {
+    unsafe fn new(gl: &glow::Context) -> Result<Self, String> {
+        let version = ShaderVersion::get(gl);
+        if !version.is_new_shader_interface() {
+            return Err(format!("{version:?} doesn't support float textures"));
+        }
+        let prefix = if version.is_embedded() {
+            format!("{}precision highp float;\n", version.version_declaration())
+        } else {
+            version.version_declaration().to_string()
+        };
+        let program = gl.create_program()?;
+        let mut shaders = Vec::new();
+        for (kind, src) in [
+            (glow::VERTEX_SHADER, VERTEX_SHADER),
+            (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+        ] {
+            let shader = gl.create_shader(kind)?;
+            gl.shader_source(shader, &format!("{prefix}{src}"));
+            gl.compile_shader(shader);
+            if !gl.get_shader_compile_status(shader) {
+                return Err(gl.get_shader_info_log(shader));
+            }
+            gl.attach_shader(program, shader);
+            shaders.push(shader);
+        }
+        gl.link_program(program);
+        if !gl.get_program_link_status(program) {
+            return Err(gl.get_program_info_log(program));
+        }
+        for shader in shaders {
+            gl.detach_shader(program, shader);
+            gl.delete_shader(shader);
+        }
+        Ok(Self {
+            program,
+            vao: gl.create_vertex_array()?,
+            texture: None,
+            max_size: gl.get_parameter_i32(glow::MAX_TEXTURE_SIZE) as usize,
+        })
+    }
+}
+
+#[derive(Default)]
+struct State {
+    gl: Option<Gl>,
+    failed: bool,
+    // Rows not yet uploaded, oldest first.
+    pending: Vec<Vec<Float>>,
+    // Texture size, and whether it needs to be (re)created.
+    width: usize,
+    height: usize,
+    realloc: bool,
+    // Texture row for the next row, and number of rows written.
+    head: usize,
+    rows: usize,
+}
+
+impl State {
+    unsafe fn paint(&mut self, gl: &glow::Context, history: usize, min_db: Float, max_db: Float) {
+        if self.failed {
+            return;
+        }
+        if self.gl.is_none() {
+            match Gl::new(gl) {
+                Ok(g) => self.gl = Some(g),
+                Err(e) => {
+                    warn!("GpuWaterfall: falling back to CPU rendering: {e}");
+                    self.failed = true;
+                    return;
+                }
+            }
+        }
+        let g = self.gl.as_mut().unwrap();
+        if self.width > g.max_size || self.height > g.max_size {
+            warn!(
+                "GpuWaterfall: {}x{} is bigger than the max texture size {}, falling back to CPU rendering",
+                self.width, self.height, g.max_size
+            );
+            self.failed = true;
+            return;
+        }
+        if self.realloc || g.texture.is_none() {
+            debug!("GpuWaterfall: texture {}x{}", self.width, self.height);
+            if let Some(t) = g.texture.take() {
+                gl.delete_texture(t);
+            }
+            let t = match gl.create_texture() {
+                Ok(t) => t,
+                Err(e) => {
+                    warn!("GpuWaterfall: falling back to CPU rendering: {e}");
+                    self.failed = true;
+                    return;
+                }
+            };
+            gl.bind_texture(glow::TEXTURE_2D, Some(t));
+            for (param, value) in [
+                (glow::TEXTURE_MIN_FILTER, glow::NEAREST),
+                (glow::TEXTURE_MAG_FILTER, glow::NEAREST),
+                (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, param, value as i32);
+            }
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::R32F as i32,
+                self.width as i32,
+                self.height as i32,
+                0,
+                glow::RED,
+                glow::FLOAT,
+                None,
+            );
+            g.texture = Some(t);
+            self.realloc = false;
+            self.head = 0;
+            self.rows = 0;
+        }
+        gl.bind_texture(glow::TEXTURE_2D, g.texture);
+
+        // Upload new rows. Only the newest fit.
+        let skip = self.pending.len().saturating_sub(self.height);
+        gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+        for row in self.pending.drain(..).skip(skip) {
+            if row.len() != self.width {
+                continue;
+            }
+            let bytes: Vec<u8> = row.iter().flat_map(|v| (*v as f32).to_ne_bytes()).collect();
+            gl.tex_sub_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                0,
+                self.head as i32,
+                self.width as i32,
+                1,
+                glow::RED,
+                glow::FLOAT,
+                glow::PixelUnpackData::Slice(&bytes),
+            );
+            self.head = (self.head + 1) % self.height;
+            self.rows = (self.rows + 1).min(self.height);
+        }
+
+        gl.use_program(Some(g.program));
+        gl.bind_vertex_array(Some(g.vao));
+        gl.active_texture(glow::TEXTURE0);
+        gl.bind_texture(glow::TEXTURE_2D, g.texture);
+        let uniform = |name| gl.get_uniform_location(g.program, name);
+        gl.uniform_1_i32(uniform("u_db").as_ref(), 0);
+        for (name, v) in [
+            ("u_head", self.head as f32),
+            ("u_rows", self.rows.min(history) as f32),
+            ("u_history", history as f32),
+            ("u_height", self.height as f32),
+            ("u_min_db", min_db as f32),
+            ("u_max_db", max_db as f32),
+        ] {
+            gl.uniform_1_f32(uniform(name).as_ref(), v);
+        }
+        gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+    }
+}
+
+/// Waterfall rendered on the GPU.
+///
+/// GL resources live as long as the window.
+#[derive(Clone, Default)]
+pub struct GpuWaterfall {
+    state: Arc<Mutex<State>>,
+}
+
+impl GpuWaterfall {
+    /// Create new GPU waterfall. Nothing is allocated until the first
+    /// paint.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// True if the GPU can't be used, and the caller should draw some
+    /// other way.
+    pub fn failed(&self) -> bool {
+        self.state.lock().unwrap().failed
+    }
+
+    /// Queue a row of dB values for upload, newer than any before.
+    pub fn push(&self, db: &[Float]) {
+        self.state.lock().unwrap().pending.push(db.to_vec());
+    }
+
+    /// Forget all rows.
+    pub fn clear(&self) {
+        let mut s = self.state.lock().unwrap();
+        s.pending.clear();
+        s.realloc = true;
+    }
+
+    /// Draw the `history` newest rows in `rect`, newest at the top,
+    /// with colors spanning `min_db` to `max_db`. Rows need to be
+    /// `bins` wide.
+    pub fn paint(
+        &self,
+        painter: &egui::Painter,
+        rect: egui::Rect,
+        bins: usize,
+        history: usize,
+        min_db: Float,
+        max_db: Float,
+    ) {
+        {
+            let mut s = self.state.lock().unwrap();
+            let height = history.max(1);
+            if (s.width, s.height) != (bins, height) {
+                s.width = bins;
+                s.height = height;
+                s.realloc = true;
+            }
+        }
+        let state = self.state.clone();
+        let history = history.max(1);
+        painter.add(egui::PaintCallback {
+            rect,
+            callback: Arc::new(egui_glow::CallbackFn::new(move |_info, painter| {
+                // SAFETY: Only GL calls on the painter's context, from
+                // the thread that owns it.
+                unsafe {
+                    state
+                        .lock()
+                        .unwrap()
+                        .paint(painter.gl(), history, min_db, max_db)
+                }
+            })),
+        });
+    }
+}
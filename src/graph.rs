@@ -1,16 +1,115 @@
 /*! Graphs contain blocks connected by streams, and run them.
  */
-use std::time::Instant;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use log::{info, trace};
+use serde::{Deserialize, Serialize};
 
-use crate::block::{Block, BlockRet};
+use crate::block::{Block, BlockRet, WorkHints};
+use crate::control::Controls;
+use crate::node::{InPort, Node, NodePorts, Nodes, OutPort, Port, Ports};
+use crate::stream::{WorkCounts, WorkLimiter};
+use crate::Error;
+
+/**
+Add a block to a graph, and return its output.
+
+Blocks own their output streams, and get their input streams when
+created, so the output has to be fetched before the block is moved
+into the graph. This does both, for blocks with an `out()`:
+
+```
+use rustradio::add_block;
+use rustradio::graph::Graph;
+use rustradio::Complex;
+use rustradio::blocks::{FileSource,RtlSdrDecode,AddConst,NullSink};
+let mut g = Graph::new();
+let prev = add_block![g, FileSource::<u8>::new("/dev/null", false)?];
+let prev = add_block![g, RtlSdrDecode::new(prev)];
+let prev = add_block![g, AddConst::new(prev, Complex::new(1.1, 2.0))];
+g.add(Box::new(NullSink::new(prev)));
+g.run()?;
+# Ok::<(), anyhow::Error>(())
+```
+
+Works with [MTGraph][crate::mtgraph::MTGraph] too. To instead add
+blocks in any order, and have the graph connect their ports, see
+[node][crate::node].
+*/
+#[macro_export]
+macro_rules! add_block {
+    ($g:ident, $cons:expr) => {{
+        let block = Box::new($cons);
+        let prev = block.out();
+        $g.add(block);
+        prev
+    }};
+}
+
+/**
+Add a chain of blocks to a graph, and return the output of the last.
+
+Each block is created with the output of the one before it available
+under the given name, so a chain is written top to bottom without
+repeating `let prev = add_block![…]`:
+
+```
+use rustradio::flowgraph;
+use rustradio::graph::Graph;
+use rustradio::Complex;
+use rustradio::blocks::{AddConst, FileSource, NullSink, RtlSdrDecode, Tee};
+let mut g = Graph::new();
+let prev = flowgraph![g, prev =>
+    FileSource::<u8>::new("/dev/null", false)?,
+    RtlSdrDecode::new(prev),
+    AddConst::new(prev, Complex::new(1.1, 2.0)),
+];
+g.add(Box::new(NullSink::new(prev)));
+# Ok::<(), anyhow::Error>(())
+```
+
+The first block may use an existing stream of that name. For
+branches, end a chain with a block with several outputs, like [Tee],
+and start a chain from each:
+
+```
+# use rustradio::flowgraph;
+# use rustradio::Float;
+# use rustradio::graph::Graph;
+# use rustradio::blocks::{AddConst, FileSource, NullSink, Tee};
+# let mut g = Graph::new();
+let (a, b) = flowgraph![g, prev =>
+    FileSource::<Float>::new("/dev/null", false)?,
+    Tee::new(prev),
+];
+let a = flowgraph![g, a => AddConst::new(a, 1.0)];
+g.add(Box::new(NullSink::new(a)));
+g.add(Box::new(NullSink::new(b)));
+# Ok::<(), anyhow::Error>(())
+```
+
+[Tee]: crate::blocks::Tee
+*/
+#[macro_export]
+macro_rules! flowgraph {
+    ($g:ident, $prev:ident => $($cons:expr),+ $(,)?) => {{
+        $(let $prev = $crate::add_block![$g, $cons];)+
+        $prev
+    }};
+}
 
 /**
 A graph is a thing that RustRadio runs, to let blocks "talk to each
 other" via streams.
 
+All blocks run on the calling thread. To run them on a thread each,
+or in groups, build an [MTGraph](crate::mtgraph::MTGraph) instead.
+
+Blocks are added already connected, as below, or as [Node]s with
+ports, connected with [Graph::connect]. See [node](crate::node).
+
 # Example
 
 ```
@@ -32,8 +131,15 @@ g.run()?;
 */
 pub struct Graph {
     blocks: Vec<Box<dyn Block>>,
+    // Nodes added with ports, built as they're connected.
+    nodes: Nodes,
+    limiters: Vec<WorkLimiter>,
     cancel_token: CancellationToken,
+    pause_token: PauseToken,
     times: Vec<std::time::Duration>,
+    stats: StatsHandle,
+    // When the graph first ran.
+    started: Option<Instant>,
 }
 
 impl Graph {
@@ -41,63 +147,202 @@ impl Graph {
     pub fn new() -> Self {
         Self {
             blocks: Vec::new(),
+            nodes: Nodes::new(),
+            limiters: Vec::new(),
             times: Vec::new(),
             cancel_token: CancellationToken::new(),
+            pause_token: PauseToken::new(),
+            stats: StatsHandle::default(),
+            started: None,
+        }
+    }
+
+    /// Add a block, or a [Node], to the flowgraph.
+    ///
+    /// Nodes return their [NodePorts], to [connect](Graph::connect)
+    /// them.
+    pub fn add<T: GraphItem>(&mut self, item: T) -> T::Id {
+        item.add_to(self)
+    }
+
+    fn push_block(&mut self, b: Box<dyn Block>) {
+        let hints = b.work_hints();
+        self.add_with_hints(b, hints);
+    }
+
+    /** Connect an output port of a node to an input port.
+
+    The ports must have the same stream type, and are used up, so
+    mismatched or repeated connections don't compile. Fails only if
+    the ports belong to another graph. Nodes are created as soon as
+    all their inputs are connected. See [node](crate::node).
+    */
+    pub fn connect<P: Port>(&mut self, src: OutPort<P>, dst: InPort<P>) -> Result<(), Error> {
+        self.nodes.connect(src, dst)?;
+        self.build_nodes();
+        Ok(())
+    }
+
+    fn build_nodes(&mut self) {
+        while let Some(b) = self.nodes.build_next() {
+            self.push_block(b);
         }
     }
 
-    /// Add a block to the flowgraph.
-    pub fn add(&mut self, b: Box<dyn Block>) {
+    /// Add a block to the flowgraph, overriding its own [WorkHints].
+    pub fn add_with_hints(&mut self, b: Box<dyn Block>, hints: WorkHints) {
         self.blocks.push(b);
+        self.limiters.push(WorkLimiter::new(hints));
     }
 
     /// Run the graph until completion.
     pub fn run(&mut self) -> Result<()> {
-        let st = Instant::now();
-        self.times
-            .resize(self.blocks.len(), std::time::Duration::default());
-        loop {
-            let mut done = true;
-            let mut all_idle = true;
-            if self.cancel_token.is_canceled() {
-                break;
+        self.run_until(None)?;
+        Ok(())
+    }
+
+    /** Run the graph for at most `duration`, or until completion.
+
+    Returns [RunState::Done] if the graph completed, and can otherwise
+    be called again to continue where it left off. For interleaving
+    processing with UI or control logic on the same thread, or for
+    tests that need a bounded amount of processing.
+
+    ```
+    use std::time::Duration;
+    use rustradio::graph::{Graph, RunState};
+    use rustradio::blocks::{NullSink, VectorSource};
+    let src = Box::new(VectorSource::new(vec![1u8, 2, 3]));
+    let sink = Box::new(NullSink::new(src.out()));
+    let mut g = Graph::new();
+    g.add(src);
+    g.add(sink);
+    while g.run_for(Duration::from_millis(100))? != RunState::Done {
+        // Update UI, change controls, …
+    }
+    # Ok::<(), anyhow::Error>(())
+    ```
+    */
+    pub fn run_for(&mut self, duration: Duration) -> Result<RunState> {
+        self.run_until(Some(Instant::now() + duration))
+    }
+
+    fn run_until(&mut self, deadline: Option<Instant>) -> Result<RunState> {
+        let st = *self.started.get_or_insert_with(|| {
+            self.stats
+                .init(self.blocks.iter().map(|b| b.block_name().to_string()));
+            Instant::now()
+        });
+        let mut stats_updated = Instant::now();
+        let state = loop {
+            if stats_updated.elapsed() >= STATS_INTERVAL {
+                stats_updated = Instant::now();
+                for (n, (t, l)) in self.times.iter().zip(&self.limiters).enumerate() {
+                    self.stats.set(n, *t, l.counts(), st.elapsed());
+                }
             }
-            for (n, b) in self.blocks.iter_mut().enumerate() {
-                let st = Instant::now();
-                let ret = b.work()?;
-                self.times[n] += st.elapsed();
-                match ret {
-                    BlockRet::Ok => {
-                        // Block did something.
-                        trace!("… {} was not starved", b.block_name());
-                        done = false;
-                        all_idle = false;
-                    }
-                    BlockRet::Pending => {
-                        done = false;
-                    }
-                    BlockRet::Noop => {}
-                    BlockRet::EOF => {}
-                    BlockRet::InternalAwaiting => {
-                        panic!("blocks must never return InternalAwaiting")
-                    }
-                };
+            let state = self.run_once()?;
+            if state == RunState::Done {
+                break state;
             }
-            if done {
-                break;
+            let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            if remaining == Some(Duration::ZERO) {
+                break state;
             }
-            if all_idle {
-                let idle_sleep = std::time::Duration::from_millis(10);
+            if state == RunState::Idle {
+                let idle_sleep = Duration::from_millis(10);
                 trace!("No output or consumption from any block. Sleeping a bit.");
-                std::thread::sleep(idle_sleep);
+                std::thread::sleep(remaining.map_or(idle_sleep, |r| r.min(idle_sleep)));
             }
+        };
+        for (n, (t, l)) in self.times.iter().zip(&self.limiters).enumerate() {
+            self.stats.set(n, *t, l.counts(), st.elapsed());
         }
-        for line in self.generate_stats(st.elapsed()).split('\n') {
-            if !line.is_empty() {
-                info!("{}", line);
+        if state == RunState::Done {
+            for line in self.generate_stats(st.elapsed()).split('\n') {
+                if !line.is_empty() {
+                    info!("{}", line);
+                }
             }
         }
-        Ok(())
+        Ok(state)
+    }
+
+    /** Call `work()` once on every block.
+
+    This is for running a graph from an event loop that must not
+    block, such as the browser's when built for WASM, where threads and
+    sleeping are not available. Call it again while it returns
+    [RunState::Busy], and again some time later on [RunState::Idle].
+
+    ```
+    use rustradio::graph::{Graph, RunState};
+    use rustradio::blocks::{NullSink, VectorSource};
+    let src = Box::new(VectorSource::new(vec![1u8, 2, 3]));
+    let sink = Box::new(NullSink::new(src.out()));
+    let mut g = Graph::new();
+    g.add(src);
+    g.add(sink);
+    while g.run_once()? != RunState::Done {}
+    # Ok::<(), anyhow::Error>(())
+    ```
+
+    While the graph is [paused](Graph::pause), this does nothing, and
+    returns [RunState::Idle].
+    */
+    pub fn run_once(&mut self) -> Result<RunState> {
+        if self.pause_token.is_paused() && !self.cancel_token.is_canceled() {
+            return Ok(RunState::Idle);
+        }
+        self.step()
+    }
+
+    /** Call `work()` once on every block, even if paused.
+
+    For single stepping a paused graph, e.g. when debugging.
+    */
+    pub fn step(&mut self) -> Result<RunState> {
+        if self.cancel_token.is_canceled() {
+            return Ok(RunState::Done);
+        }
+        // Only does anything the first time, or after adding nodes.
+        self.nodes.check()?;
+        self.times
+            .resize(self.blocks.len(), std::time::Duration::default());
+        let mut done = true;
+        let mut all_idle = true;
+        for (n, (b, limiter)) in self.blocks.iter_mut().zip(&mut self.limiters).enumerate() {
+            let st = now();
+            let ret = limiter
+                .work(|| b.work())
+                .map_err(|e| e.in_block(b.block_name(), n))?;
+            if let Some(st) = st {
+                self.times[n] += st.elapsed();
+            }
+            match ret {
+                BlockRet::Ok => {
+                    // Block did something.
+                    trace!("… {} was not starved", b.block_name());
+                    done = false;
+                    all_idle = false;
+                }
+                BlockRet::Pending => {
+                    done = false;
+                }
+                BlockRet::Noop => {}
+                BlockRet::EOF => {}
+                BlockRet::InternalAwaiting => {
+                    panic!("blocks must never return InternalAwaiting")
+                }
+            };
+        }
+        Ok(if done {
+            RunState::Done
+        } else if all_idle {
+            RunState::Idle
+        } else {
+            RunState::Busy
+        })
     }
 
     /// Return a string with stats about where time went.
@@ -155,6 +400,12 @@ impl Graph {
         s
     }
 
+    /// Return a handle for reading stats while the graph is running,
+    /// e.g. from another thread.
+    pub fn stats_handle(&self) -> StatsHandle {
+        self.stats.clone()
+    }
+
     /// Return a cancellation token, for asynchronously stopping the
     /// graph, for example if the user presses Ctrl-C.
     ///
@@ -173,6 +424,41 @@ impl Graph {
     pub fn cancel_token(&self) -> CancellationToken {
         self.cancel_token.clone()
     }
+
+    /// Pause the graph. Blocks are not run until [Graph::resume], but
+    /// keep their state and buffered samples.
+    ///
+    /// To pause while `run()` is executing, use a [PauseToken].
+    pub fn pause(&self) {
+        self.pause_token.pause();
+    }
+
+    /// Resume a paused graph.
+    pub fn resume(&self) {
+        self.pause_token.resume();
+    }
+
+    /// Check if the graph is paused.
+    pub fn is_paused(&self) -> bool {
+        self.pause_token.is_paused()
+    }
+
+    /// Return a token for pausing and resuming the graph from another
+    /// thread.
+    pub fn pause_token(&self) -> PauseToken {
+        self.pause_token.clone()
+    }
+
+    /// Return the [controls](crate::control) of all blocks, for
+    /// changing their parameters while the graph is running, e.g. from
+    /// another thread. Must be called before `run()`.
+    pub fn controls(&self) -> Controls {
+        let mut c = Controls::new();
+        for b in &self.blocks {
+            c.add_block(b.as_ref());
+        }
+        c
+    }
 }
 
 impl Default for Graph {
@@ -181,6 +467,185 @@ impl Default for Graph {
     }
 }
 
+/// Something that can be [added](Graph::add) to a [Graph], or an
+/// [MTGraph](crate::mtgraph::MTGraph): a boxed block, or a [Node].
+pub trait GraphItem<G = Graph> {
+    /// What `add` returns.
+    type Id;
+
+    #[doc(hidden)]
+    fn add_to(self, g: &mut G) -> Self::Id;
+}
+
+impl<B: Block + 'static> GraphItem for Box<B> {
+    type Id = ();
+    fn add_to(self, g: &mut Graph) {
+        g.push_block(self);
+    }
+}
+
+impl GraphItem for Box<dyn Block> {
+    type Id = ();
+    fn add_to(self, g: &mut Graph) {
+        g.push_block(self);
+    }
+}
+
+impl GraphItem for Box<dyn Block + Send> {
+    type Id = ();
+    fn add_to(self, g: &mut Graph) {
+        g.push_block(self);
+    }
+}
+
+impl<I: Ports, O: Ports> GraphItem for Node<I, O> {
+    type Id = NodePorts<I, O>;
+    fn add_to(self, g: &mut Graph) -> Self::Id {
+        let ports = g.nodes.add(self);
+        g.build_nodes();
+        ports
+    }
+}
+
+/// What a graph did in [Graph::run_once].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    /// Some block produced or consumed samples.
+    Busy,
+
+    /// No block did anything, but some are waiting for more input.
+    Idle,
+
+    /// All blocks are done, or the graph was cancelled.
+    Done,
+}
+
+// Time is not available in the browser, so blocks are not timed there.
+fn now() -> Option<Instant> {
+    if cfg!(all(target_family = "wasm", target_os = "unknown")) {
+        None
+    } else {
+        Some(Instant::now())
+    }
+}
+
+// How often stats are published while running.
+pub(crate) const STATS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time spent in, and work done by, one block.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BlockStats {
+    /// Block name.
+    pub name: String,
+
+    /// Seconds spent in the block's `work()`.
+    pub seconds: f64,
+
+    /// Number of `work()` calls.
+    pub work_calls: u64,
+
+    /// Items consumed, summed over all inputs.
+    pub items_in: u64,
+
+    /// Items produced, summed over all outputs.
+    pub items_out: u64,
+
+    /// Average fill level of the input buffers when read, from 0 to 1.
+    /// Inputs that stay near full mean this block is a bottleneck.
+    /// Zero for blocks without stream inputs.
+    pub input_fill: f64,
+}
+
+/** Stats of a running graph.
+
+Read them with [StatsHandle::get], also after the graph is done.
+With the `json` feature they can be saved as JSON, e.g. to track
+performance regressions between versions.
+
+```
+use rustradio::graph::Graph;
+use rustradio::blocks::{NullSink, VectorSource};
+let src = Box::new(VectorSource::new(vec![1u8, 2, 3]));
+let sink = Box::new(NullSink::new(src.out()));
+let mut g = Graph::new();
+g.add(src);
+g.add(sink);
+let stats = g.stats_handle();
+g.run()?;
+let s = stats.get();
+assert_eq!(s.blocks[0].items_out, 3);
+assert_eq!(s.blocks[1].items_in, 3);
+#[cfg(feature = "json")]
+println!("{}", s.to_json());
+# Ok::<(), anyhow::Error>(())
+```
+*/
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    /// Seconds since the graph started.
+    pub elapsed: f64,
+
+    /// Per block stats, in the order the blocks were added.
+    pub blocks: Vec<BlockStats>,
+}
+
+#[cfg(feature = "json")]
+impl Stats {
+    /// Return the stats as pretty printed JSON.
+    pub fn to_json(&self) -> String {
+        // unwrap: serializing plain structs can't fail.
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Parse stats saved with [Stats::to_json].
+    pub fn from_json(s: &str) -> Result<Self> {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+/** Handle for reading stats while a graph is running.
+
+Stats are updated every 100ms.
+*/
+#[derive(Clone, Default)]
+pub struct StatsHandle {
+    inner: Arc<Mutex<Stats>>,
+}
+
+impl StatsHandle {
+    /// Get the latest stats.
+    pub fn get(&self) -> Stats {
+        self.inner.lock().unwrap().clone()
+    }
+
+    pub(crate) fn init(&self, names: impl Iterator<Item = String>) {
+        let mut s = self.inner.lock().unwrap();
+        s.elapsed = 0.0;
+        s.blocks = names
+            .map(|name| BlockStats {
+                name,
+                ..Default::default()
+            })
+            .collect();
+    }
+
+    pub(crate) fn set(&self, n: usize, time: Duration, counts: &WorkCounts, elapsed: Duration) {
+        let mut s = self.inner.lock().unwrap();
+        s.elapsed = s.elapsed.max(elapsed.as_secs_f64());
+        if let Some(b) = s.blocks.get_mut(n) {
+            b.seconds = time.as_secs_f64();
+            b.work_calls = counts.calls;
+            b.items_in = counts.items_in;
+            b.items_out = counts.items_out;
+            b.input_fill = if counts.reads == 0 {
+                0.0
+            } else {
+                counts.fill / counts.reads as f64
+            };
+        }
+    }
+}
+
 /** A handle to be able to stop the Graph. For example when the user
 presses Ctrl-C.
 
@@ -235,3 +700,327 @@ impl Default for CancellationToken {
         Self::new()
     }
 }
+
+/** A handle to pause and resume a graph, e.g. from a UI thread.
+
+```
+use rustradio::graph::Graph;
+let g = Graph::new();
+let token = g.pause_token();
+token.pause();
+assert!(g.is_paused());
+token.resume();
+assert!(!g.is_paused());
+```
+*/
+#[derive(Clone, Default)]
+pub struct PauseToken {
+    inner: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl PauseToken {
+    /// Create new pause token, not paused.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pause the graph.
+    pub fn pause(&self) {
+        self.inner.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Resume the graph.
+    pub fn resume(&self) {
+        self.inner.store(false, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Check if the graph is paused.
+    pub fn is_paused(&self) -> bool {
+        self.inner.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{AddConst, MultiplyConst, NullSink, VectorSink, VectorSource};
+    use crate::mtgraph::MTGraph;
+    use crate::stream::{new_streamp, Streamp};
+    use crate::{Error, Float};
+
+    struct Failing;
+
+    impl Block for Failing {
+        fn block_name(&self) -> &str {
+            "Failing"
+        }
+        fn work(&mut self) -> Result<BlockRet, Error> {
+            Err(Error::BadParameter("nope".to_string()))
+        }
+    }
+
+    fn check(e: anyhow::Error) {
+        let e = e.downcast::<Error>().unwrap();
+        assert!(
+            matches!(&e, Error::Block { name, index: 2, .. } if name == "Failing"),
+            "{e:?}"
+        );
+        assert!(matches!(e.root(), Error::BadParameter(m) if m == "nope"));
+        assert_eq!(e.to_string(), "block 2 (Failing): bad parameter: nope");
+    }
+
+    #[test]
+    fn block_error() {
+        let src = VectorSource::new(vec![1.0f32; 10]);
+        let sink = NullSink::new(src.out());
+        let mut g = Graph::new();
+        g.add(Box::new(src));
+        g.add(Box::new(sink));
+        g.add(Box::new(Failing));
+        check(g.run().unwrap_err());
+    }
+
+    #[test]
+    fn mt_groups() -> Result<()> {
+        let src = VectorSource::new((0..10_000).map(|n| n as Float).collect());
+        let add = AddConst::new(src.out(), 1.0);
+        let mul = MultiplyConst::new(add.out(), 2.0);
+        let sink = VectorSink::new(mul.out());
+        let h = sink.handle();
+        let mut g = MTGraph::new();
+        g.add(Box::new(src));
+        g.add_group(vec![Box::new(add), Box::new(mul)]);
+        g.add(Box::new(sink));
+        let stats = g.stats_handle();
+        g.run()?;
+        let want: Vec<Float> = (0..10_000).map(|n| 2.0 * (n as Float + 1.0)).collect();
+        assert_eq!(h.data(), want);
+        let names: Vec<String> = stats.get().blocks.into_iter().map(|b| b.name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "VectorSource",
+                "AddConst<T>",
+                "MultiplyConst<T>",
+                "VectorSink"
+            ]
+        );
+
+        // Errors are reported with the index in the graph, also from
+        // a group.
+        let src = VectorSource::new(vec![1.0f32; 10]);
+        let sink = NullSink::new(src.out());
+        let mut g = MTGraph::new();
+        g.add_group(vec![Box::new(src), Box::new(sink), Box::new(Failing)]);
+        check(g.run().unwrap_err());
+        Ok(())
+    }
+
+    #[test]
+    fn block_error_mt() {
+        let src = VectorSource::new(vec![1.0f32; 10]);
+        let sink = NullSink::new(src.out());
+        let mut g = MTGraph::new();
+        g.add(Box::new(src));
+        g.add(Box::new(sink));
+        g.add(Box::new(Failing));
+        check(g.run().unwrap_err());
+    }
+
+    // Writes 50 items per call.
+    struct Trickle {
+        left: usize,
+        dst: Streamp<u8>,
+    }
+
+    impl Block for Trickle {
+        fn block_name(&self) -> &str {
+            "Trickle"
+        }
+        fn work(&mut self) -> Result<BlockRet, Error> {
+            if self.left == 0 {
+                return Ok(BlockRet::EOF);
+            }
+            let n = std::cmp::min(50, self.left);
+            self.dst.write_buf()?.produce(n, &[]);
+            self.left -= n;
+            Ok(BlockRet::Ok)
+        }
+    }
+
+    // Records the size of every chunk it gets.
+    struct Recorder {
+        src: Streamp<u8>,
+        chunks: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl Block for Recorder {
+        fn block_name(&self) -> &str {
+            "Recorder"
+        }
+        fn work(&mut self) -> Result<BlockRet, Error> {
+            let (i, _) = self.src.read_buf()?;
+            let n = i.len();
+            if n == 0 {
+                return Ok(BlockRet::Noop);
+            }
+            self.chunks.lock().unwrap().push(n);
+            i.consume(n);
+            Ok(BlockRet::Ok)
+        }
+    }
+
+    fn trickle() -> (Trickle, Recorder, Arc<Mutex<Vec<usize>>>) {
+        let src = Trickle {
+            left: 1000,
+            dst: new_streamp(),
+        };
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let sink = Recorder {
+            src: src.dst.clone(),
+            chunks: chunks.clone(),
+        };
+        (src, sink, chunks)
+    }
+
+    fn chunked(mt: bool, hints: WorkHints) -> Result<Vec<usize>> {
+        let (src, sink, chunks) = trickle();
+        if mt {
+            let mut g = MTGraph::new();
+            g.add(Box::new(src));
+            g.add_with_hints(Box::new(sink), hints);
+            g.run()?;
+        } else {
+            let mut g = Graph::new();
+            g.add(Box::new(src));
+            g.add_with_hints(Box::new(sink), hints);
+            g.run()?;
+        }
+        let chunks = chunks.lock().unwrap().clone();
+        assert_eq!(chunks.iter().sum::<usize>(), 1000, "{chunks:?}");
+        Ok(chunks)
+    }
+
+    #[test]
+    fn work_hints() -> Result<()> {
+        assert_eq!(chunked(false, WorkHints::new())?, vec![50; 20]);
+
+        // Chunks are held back until there are 120, except the last
+        // one, which is released once the input stops growing.
+        let mut want = vec![150; 6];
+        want.push(100);
+        assert_eq!(chunked(false, WorkHints::new().min_items(120))?, want);
+
+        let chunks = chunked(false, WorkHints::new().max_items(20))?;
+        assert!(chunks.iter().all(|&n| n <= 20), "{chunks:?}");
+        let chunks = chunked(true, WorkHints::new().max_items(20))?;
+        assert!(chunks.iter().all(|&n| n <= 20), "{chunks:?}");
+
+        // With threads, chunk sizes depend on timing. Just check that
+        // everything arrives.
+        chunked(true, WorkHints::new().min_items(120))?;
+        Ok(())
+    }
+
+    #[test]
+    fn pause_step() -> Result<()> {
+        let (src, sink, chunks) = trickle();
+        let mut g = Graph::new();
+        g.add(Box::new(src));
+        g.add(Box::new(sink));
+        g.pause();
+        assert!(g.is_paused());
+        assert_eq!(g.run_once()?, RunState::Idle);
+        assert_eq!(g.run_for(Duration::from_millis(20))?, RunState::Idle);
+        assert!(chunks.lock().unwrap().is_empty());
+        assert_eq!(g.step()?, RunState::Busy);
+        assert_eq!(*chunks.lock().unwrap(), [50]);
+        g.resume();
+        assert_eq!(g.run_for(Duration::from_secs(10))?, RunState::Done);
+        assert_eq!(chunks.lock().unwrap().iter().sum::<usize>(), 1000);
+        Ok(())
+    }
+
+    #[test]
+    fn run_for() -> Result<()> {
+        let src = crate::blocks::ConstantSource::new(1.0 as Float);
+        let sink = NullSink::new(src.out());
+        let mut g = Graph::new();
+        g.add(Box::new(src));
+        g.add(Box::new(sink));
+        let st = Instant::now();
+        assert_eq!(g.run_for(Duration::from_millis(20))?, RunState::Busy);
+        assert_eq!(g.run_for(Duration::from_millis(20))?, RunState::Busy);
+        let elapsed = st.elapsed();
+        assert!(elapsed >= Duration::from_millis(40), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(5), "{elapsed:?}");
+        g.cancel_token().cancel();
+        assert_eq!(g.run_for(Duration::from_secs(10))?, RunState::Done);
+        Ok(())
+    }
+
+    #[test]
+    fn pause_mt() -> Result<()> {
+        let (src, sink, chunks) = trickle();
+        let mut g = MTGraph::new();
+        g.add(Box::new(src));
+        g.add(Box::new(sink));
+        g.pause();
+        let pause = g.pause_token();
+        let c = chunks.clone();
+        let th = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            let empty = c.lock().unwrap().is_empty();
+            pause.resume();
+            empty
+        });
+        g.run()?;
+        assert!(th.join().unwrap());
+        assert!(!g.is_paused());
+        assert_eq!(chunks.lock().unwrap().iter().sum::<usize>(), 1000);
+        Ok(())
+    }
+
+    #[test]
+    fn block_stats() -> Result<()> {
+        for mt in [false, true] {
+            let (src, sink, chunks) = trickle();
+            let stats = if mt {
+                let mut g = MTGraph::new();
+                g.add(Box::new(src));
+                g.add(Box::new(sink));
+                g.run()?;
+                g.stats_handle().get()
+            } else {
+                let mut g = Graph::new();
+                g.add(Box::new(src));
+                g.add(Box::new(sink));
+                g.run()?;
+                g.stats_handle().get()
+            };
+            let (src, sink) = (&stats.blocks[0], &stats.blocks[1]);
+            assert_eq!(src.name, "Trickle");
+            // 20 chunks, and the EOF.
+            assert_eq!(src.work_calls, 21, "{stats:?}");
+            assert_eq!((src.items_in, src.items_out), (0, 1000));
+            assert_eq!(src.input_fill, 0.0);
+            assert_eq!((sink.items_in, sink.items_out), (1000, 0));
+            assert!(sink.work_calls >= chunks.lock().unwrap().len() as u64);
+            assert!(sink.input_fill > 0.0 && sink.input_fill < 0.01, "{stats:?}");
+
+            #[cfg(feature = "json")]
+            {
+                let got = Stats::from_json(&stats.to_json())?;
+                assert_eq!(got.blocks.len(), 2);
+                for (a, b) in got.blocks.iter().zip(&stats.blocks) {
+                    assert_eq!(
+                        (&a.name, a.work_calls, a.items_in, a.items_out),
+                        (&b.name, b.work_calls, b.items_in, b.items_out)
+                    );
+                    assert!((a.input_fill - b.input_fill).abs() < 1e-12);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1154 @@
+/*! Import GNU Radio Companion `.grc` files.
+
+Simple GRC receivers, like reading a file, filtering, demodulating, and
+writing to a file, can be run natively by converting them to a
+[GraphSpec] of [registry](crate::registry) blocks.
+
+```no_run
+use rustradio::registry::{default_registry, DynGraph};
+
+let spec = rustradio::grc::load("fm.grc")?;
+let mut g = DynGraph::from_spec(default_registry(), &spec)?;
+g.start()?;
+g.wait()?;
+# Ok::<(), anyhow::Error>(())
+```
+
+Only a subset of blocks are supported:
+
+| GRC block                      | rustradio block          |
+|--------------------------------|--------------------------|
+| `blocks_file_source`           | `file_source_*`          |
+| `blocks_file_sink`             | `file_sink_*`            |
+| `blocks_null_sink`             | `null_sink_*`            |
+| `blocks_add_const_vxx`         | `add_const_*`            |
+| `blocks_multiply_const_vxx`    | `multiply_const_*`       |
+| `blocks_delay`                 | `delay_*`                |
+| `blocks_skiphead`              | `skip_*`                 |
+| `blocks_complex_to_mag_squared`| `complex_to_mag2`        |
+| `blocks_float_to_complex`      | `float_to_complex`       |
+| `analog_sig_source_x`          | `signal_source`          |
+| `analog_quadrature_demod_cf`   | `quadrature_demod`       |
+| `digital_binary_slicer_fb`     | `binary_slicer`          |
+| `hilbert_fc`                   | `hilbert`                |
+| `rational_resampler_xxx`       | `rational_resampler_*`   |
+| `fir_filter_xxx`               | `fft_filter_*`           |
+| `fft_filter_xxx`               | `fft_filter_*`           |
+| `low_pass_filter`              | `fft_filter_*`           |
+
+Only complex, float, and byte streams are supported, and filters can't
+decimate. Taps can be a list, or `firdes.low_pass()` with gain 1.
+
+`blocks_throttle` is left out, since it does nothing useful outside of
+GRC simulations. So are bypassed blocks. Disabled blocks are ignored,
+and so are Qt GUI blocks, with a warning.
+
+Parameters can refer to variables, and use simple arithmetic. Other
+Python expressions are not supported.
+*/
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use log::warn;
+
+use crate::registry::{default_registry, BlockSpec, GraphSpec, Params, StreamKind};
+use crate::Error;
+
+// A parsed YAML value. Scalars are kept as strings.
+#[derive(Debug, Clone, PartialEq)]
+enum Yaml {
+    Str(String),
+    List(Vec<Yaml>),
+    Map(Vec<(String, Yaml)>),
+}
+
+impl Yaml {
+    fn get(&self, key: &str) -> Option<&Yaml> {
+        match self {
+            Yaml::Map(m) => m.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Yaml::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+    fn as_list(&self) -> &[Yaml] {
+        match self {
+            Yaml::List(l) => l,
+            _ => &[],
+        }
+    }
+}
+
+fn bad(msg: impl std::fmt::Display) -> Error {
+    Error::BadParameter(format!("grc: {msg}"))
+}
+
+/* Parser for the subset of YAML that GRC writes.
+
+That's block style maps and lists, with flow style lists of scalars
+(like connections), and plain, quoted, or block scalars.
+*/
+struct YamlParser {
+    lines: Vec<String>,
+    pos: usize,
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+// Position of the `:` ending a `key:` at the start of `s`, if any.
+fn key_end(s: &str) -> Option<usize> {
+    if s.starts_with(['\'', '"', '[', '{']) {
+        return None;
+    }
+    let n = s.find(':')?;
+    match s[n + 1..].chars().next() {
+        None | Some(' ') => Some(n),
+        _ => None,
+    }
+}
+
+// Index of the closing quote of the quoted scalar starting `s`.
+fn quote_end(s: &str) -> Option<usize> {
+    let q = s.chars().next()?;
+    let mut it = s.char_indices().skip(1).peekable();
+    while let Some((i, c)) = it.next() {
+        match c {
+            '\\' if q == '"' => {
+                it.next();
+            }
+            '\'' if q == '\'' => {
+                if it.peek().map(|(_, c)| *c) == Some('\'') {
+                    it.next();
+                } else {
+                    return Some(i);
+                }
+            }
+            c if c == q => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn unquote(s: &str) -> String {
+    match s.chars().next() {
+        Some('\'') => s[1..s.len() - 1].replace("''", "'"),
+        Some('"') => {
+            let mut ret = String::new();
+            let mut it = s[1..s.len() - 1].chars();
+            while let Some(c) = it.next() {
+                if c != '\\' {
+                    ret.push(c);
+                    continue;
+                }
+                match it.next() {
+                    Some('n') => ret.push('\n'),
+                    Some('t') => ret.push('\t'),
+                    Some(c) => ret.push(c),
+                    None => {}
+                }
+            }
+            ret
+        }
+        _ => s.to_string(),
+    }
+}
+
+// Split a flow list on top level commas.
+fn split_top(s: &str) -> Vec<&str> {
+    let mut ret = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '[' | '{' | '(') => depth += 1,
+            (None, ']' | '}' | ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                ret.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = s[start..].trim();
+    if !last.is_empty() || !ret.is_empty() {
+        ret.push(last);
+    }
+    ret
+}
+
+impl YamlParser {
+    fn new(text: &str) -> Self {
+        Self {
+            lines: text.lines().map(|s| s.trim_end().to_string()).collect(),
+            pos: 0,
+        }
+    }
+
+    fn skip_blank(&mut self) {
+        while let Some(l) = self.lines.get(self.pos) {
+            let t = l.trim_start();
+            if !t.is_empty() && !t.starts_with('#') && t != "---" {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<(usize, String)> {
+        self.skip_blank();
+        let l = self.lines.get(self.pos)?;
+        Some((indent_of(l), l.trim_start().to_string()))
+    }
+
+    fn is_item(s: &str) -> bool {
+        s == "-" || s.starts_with("- ")
+    }
+
+    fn parse(mut self) -> Result<Yaml, Error> {
+        let ret = match self.peek() {
+            None => return Ok(Yaml::Map(Vec::new())),
+            Some((i, s)) if Self::is_item(&s) => self.parse_list(i)?,
+            Some((i, _)) => self.parse_map(i)?,
+        };
+        if self.peek().is_some() {
+            return Err(bad(format!("yaml: unexpected line {}", self.pos + 1)));
+        }
+        Ok(ret)
+    }
+
+    // Value of a key or list item with nothing after it on the line.
+    fn parse_nested(&mut self, parent: usize) -> Result<Yaml, Error> {
+        match self.peek() {
+            Some((i, s)) if Self::is_item(&s) && i >= parent => self.parse_list(i),
+            Some((i, _)) if i > parent => self.parse_map(i),
+            _ => Ok(Yaml::Str(String::new())),
+        }
+    }
+
+    fn parse_map(&mut self, ind: usize) -> Result<Yaml, Error> {
+        let mut ret = Vec::new();
+        while let Some((i, s)) = self.peek() {
+            if i < ind || (i == ind && Self::is_item(&s)) {
+                break;
+            }
+            if i > ind {
+                return Err(bad(format!(
+                    "yaml: bad indentation on line {}",
+                    self.pos + 1
+                )));
+            }
+            let n = key_end(&s)
+                .ok_or_else(|| bad(format!("yaml: expected key on line {}", self.pos + 1)))?;
+            let key = unquote(s[..n].trim());
+            let rest = s[n + 1..].trim().to_string();
+            self.pos += 1;
+            let v = self.parse_value(&rest, ind)?;
+            ret.push((key, v));
+        }
+        Ok(Yaml::Map(ret))
+    }
+
+    fn parse_list(&mut self, ind: usize) -> Result<Yaml, Error> {
+        let mut ret = Vec::new();
+        while let Some((i, s)) = self.peek() {
+            if i != ind || !Self::is_item(&s) {
+                break;
+            }
+            let rest = s[1..].trim_start();
+            if rest.is_empty() {
+                self.pos += 1;
+                ret.push(self.parse_nested(ind + 1)?);
+            } else if key_end(rest).is_some() {
+                // Map starting on the same line as the dash. Blank out
+                // the dash, and parse it as a map at the key's indent.
+                let l = &mut self.lines[self.pos];
+                let keypos = l.len() - rest.len();
+                l.replace_range(..keypos, &" ".repeat(keypos));
+                ret.push(self.parse_map(keypos)?);
+            } else {
+                let rest = rest.to_string();
+                self.pos += 1;
+                ret.push(self.parse_value(&rest, ind)?);
+            }
+        }
+        Ok(Yaml::List(ret))
+    }
+
+    // Parse the rest of the line after `key:` or `-`, plus any
+    // continuation lines.
+    fn parse_value(&mut self, rest: &str, ind: usize) -> Result<Yaml, Error> {
+        if rest.is_empty() {
+            return self.parse_nested(ind);
+        }
+        if rest.starts_with(['|', '>']) {
+            return Ok(Yaml::Str(self.block_scalar(ind, rest.starts_with('|'))));
+        }
+        let mut s = rest.to_string();
+        if s.starts_with(['\'', '"']) {
+            while quote_end(&s).is_none() {
+                let next = self
+                    .lines
+                    .get(self.pos)
+                    .ok_or_else(|| bad("yaml: unterminated string"))?
+                    .trim();
+                match next.is_empty() {
+                    true => s.push('\n'),
+                    false if s.ends_with('\n') => s.push_str(next),
+                    false => {
+                        s.push(' ');
+                        s.push_str(next);
+                    }
+                }
+                self.pos += 1;
+            }
+            return Ok(Yaml::Str(unquote(&s[..=quote_end(&s).unwrap()])));
+        }
+        if s.starts_with('[') {
+            while !s.ends_with(']') {
+                let next = self
+                    .lines
+                    .get(self.pos)
+                    .ok_or_else(|| bad("yaml: unterminated list"))?;
+                s.push(' ');
+                s.push_str(next.trim());
+                self.pos += 1;
+            }
+            return Ok(Yaml::List(
+                split_top(&s[1..s.len() - 1])
+                    .into_iter()
+                    .map(|v| Yaml::Str(unquote(v)))
+                    .collect(),
+            ));
+        }
+        if s == "{}" {
+            return Ok(Yaml::Map(Vec::new()));
+        }
+        // Plain scalar, possibly folded over more indented lines.
+        if let Some(n) = s.find(" #") {
+            s.truncate(n);
+        }
+        while let Some((i, next)) = self.peek() {
+            if i <= ind {
+                break;
+            }
+            s.push(' ');
+            s.push_str(&next);
+            self.pos += 1;
+        }
+        Ok(Yaml::Str(s))
+    }
+
+    fn block_scalar(&mut self, ind: usize, literal: bool) -> String {
+        let mut lines = Vec::new();
+        while let Some(l) = self.lines.get(self.pos) {
+            if !l.trim().is_empty() && indent_of(l) <= ind {
+                break;
+            }
+            lines.push(l.clone());
+            self.pos += 1;
+        }
+        while lines.last().is_some_and(|l| l.trim().is_empty()) {
+            lines.pop();
+        }
+        let strip = lines
+            .iter()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| indent_of(l))
+            .min()
+            .unwrap_or(0);
+        let lines: Vec<_> = lines.iter().map(|l| l.get(strip..).unwrap_or("")).collect();
+        lines.join(if literal { "\n" } else { " " })
+    }
+}
+
+// Evaluator for the arithmetic subset of Python used in parameters.
+struct Eval<'a> {
+    vars: &'a HashMap<String, String>,
+    // Variables being evaluated, to detect loops.
+    stack: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(&'static str),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, Error> {
+    let mut ret = Vec::new();
+    let b = s.as_bytes();
+    let mut i = 0;
+    while i < b.len() {
+        let c = b[i] as char;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || (c == '.' && b.get(i + 1).is_some_and(u8::is_ascii_digit)) {
+            let start = i;
+            while i < b.len()
+                && ((b[i] as char).is_ascii_alphanumeric()
+                    || b[i] == b'.'
+                    || b[i] == b'_'
+                    || ((b[i] == b'-' || b[i] == b'+') && matches!(b[i - 1], b'e' | b'E')))
+            {
+                i += 1;
+            }
+            let num = s[start..i].replace('_', "");
+            ret.push(Token::Num(
+                num.parse()
+                    .map_err(|_| bad(format!("bad number {num:?} in {s:?}")))?,
+            ));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < b.len()
+                && ((b[i] as char).is_ascii_alphanumeric() || b[i] == b'_' || b[i] == b'.')
+            {
+                i += 1;
+            }
+            ret.push(Token::Ident(s[start..i].to_string()));
+        } else {
+            let op = ["**", "//", "+", "-", "*", "/", "%", "(", ")", ","]
+                .into_iter()
+                .find(|op| s[i..].starts_with(op))
+                .ok_or_else(|| bad(format!("unsupported expression {s:?}")))?;
+            ret.push(Token::Op(op));
+            i += op.len();
+        }
+    }
+    Ok(ret)
+}
+
+impl<'a> Eval<'a> {
+    fn new(vars: &'a HashMap<String, String>) -> Self {
+        Self {
+            vars,
+            stack: Vec::new(),
+        }
+    }
+
+    fn eval(&mut self, s: &str) -> Result<f64, Error> {
+        let toks = tokenize(s)?;
+        let mut pos = 0;
+        let v = self.expr(&toks, &mut pos)?;
+        if pos != toks.len() {
+            return Err(bad(format!("unsupported expression {s:?}")));
+        }
+        Ok(v)
+    }
+
+    fn var(&mut self, name: &str) -> Result<f64, Error> {
+        match name {
+            "math.pi" | "numpy.pi" | "np.pi" | "pi" => return Ok(std::f64::consts::PI),
+            "True" => return Ok(1.0),
+            "False" => return Ok(0.0),
+            _ => {}
+        }
+        let expr = self
+            .vars
+            .get(name)
+            .ok_or_else(|| bad(format!("unknown variable {name}")))?;
+        if self.stack.iter().any(|v| v == name) {
+            return Err(bad(format!("variable {name} refers to itself")));
+        }
+        self.stack.push(name.to_string());
+        let v = self.eval(expr);
+        self.stack.pop();
+        v
+    }
+
+    fn expr(&mut self, t: &[Token], pos: &mut usize) -> Result<f64, Error> {
+        let mut v = self.term(t, pos)?;
+        loop {
+            match t.get(*pos) {
+                Some(Token::Op("+")) => {
+                    *pos += 1;
+                    v += self.term(t, pos)?;
+                }
+                Some(Token::Op("-")) => {
+                    *pos += 1;
+                    v -= self.term(t, pos)?;
+                }
+                _ => return Ok(v),
+            }
+        }
+    }
+
+    fn term(&mut self, t: &[Token], pos: &mut usize) -> Result<f64, Error> {
+        let mut v = self.unary(t, pos)?;
+        loop {
+            let op = match t.get(*pos) {
+                Some(Token::Op(op @ ("*" | "/" | "//" | "%"))) => *op,
+                _ => return Ok(v),
+            };
+            *pos += 1;
+            let r = self.unary(t, pos)?;
+            v = match op {
+                "*" => v * r,
+                "/" => v / r,
+                "//" => (v / r).floor(),
+                _ => v.rem_euclid(r),
+            };
+        }
+    }
+
+    fn unary(&mut self, t: &[Token], pos: &mut usize) -> Result<f64, Error> {
+        match t.get(*pos) {
+            Some(Token::Op("-")) => {
+                *pos += 1;
+                Ok(-self.unary(t, pos)?)
+            }
+            Some(Token::Op("+")) => {
+                *pos += 1;
+                self.unary(t, pos)
+            }
+            _ => {
+                let v = self.atom(t, pos)?;
+                if t.get(*pos) == Some(&Token::Op("**")) {
+                    *pos += 1;
+                    return Ok(v.powf(self.unary(t, pos)?));
+                }
+                Ok(v)
+            }
+        }
+    }
+
+    fn atom(&mut self, t: &[Token], pos: &mut usize) -> Result<f64, Error> {
+        let tok = t.get(*pos).ok_or_else(|| bad("incomplete expression"))?;
+        *pos += 1;
+        match tok {
+            Token::Num(n) => Ok(*n),
+            Token::Op("(") => {
+                let v = self.expr(t, pos)?;
+                self.expect(t, pos, ")")?;
+                Ok(v)
+            }
+            Token::Ident(f) if t.get(*pos) == Some(&Token::Op("(")) => {
+                *pos += 1;
+                let v = self.expr(t, pos)?;
+                self.expect(t, pos, ")")?;
+                match f.as_str() {
+                    "int" => Ok(v.trunc()),
+                    "float" => Ok(v),
+                    "abs" => Ok(v.abs()),
+                    "round" => Ok(v.round()),
+                    _ => Err(bad(format!("unsupported function {f}"))),
+                }
+            }
+            Token::Ident(name) => self.var(name),
+            Token::Op(op) => Err(bad(format!("unexpected {op:?}"))),
+        }
+    }
+
+    fn expect(&self, t: &[Token], pos: &mut usize, op: &str) -> Result<(), Error> {
+        match t.get(*pos) {
+            Some(Token::Op(o)) if *o == op => {
+                *pos += 1;
+                Ok(())
+            }
+            _ => Err(bad(format!("expected {op:?}"))),
+        }
+    }
+}
+
+// How a GRC block is converted.
+enum Conv {
+    // Registry block.
+    Block(String, Params),
+    // Output 0 is input 0.
+    Pass,
+    // Not part of the stream graph.
+    Ignore,
+}
+
+struct GrcBlock<'a> {
+    name: &'a str,
+    id: &'a str,
+    params: BTreeMap<&'a str, &'a str>,
+}
+
+impl GrcBlock<'_> {
+    fn param(&self, key: &str) -> Result<&str, Error> {
+        self.params
+            .get(key)
+            .copied()
+            .ok_or_else(|| bad(format!("{}: missing parameter {key}", self.name)))
+    }
+
+    // Type suffix, from a GRC type like `complex`, or `ccf`.
+    fn suffix(&self, key: &str) -> Result<&'static str, Error> {
+        let t = self.param(key)?;
+        Ok(match t {
+            "complex" | "ccc" | "ccf" | "fir_filter_ccf" => "c",
+            "float" | "fff" | "fir_filter_fff" => "f",
+            "byte" => "b",
+            _ => return Err(bad(format!("{}: unsupported type {t}", self.name))),
+        })
+    }
+
+    fn bool(&self, key: &str) -> bool {
+        self.params.get(key) == Some(&"True")
+    }
+}
+
+fn num(v: f64) -> serde_json::Value {
+    if v.fract() == 0.0 && v.abs() < 1e15 {
+        serde_json::Value::from(v as i64)
+    } else {
+        serde_json::Value::from(v)
+    }
+}
+
+fn uint(b: &GrcBlock, ev: &mut Eval, key: &str) -> Result<serde_json::Value, Error> {
+    let v = ev.eval(b.param(key)?)?;
+    if v < 0.0 || v.fract() != 0.0 {
+        return Err(bad(format!("{}: {key} must be a whole number", b.name)));
+    }
+    Ok(num(v))
+}
+
+fn params(v: &[(&str, serde_json::Value)]) -> Params {
+    v.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()
+}
+
+// Parameters for `fft_filter_*`, from GRC taps.
+fn taps(b: &GrcBlock, ev: &mut Eval, taps: &str) -> Result<Params, Error> {
+    let taps = taps.trim();
+    if let Some(args) = taps
+        .strip_prefix("firdes.low_pass(")
+        .and_then(|s| s.strip_suffix(')'))
+    {
+        let args = split_top(args);
+        if args.len() < 4 {
+            return Err(bad(format!("{}: bad low_pass() call", b.name)));
+        }
+        let args = args[..4]
+            .iter()
+            .map(|a| ev.eval(a))
+            .collect::<Result<Vec<_>, _>>()?;
+        if args[0] != 1.0 {
+            return Err(bad(format!("{}: only low_pass() gain 1 supported", b.name)));
+        }
+        return Ok(params(&[
+            ("samp_rate", num(args[1])),
+            ("cutoff", num(args[2])),
+            ("twidth", num(args[3])),
+        ]));
+    }
+    if let Some(list) = taps.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        let v = split_top(list)
+            .into_iter()
+            .map(|t| ev.eval(t).map(num))
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(params(&[("taps", serde_json::Value::Array(v))]));
+    }
+    Err(bad(format!("{}: unsupported taps {taps:?}", b.name)))
+}
+
+fn no_decimation(b: &GrcBlock, ev: &mut Eval, key: &str) -> Result<(), Error> {
+    match b.params.get(key) {
+        Some(d) if ev.eval(d)? != 1.0 => {
+            Err(bad(format!("{}: decimating filters not supported", b.name)))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn convert(b: &GrcBlock, ev: &mut Eval) -> Result<Conv, Error> {
+    let block = |name: String, p: &[(&str, serde_json::Value)]| -> Result<Conv, Error> {
+        Ok(Conv::Block(name, params(p)))
+    };
+    let text = |s: &str| serde_json::Value::from(s);
+    match b.id {
+        "options" | "import" | "note" | "parameter" | "snippet" => Ok(Conv::Ignore),
+        id if id.starts_with("variable") => Ok(Conv::Ignore),
+        id if id.starts_with("qtgui_") => {
+            warn!("GRC: ignoring GUI block {}", b.name);
+            Ok(Conv::Ignore)
+        }
+        "blocks_throttle" | "blocks_throttle2" => Ok(Conv::Pass),
+        "blocks_file_source" => block(
+            format!("file_source_{}", b.suffix("type")?),
+            &[
+                ("filename", text(b.param("file")?)),
+                ("repeat", b.bool("repeat").into()),
+            ],
+        ),
+        "blocks_file_sink" => block(
+            format!("file_sink_{}", b.suffix("type")?),
+            &[
+                ("filename", text(b.param("file")?)),
+                (
+                    "mode",
+                    text(if b.bool("append") {
+                        "append"
+                    } else {
+                        "overwrite"
+                    }),
+                ),
+            ],
+        ),
+        "blocks_null_sink" => block(format!("null_sink_{}", b.suffix("type")?), &[]),
+        "blocks_add_const_vxx" => block(
+            format!("add_const_{}", b.suffix("type")?),
+            &[("value", num(ev.eval(b.param("const")?)?))],
+        ),
+        "blocks_multiply_const_vxx" => block(
+            format!("multiply_const_{}", b.suffix("type")?),
+            &[("value", num(ev.eval(b.param("const")?)?))],
+        ),
+        "blocks_delay" => block(
+            format!("delay_{}", b.suffix("type")?),
+            &[("delay", uint(b, ev, "delay")?)],
+        ),
+        "blocks_skiphead" => block(
+            format!("skip_{}", b.suffix("type")?),
+            &[("skip", uint(b, ev, "num_items")?)],
+        ),
+        "blocks_complex_to_mag_squared" => block("complex_to_mag2".into(), &[]),
+        "blocks_float_to_complex" => block("float_to_complex".into(), &[]),
+        "digital_binary_slicer_fb" => block("binary_slicer".into(), &[]),
+        "hilbert_fc" => block("hilbert".into(), &[("ntaps", uint(b, ev, "num_taps")?)]),
+        "analog_quadrature_demod_cf" => block(
+            "quadrature_demod".into(),
+            &[("gain", num(ev.eval(b.param("gain")?)?))],
+        ),
+        "analog_sig_source_x" => {
+            if b.suffix("type")? != "c" || b.param("waveform")? != "analog.GR_COS_WAVE" {
+                return Err(bad(format!("{}: only complex cosine supported", b.name)));
+            }
+            if ev.eval(b.params.get("offset").unwrap_or(&"0"))? != 0.0 {
+                return Err(bad(format!("{}: offset not supported", b.name)));
+            }
+            block(
+                "signal_source".into(),
+                &[
+                    ("samp_rate", num(ev.eval(b.param("samp_rate")?)?)),
+                    ("freq", num(ev.eval(b.param("freq")?)?)),
+                    ("amplitude", num(ev.eval(b.param("amp")?)?)),
+                ],
+            )
+        }
+        "rational_resampler_xxx" => {
+            if !matches!(b.params.get("taps"), None | Some(&"") | Some(&"[]")) {
+                return Err(bad(format!("{}: resampler taps not supported", b.name)));
+            }
+            block(
+                format!("rational_resampler_{}", b.suffix("type")?),
+                &[
+                    ("interp", uint(b, ev, "interp")?),
+                    ("deci", uint(b, ev, "decim")?),
+                ],
+            )
+        }
+        "fir_filter_xxx" | "fft_filter_xxx" => {
+            no_decimation(b, ev, "decim")?;
+            let p = taps(b, ev, b.param("taps")?)?;
+            Ok(Conv::Block(format!("fft_filter_{}", b.suffix("type")?), p))
+        }
+        "low_pass_filter" => {
+            no_decimation(b, ev, "decim")?;
+            if ev.eval(b.param("gain")?)? != 1.0 {
+                return Err(bad(format!("{}: only gain 1 supported", b.name)));
+            }
+            block(
+                format!("fft_filter_{}", b.suffix("type")?),
+                &[
+                    ("samp_rate", num(ev.eval(b.param("samp_rate")?)?)),
+                    ("cutoff", num(ev.eval(b.param("cutoff_freq")?)?)),
+                    ("twidth", num(ev.eval(b.param("width")?)?)),
+                ],
+            )
+        }
+        id => Err(bad(format!("{}: unsupported block {id}", b.name))),
+    }
+}
+
+fn port(p: &Yaml) -> Result<usize, Error> {
+    let p = p.as_str().unwrap_or_default();
+    p.parse()
+        .map_err(|_| bad(format!("unsupported port {p:?}, only streams supported")))
+}
+
+/// Convert the contents of a `.grc` file to a [GraphSpec].
+pub fn parse(grc: &str) -> Result<GraphSpec, Error> {
+    let yaml = YamlParser::new(grc).parse()?;
+    let empty = Yaml::Map(Vec::new());
+
+    // Enabled blocks, and variables.
+    let mut blocks = Vec::new();
+    let mut vars = HashMap::new();
+    let mut passes = Vec::new();
+    for b in yaml.get("blocks").map(Yaml::as_list).unwrap_or_default() {
+        let get = |k: &str| b.get(k).and_then(Yaml::as_str).unwrap_or_default();
+        let state = b
+            .get("states")
+            .and_then(|s| s.get("state"))
+            .and_then(Yaml::as_str)
+            .unwrap_or("enabled");
+        match state {
+            "disabled" | "false" | "False" => continue,
+            "bypassed" => passes.push(get("name")),
+            _ => {}
+        }
+        let params = match b.get("parameters").unwrap_or(&empty) {
+            Yaml::Map(m) => m
+                .iter()
+                .filter_map(|(k, v)| Some((k.as_str(), v.as_str()?)))
+                .collect(),
+            _ => BTreeMap::new(),
+        };
+        let b = GrcBlock {
+            name: get("name"),
+            id: get("id"),
+            params,
+        };
+        if b.id.starts_with("variable") || b.id == "parameter" {
+            if let Some(v) = b.params.get("value") {
+                vars.insert(b.name.to_string(), v.to_string());
+            }
+        }
+        blocks.push(b);
+    }
+
+    let reg = default_registry();
+    let mut ev = Eval::new(&vars);
+    let mut names = HashMap::new();
+    let mut nodes = Vec::new();
+    for b in &blocks {
+        let conv = match passes.contains(&b.name) {
+            true => Conv::Pass,
+            false => convert(b, &mut ev)?,
+        };
+        if let Conv::Block(reg_name, params) = conv {
+            let info = reg
+                .info(&reg_name)
+                .ok_or_else(|| bad(format!("{}: {reg_name} not available", b.name)))?;
+            names.insert(b.name, Some(nodes.len()));
+            nodes.push((b.name, reg_name, params, info));
+        } else if matches!(conv, Conv::Pass) {
+            names.insert(b.name, None);
+        }
+    }
+
+    // Stream connections, as (block, port), skipping ignored blocks,
+    // and with pass through blocks removed.
+    let mut conns = Vec::new();
+    let mut pass_src = HashMap::new();
+    for c in yaml
+        .get("connections")
+        .map(Yaml::as_list)
+        .unwrap_or_default()
+    {
+        let [src, sport, dst, dport] = c.as_list() else {
+            return Err(bad("bad connection"));
+        };
+        let (src, dst) = (src.as_str().unwrap_or(""), dst.as_str().unwrap_or(""));
+        let (Some(s), Some(d)) = (names.get(src), names.get(dst)) else {
+            continue;
+        };
+        let (sport, dport) = (port(sport)?, port(dport)?);
+        match d {
+            None if dport == 0 => {
+                pass_src.insert(dst, (src, *s, sport));
+            }
+            None => return Err(bad(format!("{dst}: only one input supported"))),
+            Some(d) => conns.push((src, *s, sport, *d, dport)),
+        }
+    }
+    let mut edges = Vec::new();
+    for (mut src, mut s, mut sport, d, dport) in conns {
+        let mut hops = 0;
+        while s.is_none() {
+            (src, s, sport) = *pass_src
+                .get(src)
+                .ok_or_else(|| bad(format!("{src}: input not connected")))?;
+            hops += 1;
+            if hops > blocks.len() {
+                return Err(bad("loop of pass through blocks"));
+            }
+        }
+        edges.push((s.unwrap(), sport, d, dport));
+    }
+
+    // Where each input comes from, and where each output goes.
+    let mut sources = HashMap::new();
+    let mut sinks: HashMap<(usize, usize), Vec<(usize, usize)>> = HashMap::new();
+    for (s, sport, d, dport) in edges {
+        if sport >= nodes[s].3.outputs.len() || dport >= nodes[d].3.inputs.len() {
+            return Err(bad(format!(
+                "no such port in connection {} {sport} -> {} {dport}",
+                nodes[s].0, nodes[d].0
+            )));
+        }
+        if sources.insert((d, dport), (s, sport)).is_some() {
+            return Err(bad(format!(
+                "{}: input {dport} connected twice",
+                nodes[d].0
+            )));
+        }
+        sinks.entry((s, sport)).or_default().push((d, dport));
+    }
+
+    // Add blocks once all their inputs exist, in file order, adding
+    // tees where an output goes to more than one block.
+    let mut spec = GraphSpec::default();
+    let mut streams = HashMap::new();
+    let mut next = 0;
+    let mut done = vec![false; nodes.len()];
+    while let Some(n) = (0..nodes.len()).find(|n| {
+        !done[*n]
+            && (0..nodes[*n].3.inputs.len())
+                .all(|i| sources.get(&(*n, i)).is_some_and(|(s, _)| done[*s]))
+    }) {
+        done[n] = true;
+        let (name, reg_name, params, info) = &nodes[n];
+        spec.blocks.push(BlockSpec {
+            name: reg_name.clone(),
+            inputs: (0..info.inputs.len()).map(|i| streams[&(n, i)]).collect(),
+            params: params.clone(),
+        });
+        for (p, kind) in info.outputs.iter().enumerate() {
+            let mut stream = next;
+            next += 1;
+            let to = sinks.remove(&(n, p)).unwrap_or_default();
+            let Some((last, rest)) = to.split_last() else {
+                return Err(bad(format!("{name}: output {p} not connected")));
+            };
+            for dst in rest {
+                let suffix = match kind {
+                    StreamKind::Complex => "c",
+                    StreamKind::Float => "f",
+                    StreamKind::U8 => "b",
+                    StreamKind::Pdu => {
+                        return Err(bad(format!("{name}: can't split output {p}")));
+                    }
+                };
+                spec.blocks.push(BlockSpec {
+                    name: format!("tee_{suffix}"),
+                    inputs: vec![stream],
+                    params: Params::new(),
+                });
+                streams.insert(*dst, next);
+                stream = next + 1;
+                next += 2;
+            }
+            streams.insert(*last, stream);
+        }
+    }
+    if let Some(n) = done.iter().position(|d| !d) {
+        return Err(bad(format!(
+            "{}: inputs not connected, or connected in a loop",
+            nodes[n].0
+        )));
+    }
+    Ok(spec)
+}
+
+/// Load a `.grc` file, and convert it to a [GraphSpec].
+pub fn load(path: impl AsRef<Path>) -> Result<GraphSpec, Error> {
+    parse(&std::fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaml() -> Result<(), Error> {
+        let y = YamlParser::new(
+            r#"
+options:
+  parameters:
+    title: 'it''s a
+
+      title'
+    code: |-
+      a
+        b
+blocks:
+- name: x
+  id: y
+  states:
+    coordinate: [8, 8.0]
+- name: z
+connections:
+- [x, '0', z, '0']
+"#,
+        )
+        .parse()?;
+        let p = y.get("options").unwrap().get("parameters").unwrap();
+        assert_eq!(p.get("title").unwrap().as_str(), Some("it's a\ntitle"));
+        assert_eq!(p.get("code").unwrap().as_str(), Some("a\n  b"));
+        let blocks = y.get("blocks").unwrap().as_list();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].get("id").unwrap().as_str(), Some("y"));
+        assert_eq!(
+            blocks[0].get("states").unwrap().get("coordinate"),
+            Some(&Yaml::List(vec![
+                Yaml::Str("8".into()),
+                Yaml::Str("8.0".into())
+            ]))
+        );
+        assert_eq!(
+            y.get("connections").unwrap().as_list()[0].as_list()[3],
+            Yaml::Str("0".into())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn eval() -> Result<(), Error> {
+        let vars: HashMap<String, String> = [
+            ("samp_rate", "1e6"),
+            ("deci", "int(samp_rate / 48000) * 2"),
+            ("loop", "loop + 1"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let mut ev = Eval::new(&vars);
+        for (expr, want) in [
+            ("1", 1.0),
+            ("-2 ** 2", -4.0),
+            ("(1 + 2) * 3 - 1", 8.0),
+            ("7 // 2 + 7 % 2", 4.0),
+            ("samp_rate/deci", 1e6 / 40.0),
+            ("1_000", 1000.0),
+        ] {
+            assert_eq!(ev.eval(expr)?, want, "{expr}");
+        }
+        for expr in ["loop", "nope", "1 +", "foo(1)", "'str'"] {
+            assert!(ev.eval(expr).is_err(), "{expr}");
+        }
+        Ok(())
+    }
+
+    const FM: &str = r#"
+options:
+  parameters:
+    id: fm
+blocks:
+- name: samp_rate
+  id: variable
+  parameters:
+    value: '50000'
+- name: src
+  id: blocks_file_source
+  parameters:
+    file: in.c32
+    repeat: 'False'
+    type: complex
+  states:
+    state: enabled
+- name: throttle
+  id: blocks_throttle
+  parameters:
+    samples_per_second: samp_rate
+    type: complex
+- name: filter
+  id: fft_filter_xxx
+  parameters:
+    decim: '1'
+    taps: firdes.low_pass(1, samp_rate, 10000, samp_rate/10)
+    type: ccc
+- name: waterfall
+  id: qtgui_waterfall_sink_x
+- name: demod
+  id: analog_quadrature_demod_cf
+  parameters:
+    gain: '1'
+- name: old_sink
+  id: blocks_wavfile_sink
+  states:
+    state: disabled
+- name: sink
+  id: blocks_file_sink
+  parameters:
+    file: out.f32
+    type: float
+- name: sink2
+  id: blocks_null_sink
+  parameters:
+    type: float
+connections:
+- [demod, '0', sink, '0']
+- [demod, '0', sink2, '0']
+- [demod, '0', old_sink, '0']
+- [filter, '0', demod, '0']
+- [filter, '0', waterfall, '0']
+- [src, '0', throttle, '0']
+- [throttle, '0', filter, '0']
+"#;
+
+    #[test]
+    fn convert_fm() -> Result<(), Error> {
+        let spec = parse(FM)?;
+        let want = GraphSpec::from_json(
+            r#"{"blocks": [
+  {"name": "file_source_c", "params": {"filename": "in.c32", "repeat": false}},
+  {"name": "fft_filter_c", "inputs": [0],
+   "params": {"samp_rate": 50000, "cutoff": 10000, "twidth": 5000}},
+  {"name": "quadrature_demod", "inputs": [1], "params": {"gain": 1}},
+  {"name": "tee_f", "inputs": [2]},
+  {"name": "file_sink_f", "inputs": [3],
+   "params": {"filename": "out.f32", "mode": "overwrite"}},
+  {"name": "null_sink_f", "inputs": [4]}
+]}"#,
+        )?;
+        assert_eq!(spec, want);
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported() {
+        for (from, to, want) in [
+            (
+                "id: blocks_file_sink",
+                "id: blocks_tag_debug",
+                "unsupported block",
+            ),
+            ("type: ccc", "type: fcc", "unsupported type"),
+            ("decim: '1'", "decim: '2'", "decimating"),
+            (
+                "[filter, '0', demod, '0']",
+                "[filter, '0', src, '0']",
+                "no such port",
+            ),
+            (
+                "- [demod, '0', sink2, '0']\n",
+                "",
+                "sink2: inputs not connected",
+            ),
+            (
+                "[src, '0', throttle, '0']",
+                "[src, '0', sink, '0']",
+                "throttle: input not",
+            ),
+        ] {
+            let grc = FM.replace(from, to);
+            assert_ne!(grc, FM, "{from}");
+            let err = parse(&grc).unwrap_err().to_string();
+            assert!(err.contains(want), "{from} -> {to}: {err}");
+        }
+    }
+
+    #[test]
+    fn example_files() {
+        let err = load("examples/gr/iq_to_wav.grc").unwrap_err().to_string();
+        assert!(
+            err.contains("unsupported block blocks_wavfile_sink"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,96 @@
+/*! Window framework for live displays.
+
+Only available with the `egui` feature.
+
+Each display, like [SpectrumView][crate::spectrum_sink::SpectrumView]
+and [WaterfallView][crate::waterfall_sink::WaterfallView], implements
+[View]. [run] opens a window with the views stacked vertically, with
+their controls at the top.
+
+The window needs to run on the main thread, so the graph has to run in
+another thread, e.g. with [MTGraph][crate::mtgraph::MTGraph].
+*/
+use std::time::Duration;
+
+use anyhow::Result;
+use eframe::egui;
+
+use crate::spectrum_sink::format_freq;
+use crate::{Error, Float};
+
+const REPAINT_INTERVAL: Duration = Duration::from_millis(33);
+
+/// A live display in a window.
+pub trait View {
+    /// Name, shown next to the controls.
+    fn name(&self) -> &str;
+
+    /// Draw the controls, laid out horizontally.
+    fn controls(&mut self, ui: &mut egui::Ui);
+
+    /// Draw the display, filling the available space.
+    fn show(&mut self, ui: &mut egui::Ui);
+}
+
+struct App {
+    views: Vec<Box<dyn View>>,
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::TopBottomPanel::top("controls").show(ctx, |ui| {
+            for view in &mut self.views {
+                ui.horizontal(|ui| {
+                    ui.strong(view.name());
+                    view.controls(ui);
+                });
+            }
+        });
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let n = self.views.len();
+            let spacing = ui.spacing().item_spacing.y;
+            let height = (ui.available_height() - spacing * (n as f32 - 1.0)) / n as f32;
+            for view in &mut self.views {
+                let size = egui::vec2(ui.available_width(), height);
+                ui.allocate_ui(size, |ui| {
+                    ui.set_min_size(size);
+                    view.show(ui);
+                });
+            }
+        });
+        ctx.request_repaint_after(REPAINT_INTERVAL);
+    }
+}
+
+/// Open a window showing the views, and run until it's closed.
+///
+/// Must be called from the main thread.
+pub fn run(title: &str, views: Vec<Box<dyn View>>) -> Result<()> {
+    eframe::run_native(
+        title,
+        eframe::NativeOptions::default(),
+        Box::new(|_cc| Box::new(App { views })),
+    )
+    .map_err(|e| Error::new(&format!("window: {e}")))?;
+    Ok(())
+}
+
+/// Draw vertical frequency grid lines, labelled at the bottom of `rect`.
+pub fn freq_grid(painter: &egui::Painter, rect: egui::Rect, min_freq: Float, max_freq: Float) {
+    let span = max_freq - min_freq;
+    for i in 0..=10 {
+        let f = min_freq + span * i as Float / 10.0;
+        let x = rect.left() + rect.width() * i as f32 / 10.0;
+        painter.vline(x, rect.y_range(), egui::Stroke::new(1.0, GRID_COLOR));
+        painter.text(
+            egui::Pos2::new(x, rect.bottom()),
+            egui::Align2::CENTER_BOTTOM,
+            format_freq(f),
+            egui::FontId::monospace(10.0),
+            egui::Color32::GRAY,
+        );
+    }
+}
+
+/// Color for grid lines.
+pub const GRID_COLOR: egui::Color32 = egui::Color32::from_gray(60);
@@ -10,6 +10,8 @@ therefore [APRS][aprs].
 use log::{debug, info, trace};
 
 use crate::block::{Block, BlockRet};
+use crate::pdu_pool::PduPool;
+use crate::rx_meta::{SAMPLE_END, SAMPLE_POS};
 use crate::stream::{new_nocopy_streamp, NoCopyStreamp, Streamp, Tag, TagValue};
 use crate::{Error, Result};
 
@@ -64,10 +66,73 @@ fn find_right_crc(data: &[u8], got: u16, fix_bits: bool) -> (Option<Vec<u8>>, u1
     (None, crc, false)
 }
 
+/// Builder for [HdlcDeframer].
+pub struct HdlcDeframerBuilder {
+    src: Streamp<u8>,
+    min_size: usize,
+    max_size: usize,
+    fcs_check: bool,
+    fix_bits: bool,
+}
+
+impl HdlcDeframerBuilder {
+    /// Create new builder.
+    ///
+    /// Defaults to packets of 10 to 1500 bytes, checking and
+    /// stripping the checksum, and not fixing bitflips.
+    pub fn new(src: Streamp<u8>) -> Self {
+        Self {
+            src,
+            min_size: 10,
+            max_size: 1500,
+            fcs_check: true,
+            fix_bits: false,
+        }
+    }
+
+    /// Set minimum packet size, in bytes.
+    pub fn min_size(mut self, v: usize) -> Self {
+        self.min_size = v;
+        self
+    }
+
+    /// Set maximum packet size, in bytes.
+    pub fn max_size(mut self, v: usize) -> Self {
+        self.max_size = v;
+        self
+    }
+
+    /// Set whether to check and strip the frame check sequence.
+    pub fn fcs_check(mut self, v: bool) -> Self {
+        self.fcs_check = v;
+        self
+    }
+
+    /// Set whether to try to fix single bitflips.
+    pub fn fix_bits(mut self, v: bool) -> Self {
+        self.fix_bits = v;
+        self
+    }
+
+    /// Build HdlcDeframer block.
+    pub fn build(self) -> HdlcDeframer {
+        let mut b = HdlcDeframer::new(self.src, self.min_size, self.max_size);
+        b.set_checksum(self.fcs_check);
+        b.set_fix_bits(self.fix_bits);
+        b
+    }
+}
+
 /** HDLC Deframer block.
 
 This block takes a stream of bits (as u8), and outputs any HDLC frames
 found as Vec<u8>.
+
+If the bits carry [SAMPLE_POS] tags from a
+[SignalMeter][crate::rx_meta::SignalMeter], each frame is tagged with
+the [SAMPLE_POS] at its opening flag and the [SAMPLE_END] at its
+closing flag, so [AnnotatePdu][crate::rx_meta::AnnotatePdu] can
+measure exactly the samples the frame was decoded from.
 */
 pub struct HdlcDeframer {
     src: Streamp<u8>,
@@ -81,6 +146,9 @@ pub struct HdlcDeframer {
     bitfixed: usize,
     stream_pos: u64,
     fix_bits: bool,
+    // Latest SAMPLE_POS tag, and its value at the start of the frame.
+    sample_pos: Option<u64>,
+    frame_start: Option<u64>,
 }
 
 impl Drop for HdlcDeframer {
@@ -109,9 +177,16 @@ impl HdlcDeframer {
             bitfixed: 0,
             stream_pos: 0,
             fix_bits: false,
+            sample_pos: None,
+            frame_start: None,
         }
     }
 
+    /// Create a builder for HdlcDeframer.
+    pub fn builder(src: Streamp<u8>) -> HdlcDeframerBuilder {
+        HdlcDeframerBuilder::new(src)
+    }
+
     /// Set fix bits.
     pub fn set_fix_bits(&mut self, v: bool) {
         self.fix_bits = v;
@@ -133,7 +208,8 @@ impl HdlcDeframer {
                 let n = (*v >> 1) | (bit << 7);
                 if n == 0x7e {
                     debug!("HdlcDeframer: Found flag!");
-                    State::Synced((0, Vec::with_capacity(self.max_size)))
+                    self.frame_start = self.sample_pos;
+                    State::Synced((0, PduPool::global().get(self.max_size)))
                 } else {
                     State::Unsynced(n)
                 }
@@ -144,6 +220,7 @@ impl HdlcDeframer {
                 // but we can swap its contents.
                 std::mem::swap(&mut bits, inbits);
                 if bits.len() > self.max_size * 8 {
+                    PduPool::global().put(bits);
                     return Ok(State::Unsynced(0xff));
                 }
                 if bit > 0 {
@@ -168,17 +245,19 @@ impl HdlcDeframer {
                 std::mem::swap(&mut bits, inbits);
                 if bit == 1 {
                     // 7 ones in a row is invalid. Discard what we've collected.
+                    PduPool::global().put(bits);
                     return Ok(State::Unsynced(0xff));
                 }
                 if bits.len() < 7 {
                     // Too short, not even zero bytes.
+                    PduPool::global().put(bits);
                     return Ok(State::Unsynced(0xff));
                 }
 
                 // Remove partial flag.
                 bits.truncate(bits.len() - 7);
 
-                if bits.len() % 8 != 0 {
+                if !bits.len().is_multiple_of(8) {
                     trace!(
                         "HdlcDeframer: Packet len not multiple of 8: {} {:?}",
                         bits.len(),
@@ -187,12 +266,19 @@ impl HdlcDeframer {
                 } else if bits.len() / 8 < self.min_size {
                     trace!("Packet too short: {} < {}", bits.len() / 8, self.min_size);
                 } else {
-                    let bytes: Vec<u8> = (0..bits.len())
-                        .step_by(8)
-                        .map(|i| bits2byte(&bits[i..i + 8]))
-                        .collect();
+                    let mut bytes = PduPool::global().get(bits.len() / 8);
+                    bytes.extend(
+                        (0..bits.len())
+                            .step_by(8)
+                            .map(|i| bits2byte(&bits[i..i + 8])),
+                    );
                     debug!("HdlcDeframer: Captured packet: {:0>2x?}", bytes);
-                    let tags = &[Tag::new(0, "packet_pos".into(), TagValue::U64(stream_pos))];
+                    let mut tags =
+                        vec![Tag::new(0, "packet_pos".into(), TagValue::U64(stream_pos))];
+                    if let (Some(start), Some(end)) = (self.frame_start, self.sample_pos) {
+                        tags.push(Tag::new(0, SAMPLE_POS.into(), TagValue::U64(start)));
+                        tags.push(Tag::new(0, SAMPLE_END.into(), TagValue::U64(end)));
+                    }
                     if self.strip_checksum {
                         let data = &bytes[..bytes.len() - 2];
                         let got_crc = u16::from_le_bytes(bytes[bytes.len() - 2..].try_into()?);
@@ -208,19 +294,26 @@ impl HdlcDeframer {
                         if crc != got_crc {
                             self.crc_error += 1;
                             debug!("want crc {:0>4x}, got {:0>4x}", crc, got_crc);
-                            return Ok(State::Synced((0, Vec::with_capacity(self.max_size))));
+                            PduPool::global().put(bytes);
+                            self.frame_start = self.sample_pos;
+                            bits.clear();
+                            return Ok(State::Synced((0, bits)));
                         }
                         self.decoded += 1;
-                        self.dst.push(data.to_vec(), tags);
+                        self.dst.push(PduPool::global().copy_from(data), &tags);
+                        PduPool::global().put(bytes);
                     } else {
                         self.decoded += 1;
-                        self.dst.push(bytes, tags);
+                        self.dst.push(bytes, &tags);
                     }
                 }
 
                 // We may or may not have seen a valid packet, but we
-                // did see a valid flag. So back to synced.
-                State::Synced((0, Vec::with_capacity(self.max_size)))
+                // did see a valid flag. So back to synced, reusing the
+                // bit buffer.
+                self.frame_start = self.sample_pos;
+                bits.clear();
+                State::Synced((0, bits))
             }
         })
     }
@@ -233,11 +326,24 @@ impl Block for HdlcDeframer {
 
     fn work(&mut self) -> Result<BlockRet, Error> {
         let ti = self.src.clone();
-        let (input, _tags) = ti.read_buf()?;
+        let (input, tags) = ti.read_buf()?;
         if input.is_empty() {
             return Ok(BlockRet::Noop);
         }
-        for bit in input.iter().copied() {
+        let mut positions: Vec<_> = tags
+            .iter()
+            .filter(|t| t.key() == SAMPLE_POS)
+            .filter_map(|t| match t.val() {
+                TagValue::U64(p) => Some((t.pos(), *p)),
+                _ => None,
+            })
+            .collect();
+        positions.sort();
+        let mut positions = positions.into_iter().peekable();
+        for (i, bit) in input.iter().copied().enumerate() {
+            while let Some((_, p)) = positions.next_if(|(pos, _)| *pos <= i) {
+                self.sample_pos = Some(p);
+            }
             // This is a bit ugly in that it destructively creates the
             // new state. The old state is moved from.
             self.state = self.update_state(bit, self.stream_pos)?;
@@ -296,7 +402,7 @@ const FCSTAB: &[u16] = &[
 ];
 
 // Calculate checksum. Code ported from RFC1662.
-fn calc_crc(data: &[u8]) -> u16 {
+pub(crate) fn calc_crc(data: &[u8]) -> u16 {
     data.iter().fold(0xffffu16, |fcs, byte| {
         let byte = *byte as u16;
         let ofs = ((fcs ^ byte) & 0xff) as usize;
@@ -364,6 +470,32 @@ mod tests {
         Ok(())
     }
     #[test]
+    fn sample_pos() -> Result<()> {
+        let bits = str2bits("01111110010101011110000001111110010101011010101001111110");
+        let s = crate::stream::new_streamp();
+        {
+            let mut o = s.write_buf()?;
+            o.fill_from_slice(&bits);
+            let tag = |pos, p| Tag::new(pos, SAMPLE_POS.into(), TagValue::U64(p));
+            o.produce(bits.len(), &[tag(0, 100), tag(20, 200), tag(40, 300)]);
+        }
+        let mut b = HdlcDeframer::new(s, 1, 10);
+        b.set_checksum(false);
+        b.work()?;
+        let o = b.out();
+        for (start, end) in [(100, 200), (200, 300)] {
+            let (_, tags) = o.pop().unwrap();
+            let get = |key| {
+                tags.iter()
+                    .find(|t| t.key() == key)
+                    .map(|t| t.val().clone())
+            };
+            assert_eq!(get(SAMPLE_POS), Some(TagValue::U64(start)));
+            assert_eq!(get(SAMPLE_END), Some(TagValue::U64(end)));
+        }
+        Ok(())
+    }
+    #[test]
     fn bitstuffed1() -> Result<()> {
         {
             let bits = &"01111110111110111110111110101111110";
@@ -421,6 +553,21 @@ mod tests {
         Ok(())
     }
     #[test]
+    fn builder() -> Result<()> {
+        let bits = &"01111110111110111110111110101111110";
+        for (min_size, max_size, want) in [(1, 10, true), (3, 10, false), (1, 1, false)] {
+            let s = streamp_from_slice(&str2bits(bits));
+            let mut b = HdlcDeframer::builder(s)
+                .min_size(min_size)
+                .max_size(max_size)
+                .fcs_check(false)
+                .build();
+            b.work()?;
+            assert_eq!(b.out().pop().is_some(), want, "{min_size} {max_size}");
+        }
+        Ok(())
+    }
+    #[test]
     fn check_crc() -> Result<()> {
         {
             let bits = &"0111111010101010000010101010111101111110";
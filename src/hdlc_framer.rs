@@ -0,0 +1,250 @@
+/*! HDLC Framer.
+
+The sending side of [HdlcDeframer][crate::hdlc_deframer::HdlcDeframer].
+Each frame gets a frame check sequence (FCS) appended, is sent LSB
+first with a zero stuffed after every five ones, and is surrounded by
+`0x7e` flags. The leading flags also give the receiver time to settle
+(the AX.25 "TX delay").
+
+```text
+  (frames) → [ HdlcFramer ] → [ NrziEncode ] → [ AfskModulator ] → [ audio ]
+```
+
+Each frame is output as one burst of bits, tagged with
+[TAG_BURST_START] on the first bit, and [TAG_BURST_END] on the last.
+*/
+use std::collections::VecDeque;
+
+use log::{debug, info};
+
+use crate::block::{Block, BlockRet};
+use crate::hdlc_deframer::calc_crc;
+use crate::stream::{
+    new_streamp, NoCopyStreamp, Streamp, Tag, TagValue, TAG_BURST_END, TAG_BURST_START,
+};
+use crate::Error;
+
+const FLAG: u8 = 0x7e;
+
+/// Builder for [HdlcFramer].
+pub struct HdlcFramerBuilder {
+    src: NoCopyStreamp<Vec<u8>>,
+    preamble: usize,
+    postamble: usize,
+    fcs: bool,
+}
+
+impl HdlcFramerBuilder {
+    /// Create new builder.
+    ///
+    /// Defaults to 32 flags before (about 200ms at 1200bps), 2 flags
+    /// after, and appending the FCS.
+    pub fn new(src: NoCopyStreamp<Vec<u8>>) -> Self {
+        Self {
+            src,
+            preamble: 32,
+            postamble: 2,
+            fcs: true,
+        }
+    }
+
+    /// Set number of flags before each frame. At least one is always
+    /// sent.
+    pub fn preamble(mut self, flags: usize) -> Self {
+        self.preamble = flags;
+        self
+    }
+
+    /// Set number of flags after each frame. At least one is always
+    /// sent.
+    pub fn postamble(mut self, flags: usize) -> Self {
+        self.postamble = flags;
+        self
+    }
+
+    /// Set whether to append the frame check sequence.
+    pub fn fcs(mut self, v: bool) -> Self {
+        self.fcs = v;
+        self
+    }
+
+    /// Build HdlcFramer block.
+    pub fn build(self) -> HdlcFramer {
+        HdlcFramer {
+            src: self.src,
+            dst: new_streamp(),
+            preamble: self.preamble.max(1),
+            postamble: self.postamble.max(1),
+            fcs: self.fcs,
+            bits: VecDeque::new(),
+            burst_start: false,
+            frames: 0,
+        }
+    }
+}
+
+/** HDLC Framer block.
+
+This block takes frames as `Vec<u8>`, and outputs them as a stream of
+bits (as u8), ready for NRZI encoding.
+*/
+pub struct HdlcFramer {
+    src: NoCopyStreamp<Vec<u8>>,
+    dst: Streamp<u8>,
+    preamble: usize,
+    postamble: usize,
+    fcs: bool,
+    // Bits of the current frame not yet output.
+    bits: VecDeque<u8>,
+    // The front of `bits` is the first bit of the burst.
+    burst_start: bool,
+    frames: usize,
+}
+
+impl Drop for HdlcFramer {
+    fn drop(&mut self) {
+        info!("HDLC Framer: Framed {}", self.frames);
+    }
+}
+
+impl HdlcFramer {
+    /// Create new HdlcFramer, with default settings.
+    pub fn new(src: NoCopyStreamp<Vec<u8>>) -> Self {
+        HdlcFramerBuilder::new(src).build()
+    }
+
+    /// Create a builder for HdlcFramer.
+    pub fn builder(src: NoCopyStreamp<Vec<u8>>) -> HdlcFramerBuilder {
+        HdlcFramerBuilder::new(src)
+    }
+
+    /// Get output stream.
+    pub fn out(&self) -> Streamp<u8> {
+        self.dst.clone()
+    }
+
+    fn push_flags(&mut self, n: usize) {
+        for _ in 0..n {
+            self.bits.extend((0..8).map(|i| (FLAG >> i) & 1));
+        }
+    }
+
+    fn frame(&mut self, data: &[u8]) {
+        self.push_flags(self.preamble);
+        let crc = calc_crc(data).to_le_bytes();
+        let fcs: &[u8] = if self.fcs { &crc } else { &[] };
+        let mut ones = 0;
+        for byte in data.iter().chain(fcs) {
+            for i in 0..8 {
+                let bit = (byte >> i) & 1;
+                self.bits.push_back(bit);
+                if bit == 0 {
+                    ones = 0;
+                    continue;
+                }
+                ones += 1;
+                if ones == 5 {
+                    self.bits.push_back(0);
+                    ones = 0;
+                }
+            }
+        }
+        self.push_flags(self.postamble);
+        self.burst_start = true;
+    }
+}
+
+impl Block for HdlcFramer {
+    fn block_name(&self) -> &str {
+        "HDLC Framer"
+    }
+
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        if self.bits.is_empty() {
+            let Some((data, _tags)) = self.src.pop() else {
+                return Ok(BlockRet::Noop);
+            };
+            debug!("HdlcFramer: framing {} bytes", data.len());
+            self.frame(&data);
+            self.frames += 1;
+        }
+        let mut o = self.dst.write_buf()?;
+        let n = o.len().min(self.bits.len());
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        let mut tags = Vec::new();
+        if self.burst_start {
+            tags.push(Tag::new(0, TAG_BURST_START.into(), TagValue::Bool(true)));
+            self.burst_start = false;
+        }
+        if n == self.bits.len() {
+            tags.push(Tag::new(n - 1, TAG_BURST_END.into(), TagValue::Bool(true)));
+        }
+        for (d, s) in o.slice().iter_mut().zip(self.bits.drain(..n)) {
+            *d = s;
+        }
+        o.produce(n, &tags);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::HdlcDeframer;
+    use crate::stream::new_nocopy_streamp;
+
+    #[test]
+    fn stuffing() -> Result<(), Error> {
+        let src = new_nocopy_streamp();
+        src.push(vec![0xff, 0x01], &[]);
+        let mut b = HdlcFramer::builder(src)
+            .preamble(1)
+            .postamble(1)
+            .fcs(false)
+            .build();
+        b.work()?;
+        let o = b.out();
+        let (res, tags) = o.read_buf()?;
+        let got: String = res.iter().map(|b| (b'0' + b) as char).collect();
+        // Stuffed zero after five ones, LSB first.
+        assert_eq!(
+            got,
+            "01111110".to_owned() + "111110111" + "10000000" + "01111110"
+        );
+        assert_eq!(
+            tags,
+            vec![
+                Tag::new(0, TAG_BURST_START.into(), TagValue::Bool(true)),
+                Tag::new(got.len() - 1, TAG_BURST_END.into(), TagValue::Bool(true)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip() -> Result<(), Error> {
+        let frames = [
+            b"hello world".to_vec(),
+            vec![0xff; 20],
+            (0..=255).collect::<Vec<u8>>(),
+        ];
+        let src = new_nocopy_streamp();
+        for f in &frames {
+            src.push(f.clone(), &[]);
+        }
+        let mut framer = HdlcFramer::new(src);
+        let mut deframer = HdlcDeframer::new(framer.out(), 1, 1000);
+        for _ in 0..frames.len() {
+            framer.work()?;
+        }
+        deframer.work()?;
+        let o = deframer.out();
+        for f in &frames {
+            assert_eq!(&o.pop().unwrap().0, f);
+        }
+        assert!(o.pop().is_none());
+        Ok(())
+    }
+}
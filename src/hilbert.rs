@@ -7,8 +7,6 @@ sideband.
 Then again I guess you can do the same with a FloatToComplex plus
 FftFilter.
 
-This implementation is a pretty inefficient.
-
 [wiki]: https://en.wikipedia.org/wiki/Hilbert_transform
 */
 
@@ -21,6 +19,9 @@ use crate::{Complex, Error, Float};
 pub struct Hilbert {
     src: Streamp<Float>,
     dst: Streamp<Complex>,
+
+    // The last `ntaps` input samples, followed by room for as many
+    // more, for filtering across the start of new input.
     history: Vec<Float>,
     filter: FIR<Float>,
     ntaps: usize,
@@ -35,7 +36,11 @@ impl Hilbert {
             src,
             ntaps,
             dst: new_streamp(),
-            history: vec![0.0; ntaps],
+            history: {
+                let mut v = Vec::with_capacity(2 * ntaps);
+                v.resize(ntaps, 0.0);
+                v
+            },
             filter: FIR::new(&taps),
         }
     }
@@ -50,7 +55,7 @@ impl Block for Hilbert {
         "Hilbert"
     }
     fn work(&mut self) -> Result<BlockRet, Error> {
-        assert_eq!(self.ntaps, self.history.len());
+        let ntaps = self.ntaps;
         let (i, tags) = self.src.read_buf()?;
         if i.is_empty() {
             return Ok(BlockRet::Noop);
@@ -60,26 +65,78 @@ impl Block for Hilbert {
             return Ok(BlockRet::Ok);
         }
 
-        let inout = std::cmp::min(i.len(), o.len());
-        let len = self.history.len() + inout;
-        let n = len - self.ntaps;
+        let n = std::cmp::min(i.len(), o.len());
+        let input = &i.slice()[..n];
+        let out = o.slice();
 
-        // TODO: Probably needless copy.
-        let mut iv = Vec::with_capacity(len);
-        iv.extend(&self.history);
-        iv.extend(i.iter().take(inout).copied());
+        // Output sample k is filtered from the `ntaps` samples before
+        // input sample k. For the first `ntaps` outputs some of those
+        // are in the history.
+        let head = std::cmp::min(n, ntaps);
+        self.history.extend_from_slice(&input[..head]);
+        for (k, o) in out[..head].iter_mut().enumerate() {
+            let t = &self.history[k..(k + ntaps)];
+            *o = Complex::new(t[ntaps / 2], self.filter.filter(t));
+        }
 
-        // I tried a couple of variations of this loop, and this was
-        // the fastest on my laptop.
-        for i in 0..n {
-            let t = &iv[i..(i + self.ntaps)];
-            o.slice()[i] = Complex::new(iv[i + self.ntaps / 2], self.filter.filter(t));
+        // The rest straight from the input.
+        for (k, o) in out[head..n].iter_mut().enumerate() {
+            let t = &input[k..(k + ntaps)];
+            *o = Complex::new(t[ntaps / 2], self.filter.filter(t));
         }
 
-        o.produce(n, &tags);
+        // Keep the last `ntaps` samples.
+        if n >= ntaps {
+            self.history.truncate(ntaps);
+            self.history.copy_from_slice(&input[(n - ntaps)..]);
+        } else {
+            self.history.drain(..n);
+        }
 
-        self.history[..self.ntaps].clone_from_slice(&iv[n..len]);
+        o.produce(n, &tags);
         i.consume(n);
         Ok(BlockRet::Ok)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::new_streamp;
+
+    #[test]
+    fn chunked() -> Result<(), Error> {
+        let ntaps = 11;
+        let input: Vec<Float> = (0..200).map(|n| (0.3 * n as Float).sin()).collect();
+
+        // Straightforward zero padded reference.
+        let want: Vec<Complex> = {
+            let filter = FIR::new(&crate::fir::hilbert(ntaps));
+            let mut padded = vec![0.0; ntaps];
+            padded.extend(&input);
+            (0..input.len())
+                .map(|k| {
+                    let t = &padded[k..(k + ntaps)];
+                    Complex::new(t[ntaps / 2], filter.filter(t))
+                })
+                .collect()
+        };
+
+        let src = new_streamp();
+        let mut b = Hilbert::new(src.clone(), ntaps);
+        // Chunks both shorter and longer than the filter.
+        let mut pos = 0;
+        for len in [3, 7, 11, 1, 40, 5, 133] {
+            let mut w = src.write_buf()?;
+            w.fill_from_slice(&input[pos..(pos + len)]);
+            w.produce(len, &[]);
+            pos += len;
+            b.work()?;
+        }
+        assert_eq!(pos, input.len());
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        assert_eq!(res.slice(), &want[..]);
+        Ok(())
+    }
+}
@@ -0,0 +1,432 @@
+/*! Sample statistics and histogram.
+
+[HistogramProbe] passes a stream through unchanged, and accumulates
+min, max, mean, variance and a histogram of the sample values. Complex
+streams are treated as two channels, I and Q.
+
+This is a quick way to diagnose problems with a capture:
+
+* Clipping shows up as samples at or beyond the edges of the histogram
+  ([Histogram::clipped]), often with a spike in the outermost bins.
+* DC offset shows up as a mean away from zero, often differing between
+  I and Q.
+* Bias, like an unused ADC bit or a stuck sign, shows up as empty or
+  lopsided bins.
+
+```text
+   Source -> HistogramProbe -> …
+                   \_ HistogramHandle -> stats()
+```
+
+With [HistogramProbe::set_dashboard], a summary is also published as a
+[dashboard][crate::dashboard] probe.
+*/
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::block::{Block, BlockRet};
+use crate::dashboard::DashboardHandle;
+use crate::stream::{new_streamp, Streamp};
+use crate::{Complex, Error, Float};
+
+const DEFAULT_BINS: usize = 64;
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sample types that statistics can be calculated for.
+pub trait ProbeSample: Copy {
+    /// Names of the channels in each sample.
+    const CHANNELS: &'static [&'static str];
+
+    /// Default histogram range.
+    const RANGE: (f64, f64);
+
+    /// Value of channel `n`.
+    fn channel(&self, n: usize) -> f64;
+}
+
+impl ProbeSample for Float {
+    const CHANNELS: &'static [&'static str] = &["value"];
+    const RANGE: (f64, f64) = (-1.0, 1.0);
+    fn channel(&self, _n: usize) -> f64 {
+        *self as f64
+    }
+}
+
+impl ProbeSample for Complex {
+    const CHANNELS: &'static [&'static str] = &["i", "q"];
+    const RANGE: (f64, f64) = (-1.0, 1.0);
+    fn channel(&self, n: usize) -> f64 {
+        if n == 0 {
+            self.re as f64
+        } else {
+            self.im as f64
+        }
+    }
+}
+
+impl ProbeSample for u8 {
+    const CHANNELS: &'static [&'static str] = &["value"];
+    const RANGE: (f64, f64) = (0.0, 256.0);
+    fn channel(&self, _n: usize) -> f64 {
+        *self as f64
+    }
+}
+
+impl ProbeSample for i16 {
+    const CHANNELS: &'static [&'static str] = &["value"];
+    const RANGE: (f64, f64) = (-32768.0, 32768.0);
+    fn channel(&self, _n: usize) -> f64 {
+        *self as f64
+    }
+}
+
+impl ProbeSample for u32 {
+    const CHANNELS: &'static [&'static str] = &["value"];
+    const RANGE: (f64, f64) = (0.0, 4294967296.0);
+    fn channel(&self, _n: usize) -> f64 {
+        *self as f64
+    }
+}
+
+/// Histogram of values, with equal width bins.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Histogram {
+    /// Lower edge of the first bin.
+    pub min: f64,
+
+    /// Upper edge of the last bin.
+    pub max: f64,
+
+    /// Number of values in each bin.
+    pub bins: Vec<u64>,
+
+    /// Number of values below `min`.
+    pub below: u64,
+
+    /// Number of values at or above `max`.
+    pub above: u64,
+}
+
+impl Histogram {
+    fn new(min: f64, max: f64, bins: usize) -> Self {
+        Self {
+            min,
+            max,
+            bins: vec![0; bins],
+            below: 0,
+            above: 0,
+        }
+    }
+
+    fn add(&mut self, v: f64) {
+        if v < self.min {
+            self.below += 1;
+        } else if v >= self.max {
+            self.above += 1;
+        } else {
+            let n = self.bins.len();
+            let bin = ((v - self.min) / (self.max - self.min) * n as f64) as usize;
+            self.bins[bin.min(n - 1)] += 1;
+        }
+    }
+
+    /// Range of bin `n`.
+    pub fn bin_range(&self, n: usize) -> (f64, f64) {
+        let w = (self.max - self.min) / self.bins.len() as f64;
+        (self.min + w * n as f64, self.min + w * (n + 1) as f64)
+    }
+
+    /// Number of values in the outermost bins or outside the range,
+    /// which for a range matching the ADC are likely clipped.
+    pub fn clipped(&self) -> u64 {
+        let edges = match self.bins.len() {
+            0 => 0,
+            1 => self.bins[0],
+            n => self.bins[0] + self.bins[n - 1],
+        };
+        self.below + self.above + edges
+    }
+}
+
+/// Statistics for one channel.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChannelStats {
+    /// Channel name, e.g. `i` or `q`.
+    pub name: String,
+
+    /// Number of samples.
+    pub count: u64,
+
+    /// Smallest value.
+    pub min: f64,
+
+    /// Largest value.
+    pub max: f64,
+
+    /// Mean value.
+    pub mean: f64,
+
+    /// Variance.
+    pub variance: f64,
+
+    /// Histogram.
+    pub histogram: Histogram,
+
+    // Sum of squared differences from the mean, for Welford's algorithm.
+    #[serde(skip)]
+    m2: f64,
+}
+
+impl ChannelStats {
+    fn new(name: &str, range: (f64, f64), bins: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            variance: 0.0,
+            histogram: Histogram::new(range.0, range.1, bins),
+            m2: 0.0,
+        }
+    }
+
+    fn add(&mut self, v: f64) {
+        self.count += 1;
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+        let d = v - self.mean;
+        self.mean += d / self.count as f64;
+        self.m2 += d * (v - self.mean);
+        self.variance = self.m2 / self.count as f64;
+        self.histogram.add(v);
+    }
+
+    /// Standard deviation.
+    pub fn stddev(&self) -> f64 {
+        self.variance.sqrt()
+    }
+
+    fn summary(&self) -> serde_json::Value {
+        serde_json::json!({
+            "count": self.count,
+            "min": self.min,
+            "max": self.max,
+            "mean": self.mean,
+            "stddev": self.stddev(),
+            "clipped": self.histogram.clipped(),
+        })
+    }
+}
+
+struct Shared {
+    channels: Vec<ChannelStats>,
+    range: (f64, f64),
+    bins: usize,
+}
+
+impl Shared {
+    fn reset(&mut self) {
+        for c in &mut self.channels {
+            *c = ChannelStats::new(&c.name, self.range, self.bins);
+        }
+    }
+}
+
+/// Shared handle to the statistics of a [HistogramProbe].
+#[derive(Clone)]
+pub struct HistogramHandle {
+    inner: Arc<Mutex<Shared>>,
+}
+
+impl HistogramHandle {
+    /// Statistics so far, one per channel.
+    pub fn stats(&self) -> Vec<ChannelStats> {
+        self.inner.lock().unwrap().channels.clone()
+    }
+
+    /// Clear the statistics.
+    pub fn reset(&self) {
+        self.inner.lock().unwrap().reset();
+    }
+
+    /// Set histogram range and number of bins, and clear the
+    /// statistics.
+    pub fn set_histogram(&self, min: f64, max: f64, bins: usize) {
+        let mut s = self.inner.lock().unwrap();
+        s.range = (min, max);
+        s.bins = bins.max(1);
+        s.reset();
+    }
+}
+
+/** Histogram and statistics probe block.
+
+Passes the stream through as is.
+*/
+pub struct HistogramProbe<T: ProbeSample> {
+    src: Streamp<T>,
+    dst: Streamp<T>,
+    handle: HistogramHandle,
+    dashboard: Option<(DashboardHandle, String)>,
+    published: Option<Instant>,
+}
+
+impl<T: ProbeSample> HistogramProbe<T> {
+    /// Create new histogram probe.
+    pub fn new(src: Streamp<T>) -> Self {
+        let range = T::RANGE;
+        let bins = DEFAULT_BINS;
+        Self {
+            src,
+            dst: new_streamp(),
+            handle: HistogramHandle {
+                inner: Arc::new(Mutex::new(Shared {
+                    channels: T::CHANNELS
+                        .iter()
+                        .map(|n| ChannelStats::new(n, range, bins))
+                        .collect(),
+                    range,
+                    bins,
+                })),
+            },
+            dashboard: None,
+            published: None,
+        }
+    }
+
+    /// Set histogram range and number of bins.
+    ///
+    /// Default is 64 bins, over -1 to 1 for Float and Complex, and the
+    /// full range for integer types.
+    pub fn set_histogram(&mut self, min: f64, max: f64, bins: usize) {
+        self.handle.set_histogram(min, max, bins);
+    }
+
+    /// Publish a summary as a dashboard probe with this name.
+    pub fn set_dashboard(&mut self, handle: DashboardHandle, name: &str) {
+        self.dashboard = Some((handle, name.to_string()));
+    }
+
+    /// Get a handle to the statistics.
+    pub fn handle(&self) -> HistogramHandle {
+        self.handle.clone()
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<T> {
+        self.dst.clone()
+    }
+
+    fn publish(&mut self) {
+        let Some((dash, name)) = &self.dashboard else {
+            return;
+        };
+        if matches!(self.published, Some(t) if t.elapsed() < PUBLISH_INTERVAL) {
+            return;
+        }
+        self.published = Some(Instant::now());
+        let s = self.handle.inner.lock().unwrap();
+        let summary: serde_json::Map<String, serde_json::Value> = s
+            .channels
+            .iter()
+            .map(|c| (c.name.clone(), c.summary()))
+            .collect();
+        dash.set_probe(name, summary);
+    }
+}
+
+impl<T: ProbeSample> Block for HistogramProbe<T> {
+    fn block_name(&self) -> &str {
+        "HistogramProbe"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let obind = self.dst.clone();
+        let mut o = obind.write_buf()?;
+        let n = std::cmp::min(input.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::Ok);
+        }
+        {
+            let mut s = self.handle.inner.lock().unwrap();
+            for x in input.iter().take(n) {
+                for (i, c) in s.channels.iter_mut().enumerate() {
+                    c.add(x.channel(i));
+                }
+            }
+        }
+        o.slice()[..n].copy_from_slice(&input.slice()[..n]);
+        let tags: Vec<_> = tags.into_iter().filter(|t| t.pos() < n).collect();
+        o.produce(n, &tags);
+        input.consume(n);
+        self.publish();
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::VectorSource;
+
+    #[test]
+    fn float() -> Result<(), Error> {
+        let mut src = VectorSource::new(vec![-0.5, 0.5, 0.25, 1.5, -1.0]);
+        let mut probe = HistogramProbe::new(src.out());
+        probe.set_histogram(-1.0, 1.0, 4);
+        src.work()?;
+        probe.work()?;
+        let stats = probe.handle().stats();
+        assert_eq!(stats.len(), 1);
+        let s = &stats[0];
+        assert_eq!(s.count, 5);
+        assert_eq!((s.min, s.max), (-1.0, 1.5));
+        assert!((s.mean - 0.15).abs() < 1e-9);
+        assert!((s.variance - 0.74).abs() < 1e-9, "{}", s.variance);
+        assert_eq!(s.histogram.bins, vec![1, 1, 1, 1]);
+        assert_eq!((s.histogram.below, s.histogram.above), (0, 1));
+        assert_eq!(s.histogram.clipped(), 3);
+        assert_eq!(s.histogram.bin_range(1), (-0.5, 0.0));
+        Ok(())
+    }
+
+    #[test]
+    fn complex() -> Result<(), Error> {
+        let mut src = VectorSource::new(vec![Complex::new(0.1, -0.2); 10]);
+        let mut probe = HistogramProbe::new(src.out());
+        let dash = DashboardHandle::default();
+        probe.set_dashboard(dash.clone(), "iq");
+        src.work()?;
+        probe.work()?;
+        let stats = probe.handle().stats();
+        assert_eq!(stats[0].name, "i");
+        assert!((stats[0].mean - 0.1).abs() < 1e-6);
+        assert!((stats[1].mean + 0.2).abs() < 1e-6);
+        assert!(stats[1].variance < 1e-12);
+        assert_eq!(stats[0].histogram.bins.iter().sum::<u64>(), 10);
+        probe.handle().reset();
+        assert_eq!(probe.handle().stats()[0].count, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn bytes() -> Result<(), Error> {
+        let mut src = VectorSource::new(vec![0u8, 127, 255, 255]);
+        let mut probe = HistogramProbe::new(src.out());
+        src.work()?;
+        probe.work()?;
+        let s = &probe.handle().stats()[0];
+        assert_eq!(s.histogram.bins[0], 1);
+        assert_eq!(s.histogram.bins[31], 1);
+        assert_eq!(s.histogram.bins[63], 2);
+        assert_eq!(s.histogram.clipped(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+/*! Run a chain of one-to-one blocks in place.
+
+Every block normally reads its input stream and writes its output
+stream, so a chain of N blocks copies every sample N times.
+[InPlaceChain] instead copies the samples once, and then runs all the
+blocks on the output buffer.
+
+This is only safe if nothing else reads the streams between the
+blocks, since those streams are never written. [Chain](crate::chain)
+knows that, and uses InPlaceChain automatically for consecutive
+[InPlace] blocks, like `add_const` and `multiply_const`.
+*/
+use std::sync::{Arc, Mutex};
+
+use crate::block::{Block, BlockRet, InPlace};
+use crate::stream::{new_streamp, Streamp};
+use crate::Error;
+
+/// Shared list of the blocks in an [InPlaceChain], to add more blocks
+/// to it after it's been added to a graph.
+pub type InPlaceStages<T> = Arc<Mutex<Vec<Box<dyn InPlace<T> + Send>>>>;
+
+/// Run one-to-one blocks in place on the output buffer. See the
+/// [module docs](crate::in_place).
+pub struct InPlaceChain<T: Copy> {
+    src: Streamp<T>,
+    dst: Streamp<T>,
+    stages: InPlaceStages<T>,
+}
+
+impl<T: Copy> InPlaceChain<T> {
+    /// Create new InPlaceChain, initially just copying the stream.
+    pub fn new(src: Streamp<T>) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            stages: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Add a block to the end of the chain.
+    ///
+    /// The block's own streams are ignored.
+    pub fn push(&self, b: Box<dyn InPlace<T> + Send>) {
+        self.stages.lock().unwrap().push(b);
+    }
+
+    /// Return the list of blocks, for adding more blocks later.
+    pub fn stages(&self) -> InPlaceStages<T> {
+        self.stages.clone()
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<T> {
+        self.dst.clone()
+    }
+}
+
+impl<T: Copy> Block for InPlaceChain<T> {
+    fn block_name(&self) -> &str {
+        "InPlaceChain"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (i, tags) = self.src.read_buf()?;
+        let mut o = self.dst.write_buf()?;
+        let n = std::cmp::min(i.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        o.fill_from_slice(&i.slice()[..n]);
+        for stage in self.stages.lock().unwrap().iter_mut() {
+            stage.process_in_place(&mut o.slice()[..n]);
+        }
+        o.produce(n, &tags);
+        i.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{AddConst, MultiplyConst, VectorSource};
+
+    #[test]
+    fn chain() -> Result<(), Error> {
+        let mut src = VectorSource::new(vec![1.0, 2.0, 3.0]);
+        let mut b = InPlaceChain::new(src.out());
+        b.push(Box::new(AddConst::new(src.out(), 1.0)));
+        b.stages()
+            .lock()
+            .unwrap()
+            .push(Box::new(MultiplyConst::new(src.out(), 10.0)));
+        src.work()?;
+        b.work()?;
+        let o = b.out();
+        let (res, tags) = o.read_buf()?;
+        assert_eq!(res.slice(), &[20.0, 30.0, 40.0]);
+        assert!(!tags.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+/*! Automatic I/Q imbalance correction.
+
+If the I and Q paths of a receiver don't have exactly the same gain,
+or aren't exactly 90° apart, then every signal gets a mirror image on
+the other side of the center frequency. [IqBalance] estimates the
+imbalance from the signal itself, and corrects it.
+
+With running averages of I², Q², and I·Q, it makes Q uncorrelated
+with I, and then scales it to the same power as I:
+
+```text
+Q1 = Q - (E[IQ] / E[I²]) · I
+Q' = Q1 · sqrt(E[I²] / E[Q1²])
+```
+
+This assumes that the wanted signal, over time, has uncorrelated I
+and Q of the same power, which is true for most received spectrum.
+It also assumes no DC offset, so put a
+[DcBlocker][crate::dc_blocker::DcBlocker] first.
+
+```
+use rustradio::blocks::{ConstantSource, DcBlocker, IqBalance};
+use rustradio::Complex;
+let src = ConstantSource::new(Complex::new(0.1, -0.05));
+let dc = DcBlocker::new(src.out(), 0.0001)?;
+let iq = IqBalance::new(dc.out(), 0.0001)?;
+# Ok::<(), rustradio::Error>(())
+```
+*/
+use crate::block::Block;
+use crate::stream::{new_streamp, Streamp};
+use crate::{Complex, Error, Float};
+
+/// Automatic I/Q imbalance correction. See the [module
+/// docs][crate::iq_balance].
+#[derive(Block)]
+pub struct IqBalance {
+    #[rustradio(input)]
+    src: Streamp<Complex>,
+    #[rustradio(output)]
+    dst: Streamp<Complex>,
+    alpha: Float,
+    // Running averages of I², Q², and I·Q.
+    ii: Float,
+    qq: Float,
+    iq: Float,
+}
+
+impl IqBalance {
+    /// Create new IqBalance. `alpha` is the weight of each new sample
+    /// in the running averages, more than 0 and at most 1.
+    pub fn new(src: Streamp<Complex>, alpha: Float) -> Result<Self, Error> {
+        if !(alpha > 0.0 && alpha <= 1.0) {
+            return Err(Error::BadParameter(format!(
+                "IqBalance alpha must be in (0, 1], got {alpha}"
+            )));
+        }
+        Ok(Self {
+            src,
+            dst: new_streamp(),
+            alpha,
+            ii: 0.0,
+            qq: 0.0,
+            iq: 0.0,
+        })
+    }
+
+    fn process_one(&mut self, x: Complex) -> Complex {
+        let (i, q) = (x.re, x.im);
+        self.ii += self.alpha * (i * i - self.ii);
+        self.qq += self.alpha * (q * q - self.qq);
+        self.iq += self.alpha * (i * q - self.iq);
+        if self.ii <= Float::EPSILON {
+            return x;
+        }
+        let k = self.iq / self.ii;
+        let q1q1 = self.qq - k * self.iq;
+        if q1q1 <= Float::EPSILON {
+            return x;
+        }
+        Complex::new(i, (q - k * i) * (self.ii / q1q1).sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    // Power of the tone and of its image, over `s`.
+    fn tone_and_image(s: &[Complex], w: Float) -> (Float, Float) {
+        let (mut tone, mut image) = (Complex::default(), Complex::default());
+        for (n, x) in s.iter().enumerate() {
+            let ph = Complex::from_polar(1.0, -w * n as Float);
+            tone += x * ph;
+            image += x * ph.conj();
+        }
+        let l = s.len() as Float;
+        ((tone / l).norm_sqr(), (image / l).norm_sqr())
+    }
+
+    #[test]
+    fn correct() -> crate::Result<()> {
+        let w = 0.3;
+        // 2dB gain error and 6° phase error.
+        let input: Vec<Complex> = (0..20000)
+            .map(|n| {
+                let t = w * n as Float;
+                Complex::new(t.cos(), 0.8 * (t + 0.1).sin())
+            })
+            .collect();
+        let (tone, image) = tone_and_image(&input[10000..], w);
+        assert!(image / tone > 0.005, "{tone} {image}");
+
+        let mut b = IqBalance::new(streamp_from_slice(&input), 0.001)?;
+        assert_eq!(b.block_name(), "IqBalance");
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        let (tone, image) = tone_and_image(&res.slice()[10000..], w);
+        assert!(image / tone < 0.0001, "{tone} {image}");
+        Ok(())
+    }
+
+    #[test]
+    fn balanced() -> crate::Result<()> {
+        let input: Vec<Complex> = (0..5000)
+            .map(|n| Complex::from_polar(0.5, n as Float * 0.7))
+            .collect();
+        let mut b = IqBalance::new(streamp_from_slice(&input), 0.001)?;
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        for (a, b) in res.slice()[4000..].iter().zip(&input[4000..]) {
+            assert!((a - b).norm() < 0.01, "{a} {b}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn silence() -> crate::Result<()> {
+        let mut b = IqBalance::new(streamp_from_slice(&[Complex::default(); 10]), 0.1)?;
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        assert_eq!(res.slice(), &[Complex::default(); 10]);
+        Ok(())
+    }
+}
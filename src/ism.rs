@@ -0,0 +1,1005 @@
+/*! ISM band OOK/FSK sensor decoding, in the style of [rtl_433].
+
+Lots of cheap devices on 433MHz and 868MHz (weather sensors, doorbells,
+remote controls) send short bursts of pulses, where the information is
+in the widths of the pulses and gaps.
+
+```text
+  Complex ─→ PulseSlicer ─→ PulseTrain ─→ IsmDecode ─→ IsmMessage
+```
+
+[PulseSlicer] turns I/Q into [PulseTrain]s, measuring pulse and gap
+widths in microseconds. For OOK, a pulse is carrier on. For FSK, a pulse
+is the higher of the two frequencies, while a carrier is present.
+
+[IsmDecode] runs each pulse train through a registry of [Device]
+decoders. Each device says how its bits are coded ([Slicing]), and gets
+to look at the resulting [BitRows]. Decoded messages are [IsmMessage],
+a JSON object with a "model" field and device specific fields.
+
+Pulse trains that no device decodes are logged at debug level, together
+with a guess from [classify] of how they're coded. This is a good
+starting point when adding a new device.
+
+[rtl_433]: https://github.com/merbanan/rtl_433
+*/
+use anyhow::Result;
+use log::{debug, info};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp, Streamp};
+use crate::{Complex, Error, Float};
+
+// Carrier must be this much above the noise floor (10dB).
+const THRESHOLD: Float = 10.0;
+
+// Carrier drops when below this fraction of the pulse train's peak.
+const PEAK_FRACTION: Float = 0.1;
+
+// Trains with fewer pulses are considered noise.
+const MIN_PULSES: usize = 8;
+
+/// Modulation of a pulse train.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Modulation {
+    /// On-off keying.
+    #[default]
+    Ook,
+
+    /// Frequency shift keying.
+    Fsk,
+}
+
+/// A pulse, and the gap following it.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Pulse {
+    /// Pulse width, in microseconds.
+    pub pulse_us: Float,
+
+    /// Gap width, in microseconds.
+    pub gap_us: Float,
+}
+
+/// A burst of pulses.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PulseTrain {
+    /// Modulation the train was received with.
+    pub modulation: Modulation,
+
+    /// Pulses. The last gap is at least the reset limit.
+    pub pulses: Vec<Pulse>,
+}
+
+impl crate::Len for PulseTrain {
+    fn len(&self) -> usize {
+        self.pulses.len()
+    }
+}
+
+/// How bits are coded into pulses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coding {
+    /// Pulse position modulation. Fixed pulse width, and a short gap
+    /// is 0 and a long gap is 1.
+    Ppm,
+
+    /// Pulse width modulation. A short pulse is 1, and a long pulse
+    /// is 0.
+    Pwm,
+
+    /// Non-return to zero. Pulse is 1, gap is 0, and `short_us` is the
+    /// bit width.
+    Nrz,
+}
+
+/// Parameters for turning a pulse train into bits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Slicing {
+    /// Coding.
+    pub coding: Coding,
+
+    /// Short width, in microseconds.
+    pub short_us: Float,
+
+    /// Long width, in microseconds. Unused for NRZ.
+    pub long_us: Float,
+
+    /// Gaps longer than this start a new row.
+    pub gap_limit_us: Float,
+}
+
+/// Rows of bits, as produced by [slice].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BitRows {
+    /// The rows.
+    pub rows: Vec<Vec<bool>>,
+}
+
+impl BitRows {
+    /// Return a row as bytes, MSB first. The last byte is zero padded.
+    pub fn bytes(&self, row: usize) -> Vec<u8> {
+        pack_bits(&self.rows[row])
+    }
+
+    /// Find the first row of at least `min_bits` bits that is repeated
+    /// at least `min_repeats` times.
+    pub fn repeated_row(&self, min_repeats: usize, min_bits: usize) -> Option<usize> {
+        self.rows.iter().enumerate().find_map(|(n, row)| {
+            let count = self.rows.iter().filter(|r| *r == row).count();
+            (row.len() >= min_bits && count >= min_repeats).then_some(n)
+        })
+    }
+
+    /// Invert all bits.
+    pub fn invert(&mut self) {
+        self.rows
+            .iter_mut()
+            .flat_map(|r| r.iter_mut())
+            .for_each(|b| *b = !*b);
+    }
+
+    /// Find the first `bits` bits of `pattern` in a row, starting at
+    /// `start`. Returns the position right after the match.
+    pub fn search(&self, row: usize, start: usize, pattern: &[u8], bits: usize) -> Option<usize> {
+        let want: Vec<bool> = (0..bits)
+            .map(|i| (pattern[i / 8] >> (7 - i % 8)) & 1 == 1)
+            .collect();
+        let r = self.rows.get(row)?;
+        r.get(start..)?
+            .windows(bits)
+            .position(|w| w == want)
+            .map(|p| start + p + bits)
+    }
+
+    /// Manchester decode a row from `start`, where 01 is 1 and 10 is 0.
+    ///
+    /// Stops at the first invalid bit cell. A final half bit cell is
+    /// completed, since a trailing 0 can't be told apart from the end
+    /// of transmission.
+    pub fn manchester(&self, row: usize, start: usize) -> Vec<bool> {
+        let mut chips = self.rows[row][start.min(self.rows[row].len())..].to_vec();
+        if chips.len() % 2 == 1 {
+            chips.push(!chips[chips.len() - 1]);
+        }
+        chips
+            .chunks_exact(2)
+            .map_while(|c| (c[0] != c[1]).then_some(c[1]))
+            .collect()
+    }
+
+    /// Differential Manchester decode a row from `start`, where a
+    /// transition at the start of a bit cell is 0, and no transition is
+    /// 1. The chip before `start` is the reference.
+    ///
+    /// Stops at the first invalid bit cell.
+    pub fn differential_manchester(&self, row: usize, start: usize) -> Vec<bool> {
+        let r = &self.rows[row];
+        let Some(mut prev) = start.checked_sub(1).and_then(|n| r.get(n)).copied() else {
+            return Vec::new();
+        };
+        let mut chips = r[start..].to_vec();
+        if chips.len() % 2 == 1 {
+            chips.push(!chips[chips.len() - 1]);
+        }
+        chips
+            .chunks_exact(2)
+            .map_while(|c| {
+                let bit = c[0] == prev;
+                prev = c[1];
+                (c[0] != c[1]).then_some(bit)
+            })
+            .collect()
+    }
+}
+
+/// Pack bits into bytes, MSB first. The last byte is zero padded.
+pub fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|c| {
+            c.iter()
+                .enumerate()
+                .fold(0, |acc, (n, b)| acc | ((*b as u8) << (7 - n)))
+        })
+        .collect()
+}
+
+/// Turn a pulse train into rows of bits.
+///
+/// Widths are classified as short or long by whichever they're closest
+/// to. Empty rows are dropped.
+pub fn slice(train: &PulseTrain, s: &Slicing) -> BitRows {
+    let mid = (s.short_us + s.long_us) / 2.0;
+    let mut rows = vec![Vec::new()];
+    for p in &train.pulses {
+        let row = rows.last_mut().unwrap();
+        match s.coding {
+            Coding::Pwm => row.push(p.pulse_us < mid),
+            Coding::Ppm => {
+                if p.gap_us <= s.gap_limit_us {
+                    row.push(p.gap_us > mid);
+                }
+            }
+            Coding::Nrz => {
+                let bits = |w: Float| (w / s.short_us).round().max(1.0) as usize;
+                row.extend(std::iter::repeat_n(true, bits(p.pulse_us)));
+                if p.gap_us <= s.gap_limit_us {
+                    row.extend(std::iter::repeat_n(false, bits(p.gap_us)));
+                }
+            }
+        }
+        if p.gap_us > s.gap_limit_us {
+            rows.push(Vec::new());
+        }
+    }
+    rows.retain(|r| !r.is_empty());
+    BitRows { rows }
+}
+
+// Cluster widths, returning the sorted cluster centers.
+fn clusters(widths: impl Iterator<Item = Float>) -> Vec<Float> {
+    let mut widths: Vec<Float> = widths.collect();
+    widths.sort_by(|a, b| a.total_cmp(b));
+    let mut ret: Vec<(Float, usize)> = Vec::new();
+    for w in widths {
+        match ret.last_mut() {
+            Some((c, n)) if w < *c * 1.3 => {
+                *c = (*c * *n as Float + w) / (*n + 1) as Float;
+                *n += 1;
+            }
+            _ => ret.push((w, 1)),
+        }
+    }
+    ret.into_iter().map(|(c, _)| c).collect()
+}
+
+/// Guess how a pulse train is coded.
+pub fn classify(train: &PulseTrain) -> Option<Slicing> {
+    let n = train.pulses.len();
+    if n < 2 {
+        return None;
+    }
+    let pc = clusters(train.pulses.iter().map(|p| p.pulse_us));
+    let gc = clusters(train.pulses[..n - 1].iter().map(|p| p.gap_us));
+    match (pc.len(), gc.len()) {
+        (1, g) if g >= 2 => Some(Slicing {
+            coding: Coding::Ppm,
+            short_us: gc[0],
+            long_us: gc[1],
+            gap_limit_us: gc[1] * 1.5,
+        }),
+        (2, _) => Some(Slicing {
+            coding: Coding::Pwm,
+            short_us: pc[0],
+            long_us: pc[1],
+            gap_limit_us: pc[1] * 3.0,
+        }),
+        _ => {
+            // NRZ if all widths are close to a multiple of the shortest.
+            let bit = pc[0].min(*gc.first().unwrap_or(&pc[0]));
+            let ok = pc.iter().chain(gc.iter()).all(|w| {
+                let r = w / bit;
+                (r - r.round()).abs() < 0.2
+            });
+            ok.then_some(Slicing {
+                coding: Coding::Nrz,
+                short_us: bit,
+                long_us: bit,
+                gap_limit_us: bit * 20.0,
+            })
+        }
+    }
+}
+
+/// A decoded message.
+///
+/// Serializes to a flat JSON object, like rtl_433's JSON output.
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct IsmMessage {
+    /// Device model.
+    pub model: String,
+
+    /// Device specific fields.
+    #[serde(flatten)]
+    pub fields: Map<String, Value>,
+}
+
+impl IsmMessage {
+    /// Create new message for a model.
+    pub fn new(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            fields: Map::new(),
+        }
+    }
+
+    /// Add a field.
+    pub fn with(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.fields.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Return a field.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.fields.get(key)
+    }
+
+    /// Serialize to a JSON string.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("IsmMessage is always valid JSON")
+    }
+}
+
+impl std::fmt::Display for IsmMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_json())
+    }
+}
+
+impl crate::Len for IsmMessage {
+    fn len(&self) -> usize {
+        self.fields.len()
+    }
+}
+
+/// A device decoder.
+pub trait Device: Send {
+    /// Model name.
+    fn name(&self) -> &str;
+
+    /// Modulation. Defaults to OOK.
+    fn modulation(&self) -> Modulation {
+        Modulation::Ook
+    }
+
+    /// How to turn pulses into bits.
+    fn slicing(&self) -> Slicing;
+
+    /// Decode bits, if they're from this device.
+    fn decode(&self, bits: &BitRows) -> Option<IsmMessage>;
+}
+
+/// Return all built in device decoders.
+pub fn devices() -> Vec<Box<dyn Device>> {
+    vec![
+        Box::new(NexusTh {}),
+        Box::new(FineOffsetWh2 {}),
+        Box::new(Ev1527 {}),
+        Box::new(crate::tpms::Renault {}),
+        Box::new(crate::tpms::Citroen {}),
+        Box::new(crate::tpms::Toyota {}),
+        Box::new(crate::tpms::Schrader {}),
+    ]
+}
+
+// CRC-8, MSB first.
+pub(crate) fn crc8(data: &[u8], poly: u8, init: u8) -> u8 {
+    data.iter().fold(init, |mut crc, b| {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ poly
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/** Nexus temperature and humidity sensor.
+
+Also sold under many other names. 36 bits, repeated 10 times:
+
+```text
+  IIIIIIII BxCCTTTT TTTTTTTT 1111HHHH HHHH
+```
+
+* I: Random ID, changing on battery change.
+* B: Battery OK.
+* C: Channel, minus one.
+* T: Temperature in 0.1C, signed.
+* H: Humidity.
+*/
+pub struct NexusTh {}
+
+impl Device for NexusTh {
+    fn name(&self) -> &str {
+        "Nexus-TH"
+    }
+    fn slicing(&self) -> Slicing {
+        Slicing {
+            coding: Coding::Ppm,
+            short_us: 1000.0,
+            long_us: 2000.0,
+            gap_limit_us: 3000.0,
+        }
+    }
+    fn decode(&self, bits: &BitRows) -> Option<IsmMessage> {
+        let r = bits.repeated_row(3, 36)?;
+        if bits.rows[r].len() > 37 {
+            return None;
+        }
+        let b = bits.bytes(r);
+        if b[3] & 0xf0 != 0xf0 {
+            return None;
+        }
+        let temp = ((((b[1] as u16 & 0x0f) << 12) | ((b[2] as u16) << 4)) as i16) >> 4;
+        let humidity = ((b[3] & 0x0f) << 4) | (b[4] >> 4);
+        if humidity > 100 {
+            return None;
+        }
+        Some(
+            IsmMessage::new(self.name())
+                .with("id", b[0])
+                .with("channel", ((b[1] >> 4) & 3) + 1)
+                .with("battery_ok", b[1] >> 7)
+                .with("temperature_C", temp as f64 / 10.0)
+                .with("humidity", humidity),
+        )
+    }
+}
+
+/** Fine Offset WH2 temperature and humidity sensor.
+
+Also sold as Agimex, Renkforce, and others. 48 bits, PWM with 500us for
+1 and 1500us for 0:
+
+```text
+  11111111 TTTTIIII IIIIMMMM MMMMMMMM HHHHHHHH CCCCCCCC
+```
+
+* T: Type, 4 for WH2.
+* I: ID.
+* M: Temperature in 0.1C, sign and magnitude.
+* H: Humidity.
+* C: CRC-8 with polynomial 0x31, over the four bytes after the preamble.
+*/
+pub struct FineOffsetWh2 {}
+
+impl Device for FineOffsetWh2 {
+    fn name(&self) -> &str {
+        "Fineoffset-WH2"
+    }
+    fn slicing(&self) -> Slicing {
+        Slicing {
+            coding: Coding::Pwm,
+            short_us: 500.0,
+            long_us: 1500.0,
+            gap_limit_us: 3000.0,
+        }
+    }
+    fn decode(&self, bits: &BitRows) -> Option<IsmMessage> {
+        let (r, _) = bits.rows.iter().enumerate().find(|(_, r)| r.len() == 48)?;
+        let b = bits.bytes(r);
+        if b[0] != 0xff || b[1] >> 4 != 4 || crc8(&b[1..5], 0x31, 0) != b[5] {
+            return None;
+        }
+        let raw = ((b[2] as i32 & 0x0f) << 8) | b[3] as i32;
+        let temp = if raw & 0x800 != 0 {
+            -(raw & 0x7ff)
+        } else {
+            raw
+        };
+        Some(
+            IsmMessage::new(self.name())
+                .with("id", ((b[1] & 0x0f) << 4) | (b[2] >> 4))
+                .with("temperature_C", temp as f64 / 10.0)
+                .with("humidity", b[4]),
+        )
+    }
+}
+
+/** EV1527 based remotes and doorbells.
+
+The EV1527 encoder chip is in most cheap wireless doorbell buttons,
+remote controls, and door sensors. A sync pulse followed by 24 bits, the
+first 20 being a fixed ID and the last 4 the buttons, repeated at least
+a few times.
+
+Bits are 1T pulse and 3T gap for 0, and 3T pulse and 1T gap for 1. The
+sync is 1T pulse and 31T gap. T is commonly around 350us.
+*/
+pub struct Ev1527 {}
+
+impl Device for Ev1527 {
+    fn name(&self) -> &str {
+        "EV1527"
+    }
+    fn slicing(&self) -> Slicing {
+        Slicing {
+            coding: Coding::Pwm,
+            short_us: 350.0,
+            long_us: 1050.0,
+            gap_limit_us: 3000.0,
+        }
+    }
+    fn decode(&self, bits: &BitRows) -> Option<IsmMessage> {
+        // The next sync pulse ends up as a 25th bit.
+        let mut bits = BitRows {
+            rows: bits
+                .rows
+                .iter()
+                .filter(|r| r.len() == 24 || r.len() == 25)
+                .map(|r| r[..24].to_vec())
+                .collect(),
+        };
+        bits.invert();
+        let r = bits.repeated_row(2, 24)?;
+        let b = bits.bytes(r);
+        let id = ((b[0] as u32) << 12) | ((b[1] as u32) << 4) | (b[2] as u32 >> 4);
+        Some(
+            IsmMessage::new(self.name())
+                .with("id", format!("{id:05x}"))
+                .with("cmd", b[2] & 0x0f),
+        )
+    }
+}
+
+/** Turn I/Q into pulse trains.
+
+The noise floor is tracked between trains, and a train starts when
+the power goes 10dB above it.
+*/
+pub struct PulseSlicer {
+    src: Streamp<Complex>,
+    dst: NoCopyStreamp<PulseTrain>,
+    modulation: Modulation,
+    us_per_sample: Float,
+    reset_samples: usize,
+    noise: Option<Float>,
+    noise_alpha: Float,
+    peak: Float,
+    prev: Complex,
+    mark: Float,
+    space: Float,
+    in_train: bool,
+    level: bool,
+    run: usize,
+    quiet: usize,
+    pulse: usize,
+    pulses: Vec<Pulse>,
+    trains: usize,
+}
+
+impl PulseSlicer {
+    /// Create new pulse slicer.
+    pub fn new(src: Streamp<Complex>, samp_rate: Float, modulation: Modulation) -> Self {
+        let mut r = Self {
+            src,
+            dst: new_nocopy_streamp(),
+            modulation,
+            us_per_sample: 1e6 / samp_rate,
+            reset_samples: 0,
+            noise: None,
+            // About 10ms time constant.
+            noise_alpha: 100.0 / samp_rate,
+            peak: 0.0,
+            prev: Complex::default(),
+            mark: 0.0,
+            space: 0.0,
+            in_train: false,
+            level: false,
+            run: 0,
+            quiet: 0,
+            pulse: 0,
+            pulses: Vec::new(),
+            trains: 0,
+        };
+        r.set_reset_limit(20_000.0);
+        r
+    }
+
+    /// Set how long carrier has to be gone to end a pulse train, in
+    /// microseconds. Default 20ms.
+    pub fn set_reset_limit(&mut self, us: Float) {
+        self.reset_samples = (us / self.us_per_sample) as usize;
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<PulseTrain> {
+        self.dst.clone()
+    }
+
+    fn carrier(&mut self, p: Float) -> bool {
+        let noise = *self.noise.get_or_insert(p);
+        let carrier = p > (noise * THRESHOLD).max(self.peak * PEAK_FRACTION) && p > 0.0;
+        if self.in_train {
+            self.peak = self.peak.max(p);
+        } else if !carrier {
+            self.noise = Some(noise + (p - noise) * self.noise_alpha);
+        }
+        carrier
+    }
+
+    // For FSK, true if the instantaneous frequency is the higher one.
+    fn mark(&mut self, s: Complex, carrier: bool) -> bool {
+        let f = (s * self.prev.conj()).arg();
+        if !carrier {
+            self.mark = f;
+            self.space = f;
+        }
+        let mid = (self.mark + self.space) / 2.0;
+        let high = f > mid;
+        if high {
+            self.mark += (f - self.mark) * 0.1;
+        } else {
+            self.space += (f - self.space) * 0.1;
+        }
+        high
+    }
+
+    pub(crate) fn process_one(&mut self, s: Complex) -> Option<PulseTrain> {
+        let carrier = self.carrier(s.norm_sqr());
+        let level = match self.modulation {
+            Modulation::Ook => carrier,
+            Modulation::Fsk => self.mark(s, carrier) && carrier,
+        };
+        self.prev = s;
+        if level != self.level {
+            if level {
+                if self.in_train {
+                    self.pulses.push(Pulse {
+                        pulse_us: self.pulse as Float * self.us_per_sample,
+                        gap_us: self.run as Float * self.us_per_sample,
+                    });
+                }
+                self.in_train = true;
+            } else {
+                self.pulse = self.run;
+            }
+            self.run = 0;
+            self.level = level;
+        }
+        self.run += 1;
+        self.quiet = if carrier { 0 } else { self.quiet + 1 };
+        if !self.in_train || self.quiet < self.reset_samples {
+            return None;
+        }
+        if self.level {
+            self.pulse = self.run;
+            self.run = 0;
+        }
+        self.pulses.push(Pulse {
+            pulse_us: self.pulse as Float * self.us_per_sample,
+            gap_us: self.run as Float * self.us_per_sample,
+        });
+        self.in_train = false;
+        self.level = false;
+        self.peak = 0.0;
+        let pulses = std::mem::take(&mut self.pulses);
+        (pulses.len() >= MIN_PULSES).then_some(PulseTrain {
+            modulation: self.modulation,
+            pulses,
+        })
+    }
+}
+
+impl Drop for PulseSlicer {
+    fn drop(&mut self) {
+        info!("PulseSlicer: {} pulse trains", self.trains);
+    }
+}
+
+impl Block for PulseSlicer {
+    fn block_name(&self) -> &str {
+        "PulseSlicer"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        for s in input.iter() {
+            if let Some(train) = self.process_one(*s) {
+                debug!("PulseSlicer: train of {} pulses", train.pulses.len());
+                self.trains += 1;
+                self.dst.push(train, &[]);
+            }
+        }
+        let n = input.len();
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+/** Decode pulse trains using a registry of device decoders.
+
+Every device matching the pulse train's modulation gets to try, so one
+pulse train can produce more than one message.
+*/
+pub struct IsmDecode {
+    src: NoCopyStreamp<PulseTrain>,
+    dst: NoCopyStreamp<IsmMessage>,
+    devices: Vec<Box<dyn Device>>,
+    decoded: usize,
+    unknown: usize,
+}
+
+impl IsmDecode {
+    /// Create new decoder, with all built in devices.
+    pub fn new(src: NoCopyStreamp<PulseTrain>) -> Self {
+        Self::with_devices(src, devices())
+    }
+
+    /// Create new decoder, with only the given devices.
+    pub fn with_devices(src: NoCopyStreamp<PulseTrain>, devices: Vec<Box<dyn Device>>) -> Self {
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            devices,
+            decoded: 0,
+            unknown: 0,
+        }
+    }
+
+    /// Add a device decoder.
+    pub fn add_device(&mut self, dev: Box<dyn Device>) {
+        self.devices.push(dev);
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<IsmMessage> {
+        self.dst.clone()
+    }
+
+    fn decode(&self, train: &PulseTrain) -> Vec<IsmMessage> {
+        self.devices
+            .iter()
+            .filter(|d| d.modulation() == train.modulation)
+            .filter_map(|d| d.decode(&slice(train, &d.slicing())))
+            .collect()
+    }
+}
+
+impl Drop for IsmDecode {
+    fn drop(&mut self) {
+        info!(
+            "IsmDecode: {} messages decoded, {} unknown pulse trains",
+            self.decoded, self.unknown
+        );
+    }
+}
+
+impl Block for IsmDecode {
+    fn block_name(&self) -> &str {
+        "IsmDecode"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (train, _tags) = match self.src.pop() {
+            None => return Ok(BlockRet::Noop),
+            Some(x) => x,
+        };
+        let msgs = self.decode(&train);
+        if msgs.is_empty() {
+            self.unknown += 1;
+            match classify(&train) {
+                Some(s) => {
+                    let bits = slice(&train, &s);
+                    let rows: Vec<String> = (0..bits.rows.len())
+                        .map(|r| format!("{{{}}}", bits.rows[r].len()) + &hex(&bits.bytes(r)))
+                        .collect();
+                    debug!("IsmDecode: unknown {s:?}: {}", rows.join(" "));
+                }
+                None => debug!("IsmDecode: unknown pulse train {:?}", train.pulses),
+            }
+        }
+        for m in msgs {
+            debug!("IsmDecode: {m}");
+            self.decoded += 1;
+            self.dst.push(m, &[]);
+        }
+        Ok(BlockRet::Ok)
+    }
+}
+
+fn hex(b: &[u8]) -> String {
+    b.iter().map(|x| format!("{x:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::new_streamp;
+    use serde_json::json;
+
+    fn bits_of(bytes: &[u8], n: usize) -> Vec<bool> {
+        (0..n)
+            .map(|i| (bytes[i / 8] >> (7 - i % 8)) & 1 == 1)
+            .collect()
+    }
+
+    fn ppm(rows: &[Vec<bool>], repeats: usize) -> PulseTrain {
+        let mut pulses = Vec::new();
+        for _ in 0..repeats {
+            for row in rows {
+                for b in row {
+                    let gap_us = if *b { 2000.0 } else { 1000.0 };
+                    pulses.push(Pulse {
+                        pulse_us: 500.0,
+                        gap_us,
+                    });
+                }
+                pulses.push(Pulse {
+                    pulse_us: 500.0,
+                    gap_us: 4000.0,
+                });
+            }
+        }
+        pulses.last_mut().unwrap().gap_us = 20000.0;
+        PulseTrain {
+            modulation: Modulation::Ook,
+            pulses,
+        }
+    }
+
+    fn pwm(row: &[bool], short: Float, long: Float, gap: Float) -> Vec<Pulse> {
+        row.iter()
+            .map(|b| Pulse {
+                pulse_us: if *b { short } else { long },
+                gap_us: gap,
+            })
+            .collect()
+    }
+
+    fn nexus_bits() -> Vec<bool> {
+        // ID 0xa5, battery ok, channel 2, -5.3C, 61%.
+        let t = (-53i16 as u16) & 0xfff;
+        let b = [
+            0xa5,
+            0x90 | (t >> 8) as u8,
+            t as u8,
+            0xf0 | (61 >> 4),
+            (61 & 0x0f) << 4,
+        ];
+        bits_of(&b, 36)
+    }
+
+    // Synthesize I/Q for an OOK pulse train.
+    fn modulate(train: &PulseTrain, samp_rate: Float) -> Vec<Complex> {
+        let spu = samp_rate / 1e6;
+        let mut out = vec![Complex::new(0.0, 0.0); 1000];
+        for p in &train.pulses {
+            out.extend(std::iter::repeat_n(
+                Complex::new(1.0, 0.0),
+                (p.pulse_us * spu) as usize,
+            ));
+            out.extend(std::iter::repeat_n(
+                Complex::new(0.0, 0.0),
+                (p.gap_us * spu) as usize,
+            ));
+        }
+        let mut x: u32 = 1;
+        out.iter()
+            .map(|s| {
+                let mut r = || {
+                    x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                    ((x >> 16) % 1000) as Float / 1000.0 - 0.5
+                };
+                s + Complex::new(r(), r()) * 0.05
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nexus() {
+        let train = ppm(&[nexus_bits()], 5);
+        let bits = slice(&train, &NexusTh {}.slicing());
+        assert_eq!(bits.rows.len(), 5);
+        let m = NexusTh {}.decode(&bits).unwrap();
+        assert_eq!(
+            m.to_json(),
+            r#"{"model":"Nexus-TH","battery_ok":1,"channel":2,"humidity":61,"id":165,"temperature_C":-5.3}"#
+        );
+    }
+
+    #[test]
+    fn wh2() {
+        let mut b = [0xff, 0x4a, 0xb0, 0xe7, 0x37, 0];
+        b[5] = crc8(&b[1..5], 0x31, 0);
+        let train = PulseTrain {
+            modulation: Modulation::Ook,
+            pulses: pwm(&bits_of(&b, 48), 500.0, 1500.0, 1000.0),
+        };
+        let bits = slice(&train, &FineOffsetWh2 {}.slicing());
+        let m = FineOffsetWh2 {}.decode(&bits).unwrap();
+        assert_eq!(m.get("id"), Some(&json!(0xab)));
+        assert_eq!(m.get("temperature_C"), Some(&json!(23.1)));
+        assert_eq!(m.get("humidity"), Some(&json!(55)));
+
+        b[4] ^= 1;
+        let train = PulseTrain {
+            modulation: Modulation::Ook,
+            pulses: pwm(&bits_of(&b, 48), 500.0, 1500.0, 1000.0),
+        };
+        assert!(FineOffsetWh2 {}
+            .decode(&slice(&train, &FineOffsetWh2 {}.slicing()))
+            .is_none());
+    }
+
+    #[test]
+    fn ev1527_pipeline() -> Result<()> {
+        let code = bits_of(&[0x12, 0x34, 0x58], 24);
+        let mut pulses = Vec::new();
+        for _ in 0..4 {
+            pulses.push(Pulse {
+                pulse_us: 350.0,
+                gap_us: 10850.0,
+            });
+            for b in &code {
+                let (pulse_us, gap_us) = if *b { (1050.0, 350.0) } else { (350.0, 1050.0) };
+                pulses.push(Pulse { pulse_us, gap_us });
+            }
+        }
+        let train = PulseTrain {
+            modulation: Modulation::Ook,
+            pulses,
+        };
+        let samp_rate = 250_000.0;
+        let mut slicer = PulseSlicer::new(new_streamp(), samp_rate, Modulation::Ook);
+        let mut trains = Vec::new();
+        // Doesn't fit in a stream, so feed it directly.
+        let mut samples = modulate(&train, samp_rate);
+        samples.extend(vec![Complex::default(); 10000]);
+        for s in samples {
+            trains.extend(slicer.process_one(s));
+        }
+        assert_eq!(trains.len(), 1);
+        assert_eq!(trains[0].pulses.len(), train.pulses.len());
+
+        let src = new_nocopy_streamp();
+        src.push(trains.remove(0), &[]);
+        let mut dec = IsmDecode::new(src);
+        dec.work()?;
+        let (m, _) = dec.out().pop().unwrap();
+        assert_eq!(m.to_json(), r#"{"model":"EV1527","cmd":8,"id":"12345"}"#);
+        Ok(())
+    }
+
+    #[test]
+    fn classifier() {
+        let s = classify(&ppm(&[nexus_bits()], 3)).unwrap();
+        assert_eq!(s.coding, Coding::Ppm);
+        assert!((s.short_us - 1000.0).abs() < 1.0);
+        assert!((s.long_us - 2000.0).abs() < 1.0);
+
+        let row = bits_of(&[0x5a, 0x3c], 16);
+        let train = PulseTrain {
+            modulation: Modulation::Ook,
+            pulses: pwm(&row, 400.0, 800.0, 600.0),
+        };
+        let s = classify(&train).unwrap();
+        assert_eq!(s.coding, Coding::Pwm);
+        assert_eq!(slice(&train, &s).rows, vec![row]);
+    }
+
+    #[test]
+    fn fsk_nrz() {
+        // 10kbps NRZ, +-20kHz deviation at 200ksps.
+        let samp_rate = 200_000.0;
+        let data = bits_of(&[0xaa, 0xaa, 0x2d, 0xd4, 0x81, 0x7e], 48);
+        let mut samples = vec![Complex::default(); 500];
+        let mut phase: Float = 0.0;
+        for b in &data {
+            let dev: Float = if *b { 20000.0 } else { -20000.0 };
+            for _ in 0..20 {
+                phase += 2.0 * std::f64::consts::PI as Float * dev / samp_rate;
+                samples.push(Complex::from_polar(1.0, phase));
+            }
+        }
+        samples.extend(vec![Complex::default(); 5000]);
+        let mut slicer = PulseSlicer::new(new_streamp(), samp_rate, Modulation::Fsk);
+        let trains: Vec<PulseTrain> = samples
+            .into_iter()
+            .filter_map(|s| slicer.process_one(s))
+            .collect();
+        assert_eq!(trains.len(), 1);
+        let s = classify(&trains[0]).unwrap();
+        assert_eq!(s.coding, Coding::Nrz);
+        let bits = slice(&trains[0], &s);
+        // A trailing 0 can't be told apart from carrier going away.
+        assert_eq!(bits.rows[0], data[..47]);
+    }
+}
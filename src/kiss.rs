@@ -0,0 +1,83 @@
+/*! KISS framing.
+
+[KISS] is the framing used between a host and a TNC, and by most
+packet radio software to exchange AX.25 frames over TCP or UDP.
+
+A frame is `FEND`, a command byte (port in the high nibble, command in
+the low nibble), the escaped data, and a closing `FEND`.
+
+[KISS]: https://en.wikipedia.org/wiki/KISS_(amateur_radio_protocol)
+*/
+
+/// Frame end.
+pub const FEND: u8 = 0xc0;
+
+/// Frame escape.
+pub const FESC: u8 = 0xdb;
+
+/// Transposed frame end.
+pub const TFEND: u8 = 0xdc;
+
+/// Transposed frame escape.
+pub const TFESC: u8 = 0xdd;
+
+/// Command for data frames.
+pub const CMD_DATA: u8 = 0x00;
+
+/// Encode a data frame for `port` (0-15).
+pub fn encode(port: u8, data: &[u8]) -> Vec<u8> {
+    let mut v = Vec::with_capacity(data.len() + 4);
+    v.push(FEND);
+    v.push((port & 0x0f) << 4 | CMD_DATA);
+    for b in data {
+        match *b {
+            FEND => v.extend([FESC, TFEND]),
+            FESC => v.extend([FESC, TFESC]),
+            b => v.push(b),
+        }
+    }
+    v.push(FEND);
+    v
+}
+
+/// Decode one frame, returning the command byte and the data.
+///
+/// Leading and trailing `FEND` are optional. Returns `None` for empty
+/// frames and bad escapes.
+pub fn decode(frame: &[u8]) -> Option<(u8, Vec<u8>)> {
+    let frame = frame.strip_prefix(&[FEND]).unwrap_or(frame);
+    let frame = frame.strip_suffix(&[FEND]).unwrap_or(frame);
+    let (cmd, rest) = frame.split_first()?;
+    let mut v = Vec::with_capacity(rest.len());
+    let mut it = rest.iter();
+    while let Some(b) = it.next() {
+        match *b {
+            FESC => v.push(match *it.next()? {
+                TFEND => FEND,
+                TFESC => FESC,
+                _ => return None,
+            }),
+            FEND => return None,
+            b => v.push(b),
+        }
+    }
+    Some((*cmd, v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let data = vec![1, FEND, 2, FESC, 3, TFEND];
+        let enc = encode(1, &data);
+        assert_eq!(
+            enc,
+            vec![FEND, 0x10, 1, FESC, TFEND, 2, FESC, TFESC, 3, TFEND, FEND]
+        );
+        assert_eq!(decode(&enc), Some((0x10, data)));
+        assert_eq!(decode(&[FEND, FEND]), None);
+        assert_eq!(decode(&[FEND, 0, FESC, 1, FEND]), None);
+    }
+}
@@ -1,4 +1,7 @@
 #![warn(missing_docs)]
+// Casts between Float and f32/f64 are redundant for one of the two
+// Float types, but needed for the other.
+#![allow(clippy::unnecessary_cast)]
 /*! This create provides a framework for running SDR (software defined
 radio) applications.
 
@@ -104,45 +107,148 @@ g.run()?;
 use anyhow::Result;
 use stream::Stream;
 
+// So that `#[derive(Block)]` can refer to `::rustradio` from inside
+// this crate too.
+extern crate self as rustradio;
+
 // Blocks.
 pub mod add;
 pub mod add_const;
+pub mod afsk;
+#[cfg(feature = "decoders")]
+pub mod aprs;
+#[cfg(feature = "decoders")]
+pub mod aprs_is;
 pub mod au;
+pub mod audio_sink;
+pub mod audio_source;
+#[cfg(feature = "decoders")]
+pub mod ax25;
+pub mod ber;
 pub mod binary_slicer;
+#[cfg(feature = "decoders")]
+pub mod ble;
+#[cfg(feature = "json")]
+pub mod burst_file_sink;
 pub mod burst_tagger;
+pub mod callback_sink;
+#[cfg(feature = "decoders")]
+pub mod ccsds;
+pub mod channel_model;
+pub mod channelizer;
 pub mod complex_to_mag2;
 pub mod constant_source;
+pub mod constellation_sink;
+pub mod control;
 pub mod convert;
 pub mod correlate_access_code;
+#[cfg(all(feature = "fft", feature = "json"))]
+pub mod dashboard;
+pub mod dc_blocker;
 pub mod debug_sink;
 pub mod delay;
 pub mod descrambler;
+pub mod divide;
+#[cfg(feature = "sgp4")]
+pub mod doppler;
+#[cfg(feature = "decoders")]
+pub mod dtmf;
+#[cfg(feature = "fft")]
 pub mod fft_filter;
 pub mod file_sink;
 pub mod file_source;
 pub mod fir;
+#[cfg(feature = "fft")]
+pub mod freq_offset;
+pub mod freq_xlating_fir;
+pub mod frequency_modulator;
+#[cfg(feature = "json")]
+pub mod golden;
+#[cfg(feature = "json")]
+pub mod grc;
 pub mod hdlc_deframer;
+pub mod hdlc_framer;
 pub mod hilbert;
-pub mod iir_filter;
+#[cfg(all(feature = "fft", feature = "json"))]
+pub mod histogram_probe;
 pub mod il2p_deframer;
+pub mod in_place;
+pub mod iq_balance;
+#[cfg(all(feature = "decoders", feature = "json"))]
+pub mod ism;
+pub mod kiss;
+#[cfg(all(feature = "decoders", feature = "fft"))]
+pub mod lora;
+#[cfg(all(feature = "decoders", feature = "png"))]
+pub mod lrpt;
+#[cfg(feature = "decoders")]
+pub mod morse;
+pub mod mqtt_sink;
+pub mod multiply;
 pub mod multiply_const;
+pub mod noise_source;
 pub mod nrzi;
 pub mod null_sink;
+pub mod pcap_sink;
+pub mod pdu;
+pub mod pdu_filter;
+pub mod pdu_pool;
+pub mod pdu_queue;
+pub mod pdu_to_stream;
 pub mod pdu_writer;
+pub mod pll;
+pub mod power_meter;
+pub mod power_squelch;
+#[cfg(feature = "decoders")]
+pub mod psk31;
+pub mod push_source;
 pub mod quadrature_demod;
 pub mod rational_resampler;
+#[cfg(feature = "decoders")]
+pub mod rds;
+#[cfg(feature = "json")]
+pub mod registry;
+pub mod rotator;
+pub mod rtl_tcp_source;
 pub mod rtlsdr_decode;
+#[cfg(feature = "decoders")]
+pub mod rtty;
+pub mod rx_meta;
+#[cfg(feature = "decoders")]
+pub mod selcall;
+#[cfg(feature = "json")]
 pub mod sigmf;
 pub mod signal_source;
 pub mod single_pole_iir_filter;
 pub mod skip;
+#[cfg(all(feature = "fft", feature = "png"))]
+pub mod spectrogram_sink;
+#[cfg(feature = "fft")]
+pub mod spectrum_sink;
+#[cfg(all(feature = "decoders", feature = "png"))]
+pub mod sstv;
 pub mod stream_to_pdu;
+pub mod subtract;
 pub mod symbol_sync;
+pub mod tcp_pdu;
 pub mod tcp_source;
 pub mod tee;
+#[cfg(feature = "decoders")]
+pub mod time_signal;
 pub mod to_text;
+#[cfg(all(feature = "decoders", feature = "json"))]
+pub mod tpms;
+pub mod udp_sink;
 pub mod vec_to_stream;
+pub mod vector_sink;
 pub mod vector_source;
+pub mod vu_meter;
+#[cfg(feature = "fft")]
+pub mod waterfall_sink;
+pub mod wbfm;
+#[cfg(feature = "decoders")]
+pub mod wmbus;
+#[cfg(feature = "fft")]
 pub mod wpcr;
 pub mod xor;
 pub mod xor_const;
@@ -154,41 +260,145 @@ pub mod rtlsdr_source;
 #[cfg(feature = "soapysdr")]
 pub mod soapysdr_source;
 
+#[cfg(feature = "zeromq")]
+pub mod zmq;
+
+#[cfg(feature = "egui")]
+pub mod gui;
+
+#[cfg(feature = "gpu")]
+pub mod gpu;
+
+#[cfg(feature = "tui")]
+pub mod tui;
+
+#[cfg(feature = "python")]
+pub mod python;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+
 pub mod block;
 pub mod blocks;
+pub mod chain;
 pub mod circular_buffer;
 pub mod graph;
 pub mod mtgraph;
+pub(crate) mod net;
+pub mod node;
 pub mod stream;
 
-/// Float type used. Usually f32, but not guaranteed.
-pub type Float = f32;
-
-/// Complex (I/Q) data.
-pub type Complex = num_complex::Complex<Float>;
+// Pure DSP, which also builds without std.
+pub use rustradio_core::{goertzel, iir_filter, kernels, reed_solomon, viterbi};
+pub use rustradio_core::{Complex, Float};
 
 /// RustRadio error.
-#[derive(Debug, Clone)]
-pub struct Error {
-    msg: String,
+///
+/// Errors from a block's `work()`, when the graph runs it, are wrapped
+/// in [Error::Block], identifying the block. [Error::root] gets the
+/// error inside.
+///
+/// Underlying errors are kept in an [Arc](std::sync::Arc), so that errors can be cloned.
+#[derive(thiserror::Error, Debug, Clone)]
+#[non_exhaustive]
+pub enum Error {
+    /// I/O error, e.g. reading a file or socket.
+    #[error("IO error: {0}")]
+    Io(#[source] std::sync::Arc<std::io::Error>),
+
+    /// Error from SDR hardware or its driver.
+    #[error("hardware error: {msg}")]
+    Hardware {
+        /// What went wrong.
+        msg: String,
+
+        /// Underlying driver error, if any.
+        #[source]
+        source: Option<std::sync::Arc<dyn std::error::Error + Send + Sync>>,
+    },
+
+    /// Invalid parameter, e.g. to a block constructor.
+    #[error("bad parameter: {0}")]
+    BadParameter(String),
+
+    /// Data of the wrong type or format, e.g. in a file.
+    #[error("type mismatch: {0}")]
+    TypeMismatch(String),
+
+    /// Unexpected end of input.
+    #[error("unexpected end of input")]
+    Eof,
+
+    /// Error in a block, while running a graph.
+    #[error("block {index} ({name}): {source}")]
+    Block {
+        /// Block name.
+        name: String,
+
+        /// Position of the block in the graph, in the order added.
+        index: usize,
+
+        /// The error.
+        #[source]
+        source: Box<Error>,
+    },
+
+    /// Other errors.
+    #[error("RustRadio Error: {msg}")]
+    Other {
+        /// What went wrong.
+        msg: String,
+
+        /// Underlying error, if any.
+        #[source]
+        source: Option<std::sync::Arc<dyn std::error::Error + Send + Sync>>,
+    },
 }
 
 impl Error {
     /// Create new error with message.
     pub fn new(msg: &str) -> Self {
-        Self {
+        Self::Other {
             msg: msg.to_string(),
+            source: None,
         }
     }
-}
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "RustRadio Error: {}", self.msg)
+    /// Create new hardware error, with the driver error as source.
+    pub fn hardware<E>(msg: &str, source: E) -> Self
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Self::Hardware {
+            msg: msg.to_string(),
+            source: Some(source.into().into()),
+        }
+    }
+
+    /// Wrap the error as coming from block number `index`, called
+    /// `name`.
+    pub fn in_block(self, name: &str, index: usize) -> Self {
+        Self::Block {
+            name: name.to_string(),
+            index,
+            source: Box::new(self),
+        }
+    }
+
+    /// The error, without any [Error::Block] wrapping.
+    pub fn root(&self) -> &Error {
+        match self {
+            Self::Block { source, .. } => source.root(),
+            e => e,
+        }
     }
 }
 
-impl std::error::Error for Error {}
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(std::sync::Arc::new(e))
+    }
+}
 
 impl<T> From<std::sync::PoisonError<std::sync::MutexGuard<'_, Stream<T>>>> for Error {
     fn from(e: std::sync::PoisonError<std::sync::MutexGuard<'_, Stream<T>>>) -> Error {
@@ -196,14 +406,34 @@ impl<T> From<std::sync::PoisonError<std::sync::MutexGuard<'_, Stream<T>>>> for E
     }
 }
 
-impl From<anyhow::Error> for Error {
-    fn from(e: anyhow::Error) -> Error {
-        Error::new(&format!("{}", e))
+impl From<rustradio_core::Error> for Error {
+    fn from(e: rustradio_core::Error) -> Self {
+        match e {
+            rustradio_core::Error::BadParameter(msg) => Error::BadParameter(msg),
+            e => Error::new(&e.to_string()),
+        }
     }
 }
-impl From<std::io::Error> for Error {
-    fn from(e: std::io::Error) -> Self {
-        Error::new(&format!("IO error: {}", e))
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Error {
+        // Don't wrap our own errors twice.
+        let e = match e.downcast::<Error>() {
+            Ok(e) => return e,
+            Err(e) => e,
+        };
+        // Plain I/O errors, if there's no context that would be lost.
+        let e = match e.chain().len() {
+            1 => match e.downcast::<std::io::Error>() {
+                Ok(e) => return e.into(),
+                Err(e) => e,
+            },
+            _ => e,
+        };
+        Error::Other {
+            msg: format!("{e}"),
+            source: Some(Box::<dyn std::error::Error + Send + Sync>::from(e).into()),
+        }
     }
 }
 
@@ -328,6 +558,38 @@ impl Sample for i32 {
     }
 }
 
+impl Sample for i16 {
+    type Type = i16;
+    fn size() -> usize {
+        2
+    }
+    fn parse(data: &[u8]) -> Result<Self::Type> {
+        if data.len() != Self::size() {
+            panic!("TODO: i16 is wrong size");
+        }
+        Ok(i16::from_le_bytes(data[0..Self::size()].try_into()?))
+    }
+    fn serialize(&self) -> Vec<u8> {
+        i16::to_le_bytes(*self).to_vec()
+    }
+}
+
+impl Sample for i8 {
+    type Type = i8;
+    fn size() -> usize {
+        1
+    }
+    fn parse(data: &[u8]) -> Result<Self::Type> {
+        if data.len() != Self::size() {
+            panic!("TODO: i8 is wrong size");
+        }
+        Ok(data[0] as i8)
+    }
+    fn serialize(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+}
+
 impl Sample for String {
     type Type = String;
     fn size() -> usize {
@@ -348,6 +610,29 @@ impl Sample for String {
     }
 }
 
+/// Stream types a spectrum, or power, can be calculated for.
+pub trait SpectrumInput: Copy {
+    /// True for real samples, which only have positive frequencies.
+    const REAL: bool;
+
+    /// Convert sample to complex.
+    fn to_complex(self) -> Complex;
+}
+
+impl SpectrumInput for Complex {
+    const REAL: bool = false;
+    fn to_complex(self) -> Complex {
+        self
+    }
+}
+
+impl SpectrumInput for Float {
+    const REAL: bool = true;
+    fn to_complex(self) -> Complex {
+        Complex::new(self, 0.0)
+    }
+}
+
 /// Trivial trait for types that have .len().
 #[allow(clippy::len_without_is_empty)]
 pub trait Len {
@@ -365,45 +650,146 @@ pub mod tests {
     //! Test helper functions.
     use super::*;
 
+    /// Tolerance when comparing floating point samples.
+    ///
+    /// A sample is close enough if the error is at most `abs`, plus
+    /// `rel` times the magnitude of the expected value.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Tolerance {
+        /// Absolute tolerance.
+        pub abs: Float,
+
+        /// Relative tolerance.
+        pub rel: Float,
+    }
+
+    impl Tolerance {
+        /// Only absolute tolerance.
+        pub fn abs(abs: Float) -> Self {
+            Self { abs, rel: 0.0 }
+        }
+
+        /// Only relative tolerance.
+        pub fn rel(rel: Float) -> Self {
+            Self { abs: 0.0, rel }
+        }
+    }
+
+    impl Default for Tolerance {
+        fn default() -> Self {
+            Self::abs(0.001)
+        }
+    }
+
+    /// Sample types that can be compared with a tolerance.
+    pub trait Close: Copy + std::fmt::Debug {
+        /// Magnitude of the difference.
+        fn error(self, other: Self) -> Float;
+
+        /// Magnitude.
+        fn magnitude(self) -> Float;
+    }
+
+    impl Close for Float {
+        fn error(self, other: Self) -> Float {
+            (self - other).abs()
+        }
+        fn magnitude(self) -> Float {
+            self.abs()
+        }
+    }
+
+    impl Close for Complex {
+        fn error(self, other: Self) -> Float {
+            (self - other).norm()
+        }
+        fn magnitude(self) -> Float {
+            self.norm()
+        }
+    }
+
+    /// For testing, assert that `got` is within tolerance of `want`.
+    ///
+    /// On failure, reports the number of bad elements, the first one,
+    /// and the max error.
+    pub fn assert_close<T: Close>(got: &[T], want: &[T], tol: Tolerance) {
+        assert_eq!(got.len(), want.len(), "\ngot: {:?}\nwant: {:?}", got, want);
+        let mut bad = Vec::new();
+        let mut max = (0, 0.0);
+        for (n, (g, w)) in got.iter().zip(want).enumerate() {
+            let err = g.error(*w);
+            if err.is_nan() || err > tol.abs + tol.rel * w.magnitude() {
+                bad.push(n);
+            }
+            if err > max.1 || err.is_nan() {
+                max = (n, err);
+            }
+        }
+        if let Some(&first) = bad.first() {
+            panic!(
+                "{} of {} elements not within {tol:?}\n\
+                 first bad element {first}: got {:?}, want {:?}\n\
+                 max error {} at element {}\n\
+                 got: {:?}\nwant: {:?}",
+                bad.len(),
+                got.len(),
+                got[first],
+                want[first],
+                max.1,
+                max.0,
+                got,
+                want
+            );
+        }
+    }
+
     /// For testing, assert that two slices are almost equal.
     ///
     /// Floating point numbers are almost never exactly equal.
     pub fn assert_almost_equal_complex(left: &[Complex], right: &[Complex]) {
-        assert_eq!(
-            left.len(),
-            right.len(),
-            "\nleft: {:?}\nright: {:?}",
-            left,
-            right
-        );
-        for i in 0..left.len() {
-            let dist = (left[i] - right[i]).norm_sqr().sqrt();
-            if dist > 0.001 {
-                assert_eq!(
-                    left[i], right[i],
-                    "\nElement {i}:\nleft: {:?}\nright: {:?}",
-                    left, right
-                );
-            }
-        }
+        assert_close(left, right, Tolerance::default());
     }
 
     /// For testing, assert that two slices are almost equal.
     ///
     /// Floating point numbers are almost never exactly equal.
     pub fn assert_almost_equal_float(left: &[Float], right: &[Float]) {
-        assert_eq!(
-            left.len(),
-            right.len(),
-            "\nleft: {:?}\nright: {:?}",
-            left,
-            right
+        assert_close(left, right, Tolerance::default());
+    }
+
+    #[test]
+    fn close() {
+        assert_close(&[1.0, 100.0], &[1.0005, 100.0005], Tolerance::default());
+        assert_close(&[100.0], &[101.0], Tolerance::rel(0.01));
+        assert_close(
+            &[Complex::new(1.0, 1.0)],
+            &[Complex::new(1.0003, 0.9996)],
+            Tolerance::default(),
         );
-        for i in 0..left.len() {
-            let dist = (left[i] - right[i]).sqrt();
-            if dist > 0.001 {
-                assert_eq!(left[i], right[i], "\nleft: {:?}\nright: {:?}", left, right);
-            }
+        for (got, want, tol) in [
+            (1.0, 1.01, Tolerance::default()),
+            (100.0, 102.0, Tolerance::rel(0.01)),
+            (Float::NAN, 1.0, Tolerance::default()),
+        ] {
+            let res = std::panic::catch_unwind(|| assert_close(&[got], &[want], tol));
+            assert!(res.is_err(), "{got} vs {want} with {tol:?}");
         }
     }
+
+    #[test]
+    fn error_clone() {
+        use std::error::Error as _;
+        let e = Error::from(std::io::Error::other("disk on fire"));
+        let c = e.clone();
+        assert_eq!(c.to_string(), "IO error: disk on fire");
+        assert!(matches!(c, Error::Io(_)));
+
+        let e = Error::hardware("open failed", std::io::Error::other("no device"));
+        let c = e.clone();
+        assert_eq!(c.to_string(), e.to_string());
+        assert_eq!(c.source().unwrap().to_string(), "no device");
+
+        let e = Error::from(anyhow::anyhow!("inner").context("outer"));
+        assert_eq!(e.clone().to_string(), e.to_string());
+    }
 }
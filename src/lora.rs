@@ -0,0 +1,802 @@
+/*! LoRa PHY receiver.
+
+[LoRa] uses chirp spread spectrum: each symbol is a frequency sweep
+across the whole bandwidth, cyclically shifted by the symbol value.
+With spreading factor SF, there are N=2^SF chips per symbol, and each
+symbol carries SF bits.
+
+A frame is:
+
+* Preamble of (usually 8) unshifted upchirps.
+* Two sync word upchirps, e.g. 0x12 for private networks and 0x34 for
+  LoRaWAN.
+* 2.25 downchirps.
+* Explicit header, in the first 8 symbols, at reduced rate (SF-2 bits
+  per symbol) and coding rate 4/8.
+* Payload, whitened, Hamming coded at rate 4/5 to 4/8, and diagonally
+  interleaved. Optionally followed by a CRC16.
+
+[LoraDecode] takes I/Q at exactly the LoRa bandwidth (1 sample per
+chip), centered on the channel, and outputs a [LoraFrame] for each
+frame with a valid header. Integer carrier frequency offsets of up to
+N/4 bins are corrected, using the preamble upchirps and the
+downchirps.
+
+Implicit header mode is not supported.
+
+The bit level conventions follow [gr-lora_sdr].
+
+[LoRa]: https://en.wikipedia.org/wiki/LoRa
+[gr-lora_sdr]: https://github.com/tapparelj/gr-lora_sdr
+*/
+use std::sync::Arc;
+
+use anyhow::Result;
+use log::{debug, info, trace};
+use rustfft::FftPlanner;
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp, Streamp};
+use crate::{Complex, Error, Float};
+
+// Number of matching preamble windows needed before trying to sync.
+const MIN_PREAMBLE: usize = 4;
+
+// Peak to average ratio needed to consider a window to contain a chirp.
+const MIN_PEAK_RATIO: Float = 8.0;
+
+/// Decoded LoRa frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoraFrame {
+    /// Spreading factor.
+    pub sf: u8,
+
+    /// Coding rate, 1-4 meaning 4/5 to 4/8.
+    pub cr: u8,
+
+    /// True if the frame has a CRC.
+    pub has_crc: bool,
+
+    /// True if the CRC matched. False if it didn't, or there was none.
+    pub crc_ok: bool,
+
+    /// Carrier frequency offset, in FFT bins.
+    pub cfo_bins: i32,
+
+    /// Dewhitened payload.
+    pub payload: Vec<u8>,
+}
+
+impl crate::Len for LoraFrame {
+    fn len(&self) -> usize {
+        self.payload.len()
+    }
+}
+
+/// Generate the whitening sequence.
+pub fn whitening_sequence(len: usize) -> Vec<u8> {
+    let mut w: u8 = 0xff;
+    (0..len)
+        .map(|_| {
+            let ret = w;
+            w = (w << 1) | ((w & 0xb8).count_ones() & 1) as u8;
+            ret
+        })
+        .collect()
+}
+
+/// CRC16 CCITT, with initial value 0, as used for LoRa payloads.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for b in data {
+        crc ^= (*b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// The LoRa payload CRC, as sent after the payload.
+pub fn payload_crc(payload: &[u8]) -> u16 {
+    let n = payload.len();
+    if n < 2 {
+        return crc16(payload);
+    }
+    crc16(&payload[..n - 2]) ^ payload[n - 1] as u16 ^ ((payload[n - 2] as u16) << 8)
+}
+
+/// Checksum of the explicit header's first three nibbles.
+pub fn header_checksum(n: [u8; 3]) -> u8 {
+    let b = |nib: usize, bit: u8| (n[nib] >> bit) & 1;
+    let c4 = b(0, 3) ^ b(0, 2) ^ b(0, 1) ^ b(0, 0);
+    let c3 = b(0, 3) ^ b(1, 3) ^ b(1, 2) ^ b(1, 1) ^ b(2, 0);
+    let c2 = b(0, 2) ^ b(1, 3) ^ b(1, 0) ^ b(2, 3) ^ b(2, 1);
+    let c1 = b(0, 1) ^ b(1, 2) ^ b(1, 0) ^ b(2, 2) ^ b(2, 1) ^ b(2, 0);
+    let c0 = b(0, 0) ^ b(1, 1) ^ b(2, 3) ^ b(2, 2) ^ b(2, 1) ^ b(2, 0);
+    (c4 << 4) | (c3 << 3) | (c2 << 2) | (c1 << 1) | c0
+}
+
+/// Hamming encode a nibble at coding rate `cr` (1-4).
+///
+/// The codeword has 4+cr bits, with the data bits first, LSB first.
+pub fn hamming_encode(nibble: u8, cr: u8) -> u8 {
+    let d = |n: u8| (nibble >> n) & 1;
+    let parity = match cr {
+        1 => vec![d(0) ^ d(1) ^ d(2) ^ d(3)],
+        _ => {
+            let p = [
+                d(0) ^ d(1) ^ d(2),
+                d(1) ^ d(2) ^ d(3),
+                d(0) ^ d(1) ^ d(3),
+                d(0) ^ d(2) ^ d(3),
+            ];
+            p[..cr as usize].to_vec()
+        }
+    };
+    (0..4)
+        .map(d)
+        .chain(parity)
+        .fold(0, |acc, bit| (acc << 1) | bit)
+}
+
+/// Hamming decode a codeword at coding rate `cr`.
+///
+/// Rates 4/7 and 4/8 correct single bit errors. Lower rates only
+/// detect errors, and the data bits are returned as is.
+pub fn hamming_decode(cw: u8, cr: u8) -> u8 {
+    if cr >= 3 {
+        return (0..16)
+            .min_by_key(|n| (hamming_encode(*n, cr) ^ cw).count_ones())
+            .unwrap();
+    }
+    let len = 4 + cr;
+    (0..4).fold(0, |acc, n| acc | (((cw >> (len - 1 - n)) & 1) << n))
+}
+
+/// Deinterleave a block of `cw_len` symbols of `sf_app` bits each, into
+/// `sf_app` codewords of `cw_len` bits each.
+pub fn deinterleave(symbols: &[u16], sf_app: usize) -> Vec<u8> {
+    let cw_len = symbols.len();
+    let mut cws = vec![0u8; sf_app];
+    for (i, s) in symbols.iter().enumerate() {
+        for j in 0..sf_app {
+            let bit = ((s >> (sf_app - 1 - j)) & 1) as u8;
+            let row = (i + 2 * sf_app - j - 1) % sf_app;
+            cws[row] |= bit << (cw_len - 1 - i);
+        }
+    }
+    cws
+}
+
+/// Interleave `sf_app` codewords of `cw_len` bits into `cw_len`
+/// symbols of `sf_app` bits.
+pub fn interleave(cws: &[u8], cw_len: usize) -> Vec<u16> {
+    let sf_app = cws.len();
+    (0..cw_len)
+        .map(|i| {
+            (0..sf_app).fold(0u16, |acc, j| {
+                let row = (i + 2 * sf_app - j - 1) % sf_app;
+                let bit = (cws[row] >> (cw_len - 1 - i)) & 1;
+                (acc << 1) | bit as u16
+            })
+        })
+        .collect()
+}
+
+/// Header fields.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Header {
+    len: usize,
+    cr: u8,
+    has_crc: bool,
+}
+
+/// Symbol to frame decoding, independent of the signal processing.
+pub struct LoraSymbolDecoder {
+    sf: usize,
+    ldro: bool,
+}
+
+impl LoraSymbolDecoder {
+    /// Create new symbol decoder.
+    pub fn new(sf: u8, ldro: bool) -> Self {
+        Self {
+            sf: sf as usize,
+            ldro,
+        }
+    }
+
+    // Demodulated FFT bin to interleaver symbol.
+    fn symbol(&self, bin: usize, reduced: bool) -> u16 {
+        let n = 1 << self.sf;
+        let mut v = (bin + n - 1) % n;
+        if reduced {
+            v >>= 2;
+        }
+        (v ^ (v >> 1)) as u16
+    }
+
+    fn block(&self, bins: &[usize], cr: u8, reduced: bool) -> Vec<u8> {
+        let sf_app = if reduced { self.sf - 2 } else { self.sf };
+        let syms: Vec<u16> = bins.iter().map(|b| self.symbol(*b, reduced)).collect();
+        deinterleave(&syms, sf_app)
+            .iter()
+            .map(|cw| hamming_decode(*cw, cr))
+            .collect()
+    }
+
+    /// Decode the header from the first 8 symbol bins.
+    ///
+    /// Returns the header and the remaining nibbles of the first block.
+    fn header(&self, bins: &[usize]) -> Option<(Header, Vec<u8>)> {
+        let nibbles = self.block(&bins[..8], 4, true);
+        let chk = ((nibbles[3] & 1) << 4) | nibbles[4];
+        if chk != header_checksum([nibbles[0], nibbles[1], nibbles[2]]) {
+            debug!("LoRa: header checksum mismatch");
+            return None;
+        }
+        let h = Header {
+            len: ((nibbles[0] << 4) | nibbles[1]) as usize,
+            cr: nibbles[2] >> 1,
+            has_crc: nibbles[2] & 1 == 1,
+        };
+        if !(1..=4).contains(&h.cr) || h.len == 0 {
+            debug!("LoRa: invalid header {h:?}");
+            return None;
+        }
+        Some((h, nibbles[5..].to_vec()))
+    }
+
+    /// Number of payload symbols after the 8 header symbols.
+    fn payload_symbols(&self, h: &Header) -> usize {
+        let total = 2 * h.len + if h.has_crc { 4 } else { 0 };
+        let rest = total.saturating_sub(self.sf - 2 - 5);
+        let sf_app = if self.ldro { self.sf - 2 } else { self.sf };
+        rest.div_ceil(sf_app) * (4 + h.cr as usize)
+    }
+
+    /// Decode a whole frame from its symbol bins, header first.
+    fn frame(&self, h: &Header, first: &[u8], bins: &[usize]) -> LoraFrame {
+        let cw_len = 4 + h.cr as usize;
+        let mut nibbles = first.to_vec();
+        for block in bins.chunks_exact(cw_len) {
+            nibbles.extend(self.block(block, h.cr, self.ldro));
+        }
+        let bytes: Vec<u8> = nibbles
+            .chunks_exact(2)
+            .map(|n| n[0] | (n[1] << 4))
+            .collect();
+        let white = whitening_sequence(h.len);
+        let payload: Vec<u8> = bytes[..h.len]
+            .iter()
+            .zip(white.iter())
+            .map(|(b, w)| b ^ w)
+            .collect();
+        let crc_ok = h.has_crc && {
+            let rx = bytes[h.len] as u16 | ((bytes[h.len + 1] as u16) << 8);
+            rx == payload_crc(&payload)
+        };
+        LoraFrame {
+            sf: self.sf as u8,
+            cr: h.cr,
+            has_crc: h.has_crc,
+            crc_ok,
+            cfo_bins: 0,
+            payload,
+        }
+    }
+}
+
+enum State {
+    // Looking for repeated upchirps.
+    Detect {
+        count: usize,
+        bin: usize,
+        pos: usize,
+    },
+
+    // Preamble found, looking for the downchirps.
+    Sync {
+        up: usize,
+        pos: usize,
+        best: Option<(Float, usize)>,
+        windows: usize,
+    },
+
+    // CFO and timing known, decoding the frame starting at `pos`.
+    Frame {
+        cfo: i32,
+        pos: usize,
+    },
+}
+
+/** LoRa receiver block.
+
+Input is I/Q at the LoRa bandwidth. Output is decoded frames.
+*/
+pub struct LoraDecode {
+    src: Streamp<Complex>,
+    dst: NoCopyStreamp<LoraFrame>,
+    sf: u8,
+    n: usize,
+    sync_word: u8,
+    decoder: LoraSymbolDecoder,
+    fft: Arc<dyn rustfft::Fft<Float>>,
+    upchirp: Vec<Complex>,
+    downchirp: Vec<Complex>,
+    buf: Vec<Complex>,
+    // Absolute sample index of buf[0].
+    base: u64,
+    state: State,
+    frames: usize,
+    crc_errors: usize,
+}
+
+impl LoraDecode {
+    /// Create new LoRa receiver for spreading factor `sf` (7-12).
+    pub fn new(src: Streamp<Complex>, sf: u8) -> Result<Self, Error> {
+        if !(7..=12).contains(&sf) {
+            return Err(Error::BadParameter(format!(
+                "LoRa spreading factor must be 7-12, got {sf}"
+            )));
+        }
+        let n = 1usize << sf;
+        let upchirp: Vec<Complex> = (0..n).map(|i| chirp(n, i)).collect();
+        let downchirp = upchirp.iter().map(|c| c.conj()).collect();
+        Ok(Self {
+            src,
+            dst: new_nocopy_streamp(),
+            sf,
+            n,
+            sync_word: 0x12,
+            decoder: LoraSymbolDecoder::new(sf, false),
+            fft: FftPlanner::new().plan_fft_forward(n),
+            upchirp,
+            downchirp,
+            buf: Vec::new(),
+            base: 0,
+            state: State::Detect {
+                count: 0,
+                bin: 0,
+                pos: 0,
+            },
+            frames: 0,
+            crc_errors: 0,
+        })
+    }
+
+    /// Set the expected sync word. Default 0x12.
+    ///
+    /// Frames with other sync words are still decoded, but logged.
+    pub fn set_sync_word(&mut self, sw: u8) {
+        self.sync_word = sw;
+    }
+
+    /// Enable low data rate optimization. It's mandatory for SF11 and
+    /// SF12 at 125kHz bandwidth.
+    pub fn set_ldro(&mut self, ldro: bool) {
+        self.decoder = LoraSymbolDecoder::new(self.sf, ldro);
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<LoraFrame> {
+        self.dst.clone()
+    }
+
+    // Dechirp N samples at `pos` with `chirp`, after removing `cfo`
+    // bins of offset. Returns the peak bin, its magnitude, and the
+    // peak to average ratio.
+    fn peak(&self, pos: usize, chirp: &[Complex], cfo: i32) -> (usize, Float, Float) {
+        let n = self.n;
+        let w = -2.0 * std::f64::consts::PI * cfo as f64 / n as f64;
+        let start = self.base + pos as u64;
+        let mut v: Vec<Complex> = self.buf[pos..pos + n]
+            .iter()
+            .zip(chirp.iter())
+            .enumerate()
+            .map(|(i, (s, c))| {
+                let rot = if cfo == 0 {
+                    Complex::new(1.0, 0.0)
+                } else {
+                    // Wrap phase in f64 to stay accurate for long streams.
+                    let ph = (w * ((start + i as u64) % n as u64) as f64) as Float;
+                    Complex::from_polar(1.0, ph)
+                };
+                s * c * rot
+            })
+            .collect();
+        self.fft.process(&mut v);
+        let (bin, mag) = v
+            .iter()
+            .map(|x| x.norm_sqr())
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap();
+        let avg = v.iter().map(|x| x.norm_sqr()).sum::<Float>() / n as Float;
+        (bin, mag, mag / avg.max(1e-30))
+    }
+
+    // Run the state machine as far as the buffer allows. Returns false
+    // when more samples are needed.
+    fn step(&mut self) -> bool {
+        let n = self.n;
+        match self.state {
+            State::Detect { count, bin, pos } => {
+                if self.buf.len() < pos + n {
+                    return false;
+                }
+                let (b, _, ratio) = self.peak(pos, &self.downchirp, 0);
+                let close = (b + n - bin) % n <= 1 || (bin + n - b) % n <= 1;
+                if ratio >= MIN_PEAK_RATIO && count > 0 && close {
+                    if count + 1 >= MIN_PREAMBLE {
+                        trace!("LoRa: preamble at bin {b}");
+                        self.state = State::Sync {
+                            up: b,
+                            pos: pos + n,
+                            best: None,
+                            windows: 0,
+                        };
+                    } else {
+                        self.state = State::Detect {
+                            count: count + 1,
+                            bin: b,
+                            pos: pos + n,
+                        };
+                    }
+                } else if ratio >= MIN_PEAK_RATIO {
+                    // Start a new run at this window, forgetting the past.
+                    self.drain(pos);
+                    self.state = State::Detect {
+                        count: 1,
+                        bin: b,
+                        pos: n,
+                    };
+                } else {
+                    self.reset(pos + n);
+                }
+                true
+            }
+            State::Sync {
+                up,
+                pos,
+                best,
+                windows,
+            } => {
+                if self.buf.len() < pos + n {
+                    return false;
+                }
+                let (_, umag, _) = self.peak(pos, &self.downchirp, 0);
+                let (db, dmag, _) = self.peak(pos, &self.upchirp, 0);
+                let down = dmag > umag;
+                let best = match best {
+                    Some((m, _)) if m >= dmag => best,
+                    _ if down => Some((dmag, db)),
+                    _ => best,
+                };
+                let done = (best.is_some() && !down) || windows > 16;
+                if !done {
+                    self.state = State::Sync {
+                        up,
+                        pos: pos + n,
+                        best,
+                        windows: windows + 1,
+                    };
+                    return true;
+                }
+                let Some((_, down_bin)) = best else {
+                    debug!("LoRa: no downchirps after preamble");
+                    self.reset(pos);
+                    return true;
+                };
+                // up = cfo - delay, down = cfo + delay.
+                let half = (up + down_bin) % n / 2;
+                let cfo = [half as i32, (half + n / 2) as i32]
+                    .iter()
+                    .map(|c| if *c >= n as i32 / 2 { c - n as i32 } else { *c })
+                    .min_by_key(|c| c.abs())
+                    .unwrap();
+                let delay = (down_bin as i32 - cfo).rem_euclid(n as i32) as usize;
+                trace!("LoRa: up {up} down {down_bin}: cfo {cfo} delay {delay}");
+                match self.find_payload(delay, cfo, pos + n) {
+                    Some(start) => self.state = State::Frame { cfo, pos: start },
+                    None => self.reset(pos),
+                }
+                true
+            }
+            State::Frame { cfo, pos } => {
+                if self.buf.len() < pos + 8 * n {
+                    return false;
+                }
+                let bins: Vec<usize> = (0..8)
+                    .map(|i| self.peak(pos + i * n, &self.downchirp, cfo).0)
+                    .collect();
+                let Some((h, first)) = self.decoder.header(&bins) else {
+                    self.reset(pos);
+                    return true;
+                };
+                let nsyms = self.decoder.payload_symbols(&h);
+                let end = pos + (8 + nsyms) * n;
+                if self.buf.len() < end {
+                    return false;
+                }
+                let bins: Vec<usize> = (0..nsyms)
+                    .map(|i| self.peak(pos + (8 + i) * n, &self.downchirp, cfo).0)
+                    .collect();
+                let mut frame = self.decoder.frame(&h, &first, &bins);
+                frame.cfo_bins = cfo;
+                debug!(
+                    "LoRa: frame of {} bytes, CR 4/{}, CRC {}",
+                    frame.payload.len(),
+                    4 + frame.cr,
+                    if !frame.has_crc {
+                        "none"
+                    } else if frame.crc_ok {
+                        "ok"
+                    } else {
+                        "bad"
+                    }
+                );
+                if frame.has_crc && !frame.crc_ok {
+                    self.crc_errors += 1;
+                }
+                self.frames += 1;
+                self.dst.push(frame, &[]);
+                self.reset(end);
+                true
+            }
+        }
+    }
+
+    // Walk symbol aligned windows from the start of the buffer, to find
+    // the downchirps. Returns start of payload.
+    fn find_payload(&self, delay: usize, cfo: i32, limit: usize) -> Option<usize> {
+        let n = self.n;
+        let mut syms = Vec::new();
+        let mut pos = delay;
+        while pos + n <= limit.min(self.buf.len()) {
+            let (b, umag, _) = self.peak(pos, &self.downchirp, cfo);
+            let (_, dmag, _) = self.peak(pos, &self.upchirp, cfo);
+            if dmag > umag {
+                if syms.len() >= 2 {
+                    let sw = ((syms[syms.len() - 2] / 8) << 4) | (syms[syms.len() - 1] / 8);
+                    let sw = sw as u8;
+                    if sw != self.sync_word {
+                        debug!("LoRa: sync word {sw:02x}, expected {:02x}", self.sync_word);
+                    }
+                }
+                return Some(pos + 2 * n + n / 4);
+            }
+            // Round to the nearest multiple of 8 for the sync word.
+            syms.push(((b + 4) % n) / 8 * 8);
+            pos += n;
+        }
+        None
+    }
+
+    fn drain(&mut self, n: usize) {
+        let n = n.min(self.buf.len());
+        self.buf.drain(..n);
+        self.base += n as u64;
+    }
+
+    fn reset(&mut self, pos: usize) {
+        self.drain(pos);
+        self.state = State::Detect {
+            count: 0,
+            bin: 0,
+            pos: 0,
+        };
+    }
+}
+
+/// One sample of the base upchirp, for N chips.
+fn chirp(n: usize, i: usize) -> Complex {
+    let (nf, i) = (n as f64, i as f64);
+    let ph = std::f64::consts::PI * (i * i / nf - i);
+    Complex::from_polar(1.0, ph as Float)
+}
+
+impl Drop for LoraDecode {
+    fn drop(&mut self) {
+        info!(
+            "LoraDecode: {} frames, {} CRC errors",
+            self.frames, self.crc_errors
+        );
+    }
+}
+
+impl Block for LoraDecode {
+    fn block_name(&self) -> &str {
+        "LoraDecode"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        self.buf.extend(input.iter());
+        let n = input.len();
+        input.consume(n);
+        while self.step() {}
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::{new_streamp, streamp_from_slice};
+
+    // Encode a frame into symbol values (before modulation), the way a
+    // LoRa transmitter does.
+    fn encode(sf: usize, cr: u8, payload: &[u8], ldro: bool) -> Vec<usize> {
+        let n = 1 << sf;
+        let white = whitening_sequence(payload.len());
+        let mut bytes: Vec<u8> = payload
+            .iter()
+            .zip(white.iter())
+            .map(|(b, w)| b ^ w)
+            .collect();
+        bytes.extend(payload_crc(payload).to_le_bytes());
+        let mut nibbles: Vec<u8> = bytes.iter().flat_map(|b| [b & 0xf, b >> 4]).collect();
+
+        let len = payload.len() as u8;
+        let h = [len >> 4, len & 0xf, (cr << 1) | 1];
+        let chk = header_checksum(h);
+        let mut first = vec![h[0], h[1], h[2], chk >> 4, chk & 0xf];
+        let take = (sf - 2 - 5).min(nibbles.len());
+        first.extend(nibbles.drain(..take));
+        first.resize(sf - 2, 0);
+
+        let mut values = Vec::new();
+        let mut emit = |nibs: &[u8], cr: u8, reduced: bool| {
+            let cws: Vec<u8> = nibs.iter().map(|x| hamming_encode(*x, cr)).collect();
+            for mut v in interleave(&cws, 4 + cr as usize) {
+                if reduced {
+                    v <<= 2;
+                }
+                // Inverse gray over all SF bits.
+                let mut y = v as usize;
+                for j in 1..sf {
+                    y ^= (v as usize) >> j;
+                }
+                values.push((y + 1) % n);
+            }
+        };
+        emit(&first, 4, true);
+        let sf_app = if ldro { sf - 2 } else { sf };
+        for chunk in nibbles.chunks(sf_app) {
+            let mut c = chunk.to_vec();
+            c.resize(sf_app, 0);
+            emit(&c, cr, ldro);
+        }
+        values
+    }
+
+    fn modulate(sf: usize, values: &[usize], delay: usize, cfo: Float) -> Vec<Complex> {
+        let n = 1 << sf;
+        let mut out = vec![Complex::default(); delay];
+        let sym = |v: usize| (0..n).map(move |i| chirp(n, (i + v) % n));
+        for _ in 0..8 {
+            out.extend(sym(0));
+        }
+        out.extend(sym(8));
+        out.extend(sym(16));
+        for _ in 0..2 {
+            out.extend(sym(0).map(|c| c.conj()));
+        }
+        out.extend(sym(0).take(n / 4).map(|c| c.conj()));
+        for v in values {
+            out.extend(sym(*v));
+        }
+        out.extend(vec![Complex::default(); 3 * n]);
+        // Frequency offset and some noise.
+        let mut x: u32 = 7;
+        out.iter()
+            .enumerate()
+            .map(|(i, s)| {
+                let mut r = || {
+                    x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                    ((x >> 16) % 1000) as Float / 1000.0 - 0.5
+                };
+                let noise = Complex::new(r(), r()) * 0.5;
+                s * Complex::from_polar(
+                    1.0,
+                    2.0 * std::f64::consts::PI as Float * cfo * i as Float / n as Float,
+                ) + noise
+            })
+            .collect()
+    }
+
+    fn decode(sf: u8, signal: &[Complex], ldro: bool) -> Result<Vec<LoraFrame>> {
+        // Doesn't fit in a stream, so feed it directly.
+        let mut dec = LoraDecode::new(new_streamp(), sf)?;
+        dec.set_ldro(ldro);
+        dec.buf.extend(signal);
+        while dec.step() {}
+        let out = dec.out();
+        let mut ret = Vec::new();
+        while let Some((f, _)) = out.pop() {
+            ret.push(f);
+        }
+        Ok(ret)
+    }
+
+    #[test]
+    fn whitening() {
+        assert_eq!(
+            whitening_sequence(9),
+            vec![0xff, 0xfe, 0xfc, 0xf8, 0xf0, 0xe1, 0xc2, 0x85, 0x0b]
+        );
+    }
+
+    #[test]
+    fn hamming() {
+        for cr in 1..=4 {
+            for nib in 0..16 {
+                let cw = hamming_encode(nib, cr);
+                assert_eq!(hamming_decode(cw, cr), nib);
+                if cr >= 3 {
+                    for bit in 0..(4 + cr) {
+                        assert_eq!(hamming_decode(cw ^ (1 << bit), cr), nib);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn interleaver() {
+        let cws = vec![0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde];
+        assert_eq!(deinterleave(&interleave(&cws, 8), 7), cws);
+    }
+
+    #[test]
+    fn sf7() -> Result<()> {
+        let payload = b"Hello LoRa world";
+        let values = encode(7, 1, payload, false);
+        let signal = modulate(7, &values, 300, 5.0);
+        let got = decode(7, &signal, false)?;
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].payload, payload);
+        assert_eq!(got[0].cr, 1);
+        assert!(got[0].crc_ok);
+        assert_eq!(got[0].cfo_bins, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn sf9_cr48_negative_cfo() -> Result<()> {
+        let payload: Vec<u8> = (0..40).collect();
+        let values = encode(9, 4, &payload, false);
+        let signal = modulate(9, &values, 1234, -11.0);
+        let got = decode(9, &signal, false)?;
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].payload, payload);
+        assert!(got[0].crc_ok);
+        Ok(())
+    }
+
+    #[test]
+    fn sf11_ldro() -> Result<()> {
+        let payload = b"ldro";
+        let values = encode(11, 2, payload, true);
+        let signal = modulate(11, &values, 100, 3.0);
+        let got = decode(11, &signal, true)?;
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].payload, payload);
+        assert!(got[0].crc_ok);
+        Ok(())
+    }
+
+    #[test]
+    fn bad_sf() {
+        assert!(LoraDecode::new(streamp_from_slice(&[Complex::default()]), 13).is_err());
+    }
+}
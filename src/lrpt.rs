@@ -0,0 +1,1100 @@
+/*! Meteor-M LRPT receiver.
+
+[LRPT][lrpt] is the low rate picture transmission from the Meteor-M
+weather satellites, on 137MHz. The chain is:
+
+```text
+  [ IQ at 2+ samples per symbol ] → [ LrptDemod ] → (soft bits)
+    → [ LrptDeinterleave ] (80k interleaved mode only)
+    → [ LrptDecode ] → (VCDUs)
+    → [ CcsdsPacketizer ] → (space packets)
+    → [ LrptImageWriter ] → (PNG per channel)
+```
+
+* [LrptDemod] is a QPSK demodulator: AGC, root raised cosine matched
+  filter, Costas loop, and Gardner timing recovery. It outputs two
+  soft bits per symbol.
+* [LrptDeinterleave] strips sync markers and undoes the convolutional
+  interleaving used by the 80k symbol interleaved mode.
+* [LrptDecode] finds the convolutionally encoded sync marker
+  (resolving the QPSK phase ambiguity), Viterbi decodes, derandomizes,
+  and Reed-Solomon corrects the 4 interleaved codewords of each CADU.
+* [LrptImageWriter] decodes the JPEG-like MSU-MR image packets, and
+  writes one greyscale PNG per channel (APID 64-69).
+
+See `examples/lrpt-rx.rs`.
+
+[lrpt]: https://www.sigidwiki.com/wiki/Low_Rate_Picture_Transmission_(LRPT)
+*/
+use std::collections::BTreeMap;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use log::{debug, info, trace, warn};
+
+use crate::block::{Block, BlockRet};
+use crate::ccsds::{self, Packet};
+use crate::reed_solomon::ReedSolomon;
+use crate::stream::{new_nocopy_streamp, new_streamp, NoCopyStreamp, Streamp};
+use crate::viterbi;
+use crate::{Complex, Error, Float};
+
+/// Symbol rate of the classic Meteor-M LRPT mode.
+pub const SYMBOL_RATE: Float = 72000.0;
+
+/// Symbol rate of the Meteor-M N2-2 and later 80k mode.
+pub const SYMBOL_RATE_80K: Float = 80000.0;
+
+/// Virtual channel carrying MSU-MR imagery.
+pub const IMAGE_VCID: u8 = 5;
+
+/// Length of a CADU, including ASM, in bytes.
+pub const CADU_LEN: usize = 1024;
+
+/// Length of a VCDU, after Reed-Solomon parity is removed.
+pub const VCDU_LEN: usize = 892;
+
+const RS_INTERLEAVE: usize = 4;
+
+// Coded soft bits per CADU.
+const CADU_SOFT: usize = CADU_LEN * 8 * 2;
+
+// Extra decoded bits before and after each frame, to let the Viterbi
+// decoder settle.
+const VITERBI_MARGIN: usize = 64;
+
+// Max hard bit errors in the 52 bit encoded sync marker.
+const SYNC_MAX_ERRORS: usize = 5;
+
+// Frames with bad sync before going back to searching.
+const MAX_SYNC_MISSES: usize = 3;
+
+/// Root raised cosine taps, `span` symbols long.
+fn rrc_taps(sps: Float, alpha: Float, span: usize) -> Vec<Float> {
+    crate::fir::root_raised_cosine(sps, 1.0, alpha, (span as Float * sps) as usize | 1)
+}
+
+/** QPSK demodulator for LRPT.
+
+Input is IQ centered on the signal, at at least 2 samples per symbol.
+Output is soft bits, I and Q interleaved, with positive meaning 1.
+*/
+pub struct LrptDemod {
+    src: Streamp<Complex>,
+    dst: Streamp<Float>,
+    sps: Float,
+    taps: Vec<Complex>,
+    hist: Vec<Complex>,
+    agc: Float,
+
+    // Costas loop.
+    phase: Float,
+    freq: Float,
+    alpha: Float,
+    beta: Float,
+
+    // Gardner timing. `t` counts down to the next half symbol strobe.
+    t: Float,
+    half: Float,
+    on_time: bool,
+    prev: Complex,
+    mid: Complex,
+    last_sym: Complex,
+    symbols: usize,
+}
+
+impl LrptDemod {
+    /// Create new demodulator.
+    pub fn new(src: Streamp<Complex>, samp_rate: Float, symbol_rate: Float) -> Result<Self, Error> {
+        let sps = samp_rate / symbol_rate;
+        if sps < 2.0 {
+            return Err(Error::BadParameter(format!(
+                "LrptDemod needs at least 2 samples per symbol, got {sps}"
+            )));
+        }
+        let taps = rrc_taps(sps, 0.6, 16)
+            .iter()
+            .map(|t| Complex::new(*t, 0.0))
+            .collect::<Vec<_>>();
+        let ntaps = taps.len();
+        let bw = 0.005;
+        let damping = 0.707;
+        let denom = 1.0 + 2.0 * damping * bw + bw * bw;
+        Ok(Self {
+            src,
+            dst: new_streamp(),
+            sps,
+            taps,
+            hist: vec![Complex::default(); ntaps],
+            agc: 1.0,
+            phase: 0.0,
+            freq: 0.0,
+            alpha: 4.0 * damping * bw / denom,
+            beta: 4.0 * bw * bw / denom,
+            t: sps / 2.0,
+            half: sps / 2.0,
+            on_time: true,
+            prev: Complex::default(),
+            mid: Complex::default(),
+            last_sym: Complex::default(),
+            symbols: 0,
+        })
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Float> {
+        self.dst.clone()
+    }
+
+    /// Current carrier offset estimate, in radians per sample.
+    pub fn frequency(&self) -> Float {
+        self.freq
+    }
+
+    fn process_one(&mut self, s: Complex, out: &mut Vec<Float>) {
+        // AGC.
+        self.agc += 0.0001 * (s.norm() - self.agc);
+        let s = s / self.agc.max(1e-9);
+
+        // Matched filter.
+        self.hist.rotate_left(1);
+        *self.hist.last_mut().unwrap() = s;
+        let x: Complex = self
+            .hist
+            .iter()
+            .zip(self.taps.iter())
+            .map(|(a, b)| a * b)
+            .sum();
+
+        // Carrier.
+        let x = x * Complex::from_polar(1.0, -self.phase);
+        self.phase += self.freq;
+        self.phase %= 2.0 * std::f64::consts::PI as Float;
+
+        // Timing. Strobe when the countdown passes this sample,
+        // interpolating linearly between this and the previous sample.
+        self.t -= 1.0;
+        while self.t < 0.0 {
+            let frac = 1.0 + self.t;
+            let y = self.prev + (x - self.prev) * frac;
+            if self.on_time {
+                let err = (y.re - self.last_sym.re) * self.mid.re
+                    + (y.im - self.last_sym.im) * self.mid.im;
+                let err = err.clamp(-1.0, 1.0);
+                self.half -= 0.0002 * err;
+                self.half = self.half.clamp(self.sps * 0.45, self.sps * 0.55);
+                self.t -= 0.05 * err;
+                self.last_sym = y;
+                self.symbols += 1;
+
+                let cerr = y.re.signum() * y.im - y.im.signum() * y.re;
+                let cerr = cerr.clamp(-1.0, 1.0);
+                self.freq += self.beta * cerr;
+                self.freq = self.freq.clamp(-0.3, 0.3);
+                self.phase += self.alpha * cerr;
+                out.push(y.re);
+                out.push(y.im);
+            } else {
+                self.mid = y;
+            }
+            self.on_time = !self.on_time;
+            self.t += self.half;
+        }
+        self.prev = x;
+    }
+}
+
+impl Drop for LrptDemod {
+    fn drop(&mut self) {
+        info!("LrptDemod: {} symbols", self.symbols);
+    }
+}
+
+impl Block for LrptDemod {
+    fn block_name(&self) -> &str {
+        "LrptDemod"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let obind = self.dst.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let mut o = obind.write_buf()?;
+        let n = std::cmp::min(input.len(), o.len() / 2);
+        let mut out = Vec::with_capacity(n);
+        for s in input.iter().take(n) {
+            self.process_one(*s, &mut out);
+        }
+        o.fill_from_slice(&out);
+        o.produce(out.len(), &[]);
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+/// Apply one of the 8 QPSK phase/mirror ambiguities to a soft bit pair.
+fn rotate(a: Float, b: Float, variant: usize) -> (Float, Float) {
+    let (mut a, mut b) = if variant & 4 != 0 { (b, a) } else { (a, b) };
+    for _ in 0..(variant & 3) {
+        (a, b) = (b, -a);
+    }
+    (a, b)
+}
+
+fn rotate_all(soft: &mut [Float], variant: usize) {
+    for pair in soft.chunks_exact_mut(2) {
+        (pair[0], pair[1]) = rotate(pair[0], pair[1], variant);
+    }
+}
+
+// Interleaver parameters for the 80k mode.
+const INTER_MARKER: u8 = 0x27;
+const INTER_STRIDE: usize = 80;
+const INTER_BRANCHES: usize = 36;
+const INTER_DELAY: usize = 2048;
+
+/** Deinterleaver for the Meteor-M 80k interleaved mode.
+
+Every 80 soft bits start with the 8 bit marker 0x27. This block finds
+the markers (in any of the QPSK phase ambiguities), strips them, and
+runs the remaining 72 bits per stride through a 36 branch
+convolutional deinterleaver.
+
+The output is delayed by the deinterleaver depth, about 2.6M soft bits.
+*/
+pub struct LrptDeinterleave {
+    src: Streamp<Float>,
+    dst: Streamp<Float>,
+    buf: Vec<Float>,
+    lock: Option<usize>,
+    misses: usize,
+    delay_line: Vec<Float>,
+    pos: usize,
+    branch: usize,
+}
+
+impl LrptDeinterleave {
+    /// Create new deinterleaver.
+    pub fn new(src: Streamp<Float>) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            buf: Vec::new(),
+            lock: None,
+            misses: 0,
+            delay_line: vec![0.0; INTER_BRANCHES * INTER_BRANCHES * INTER_DELAY],
+            pos: 0,
+            branch: 0,
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Float> {
+        self.dst.clone()
+    }
+
+    fn marker_errors(soft: &[Float]) -> usize {
+        (0..8)
+            .filter(|n| ((INTER_MARKER >> (7 - n)) & 1 == 1) != (soft[*n] > 0.0))
+            .count()
+    }
+
+    // Find marker alignment and phase, using several strides.
+    fn search(&self) -> Option<(usize, usize)> {
+        const STRIDES: usize = 8;
+        let mut tmp = vec![0.0; INTER_STRIDE * STRIDES + INTER_STRIDE];
+        for variant in 0..8 {
+            let len = tmp.len();
+            tmp.copy_from_slice(&self.buf[..len]);
+            rotate_all(&mut tmp, variant);
+            for ofs in (0..INTER_STRIDE).step_by(2) {
+                let errs: usize = (0..STRIDES)
+                    .map(|s| Self::marker_errors(&tmp[ofs + s * INTER_STRIDE..]))
+                    .sum();
+                if errs <= STRIDES {
+                    return Some((ofs, variant));
+                }
+            }
+        }
+        None
+    }
+
+    fn deinterleave(&mut self, s: Float) -> Float {
+        // Branch b is delayed (BRANCHES-1-b)*DELAY commutator cycles.
+        let size = self.delay_line.len();
+        let delay = (INTER_BRANCHES - 1 - self.branch) * INTER_DELAY * INTER_BRANCHES;
+        self.delay_line[(self.pos + delay) % size] = s;
+        let ret = self.delay_line[self.pos];
+        self.pos = (self.pos + 1) % size;
+        self.branch = (self.branch + 1) % INTER_BRANCHES;
+        ret
+    }
+}
+
+impl Block for LrptDeinterleave {
+    fn block_name(&self) -> &str {
+        "LrptDeinterleave"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let obind = self.dst.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        let mut o = obind.write_buf()?;
+        let room = o.len() / (INTER_STRIDE - 8) * INTER_STRIDE;
+        let n = std::cmp::min(input.len(), room.saturating_sub(self.buf.len()));
+        self.buf.extend(input.iter().take(n));
+        input.consume(n);
+
+        let mut out = Vec::new();
+        loop {
+            let variant = match self.lock {
+                Some(v) => v,
+                None => {
+                    if self.buf.len() < INTER_STRIDE * 9 {
+                        break;
+                    }
+                    match self.search() {
+                        Some((ofs, variant)) => {
+                            debug!("LrptDeinterleave: locked at offset {ofs} variant {variant}");
+                            self.buf.drain(..ofs);
+                            self.lock = Some(variant);
+                            self.misses = 0;
+                            variant
+                        }
+                        None => {
+                            self.buf.drain(..INTER_STRIDE);
+                            continue;
+                        }
+                    }
+                }
+            };
+            if self.buf.len() < INTER_STRIDE {
+                break;
+            }
+            let mut stride: Vec<Float> = self.buf.drain(..INTER_STRIDE).collect();
+            rotate_all(&mut stride, variant);
+            if Self::marker_errors(&stride) > 2 {
+                self.misses += 1;
+                if self.misses > 8 {
+                    debug!("LrptDeinterleave: lost lock");
+                    self.lock = None;
+                }
+            } else {
+                self.misses = 0;
+            }
+            for s in &stride[8..] {
+                let d = self.deinterleave(*s);
+                out.push(d);
+            }
+        }
+        if out.is_empty() {
+            return Ok(if n == 0 { BlockRet::Noop } else { BlockRet::Ok });
+        }
+        o.fill_from_slice(&out);
+        o.produce(out.len(), &[]);
+        Ok(BlockRet::Ok)
+    }
+}
+
+/** LRPT frame decoder.
+
+Takes soft bits, and outputs error corrected VCDUs (892 bytes each).
+*/
+pub struct LrptDecode {
+    src: Streamp<Float>,
+    dst: NoCopyStreamp<Vec<u8>>,
+    buf: Vec<Float>,
+    history: Vec<Float>,
+    sync: Vec<u8>,
+    lock: Option<usize>,
+    misses: usize,
+    viterbi: viterbi::Decoder,
+    rs: ReedSolomon,
+    pn: Vec<u8>,
+    frames: usize,
+    rs_failed: usize,
+    corrected: usize,
+}
+
+impl LrptDecode {
+    /// Create new decoder.
+    pub fn new(src: Streamp<Float>) -> Self {
+        // The encoded ASM, minus the first bits that depend on the
+        // previous frame.
+        let asm_bits: Vec<u8> = (0..32)
+            .map(|n| ((ccsds::ASM >> (31 - n)) & 1) as u8)
+            .collect();
+        let sync = viterbi::Encoder::new().encode(&asm_bits)[2 * (viterbi::K - 1)..].to_vec();
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            buf: Vec::new(),
+            history: Vec::new(),
+            sync,
+            lock: None,
+            misses: 0,
+            viterbi: viterbi::Decoder::new(),
+            rs: ReedSolomon::ccsds(),
+            pn: ccsds::pn_sequence(CADU_LEN - 4),
+            frames: 0,
+            rs_failed: 0,
+            corrected: 0,
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<Vec<u8>> {
+        self.dst.clone()
+    }
+
+    fn sync_errors(&self, soft: &[Float], variant: usize) -> usize {
+        let ofs = 2 * (viterbi::K - 1);
+        self.sync
+            .chunks_exact(2)
+            .enumerate()
+            .map(|(n, bits)| {
+                let (a, b) = rotate(soft[ofs + 2 * n], soft[ofs + 2 * n + 1], variant);
+                ((bits[0] == 1) != (a > 0.0)) as usize + ((bits[1] == 1) != (b > 0.0)) as usize
+            })
+            .sum()
+    }
+
+    fn search(&self, limit: usize) -> Option<(usize, usize)> {
+        for pos in (0..limit).step_by(2) {
+            for variant in 0..8 {
+                if self.sync_errors(&self.buf[pos..], variant) <= SYNC_MAX_ERRORS {
+                    return Some((pos, variant));
+                }
+            }
+        }
+        None
+    }
+
+    /// Decode one CADU starting at the beginning of `buf`.
+    fn decode_frame(&mut self, variant: usize) -> Option<Vec<u8>> {
+        let margin = 2 * VITERBI_MARGIN;
+        let mut soft = self.history.clone();
+        let head = soft.len() / 2;
+        soft.extend(&self.buf[..CADU_SOFT + margin]);
+        rotate_all(&mut soft[self.history.len()..], variant);
+        let bits = self.viterbi.decode(&soft);
+        let bytes: Vec<u8> = bits[head..head + CADU_LEN * 8]
+            .chunks_exact(8)
+            .map(|b| b.iter().fold(0, |acc, bit| (acc << 1) | bit))
+            .collect();
+        self.history = soft[soft.len() - margin - margin..soft.len() - margin].to_vec();
+        trace!("LrptDecode: ASM {:02x?}", &bytes[..4]);
+
+        let mut data = bytes[4..].to_vec();
+        ccsds::derandomize(&mut data, &self.pn);
+        let mut vcdu = vec![0u8; VCDU_LEN];
+        for i in 0..RS_INTERLEAVE {
+            let mut cw: Vec<u8> = data
+                .iter()
+                .skip(i)
+                .step_by(RS_INTERLEAVE)
+                .cloned()
+                .collect();
+            match self.rs.decode_ccsds(&mut cw) {
+                Some(n) => self.corrected += n,
+                None => {
+                    self.rs_failed += 1;
+                    return None;
+                }
+            }
+            for (j, b) in cw.iter().take(VCDU_LEN / RS_INTERLEAVE).enumerate() {
+                vcdu[j * RS_INTERLEAVE + i] = *b;
+            }
+        }
+        Some(vcdu)
+    }
+}
+
+impl Drop for LrptDecode {
+    fn drop(&mut self) {
+        info!(
+            "LrptDecode: {} frames decoded, {} RS failures, {} bytes corrected",
+            self.frames, self.rs_failed, self.corrected
+        );
+    }
+}
+
+impl Block for LrptDecode {
+    fn block_name(&self) -> &str {
+        "LrptDecode"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        self.buf.extend(input.iter());
+        let n = input.len();
+        input.consume(n);
+
+        let need = CADU_SOFT + 2 * VITERBI_MARGIN;
+        loop {
+            let variant = match self.lock {
+                Some(v) => v,
+                None => {
+                    if self.buf.len() < need + CADU_SOFT {
+                        break;
+                    }
+                    match self.search(CADU_SOFT) {
+                        Some((pos, variant)) => {
+                            debug!("LrptDecode: found sync, variant {variant}");
+                            self.buf.drain(..pos);
+                            self.history.clear();
+                            self.lock = Some(variant);
+                            self.misses = 0;
+                            variant
+                        }
+                        None => {
+                            self.buf.drain(..CADU_SOFT);
+                            continue;
+                        }
+                    }
+                }
+            };
+            if self.buf.len() < need {
+                break;
+            }
+            if self.sync_errors(&self.buf, variant) > SYNC_MAX_ERRORS * 2 {
+                self.misses += 1;
+                if self.misses >= MAX_SYNC_MISSES {
+                    debug!("LrptDecode: lost sync");
+                    self.lock = None;
+                    continue;
+                }
+            } else {
+                self.misses = 0;
+            }
+            if let Some(vcdu) = self.decode_frame(variant) {
+                self.frames += 1;
+                self.dst.push(vcdu, &[]);
+            }
+            self.buf.drain(..CADU_SOFT);
+        }
+        Ok(BlockRet::Ok)
+    }
+}
+
+/// Width of MSU-MR images in pixels.
+pub const IMAGE_WIDTH: usize = 1568;
+
+// MCUs (8x8 blocks) per image packet.
+const MCUS_PER_PACKET: usize = 14;
+
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+const STD_QUANT: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113,
+    92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+const DC_COUNTS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const AC_COUNTS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+const AC_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+/// Canonical Huffman table, as (code length, code, value).
+struct Huffman {
+    codes: Vec<(u8, u16, u8)>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8; 16], values: &[u8]) -> Self {
+        let mut codes = Vec::with_capacity(values.len());
+        let mut code: u16 = 0;
+        let mut vals = values.iter();
+        for (len, count) in counts.iter().enumerate() {
+            for _ in 0..*count {
+                codes.push((len as u8 + 1, code, *vals.next().unwrap()));
+                code += 1;
+            }
+            code <<= 1;
+        }
+        Self { codes }
+    }
+
+    fn decode(&self, r: &mut BitReader) -> Option<u8> {
+        let mut code: u16 = 0;
+        let mut len = 0;
+        let mut i = 0;
+        while len < 16 {
+            code = (code << 1) | r.bit()? as u16;
+            len += 1;
+            while i < self.codes.len() && self.codes[i].0 == len {
+                if self.codes[i].1 == code {
+                    return Some(self.codes[i].2);
+                }
+                i += 1;
+            }
+        }
+        None
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Option<u8> {
+        let b = self.data.get(self.pos / 8)?;
+        let ret = (b >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(ret)
+    }
+
+    // Read a JPEG style signed value of `len` bits.
+    fn value(&mut self, len: u8) -> Option<i32> {
+        if len == 0 {
+            return Some(0);
+        }
+        let mut v: i32 = 0;
+        for _ in 0..len {
+            v = (v << 1) | self.bit()? as i32;
+        }
+        if v < 1 << (len - 1) {
+            v -= (1 << len) - 1;
+        }
+        Some(v)
+    }
+}
+
+/// Decoder for MSU-MR image packets.
+pub struct MsumrDecoder {
+    dc: Huffman,
+    ac: Huffman,
+    cos: [[Float; 8]; 8],
+}
+
+impl Default for MsumrDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MsumrDecoder {
+    /// Create new decoder.
+    pub fn new() -> Self {
+        let mut cos = [[0.0; 8]; 8];
+        for (x, row) in cos.iter_mut().enumerate() {
+            for (u, c) in row.iter_mut().enumerate() {
+                let cu = if u == 0 { 1.0 / Float::sqrt(2.0) } else { 1.0 };
+                *c = cu
+                    * ((2 * x + 1) as Float * u as Float * std::f64::consts::PI as Float / 16.0)
+                        .cos();
+            }
+        }
+        Self {
+            dc: Huffman::new(&DC_COUNTS, &DC_VALUES),
+            ac: Huffman::new(&AC_COUNTS, &AC_VALUES),
+            cos,
+        }
+    }
+
+    fn quant_table(quality: u8) -> [Float; 64] {
+        let q = quality as Float;
+        let f = if q > 20.0 && q < 50.0 {
+            5000.0 / q
+        } else {
+            200.0 - 2.0 * q
+        };
+        let mut ret = [0.0; 64];
+        for (r, s) in ret.iter_mut().zip(STD_QUANT.iter()) {
+            *r = (f / 100.0 * *s as Float).round().max(1.0);
+        }
+        ret
+    }
+
+    fn idct(&self, coef: &[Float; 64]) -> [u8; 64] {
+        let mut ret = [0u8; 64];
+        for y in 0..8 {
+            for x in 0..8 {
+                let mut sum = 0.0;
+                for v in 0..8 {
+                    for u in 0..8 {
+                        sum += coef[v * 8 + u] * self.cos[x][u] * self.cos[y][v];
+                    }
+                }
+                ret[y * 8 + x] = (sum / 4.0 + 128.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        ret
+    }
+
+    /// Decode the payload of an image packet (after the primary header).
+    ///
+    /// Returns the MCU ID of the first block, and the decoded blocks.
+    /// Decoding stops at the first error, so fewer than 14 blocks may be
+    /// returned.
+    pub fn decode(&self, data: &[u8]) -> Option<(usize, Vec<[u8; 64]>)> {
+        // 8 bytes timestamp, MCU ID, 2 bytes scan header, 2 bytes
+        // segment header, quality.
+        if data.len() < 14 {
+            return None;
+        }
+        let mcu_id = data[8] as usize;
+        let quant = Self::quant_table(data[13]);
+        let mut r = BitReader {
+            data: &data[14..],
+            pos: 0,
+        };
+        let mut blocks = Vec::with_capacity(MCUS_PER_PACKET);
+        let mut prev_dc = 0;
+        'blocks: for _ in 0..MCUS_PER_PACKET {
+            let mut zz = [0i32; 64];
+            let Some(cat) = self.dc.decode(&mut r) else {
+                break;
+            };
+            let Some(diff) = r.value(cat) else {
+                break;
+            };
+            prev_dc += diff;
+            zz[0] = prev_dc;
+            let mut k = 1;
+            while k < 64 {
+                let Some(rs) = self.ac.decode(&mut r) else {
+                    break 'blocks;
+                };
+                let (run, size) = ((rs >> 4) as usize, rs & 0xf);
+                if size == 0 {
+                    if run == 15 {
+                        k += 16;
+                        continue;
+                    }
+                    break;
+                }
+                k += run;
+                if k > 63 {
+                    break 'blocks;
+                }
+                let Some(v) = r.value(size) else {
+                    break 'blocks;
+                };
+                zz[k] = v;
+                k += 1;
+            }
+            let mut coef = [0.0; 64];
+            for (n, z) in zz.iter().enumerate() {
+                coef[ZIGZAG[n]] = *z as Float * quant[ZIGZAG[n]];
+            }
+            blocks.push(self.idct(&coef));
+        }
+        Some((mcu_id, blocks))
+    }
+}
+
+/// One MSU-MR channel image.
+#[derive(Default)]
+pub struct MsumrImage {
+    /// Greyscale pixels, `IMAGE_WIDTH` wide.
+    pub pixels: Vec<u8>,
+
+    last_mcu: Option<usize>,
+    row: usize,
+}
+
+impl MsumrImage {
+    /// Image height in pixels.
+    pub fn height(&self) -> usize {
+        self.pixels.len() / IMAGE_WIDTH
+    }
+
+    fn add(&mut self, mcu_id: usize, blocks: &[[u8; 64]]) {
+        if let Some(last) = self.last_mcu {
+            if mcu_id <= last {
+                self.row += 1;
+            }
+        }
+        self.last_mcu = Some(mcu_id);
+        let need = (self.row + 1) * 8 * IMAGE_WIDTH;
+        if self.pixels.len() < need {
+            self.pixels.resize(need, 0);
+        }
+        for (n, block) in blocks.iter().enumerate() {
+            let x0 = (mcu_id + n) * 8;
+            if x0 + 8 > IMAGE_WIDTH {
+                break;
+            }
+            for y in 0..8 {
+                let start = (self.row * 8 + y) * IMAGE_WIDTH + x0;
+                self.pixels[start..start + 8].copy_from_slice(&block[y * 8..y * 8 + 8]);
+            }
+        }
+    }
+
+    /// Write image as greyscale PNG.
+    pub fn write_png(&self, path: &Path) -> Result<()> {
+        let f = BufWriter::new(std::fs::File::create(path)?);
+        let mut enc = png::Encoder::new(f, IMAGE_WIDTH as u32, self.height() as u32);
+        enc.set_color(png::ColorType::Grayscale);
+        enc.set_depth(png::BitDepth::Eight);
+        let mut w = enc.write_header()?;
+        w.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+}
+
+// Write images every this many new packets.
+const WRITE_INTERVAL: usize = 14 * 50;
+
+/** Decode MSU-MR image packets, and write one PNG per channel.
+
+Files are named `apid64.png` and so on, and are rewritten as more
+lines arrive, and when the block is dropped.
+*/
+pub struct LrptImageWriter {
+    src: NoCopyStreamp<Packet>,
+    dir: PathBuf,
+    decoder: MsumrDecoder,
+    images: BTreeMap<u16, MsumrImage>,
+    unwritten: usize,
+    packets: usize,
+}
+
+impl LrptImageWriter {
+    /// Create new image writer, writing to `dir`.
+    pub fn new(src: NoCopyStreamp<Packet>, dir: PathBuf) -> Self {
+        Self {
+            src,
+            dir,
+            decoder: MsumrDecoder::new(),
+            images: BTreeMap::new(),
+            unwritten: 0,
+            packets: 0,
+        }
+    }
+
+    fn write_all(&mut self) -> Result<()> {
+        for (apid, img) in &self.images {
+            let path = self.dir.join(format!("apid{apid}.png"));
+            debug!("LrptImageWriter: writing {}", path.display());
+            img.write_png(&path)?;
+        }
+        self.unwritten = 0;
+        Ok(())
+    }
+}
+
+impl Drop for LrptImageWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.write_all() {
+            warn!("LrptImageWriter: failed to write images: {e}");
+        }
+        for (apid, img) in &self.images {
+            info!("LrptImageWriter: APID {apid}: {} lines", img.height());
+        }
+    }
+}
+
+impl Block for LrptImageWriter {
+    fn block_name(&self) -> &str {
+        "LrptImageWriter"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let packet = match self.src.pop() {
+            None => return Ok(BlockRet::Noop),
+            Some((x, _tags)) => x,
+        };
+        if !(64..=69).contains(&packet.apid) {
+            return Ok(BlockRet::Ok);
+        }
+        self.packets += 1;
+        if let Some((mcu_id, blocks)) = self.decoder.decode(&packet.data) {
+            trace!(
+                "LrptImageWriter: APID {} MCU {mcu_id}: {} blocks",
+                packet.apid,
+                blocks.len()
+            );
+            self.images
+                .entry(packet.apid)
+                .or_default()
+                .add(mcu_id, &blocks);
+            self.unwritten += 1;
+        }
+        if self.unwritten >= WRITE_INTERVAL {
+            self.write_all()?;
+        }
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    fn vcdu(counter: u32) -> Vec<u8> {
+        let mut v = vec![0x40, 0x05];
+        v.extend(&counter.to_be_bytes()[1..]);
+        v.push(0);
+        v.extend((0..VCDU_LEN - 6).map(|n| (n as u32 * 7 + counter) as u8));
+        v
+    }
+
+    // Build the soft bits for a sequence of VCDUs.
+    fn modulate(vcdus: &[Vec<u8>]) -> Vec<Float> {
+        let rs = ReedSolomon::ccsds();
+        let pn = ccsds::pn_sequence(CADU_LEN - 4);
+        let mut bits = Vec::new();
+        for v in vcdus {
+            let mut frame = vec![0u8; CADU_LEN - 4];
+            for i in 0..RS_INTERLEAVE {
+                let data: Vec<u8> = v.iter().skip(i).step_by(RS_INTERLEAVE).cloned().collect();
+                let parity = rs.encode_ccsds(&data);
+                for (j, b) in data.iter().chain(parity.iter()).enumerate() {
+                    frame[j * RS_INTERLEAVE + i] = *b;
+                }
+            }
+            ccsds::derandomize(&mut frame, &pn);
+            let mut cadu = ccsds::ASM.to_be_bytes().to_vec();
+            cadu.extend(frame);
+            for b in cadu {
+                for n in 0..8 {
+                    bits.push((b >> (7 - n)) & 1);
+                }
+            }
+        }
+        viterbi::Encoder::new()
+            .encode(&bits)
+            .iter()
+            .map(|b| if *b == 1 { 1.0 } else { -1.0 })
+            .collect()
+    }
+
+    #[test]
+    fn decode_frames() -> Result<()> {
+        let vcdus: Vec<_> = (0..3).map(vcdu).collect();
+        let mut soft = vec![0.3; 1000];
+        soft.extend(modulate(&vcdus));
+        // Rotate by 90 degrees, and add some errors.
+        rotate_all(&mut soft, 1);
+        for i in (0..soft.len()).step_by(17) {
+            soft[i] = -soft[i] * 0.5;
+        }
+        soft.extend(vec![0.0; CADU_SOFT]);
+        let src = streamp_from_slice(&soft);
+        let mut dec = LrptDecode::new(src);
+        dec.work()?;
+        let out = dec.out();
+        let mut got = Vec::new();
+        while let Some((v, _)) = out.pop() {
+            got.push(v);
+        }
+        assert_eq!(got, vcdus);
+        Ok(())
+    }
+
+    #[test]
+    fn demod() -> Result<()> {
+        // QPSK at 4 samples per symbol, with a frequency offset.
+        let sps = 4;
+        let nsym = 10000;
+        let mut x: u32 = 1;
+        let syms: Vec<(Float, Float)> = (0..nsym)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                let a = if (x >> 16) & 1 == 1 { 1.0 } else { -1.0 };
+                let b = if (x >> 17) & 1 == 1 { 1.0 } else { -1.0 };
+                (a, b)
+            })
+            .collect();
+        let taps = rrc_taps(sps as Float, 0.6, 16);
+        let mut up = vec![Complex::default(); nsym * sps + taps.len()];
+        for (n, (a, b)) in syms.iter().enumerate() {
+            for (k, t) in taps.iter().enumerate() {
+                up[n * sps + k] += Complex::new(*a, *b) * *t;
+            }
+        }
+        let signal: Vec<Complex> = up
+            .iter()
+            .enumerate()
+            .map(|(n, s)| s * Complex::from_polar(0.5, 0.002 * n as Float))
+            .collect();
+        let mut demod = LrptDemod::new(streamp_from_slice(&signal), 4.0, 1.0)?;
+        demod.work()?;
+        let out = demod.out();
+        let (res, _) = out.read_buf()?;
+        let soft: Vec<Float> = res.iter().cloned().collect();
+
+        // Check the last 5000 symbols, after lock, for any ambiguity
+        // and alignment.
+        let want: Vec<Float> = syms.iter().flat_map(|(a, b)| [*a, *b]).collect();
+        let tail = &soft[soft.len() - 12000..soft.len() - 2000];
+        let best = (0..8)
+            .flat_map(|variant| (0..200).map(move |ofs| (variant, ofs)))
+            .map(|(variant, ofs)| {
+                let start = want.len() - 12000 - 2 * ofs;
+                let mut w = want[start..start + 10000].to_vec();
+                rotate_all(&mut w, variant);
+                w.iter()
+                    .zip(tail.iter())
+                    .filter(|(a, b)| (**a > 0.0) == (**b > 0.0))
+                    .count()
+            })
+            .max()
+            .unwrap();
+        assert!(best > 9990, "best match {best} of 10000");
+        Ok(())
+    }
+
+    #[test]
+    fn msumr_flat() {
+        // 14 blocks, each DC diff 0 ("00") and EOB ("1010"), so 6 bits per
+        // block, all grey.
+        let mut data = vec![0u8; 14];
+        data[8] = 28;
+        data[13] = 80;
+        let bits: Vec<u8> = (0..14).flat_map(|_| [0, 0, 1, 0, 1, 0]).collect();
+        for chunk in bits.chunks(8) {
+            let mut b = 0;
+            for (n, bit) in chunk.iter().enumerate() {
+                b |= bit << (7 - n);
+            }
+            data.push(b);
+        }
+        let (mcu, blocks) = MsumrDecoder::new().decode(&data).unwrap();
+        assert_eq!(mcu, 28);
+        assert_eq!(blocks.len(), 14);
+        assert!(blocks.iter().all(|b| b.iter().all(|p| *p == 128)));
+    }
+
+    #[test]
+    fn msumr_dc() {
+        // One block with DC category 2, value 3 ("011" + "11"), then EOB.
+        let dec = MsumrDecoder::new();
+        let mut data = vec![0u8; 14];
+        data[13] = 50;
+        data.push(0b0111_1101);
+        data.push(0b0000_0000);
+        let (_, blocks) = dec.decode(&data).unwrap();
+        // With quality 50, the DC quantizer is 16. 3*16/8 = 6.
+        assert_eq!(blocks[0][0], 134);
+        assert!(blocks[0].iter().all(|p| *p == 134));
+    }
+}
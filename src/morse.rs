@@ -0,0 +1,397 @@
+/*! Morse code (CW) keyer.
+
+Turns text into properly timed on/off keyed Morse code, either as an
+audio tone (Float), or as Complex baseband.
+
+Timing follows the standard "PARIS" convention: one dit is `1.2 / wpm`
+seconds, a dah is three dits, the gap between elements is one dit,
+between characters three dits, and between words seven dits.
+
+Hard keying creates key clicks, spreading the signal over a wide
+bandwidth. The keyer therefore shapes each element with a raised
+cosine ramp of configurable rise time.
+
+```
+use rustradio::graph::Graph;
+use rustradio::blocks::{MorseKeyerBuilder, NullSink};
+use rustradio::Float;
+let src = MorseKeyerBuilder::<Float>::new("CQ CQ DE SM0ABC", 8000.0)
+    .wpm(20.0)
+    .frequency(700.0)
+    .build();
+let sink = NullSink::new(src.out());
+let mut g = Graph::new();
+g.add(Box::new(src));
+g.add(Box::new(sink));
+g.run()?;
+# Ok::<(), anyhow::Error>(())
+```
+*/
+use log::warn;
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, Streamp};
+use crate::{Complex, Error, Float};
+
+/// Look up the Morse code for a character, as a string of `.` and `-`.
+///
+/// Lower case letters are treated as upper case. Returns None for
+/// characters that don't have a Morse representation.
+pub fn encode_char(ch: char) -> Option<&'static str> {
+    Some(match ch.to_ascii_uppercase() {
+        'A' => ".-",
+        'B' => "-...",
+        'C' => "-.-.",
+        'D' => "-..",
+        'E' => ".",
+        'F' => "..-.",
+        'G' => "--.",
+        'H' => "....",
+        'I' => "..",
+        'J' => ".---",
+        'K' => "-.-",
+        'L' => ".-..",
+        'M' => "--",
+        'N' => "-.",
+        'O' => "---",
+        'P' => ".--.",
+        'Q' => "--.-",
+        'R' => ".-.",
+        'S' => "...",
+        'T' => "-",
+        'U' => "..-",
+        'V' => "...-",
+        'W' => ".--",
+        'X' => "-..-",
+        'Y' => "-.--",
+        'Z' => "--..",
+        '0' => "-----",
+        '1' => ".----",
+        '2' => "..---",
+        '3' => "...--",
+        '4' => "....-",
+        '5' => ".....",
+        '6' => "-....",
+        '7' => "--...",
+        '8' => "---..",
+        '9' => "----.",
+        '.' => ".-.-.-",
+        ',' => "--..--",
+        '?' => "..--..",
+        '\'' => ".----.",
+        '!' => "-.-.--",
+        '/' => "-..-.",
+        '(' => "-.--.",
+        ')' => "-.--.-",
+        '&' => ".-...",
+        ':' => "---...",
+        ';' => "-.-.-.",
+        '=' => "-...-",
+        '+' => ".-.-.",
+        '-' => "-....-",
+        '_' => "..--.-",
+        '"' => ".-..-.",
+        '$' => "...-..-",
+        '@' => ".--.-.",
+        _ => return None,
+    })
+}
+
+/// Turn text into a list of key states, with lengths in dits.
+///
+/// Returned tuples are `(key_down, dits)`. Unknown characters are
+/// skipped with a warning.
+pub fn encode(text: &str) -> Vec<(bool, usize)> {
+    let mut ret: Vec<(bool, usize)> = Vec::new();
+    let gap = |ret: &mut Vec<(bool, usize)>, dits: usize| {
+        match ret.last_mut() {
+            // Never start with silence.
+            None => {}
+            Some((false, n)) => *n = std::cmp::max(*n, dits),
+            Some((true, _)) => ret.push((false, dits)),
+        }
+    };
+    for word in text.split_whitespace() {
+        gap(&mut ret, 7);
+        for ch in word.chars() {
+            let Some(code) = encode_char(ch) else {
+                warn!("MorseKeyer: no Morse code for {:?}, skipping", ch);
+                continue;
+            };
+            gap(&mut ret, 3);
+            for (n, sym) in code.chars().enumerate() {
+                if n > 0 {
+                    ret.push((false, 1));
+                }
+                ret.push((true, if sym == '-' { 3 } else { 1 }));
+            }
+        }
+    }
+    ret
+}
+
+/// Sample types that the keyer can generate.
+pub trait KeyerSample: Copy {
+    /// Create a sample of the keyed carrier, given the current
+    /// envelope amplitude and oscillator phase in radians.
+    fn keyed(amplitude: Float, phase: f64) -> Self;
+}
+
+impl KeyerSample for Float {
+    fn keyed(amplitude: Float, phase: f64) -> Self {
+        amplitude * phase.cos() as Float
+    }
+}
+
+impl KeyerSample for Complex {
+    fn keyed(amplitude: Float, phase: f64) -> Self {
+        Complex::new(
+            amplitude * phase.cos() as Float,
+            amplitude * phase.sin() as Float,
+        )
+    }
+}
+
+/// Builder for MorseKeyer.
+pub struct MorseKeyerBuilder<T: KeyerSample> {
+    text: String,
+    samp_rate: Float,
+    wpm: Float,
+    rise_time: Float,
+    frequency: Float,
+    amplitude: Float,
+    repeat_gap: Option<Float>,
+    dummy: std::marker::PhantomData<T>,
+}
+
+impl<T: KeyerSample> MorseKeyerBuilder<T> {
+    /// Create new MorseKeyer builder.
+    ///
+    /// Defaults to 20 WPM, 5ms rise time, 0Hz (baseband), full
+    /// amplitude, and sending the text only once.
+    pub fn new(text: &str, samp_rate: Float) -> Self {
+        Self {
+            text: text.to_string(),
+            samp_rate,
+            wpm: 20.0,
+            rise_time: 0.005,
+            frequency: 0.0,
+            amplitude: 1.0,
+            repeat_gap: None,
+            dummy: std::marker::PhantomData,
+        }
+    }
+
+    /// Set speed, in words per minute.
+    pub fn wpm(mut self, wpm: Float) -> Self {
+        self.wpm = wpm;
+        self
+    }
+
+    /// Set rise (and fall) time of each element, in seconds.
+    ///
+    /// Zero gives hard keying, with the associated key clicks.
+    pub fn rise_time(mut self, t: Float) -> Self {
+        self.rise_time = t;
+        self
+    }
+
+    /// Set tone frequency in Hz.
+    ///
+    /// For Complex output, 0 gives the keyed baseband envelope.
+    pub fn frequency(mut self, f: Float) -> Self {
+        self.frequency = f;
+        self
+    }
+
+    /// Set peak amplitude.
+    pub fn amplitude(mut self, a: Float) -> Self {
+        self.amplitude = a;
+        self
+    }
+
+    /// Repeat the text forever, with `gap` seconds of silence in
+    /// between. Useful for beacons.
+    pub fn repeat(mut self, gap: Float) -> Self {
+        self.repeat_gap = Some(gap);
+        self
+    }
+
+    /// Build MorseKeyer block.
+    pub fn build(self) -> MorseKeyer<T> {
+        let dit = (1.2 / self.wpm * self.samp_rate).round() as usize;
+        let elements = encode(&self.text)
+            .into_iter()
+            .map(|(on, dits)| (on, dits * dit))
+            .collect();
+        MorseKeyer {
+            dst: new_streamp(),
+            elements,
+            rise: (self.rise_time * self.samp_rate).round() as usize,
+            rad_per_sample: 2.0 * std::f64::consts::PI * self.frequency as f64
+                / self.samp_rate as f64,
+            amplitude: self.amplitude,
+            repeat_gap: self
+                .repeat_gap
+                .map(|g| (g * self.samp_rate).round() as usize),
+            phase: 0.0,
+            element: 0,
+            pos: 0,
+            gap_left: None,
+        }
+    }
+}
+
+/// Morse code keyer block.
+pub struct MorseKeyer<T: KeyerSample> {
+    dst: Streamp<T>,
+    elements: Vec<(bool, usize)>,
+    rise: usize,
+    rad_per_sample: f64,
+    amplitude: Float,
+    repeat_gap: Option<usize>,
+    phase: f64,
+    element: usize,
+    pos: usize,
+    gap_left: Option<usize>,
+}
+
+impl<T: KeyerSample> MorseKeyer<T> {
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<T> {
+        self.dst.clone()
+    }
+
+    // Envelope at position `pos` into a key down of length `len`.
+    fn envelope(&self, pos: usize, len: usize) -> Float {
+        let rise = std::cmp::min(self.rise, len / 2);
+        if rise == 0 {
+            return 1.0;
+        }
+        let edge = std::cmp::min(pos, len - 1 - pos);
+        if edge >= rise {
+            return 1.0;
+        }
+        let x = (edge as Float + 0.5) / rise as Float;
+        0.5 - 0.5 * (std::f64::consts::PI as Float * x).cos()
+    }
+
+    // Generate the next sample, or None if done.
+    fn next_sample(&mut self) -> Option<T> {
+        if let Some(left) = self.gap_left {
+            if left == 0 {
+                self.gap_left = None;
+                self.element = 0;
+                self.pos = 0;
+            } else {
+                self.gap_left = Some(left - 1);
+                return Some(T::keyed(0.0, 0.0));
+            }
+        }
+        if self.element >= self.elements.len() {
+            let gap = self.repeat_gap?;
+            if self.elements.is_empty() {
+                return None;
+            }
+            self.gap_left = Some(gap);
+            return self.next_sample();
+        }
+        let (on, len) = self.elements[self.element];
+        let amp = if on {
+            self.amplitude * self.envelope(self.pos, len)
+        } else {
+            0.0
+        };
+        let s = T::keyed(amp, self.phase);
+        self.phase = (self.phase + self.rad_per_sample) % (2.0 * std::f64::consts::PI);
+        self.pos += 1;
+        if self.pos >= len {
+            self.pos = 0;
+            self.element += 1;
+        }
+        Some(s)
+    }
+}
+
+impl<T: KeyerSample> Block for MorseKeyer<T> {
+    fn block_name(&self) -> &str {
+        "MorseKeyer"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let obind = self.dst.clone();
+        let mut o = obind.write_buf()?;
+        let mut n = 0;
+        for place in o.slice().iter_mut() {
+            match self.next_sample() {
+                Some(s) => *place = s,
+                None => break,
+            }
+            n += 1;
+        }
+        if n == 0 {
+            return Ok(BlockRet::EOF);
+        }
+        o.produce(n, &[]);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_paris() {
+        // PARIS is by definition 50 dits long, including the word gap.
+        let e = encode("PARIS PARIS");
+        let total: usize = e.iter().map(|(_, n)| n).sum();
+        // Two words, one word gap in between but not after.
+        assert_eq!(total, 50 + 43);
+        assert!(e[0].0);
+        assert!(e.last().unwrap().0);
+    }
+
+    #[test]
+    fn encode_skips_unknown() {
+        assert_eq!(encode("e~e"), vec![(true, 1), (false, 3), (true, 1)]);
+        assert_eq!(encode("  "), vec![]);
+    }
+
+    #[test]
+    fn keyer_timing() -> crate::Result<()> {
+        // 12 WPM at 1000sps gives 100 samples per dit.
+        let mut b = MorseKeyerBuilder::<Float>::new("ET", 1000.0)
+            .wpm(12.0)
+            .rise_time(0.0)
+            .build();
+        assert!(matches!(b.work()?, BlockRet::Ok));
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        assert_eq!(res.len(), 100 + 300 + 300);
+        assert!(res.slice()[..100].iter().all(|s| *s == 1.0));
+        assert!(res.slice()[100..400].iter().all(|s| *s == 0.0));
+        assert!(res.slice()[400..].iter().all(|s| *s == 1.0));
+        drop(res);
+        assert!(matches!(b.work()?, BlockRet::EOF));
+        Ok(())
+    }
+
+    #[test]
+    fn keyer_shaping() -> crate::Result<()> {
+        let mut b = MorseKeyerBuilder::<Complex>::new("E", 1000.0)
+            .wpm(12.0)
+            .rise_time(0.01)
+            .build();
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        assert_eq!(res.len(), 100);
+        assert!(res[0].re < 0.1);
+        assert!(res[99].re < 0.1);
+        assert_eq!(res[50].re, 1.0);
+        for i in 1..10 {
+            assert!(res[i].re > res[i - 1].re);
+        }
+        Ok(())
+    }
+}
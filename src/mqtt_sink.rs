@@ -0,0 +1,518 @@
+/*! MQTT sink.
+
+Publishes PDUs to an [MQTT] broker, e.g. to feed decoded sensor data
+to Home Assistant.
+
+The topic is a template, where `{key}` is replaced by the PDU metadata
+value for `key`, e.g. `rustradio/{model}/{id}`. Missing keys are
+replaced with `unknown`. Characters that have special meaning in topics
+(`/`, `+`, `#`) are replaced with `_` in the substituted values.
+
+Only the parts of MQTT 3.1.1 needed to publish are implemented. QoS 0
+and 1 are supported. Keepalive is disabled, and the connection is
+re-established if a publish fails. After a failure it waits before
+reconnecting, starting at 1s and doubling up to 5 minutes.
+
+With QoS 1, messages not acknowledged by the broker are kept, and sent
+again after reconnecting. At most 1000 are kept, after which the
+oldest are dropped. With QoS 0, messages that fail, or arrive while
+waiting to reconnect, are dropped.
+
+TLS requires the `mqtt-tls` feature, and verifies the broker
+certificate against the Mozilla root certificates.
+
+```text
+HdlcDeframer -> ToPdu -> MqttSink
+```
+
+[MQTT]: https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/mqtt-v3.1.1.html
+*/
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{debug, info, warn};
+
+use crate::block::{Block, BlockRet};
+use crate::net::{self, Backoff};
+use crate::pdu::Pdu;
+use crate::stream::{NoCopyStreamp, TagValue};
+use crate::Error;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const BACKOFF_MIN: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(300);
+const MAX_QUEUED: usize = 1000;
+
+/// Quality of service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Qos {
+    /// At most once.
+    #[default]
+    AtMostOnce,
+
+    /// At least once. Waits for the broker to acknowledge each message.
+    AtLeastOnce,
+}
+
+/// Message payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Payload {
+    /// PDU data as is.
+    #[default]
+    Raw,
+
+    /// JSON object, as from [Pdu::to_json].
+    #[cfg(feature = "json")]
+    Json,
+}
+
+/// Expand a topic template with PDU metadata.
+pub fn expand_topic(template: &str, pdu: &Pdu) -> String {
+    let mut ret = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        ret.push_str(&rest[..start]);
+        let key = &rest[start + 1..start + len];
+        let val = match pdu.get(key) {
+            Some(TagValue::String(s)) => s.clone(),
+            Some(TagValue::Float(f)) => f.to_string(),
+            Some(TagValue::Bool(b)) => b.to_string(),
+            Some(TagValue::U64(n)) => n.to_string(),
+            None => "unknown".to_string(),
+        };
+        ret.extend(val.chars().map(|c| match c {
+            '/' | '+' | '#' => '_',
+            c => c,
+        }));
+        rest = &rest[start + len + 1..];
+    }
+    ret.push_str(rest);
+    ret
+}
+
+fn encode_str(v: &mut Vec<u8>, s: &[u8]) {
+    v.extend((s.len() as u16).to_be_bytes());
+    v.extend(s);
+}
+
+// Build a packet from type byte and body.
+fn packet(typ: u8, body: &[u8]) -> Vec<u8> {
+    let mut v = vec![typ];
+    let mut len = body.len();
+    loop {
+        let b = (len % 128) as u8;
+        len /= 128;
+        v.push(if len > 0 { b | 0x80 } else { b });
+        if len == 0 {
+            break;
+        }
+    }
+    v.extend(body);
+    v
+}
+
+// Read one packet, returning type byte and body.
+fn read_packet(r: &mut dyn Read) -> Result<(u8, Vec<u8>)> {
+    let mut b = [0u8; 1];
+    r.read_exact(&mut b)?;
+    let typ = b[0];
+    let mut len = 0usize;
+    for shift in 0..4 {
+        r.read_exact(&mut b)?;
+        len |= ((b[0] & 0x7f) as usize) << (7 * shift);
+        if b[0] & 0x80 == 0 {
+            let mut body = vec![0u8; len];
+            r.read_exact(&mut body)?;
+            return Ok((typ, body));
+        }
+    }
+    Err(Error::new("MQTT: bad remaining length").into())
+}
+
+trait Transport: Read + Write + Send {}
+impl<T: Read + Write + Send> Transport for T {}
+
+/// Builder for [MqttSink].
+pub struct MqttSinkBuilder {
+    src: NoCopyStreamp<Pdu>,
+    server: String,
+    topic: String,
+    client_id: String,
+    credentials: Option<(String, String)>,
+    qos: Qos,
+    retain: bool,
+    payload: Payload,
+    tls: bool,
+}
+
+impl MqttSinkBuilder {
+    /// Start building, for broker `server` (e.g. `"localhost:1883"`)
+    /// and topic template `topic`.
+    pub fn new(src: NoCopyStreamp<Pdu>, server: &str, topic: &str) -> Self {
+        Self {
+            src,
+            server: server.to_string(),
+            topic: topic.to_string(),
+            client_id: format!("rustradio-{}", std::process::id()),
+            credentials: None,
+            qos: Qos::default(),
+            retain: false,
+            payload: Payload::default(),
+            tls: false,
+        }
+    }
+
+    /// Set client ID. Default `rustradio-<pid>`.
+    pub fn client_id(mut self, id: &str) -> Self {
+        self.client_id = id.to_string();
+        self
+    }
+
+    /// Set username and password.
+    pub fn credentials(mut self, user: &str, password: &str) -> Self {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        self
+    }
+
+    /// Set QoS.
+    pub fn qos(mut self, qos: Qos) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    /// Set the retain flag on published messages.
+    pub fn retain(mut self, retain: bool) -> Self {
+        self.retain = retain;
+        self
+    }
+
+    /// Set payload format.
+    pub fn payload(mut self, payload: Payload) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Use TLS. Requires the `mqtt-tls` feature.
+    pub fn tls(mut self, tls: bool) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Connect to the broker, and build the block.
+    pub fn build(self) -> Result<MqttSink> {
+        if self.tls && !cfg!(feature = "mqtt-tls") {
+            return Err(Error::new("MQTT TLS requires the mqtt-tls feature").into());
+        }
+        let mut ret = MqttSink {
+            src: self.src,
+            server: self.server,
+            topic: self.topic,
+            client_id: self.client_id,
+            credentials: self.credentials,
+            qos: self.qos,
+            retain: self.retain,
+            payload: self.payload,
+            tls: self.tls,
+            conn: None,
+            backoff: Backoff::new(BACKOFF_MIN, BACKOFF_MAX),
+            queue: VecDeque::new(),
+            packet_id: 0,
+            sent: 0,
+            errors: 0,
+        };
+        ret.connect()?;
+        Ok(ret)
+    }
+}
+
+/// MQTT sink block.
+pub struct MqttSink {
+    src: NoCopyStreamp<Pdu>,
+    server: String,
+    topic: String,
+    client_id: String,
+    credentials: Option<(String, String)>,
+    qos: Qos,
+    retain: bool,
+    payload: Payload,
+    tls: bool,
+    conn: Option<Box<dyn Transport>>,
+    backoff: Backoff,
+    // Messages not yet acknowledged, oldest first.
+    queue: VecDeque<Pdu>,
+    packet_id: u16,
+    sent: usize,
+    errors: usize,
+}
+
+impl MqttSink {
+    fn open(&self) -> Result<Box<dyn Transport>> {
+        let tcp = net::connect(&self.server, TIMEOUT)?;
+        tcp.set_read_timeout(Some(TIMEOUT))?;
+        tcp.set_write_timeout(Some(TIMEOUT))?;
+        if !self.tls {
+            return Ok(Box::new(tcp));
+        }
+        #[cfg(feature = "mqtt-tls")]
+        {
+            use std::sync::Arc;
+            let roots = rustls::RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            };
+            let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+                rustls::crypto::ring::default_provider(),
+            ))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+            let host = self
+                .server
+                .rsplit_once(':')
+                .map(|(h, _)| h)
+                .unwrap_or(&self.server)
+                .trim_matches(|c| c == '[' || c == ']')
+                .to_string();
+            let conn = rustls::ClientConnection::new(Arc::new(config), host.try_into()?)?;
+            Ok(Box::new(rustls::StreamOwned::new(conn, tcp)))
+        }
+        #[cfg(not(feature = "mqtt-tls"))]
+        unreachable!("checked in build()")
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        info!("MqttSink: connecting to {}", self.server);
+        let mut conn = self.open()?;
+        let mut body = Vec::new();
+        encode_str(&mut body, b"MQTT");
+        body.push(4); // Protocol level 3.1.1.
+        let mut flags = 0x02; // Clean session.
+        if self.credentials.is_some() {
+            flags |= 0xc0;
+        }
+        body.push(flags);
+        body.extend(0u16.to_be_bytes()); // Keepalive disabled.
+        encode_str(&mut body, self.client_id.as_bytes());
+        if let Some((user, pass)) = &self.credentials {
+            encode_str(&mut body, user.as_bytes());
+            encode_str(&mut body, pass.as_bytes());
+        }
+        conn.write_all(&packet(CONNECT, &body))?;
+        conn.flush()?;
+        let (typ, body) = read_packet(&mut conn)?;
+        if typ != CONNACK || body.len() != 2 {
+            return Err(Error::new(&format!("MQTT: expected CONNACK, got {typ:02x}")).into());
+        }
+        if body[1] != 0 {
+            return Err(Error::new(&format!("MQTT: connection refused, code {}", body[1])).into());
+        }
+        debug!("MqttSink: connected");
+        self.conn = Some(conn);
+        Ok(())
+    }
+
+    fn publish(&mut self, pdu: &Pdu) -> Result<()> {
+        if self.conn.is_none() {
+            self.connect()?;
+        }
+        let topic = expand_topic(&self.topic, pdu);
+        let payload = match self.payload {
+            Payload::Raw => pdu.data().to_vec(),
+            #[cfg(feature = "json")]
+            Payload::Json => pdu.to_json().into_bytes(),
+        };
+        let mut body = Vec::with_capacity(topic.len() + payload.len() + 4);
+        encode_str(&mut body, topic.as_bytes());
+        let mut typ = PUBLISH | self.retain as u8;
+        if self.qos == Qos::AtLeastOnce {
+            typ |= 0x02;
+            self.packet_id = self.packet_id.checked_add(1).unwrap_or(1);
+            body.extend(self.packet_id.to_be_bytes());
+        }
+        body.extend(payload);
+        debug!("MqttSink: publishing to {topic}");
+        let conn = self.conn.as_mut().unwrap();
+        conn.write_all(&packet(typ, &body))?;
+        conn.flush()?;
+        if self.qos == Qos::AtLeastOnce {
+            loop {
+                let (typ, body) = read_packet(conn)?;
+                if typ == PUBACK && body == self.packet_id.to_be_bytes() {
+                    break;
+                }
+                debug!("MqttSink: ignoring packet type {typ:02x}");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MqttSink {
+    fn drop(&mut self) {
+        info!("MqttSink: sent {}, errors {}", self.sent, self.errors);
+    }
+}
+
+impl Block for MqttSink {
+    fn block_name(&self) -> &str {
+        "MqttSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        if let Some((pdu, _tags)) = self.src.pop() {
+            if self.queue.len() >= MAX_QUEUED {
+                warn!("MqttSink: too many unacknowledged messages, dropping oldest");
+                self.queue.pop_front();
+                self.errors += 1;
+            }
+            self.queue.push_back(pdu);
+        }
+        let Some(pdu) = self.queue.pop_front() else {
+            return Ok(BlockRet::Noop);
+        };
+        if self.conn.is_none() && !self.backoff.ready() {
+            // Waiting to reconnect.
+            if self.qos == Qos::AtMostOnce {
+                self.errors += 1;
+                return Ok(BlockRet::Ok);
+            }
+            self.queue.push_front(pdu);
+            return Ok(BlockRet::Pending);
+        }
+        match self.publish(&pdu) {
+            Ok(()) => {
+                self.sent += 1;
+                self.backoff.succeeded();
+            }
+            Err(e) => {
+                warn!("MqttSink: failed to publish: {e}");
+                self.errors += 1;
+                self.conn = None;
+                self.backoff.failed();
+                if self.qos == Qos::AtLeastOnce {
+                    self.queue.push_front(pdu);
+                }
+            }
+        }
+        Ok(if self.queue.is_empty() {
+            BlockRet::Ok
+        } else {
+            BlockRet::Pending
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::new_nocopy_streamp;
+    use std::net::TcpListener;
+
+    #[test]
+    fn topic() {
+        let pdu = Pdu::new(vec![])
+            .with("model", TagValue::String("Nexus/TH".into()))
+            .with("id", TagValue::U64(12));
+        assert_eq!(
+            expand_topic("rr/{model}/{id}/{channel}/x", &pdu),
+            "rr/Nexus_TH/12/unknown/x"
+        );
+        assert_eq!(expand_topic("a/{b", &pdu), "a/{b");
+    }
+
+    #[test]
+    fn remaining_length() -> Result<()> {
+        for len in [0, 127, 128, 16383, 16384] {
+            let p = packet(PUBLISH, &vec![7; len]);
+            let (typ, body) = read_packet(&mut &p[..])?;
+            assert_eq!(typ, PUBLISH);
+            assert_eq!(body.len(), len);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn publish() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?.to_string();
+        let broker = std::thread::spawn(move || -> Result<Vec<(u8, Vec<u8>)>> {
+            let (mut s, _) = listener.accept()?;
+            let connect = read_packet(&mut s)?;
+            s.write_all(&[CONNACK, 2, 0, 0])?;
+            let publish = read_packet(&mut s)?;
+            let id = &publish.1[publish.1.len() - 5..publish.1.len() - 3];
+            s.write_all(&packet(PUBACK, id))?;
+            Ok(vec![connect, publish])
+        });
+        let src = new_nocopy_streamp();
+        let mut sink = MqttSinkBuilder::new(src.clone(), &addr, "rr/{id}")
+            .client_id("test")
+            .credentials("user", "pw")
+            .qos(Qos::AtLeastOnce)
+            .build()?;
+        src.push(Pdu::new(b"abc".to_vec()).with("id", TagValue::U64(5)), &[]);
+        sink.work()?;
+        assert_eq!(sink.sent, 1);
+
+        let got = broker.join().unwrap()?;
+        assert_eq!(got[0].0, CONNECT);
+        assert_eq!(&got[0].1[..8], b"\0\x04MQTT\x04\xc2");
+        assert_eq!(&got[0].1[10..], b"\0\x04test\0\x04user\0\x02pw");
+        assert_eq!(got[1].0, PUBLISH | 0x02);
+        assert_eq!(got[1].1, b"\0\x04rr/5\0\x01abc");
+        Ok(())
+    }
+
+    #[test]
+    fn retry_unacked() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?.to_string();
+        let broker = std::thread::spawn(move || -> Result<Vec<Vec<u8>>> {
+            let mut ret = Vec::new();
+            for ack in [false, true] {
+                let (mut s, _) = listener.accept()?;
+                read_packet(&mut s)?;
+                s.write_all(&[CONNACK, 2, 0, 0])?;
+                let publish = read_packet(&mut s)?.1;
+                if ack {
+                    let id = &publish[publish.len() - 5..publish.len() - 3];
+                    s.write_all(&packet(PUBACK, id))?;
+                }
+                // Unacked messages are followed by dropping the connection.
+                ret.push(publish);
+            }
+            Ok(ret)
+        });
+        let src = new_nocopy_streamp();
+        let mut sink = MqttSinkBuilder::new(src.clone(), &addr, "rr")
+            .qos(Qos::AtLeastOnce)
+            .build()?;
+        src.push(Pdu::new(b"abc".to_vec()), &[]);
+
+        // Not acked, so kept, and not retried until the backoff expires.
+        assert!(matches![sink.work()?, BlockRet::Pending]);
+        assert_eq!((sink.sent, sink.errors, sink.queue.len()), (0, 1, 1));
+        assert!(matches![sink.work()?, BlockRet::Pending]);
+        assert_eq!(sink.errors, 1);
+
+        // Retried after reconnect.
+        sink.backoff = Backoff::new(Duration::ZERO, Duration::ZERO);
+        assert!(matches![sink.work()?, BlockRet::Ok]);
+        assert_eq!((sink.sent, sink.errors, sink.queue.len()), (1, 1, 0));
+
+        let got = broker.join().unwrap()?;
+        assert_eq!(got.len(), 2);
+        assert_eq!(&got[0][..4], b"\0\x02rr");
+        assert_eq!(&got[0][6..], b"abc");
+        assert_eq!(&got[1][6..], b"abc");
+        Ok(())
+    }
+}
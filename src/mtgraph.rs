@@ -1,13 +1,31 @@
 /*! Multithreaded version of Graph, otherwise the same as graph.rs.
+
+[Graph](crate::graph::Graph) runs all blocks cooperatively on one
+thread, so one heavy block, like an FftFilter, slows down everything
+else. [MTGraph] runs each block on its own thread, with the streams
+between them as bounded queues.
+
+A thread per block is not always best. Cheap blocks spend more time
+waking up and handing off data than working, so they can be put on a
+shared thread with [MTGraph::add_group].
  */
 use std::collections::BTreeMap;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use log::{debug, error, info, trace};
 
-use crate::block::{Block, BlockRet};
-use crate::graph::CancellationToken;
+use crate::block::{Block, BlockRet, WorkHints};
+use crate::control::Controls;
+use crate::graph::{CancellationToken, GraphItem, PauseToken, StatsHandle, STATS_INTERVAL};
+use crate::node::{InPort, Node, NodePorts, Nodes, OutPort, Port, Ports};
+use crate::stream::{WorkCounts, WorkLimiter};
+
+// How often paused block threads check if they've been resumed.
+const PAUSE_SLEEP: Duration = Duration::from_millis(10);
+
+// A block, its index in the graph, and its limiter.
+type GroupBlock = (usize, Box<dyn Block + Send>, WorkLimiter);
 
 /**
 A graph is a thing that RustRadio runs, to let blocks "talk to each
@@ -34,8 +52,16 @@ g.run()?;
 */
 pub struct MTGraph {
     blocks: Vec<Box<dyn Block + Send>>,
+    // Nodes added with ports, built as they're connected.
+    nodes: Nodes,
+    limiters: Vec<WorkLimiter>,
+
+    // Thread number of each block.
+    groups: Vec<usize>,
     cancel_token: CancellationToken,
+    pause_token: PauseToken,
     times: BTreeMap<(usize, String), std::time::Duration>,
+    stats: StatsHandle,
 }
 
 impl MTGraph {
@@ -43,18 +69,93 @@ impl MTGraph {
     pub fn new() -> Self {
         Self {
             blocks: Vec::new(),
+            nodes: Nodes::new(),
+            limiters: Vec::new(),
+            groups: Vec::new(),
             times: BTreeMap::new(),
             cancel_token: CancellationToken::new(),
+            pause_token: PauseToken::new(),
+            stats: StatsHandle::default(),
         }
     }
 
-    /// Add a block to the flowgraph.
-    pub fn add(&mut self, b: Box<dyn Block + Send>) {
+    /// Add a block, or a [Node], to the flowgraph. Each gets a thread
+    /// of its own.
+    ///
+    /// Nodes return their [NodePorts], to [connect](MTGraph::connect)
+    /// them.
+    pub fn add<T: GraphItem<Self>>(&mut self, item: T) -> T::Id {
+        item.add_to(self)
+    }
+
+    fn push_block(&mut self, b: Box<dyn Block + Send>) {
+        let hints = b.work_hints();
+        self.add_with_hints(b, hints);
+    }
+
+    /// Connect an output port of a node to an input port. Same as
+    /// [Graph::connect](crate::graph::Graph::connect).
+    pub fn connect<P: Port>(
+        &mut self,
+        src: OutPort<P>,
+        dst: InPort<P>,
+    ) -> Result<(), crate::Error> {
+        self.nodes.connect(src, dst)?;
+        self.build_nodes();
+        Ok(())
+    }
+
+    fn build_nodes(&mut self) {
+        while let Some(b) = self.nodes.build_next() {
+            self.push_block(b);
+        }
+    }
+
+    /// Add a block to the flowgraph, overriding its own [WorkHints].
+    pub fn add_with_hints(&mut self, b: Box<dyn Block + Send>, hints: WorkHints) {
+        let group = self.groups.last().map_or(0, |g| g + 1);
+        self.push(b, hints, group);
+    }
+
+    /** Add blocks that share one thread.
+
+    The blocks are run cooperatively, in order, like in a
+    [Graph](crate::graph::Graph). Order them the way the data flows.
+
+    ```
+    use rustradio::mtgraph::MTGraph;
+    use rustradio::blocks::{AddConst, FIRFilter, MultiplyConst, NullSink, VectorSource};
+    let src = VectorSource::new(vec![1.0; 10_000]);
+    let filter = FIRFilter::new(src.out(), &[0.5, 0.5]);
+    let add = AddConst::new(filter.out(), 1.0);
+    let mul = MultiplyConst::new(add.out(), 2.0);
+    let sink = NullSink::new(mul.out());
+    let mut g = MTGraph::new();
+    g.add(Box::new(src));
+    // The filter gets a thread of its own.
+    g.add(Box::new(filter));
+    g.add_group(vec![Box::new(add), Box::new(mul), Box::new(sink)]);
+    g.run()?;
+    # Ok::<(), anyhow::Error>(())
+    ```
+    */
+    pub fn add_group(&mut self, blocks: Vec<Box<dyn Block + Send>>) {
+        let group = self.groups.last().map_or(0, |g| g + 1);
+        for b in blocks {
+            let hints = b.work_hints();
+            self.push(b, hints, group);
+        }
+    }
+
+    fn push(&mut self, b: Box<dyn Block + Send>, hints: WorkHints, group: usize) {
         self.blocks.push(b);
+        self.limiters.push(WorkLimiter::new(hints));
+        self.groups.push(group);
     }
 
     /// Run the graph until completion.
     pub fn run(&mut self) -> Result<()> {
+        self.nodes.check()?;
         let (exit_monitor, em_tx) = {
             let cancel_token = self.cancel_token.clone();
             let block_count = self.blocks.len();
@@ -133,43 +234,89 @@ impl MTGraph {
         };
 
         let st = Instant::now();
+        self.stats
+            .init(self.blocks.iter().map(|b| b.block_name().to_string()));
+
+        // Sort the blocks into threads.
+        let mut groups: BTreeMap<usize, Vec<GroupBlock>> = BTreeMap::new();
+        let blocks = std::mem::take(&mut self.blocks);
+        let limiters = std::mem::take(&mut self.limiters);
+        let group_ids = std::mem::take(&mut self.groups);
+        for (index, ((b, limiter), group)) in
+            blocks.into_iter().zip(limiters).zip(group_ids).enumerate()
+        {
+            groups.entry(group).or_default().push((index, b, limiter));
+        }
+
         let mut threads = Vec::new();
-        let mut index = self.blocks.len();
-        while let Some(mut b) = self.blocks.pop() {
-            index -= 1;
+        for mut group in groups.into_values() {
             let cancel_token = self.cancel_token.clone();
+            let pause_token = self.pause_token.clone();
             let em_tx = em_tx.clone();
-            debug!("Starting thread {}", b.block_name());
-            let th = std::thread::Builder::new()
-                .name(b.block_name().to_string())
-                .spawn(move || -> Result<std::time::Duration> {
-                    let idle_sleep = std::time::Duration::from_millis(1);
-                    let mut tt = std::time::Duration::new(0, 0);
-                    while !cancel_token.is_canceled() {
-                        let st = Instant::now();
-                        let ret = b.work()?;
-                        tt += st.elapsed();
-                        em_tx
-                            .send((index, ret.clone()))
-                            .expect("mpsc status send failed");
-                        match ret {
-                            BlockRet::Ok => {}
-                            BlockRet::EOF => {
-                                return Ok(tt);
-                            }
-                            BlockRet::Noop => {
-                                std::thread::sleep(idle_sleep);
+            let stats = self.stats.clone();
+            let name = group
+                .iter()
+                .map(|(_, b, _)| b.block_name())
+                .collect::<Vec<_>>()
+                .join(",");
+            debug!("Starting thread {name}");
+            let th = std::thread::Builder::new().name(name).spawn(
+                move || -> Result<Vec<(usize, String, Duration, WorkCounts)>> {
+                    let idle_sleep = Duration::from_millis(1);
+                    let mut times = vec![Duration::new(0, 0); group.len()];
+                    let mut eof = vec![false; group.len()];
+                    let mut stats_updated = Instant::now();
+                    while !cancel_token.is_canceled() && !eof.iter().all(|&e| e) {
+                        if pause_token.is_paused() {
+                            std::thread::sleep(PAUSE_SLEEP);
+                            continue;
+                        }
+                        let mut idle = true;
+                        for (n, (index, b, limiter)) in group.iter_mut().enumerate() {
+                            if eof[n] {
+                                continue;
                             }
-                            BlockRet::Pending => {
-                                std::thread::sleep(idle_sleep);
+                            let bst = Instant::now();
+                            let ret = match limiter.work(|| b.work()) {
+                                Ok(ret) => ret,
+                                Err(e) => {
+                                    // Stop the other blocks too.
+                                    cancel_token.cancel();
+                                    return Err(e.in_block(b.block_name(), *index).into());
+                                }
+                            };
+                            times[n] += bst.elapsed();
+                            em_tx
+                                .send((*index, ret.clone()))
+                                .expect("mpsc status send failed");
+                            match ret {
+                                BlockRet::Ok => idle = false,
+                                BlockRet::EOF => eof[n] = true,
+                                BlockRet::Noop | BlockRet::Pending => {}
+                                BlockRet::InternalAwaiting => {
+                                    panic!("blocks must never return InternalAwaiting")
+                                }
                             }
-                            BlockRet::InternalAwaiting => {
-                                panic!("blocks must never return InternalAwaiting")
+                        }
+                        if stats_updated.elapsed() >= STATS_INTERVAL {
+                            stats_updated = Instant::now();
+                            for ((index, _, limiter), t) in group.iter().zip(&times) {
+                                stats.set(*index, *t, limiter.counts(), st.elapsed());
                             }
                         }
+                        if idle {
+                            std::thread::sleep(idle_sleep);
+                        }
                     }
-                    Ok(tt)
-                });
+                    Ok(group
+                        .iter()
+                        .zip(times)
+                        .map(|((index, b, limiter), t)| {
+                            (*index, b.block_name().to_string(), t, *limiter.counts())
+                        })
+                        .collect())
+                },
+            );
             let th = match th {
                 Err(x) => {
                     error!("Failed to spawn block thread: {:?}", x);
@@ -182,17 +329,28 @@ impl MTGraph {
         }
         drop(em_tx);
         debug!("Joining threads");
-        for (n, th) in threads.into_iter().rev().enumerate() {
+        let mut first_err = None;
+        for th in threads {
             let name = th.thread().name().unwrap().to_string();
             debug!("Waiting for {}", name);
-            let j = th
-                .join()
-                .expect("joining thread")
-                .expect("block exit status");
+            let j = match th.join().expect("joining thread") {
+                Ok(j) => j,
+                Err(e) => {
+                    error!("{e}");
+                    first_err.get_or_insert(e);
+                    continue;
+                }
+            };
             debug!("Thread {} finished with {:?}", name, j);
-            self.times.insert((n, name), j);
+            for (index, name, t, counts) in j {
+                self.stats.set(index, t, &counts, st.elapsed());
+                self.times.insert((index, name), t);
+            }
         }
         exit_monitor.join().unwrap().unwrap();
+        if let Some(e) = first_err {
+            return Err(e);
+        }
         for line in self.generate_stats(st.elapsed()).split('\n') {
             if !line.is_empty() {
                 info!("{}", line);
@@ -256,6 +414,12 @@ impl MTGraph {
         s
     }
 
+    /// Return a handle for reading stats while the graph is running,
+    /// e.g. from another thread.
+    pub fn stats_handle(&self) -> StatsHandle {
+        self.stats.clone()
+    }
+
     /// Return a cancellation token, for asynchronously stopping the
     /// graph, for example if the user presses Ctrl-C.
     ///
@@ -274,6 +438,64 @@ impl MTGraph {
     pub fn cancel_token(&self) -> CancellationToken {
         self.cancel_token.clone()
     }
+
+    /// Pause the graph. Block threads stop calling `work()` until
+    /// [MTGraph::resume].
+    ///
+    /// To pause while `run()` is executing, use a [PauseToken].
+    pub fn pause(&self) {
+        self.pause_token.pause();
+    }
+
+    /// Resume a paused graph.
+    pub fn resume(&self) {
+        self.pause_token.resume();
+    }
+
+    /// Check if the graph is paused.
+    pub fn is_paused(&self) -> bool {
+        self.pause_token.is_paused()
+    }
+
+    /// Return a token for pausing and resuming the graph from another
+    /// thread.
+    pub fn pause_token(&self) -> PauseToken {
+        self.pause_token.clone()
+    }
+
+    /// Return the [controls](crate::control) of all blocks, for
+    /// changing their parameters while the graph is running, e.g. from
+    /// another thread. Must be called before `run()`.
+    pub fn controls(&self) -> Controls {
+        let mut c = Controls::new();
+        for b in &self.blocks {
+            c.add_block(b.as_ref());
+        }
+        c
+    }
+}
+
+impl<B: Block + Send + 'static> GraphItem<MTGraph> for Box<B> {
+    type Id = ();
+    fn add_to(self, g: &mut MTGraph) {
+        g.push_block(self);
+    }
+}
+
+impl GraphItem<MTGraph> for Box<dyn Block + Send> {
+    type Id = ();
+    fn add_to(self, g: &mut MTGraph) {
+        g.push_block(self);
+    }
+}
+
+impl<I: Ports, O: Ports> GraphItem<MTGraph> for Node<I, O> {
+    type Id = NodePorts<I, O>;
+    fn add_to(self, g: &mut MTGraph) -> Self::Id {
+        let ports = g.nodes.add(self);
+        g.build_nodes();
+        ports
+    }
 }
 
 impl Default for MTGraph {
@@ -0,0 +1,79 @@
+//! Multiply two streams.
+use crate::block::Block;
+use crate::stream::{new_streamp, Streamp};
+
+/// Multiplies two streams, sample by sample.
+///
+/// For complex streams this is a mixer: multiply with a
+/// [`SignalSource`][crate::signal_source::SignalSource] to shift the
+/// frequency.
+#[derive(Block)]
+pub struct Multiply<T>
+where
+    T: Copy + std::ops::Mul<Output = T>,
+{
+    #[rustradio(input)]
+    a: Streamp<T>,
+    #[rustradio(input)]
+    b: Streamp<T>,
+    #[rustradio(output)]
+    dst: Streamp<T>,
+}
+
+impl<T> Multiply<T>
+where
+    T: Copy + std::ops::Mul<Output = T>,
+{
+    /// Create a new Multiply block.
+    pub fn new(a: Streamp<T>, b: Streamp<T>) -> Self {
+        Self {
+            a,
+            b,
+            dst: new_streamp(),
+        }
+    }
+
+    fn process_one(&self, a: T, b: T) -> T {
+        a * b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockRet;
+    use crate::stream::streamp_from_slice;
+    use crate::tests::assert_almost_equal_complex;
+    use crate::{Complex, Float};
+
+    #[test]
+    fn multiply() -> crate::Result<()> {
+        let a = streamp_from_slice(&[1.0 as Float, 2.0]);
+        let b = streamp_from_slice(&[3.0 as Float, 4.0, 5.0]);
+        let mut mul = Multiply::new(a.clone(), b.clone());
+        assert_eq!(mul.block_name(), "Multiply");
+        mul.work()?;
+        assert!(matches!(mul.work()?, BlockRet::Noop));
+        let o = mul.out();
+        let (res, _) = o.read_buf()?;
+        assert_eq!(res.slice(), &[3.0, 8.0]);
+        assert!(a.read_buf()?.0.is_empty());
+        assert_eq!(b.read_buf()?.0.slice(), &[5.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn mix() -> crate::Result<()> {
+        let a = streamp_from_slice(&[Complex::new(1.0, 0.0), Complex::new(0.0, 1.0)]);
+        let b = streamp_from_slice(&[Complex::new(0.0, 1.0), Complex::new(0.0, 1.0)]);
+        let mut mul = Multiply::new(a, b);
+        mul.work()?;
+        let o = mul.out();
+        let (res, _) = o.read_buf()?;
+        assert_almost_equal_complex(
+            res.slice(),
+            &[Complex::new(0.0, 1.0), Complex::new(-1.0, 0.0)],
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+/*! Helpers for blocks that talk to network servers.
+
+Blocks run in the graph thread, so they must not block on a server
+that's down. Connect with a timeout, and after a failure wait before
+trying again, doubling the wait each time up to a max.
+*/
+use std::io::{Error, ErrorKind};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// Connect to `server` (`host:port`), trying each address with a
+/// timeout.
+pub(crate) fn connect(server: &str, timeout: Duration) -> std::io::Result<TcpStream> {
+    let mut last = None;
+    for addr in server.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(s) => return Ok(s),
+            Err(e) => last = Some(e),
+        }
+    }
+    Err(last.unwrap_or_else(|| Error::new(ErrorKind::NotFound, format!("{server}: no address"))))
+}
+
+/// Exponential reconnect backoff.
+pub(crate) struct Backoff {
+    min: Duration,
+    max: Duration,
+    delay: Duration,
+    next: Option<Instant>,
+}
+
+impl Backoff {
+    /// Create new backoff, waiting `min` after the first failure, and
+    /// at most `max`.
+    pub(crate) fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+            delay: min,
+            next: None,
+        }
+    }
+
+    /// Return true if it's time to try again.
+    pub(crate) fn ready(&self) -> bool {
+        self.next.is_none_or(|t| Instant::now() >= t)
+    }
+
+    /// Record a failed attempt.
+    pub(crate) fn failed(&mut self) {
+        self.next = Some(Instant::now() + self.delay);
+        self.delay = (self.delay * 2).min(self.max);
+    }
+
+    /// Record a successful attempt.
+    pub(crate) fn succeeded(&mut self) {
+        self.delay = self.min;
+        self.next = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn backoff() {
+        let mut b = Backoff::new(Duration::from_millis(20), Duration::from_millis(50));
+        assert!(b.ready());
+        b.failed();
+        assert!(!b.ready());
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(b.ready());
+        b.failed();
+        assert_eq!(b.delay, Duration::from_millis(50));
+        b.failed();
+        assert_eq!(b.delay, Duration::from_millis(50));
+        b.succeeded();
+        assert!(b.ready());
+        assert_eq!(b.delay, Duration::from_millis(20));
+    }
+
+    #[test]
+    fn connect_refused() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?.to_string();
+        connect(&addr, Duration::from_secs(1))?;
+        drop(listener);
+        assert!(connect(&addr, Duration::from_secs(1)).is_err());
+        Ok(())
+    }
+}
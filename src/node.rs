@@ -0,0 +1,502 @@
+/*! Blocks with ports, connected by the graph.
+
+Normally a block gets its input streams when it's created, and creates
+its output streams, so blocks have to be created in order, from source
+to sink. A [Node] instead declares the types of its input and output
+ports, and how to create the block once its inputs are known. Adding
+it to a graph returns [NodePorts], with a typed [InPort] or [OutPort]
+per port, and the graph then owns the wiring, with
+[Graph::connect]:
+
+```
+use rustradio::blocks::{FIRFilter, NullSink, VectorSource};
+use rustradio::graph::Graph;
+use rustradio::node::Node;
+use rustradio::Float;
+
+let mut g = Graph::new();
+let fir = g.add(Node::block(|src| FIRFilter::new(src, &[0.5, 0.5]), FIRFilter::out));
+let src = g.add(Node::source(VectorSource::new(vec![1.0 as Float; 10]), VectorSource::out));
+let sink = g.add(Node::sink(NullSink::<Float>::new));
+g.connect(src.output, fir.input)?;
+g.connect(fir.output, sink.input)?;
+g.run()?;
+# Ok::<(), anyhow::Error>(())
+```
+
+Blocks with several inputs or outputs have a tuple of ports, e.g.
+`tee.output.1`. For anything else, [Node::new] takes a closure that
+gets the input streams, and returns the output streams together with
+the block. The block is created as soon as all its inputs are
+connected, so nodes can be added and connected in any order.
+
+Connecting ports of different stream types doesn't compile:
+
+```compile_fail
+use rustradio::blocks::{NullSink, VectorSource};
+use rustradio::graph::Graph;
+use rustradio::node::Node;
+use rustradio::{Complex, Float};
+
+let mut g = Graph::new();
+let src = g.add(Node::source(VectorSource::new(vec![1.0 as Float]), VectorSource::out));
+let sink = g.add(Node::sink(NullSink::<Complex>::new));
+g.connect(src.output, sink.input)?;
+# Ok::<(), anyhow::Error>(())
+```
+
+Neither does connecting a port twice, since connecting consumes it:
+
+```compile_fail
+use rustradio::blocks::{NullSink, VectorSource};
+use rustradio::graph::Graph;
+use rustradio::node::Node;
+
+let mut g = Graph::new();
+let src = g.add(Node::source(VectorSource::new(vec![1u8]), VectorSource::out));
+let a = g.add(Node::sink(NullSink::<u8>::new));
+let b = g.add(Node::sink(NullSink::<u8>::new));
+g.connect(src.output, a.input)?;
+g.connect(src.output, b.input)?;
+# Ok::<(), anyhow::Error>(())
+```
+
+Ports left unconnected are an error when the graph starts. Nodes work
+the same in an [MTGraph](crate::mtgraph::MTGraph), each getting a
+thread of its own.
+
+[Graph::connect]: crate::graph::Graph::connect
+*/
+use std::any::Any;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::block::Block;
+use crate::stream::{NoCopyStreamp, Streamp};
+use crate::Error;
+
+type AnyPort = Box<dyn Any + Send>;
+
+/// A stream that can be a port of a [Node].
+pub trait Port: Send + 'static {}
+
+impl<T: 'static> Port for Streamp<T> where Streamp<T>: Send {}
+impl<T: 'static> Port for NoCopyStreamp<T> where NoCopyStreamp<T>: Send {}
+
+/// Output port `P` of a node in a graph. Connected, and used up, by
+/// [Graph::connect](crate::graph::Graph::connect).
+#[must_use]
+pub struct OutPort<P> {
+    at: At,
+    _p: PhantomData<fn() -> P>,
+}
+
+/// Input port `P` of a node in a graph. Connected, and used up, by
+/// [Graph::connect](crate::graph::Graph::connect).
+#[must_use]
+pub struct InPort<P> {
+    at: At,
+    _p: PhantomData<fn() -> P>,
+}
+
+// Graph, node, and port number of a port.
+#[derive(Debug, Clone, Copy)]
+struct At {
+    graph: usize,
+    node: usize,
+    port: usize,
+}
+
+/// The ports of a [Node]: none, one [Port], or a tuple of them.
+pub trait Ports: Sized + Send + 'static {
+    /// [InPort]s of these ports.
+    type In;
+
+    /// [OutPort]s of these ports.
+    type Out;
+
+    #[doc(hidden)]
+    const COUNT: usize;
+
+    #[doc(hidden)]
+    fn in_ports(graph: usize, node: usize) -> Self::In;
+
+    #[doc(hidden)]
+    fn out_ports(graph: usize, node: usize) -> Self::Out;
+
+    #[doc(hidden)]
+    fn from_any(v: Vec<AnyPort>) -> Self;
+
+    #[doc(hidden)]
+    fn into_any(self) -> Vec<AnyPort>;
+}
+
+// unwrap: connect() only takes ports of the same type.
+fn downcast<P: Port>(b: AnyPort) -> P {
+    *(b as Box<dyn Any>).downcast::<P>().unwrap()
+}
+
+fn in_port<P>(graph: usize, node: usize, port: usize) -> InPort<P> {
+    InPort {
+        at: At { graph, node, port },
+        _p: PhantomData,
+    }
+}
+
+fn out_port<P>(graph: usize, node: usize, port: usize) -> OutPort<P> {
+    OutPort {
+        at: At { graph, node, port },
+        _p: PhantomData,
+    }
+}
+
+impl Ports for () {
+    type In = ();
+    type Out = ();
+    const COUNT: usize = 0;
+    fn in_ports(_: usize, _: usize) {}
+    fn out_ports(_: usize, _: usize) {}
+    fn from_any(_: Vec<AnyPort>) -> Self {}
+    fn into_any(self) -> Vec<AnyPort> {
+        Vec::new()
+    }
+}
+
+macro_rules! single_port {
+    ($t:ident) => {
+        impl<T: 'static> Ports for $t<T>
+        where
+            $t<T>: Send,
+        {
+            type In = InPort<Self>;
+            type Out = OutPort<Self>;
+            const COUNT: usize = 1;
+            fn in_ports(graph: usize, node: usize) -> Self::In {
+                in_port(graph, node, 0)
+            }
+            fn out_ports(graph: usize, node: usize) -> Self::Out {
+                out_port(graph, node, 0)
+            }
+            fn from_any(v: Vec<AnyPort>) -> Self {
+                downcast(v.into_iter().next().unwrap())
+            }
+            fn into_any(self) -> Vec<AnyPort> {
+                vec![Box::new(self)]
+            }
+        }
+    };
+}
+single_port!(Streamp);
+single_port!(NoCopyStreamp);
+
+macro_rules! tuple_ports {
+    ($n:literal; $($p:ident $i:tt),+) => {
+        impl<$($p: Port),+> Ports for ($($p,)+) {
+            type In = ($(InPort<$p>,)+);
+            type Out = ($(OutPort<$p>,)+);
+            const COUNT: usize = $n;
+            fn in_ports(graph: usize, node: usize) -> Self::In {
+                ($(in_port(graph, node, $i),)+)
+            }
+            fn out_ports(graph: usize, node: usize) -> Self::Out {
+                ($(out_port(graph, node, $i),)+)
+            }
+            fn from_any(v: Vec<AnyPort>) -> Self {
+                let mut v = v.into_iter();
+                ($(downcast::<$p>(v.next().unwrap()),)+)
+            }
+            #[allow(non_snake_case)]
+            fn into_any(self) -> Vec<AnyPort> {
+                let ($($p,)+) = self;
+                vec![$(Box::new($p)),+]
+            }
+        }
+    };
+}
+tuple_ports!(1; A 0);
+tuple_ports!(2; A 0, B 1);
+tuple_ports!(3; A 0, B 1, C 2);
+tuple_ports!(4; A 0, B 1, C 2, D 3);
+
+type Build = Box<dyn FnOnce(Vec<AnyPort>) -> (Vec<AnyPort>, Box<dyn Block + Send>) + Send>;
+
+/// A block with typed ports, created once its inputs are connected.
+/// See the [module docs][crate::node].
+pub struct Node<I: Ports, O: Ports> {
+    name: &'static str,
+    build: Build,
+    _p: PhantomData<fn(I) -> O>,
+}
+
+impl<I: Ports, O: Ports> Node<I, O> {
+    /// Create a node. `build` gets the input streams, and returns the
+    /// output streams and the block.
+    pub fn new<B: Block + Send + 'static>(
+        build: impl FnOnce(I) -> (O, B) + Send + 'static,
+    ) -> Self {
+        Self {
+            name: std::any::type_name::<B>(),
+            build: Box::new(move |ins| {
+                let (outs, b) = build(I::from_any(ins));
+                (outs.into_any(), Box::new(b) as Box<dyn Block + Send>)
+            }),
+            _p: PhantomData,
+        }
+    }
+
+    /// Create a node from a block constructor, and the method getting
+    /// the block's outputs, like `Node::block(Tee::new, Tee::out)`.
+    pub fn block<B: Block + Send + 'static>(
+        new: impl FnOnce(I) -> B + Send + 'static,
+        out: impl FnOnce(&B) -> O + Send + 'static,
+    ) -> Self {
+        Self::new(move |ins| {
+            let b = new(ins);
+            (out(&b), b)
+        })
+    }
+}
+
+impl<I: Ports> Node<I, ()> {
+    /// Create a node without outputs from a block constructor, like
+    /// `Node::sink(NullSink::new)`.
+    pub fn sink<B: Block + Send + 'static>(new: impl FnOnce(I) -> B + Send + 'static) -> Self {
+        Self::new(move |ins| ((), new(ins)))
+    }
+}
+
+impl<O: Ports> Node<(), O> {
+    /// Create a node without inputs from an already created block.
+    pub fn source<B: Block + Send + 'static>(
+        b: B,
+        out: impl FnOnce(&B) -> O + Send + 'static,
+    ) -> Self {
+        Self::block(move |()| b, out)
+    }
+}
+
+/// The ports of a node added to a graph, to
+/// [connect](crate::graph::Graph::connect).
+///
+/// Each is an [InPort] or [OutPort], or a tuple of them for nodes with
+/// several.
+pub struct NodePorts<I: Ports, O: Ports> {
+    /// Input ports.
+    pub input: I::In,
+
+    /// Output ports.
+    pub output: O::Out,
+}
+
+// A node in the graph, and its connections.
+struct Slot {
+    name: &'static str,
+    // Until built.
+    build: Option<Build>,
+    // Node and port connected to each input.
+    inputs: Vec<Option<(usize, usize)>>,
+    // Output streams, once built, until taken by the node they're
+    // connected to.
+    outputs: Vec<Option<AnyPort>>,
+    connected: Vec<bool>,
+}
+
+/// The nodes of a graph, until they're all connected and built.
+pub(crate) struct Nodes {
+    // To tell ports of different graphs apart.
+    id: usize,
+    slots: Vec<Slot>,
+    checked: bool,
+}
+
+impl Nodes {
+    pub(crate) fn new() -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            slots: Vec::new(),
+            checked: true,
+        }
+    }
+
+    /// Add a node, returning its ports.
+    pub(crate) fn add<I: Ports, O: Ports>(&mut self, node: Node<I, O>) -> NodePorts<I, O> {
+        let n = self.slots.len();
+        self.slots.push(Slot {
+            name: node.name,
+            build: Some(node.build),
+            inputs: vec![None; I::COUNT],
+            outputs: Vec::new(),
+            connected: vec![false; O::COUNT],
+        });
+        self.checked = false;
+        NodePorts {
+            input: I::in_ports(self.id, n),
+            output: O::out_ports(self.id, n),
+        }
+    }
+
+    // Node `n`, for error messages.
+    fn describe(&self, n: usize) -> String {
+        format!("node {n} ({})", self.slots[n].name)
+    }
+
+    /// Connect an output to an input.
+    pub(crate) fn connect<P: Port>(
+        &mut self,
+        src: OutPort<P>,
+        dst: InPort<P>,
+    ) -> Result<(), Error> {
+        let (s, d) = (src.at, dst.at);
+        if s.graph != self.id || d.graph != self.id {
+            return Err(Error::BadParameter(
+                "port belongs to another graph".to_string(),
+            ));
+        }
+        self.slots[s.node].connected[s.port] = true;
+        self.slots[d.node].inputs[d.port] = Some((s.node, s.port));
+        Ok(())
+    }
+
+    /// Build the next node whose inputs are all available, if any.
+    pub(crate) fn build_next(&mut self) -> Option<Box<dyn Block + Send>> {
+        let slots = &mut self.slots;
+        let n = (0..slots.len()).find(|n| {
+            slots[*n].build.is_some()
+                && slots[*n]
+                    .inputs
+                    .iter()
+                    .all(|i| matches!(i, Some((u, p)) if slots[*u].outputs.get(*p).is_some_and(Option::is_some)))
+        })?;
+        let ins: Vec<_> = (0..slots[n].inputs.len())
+            .map(|i| {
+                let (u, p) = slots[n].inputs[i].unwrap();
+                slots[u].outputs[p].take().unwrap()
+            })
+            .collect();
+        let build = slots[n].build.take().unwrap();
+        let (outs, block) = build(ins);
+        slots[n].outputs = outs.into_iter().map(Some).collect();
+        Some(block)
+    }
+
+    /// Check that all ports are connected, so all nodes are built.
+    /// Only checks again if nodes were added since.
+    pub(crate) fn check(&mut self) -> Result<(), Error> {
+        if self.checked {
+            return Ok(());
+        }
+        for (n, s) in self.slots.iter().enumerate() {
+            if let Some(p) = s.inputs.iter().position(Option::is_none) {
+                return Err(Error::BadParameter(format!(
+                    "input {p} of {} is not connected",
+                    self.describe(n)
+                )));
+            }
+            if let Some(p) = s.connected.iter().position(|c| !c) {
+                return Err(Error::BadParameter(format!(
+                    "output {p} of {} is not connected",
+                    self.describe(n)
+                )));
+            }
+            if s.build.is_some() {
+                return Err(Error::BadParameter(format!(
+                    "{} is in a loop",
+                    self.describe(n)
+                )));
+            }
+        }
+        self.checked = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{Add, NullSink, Tee, VectorSink, VectorSource};
+    use crate::graph::Graph;
+    use crate::mtgraph::MTGraph;
+    use crate::Float;
+    use std::sync::{Arc, Mutex};
+
+    fn source() -> Node<(), Streamp<Float>> {
+        Node::source(
+            VectorSource::new(vec![1.0 as Float, 2.0, 3.0]),
+            VectorSource::out,
+        )
+    }
+
+    #[test]
+    fn any_order() -> crate::Result<()> {
+        let mut g = Graph::new();
+        let data = Arc::new(Mutex::new(None));
+        let d = data.clone();
+        let sink = g.add(Node::sink(move |src: Streamp<Float>| {
+            let b = VectorSink::new(src);
+            *d.lock().unwrap() = Some(b.handle());
+            b
+        }));
+        let add = g.add(Node::block(|(a, b)| Add::new(a, b), Add::out));
+        let tee = g.add(Node::block(Tee::new, Tee::out));
+        let src = g.add(source());
+        g.connect(add.output, sink.input)?;
+        g.connect(tee.output.1, add.input.1)?;
+        g.connect(tee.output.0, add.input.0)?;
+        assert!(data.lock().unwrap().is_none());
+        g.connect(src.output, tee.input)?;
+        g.run()?;
+        let data = data.lock().unwrap().take().unwrap().data();
+        assert_eq!(data, vec![2.0, 4.0, 6.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn other_graph() {
+        let mut g = Graph::new();
+        let mut g2 = Graph::new();
+        let src = g.add(source());
+        let sink = g2.add(Node::sink(NullSink::<Float>::new));
+        let e = g.connect(src.output, sink.input).unwrap_err().to_string();
+        assert!(e.contains("another graph"), "{e}");
+    }
+
+    #[test]
+    fn unconnected_input() {
+        let mut g = Graph::new();
+        let _sink = g.add(Node::sink(NullSink::<Float>::new));
+        let e = g.run().unwrap_err().to_string();
+        assert!(e.contains("input 0 of node 0"), "{e}");
+    }
+
+    #[test]
+    fn unconnected_output() -> crate::Result<()> {
+        let mut g = Graph::new();
+        let src = g.add(source());
+        let tee = g.add(Node::block(Tee::new, Tee::out));
+        let sink = g.add(Node::sink(NullSink::new));
+        g.connect(src.output, tee.input)?;
+        g.connect(tee.output.0, sink.input)?;
+        let e = g.run().unwrap_err().to_string();
+        assert!(e.contains("output 1 of node 1"), "{e}");
+        Ok(())
+    }
+
+    #[test]
+    fn mtgraph() -> crate::Result<()> {
+        let mut g = MTGraph::new();
+        let data = Arc::new(Mutex::new(None));
+        let d = data.clone();
+        let sink = g.add(Node::sink(move |src: Streamp<Float>| {
+            let b = VectorSink::new(src);
+            *d.lock().unwrap() = Some(b.handle());
+            b
+        }));
+        let src = g.add(source());
+        g.connect(src.output, sink.input)?;
+        g.run()?;
+        let data = data.lock().unwrap().take().unwrap().data();
+        assert_eq!(data, vec![1.0, 2.0, 3.0]);
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+/*! Gaussian noise source.
+
+White gaussian noise, as [Float] or [Complex]. The amplitude is the
+RMS value. For complex noise the power is split equally between I and
+Q, as with [ChannelModelBuilder::noise_voltage].
+
+The noise is pseudo random, from a fixed seed by default, so that
+tests are repeatable.
+
+```
+use rustradio::blocks::{Add, NoiseSource, SignalSource};
+use rustradio::Complex;
+let tone = SignalSource::<Complex>::new(48_000.0, 1000.0, 1.0);
+let noise = NoiseSource::<Complex>::new(0.1);
+// 20dB SNR.
+let noisy = Add::new(tone.out(), noise.out());
+```
+
+[ChannelModelBuilder::noise_voltage]: crate::channel_model::ChannelModelBuilder::noise_voltage
+*/
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, Streamp};
+use crate::{Complex, Error, Float};
+
+// xorshift64*, giving standard normal samples with Box-Muller.
+pub(crate) struct Gaussian {
+    state: u64,
+}
+
+impl Gaussian {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            // State must not be zero.
+            state: seed.max(1),
+        }
+    }
+
+    // Uniform in (0, 1].
+    fn uniform(&mut self) -> f64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let r = self.state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        ((r >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    // Two independent standard normal samples.
+    pub(crate) fn pair(&mut self) -> (Float, Float) {
+        let r = (-2.0 * self.uniform().ln()).sqrt();
+        let (s, c) = (2.0 * std::f64::consts::PI * self.uniform()).sin_cos();
+        ((r * c) as Float, (r * s) as Float)
+    }
+}
+
+/// Sample types [NoiseSource] can generate.
+pub trait NoiseType: Copy + Default {
+    /// Noise of RMS `amplitude`, from two independent standard
+    /// normal samples.
+    fn from_normal(a: Float, b: Float, amplitude: Float) -> Self;
+}
+
+impl NoiseType for Float {
+    fn from_normal(a: Float, _b: Float, amplitude: Float) -> Self {
+        a * amplitude
+    }
+}
+
+impl NoiseType for Complex {
+    fn from_normal(a: Float, b: Float, amplitude: Float) -> Self {
+        Complex::new(a, b) * (amplitude / (2.0 as Float).sqrt())
+    }
+}
+
+/// Builder for [NoiseSource].
+pub struct NoiseSourceBuilder<T> {
+    amplitude: Float,
+    seed: u64,
+    dummy: std::marker::PhantomData<T>,
+}
+
+impl<T: NoiseType> NoiseSourceBuilder<T> {
+    /// Create new builder, for noise of RMS `amplitude`.
+    pub fn new(amplitude: Float) -> Self {
+        Self {
+            amplitude,
+            seed: 1,
+            dummy: std::marker::PhantomData,
+        }
+    }
+
+    /// Set seed. Default 1.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Build NoiseSource block.
+    pub fn build(self) -> NoiseSource<T> {
+        NoiseSource {
+            dst: new_streamp(),
+            noise: Gaussian::new(self.seed),
+            amplitude: self.amplitude,
+        }
+    }
+}
+
+/// Gaussian noise source block. See the [module docs](crate::noise_source).
+pub struct NoiseSource<T: Copy> {
+    dst: Streamp<T>,
+    noise: Gaussian,
+    amplitude: Float,
+}
+
+impl<T: NoiseType> NoiseSource<T> {
+    /// Create new noise source, of RMS `amplitude`.
+    pub fn new(amplitude: Float) -> Self {
+        NoiseSourceBuilder::new(amplitude).build()
+    }
+
+    /// Create a builder.
+    pub fn builder(amplitude: Float) -> NoiseSourceBuilder<T> {
+        NoiseSourceBuilder::new(amplitude)
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<T> {
+        self.dst.clone()
+    }
+}
+
+impl<T: NoiseType> Block for NoiseSource<T> {
+    fn block_name(&self) -> &str {
+        "NoiseSource"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let mut o = self.dst.write_buf()?;
+        let n = o.len();
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        for d in o.slice().iter_mut() {
+            let (a, b) = self.noise.pair();
+            *d = T::from_normal(a, b, self.amplitude);
+        }
+        o.produce(n, &[]);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate<T: NoiseType>(b: NoiseSourceBuilder<T>) -> crate::Result<Vec<T>> {
+        let mut src = b.build();
+        src.work()?;
+        let o = src.out();
+        let (res, _) = o.read_buf()?;
+        Ok(res.slice().to_vec())
+    }
+
+    #[test]
+    fn float() -> crate::Result<()> {
+        let res = generate(NoiseSource::<Float>::builder(0.5))?;
+        let n = res.len() as Float;
+        assert!(n > 10_000.0);
+        let mean = res.iter().sum::<Float>() / n;
+        let power = res.iter().map(|s| s * s).sum::<Float>() / n;
+        assert!(mean.abs() < 0.02, "{mean}");
+        assert!((power - 0.25).abs() < 0.01, "{power}");
+        Ok(())
+    }
+
+    #[test]
+    fn complex() -> crate::Result<()> {
+        let res = generate(NoiseSource::<Complex>::builder(2.0))?;
+        let n = res.len() as Float;
+        let power_i = res.iter().map(|s| s.re * s.re).sum::<Float>() / n;
+        let power_q = res.iter().map(|s| s.im * s.im).sum::<Float>() / n;
+        assert!((power_i - 2.0).abs() < 0.1, "{power_i}");
+        assert!((power_q - 2.0).abs() < 0.1, "{power_q}");
+
+        // Repeatable, unless the seed changes.
+        let again = generate(NoiseSource::<Complex>::builder(2.0))?;
+        assert_eq!(again[..100], res[..100]);
+        let other = generate(NoiseSource::<Complex>::builder(2.0).seed(2))?;
+        assert_ne!(other[..100], res[..100]);
+        Ok(())
+    }
+}
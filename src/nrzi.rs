@@ -1,4 +1,4 @@
-/*! NRZI — Non return to zero
+/*! NRZI — Non return to zero inverted
 
 <https://en.wikipedia.org/wiki/Non-return-to-zero>
 
@@ -19,6 +19,31 @@ constant is one, because that's what done by AX.25, both 1200bps Bell
 use crate::map_block_convert_macro;
 use crate::stream::{new_streamp, Streamp};
 
+/// NRZI encoder.
+pub struct NrziEncode {
+    last: u8,
+    src: Streamp<u8>,
+    dst: Streamp<u8>,
+}
+
+impl NrziEncode {
+    /// Create a new NRZI encoder block.
+    pub fn new(src: Streamp<u8>) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            last: 0,
+        }
+    }
+
+    fn process_one(&mut self, a: u8) -> u8 {
+        // Toggle on zero.
+        self.last ^= 1 ^ a;
+        self.last
+    }
+}
+map_block_convert_macro![NrziEncode, u8];
+
 /// NRZI decoder.
 pub struct NrziDecode {
     last: u8,
@@ -43,3 +68,24 @@ impl NrziDecode {
     }
 }
 map_block_convert_macro![NrziDecode, u8];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::stream::streamp_from_slice;
+
+    #[test]
+    fn roundtrip() -> crate::Result<()> {
+        let bits = [1u8, 0, 0, 1, 1, 1, 0, 1, 0, 0];
+        let mut enc = NrziEncode::new(streamp_from_slice(&bits));
+        enc.work()?;
+        let o = enc.out();
+        assert_eq!(o.read_buf()?.0.slice(), &[0, 1, 0, 0, 0, 0, 1, 1, 0, 1]);
+        let mut dec = NrziDecode::new(enc.out());
+        dec.work()?;
+        let o = dec.out();
+        assert_eq!(o.read_buf()?.0.slice(), &bits);
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+/*! PCAP sink.
+
+Writes PDUs to a [pcap] file, so that decoded traffic can be inspected
+with Wireshark or tcpdump, instead of as one file per packet like
+[PduWriter][crate::pdu_writer::PduWriter].
+
+Timestamps are taken from the [TIME][crate::pdu::TIME] metadata of the
+PDU, if set (e.g. by [ToPdu][crate::pdu::ToPdu]), and otherwise from
+when the PDU reached the sink.
+
+```text
+HdlcDeframer -> ToPdu -> PcapSink
+```
+
+[pcap]: https://www.tcpdump.org/manpages/pcap-savefile.5.html
+*/
+use std::io::{BufWriter, Write};
+use std::time::SystemTime;
+
+use anyhow::Result;
+use log::{debug, info};
+
+use crate::block::{Block, BlockRet};
+use crate::pdu::Pdu;
+use crate::stream::NoCopyStreamp;
+use crate::Error;
+
+const MAGIC: u32 = 0xa1b2c3d4;
+const VERSION_MAJOR: u16 = 2;
+const VERSION_MINOR: u16 = 4;
+const SNAPLEN: u32 = 65535;
+
+/// Link type, as in the pcap header.
+///
+/// See <https://www.tcpdump.org/linktypes.html>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkType {
+    /// AX.25 frame, without FCS. This is what
+    /// [HdlcDeframer][crate::hdlc_deframer::HdlcDeframer] outputs.
+    Ax25,
+
+    /// AX.25 frame, prefixed with a one byte KISS header. The sink
+    /// adds the header, for KISS port 0.
+    Ax25Kiss,
+
+    /// Any other link type, data written as is.
+    Other(u32),
+}
+
+impl LinkType {
+    /// Link type number.
+    pub fn value(&self) -> u32 {
+        match self {
+            LinkType::Ax25 => 3,
+            LinkType::Ax25Kiss => 202,
+            LinkType::Other(n) => *n,
+        }
+    }
+}
+
+/// Encode the pcap file header.
+pub fn file_header(link: LinkType) -> Vec<u8> {
+    let mut v = Vec::with_capacity(24);
+    v.extend(MAGIC.to_le_bytes());
+    v.extend(VERSION_MAJOR.to_le_bytes());
+    v.extend(VERSION_MINOR.to_le_bytes());
+    v.extend(0i32.to_le_bytes()); // thiszone
+    v.extend(0u32.to_le_bytes()); // sigfigs
+    v.extend(SNAPLEN.to_le_bytes());
+    v.extend(link.value().to_le_bytes());
+    v
+}
+
+/// Encode a pcap record, with time in microseconds since epoch.
+pub fn record(link: LinkType, time_us: u64, data: &[u8]) -> Vec<u8> {
+    let kiss = link == LinkType::Ax25Kiss;
+    let len = data.len() + kiss as usize;
+    let mut v = Vec::with_capacity(16 + len);
+    v.extend(((time_us / 1_000_000) as u32).to_le_bytes());
+    v.extend(((time_us % 1_000_000) as u32).to_le_bytes());
+    v.extend((len.min(SNAPLEN as usize) as u32).to_le_bytes());
+    v.extend((len as u32).to_le_bytes());
+    if kiss {
+        v.push(0);
+    }
+    v.extend(&data[..data.len().min(SNAPLEN as usize - kiss as usize)]);
+    v
+}
+
+/** PCAP sink block.
+
+Takes PDUs, and writes them to a pcap file.
+*/
+pub struct PcapSink {
+    src: NoCopyStreamp<Pdu>,
+    f: BufWriter<std::fs::File>,
+    link: LinkType,
+    written: usize,
+}
+
+impl PcapSink {
+    /// Create new PcapSink, overwriting `filename`.
+    pub fn new(
+        src: NoCopyStreamp<Pdu>,
+        filename: std::path::PathBuf,
+        link: LinkType,
+    ) -> Result<Self> {
+        debug!("Opening pcap sink {}", filename.display());
+        let mut f = BufWriter::new(std::fs::File::create(filename)?);
+        f.write_all(&file_header(link))?;
+        f.flush()?;
+        Ok(Self {
+            src,
+            f,
+            link,
+            written: 0,
+        })
+    }
+}
+
+impl Drop for PcapSink {
+    fn drop(&mut self) {
+        info!("PcapSink: wrote {} packets", self.written);
+    }
+}
+
+impl Block for PcapSink {
+    fn block_name(&self) -> &str {
+        "PcapSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let Some((pdu, _tags)) = self.src.pop() else {
+            return Ok(BlockRet::Noop);
+        };
+        let time = pdu.time().unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_micros() as u64
+        });
+        self.f.write_all(&record(self.link, time, pdu.data()))?;
+        self.f.flush()?;
+        self.written += 1;
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdu::TIME;
+    use crate::stream::{new_nocopy_streamp, TagValue};
+
+    #[test]
+    fn header() {
+        let h = file_header(LinkType::Ax25);
+        assert_eq!(h.len(), 24);
+        assert_eq!(&h[..4], &[0xd4, 0xc3, 0xb2, 0xa1]);
+        assert_eq!(&h[20..], &[3, 0, 0, 0]);
+        assert_eq!(&file_header(LinkType::Other(147))[20..], &[147, 0, 0, 0]);
+    }
+
+    #[test]
+    fn write() -> Result<()> {
+        let tmpd = tempfile::tempdir()?;
+        let path = tmpd.path().join("out.pcap");
+        let src = new_nocopy_streamp();
+        src.push(
+            Pdu::new(vec![1, 2, 3]).with(TIME, TagValue::U64(1_700_000_000_123_456)),
+            &[],
+        );
+        {
+            let mut sink = PcapSink::new(src, path.clone(), LinkType::Ax25Kiss)?;
+            sink.work()?;
+        }
+        let got = std::fs::read(path)?;
+        assert_eq!(got.len(), 24 + 16 + 4);
+        let rec = &got[24..];
+        assert_eq!(u32::from_le_bytes(rec[0..4].try_into()?), 1_700_000_000);
+        assert_eq!(u32::from_le_bytes(rec[4..8].try_into()?), 123_456);
+        assert_eq!(u32::from_le_bytes(rec[8..12].try_into()?), 4);
+        assert_eq!(u32::from_le_bytes(rec[12..16].try_into()?), 4);
+        assert_eq!(&rec[16..], &[0, 1, 2, 3]);
+        Ok(())
+    }
+}
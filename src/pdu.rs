@@ -0,0 +1,321 @@
+/*! PDU with metadata.
+
+A [Pdu] is a packet of bytes together with key/value metadata, such as
+receive time, frequency, or SNR. Unlike tags on a [NoCopyStream],
+which most blocks don't pass on, the metadata stays with the packet as
+it's passed between blocks.
+
+[ToPdu] turns a stream of `Vec<u8>`, such as from
+[HdlcDeframer][crate::hdlc_deframer::HdlcDeframer], into PDUs, adding
+the receive time and any fixed metadata. [PduData] goes the other way,
+for blocks that take plain `Vec<u8>`, such as
+[PduWriter][crate::pdu_writer::PduWriter].
+
+Dropped PDUs give their buffer back to the [PduPool].
+
+```text
+HdlcDeframer -> ToPdu -> parser -> sink
+```
+
+[NoCopyStream]: crate::stream::NoCopyStream
+*/
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use crate::block::{Block, BlockRet};
+use crate::pdu_pool::PduPool;
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp, Tag, TagValue};
+use crate::{Error, Len};
+
+/// Metadata key for receive time, as microseconds since epoch (U64).
+pub const TIME: &str = "time";
+
+/// Metadata key for frequency, in Hz (Float).
+pub const FREQUENCY: &str = "frequency";
+
+/// Metadata key for signal to noise ratio, in dB (Float).
+pub const SNR: &str = "snr";
+
+/// Packet of bytes with metadata.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Pdu {
+    data: Vec<u8>,
+    meta: BTreeMap<String, TagValue>,
+}
+
+impl Pdu {
+    /// Create new PDU without metadata.
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            meta: BTreeMap::new(),
+        }
+    }
+
+    /// Create new PDU with metadata from tags.
+    ///
+    /// Tag positions are ignored. If a key is repeated, the last tag
+    /// wins.
+    pub fn from_tags(data: Vec<u8>, tags: &[Tag]) -> Self {
+        let mut ret = Self::new(data);
+        for tag in tags {
+            ret.set(tag.key(), tag.val().clone());
+        }
+        ret
+    }
+
+    /// Add metadata, builder style.
+    pub fn with(mut self, key: &str, val: TagValue) -> Self {
+        self.set(key, val);
+        self
+    }
+
+    /// Set metadata, replacing any previous value.
+    pub fn set(&mut self, key: &str, val: TagValue) {
+        self.meta.insert(key.to_string(), val);
+    }
+
+    /// Get metadata.
+    pub fn get(&self, key: &str) -> Option<&TagValue> {
+        self.meta.get(key)
+    }
+
+    /// Remove metadata, returning the old value.
+    pub fn remove(&mut self, key: &str) -> Option<TagValue> {
+        self.meta.remove(key)
+    }
+
+    /// Iterate over metadata, sorted by key.
+    pub fn meta(&self) -> impl Iterator<Item = (&str, &TagValue)> {
+        self.meta.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Metadata as tags, all at position 0.
+    pub fn tags(&self) -> Vec<Tag> {
+        self.meta
+            .iter()
+            .map(|(k, v)| Tag::new(0, k.clone(), v.clone()))
+            .collect()
+    }
+
+    /// Get the data.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the data, mutably.
+    pub fn data_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.data
+    }
+
+    /// Take the data, dropping the metadata.
+    pub fn into_data(mut self) -> Vec<u8> {
+        std::mem::take(&mut self.data)
+    }
+
+    /// Encode as JSON, with the data as hex in `data`, and the metadata
+    /// as other fields.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        let mut m = serde_json::Map::new();
+        for (k, v) in &self.meta {
+            let v = match v {
+                TagValue::String(s) => serde_json::Value::from(s.clone()),
+                TagValue::Float(f) => serde_json::Value::from(*f),
+                TagValue::Bool(b) => serde_json::Value::from(*b),
+                TagValue::U64(n) => serde_json::Value::from(*n),
+            };
+            m.insert(k.clone(), v);
+        }
+        let hex: String = self.data.iter().map(|b| format!("{b:02x}")).collect();
+        m.insert("data".to_string(), hex.into());
+        serde_json::Value::Object(m).to_string()
+    }
+
+    /// Receive time, if set.
+    pub fn time(&self) -> Option<u64> {
+        match self.get(TIME)? {
+            TagValue::U64(t) => Some(*t),
+            _ => None,
+        }
+    }
+}
+
+// Give the buffer back, for the next packet.
+impl Drop for Pdu {
+    fn drop(&mut self) {
+        PduPool::global().put(std::mem::take(&mut self.data));
+    }
+}
+
+impl From<Vec<u8>> for Pdu {
+    fn from(data: Vec<u8>) -> Self {
+        Self::new(data)
+    }
+}
+
+impl Len for Pdu {
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_micros() as u64
+}
+
+/** Turn `Vec<u8>` into [Pdu].
+
+Sets [TIME] to when the PDU passed through this block, unless already
+set by a tag, and adds any metadata set with [ToPdu::set_meta].
+*/
+pub struct ToPdu {
+    src: NoCopyStreamp<Vec<u8>>,
+    dst: NoCopyStreamp<Pdu>,
+    meta: Vec<(String, TagValue)>,
+    timestamp: bool,
+}
+
+impl ToPdu {
+    /// Create new ToPdu block.
+    pub fn new(src: NoCopyStreamp<Vec<u8>>) -> Self {
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            meta: Vec::new(),
+            timestamp: true,
+        }
+    }
+
+    /// Add metadata to every PDU, e.g. the receive frequency.
+    pub fn set_meta(&mut self, key: &str, val: TagValue) {
+        self.meta.retain(|(k, _)| k != key);
+        self.meta.push((key.to_string(), val));
+    }
+
+    /// Enable or disable setting [TIME]. Default enabled.
+    pub fn set_timestamp(&mut self, timestamp: bool) {
+        self.timestamp = timestamp;
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<Pdu> {
+        self.dst.clone()
+    }
+}
+
+impl Block for ToPdu {
+    fn block_name(&self) -> &str {
+        "ToPdu"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let Some((data, tags)) = self.src.pop() else {
+            return Ok(BlockRet::Noop);
+        };
+        let mut pdu = Pdu::from_tags(data, &tags);
+        for (k, v) in &self.meta {
+            pdu.set(k, v.clone());
+        }
+        if self.timestamp && pdu.get(TIME).is_none() {
+            pdu.set(TIME, TagValue::U64(now_micros()));
+        }
+        self.dst.push(pdu, &[]);
+        Ok(BlockRet::Ok)
+    }
+}
+
+/// Turn [Pdu] into `Vec<u8>`, pushing the metadata as tags.
+pub struct PduData {
+    src: NoCopyStreamp<Pdu>,
+    dst: NoCopyStreamp<Vec<u8>>,
+}
+
+impl PduData {
+    /// Create new PduData block.
+    pub fn new(src: NoCopyStreamp<Pdu>) -> Self {
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<Vec<u8>> {
+        self.dst.clone()
+    }
+}
+
+impl Block for PduData {
+    fn block_name(&self) -> &str {
+        "PduData"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let Some((pdu, _tags)) = self.src.pop() else {
+            return Ok(BlockRet::Noop);
+        };
+        let tags = pdu.tags();
+        self.dst.push(pdu.into_data(), &tags);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meta() {
+        let mut pdu = Pdu::new(vec![1, 2, 3])
+            .with(FREQUENCY, TagValue::Float(144.8e6))
+            .with(SNR, TagValue::Float(12.0));
+        assert_eq!(pdu.len(), 3);
+        assert_eq!(pdu.get(SNR), Some(&TagValue::Float(12.0)));
+        pdu.set(SNR, TagValue::Float(10.0));
+        let keys: Vec<_> = pdu.meta().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![FREQUENCY, SNR]);
+        assert_eq!(pdu.remove(SNR), Some(TagValue::Float(10.0)));
+        assert_eq!(pdu.time(), None);
+        #[cfg(feature = "json")]
+        assert_eq!(
+            pdu.to_json(),
+            r#"{"data":"010203","frequency":144800000.0}"#
+        );
+
+        let tags = pdu.tags();
+        let back = Pdu::from_tags(pdu.data().to_vec(), &tags);
+        assert_eq!(back, pdu);
+        assert_eq!(back.into_data(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn blocks() -> anyhow::Result<()> {
+        let src = new_nocopy_streamp();
+        src.push(
+            vec![0x55u8, 0xaa],
+            &[Tag::new(0, "packet_pos".into(), TagValue::U64(7))],
+        );
+        let mut to = ToPdu::new(src.clone());
+        to.set_meta(FREQUENCY, TagValue::Float(1.0));
+        to.set_meta(FREQUENCY, TagValue::Float(433.92e6));
+        assert!(matches![to.work()?, BlockRet::Ok]);
+        assert!(matches![to.work()?, BlockRet::Noop]);
+
+        let (pdu, _) = to.out().pop().unwrap();
+        assert_eq!(pdu.data(), &[0x55, 0xaa]);
+        assert_eq!(pdu.get(FREQUENCY), Some(&TagValue::Float(433.92e6)));
+        assert!(pdu.time().unwrap() > 0);
+        assert_eq!(pdu.get("packet_pos"), Some(&TagValue::U64(7)));
+
+        let pdus = new_nocopy_streamp();
+        pdus.push(pdu, &[]);
+        let mut data = PduData::new(pdus);
+        data.work()?;
+        let (data, tags) = data.out().pop().unwrap();
+        assert_eq!(data, vec![0x55, 0xaa]);
+        assert!(tags.iter().any(|t| t.key() == "packet_pos"));
+        Ok(())
+    }
+}
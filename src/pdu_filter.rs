@@ -0,0 +1,189 @@
+/*! PDU filter and deduplication.
+
+Drops PDUs that don't match a filter, and PDUs that are duplicates of
+one seen recently. E.g. an APRS digipeater or igate hears the same
+packet both directly and via one or more digipeaters, and should only
+act on it once.
+
+By default all PDUs pass, and duplicates are detected on the whole PDU
+data. [PduFilter::set_key] can be used to ignore parts of the PDU,
+such as the AX.25 digipeater path, which changes with each hop.
+
+Time is taken from the [TIME][crate::pdu::TIME] metadata, if set, and
+otherwise from when the PDU reached the block.
+*/
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use log::{debug, info};
+
+use crate::block::{Block, BlockRet};
+use crate::pdu::Pdu;
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp};
+use crate::Error;
+
+/// Filter function. Return true to keep the PDU.
+pub type FilterFn = Box<dyn Fn(&Pdu) -> bool + Send>;
+
+/// Deduplication key function.
+pub type KeyFn = Box<dyn Fn(&Pdu) -> Vec<u8> + Send>;
+
+/// PDU filter block.
+pub struct PduFilter {
+    src: NoCopyStreamp<Pdu>,
+    dst: NoCopyStreamp<Pdu>,
+    filters: Vec<FilterFn>,
+    key: KeyFn,
+    window: Option<Duration>,
+    seen: HashMap<Vec<u8>, u64>,
+    passed: usize,
+    filtered: usize,
+    duplicates: usize,
+}
+
+impl PduFilter {
+    /// Create new PDU filter, passing everything.
+    pub fn new(src: NoCopyStreamp<Pdu>) -> Self {
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            filters: Vec::new(),
+            key: Box::new(|pdu| pdu.data().to_vec()),
+            window: None,
+            seen: HashMap::new(),
+            passed: 0,
+            filtered: 0,
+            duplicates: 0,
+        }
+    }
+
+    /// Add a filter. PDUs must pass all filters.
+    pub fn add_filter(&mut self, f: FilterFn) {
+        self.filters.push(f);
+    }
+
+    /// Add a filter only passing PDUs starting with `prefix`.
+    pub fn add_prefix(&mut self, prefix: Vec<u8>) {
+        self.add_filter(Box::new(move |pdu| pdu.data().starts_with(&prefix)));
+    }
+
+    /// Drop PDUs with the same key as one passed within `window`.
+    /// Default disabled.
+    pub fn set_dedup_window(&mut self, window: Option<Duration>) {
+        self.window = window;
+    }
+
+    /// Set the deduplication key function.
+    pub fn set_key(&mut self, key: KeyFn) {
+        self.key = key;
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<Pdu> {
+        self.dst.clone()
+    }
+
+    fn keep(&mut self, pdu: &Pdu) -> bool {
+        if !self.filters.iter().all(|f| f(pdu)) {
+            self.filtered += 1;
+            return false;
+        }
+        let Some(window) = self.window else {
+            return true;
+        };
+        let now = pdu.time().unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_micros() as u64
+        });
+        let window = window.as_micros() as u64;
+        self.seen.retain(|_, t| now.saturating_sub(*t) < window);
+        let key = (self.key)(pdu);
+        if self.seen.contains_key(&key) {
+            debug!("PduFilter: dropping duplicate");
+            self.duplicates += 1;
+            return false;
+        }
+        self.seen.insert(key, now);
+        true
+    }
+}
+
+impl Drop for PduFilter {
+    fn drop(&mut self) {
+        info!(
+            "PduFilter: passed {}, filtered {}, duplicates {}",
+            self.passed, self.filtered, self.duplicates
+        );
+    }
+}
+
+impl Block for PduFilter {
+    fn block_name(&self) -> &str {
+        "PduFilter"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let Some((pdu, tags)) = self.src.pop() else {
+            return Ok(BlockRet::Noop);
+        };
+        if self.keep(&pdu) {
+            self.passed += 1;
+            self.dst.push(pdu, &tags);
+        }
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdu::TIME;
+    use crate::stream::TagValue;
+
+    fn run(b: &mut PduFilter, pdus: &[(&[u8], u64)]) -> anyhow::Result<Vec<Vec<u8>>> {
+        for (data, t) in pdus {
+            let pdu = Pdu::new(data.to_vec()).with(TIME, TagValue::U64(*t));
+            b.src.push(pdu, &[]);
+            b.work()?;
+        }
+        let mut ret = Vec::new();
+        while let Some((pdu, _)) = b.out().pop() {
+            ret.push(pdu.into_data());
+        }
+        Ok(ret)
+    }
+
+    #[test]
+    fn filter() -> anyhow::Result<()> {
+        let mut b = PduFilter::new(new_nocopy_streamp());
+        b.add_prefix(vec![1]);
+        b.add_filter(Box::new(|pdu| pdu.data().len() < 3));
+        let got = run(
+            &mut b,
+            &[(&[1, 2], 0), (&[2, 2], 0), (&[1, 2, 3], 0), (&[1], 0)],
+        )?;
+        assert_eq!(got, vec![vec![1, 2], vec![1]]);
+        Ok(())
+    }
+
+    #[test]
+    fn dedup() -> anyhow::Result<()> {
+        let mut b = PduFilter::new(new_nocopy_streamp());
+        b.set_dedup_window(Some(Duration::from_secs(30)));
+        // Ignore the first byte, like a digipeater path.
+        b.set_key(Box::new(|pdu| pdu.data()[1..].to_vec()));
+        let s = 1_000_000;
+        let got = run(
+            &mut b,
+            &[
+                (&[1, 9], 0),
+                (&[2, 9], 10 * s),
+                (&[3, 8], 11 * s),
+                (&[4, 9], 31 * s),
+            ],
+        )?;
+        assert_eq!(got, vec![vec![1, 9], vec![3, 8], vec![4, 9]]);
+        Ok(())
+    }
+}
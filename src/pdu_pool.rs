@@ -0,0 +1,153 @@
+/*! Pool of reusable packet buffers.
+
+Packet decoders allocate a buffer per packet, and in noise also per
+false start. Over days of running, e.g. as an igate, that both keeps
+the allocator busy and fragments the heap. Instead, buffers can be
+taken from a [PduPool], and given back when done.
+
+[Pdu](crate::pdu::Pdu) gives its buffer back to the
+[global](PduPool::global) pool when dropped, and
+[HdlcDeframer](crate::hdlc_deframer::HdlcDeframer) takes its buffers
+from there, so a graph like this reuses the same few buffers:
+
+```text
+HdlcDeframer -> ToPdu -> UdpSink
+```
+
+```
+use rustradio::pdu_pool::PduPool;
+let pool = PduPool::new(16, 1024);
+let mut buf = pool.get(100);
+buf.extend_from_slice(b"hello");
+pool.put(buf);
+assert!(pool.get(10).capacity() >= 100);
+```
+*/
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+static GLOBAL: PduPool = PduPool::new(256, 64 * 1024);
+
+/// Counters for a [PduPool].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PduPoolStats {
+    /// Buffers handed out that had to be allocated.
+    pub allocated: u64,
+
+    /// Buffers handed out that were reused.
+    pub reused: u64,
+
+    /// Buffers currently in the pool.
+    pub free: usize,
+}
+
+/// Pool of reusable byte buffers. See the [module
+/// docs](crate::pdu_pool).
+pub struct PduPool {
+    free: Mutex<Vec<Vec<u8>>>,
+    max_buffers: usize,
+    max_capacity: usize,
+    allocated: AtomicU64,
+    reused: AtomicU64,
+}
+
+impl PduPool {
+    /// Create new pool, keeping at most `max_buffers` buffers.
+    ///
+    /// Buffers that have grown beyond `max_capacity` bytes are freed
+    /// instead of kept, so that one huge packet doesn't pin memory
+    /// forever.
+    pub const fn new(max_buffers: usize, max_capacity: usize) -> Self {
+        Self {
+            free: Mutex::new(Vec::new()),
+            max_buffers,
+            max_capacity,
+            allocated: AtomicU64::new(0),
+            reused: AtomicU64::new(0),
+        }
+    }
+
+    /// The pool used by the PDU blocks.
+    pub fn global() -> &'static PduPool {
+        &GLOBAL
+    }
+
+    /// Get an empty buffer, with room for at least `capacity` bytes.
+    pub fn get(&self, capacity: usize) -> Vec<u8> {
+        let buf = self.free.lock().unwrap().pop();
+        match buf {
+            Some(mut buf) => {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                buf.reserve(capacity);
+                buf
+            }
+            None => {
+                self.allocated.fetch_add(1, Ordering::Relaxed);
+                Vec::with_capacity(capacity)
+            }
+        }
+    }
+
+    /// Get a buffer holding a copy of `data`.
+    pub fn copy_from(&self, data: &[u8]) -> Vec<u8> {
+        let mut buf = self.get(data.len());
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    /// Give a buffer back to the pool.
+    ///
+    /// It's freed instead if the pool is full, or the buffer is too
+    /// big or has no capacity.
+    pub fn put(&self, mut buf: Vec<u8>) {
+        if buf.capacity() == 0 || buf.capacity() > self.max_capacity {
+            return;
+        }
+        let mut free = self.free.lock().unwrap();
+        if free.len() < self.max_buffers {
+            buf.clear();
+            free.push(buf);
+        }
+    }
+
+    /// Get counters.
+    pub fn stats(&self) -> PduPoolStats {
+        PduPoolStats {
+            allocated: self.allocated.load(Ordering::Relaxed),
+            reused: self.reused.load(Ordering::Relaxed),
+            free: self.free.lock().unwrap().len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuse() {
+        let pool = PduPool::new(2, 100);
+        let a = pool.copy_from(&[1, 2, 3]);
+        let b = pool.get(200);
+        let c = pool.get(10);
+        assert_eq!(a, vec![1, 2, 3]);
+        pool.put(a);
+        // Too big.
+        pool.put(b);
+        pool.put(c);
+        // Pool full.
+        pool.put(Vec::with_capacity(10));
+        assert_eq!(
+            pool.stats(),
+            PduPoolStats {
+                allocated: 3,
+                reused: 0,
+                free: 2
+            }
+        );
+        let d = pool.get(50);
+        assert!(d.is_empty());
+        assert!(d.capacity() >= 50);
+        assert_eq!(pool.stats().reused, 1);
+    }
+}
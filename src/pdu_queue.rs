@@ -0,0 +1,301 @@
+/*! Bounded PDU queue with optional rate limit.
+
+Protects slow sinks, like network uploads or disk, from bursts of
+PDUs. The queue holds at most `depth` PDUs, and what happens when it's
+full is decided by the [Overflow] policy.
+
+PDUs are only released while the output stream holds fewer than
+[`out_depth`](PduQueue::set_out_depth) PDUs, default 1. So if the sink
+can't keep up, the backlog builds up here, where it's bounded, and not
+in the output stream.
+
+With a rate limit, PDUs are released using a token bucket: up to
+`burst` PDUs can go out back to back, after which they're spaced out to
+`rate` per second.
+
+```text
+HdlcDeframer -> ToPdu -> PduQueue -> AprsIsSink
+```
+*/
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use log::{debug, info};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp, Tag};
+use crate::{Error, Float};
+
+/// What to do when the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Drop the oldest queued PDU, to make room for the new one.
+    #[default]
+    DropOldest,
+
+    /// Drop the new PDU.
+    DropNewest,
+
+    /// Leave new PDUs in the input stream until there's room.
+    Block,
+}
+
+struct RateLimit {
+    rate: Float,
+    burst: Float,
+    tokens: Float,
+    last: Instant,
+}
+
+impl RateLimit {
+    fn take(&mut self, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.last).as_secs_f64() as Float;
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Bounded PDU queue block.
+pub struct PduQueue<T> {
+    src: NoCopyStreamp<T>,
+    dst: NoCopyStreamp<T>,
+    queue: VecDeque<(T, Vec<Tag>)>,
+    depth: usize,
+    overflow: Overflow,
+    limit: Option<RateLimit>,
+    out_depth: usize,
+    dropped: usize,
+}
+
+impl<T> PduQueue<T> {
+    /// Create new queue holding up to `depth` PDUs.
+    pub fn new(src: NoCopyStreamp<T>, depth: usize, overflow: Overflow) -> Self {
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            queue: VecDeque::with_capacity(depth),
+            depth: depth.max(1),
+            overflow,
+            limit: None,
+            out_depth: 1,
+            dropped: 0,
+        }
+    }
+
+    /// Limit output to `rate` PDUs per second, with bursts of up to
+    /// `burst` PDUs. Default unlimited.
+    pub fn set_rate_limit(&mut self, rate: Float, burst: usize) {
+        let burst = burst.max(1) as Float;
+        self.limit = Some(RateLimit {
+            rate,
+            burst,
+            tokens: burst,
+            last: Instant::now(),
+        });
+    }
+
+    /// Only release PDUs while the output stream holds fewer than
+    /// `depth` PDUs. Default 1.
+    pub fn set_out_depth(&mut self, depth: usize) {
+        self.out_depth = depth.max(1);
+    }
+
+    /// Number of PDUs currently queued.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Return true if nothing is queued.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Number of PDUs dropped.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<T> {
+        self.dst.clone()
+    }
+
+    fn fill(&mut self) {
+        loop {
+            if self.queue.len() >= self.depth && self.overflow == Overflow::Block {
+                return;
+            }
+            let Some(item) = self.src.pop() else {
+                return;
+            };
+            if self.queue.len() >= self.depth {
+                self.dropped += 1;
+                match self.overflow {
+                    Overflow::DropOldest => {
+                        debug!("PduQueue: full, dropping oldest");
+                        self.queue.pop_front();
+                    }
+                    Overflow::DropNewest => {
+                        debug!("PduQueue: full, dropping newest");
+                        continue;
+                    }
+                    Overflow::Block => unreachable!(),
+                }
+            }
+            self.queue.push_back(item);
+        }
+    }
+}
+
+impl<T> Drop for PduQueue<T> {
+    fn drop(&mut self) {
+        info!(
+            "PduQueue: dropped {}, {} still queued",
+            self.dropped,
+            self.queue.len()
+        );
+    }
+}
+
+impl<T> Block for PduQueue<T> {
+    fn block_name(&self) -> &str {
+        "PduQueue"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        self.fill();
+        if self.queue.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let now = Instant::now();
+        let mut sent = false;
+        while !self.queue.is_empty() && self.dst.len() < self.out_depth {
+            if let Some(limit) = &mut self.limit {
+                if !limit.take(now) {
+                    break;
+                }
+            }
+            let (item, tags) = self.queue.pop_front().unwrap();
+            self.dst.push(item, &tags);
+            sent = true;
+        }
+        self.fill();
+        Ok(if !self.queue.is_empty() {
+            // Rate limited or sink busy, more to send later.
+            BlockRet::Pending
+        } else if sent {
+            BlockRet::Ok
+        } else {
+            BlockRet::Noop
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain<T>(s: &NoCopyStreamp<T>) -> Vec<T> {
+        let mut ret = Vec::new();
+        while let Some((x, _)) = s.pop() {
+            ret.push(x);
+        }
+        ret
+    }
+
+    #[test]
+    fn overflow() -> anyhow::Result<()> {
+        for (overflow, out, queued, left) in [
+            (Overflow::DropOldest, vec![2], vec![3, 4], 0),
+            (Overflow::DropNewest, vec![0], vec![1, 2], 0),
+            (Overflow::Block, vec![0], vec![1, 2, 3], 1),
+        ] {
+            let src = new_nocopy_streamp();
+            let mut q = PduQueue::new(src.clone(), 3, overflow);
+            q.set_rate_limit(0.0, 1);
+            for n in 0..5 {
+                src.push(n, &[]);
+            }
+            // One goes out immediately, then the rate limit holds.
+            assert!(matches![q.work()?, BlockRet::Pending]);
+            assert_eq!(drain(&q.out()), out, "{overflow:?}");
+            let got: Vec<_> = q.queue.iter().map(|(x, _)| *x).collect();
+            assert_eq!(got, queued, "{overflow:?}");
+            assert_eq!(drain(&src).len(), left, "{overflow:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn stalled_sink() -> anyhow::Result<()> {
+        for (overflow, out, queued, left, dropped) in [
+            (Overflow::DropOldest, 7, vec![8, 9], 0, 7),
+            (Overflow::DropNewest, 0, vec![1, 2], 0, 7),
+            (Overflow::Block, 0, vec![1, 2, 3], 6, 0),
+        ] {
+            let src = new_nocopy_streamp();
+            let mut q = PduQueue::new(src.clone(), 3, overflow);
+            for n in 0..10 {
+                src.push(n, &[]);
+            }
+            // Nobody reads the output, so only one PDU is released.
+            assert!(matches![q.work()?, BlockRet::Pending]);
+            assert!(matches![q.work()?, BlockRet::Pending]);
+            assert_eq!(q.out().len(), 1, "{overflow:?}");
+            let got: Vec<_> = q.queue.iter().map(|(x, _)| *x).collect();
+            assert_eq!(got, queued, "{overflow:?}");
+            assert_eq!(src.len(), left, "{overflow:?}");
+            assert_eq!(q.dropped(), dropped, "{overflow:?}");
+
+            // Sink catches up.
+            let mut got = drain(&q.out());
+            while !q.is_empty() || !src.is_empty() {
+                q.work()?;
+                got.extend(drain(&q.out()));
+            }
+            assert_eq!(got[0], out, "{overflow:?}");
+            assert_eq!(got.len(), 10 - dropped, "{overflow:?}");
+        }
+
+        // Bigger output depth.
+        let src = new_nocopy_streamp();
+        let mut q = PduQueue::new(src.clone(), 3, Overflow::Block);
+        q.set_out_depth(4);
+        for n in 0..10 {
+            src.push(n, &[]);
+        }
+        q.work()?;
+        q.work()?;
+        q.work()?;
+        assert_eq!(drain(&q.out()), vec![0, 1, 2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn rate() -> anyhow::Result<()> {
+        let src = new_nocopy_streamp();
+        let mut q = PduQueue::new(src.clone(), 100, Overflow::Block);
+        q.set_rate_limit(1000.0, 2);
+        q.set_out_depth(10);
+        for n in 0..10 {
+            src.push(n, &[]);
+        }
+        q.work()?;
+        assert_eq!(drain(&q.out()), vec![0, 1]);
+        let start = Instant::now();
+        let mut got = Vec::new();
+        while got.len() < 8 {
+            q.work()?;
+            got.extend(drain(&q.out()));
+        }
+        assert_eq!(got, (2..10).collect::<Vec<_>>());
+        assert!(start.elapsed().as_secs_f32() > 0.005);
+        assert!(matches![q.work()?, BlockRet::Noop]);
+        Ok(())
+    }
+}
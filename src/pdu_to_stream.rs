@@ -0,0 +1,127 @@
+/*! PDU to tagged stream.
+
+The reverse of [StreamToPdu][crate::stream_to_pdu::StreamToPdu]. Each
+PDU is written to the stream with padding before and after, and tagged
+like [BurstTagger][crate::burst_tagger::BurstTagger] does: `true` on
+the first sample of the PDU, and `false` on the first sample after it.
+
+```text
+[ head padding ][ PDU ][ tail padding ]
+                ^      ^
+                true   false
+```
+
+This is useful for burst mode transmitters, where the modulator needs
+some silence around each packet, and for feeding PDUs back into blocks
+that expect a tagged stream.
+*/
+use log::debug;
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, NoCopyStreamp, Streamp, Tag, TagValue};
+use crate::Error;
+
+/// PDU to tagged stream block.
+pub struct PduToStream<T> {
+    src: NoCopyStreamp<Vec<T>>,
+    dst: Streamp<T>,
+    tag: String,
+    head: usize,
+    tail: usize,
+}
+
+impl<T> PduToStream<T> {
+    /// Create new PduToStream.
+    ///
+    /// `head` and `tail` are the number of padding samples before and
+    /// after each PDU. `tail` is at least 1, since the end tag is on
+    /// the first sample after the PDU.
+    pub fn new(src: NoCopyStreamp<Vec<T>>, tag: String, head: usize, tail: usize) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            tag,
+            head,
+            tail: tail.max(1),
+        }
+    }
+
+    /// Return output stream.
+    pub fn out(&self) -> Streamp<T> {
+        self.dst.clone()
+    }
+}
+
+impl<T: Copy + Default> Block for PduToStream<T> {
+    fn block_name(&self) -> &str {
+        "PduToStream"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let Some(size) = self.src.peek_size() else {
+            return Ok(BlockRet::Noop);
+        };
+        let n = self.head + size + self.tail;
+        let mut o = self.dst.write_buf()?;
+        if n > o.len() {
+            return Ok(BlockRet::Ok);
+        }
+        let (v, _tags) = self
+            .src
+            .pop()
+            .expect("we just checked the size. It must exist");
+        debug!("PduToStream: writing PDU of {size} samples");
+        o.fill_from_iter(
+            std::iter::repeat_n(T::default(), self.head)
+                .chain(v)
+                .chain(std::iter::repeat_n(T::default(), self.tail)),
+        );
+        o.produce(
+            n,
+            &[
+                Tag::new(self.head, self.tag.clone(), TagValue::Bool(true)),
+                Tag::new(self.head + size, self.tag.clone(), TagValue::Bool(false)),
+            ],
+        );
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::new_nocopy_streamp;
+    use crate::stream_to_pdu::StreamToPdu;
+
+    #[test]
+    fn roundtrip() -> anyhow::Result<()> {
+        let src = new_nocopy_streamp();
+        src.push(vec![1u8, 2, 3], &[]);
+        src.push(vec![4u8], &[]);
+        let mut b = PduToStream::new(src, "burst".to_string(), 2, 0);
+        b.work()?;
+        b.work()?;
+        {
+            let stream = b.out();
+            let (out, tags) = stream.read_buf()?;
+            assert_eq!(out.slice(), &[0, 0, 1, 2, 3, 0, 0, 0, 4, 0]);
+            let tags: Vec<_> = tags.iter().map(|t| (t.pos(), t.val().clone())).collect();
+            assert_eq!(
+                tags,
+                vec![
+                    (2, TagValue::Bool(true)),
+                    (5, TagValue::Bool(false)),
+                    (8, TagValue::Bool(true)),
+                    (9, TagValue::Bool(false)),
+                ]
+            );
+        }
+
+        let mut back = StreamToPdu::new(b.out(), "burst".to_string(), 100, 0);
+        back.work()?;
+        let out = back.out();
+        assert_eq!(out.pop().unwrap().0, vec![1, 2, 3]);
+        // The last PDU is output when the sample after its end arrives.
+        assert!(out.pop().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,400 @@
+/*! Carrier recovery: PLL and Costas loop.
+
+[Pll] locks on to an unmodulated carrier, and [CostasLoop] on to the
+suppressed carrier of a BPSK, QPSK, or 8PSK signal. Both output the
+input mixed down by the recovered carrier, so that the carrier is at
+0Hz with phase 0, ready for coherent demodulation. The Costas loop
+puts BPSK and 8PSK constellations with a point at phase 0, and QPSK
+with points at ±π/4 and ±3π/4. Which of the `order` rotations it
+locks on to is ambiguous, as usual.
+
+The loop is second order, with critical damping, as in GNU Radio. Its
+bandwidth is in radians per sample. Around 2π/100 to 2π/200 is a good
+start. Wider locks faster, narrower is less noisy.
+
+The frequency estimate, in radians per sample, is optionally output as
+a second stream.
+
+```
+use rustradio::blocks::{CostasLoop, NullSink};
+use rustradio::stream::new_streamp;
+let src = new_streamp();
+let mut costas = CostasLoop::builder(src, 4).loop_bw(0.05).build()?;
+let freq = costas.out_freq();
+let sink = NullSink::new(costas.out());
+# Ok::<(), rustradio::Error>(())
+```
+*/
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, Streamp};
+use crate::{Complex, Error, Float};
+
+const TAU: Float = 2.0 * std::f64::consts::PI as Float;
+
+// Second order loop filter and NCO.
+struct Loop {
+    phase: Float,
+    freq: Float,
+    alpha: Float,
+    beta: Float,
+    max_freq: Float,
+}
+
+impl Loop {
+    fn new(loop_bw: Float, max_freq: Float) -> Self {
+        let damping = std::f64::consts::FRAC_1_SQRT_2 as Float;
+        let denom = 1.0 + 2.0 * damping * loop_bw + loop_bw * loop_bw;
+        Self {
+            phase: 0.0,
+            freq: 0.0,
+            alpha: 4.0 * damping * loop_bw / denom,
+            beta: 4.0 * loop_bw * loop_bw / denom,
+            max_freq,
+        }
+    }
+
+    // Mix `s` down by the current carrier estimate.
+    fn mix(&self, s: Complex) -> Complex {
+        s * Complex::from_polar(1.0, -self.phase)
+    }
+
+    // Update from phase error `err` of the last mixed sample.
+    fn advance(&mut self, err: Float) {
+        self.freq = (self.freq + self.beta * err).clamp(-self.max_freq, self.max_freq);
+        self.phase += self.freq + self.alpha * err;
+        self.phase = self.phase.rem_euclid(TAU);
+    }
+}
+
+// Mix down all available input, with `err` giving the phase error of
+// a mixed sample.
+fn work_loop(
+    src: &Streamp<Complex>,
+    dst: &Streamp<Complex>,
+    out_freq: &Option<Streamp<Float>>,
+    lp: &mut Loop,
+    err: impl Fn(Complex) -> Float,
+) -> Result<BlockRet, Error> {
+    let (input, tags) = src.read_buf()?;
+    if input.is_empty() {
+        return Ok(BlockRet::Noop);
+    }
+    let mut o = dst.write_buf()?;
+    let mut of = match out_freq {
+        Some(s) => Some(s.write_buf()?),
+        None => None,
+    };
+    let mut n = input.len().min(o.len());
+    if let Some(f) = &of {
+        n = n.min(f.len());
+    }
+    if n == 0 {
+        return Ok(BlockRet::Noop);
+    }
+    for (i, s) in input.iter().take(n).enumerate() {
+        let y = lp.mix(*s);
+        o.slice()[i] = y;
+        if let Some(f) = &mut of {
+            f.slice()[i] = lp.freq;
+        }
+        lp.advance(err(y));
+    }
+    o.produce(n, &tags);
+    if let Some(f) = of {
+        f.produce(n, &tags);
+    }
+    input.consume(n);
+    Ok(BlockRet::Ok)
+}
+
+/// Builder for [Pll].
+pub struct PllBuilder {
+    src: Streamp<Complex>,
+    loop_bw: Float,
+    max_freq: Float,
+}
+
+impl PllBuilder {
+    /// Create new builder.
+    pub fn new(src: Streamp<Complex>) -> Self {
+        Self {
+            src,
+            loop_bw: TAU / 100.0,
+            max_freq: 1.0,
+        }
+    }
+
+    /// Set loop bandwidth, in radians per sample. Default 2π/100.
+    pub fn loop_bw(mut self, bw: Float) -> Self {
+        self.loop_bw = bw;
+        self
+    }
+
+    /// Set max frequency offset to track, in radians per sample.
+    /// Default 1.0.
+    pub fn max_freq(mut self, f: Float) -> Self {
+        self.max_freq = f;
+        self
+    }
+
+    /// Build Pll block.
+    pub fn build(self) -> Pll {
+        Pll {
+            src: self.src,
+            dst: new_streamp(),
+            out_freq: None,
+            lp: Loop::new(self.loop_bw, self.max_freq),
+        }
+    }
+}
+
+/// Phase locked loop, locking on to an unmodulated carrier.
+///
+/// See the [module docs](crate::pll).
+pub struct Pll {
+    src: Streamp<Complex>,
+    dst: Streamp<Complex>,
+    out_freq: Option<Streamp<Float>>,
+    lp: Loop,
+}
+
+impl Pll {
+    /// Create a builder.
+    pub fn builder(src: Streamp<Complex>) -> PllBuilder {
+        PllBuilder::new(src)
+    }
+
+    /// Create new Pll block, with default loop bandwidth.
+    pub fn new(src: Streamp<Complex>) -> Self {
+        PllBuilder::new(src).build()
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Complex> {
+        self.dst.clone()
+    }
+
+    /// Return frequency estimate stream, in radians per sample.
+    pub fn out_freq(&mut self) -> Streamp<Float> {
+        self.out_freq.get_or_insert(new_streamp()).clone()
+    }
+
+    /// Current frequency estimate, in radians per sample.
+    pub fn frequency(&self) -> Float {
+        self.lp.freq
+    }
+}
+
+impl Block for Pll {
+    fn block_name(&self) -> &str {
+        "Pll"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        work_loop(&self.src, &self.dst, &self.out_freq, &mut self.lp, |y| {
+            y.arg()
+        })
+    }
+}
+
+/// Builder for [CostasLoop].
+pub struct CostasLoopBuilder {
+    src: Streamp<Complex>,
+    order: usize,
+    loop_bw: Float,
+    max_freq: Float,
+}
+
+impl CostasLoopBuilder {
+    /// Create new builder, for BPSK (order 2), QPSK (4), or 8PSK (8).
+    pub fn new(src: Streamp<Complex>, order: usize) -> Self {
+        Self {
+            src,
+            order,
+            loop_bw: TAU / 100.0,
+            max_freq: 1.0,
+        }
+    }
+
+    /// Set loop bandwidth, in radians per sample. Default 2π/100.
+    pub fn loop_bw(mut self, bw: Float) -> Self {
+        self.loop_bw = bw;
+        self
+    }
+
+    /// Set max frequency offset to track, in radians per sample.
+    /// Default 1.0.
+    pub fn max_freq(mut self, f: Float) -> Self {
+        self.max_freq = f;
+        self
+    }
+
+    /// Build CostasLoop block.
+    pub fn build(self) -> Result<CostasLoop, Error> {
+        if ![2, 4, 8].contains(&self.order) {
+            return Err(Error::BadParameter(format!(
+                "CostasLoop order must be 2, 4, or 8, got {}",
+                self.order
+            )));
+        }
+        Ok(CostasLoop {
+            src: self.src,
+            dst: new_streamp(),
+            out_freq: None,
+            order: self.order,
+            lp: Loop::new(self.loop_bw, self.max_freq),
+        })
+    }
+}
+
+/// Costas loop, for BPSK, QPSK, or 8PSK carrier recovery.
+///
+/// See the [module docs](crate::pll).
+pub struct CostasLoop {
+    src: Streamp<Complex>,
+    dst: Streamp<Complex>,
+    out_freq: Option<Streamp<Float>>,
+    order: usize,
+    lp: Loop,
+}
+
+impl CostasLoop {
+    /// Create a builder.
+    pub fn builder(src: Streamp<Complex>, order: usize) -> CostasLoopBuilder {
+        CostasLoopBuilder::new(src, order)
+    }
+
+    /// Create new CostasLoop block, with default loop bandwidth.
+    pub fn new(src: Streamp<Complex>, order: usize) -> Result<Self, Error> {
+        CostasLoopBuilder::new(src, order).build()
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Complex> {
+        self.dst.clone()
+    }
+
+    /// Return frequency estimate stream, in radians per sample.
+    pub fn out_freq(&mut self) -> Streamp<Float> {
+        self.out_freq.get_or_insert(new_streamp()).clone()
+    }
+
+    /// Current frequency estimate, in radians per sample.
+    pub fn frequency(&self) -> Float {
+        self.lp.freq
+    }
+}
+
+// Phase error detectors, for constellations with a point at phase 0,
+// except QPSK, which has its points at ±π/4 and ±3π/4.
+fn bpsk_error(y: Complex) -> Float {
+    y.re * y.im
+}
+
+fn qpsk_error(y: Complex) -> Float {
+    y.re.signum() * y.im - y.im.signum() * y.re
+}
+
+// Decision directed, against the nearest point.
+fn psk8_error(y: Complex) -> Float {
+    let step = TAU / 8.0;
+    let nearest = Complex::from_polar(1.0, (y.arg() / step).round() * step);
+    (y * nearest.conj()).im
+}
+
+impl Block for CostasLoop {
+    fn block_name(&self) -> &str {
+        "CostasLoop"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let err = match self.order {
+            2 => bpsk_error,
+            4 => qpsk_error,
+            _ => psk8_error,
+        };
+        work_loop(&self.src, &self.dst, &self.out_freq, &mut self.lp, |y| {
+            err(y).clamp(-1.0, 1.0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    // PSK symbols of `order`, with a frequency and phase offset.
+    fn psk(order: usize, n: usize, freq: Float) -> Vec<Complex> {
+        let mut state = 12345u32;
+        (0..n)
+            .map(|i| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let sym = ((state >> 16) as usize) % order;
+                let offset = if order == 4 { TAU / 8.0 } else { 0.0 };
+                let ph = sym as Float * TAU / order as Float + offset;
+                Complex::from_polar(1.0, ph + freq * i as Float + 0.7)
+            })
+            .collect()
+    }
+
+    // Check that the last samples are near a rotation of the
+    // constellation.
+    fn check_locked(out: &[Complex], order: usize) {
+        let offset = if order == 4 { TAU / 8.0 } else { 0.0 };
+        for s in &out[out.len() - 500..] {
+            let step = TAU / order as Float;
+            let ph = (s.arg() - offset).rem_euclid(step);
+            let err = ph.min(step - ph);
+            assert!(err < 0.05, "order {order}: {s} off by {err}");
+        }
+    }
+
+    #[test]
+    fn pll() -> Result<(), Error> {
+        let input: Vec<Complex> = (0..5000)
+            .map(|i| Complex::from_polar(2.0, 0.05 * i as Float + 1.0))
+            .collect();
+        let mut pll = Pll::new(streamp_from_slice(&input));
+        let freq = pll.out_freq();
+        pll.work()?;
+        assert!((pll.frequency() - 0.05).abs() < 1e-3, "{}", pll.frequency());
+        let o = pll.out();
+        let (out, _) = o.read_buf()?;
+        assert_eq!(out.len(), 5000);
+        for s in &out.slice()[4000..] {
+            assert!(s.arg().abs() < 0.01, "{s}");
+            assert!((s.norm() - 2.0).abs() < 1e-3, "{s}");
+        }
+        let (f, _) = freq.read_buf()?;
+        assert_eq!(f.len(), 5000);
+        assert_eq!(f.slice()[0], 0.0);
+        assert!((f.slice()[4999] - 0.05).abs() < 1e-3);
+        Ok(())
+    }
+
+    #[test]
+    fn costas() -> Result<(), Error> {
+        for order in [2, 4, 8] {
+            for freq in [-0.01, 0.0, 0.02] {
+                let input = psk(order, 5000, freq);
+                let mut c = CostasLoop::builder(streamp_from_slice(&input), order)
+                    .loop_bw(0.03)
+                    .build()?;
+                c.work()?;
+                assert!(
+                    (c.frequency() - freq).abs() < 1e-3,
+                    "order {order}: want {freq} got {}",
+                    c.frequency()
+                );
+                let o = c.out();
+                let (out, _) = o.read_buf()?;
+                check_locked(out.slice(), order);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn bad_order() {
+        assert!(CostasLoop::new(new_streamp(), 3).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+/*! Calibrated power meter.
+
+[PowerMeter] passes a stream through unchanged, and measures RMS and
+peak power over a short window, for comparing antennas and setting
+levels.
+
+```text
+   Source -> PowerMeter -> …
+                  \_ PowerHandle -> reading()
+```
+
+Power is in dBFS, where a sample of magnitude 1 is 0dB. So a complex
+full scale tone is 0dBFS, and a real full scale sine wave -3dBFS RMS
+and 0dBFS peak.
+
+With a calibration offset, found by feeding a known level from a signal
+generator, readings are also given in dBm. The offset depends on the
+gain settings of the SDR, so needs to be redone if they change.
+
+Readings can also be logged periodically, with [PowerMeter::set_log_interval].
+*/
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::info;
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, Streamp};
+use crate::SpectrumInput;
+use crate::{Error, Float};
+
+const DEFAULT_WINDOW: Duration = Duration::from_millis(100);
+
+fn to_db(power: Float) -> Float {
+    10.0 * power.max(1e-20).log10()
+}
+
+/// A power measurement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PowerReading {
+    /// RMS power over the window, in dBFS.
+    pub rms: Float,
+
+    /// Peak sample power over the window, in dBFS.
+    pub peak: Float,
+
+    /// Highest RMS power since the last reset, in dBFS.
+    pub max_rms: Float,
+
+    /// Calibration offset, in dB. Add to dBFS to get dBm.
+    pub calibration: Option<Float>,
+}
+
+impl PowerReading {
+    /// RMS power, in dBm, if calibrated.
+    pub fn rms_dbm(&self) -> Option<Float> {
+        self.calibration.map(|c| self.rms + c)
+    }
+
+    /// Peak power, in dBm, if calibrated.
+    pub fn peak_dbm(&self) -> Option<Float> {
+        self.calibration.map(|c| self.peak + c)
+    }
+
+    /// Highest RMS power since the last reset, in dBm, if calibrated.
+    pub fn max_rms_dbm(&self) -> Option<Float> {
+        self.calibration.map(|c| self.max_rms + c)
+    }
+}
+
+impl std::fmt::Display for PowerReading {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "RMS {:.1} dBFS, peak {:.1} dBFS", self.rms, self.peak)?;
+        if let (Some(rms), Some(peak)) = (self.rms_dbm(), self.peak_dbm()) {
+            write!(f, " (RMS {rms:.1} dBm, peak {peak:.1} dBm)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    rms: Option<Float>,
+    peak: Float,
+    max_rms: Float,
+    calibration: Option<Float>,
+}
+
+/// Shared handle to the readings of a [PowerMeter].
+#[derive(Clone)]
+pub struct PowerHandle {
+    inner: Arc<Mutex<Shared>>,
+}
+
+impl PowerHandle {
+    /// Latest reading, or None before the first window is complete.
+    pub fn reading(&self) -> Option<PowerReading> {
+        let s = self.inner.lock().unwrap();
+        Some(PowerReading {
+            rms: to_db(s.rms?),
+            peak: to_db(s.peak),
+            max_rms: to_db(s.max_rms),
+            calibration: s.calibration,
+        })
+    }
+
+    /// Set calibration offset in dB, added to dBFS to get dBm.
+    pub fn set_calibration(&self, offset: Option<Float>) {
+        self.inner.lock().unwrap().calibration = offset;
+    }
+
+    /// Calibrate so that the latest RMS reading is `dbm`. Returns the
+    /// new offset, or None if there's no reading yet.
+    pub fn calibrate(&self, dbm: Float) -> Option<Float> {
+        let mut s = self.inner.lock().unwrap();
+        let offset = dbm - to_db(s.rms?);
+        s.calibration = Some(offset);
+        Some(offset)
+    }
+
+    /// Reset the max RMS hold.
+    pub fn reset(&self) {
+        let mut s = self.inner.lock().unwrap();
+        s.max_rms = s.rms.unwrap_or(0.0);
+    }
+}
+
+// Sum of power, and peak, over a number of samples.
+#[derive(Default)]
+struct Acc {
+    sum: f64,
+    peak: Float,
+    n: usize,
+}
+
+impl Acc {
+    fn add(&mut self, power: Float) {
+        self.sum += power as f64;
+        self.peak = self.peak.max(power);
+        self.n += 1;
+    }
+    fn merge(&mut self, other: &Acc) {
+        self.sum += other.sum;
+        self.peak = self.peak.max(other.peak);
+        self.n += other.n;
+    }
+    fn rms(&self) -> Float {
+        (self.sum / self.n as f64) as Float
+    }
+}
+
+/// Builder for [PowerMeter].
+pub struct PowerMeterBuilder<T: SpectrumInput> {
+    meter: PowerMeter<T>,
+}
+
+impl<T: SpectrumInput> PowerMeterBuilder<T> {
+    /// Create new builder.
+    pub fn new(src: Streamp<T>, samp_rate: Float) -> Self {
+        Self {
+            meter: PowerMeter::new(src, samp_rate),
+        }
+    }
+
+    /// Set measurement window. Default 100ms.
+    pub fn window(mut self, d: Duration) -> Self {
+        self.meter.set_window(d);
+        self
+    }
+
+    /// Set calibration offset in dB, added to dBFS to get dBm.
+    pub fn calibration(mut self, offset: Float) -> Self {
+        self.meter.set_calibration(Some(offset));
+        self
+    }
+
+    /// Log RMS and peak power over this interval.
+    pub fn log_interval(mut self, d: Duration) -> Self {
+        self.meter.set_log_interval(Some(d));
+        self
+    }
+
+    /// Build PowerMeter block.
+    pub fn build(self) -> PowerMeter<T> {
+        self.meter
+    }
+}
+
+/** Power meter block.
+
+Passes the stream through as is.
+*/
+pub struct PowerMeter<T: SpectrumInput> {
+    src: Streamp<T>,
+    dst: Streamp<T>,
+    samp_rate: Float,
+    handle: PowerHandle,
+    window: usize,
+    log: Option<usize>,
+    acc: Acc,
+    log_acc: Acc,
+}
+
+impl<T: SpectrumInput> PowerMeter<T> {
+    /// Create new power meter.
+    pub fn new(src: Streamp<T>, samp_rate: Float) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            samp_rate,
+            handle: PowerHandle {
+                inner: Arc::new(Mutex::new(Shared::default())),
+            },
+            window: Self::samples(samp_rate, DEFAULT_WINDOW),
+            log: None,
+            acc: Acc::default(),
+            log_acc: Acc::default(),
+        }
+    }
+
+    /// Create a builder for PowerMeter.
+    pub fn builder(src: Streamp<T>, samp_rate: Float) -> PowerMeterBuilder<T> {
+        PowerMeterBuilder::new(src, samp_rate)
+    }
+
+    fn samples(samp_rate: Float, d: Duration) -> usize {
+        ((d.as_secs_f64() * samp_rate as f64) as usize).max(1)
+    }
+
+    /// Set measurement window. Default 100ms.
+    pub fn set_window(&mut self, d: Duration) {
+        self.window = Self::samples(self.samp_rate, d);
+    }
+
+    /// Set calibration offset in dB, added to dBFS to get dBm.
+    pub fn set_calibration(&mut self, offset: Option<Float>) {
+        self.handle.set_calibration(offset);
+    }
+
+    /// Log RMS and peak power over this interval. Default off.
+    pub fn set_log_interval(&mut self, d: Option<Duration>) {
+        self.log = d.map(|d| Self::samples(self.samp_rate, d));
+    }
+
+    /// Get a handle to the readings.
+    pub fn handle(&self) -> PowerHandle {
+        self.handle.clone()
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<T> {
+        self.dst.clone()
+    }
+
+    fn window_done(&mut self) {
+        let rms = self.acc.rms();
+        {
+            let mut s = self.handle.inner.lock().unwrap();
+            s.rms = Some(rms);
+            s.peak = self.acc.peak;
+            s.max_rms = s.max_rms.max(rms);
+        }
+        self.log_acc.merge(&self.acc);
+        self.acc = Acc::default();
+        if let Some(log) = self.log {
+            if self.log_acc.n >= log {
+                let s = self.handle.inner.lock().unwrap();
+                let reading = PowerReading {
+                    rms: to_db(self.log_acc.rms()),
+                    peak: to_db(self.log_acc.peak),
+                    max_rms: to_db(s.max_rms),
+                    calibration: s.calibration,
+                };
+                drop(s);
+                info!("PowerMeter: {reading}");
+                self.log_acc = Acc::default();
+            }
+        }
+    }
+}
+
+impl<T: SpectrumInput> Block for PowerMeter<T> {
+    fn block_name(&self) -> &str {
+        "PowerMeter"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let obind = self.dst.clone();
+        let mut o = obind.write_buf()?;
+        let n = std::cmp::min(input.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::Ok);
+        }
+        for x in input.iter().take(n) {
+            self.acc.add(x.to_complex().norm_sqr());
+            if self.acc.n >= self.window {
+                self.window_done();
+            }
+        }
+        o.slice()[..n].copy_from_slice(&input.slice()[..n]);
+        let tags: Vec<_> = tags.into_iter().filter(|t| t.pos() < n).collect();
+        o.produce(n, &tags);
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::VectorSource;
+    use crate::Complex;
+
+    fn run<T: SpectrumInput>(data: Vec<T>, window: Duration) -> Result<PowerHandle, Error> {
+        let mut src = VectorSource::new(data);
+        let mut m = PowerMeter::builder(src.out(), 1000.0)
+            .window(window)
+            .build();
+        src.work()?;
+        m.work()?;
+        Ok(m.handle())
+    }
+
+    #[test]
+    fn levels() -> Result<(), Error> {
+        // Complex full scale tone.
+        let tone: Vec<Complex> = (0..1000)
+            .map(|n| Complex::from_polar(1.0, n as Float * 0.3))
+            .collect();
+        let r = run(tone, Duration::from_millis(100))?.reading().unwrap();
+        assert!(r.rms.abs() < 0.01, "{r:?}");
+        assert!(r.peak.abs() < 0.01, "{r:?}");
+
+        // Real sine, half scale: -6dB peak, -9dB RMS.
+        let sine: Vec<Float> = (0..1000)
+            .map(|n| 0.5 * (n as Float * 2.0 * std::f64::consts::PI as Float / 4.0).sin())
+            .collect();
+        let h = run(sine, Duration::from_millis(100))?;
+        let r = h.reading().unwrap();
+        assert!((r.rms + 9.03).abs() < 0.05, "{r:?}");
+        assert!((r.peak + 6.02).abs() < 0.05, "{r:?}");
+        assert_eq!(r.rms_dbm(), None);
+
+        let offset = h.calibrate(-50.0).unwrap();
+        assert!((offset + 40.97).abs() < 0.05);
+        let r = h.reading().unwrap();
+        assert!((r.rms_dbm().unwrap() + 50.0).abs() < 0.001);
+        assert!((r.peak_dbm().unwrap() + 47.0).abs() < 0.05);
+        Ok(())
+    }
+
+    #[test]
+    fn max_hold() -> Result<(), Error> {
+        let mut data = vec![Complex::new(1.0, 0.0); 100];
+        data.extend(vec![Complex::new(0.1, 0.0); 100]);
+        let h = run(data, Duration::from_millis(100))?;
+        let r = h.reading().unwrap();
+        assert!((r.rms + 20.0).abs() < 0.01, "{r:?}");
+        assert!(r.max_rms.abs() < 0.01, "{r:?}");
+        h.reset();
+        assert!((h.reading().unwrap().max_rms + 20.0).abs() < 0.01);
+        Ok(())
+    }
+
+    #[test]
+    fn incomplete_window() -> Result<(), Error> {
+        let h = run(vec![Complex::new(1.0, 0.0); 99], Duration::from_millis(100))?;
+        assert_eq!(h.reading(), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+/*! Power squelch.
+
+[PowerSquelch] passes samples through while the average input power is
+above a threshold, and mutes them, or drops them, when it's not. So a
+[FileSink][crate::file_sink::FileSink] after it only records actual
+transmissions, and demodulators don't decode noise.
+
+```text
+   Source -> PowerSquelch -> …
+                  \_ PowerSquelchHandle -> set_threshold(), is_open()
+```
+
+The threshold is also a [control][crate::control], "threshold".
+
+Power is averaged with a single pole IIR filter, and compared to the
+threshold in dBFS, where a sample of magnitude 1 is 0dB. To not flap
+open and closed on noise, power has to be above the threshold for
+`attack` samples to open, and below it for `release` samples to
+close.
+
+Each transmission is tagged with the common burst keys,
+[TAG_BURST_START] on the first sample and [TAG_BURST_END] on the last,
+like from [HdlcFramer][crate::hdlc_framer::HdlcFramer]. So
+[BurstFileSink][crate::burst_file_sink::BurstFileSink] can write each
+one to its own file.
+
+```no_run
+use rustradio::blocks::{ConstantSource, FileSink, PowerSquelch};
+use rustradio::file_sink::Mode;
+use rustradio::Complex;
+let src = ConstantSource::new(Complex::new(1.0, 0.0));
+let sq = PowerSquelch::builder(src.out(), -40.0)
+    .release(48_000)
+    .gate(true)
+    .build();
+let sink = FileSink::new(sq.out(), "transmissions.c32".into(), Mode::Overwrite)?;
+# Ok::<(), anyhow::Error>(())
+```
+*/
+use std::sync::{Arc, Mutex};
+
+use crate::block::{Block, BlockRet};
+use crate::control::Control;
+use crate::stream::{new_streamp, Streamp, Tag, TagValue, TAG_BURST_END, TAG_BURST_START};
+use crate::SpectrumInput;
+use crate::{Error, Float};
+
+const DEFAULT_ALPHA: Float = 0.0001;
+
+struct Shared {
+    threshold: Float,
+    open: bool,
+}
+
+/// Shared handle to a [PowerSquelch].
+#[derive(Clone)]
+pub struct PowerSquelchHandle {
+    inner: Arc<Mutex<Shared>>,
+}
+
+impl PowerSquelchHandle {
+    /// Threshold, in dBFS.
+    pub fn threshold(&self) -> Float {
+        self.inner.lock().unwrap().threshold
+    }
+
+    /// Set threshold, in dBFS. Used from the next `work()` call.
+    pub fn set_threshold(&self, db: Float) {
+        self.inner.lock().unwrap().threshold = db;
+    }
+
+    /// True if the squelch is open, i.e. passing samples.
+    pub fn is_open(&self) -> bool {
+        self.inner.lock().unwrap().open
+    }
+}
+
+/// Builder for [PowerSquelch].
+pub struct PowerSquelchBuilder<T> {
+    src: Streamp<T>,
+    threshold: Float,
+    alpha: Float,
+    attack: usize,
+    release: usize,
+    gate: bool,
+}
+
+impl<T> PowerSquelchBuilder<T> {
+    /// Create new builder, with threshold in dBFS.
+    pub fn new(src: Streamp<T>, threshold: Float) -> Self {
+        Self {
+            src,
+            threshold,
+            alpha: DEFAULT_ALPHA,
+            attack: 1,
+            release: 1,
+            gate: false,
+        }
+    }
+
+    /// Weight of each new sample in the power average. Default 0.0001.
+    pub fn alpha(mut self, alpha: Float) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// Samples above the threshold before opening. Default 1.
+    pub fn attack(mut self, samples: usize) -> Self {
+        self.attack = samples.max(1);
+        self
+    }
+
+    /// Samples below the threshold before closing. Default 1.
+    pub fn release(mut self, samples: usize) -> Self {
+        self.release = samples.max(1);
+        self
+    }
+
+    /// If true, drop samples while closed, instead of outputting
+    /// zeros. Default false.
+    pub fn gate(mut self, gate: bool) -> Self {
+        self.gate = gate;
+        self
+    }
+
+    /// Build PowerSquelch block.
+    pub fn build(self) -> PowerSquelch<T> {
+        PowerSquelch {
+            src: self.src,
+            dst: new_streamp(),
+            handle: PowerSquelchHandle {
+                inner: Arc::new(Mutex::new(Shared {
+                    threshold: self.threshold,
+                    open: false,
+                })),
+            },
+            alpha: self.alpha,
+            attack: self.attack,
+            release: self.release,
+            gate: self.gate,
+            power: 0.0,
+            open: false,
+            count: 0,
+        }
+    }
+}
+
+/// Power squelch. See the [module docs][crate::power_squelch].
+pub struct PowerSquelch<T> {
+    src: Streamp<T>,
+    dst: Streamp<T>,
+    handle: PowerSquelchHandle,
+    alpha: Float,
+    attack: usize,
+    release: usize,
+    gate: bool,
+    power: Float,
+    open: bool,
+    // Samples in a row on the other side of the threshold.
+    count: usize,
+}
+
+impl<T> PowerSquelch<T> {
+    /// Create new PowerSquelch, with threshold in dBFS.
+    pub fn new(src: Streamp<T>, threshold: Float) -> Self {
+        PowerSquelchBuilder::new(src, threshold).build()
+    }
+
+    /// Create new builder.
+    pub fn builder(src: Streamp<T>, threshold: Float) -> PowerSquelchBuilder<T> {
+        PowerSquelchBuilder::new(src, threshold)
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<T> {
+        self.dst.clone()
+    }
+
+    /// Handle for changing the threshold, and checking the state,
+    /// while running.
+    pub fn handle(&self) -> PowerSquelchHandle {
+        self.handle.clone()
+    }
+}
+
+fn burst_tag(pos: usize, key: &str) -> Tag {
+    Tag::new(pos, key.into(), TagValue::Bool(true))
+}
+
+impl<T> Block for PowerSquelch<T>
+where
+    T: SpectrumInput + Default,
+{
+    fn block_name(&self) -> &str {
+        "PowerSquelch"
+    }
+    fn controls(&self) -> Vec<Control> {
+        let (g, s) = (self.handle.clone(), self.handle.clone());
+        vec![Control::float(
+            "threshold",
+            move || g.threshold(),
+            move |db| {
+                s.set_threshold(db);
+                Ok(())
+            },
+        )]
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (i, tags) = self.src.read_buf()?;
+        let mut o = self.dst.write_buf()?;
+        let n = std::cmp::min(i.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        let threshold = (10.0 as Float).powf(self.handle.threshold() / 10.0);
+        let mut otags = Vec::new();
+        let mut opos = 0;
+        let oslice = o.slice();
+        for (pos, s) in i.iter().take(n).enumerate() {
+            self.power += self.alpha * (s.to_complex().norm_sqr() - self.power);
+            if (self.power > threshold) != self.open {
+                self.count += 1;
+            } else {
+                self.count = 0;
+            }
+            let mut closing = false;
+            if !self.open && self.count >= self.attack {
+                self.open = true;
+                self.count = 0;
+                otags.push(burst_tag(opos, TAG_BURST_START));
+            } else if self.open && self.count >= self.release {
+                closing = true;
+            }
+            if !self.open && self.gate {
+                continue;
+            }
+            otags.extend(
+                tags.iter()
+                    .filter(|t| t.pos() == pos)
+                    .map(|t| t.with_pos(opos)),
+            );
+            oslice[opos] = if self.open { *s } else { T::default() };
+            if closing {
+                otags.push(burst_tag(opos, TAG_BURST_END));
+                self.open = false;
+                self.count = 0;
+            }
+            opos += 1;
+        }
+        self.handle.inner.lock().unwrap().open = self.open;
+        i.consume(n);
+        o.produce(opos, &otags);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+    use crate::Complex;
+
+    // Silence, then a full scale carrier, then silence.
+    fn input() -> Vec<Complex> {
+        let mut v = vec![Complex::default(); 100];
+        v.extend(vec![Complex::new(1.0, 0.0); 200]);
+        v.extend(vec![Complex::default(); 100]);
+        v
+    }
+
+    // Burst tags as (pos, start).
+    fn tags_of(tags: &[Tag]) -> Vec<(usize, bool)> {
+        tags.iter()
+            .map(|t| {
+                assert_eq!(t.val(), &TagValue::Bool(true));
+                match t.key() {
+                    TAG_BURST_START => (t.pos(), true),
+                    TAG_BURST_END => (t.pos(), false),
+                    _ => panic!("unexpected tag {t:?}"),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mute() -> crate::Result<()> {
+        let mut b = PowerSquelch::builder(streamp_from_slice(&input()), -10.0)
+            .alpha(0.5)
+            .build();
+        assert_eq!(b.block_name(), "PowerSquelch");
+        let h = b.handle();
+        b.work()?;
+        assert!(!h.is_open());
+        let o = b.out();
+        let (res, tags) = o.read_buf()?;
+        assert_eq!(res.len(), 400);
+        // Average power crosses -10dB on the first carrier sample,
+        // and 0.1 on the fourth silent one.
+        assert_eq!(tags_of(&tags), vec![(100, true), (303, false)]);
+        assert!(res.slice()[..100].iter().all(|s| s.norm() == 0.0));
+        assert!(res.slice()[100..300].iter().all(|s| s.norm() == 1.0));
+        assert!(res.slice()[304..].iter().all(|s| s.norm() == 0.0));
+        Ok(())
+    }
+
+    #[test]
+    fn gate() -> crate::Result<()> {
+        let mut b = PowerSquelch::builder(streamp_from_slice(&input()), -10.0)
+            .alpha(0.5)
+            .gate(true)
+            .build();
+        b.work()?;
+        let o = b.out();
+        let (res, tags) = o.read_buf()?;
+        assert_eq!(res.len(), 204);
+        assert_eq!(tags_of(&tags), vec![(0, true), (203, false)]);
+        Ok(())
+    }
+
+    #[test]
+    fn attack_release() -> crate::Result<()> {
+        // A short spike, then a gap shorter than the release time.
+        let mut v = vec![0.0 as Float; 10];
+        v.extend([1.0, 1.0]);
+        v.extend(vec![0.0; 10]);
+        v.extend(vec![1.0; 20]);
+        v.extend(vec![0.0; 5]);
+        v.extend(vec![1.0; 20]);
+        v.extend(vec![0.0; 20]);
+        let mut b = PowerSquelch::builder(streamp_from_slice(&v), -3.0)
+            .alpha(1.0)
+            .attack(5)
+            .release(10)
+            .gate(true)
+            .build();
+        b.work()?;
+        let o = b.out();
+        let (res, tags) = o.read_buf()?;
+        // Opens 5 samples into the long carrier, and stays open
+        // through the gap.
+        assert_eq!(tags_of(&tags), vec![(0, true), (res.len() - 1, false)]);
+        assert_eq!(res.len(), 16 + 5 + 20 + 10);
+        Ok(())
+    }
+
+    #[test]
+    fn threshold() -> crate::Result<()> {
+        let mut b = PowerSquelch::new(streamp_from_slice(&input()), 10.0);
+        let h = b.handle();
+        assert_eq!(h.threshold(), 10.0);
+        h.set_threshold(-50.0);
+        b.work()?;
+        let o = b.out();
+        let (_, tags) = o.read_buf()?;
+        assert_eq!(tags_of(&tags)[0], (100, true));
+        Ok(())
+    }
+}
@@ -0,0 +1,578 @@
+/*! PSK31 modem.
+
+[PSK31][psk31] is a keyboard-to-keyboard HF mode, sending 31.25 baud
+differential BPSK. A one bit is sent as no phase change, and a zero as
+a phase reversal. During a reversal the amplitude follows a cosine
+through zero, which keeps the signal very narrow.
+
+Characters are [varicode][varicode] encoded, where more common
+characters have shorter codes. No code contains two zeros in a row,
+and codes are separated by `00`.
+
+The receiver takes a Complex signal, such as the output of a `Hilbert`
+block fed with audio from an SSB receiver, and the audio frequency of
+the signal to decode (as seen on a waterfall). Automatic frequency
+control (AFC) then tracks the signal.
+
+```text
+  [ audio ] → [ Hilbert ] → [ Psk31Demod ] → ASCII bytes
+```
+
+[psk31]: https://en.wikipedia.org/wiki/PSK31
+[varicode]: https://en.wikipedia.org/wiki/Varicode
+*/
+use std::collections::{HashMap, VecDeque};
+
+use log::{debug, trace};
+
+use crate::block::{Block, BlockRet};
+use crate::fir::FIR;
+use crate::stream::{new_streamp, Streamp};
+use crate::{Complex, Error, Float};
+
+/// PSK31 symbol rate.
+pub const BAUD: Float = 31.25;
+
+// Samples per symbol after decimation in the receiver.
+const RX_SPS: usize = 16;
+
+// Max AFC pull, in Hz.
+const AFC_RANGE: Float = 50.0;
+
+/// Varicode table, indexed by ASCII value.
+pub const VARICODE: [&str; 128] = [
+    "1010101011",
+    "1011011011",
+    "1011101101",
+    "1101110111",
+    "1011101011",
+    "1101011111",
+    "1011101111",
+    "1011111101",
+    "1011111111",
+    "11101111",
+    "11101",
+    "1101101111",
+    "1011011101",
+    "11111",
+    "1101110101",
+    "1110101011",
+    "1011110111",
+    "1011110101",
+    "1110101101",
+    "1110101111",
+    "1101011011",
+    "1101101011",
+    "1101101101",
+    "1101010111",
+    "1101111011",
+    "1101111101",
+    "1110110111",
+    "1101010101",
+    "1101011101",
+    "1110111011",
+    "1011111011",
+    "1101111111",
+    "1",
+    "111111111",
+    "101011111",
+    "111110101",
+    "111011011",
+    "1011010101",
+    "1010111011",
+    "101111111",
+    "11111011",
+    "11110111",
+    "101101111",
+    "111011111",
+    "1110101",
+    "110101",
+    "1010111",
+    "110101111",
+    "10110111",
+    "10111101",
+    "11101101",
+    "11111111",
+    "101110111",
+    "101011011",
+    "101101011",
+    "110101101",
+    "110101011",
+    "110110111",
+    "11110101",
+    "110111101",
+    "111101101",
+    "1010101",
+    "111010111",
+    "1010101111",
+    "1010111101",
+    "1111101",
+    "11101011",
+    "10101101",
+    "10110101",
+    "1110111",
+    "11011011",
+    "11111101",
+    "101010101",
+    "1111111",
+    "111111101",
+    "101111101",
+    "11010111",
+    "10111011",
+    "11011101",
+    "10101011",
+    "11010101",
+    "111011101",
+    "10101111",
+    "1101111",
+    "1101101",
+    "101010111",
+    "110110101",
+    "101011101",
+    "101110101",
+    "101111011",
+    "1010101101",
+    "111110111",
+    "111101111",
+    "111111011",
+    "1010111111",
+    "101101101",
+    "1011011111",
+    "1011",
+    "1011111",
+    "101111",
+    "101101",
+    "11",
+    "111101",
+    "1011011",
+    "101011",
+    "1101",
+    "111101011",
+    "10111111",
+    "11011",
+    "111011",
+    "1111",
+    "111",
+    "111111",
+    "110111111",
+    "10101",
+    "10111",
+    "101",
+    "110111",
+    "1111011",
+    "1101011",
+    "11011111",
+    "1011101",
+    "111010101",
+    "1010110111",
+    "110111011",
+    "1010110101",
+    "1011010111",
+    "1110110101",
+];
+
+/// Encode text as varicode bits, including the `00` after each
+/// character.
+///
+/// Non-ASCII characters are skipped.
+pub fn varicode_encode(text: &str) -> Vec<bool> {
+    let mut ret = Vec::new();
+    for b in text.bytes().filter(|b| b.is_ascii()) {
+        ret.extend(VARICODE[b as usize].chars().map(|c| c == '1'));
+        ret.extend([false, false]);
+    }
+    ret
+}
+
+/// Varicode bit stream decoder.
+pub struct VaricodeDecoder {
+    table: HashMap<u32, u8>,
+    code: u32,
+    len: usize,
+}
+
+impl VaricodeDecoder {
+    /// Create new varicode decoder.
+    pub fn new() -> Self {
+        let table = VARICODE
+            .iter()
+            .enumerate()
+            .map(|(n, code)| {
+                (
+                    code.chars()
+                        .fold(0u32, |acc, c| (acc << 1) | (c == '1') as u32),
+                    n as u8,
+                )
+            })
+            .collect();
+        Self {
+            table,
+            code: 0,
+            len: 0,
+        }
+    }
+
+    /// Feed one bit, returning the decoded character when complete.
+    pub fn push(&mut self, bit: bool) -> Option<u8> {
+        if !bit && self.code & 1 == 0 {
+            // Two zeros in a row. End of character.
+            let code = self.code >> 1;
+            self.code = 0;
+            self.len = 0;
+            if code == 0 {
+                return None;
+            }
+            let ch = self.table.get(&code).copied();
+            if ch.is_none() {
+                trace!("VaricodeDecoder: unknown code {code:b}");
+            }
+            return ch;
+        }
+        self.code = (self.code << 1) | bit as u32;
+        self.len += 1;
+        if self.len > 12 {
+            // Longest code is 10 bits, plus one zero. This is garbage,
+            // or idle.
+            self.code = 0;
+            self.len = 0;
+        }
+        None
+    }
+}
+
+impl Default for VaricodeDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/** PSK31 demodulator and decoder block.
+
+Input is Complex samples at a sample rate that's a multiple of 500Hz
+(e.g. 8000 or 48000). Output is decoded ASCII bytes.
+*/
+pub struct Psk31Demod {
+    src: Streamp<Complex>,
+    dst: Streamp<u8>,
+    samp_rate: Float,
+    frequency: Float,
+    start_frequency: Float,
+    afc: bool,
+    phase: f64,
+    decim: usize,
+    acc: Complex,
+    acc_count: usize,
+    filter: FIR<Complex>,
+    history: VecDeque<Complex>,
+    energy: [Float; RX_SPS],
+    clock: usize,
+    countdown: usize,
+    last_symbol: Complex,
+    decoder: VaricodeDecoder,
+}
+
+impl Psk31Demod {
+    /// Create new PSK31 demodulator.
+    ///
+    /// `frequency` is the frequency of the signal in Hz, in the
+    /// input. E.g. the audio frequency if input is SSB audio.
+    pub fn new(src: Streamp<Complex>, samp_rate: Float, frequency: Float) -> Result<Self, Error> {
+        let rx_rate = BAUD * RX_SPS as Float;
+        let decim = (samp_rate / rx_rate).round() as usize;
+        if decim == 0 || (decim as Float * rx_rate - samp_rate).abs() > 0.01 {
+            return Err(Error::BadParameter(format!(
+                "PSK31 sample rate must be a multiple of {rx_rate}, got {samp_rate}"
+            )));
+        }
+        let taps = crate::fir::low_pass_complex(rx_rate, 25.0, 20.0);
+        Ok(Self {
+            src,
+            dst: new_streamp(),
+            samp_rate,
+            frequency,
+            start_frequency: frequency,
+            afc: true,
+            phase: 0.0,
+            decim,
+            acc: Complex::default(),
+            acc_count: 0,
+            history: VecDeque::from(vec![Complex::default(); taps.len()]),
+            filter: FIR::new(&taps),
+            energy: [0.0; RX_SPS],
+            clock: 0,
+            countdown: RX_SPS,
+            last_symbol: Complex::default(),
+            decoder: VaricodeDecoder::new(),
+        })
+    }
+
+    /// Enable or disable automatic frequency control. Default on.
+    pub fn set_afc(&mut self, v: bool) {
+        self.afc = v;
+    }
+
+    /// Set signal frequency, e.g. when clicking on a waterfall.
+    pub fn set_frequency(&mut self, f: Float) {
+        self.frequency = f;
+        self.start_frequency = f;
+    }
+
+    /// Current signal frequency, including AFC correction.
+    pub fn frequency(&self) -> Float {
+        self.frequency
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<u8> {
+        self.dst.clone()
+    }
+
+    // Take one symbol sample, and maybe return a decoded char.
+    fn symbol(&mut self, s: Complex) -> Option<u8> {
+        let d = s * self.last_symbol.conj();
+        self.last_symbol = s;
+        let bit = d.re > 0.0;
+        if self.afc && d.norm_sqr() > 0.0 {
+            // Squaring removes the BPSK modulation, leaving twice the
+            // phase drift per symbol.
+            let drift = (d * d).arg() / 2.0;
+            let err_hz = drift * BAUD / (2.0 * std::f64::consts::PI as Float);
+            self.frequency += 0.05 * err_hz;
+            self.frequency = self.frequency.clamp(
+                self.start_frequency - AFC_RANGE,
+                self.start_frequency + AFC_RANGE,
+            );
+            trace!("Psk31Demod: freq error {err_hz}, now {}", self.frequency);
+        }
+        self.decoder.push(bit)
+    }
+
+    // Process one sample after decimation.
+    fn process_decimated(&mut self, s: Complex) -> Option<u8> {
+        self.history.pop_front();
+        self.history.push_back(s);
+        let y = self.filter.filter(self.history.make_contiguous());
+
+        // Symbol timing. Amplitude is highest in the middle of
+        // symbols, and dips during phase reversals. Nudge the sampling
+        // point towards the highest average energy.
+        let e = &mut self.energy[self.clock];
+        *e = 0.9 * *e + 0.1 * y.norm_sqr();
+        let clock = self.clock;
+        self.clock = (self.clock + 1) % RX_SPS;
+        self.countdown -= 1;
+        if self.countdown > 0 {
+            return None;
+        }
+        let best = self
+            .energy
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(n, _)| n)
+            .unwrap(); // unwrap: array is not empty.
+        let diff = (best + RX_SPS - clock) % RX_SPS;
+        self.countdown = match diff {
+            0 => RX_SPS,
+            d if d <= RX_SPS / 2 => RX_SPS + 1,
+            _ => RX_SPS - 1,
+        };
+        self.symbol(y)
+    }
+
+    fn process_one(&mut self, s: Complex) -> Option<u8> {
+        let rot = Complex::new(self.phase.cos() as Float, self.phase.sin() as Float);
+        self.phase -= 2.0 * std::f64::consts::PI * self.frequency as f64 / self.samp_rate as f64;
+        self.phase %= 2.0 * std::f64::consts::PI;
+        self.acc += s * rot;
+        self.acc_count += 1;
+        if self.acc_count < self.decim {
+            return None;
+        }
+        let v = self.acc / self.decim as Float;
+        self.acc = Complex::default();
+        self.acc_count = 0;
+        self.process_decimated(v)
+    }
+}
+
+impl Block for Psk31Demod {
+    fn block_name(&self) -> &str {
+        "Psk31Demod"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let obind = self.dst.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let mut o = obind.write_buf()?;
+        let n = std::cmp::min(input.len(), o.len());
+        let mut out = Vec::new();
+        for s in input.iter().take(n) {
+            if let Some(ch) = self.process_one(*s) {
+                debug!("Psk31Demod: got {:?}", ch as char);
+                out.push(ch);
+            }
+        }
+        o.fill_from_slice(&out);
+        o.produce(out.len(), &[]);
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+/** PSK31 modulator.
+
+Generates a Complex PSK31 signal at the given frequency, sending the
+text once, with a preamble of reversals and a postamble of carrier.
+
+Use `.re` of the output (e.g. via `Map`) to get audio for an SSB
+transmitter.
+*/
+pub struct Psk31Modulator {
+    dst: Streamp<Complex>,
+    bits: VecDeque<bool>,
+    sps: usize,
+    pos: usize,
+    prev_amp: Float,
+    amp: Float,
+    phase: f64,
+    rad_per_sample: f64,
+}
+
+impl Psk31Modulator {
+    /// Number of reversals sent before, and carrier symbols after, the
+    /// text.
+    pub const PREAMBLE: usize = 32;
+
+    /// Create new PSK31 modulator.
+    pub fn new(text: &str, samp_rate: Float, frequency: Float) -> Self {
+        let mut bits = VecDeque::new();
+        bits.extend(std::iter::repeat_n(false, Self::PREAMBLE));
+        bits.extend(varicode_encode(text));
+        bits.extend(std::iter::repeat_n(true, Self::PREAMBLE));
+        Self {
+            dst: new_streamp(),
+            bits,
+            sps: (samp_rate / BAUD).round() as usize,
+            pos: 0,
+            prev_amp: 1.0,
+            amp: 1.0,
+            phase: 0.0,
+            rad_per_sample: 2.0 * std::f64::consts::PI * frequency as f64 / samp_rate as f64,
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Complex> {
+        self.dst.clone()
+    }
+
+    fn next_sample(&mut self) -> Option<Complex> {
+        if self.pos == 0 {
+            let bit = self.bits.pop_front()?;
+            self.prev_amp = self.amp;
+            if !bit {
+                self.amp = -self.amp;
+            }
+        }
+        // Raised cosine transition from previous to current amplitude.
+        let x = std::f64::consts::PI as Float * self.pos as Float / self.sps as Float;
+        let w = 0.5 + 0.5 * x.cos();
+        let a = self.prev_amp * w + self.amp * (1.0 - w);
+        let s = a * Complex::new(self.phase.cos() as Float, self.phase.sin() as Float);
+        self.phase = (self.phase + self.rad_per_sample) % (2.0 * std::f64::consts::PI);
+        self.pos = (self.pos + 1) % self.sps;
+        Some(s)
+    }
+}
+
+impl Block for Psk31Modulator {
+    fn block_name(&self) -> &str {
+        "Psk31Modulator"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let obind = self.dst.clone();
+        let mut o = obind.write_buf()?;
+        let mut n = 0;
+        for place in o.slice().iter_mut() {
+            match self.next_sample() {
+                Some(s) => *place = s,
+                None => break,
+            }
+            n += 1;
+        }
+        if n == 0 {
+            return Ok(BlockRet::EOF);
+        }
+        o.produce(n, &[]);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varicode_table() {
+        let mut seen = std::collections::HashSet::new();
+        for code in VARICODE {
+            assert!(code.starts_with('1') && code.ends_with('1'), "{code}");
+            assert!(!code.contains("00"), "{code}");
+            assert!(seen.insert(code), "duplicate {code}");
+        }
+    }
+
+    #[test]
+    fn varicode_roundtrip() {
+        let text = "CQ CQ de SM0ABC pse k\r\n";
+        let mut d = VaricodeDecoder::new();
+        let got: Vec<u8> = varicode_encode(text)
+            .into_iter()
+            .filter_map(|b| d.push(b))
+            .collect();
+        assert_eq!(String::from_utf8(got).unwrap(), text);
+    }
+
+    fn loopback(text: &str, tx_freq: Float, rx_freq: Float) -> crate::Result<String> {
+        let samp_rate = 8000.0;
+        let mut tx = Psk31Modulator::new(text, samp_rate, tx_freq);
+        let mut rx = Psk31Demod::new(tx.out(), samp_rate, rx_freq)?;
+        let mut got = Vec::new();
+        loop {
+            let ret = tx.work()?;
+            rx.work()?;
+            let o = rx.out();
+            let (res, _) = o.read_buf()?;
+            got.extend(res.slice());
+            let n = res.len();
+            res.consume(n);
+            if matches!(ret, BlockRet::EOF) {
+                break;
+            }
+        }
+        Ok(String::from_utf8(got)?)
+    }
+
+    #[test]
+    fn modem_loopback() -> crate::Result<()> {
+        let text = "CQ CQ de SM0ABC";
+        assert_eq!(loopback(text, 1000.0, 1000.0)?, text);
+        Ok(())
+    }
+
+    #[test]
+    fn modem_afc() -> crate::Result<()> {
+        let text = "the quick brown fox";
+        assert!(loopback(text, 1005.0, 1000.0)?.contains("quick brown fox"));
+        Ok(())
+    }
+
+    #[test]
+    fn bad_rate() {
+        assert!(Psk31Demod::new(new_streamp(), 8001.0, 1000.0).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+/*! Source of samples pushed from outside the graph.
+
+[PushSource] outputs whatever is pushed into its [PushHandle], for
+when the samples come in callbacks rather than from something the
+block can read, such as WebAudio, WebUSB, or a file uploaded to a web
+page when running in the browser.
+
+```text
+  app -> PushHandle::push() -> PushSource -> …
+```
+
+Samples can be pushed either as values, or as raw bytes in the same
+format as a [FileSource][crate::blocks::FileSource] reads, e.g. `u8`
+I/Q from an RTL-SDR, or `c32` from an IQ file. Pushing never blocks,
+so check [PushHandle::pending] to not queue up too much.
+
+```
+use rustradio::graph::{Graph, RunState};
+use rustradio::blocks::{PushSource, RtlSdrDecode, NullSink};
+let src = PushSource::<u8>::new();
+let handle = src.handle();
+let dec = RtlSdrDecode::new(src.out());
+let sink = NullSink::new(dec.out());
+let mut g = Graph::new();
+g.add(Box::new(src));
+g.add(Box::new(dec));
+g.add(Box::new(sink));
+
+// E.g. from a WebUSB transfer callback.
+handle.push_bytes(&[127, 128, 200, 50])?;
+handle.close();
+while g.run_once()? != RunState::Done {}
+# Ok::<(), anyhow::Error>(())
+```
+*/
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, Streamp};
+use crate::{Error, Sample};
+
+struct Shared<T> {
+    queue: VecDeque<T>,
+    partial: Vec<u8>,
+    closed: bool,
+}
+
+/// Handle for pushing samples into a [PushSource].
+pub struct PushHandle<T> {
+    inner: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> Clone for PushHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Copy> PushHandle<T> {
+    /// Queue samples for output.
+    pub fn push(&self, samples: &[T]) {
+        self.inner.lock().unwrap().queue.extend(samples);
+    }
+
+    /// Signal end of stream. The source outputs EOF once the queue is
+    /// empty.
+    pub fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+    }
+
+    /// Number of samples queued, not yet output.
+    pub fn pending(&self) -> usize {
+        self.inner.lock().unwrap().queue.len()
+    }
+}
+
+impl<T> PushHandle<T>
+where
+    T: Sample<Type = T> + Copy,
+{
+    /// Queue samples serialized as raw bytes.
+    ///
+    /// The data doesn't need to end on a sample boundary. The rest is
+    /// kept for the next call.
+    pub fn push_bytes(&self, data: &[u8]) -> Result<()> {
+        let mut s = self.inner.lock().unwrap();
+        let size = T::size();
+        let mut data = data;
+        if !s.partial.is_empty() {
+            let n = (size - s.partial.len()).min(data.len());
+            s.partial.extend(&data[..n]);
+            data = &data[n..];
+            if s.partial.len() < size {
+                return Ok(());
+            }
+            let v = T::parse(&s.partial)?;
+            s.queue.push_back(v);
+            s.partial.clear();
+        }
+        let whole = data.len() / size * size;
+        for chunk in data[..whole].chunks_exact(size) {
+            let v = T::parse(chunk)?;
+            s.queue.push_back(v);
+        }
+        s.partial.extend(&data[whole..]);
+        Ok(())
+    }
+}
+
+/// Output samples pushed through a [PushHandle].
+pub struct PushSource<T: Copy> {
+    inner: Arc<Mutex<Shared<T>>>,
+    dst: Streamp<T>,
+}
+
+impl<T: Copy> PushSource<T> {
+    /// Create new PushSource.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Shared {
+                queue: VecDeque::new(),
+                partial: Vec::new(),
+                closed: false,
+            })),
+            dst: new_streamp(),
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<T> {
+        self.dst.clone()
+    }
+
+    /// Return a handle for pushing samples.
+    pub fn handle(&self) -> PushHandle<T> {
+        PushHandle {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Copy> Default for PushSource<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy> Block for PushSource<T> {
+    fn block_name(&self) -> &str {
+        "PushSource"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let mut s = self.inner.lock().unwrap();
+        if s.queue.is_empty() {
+            return Ok(if s.closed {
+                BlockRet::EOF
+            } else {
+                BlockRet::Pending
+            });
+        }
+        let mut o = self.dst.write_buf()?;
+        let n = std::cmp::min(o.len(), s.queue.len());
+        if n == 0 {
+            return Ok(BlockRet::Pending);
+        }
+        o.fill_from_iter(s.queue.drain(..n));
+        o.produce(n, &[]);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push() -> Result<()> {
+        let mut src = PushSource::<u8>::new();
+        let h = src.handle();
+        assert!(matches!(src.work()?, BlockRet::Pending));
+        h.push(&[1, 2]);
+        h.push(&[3]);
+        assert_eq!(h.pending(), 3);
+        assert!(matches!(src.work()?, BlockRet::Ok));
+        assert_eq!(h.pending(), 0);
+        h.close();
+        assert!(matches!(src.work()?, BlockRet::EOF));
+        let (res, _) = src.dst.read_buf()?;
+        assert_eq!(res.slice(), &[1, 2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn push_bytes() -> Result<()> {
+        let mut src = PushSource::<u32>::new();
+        let h = src.handle();
+        h.push_bytes(&[1, 0])?;
+        h.push_bytes(&[0])?;
+        assert_eq!(h.pending(), 0);
+        h.push_bytes(&[0, 2, 0, 0, 0, 3, 0])?;
+        h.push_bytes(&[0, 0])?;
+        assert_eq!(h.pending(), 3);
+        src.work()?;
+        let (res, _) = src.dst.read_buf()?;
+        assert_eq!(res.slice(), &[1, 2, 3]);
+        Ok(())
+    }
+}
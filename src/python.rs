@@ -0,0 +1,385 @@
+/*! Python bindings.
+
+Only available with the `python` feature. Build the Python module
+with [maturin](https://www.maturin.rs/), which turns it on:
+
+```text
+$ maturin develop --release
+```
+
+Blocks are created by name from the [block
+registry][crate::registry::Registry], and connected by passing the
+streams returned by one block to the next:
+
+```text
+import rustradio
+
+g = rustradio.Graph()
+src = g.add("file_source_c", filename="in.c32")
+src = g.add("fft_filter_c", src, samp_rate=1e6, cutoff=100e3, twidth=10e3)
+src = g.add("rational_resampler_c", src, interp=1, deci=20)
+src = g.add("quadrature_demod", src)
+g.add("file_sink_f", src, filename="out.f32")
+print(g.run())
+```
+
+The graph runs in its own thread, without holding the GIL. `run()`
+waits for it to finish, and cancels it on Ctrl-C. For more control, use
+`start()`, `cancel()`, and `wait()`.
+
+Parameters are checked, and blocks created, when they are added, so
+mistakes raise right there:
+
+* Unknown blocks or bad parameters raise `ValueError`.
+* Connecting the wrong type of stream raises `TypeError`.
+* I/O errors raise `OSError`.
+
+`to_json()` saves the blocks, connections, and parameters of a graph,
+and `Graph.from_json()` loads them again.
+
+`rustradio.blocks()` lists the blocks, and
+`rustradio.block_info(name)` describes one.
+
+Samples go from and to Python through `vector_source_*` and files,
+e.g. read with `numpy.fromfile(name, dtype=numpy.complex64)`.
+*/
+// Triggered by the code #[pymethods] generates.
+#![allow(clippy::useless_conversion)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use pyo3::exceptions::{PyIOError, PyRuntimeError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyComplex, PyDict, PyString, PyTuple};
+
+use crate::registry::{default_registry, DynGraph, GraphSpec, Params, StreamKind};
+use crate::Error;
+
+impl From<Error> for PyErr {
+    fn from(e: Error) -> PyErr {
+        let msg = e.to_string();
+        match e.root() {
+            Error::BadParameter(_) => PyValueError::new_err(msg),
+            Error::TypeMismatch(_) => PyTypeError::new_err(msg),
+            Error::Io(_) => PyIOError::new_err(msg),
+            _ => PyRuntimeError::new_err(msg),
+        }
+    }
+}
+
+// Convert a Python keyword argument to a block parameter.
+fn to_value(obj: &Bound<PyAny>) -> PyResult<serde_json::Value> {
+    use serde_json::Value;
+    if obj.is_instance_of::<PyBool>() {
+        return Ok(Value::Bool(obj.extract()?));
+    }
+    if let Ok(v) = obj.extract::<i64>() {
+        return Ok(v.into());
+    }
+    if let Ok(v) = obj.extract::<f64>() {
+        return Ok(v.into());
+    }
+    if let Ok(c) = obj.downcast::<PyComplex>() {
+        return Ok(serde_json::json!([c.real(), c.imag()]));
+    }
+    if let Ok(s) = obj.downcast::<PyString>() {
+        return Ok(Value::String(s.to_str()?.to_string()));
+    }
+    if let Ok(it) = obj.iter() {
+        return Ok(Value::Array(
+            it.map(|v| to_value(&v?)).collect::<PyResult<Vec<_>>>()?,
+        ));
+    }
+    Err(PyTypeError::new_err(format!(
+        "unsupported parameter type {}",
+        obj.get_type().name()?
+    )))
+}
+
+/// Stream between two blocks.
+#[pyclass(module = "rustradio", frozen)]
+struct Stream {
+    graph: usize,
+    id: usize,
+    kind: StreamKind,
+}
+
+#[pymethods]
+impl Stream {
+    /// Sample type: "float", "complex", "u8", or "pdu".
+    #[getter]
+    fn kind(&self) -> String {
+        self.kind.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<rustradio.Stream {} {}>", self.id, self.kind)
+    }
+}
+
+/// Graph of blocks.
+///
+/// A graph can only be run once.
+#[pyclass(module = "rustradio")]
+struct Graph {
+    id: usize,
+    graph: DynGraph,
+}
+
+#[pymethods]
+impl Graph {
+    #[new]
+    fn new() -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            graph: DynGraph::new(default_registry()),
+        }
+    }
+
+    /// Add a block, connected to the given input streams.
+    ///
+    /// Returns None, the output stream, or a tuple of output streams,
+    /// depending on how many outputs the block has.
+    #[pyo3(signature = (name, *inputs, **params))]
+    fn add(
+        &mut self,
+        py: Python<'_>,
+        name: &str,
+        inputs: &Bound<PyTuple>,
+        params: Option<&Bound<PyDict>>,
+    ) -> PyResult<PyObject> {
+        let mut p = Params::new();
+        for (k, v) in params.into_iter().flat_map(|d| d.iter()) {
+            p.insert(k.extract()?, to_value(&v)?);
+        }
+        let mut ids = Vec::new();
+        for i in inputs.iter() {
+            let s = i.downcast::<Stream>()?.get();
+            if s.graph != self.id {
+                return Err(PyValueError::new_err("stream is from another graph"));
+            }
+            ids.push(s.id);
+        }
+        let mut outs = Vec::new();
+        for id in self.graph.add(name, &ids, &p)? {
+            outs.push(Py::new(
+                py,
+                Stream {
+                    graph: self.id,
+                    id,
+                    kind: self.graph.kind(id).unwrap(),
+                },
+            )?);
+        }
+        Ok(match outs.len() {
+            0 => py.None(),
+            1 => outs.remove(0).into_py(py),
+            _ => PyTuple::new_bound(py, outs).into_py(py),
+        })
+    }
+
+    /// Blocks, connections, and parameters of the graph, as JSON.
+    fn to_json(&self) -> PyResult<String> {
+        Ok(self.graph.spec()?.to_json())
+    }
+
+    /// Create graph from JSON made by `to_json()`.
+    ///
+    /// Streams of the loaded graph can't be connected to from Python.
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        let spec = GraphSpec::from_json(json)?;
+        let mut g = Self::new();
+        g.graph = DynGraph::from_spec(default_registry(), &spec)?;
+        Ok(g)
+    }
+
+    /// Start running the graph in the background.
+    fn start(&mut self) -> PyResult<()> {
+        self.graph.start()?;
+        Ok(())
+    }
+
+    /// Ask the running graph to stop.
+    fn cancel(&self) {
+        self.graph.cancel();
+    }
+
+    /// True if the graph has been started, and not yet finished.
+    #[getter]
+    fn running(&self) -> bool {
+        self.graph.started() && !self.graph.finished()
+    }
+
+    /// Wait for the graph to finish, and return block statistics.
+    ///
+    /// On Ctrl-C the graph is cancelled, and KeyboardInterrupt raised
+    /// once it has stopped.
+    fn wait(&mut self, py: Python<'_>) -> PyResult<String> {
+        if !self.graph.started() {
+            return Err(PyRuntimeError::new_err("graph not started"));
+        }
+        let mut interrupted = None;
+        while !self.graph.finished() {
+            py.allow_threads(|| std::thread::sleep(Duration::from_millis(50)));
+            if let Err(e) = py.check_signals() {
+                self.graph.cancel();
+                interrupted.get_or_insert(e);
+            }
+        }
+        let res = self.graph.wait();
+        if let Some(e) = interrupted {
+            return Err(e);
+        }
+        Ok(res?)
+    }
+
+    /// Run the graph until done, and return block statistics.
+    fn run(&mut self, py: Python<'_>) -> PyResult<String> {
+        self.start()?;
+        self.wait(py)
+    }
+}
+
+/// Names of all blocks that can be added to a graph.
+#[pyfunction]
+fn blocks() -> Vec<String> {
+    default_registry()
+        .names()
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+/// Description, input and output types, and parameters of a block.
+#[pyfunction]
+fn block_info(py: Python<'_>, name: &str) -> PyResult<PyObject> {
+    let i = default_registry()
+        .info(name)
+        .ok_or_else(|| PyValueError::new_err(format!("unknown block {name}")))?;
+    let d = PyDict::new_bound(py);
+    d.set_item("name", &i.name)?;
+    d.set_item("description", &i.description)?;
+    d.set_item(
+        "inputs",
+        i.inputs.iter().map(|k| k.to_string()).collect::<Vec<_>>(),
+    )?;
+    d.set_item(
+        "outputs",
+        i.outputs.iter().map(|k| k.to_string()).collect::<Vec<_>>(),
+    )?;
+    d.set_item("params", &i.params)?;
+    Ok(d.into_py(py))
+}
+
+#[pymodule]
+fn rustradio(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Graph>()?;
+    m.add_class::<Stream>()?;
+    m.add_function(wrap_pyfunction!(blocks, m)?)?;
+    m.add_function(wrap_pyfunction!(block_info, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::IntoPyDict;
+    use serde_json::json;
+
+    fn with_py<R>(f: impl FnOnce(Python<'_>) -> R) -> R {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(f)
+    }
+
+    #[test]
+    fn values() -> PyResult<()> {
+        with_py(|py| {
+            let v = |code: &str| to_value(&py.eval_bound(code, None, None)?);
+            assert_eq!(v("True")?, json!(true));
+            assert_eq!(v("-3")?, json!(-3));
+            assert_eq!(v("2.5")?, json!(2.5));
+            assert_eq!(v("1-2j")?, json!([1.0, -2.0]));
+            assert_eq!(v("'abc'")?, json!("abc"));
+            assert_eq!(
+                v("[1, 2.5, 'x', (True, 1j)]")?,
+                json!([1, 2.5, "x", [true, [0.0, 1.0]]])
+            );
+            let e = v("None").unwrap_err();
+            assert!(e.is_instance_of::<PyTypeError>(py), "{e}");
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn errors() {
+        with_py(|py| {
+            let io = std::io::Error::new(std::io::ErrorKind::NotFound, "nope");
+            for (e, want) in [
+                (Error::BadParameter("x".into()), "ValueError"),
+                (Error::TypeMismatch("x".into()), "TypeError"),
+                (io.into(), "OSError"),
+                (Error::new("x"), "RuntimeError"),
+                (
+                    Error::BadParameter("x".into()).in_block("foo", 2),
+                    "ValueError",
+                ),
+            ] {
+                let msg = e.to_string();
+                let e = PyErr::from(e);
+                assert_eq!(e.get_type_bound(py).name().unwrap(), want, "{msg}");
+                assert_eq!(e.value_bound(py).to_string(), msg);
+            }
+        })
+    }
+
+    #[test]
+    fn graph() -> PyResult<()> {
+        let dir = tempfile::tempdir()?;
+        let out = dir.path().join("out.f32");
+        with_py(|py| {
+            let m = PyModule::new_bound(py, "rustradio")?;
+            rustradio(&m)?;
+            let locals = [("rustradio", m.into_any())].into_py_dict_bound(py);
+            locals.set_item("out", out.to_str().unwrap())?;
+            py.run_bound(
+                r#"
+assert "add_const_f" in rustradio.blocks()
+assert rustradio.block_info("add_const_f")["inputs"] == ["float"]
+
+bad = rustradio.Graph()
+src = bad.add("vector_source_f", data=[1.0])
+try:
+    bad.add("quadrature_demod", src)
+    assert False
+except TypeError:
+    pass
+try:
+    bad.add("add_const_f", src, value="x")
+    assert False
+except ValueError:
+    pass
+
+g = rustradio.Graph()
+src = g.add("vector_source_f", data=[1.0, 2.0, 3.0])
+assert src.kind == "float"
+src = g.add("multiply_const_f", src, value=2)
+g.add("file_sink_f", src, filename=out)
+stats = g.run()
+assert isinstance(stats, str)
+assert not g.running
+"#,
+                None,
+                Some(&locals),
+            )
+        })?;
+        let got: Vec<f32> = std::fs::read(&out)?
+            .chunks(4)
+            .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        assert_eq!(got, vec![2.0, 4.0, 6.0]);
+        Ok(())
+    }
+}
@@ -20,19 +20,78 @@ frequency changes (FM, FSK, …), not things like QAM.
 [This article][vectorized] gives some good illustrations.
 
 Enabling the `fast-math` feature (dependency) speeds up
-QuadratureDemod by about 4x.
+QuadratureDemod by about 4x. Or, per block, use the approximation from
+[the article][vectorized] with [QuadratureDemodBuilder::fast_atan2].
+Its error is below 0.00001 radians, which is plenty for FM.
 
 [vectorized]: https://mazzo.li/posts/vectorized-atan2.html
  */
 use anyhow::Result;
 
+use crate::block::{Block, BlockRet};
 use crate::stream::{new_streamp, Streamp};
-use crate::{map_block_convert_macro, Complex, Float};
+use crate::{kernels, map_block_convert_macro, Complex, Error, Float};
+
+/// Approximate atan2, with error less than 0.00001 radians.
+///
+/// A polynomial for atan on [-1, 1], with the octants mapped onto
+/// that. From <https://mazzo.li/posts/vectorized-atan2.html>.
+pub fn fast_atan2(y: Float, x: Float) -> Float {
+    const PI: Float = std::f64::consts::PI as Float;
+    const PI_2: Float = std::f64::consts::FRAC_PI_2 as Float;
+    let swap = x.abs() < y.abs();
+    if !swap && x == 0.0 {
+        // Both zero.
+        return 0.0;
+    }
+    let a = if swap { x / y } else { y / x };
+    let a2 = a * a;
+    let mut r = a
+        * (0.999_977_26
+            + a2 * (-0.332_623_47
+                + a2 * (0.193_543_46
+                    + a2 * (-0.116_432_87 + a2 * (0.052_653_32 + a2 * -0.011_721_2)))));
+    if swap {
+        r = if a >= 0.0 { PI_2 } else { -PI_2 } - r;
+    }
+    if x < 0.0 {
+        r += if y >= 0.0 { PI } else { -PI };
+    }
+    r
+}
+
+/// Builder for [QuadratureDemod].
+pub struct QuadratureDemodBuilder {
+    demod: QuadratureDemod,
+}
+
+impl QuadratureDemodBuilder {
+    /// Create new builder.
+    pub fn new(src: Streamp<Complex>, gain: Float) -> Self {
+        Self {
+            demod: QuadratureDemod::new(src, gain),
+        }
+    }
+
+    /// Use [fast_atan2] instead of the exact atan2. Default false.
+    pub fn fast_atan2(mut self, v: bool) -> Self {
+        self.demod.fast = v;
+        self
+    }
+
+    /// Build QuadratureDemod block.
+    pub fn build(self) -> QuadratureDemod {
+        self.demod
+    }
+}
 
 /// Quadrature demod, the core of an FM demodulator.
 pub struct QuadratureDemod {
     gain: Float,
+    fast: bool,
     last: Complex,
+    // Phase differences, reused between calls.
+    tmp: Vec<Complex>,
     src: Streamp<Complex>,
     dst: Streamp<Float>,
 }
@@ -47,12 +106,26 @@ impl QuadratureDemod {
             src,
             dst: new_streamp(),
             gain,
+            fast: false,
             last: Complex::default(),
+            tmp: Vec::new(),
         }
     }
-    fn process_one(&mut self, s: Complex) -> Float {
-        let t = s * self.last.conj();
-        self.last = s;
+
+    /// Create a builder.
+    pub fn builder(src: Streamp<Complex>, gain: Float) -> QuadratureDemodBuilder {
+        QuadratureDemodBuilder::new(src, gain)
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Float> {
+        self.dst.clone()
+    }
+
+    fn angle(&self, t: Complex) -> Float {
+        if self.fast {
+            return self.gain * fast_atan2(t.im, t.re);
+        }
 
         #[cfg(feature = "fast-math")]
         return self.gain * fast_math::atan2(t.im, t.re);
@@ -61,7 +134,34 @@ impl QuadratureDemod {
         return self.gain * t.im.atan2(t.re);
     }
 }
-map_block_convert_macro![QuadratureDemod, Float];
+
+impl Block for QuadratureDemod {
+    fn block_name(&self) -> &str {
+        "QuadratureDemod"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (i, tags) = self.src.read_buf()?;
+        let mut o = self.dst.write_buf()?;
+        let n = std::cmp::min(i.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        let input = &i.slice()[..n];
+
+        // Each sample times the conjugate of the one before it.
+        self.tmp.resize(n, Complex::default());
+        self.tmp[0] = input[0] * self.last.conj();
+        kernels::multiply_conj(&input[1..], &input[..n - 1], &mut self.tmp[1..]);
+        self.last = input[n - 1];
+
+        for (place, t) in o.slice().iter_mut().zip(&self.tmp) {
+            *place = self.angle(*t);
+        }
+        o.produce(n, &tags);
+        i.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
 
 /// A faster version of FM demodulation, that makes some assumptions.
 ///
@@ -118,3 +218,65 @@ impl FastFM {
     }
 }
 map_block_convert_macro![FastFM, Float];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::new_streamp;
+    use crate::tests::assert_almost_equal_float;
+
+    #[test]
+    fn demod() -> Result<()> {
+        // Rotating a quarter turn per sample, across two work() calls.
+        let input: Vec<Complex> = (0..20)
+            .map(|n| Complex::from_polar(1.0, n as Float * std::f64::consts::FRAC_PI_2 as Float))
+            .collect();
+        let src = new_streamp();
+        let mut b = QuadratureDemod::new(src.clone(), 2.0);
+        for chunk in input.chunks(10) {
+            let mut w = src.write_buf()?;
+            w.fill_from_slice(chunk);
+            w.produce(chunk.len(), &[]);
+            b.work()?;
+        }
+        let mut want = vec![std::f64::consts::PI as Float; 20];
+        want[0] = 0.0;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        assert_almost_equal_float(res.slice(), &want);
+        Ok(())
+    }
+
+    #[test]
+    fn fast_atan2_error() {
+        assert_eq!(fast_atan2(0.0, 0.0), 0.0);
+        let mut worst: Float = 0.0;
+        for i in 0..3600 {
+            let a = (i as Float / 10.0 - 180.0).to_radians();
+            for m in [0.001, 1.0, 1000.0] {
+                let (y, x) = (m * a.sin(), m * a.cos());
+                worst = worst.max((fast_atan2(y, x) - y.atan2(x)).abs());
+            }
+        }
+        assert!(worst < 0.00001, "worst error {worst}");
+    }
+
+    #[test]
+    fn fast_demod() -> Result<()> {
+        let input: Vec<Complex> = (0..100)
+            .map(|n| Complex::from_polar(1.0, n as Float * 0.3))
+            .collect();
+        let src = new_streamp();
+        let mut w = src.write_buf()?;
+        w.fill_from_slice(&input);
+        w.produce(input.len(), &[]);
+        let mut b = QuadratureDemod::builder(src, 1.0).fast_atan2(true).build();
+        b.work()?;
+        let mut want = vec![0.3; 100];
+        want[0] = 0.0;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        assert_almost_equal_float(res.slice(), &want);
+        Ok(())
+    }
+}
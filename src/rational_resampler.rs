@@ -1,13 +1,22 @@
-//! Resample by a fractional amount.
-/*
-* Unlike the rational resampler in GNURadio, this one doesn't filter.
- */
+/*! Resample by a fractional amount.
+
+[RationalResampler::new] doesn't filter. It repeats or drops samples,
+so the signal needs to be filtered first, to not alias.
+
+[RationalResampler::filtered] and [RationalResampler::with_taps] use a
+polyphase filter instead, like GNU Radio. It's the same as inserting
+`interp-1` zeros between samples, low pass filtering, and keeping every
+`deci`th sample, except that only the kept samples are calculated, and
+never the multiplications by the zeros. That also makes a separate
+anti-alias filter before the resampler unnecessary.
+*/
 use anyhow::Result;
 use log::trace;
 
 use crate::block::{Block, BlockRet};
-use crate::stream::{new_streamp, Streamp};
-use crate::Error;
+use crate::kernels::Dot;
+use crate::stream::{new_streamp, Streamp, Tag};
+use crate::{Error, Float};
 
 fn gcd(mut a: usize, mut b: usize) -> usize {
     while b != 0 {
@@ -18,17 +27,62 @@ fn gcd(mut a: usize, mut b: usize) -> usize {
     a
 }
 
+// Polyphase filter state.
+struct Polyphase<T> {
+    // One filter per phase, reversed so that the newest sample is
+    // multiplied with the last tap.
+    phases: Vec<Vec<T>>,
+
+    // Input samples, starting with the `ntaps-1` from before.
+    buf: Vec<T>,
+
+    // Position of the next output, in upsampled samples, from the
+    // first sample after the history in `buf`.
+    pos: usize,
+    dot: fn(&[T], &[T]) -> T,
+
+    // Tags not yet passed on, with positions counted like `pos`, but
+    // in input samples.
+    tags: Vec<Tag>,
+}
+
+impl<T: Dot> Polyphase<T> {
+    fn new(taps: &[T], interp: usize) -> Self {
+        let ntaps = taps.len().div_ceil(interp).max(1);
+        let phases = (0..interp)
+            .map(|p| {
+                let mut v: Vec<T> = (0..ntaps)
+                    .map(|j| taps.get(p + j * interp).copied().unwrap_or_default())
+                    .collect();
+                v.reverse();
+                v
+            })
+            .collect();
+        Self {
+            phases,
+            buf: vec![T::default(); ntaps - 1],
+            pos: 0,
+            dot: T::dot,
+            tags: Vec::new(),
+        }
+    }
+}
+
 /// Resample by a fractional amount.
 pub struct RationalResampler<T: Copy> {
     deci: i64,
     interp: i64,
     counter: i64,
+    filter: Option<Polyphase<T>>,
+
+    // Tags on input that was consumed without producing output yet.
+    pending: Vec<Tag>,
     src: Streamp<T>,
     dst: Streamp<T>,
 }
 
 impl<T: Copy> RationalResampler<T> {
-    /// Create new RationalResampler block.
+    /// Create new RationalResampler block, that doesn't filter.
     ///
     /// A common pattern to convert between arbitrary sample rates X
     /// and Y is to decimate by X and interpolate by Y.
@@ -40,15 +94,112 @@ impl<T: Copy> RationalResampler<T> {
             interp: i64::try_from(interp)?,
             deci: i64::try_from(deci)?,
             counter: 0,
+            filter: None,
+            pending: Vec::new(),
             src,
             dst: new_streamp(),
         })
     }
 
+    /// Create new polyphase RationalResampler, with a low pass
+    /// filter keeping 80% of the output bandwidth.
+    pub fn filtered(src: Streamp<T>, interp: usize, deci: usize) -> Result<Self>
+    where
+        T: Dot + From<Float>,
+    {
+        let g = gcd(deci, interp);
+        let (i, d) = ((interp / g) as Float, (deci / g) as Float);
+
+        // Same as GNU Radio's default, with fractional bandwidth 0.4,
+        // in units of the input sample rate.
+        let rate = (i / d).min(1.0);
+        let twidth = rate * 0.1;
+        let cutoff = rate * 0.5 - twidth / 2.0;
+        let taps: Vec<T> = crate::fir::low_pass(i, cutoff, twidth)
+            .into_iter()
+            .map(|t| T::from(t * i))
+            .collect();
+        Self::with_taps(src, interp, deci, &taps)
+    }
+
+    /// Create new polyphase RationalResampler, with the given taps.
+    ///
+    /// `interp` and `deci` are first divided by their greatest common
+    /// divisor. The taps are for the interpolated sample rate, the
+    /// input rate times the reduced `interp`, and need a gain of the
+    /// reduced `interp` to keep the amplitude.
+    pub fn with_taps(src: Streamp<T>, interp: usize, deci: usize, taps: &[T]) -> Result<Self>
+    where
+        T: Dot,
+    {
+        if interp == 0 || deci == 0 {
+            return Err(Error::BadParameter(format!(
+                "RationalResampler: interp ({interp}) and deci ({deci}) must be positive"
+            ))
+            .into());
+        }
+        let mut b = Self::new(src, interp, deci)?;
+        b.filter = Some(Polyphase::new(taps, b.interp as usize));
+        Ok(b)
+    }
+
     /// Return the output stream.
     pub fn out(&self) -> Streamp<T> {
         self.dst.clone()
     }
+
+    // Polyphase filter, calculating only the kept outputs.
+    fn work_filtered(&mut self) -> Result<BlockRet, Error> {
+        let (interp, deci) = (self.interp as usize, self.deci as usize);
+        let f = self.filter.as_mut().unwrap();
+        let ntaps = f.phases[0].len();
+        let (i, tags) = self.src.read_buf()?;
+        let mut o = self.dst.write_buf()?;
+
+        // Don't read much more than there's output space for.
+        let take = std::cmp::min(i.len(), (o.len() * deci).div_ceil(interp) + 1);
+        let offset = f.buf.len() + 1 - ntaps;
+        f.tags.extend(
+            tags.iter()
+                .filter(|t| t.pos() < take)
+                .map(|t| t.with_pos(t.pos() + offset)),
+        );
+        f.buf.extend_from_slice(&i.slice()[..take]);
+        i.consume(take);
+        let avail = f.buf.len() + 1 - ntaps;
+
+        let mut n = 0;
+        let mut otags = Vec::new();
+        while n < o.len() && f.pos / interp < avail {
+            let start = f.pos / interp;
+            let phase = &f.phases[f.pos % interp];
+            o.slice()[n] = (f.dot)(&f.buf[start..start + ntaps], phase);
+
+            // Tags go on the first output that includes their sample.
+            f.tags.retain(|t| {
+                if t.pos() > start {
+                    return true;
+                }
+                otags.push(t.with_pos(n));
+                false
+            });
+            f.pos += deci;
+            n += 1;
+        }
+
+        // Drop input that no more outputs need.
+        let used = std::cmp::min(f.pos / interp, avail);
+        f.buf.drain(..used);
+        f.pos -= used * interp;
+        for t in &mut f.tags {
+            *t = t.with_pos(t.pos().saturating_sub(used));
+        }
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        o.produce(n, &otags);
+        Ok(BlockRet::Ok)
+    }
 }
 
 impl<T: Copy> Block for RationalResampler<T> {
@@ -56,7 +207,10 @@ impl<T: Copy> Block for RationalResampler<T> {
         "RationalResampler"
     }
     fn work(&mut self) -> Result<BlockRet, Error> {
-        let (i, _tags) = self.src.read_buf()?;
+        if self.filter.is_some() {
+            return self.work_filtered();
+        }
+        let (i, tags) = self.src.read_buf()?;
         let mut o = self.dst.write_buf()?;
         if i.len() < self.interp as usize || o.len() < self.deci as usize {
             return Ok(BlockRet::Noop);
@@ -68,7 +222,15 @@ impl<T: Copy> Block for RationalResampler<T> {
         }
         let mut opos = 0;
         let mut taken = 0;
+        let mut otags = std::mem::take(&mut self.pending);
         'outer: for s in i.iter() {
+            // Tags go on the first output from their sample, or the
+            // next output if the sample is dropped.
+            otags.extend(
+                tags.iter()
+                    .filter(|t| t.pos() == taken)
+                    .map(|t| t.with_pos(opos)),
+            );
             taken += 1;
             self.counter += self.interp;
             while self.counter > 0 {
@@ -81,7 +243,12 @@ impl<T: Copy> Block for RationalResampler<T> {
             }
         }
         i.consume(taken);
-        o.produce(opos, &[]);
+        self.pending = otags
+            .iter()
+            .filter(|t| t.pos() >= opos)
+            .map(|t| t.with_pos(0))
+            .collect();
+        o.produce(opos, &otags);
         Ok(BlockRet::Ok)
     }
 }
@@ -111,6 +278,86 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn polyphase() -> Result<()> {
+        use crate::tests::assert_almost_equal_float;
+        let input: Vec<Float> = (0..200).map(|n| ((n * 17) % 11) as Float - 5.0).collect();
+        let taps: Vec<Float> = (0..10).map(|n| 1.0 / (n + 1) as Float).collect();
+        for (interp, deci) in [(3, 2), (2, 3), (1, 4), (5, 1), (4, 6)] {
+            // Insert zeros, filter, and decimate, the slow way.
+            let want: Vec<Float> = {
+                let g = gcd(interp, deci);
+                let (i, d) = (interp / g, deci / g);
+                let up: Vec<Float> = (0..input.len() * i)
+                    .map(|k| if k % i == 0 { input[k / i] } else { 0.0 })
+                    .collect();
+                (0..up.len())
+                    .step_by(d)
+                    .map(|k| {
+                        (0..=k.min(taps.len() - 1))
+                            .map(|j| taps[j] * up[k - j])
+                            .sum()
+                    })
+                    .collect()
+            };
+
+            let src = new_streamp();
+            let mut b = RationalResampler::with_taps(src.clone(), interp, deci, &taps)?;
+            for chunk in input.chunks(13) {
+                let mut w = src.write_buf()?;
+                w.fill_from_slice(chunk);
+                w.produce(chunk.len(), &[]);
+                b.work()?;
+            }
+            let o = b.out();
+            let (res, _) = o.read_buf()?;
+            assert_eq!(res.len(), want.len(), "{interp}/{deci}");
+            assert_almost_equal_float(res.slice(), &want);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn tags() -> Result<()> {
+        use crate::stream::TagValue;
+        let tag = |pos| Tag::new(pos, "t".into(), TagValue::U64(pos as u64));
+        for filtered in [false, true] {
+            for (interp, deci, want) in [(2, 1, [0, 20, 22, 102]), (1, 2, [0, 5, 6, 26])] {
+                let src = new_streamp();
+                let mut b = if filtered {
+                    RationalResampler::with_taps(src.clone(), interp, deci, &[1.0, 1.0, 1.0])?
+                } else {
+                    RationalResampler::new(src.clone(), interp, deci)?
+                };
+                // Two writes, to check tags carried between calls.
+                src.write_buf()?.produce(50, &[tag(0), tag(10), tag(11)]);
+                b.work()?;
+                src.write_buf()?.produce(50, &[tag(1)]);
+                b.work()?;
+                let o = b.out();
+                let (_, got) = o.read_buf()?;
+                let got: Vec<usize> = got.iter().map(|t| t.pos()).collect();
+                assert_eq!(got, want, "filtered={filtered} {interp}/{deci}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn filtered() -> Result<()> {
+        let mut src = VectorSource::new(vec![Complex::new(1.0, -1.0); 3000]);
+        src.work()?;
+        let mut b = RationalResampler::filtered(src.out(), 2, 3)?;
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        assert_eq!(res.len(), 2000);
+        for s in &res.slice()[1000..] {
+            assert!((s - Complex::new(1.0, -1.0)).norm() < 0.01, "got {s}");
+        }
+        Ok(())
+    }
+
     #[test]
     fn foo() -> Result<()> {
         runtest(10, 1, 1, 10)?;
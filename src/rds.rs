@@ -0,0 +1,808 @@
+/*! RDS / RBDS decoder.
+
+[RDS][rds] is the digital side channel on broadcast FM, carrying the
+station name, radiotext, clock time, and more. It's sent as 1187.5 bps
+differentially encoded biphase BPSK, on a 57kHz subcarrier in the FM
+multiplex.
+
+Input to [RdsDecode] is the output of the FM demodulator (before
+de-emphasis and audio filtering), at a sample rate that's a multiple of
+19kHz, e.g. 228kHz. Output is an [RdsStation] every time the decoded
+station data changes.
+
+```text
+  [ IQ ] → [ QuadratureDemod ] → [ RdsDecode ] → (RdsStation)
+```
+
+With a broadcast FM receiver, the multiplex comes from
+[WbfmDecode::out_mpx](crate::wbfm::WbfmDecode::out_mpx). See
+`examples/fm-radio.rs`.
+
+The decoding steps are:
+
+* Mix the 57kHz subcarrier down to baseband, low pass, and decimate to
+  16 samples per bit.
+* Biphase matched filter, and bit timing recovery.
+* BPSK carrier phase tracking, and differential decoding.
+* Block synchronization using the offset words, correcting burst
+  errors of up to two bits.
+* Group parsing into PI, PTY, PS, RT, and CT.
+
+[rds]: https://en.wikipedia.org/wiki/Radio_Data_System
+*/
+use anyhow::Result;
+use log::{debug, info, trace};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp, Streamp};
+use crate::{Complex, Error, Float};
+
+/// RDS subcarrier frequency.
+pub const CARRIER: Float = 57000.0;
+
+/// RDS bit rate.
+pub const BIT_RATE: Float = 1187.5;
+
+const SAMPLES_PER_BIT: usize = 16;
+const DECIMATED_RATE: Float = BIT_RATE * SAMPLES_PER_BIT as Float;
+
+const BLOCK_BITS: usize = 26;
+const POLY: u32 = 0x5b9;
+
+// Offset words A, B, C, C', D.
+const OFFSETS: [u16; 5] = [0x0fc, 0x198, 0x168, 0x350, 0x1b4];
+
+// Block index (0-3) for each offset word.
+const OFFSET_BLOCK: [usize; 5] = [0, 1, 2, 2, 3];
+
+// Give up sync after this many bad blocks in a row.
+const MAX_BAD_BLOCKS: usize = 10;
+
+/// Calculate the syndrome of a 26 bit block.
+///
+/// For a correct block this is the offset word.
+pub fn syndrome(block: u32) -> u16 {
+    let mut reg = block & ((1 << BLOCK_BITS) - 1);
+    for n in (10..BLOCK_BITS).rev() {
+        if reg & (1 << n) != 0 {
+            reg ^= POLY << (n - 10);
+        }
+    }
+    reg as u16
+}
+
+/// Create a 26 bit block from 16 bits of data and the offset word.
+pub fn encode_block(data: u16, offset: u16) -> u32 {
+    let word = (data as u32) << 10;
+    word | (syndrome(word) ^ offset) as u32
+}
+
+/// One RDS group. Blocks that failed error correction are None.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Group {
+    /// The four blocks A-D.
+    pub blocks: [Option<u16>; 4],
+}
+
+impl Group {
+    /// Group type, 0-15.
+    pub fn group_type(&self) -> Option<u8> {
+        self.blocks[1].map(|b| (b >> 12) as u8)
+    }
+
+    /// True for version B groups.
+    pub fn version_b(&self) -> Option<bool> {
+        self.blocks[1].map(|b| b & 0x800 != 0)
+    }
+}
+
+/// Block synchronizer and error corrector.
+///
+/// Feed it bits, and get groups out.
+pub struct BlockSync {
+    reg: u32,
+    bits: usize,
+    // Position and offset index of last syndrome match, when unsynced.
+    last_match: Option<(usize, usize)>,
+    // Expected block index, when synced.
+    synced: Option<usize>,
+    bits_left: usize,
+    bad_blocks: usize,
+    group: Group,
+    // Syndromes of correctable burst errors.
+    bursts: Vec<(u16, u32)>,
+    corrected: usize,
+}
+
+impl Default for BlockSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockSync {
+    /// Create new block synchronizer.
+    pub fn new() -> Self {
+        let mut bursts = Vec::new();
+        for pattern in [1u32, 3] {
+            for shift in 0..BLOCK_BITS {
+                let e = (pattern << shift) & ((1 << BLOCK_BITS) - 1);
+                if e.count_ones() == pattern.count_ones() {
+                    bursts.push((syndrome(e), e));
+                }
+            }
+        }
+        Self {
+            reg: 0,
+            bits: 0,
+            last_match: None,
+            synced: None,
+            bits_left: 0,
+            bad_blocks: 0,
+            group: Group::default(),
+            bursts,
+            corrected: 0,
+        }
+    }
+
+    /// True if currently synchronized.
+    pub fn synced(&self) -> bool {
+        self.synced.is_some()
+    }
+
+    /// Number of corrected blocks.
+    pub fn corrected(&self) -> usize {
+        self.corrected
+    }
+
+    fn offset_index(syn: u16) -> Option<usize> {
+        OFFSETS.iter().position(|o| *o == syn)
+    }
+
+    // Check and correct block with the expected block index.
+    fn check(&mut self, block: u32, idx: usize) -> Option<u16> {
+        let syn = syndrome(block);
+        for (o, b) in OFFSETS.iter().zip(OFFSET_BLOCK.iter()) {
+            if *b != idx {
+                continue;
+            }
+            if syn == *o {
+                return Some((block >> 10) as u16);
+            }
+            let want = syn ^ o;
+            if let Some((_, e)) = self.bursts.iter().find(|(s, _)| *s == want) {
+                self.corrected += 1;
+                return Some(((block ^ e) >> 10) as u16);
+            }
+        }
+        None
+    }
+
+    /// Push one bit, returning a group when one is complete.
+    pub fn push(&mut self, bit: bool) -> Option<Group> {
+        self.reg = ((self.reg << 1) | bit as u32) & ((1 << BLOCK_BITS) - 1);
+        self.bits += 1;
+
+        if self.synced.is_none() {
+            if self.bits < BLOCK_BITS {
+                return None;
+            }
+            let oi = Self::offset_index(syndrome(self.reg))?;
+            let idx = OFFSET_BLOCK[oi];
+            if let Some((pos, last)) = self.last_match {
+                let dist = self.bits - pos;
+                if dist.is_multiple_of(BLOCK_BITS)
+                    && dist <= 4 * BLOCK_BITS
+                    && (OFFSET_BLOCK[last] + dist / BLOCK_BITS) % 4 == idx
+                {
+                    debug!("RDS: block sync");
+                    self.synced = Some(idx);
+                    self.bits_left = 0;
+                    self.bad_blocks = 0;
+                    self.group = Group::default();
+                    return self.block_done();
+                }
+            }
+            self.last_match = Some((self.bits, oi));
+            return None;
+        }
+        self.bits_left -= 1;
+        if self.bits_left > 0 {
+            return None;
+        }
+        self.block_done()
+    }
+
+    // A full block has been shifted in, at the expected position.
+    fn block_done(&mut self) -> Option<Group> {
+        let idx = self.synced?;
+        self.bits_left = BLOCK_BITS;
+        let data = self.check(self.reg, idx);
+        if data.is_none() {
+            self.bad_blocks += 1;
+            if self.bad_blocks >= MAX_BAD_BLOCKS {
+                debug!("RDS: lost block sync");
+                self.synced = None;
+                self.last_match = None;
+                return None;
+            }
+        } else {
+            self.bad_blocks = 0;
+        }
+        if idx == 0 {
+            self.group = Group::default();
+        }
+        self.group.blocks[idx] = data;
+        self.synced = Some((idx + 1) % 4);
+        if idx == 3 {
+            return Some(std::mem::take(&mut self.group));
+        }
+        None
+    }
+}
+
+/// Clock time from group 4A.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockTime {
+    /// Year.
+    pub year: u32,
+    /// Month, 1-12.
+    pub month: u32,
+    /// Day of month, 1-31.
+    pub day: u32,
+    /// UTC hour.
+    pub hour: u32,
+    /// UTC minute.
+    pub minute: u32,
+    /// Local time offset from UTC, in minutes.
+    pub offset_minutes: i32,
+}
+
+impl ClockTime {
+    fn from_mjd(mjd: u32, hour: u32, minute: u32, offset_minutes: i32) -> Self {
+        // From the RDS standard, annex G.
+        let mjd = mjd as f64;
+        let yp = ((mjd - 15078.2) / 365.25).floor();
+        let mp = ((mjd - 14956.1 - (yp * 365.25).floor()) / 30.6001).floor();
+        let day = mjd - 14956.0 - (yp * 365.25).floor() - (mp * 30.6001).floor();
+        let k = if mp == 14.0 || mp == 15.0 { 1.0 } else { 0.0 };
+        Self {
+            year: (yp + k + 1900.0) as u32,
+            month: (mp - 1.0 - k * 12.0) as u32,
+            day: day as u32,
+            hour,
+            minute,
+            offset_minutes,
+        }
+    }
+}
+
+/// Decoded station data.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RdsStation {
+    /// Program identification code.
+    pub pi: Option<u16>,
+
+    /// Program type.
+    pub pty: u8,
+
+    /// Traffic program flag.
+    pub tp: bool,
+
+    /// Traffic announcement flag.
+    pub ta: bool,
+
+    /// Program service name, e.g. station name. Up to 8 characters.
+    pub ps: String,
+
+    /// Radiotext, up to 64 characters.
+    pub rt: String,
+
+    /// Last received clock time.
+    pub clock: Option<ClockTime>,
+}
+
+impl std::fmt::Display for RdsStation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.pi {
+            Some(pi) => write!(f, "PI={pi:04X}")?,
+            None => write!(f, "PI=????")?,
+        }
+        write!(f, " PTY={} PS=\"{}\"", self.pty, self.ps)?;
+        if self.tp {
+            write!(f, " TP")?;
+        }
+        if self.ta {
+            write!(f, " TA")?;
+        }
+        if !self.rt.is_empty() {
+            write!(f, " RT=\"{}\"", self.rt)?;
+        }
+        if let Some(c) = &self.clock {
+            write!(
+                f,
+                " CT={:04}-{:02}-{:02} {:02}:{:02}Z{:+}min",
+                c.year, c.month, c.day, c.hour, c.minute, c.offset_minutes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl crate::Len for RdsStation {
+    fn len(&self) -> usize {
+        1
+    }
+}
+
+fn rds_char(c: u8) -> char {
+    // The RDS character set matches ASCII for printable characters.
+    if (0x20..0x7f).contains(&c) {
+        c as char
+    } else {
+        '?'
+    }
+}
+
+/// Group parser, accumulating station data.
+pub struct RdsParser {
+    station: RdsStation,
+    ps: [u8; 8],
+    ps_seen: u8,
+    rt: [u8; 64],
+    rt_seen: u16,
+    rt_ab: Option<bool>,
+}
+
+impl Default for RdsParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RdsParser {
+    /// Create new parser.
+    pub fn new() -> Self {
+        Self {
+            station: RdsStation::default(),
+            ps: [b' '; 8],
+            ps_seen: 0,
+            rt: [b' '; 64],
+            rt_seen: 0,
+            rt_ab: None,
+        }
+    }
+
+    /// Current station data.
+    pub fn station(&self) -> &RdsStation {
+        &self.station
+    }
+
+    fn rt_complete(&mut self, segments: usize) -> bool {
+        // Complete if all segments up to an end of text marker are seen.
+        let chars = segments * 4;
+        let end = self.rt[..chars]
+            .iter()
+            .position(|c| *c == 0x0d)
+            .unwrap_or(chars);
+        let need = end.div_ceil(4).max(1);
+        (0..need).all(|s| self.rt_seen & (1 << s) != 0)
+    }
+
+    /// Parse a group, returning true if the station data changed.
+    pub fn push(&mut self, group: &Group) -> bool {
+        let before = self.station.clone();
+        let [a, b, c, d] = group.blocks;
+        if let Some(a) = a {
+            if self.station.pi != Some(a) {
+                if self.station.pi.is_some() {
+                    info!("RDS: PI changed to {a:04X}, resetting");
+                    *self = Self::default();
+                }
+                self.station.pi = Some(a);
+            }
+        }
+        let Some(b) = b else {
+            return self.station != before;
+        };
+        self.station.tp = b & 0x400 != 0;
+        self.station.pty = ((b >> 5) & 0x1f) as u8;
+        let gtype = b >> 12;
+        let version_b = b & 0x800 != 0;
+        trace!("RDS: group {gtype}{}", if version_b { 'B' } else { 'A' });
+        match (gtype, version_b) {
+            (0, _) => {
+                self.station.ta = b & 0x10 != 0;
+                if let Some(d) = d {
+                    let seg = (b & 3) as usize;
+                    self.ps[seg * 2] = (d >> 8) as u8;
+                    self.ps[seg * 2 + 1] = d as u8;
+                    self.ps_seen |= 1 << seg;
+                    if self.ps_seen == 0xf {
+                        self.station.ps = self.ps.iter().map(|c| rds_char(*c)).collect();
+                    }
+                }
+            }
+            (2, _) => {
+                let ab = b & 0x10 != 0;
+                if self.rt_ab.is_some_and(|x| x != ab) {
+                    self.rt = [b' '; 64];
+                    self.rt_seen = 0;
+                }
+                self.rt_ab = Some(ab);
+                let seg = (b & 0xf) as usize;
+                let segments = if version_b {
+                    if let Some(d) = d {
+                        self.rt[seg * 2] = (d >> 8) as u8;
+                        self.rt[seg * 2 + 1] = d as u8;
+                        self.rt_seen |= 1 << (seg / 2);
+                    }
+                    8
+                } else {
+                    if let (Some(c), Some(d)) = (c, d) {
+                        let chars = [(c >> 8) as u8, c as u8, (d >> 8) as u8, d as u8];
+                        self.rt[seg * 4..seg * 4 + 4].copy_from_slice(&chars);
+                        self.rt_seen |= 1 << seg;
+                    }
+                    16
+                };
+                if self.rt_complete(segments) {
+                    self.station.rt = self.rt[..segments * 4]
+                        .iter()
+                        .take_while(|c| **c != 0x0d)
+                        .map(|c| rds_char(*c))
+                        .collect::<String>()
+                        .trim_end()
+                        .to_string();
+                }
+            }
+            (4, false) => {
+                if let (Some(c), Some(d)) = (c, d) {
+                    let mjd = (((b & 3) as u32) << 15) | ((c >> 1) as u32);
+                    let hour = (((c & 1) as u32) << 4) | ((d >> 12) as u32);
+                    let minute = ((d >> 6) & 0x3f) as u32;
+                    let offset = (d & 0x1f) as i32 * 30;
+                    let offset = if d & 0x20 != 0 { -offset } else { offset };
+                    self.station.clock = Some(ClockTime::from_mjd(mjd, hour, minute, offset));
+                }
+            }
+            _ => {}
+        }
+        self.station != before
+    }
+}
+
+/** RDS decoder block.
+
+Takes FM demodulated multiplex, and outputs station data every time
+it changes.
+*/
+pub struct RdsDecode {
+    src: Streamp<Float>,
+    dst: NoCopyStreamp<RdsStation>,
+    decim: usize,
+    taps: Vec<Float>,
+
+    // Mixing.
+    hist: Vec<Complex>,
+    pos: usize,
+    nco_phase: f64,
+    nco_step: f64,
+    carrier_phase: Float,
+
+    // Bit timing.
+    mf: Vec<Complex>,
+    last3: [Complex; 3],
+    energy: [Float; 3],
+    countdown: usize,
+    prev_bit: bool,
+
+    sync: BlockSync,
+    parser: RdsParser,
+    groups: usize,
+}
+
+impl RdsDecode {
+    /// Create new RDS decoder.
+    ///
+    /// Sample rate must be a multiple of 19kHz.
+    pub fn new(src: Streamp<Float>, samp_rate: Float) -> Result<Self, Error> {
+        let decim = (samp_rate / DECIMATED_RATE).round() as usize;
+        if decim == 0 || (decim as Float * DECIMATED_RATE - samp_rate).abs() > 0.5 {
+            return Err(Error::BadParameter(format!(
+                "RDS sample rate must be a multiple of {DECIMATED_RATE}, got {samp_rate}"
+            )));
+        }
+        let taps = crate::fir::low_pass(samp_rate, 2400.0, 1500.0);
+        Ok(Self {
+            src,
+            dst: new_nocopy_streamp(),
+            decim,
+            hist: vec![Complex::default(); taps.len()],
+            taps,
+            pos: 0,
+            nco_phase: 0.0,
+            nco_step: 2.0 * std::f64::consts::PI * CARRIER as f64 / samp_rate as f64,
+            carrier_phase: 0.0,
+            mf: vec![Complex::default(); SAMPLES_PER_BIT],
+            last3: [Complex::default(); 3],
+            energy: [0.0; 3],
+            countdown: SAMPLES_PER_BIT,
+            prev_bit: false,
+            sync: BlockSync::new(),
+            parser: RdsParser::new(),
+            groups: 0,
+        })
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<RdsStation> {
+        self.dst.clone()
+    }
+
+    /// Current station data.
+    pub fn station(&self) -> &RdsStation {
+        self.parser.station()
+    }
+
+    fn process_one(&mut self, s: Float) {
+        // Mix down.
+        let (sin, cos) = self.nco_phase.sin_cos();
+        self.nco_phase = (self.nco_phase + self.nco_step) % (2.0 * std::f64::consts::PI);
+        self.hist.rotate_left(1);
+        *self.hist.last_mut().unwrap() = Complex::new(s * cos as Float, -s * sin as Float);
+        self.pos += 1;
+        if self.pos < self.decim {
+            return;
+        }
+        self.pos = 0;
+
+        // Decimating low pass.
+        let y: Complex = self
+            .hist
+            .iter()
+            .zip(self.taps.iter())
+            .map(|(a, b)| a * b)
+            .sum();
+        let y = y * Complex::from_polar(1.0, -self.carrier_phase);
+        self.process_decimated(y);
+    }
+
+    fn process_decimated(&mut self, y: Complex) {
+        // Biphase matched filter: first half bit minus second half.
+        self.mf.rotate_left(1);
+        *self.mf.last_mut().unwrap() = y;
+        let half = SAMPLES_PER_BIT / 2;
+        let first: Complex = self.mf[..half].iter().sum();
+        let second: Complex = self.mf[half..].iter().sum();
+        let m = first - second;
+        self.last3 = [self.last3[1], self.last3[2], m];
+
+        // The strobe is evaluated one sample late, so that the sample
+        // after it is also known.
+        self.countdown -= 1;
+        if self.countdown > 0 {
+            return;
+        }
+        for (e, v) in self.energy.iter_mut().zip(self.last3.iter()) {
+            *e += 0.05 * (v.norm_sqr() - *e);
+        }
+        let [early, on, late] = self.energy;
+        self.countdown = if late > on && late > early {
+            SAMPLES_PER_BIT + 1
+        } else if early > on && early > late {
+            SAMPLES_PER_BIT - 1
+        } else {
+            SAMPLES_PER_BIT
+        };
+        let d = self.last3[1];
+
+        // BPSK carrier phase.
+        let err = d.re * d.im / d.norm_sqr().max(1e-20);
+        self.carrier_phase += 0.1 * err;
+
+        // Differential decode.
+        let hard = d.re > 0.0;
+        let bit = hard != self.prev_bit;
+        self.prev_bit = hard;
+        if let Some(group) = self.sync.push(bit) {
+            self.groups += 1;
+            if self.parser.push(&group) {
+                debug!("RDS: {:?}", self.parser.station());
+                self.dst.push(self.parser.station().clone(), &[]);
+            }
+        }
+    }
+}
+
+impl Drop for RdsDecode {
+    fn drop(&mut self) {
+        info!(
+            "RdsDecode: {} groups, {} corrected blocks",
+            self.groups,
+            self.sync.corrected()
+        );
+    }
+}
+
+impl Block for RdsDecode {
+    fn block_name(&self) -> &str {
+        "RdsDecode"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        for s in input.iter() {
+            self.process_one(*s);
+        }
+        let n = input.len();
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    fn group_bits(blocks: [u16; 4], c_prime: bool) -> Vec<bool> {
+        let offsets = [
+            OFFSETS[0],
+            OFFSETS[1],
+            if c_prime { OFFSETS[3] } else { OFFSETS[2] },
+            OFFSETS[4],
+        ];
+        blocks
+            .iter()
+            .zip(offsets.iter())
+            .flat_map(|(b, o)| {
+                let w = encode_block(*b, *o);
+                (0..BLOCK_BITS).rev().map(move |n| (w >> n) & 1 == 1)
+            })
+            .collect()
+    }
+
+    fn ps_groups(pi: u16, name: &str) -> Vec<bool> {
+        let name = name.as_bytes();
+        (0..4)
+            .flat_map(|seg| {
+                let d = ((name[seg * 2] as u16) << 8) | name[seg * 2 + 1] as u16;
+                group_bits([pi, (3 << 5) | seg as u16, 0xe0cd, d], false)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn syndromes() {
+        for (n, o) in OFFSETS.iter().enumerate() {
+            let b = encode_block(0x1234 + n as u16, *o);
+            assert_eq!(syndrome(b), *o);
+        }
+    }
+
+    #[test]
+    fn block_sync_with_errors() {
+        let mut bits = vec![true, false, true];
+        for _ in 0..3 {
+            bits.extend(ps_groups(0x5678, "RUSTRAD!"));
+        }
+        // Burst error in one block.
+        bits[3 + 26 * 4 + 30] ^= true;
+        bits[3 + 26 * 4 + 31] ^= true;
+        let mut sync = BlockSync::new();
+        let mut parser = RdsParser::new();
+        for b in bits {
+            if let Some(g) = sync.push(b) {
+                parser.push(&g);
+            }
+        }
+        assert_eq!(parser.station().pi, Some(0x5678));
+        assert_eq!(parser.station().ps, "RUSTRAD!");
+        assert_eq!(parser.station().pty, 3);
+        assert!(sync.corrected() > 0);
+    }
+
+    #[test]
+    fn radiotext_and_clock() {
+        let mut parser = RdsParser::new();
+        let text = b"Hello world\r";
+        for seg in 0..3 {
+            let c = |i: usize| *text.get(seg * 4 + i).unwrap_or(&b' ') as u16;
+            parser.push(&Group {
+                blocks: [
+                    Some(0x1111),
+                    Some(0x2000 | seg as u16),
+                    Some((c(0) << 8) | c(1)),
+                    Some((c(2) << 8) | c(3)),
+                ],
+            });
+        }
+        assert_eq!(parser.station().rt, "Hello world");
+        assert_eq!(
+            parser.station().to_string(),
+            "PI=1111 PTY=0 PS=\"\" RT=\"Hello world\""
+        );
+
+        // 2024-03-15 13:45 UTC, +1h. MJD 60384.
+        let mjd: u32 = 60384;
+        let (hour, minute) = (13u16, 45u16);
+        parser.push(&Group {
+            blocks: [
+                Some(0x1111),
+                Some(0x4000 | (mjd >> 15) as u16),
+                Some((((mjd & 0x7fff) << 1) as u16) | (hour >> 4)),
+                Some(((hour & 0xf) << 12) | (minute << 6) | 2),
+            ],
+        });
+        assert_eq!(
+            parser.station().clock,
+            Some(ClockTime {
+                year: 2024,
+                month: 3,
+                day: 15,
+                hour: 13,
+                minute: 45,
+                offset_minutes: 60,
+            })
+        );
+    }
+
+    #[test]
+    fn decode_multiplex() -> Result<()> {
+        let samp_rate = 228000.0;
+        let mut bits = Vec::new();
+        for _ in 0..6 {
+            bits.extend(ps_groups(0xabcd, "TEST FM "));
+        }
+
+        // Differential and biphase encoding.
+        let mut prev = false;
+        let symbols: Vec<Float> = bits
+            .iter()
+            .flat_map(|b| {
+                prev ^= b;
+                if prev {
+                    [1.0, -1.0]
+                } else {
+                    [-1.0, 1.0]
+                }
+            })
+            .collect();
+        let sps = samp_rate / (BIT_RATE * 2.0);
+        let n = (symbols.len() as Float * sps) as usize;
+        let pi2 = 2.0 * std::f64::consts::PI;
+        let mpx: Vec<Float> = (0..n)
+            .map(|i| {
+                let t = i as f64 / samp_rate as f64;
+                let sym = symbols[(i as Float / sps) as usize];
+                let rds = 0.05 * sym * (pi2 * 57000.0 * t + 1.0).cos() as Float;
+                let pilot = 0.1 * (pi2 * 19000.0 * t).cos() as Float;
+                let audio = 0.5 * (pi2 * 1000.0 * t).sin() as Float;
+                rds + pilot + audio
+            })
+            .collect();
+        // The signal is longer than a stream, so feed it directly.
+        let mut dec = RdsDecode::new(streamp_from_slice(&[0.0]), samp_rate)?;
+        for s in &mpx {
+            dec.process_one(*s);
+        }
+        let out = dec.out();
+        let mut got = None;
+        while let Some((s, _)) = out.pop() {
+            got = Some(s);
+        }
+        let got = got.expect("no station data decoded");
+        assert_eq!(got.pi, Some(0xabcd));
+        assert_eq!(got.ps, "TEST FM ");
+        Ok(())
+    }
+
+    #[test]
+    fn bad_rate() {
+        assert!(RdsDecode::new(streamp_from_slice(&[0.0]), 200000.0).is_err());
+    }
+}
@@ -0,0 +1,1240 @@
+/*! Registry of blocks that can be created by name.
+
+Normally graphs are built in Rust, where block types and stream types
+are checked at compile time. The registry is for building graphs from
+somewhere else, like a script or a config file, where all that's known
+is the name of a block, which streams to connect to it, and its
+parameters.
+
+```text
+  "quadrature_demod", [Complex stream], {"gain": 1.0}
+        |
+   Registry::create()
+        |
+  QuadratureDemod block, [Float stream]
+```
+
+Streams are passed around as [AnyStream], and parameters as JSON
+values. Inputs are checked against the [BlockInfo] of the block, so
+connecting the wrong type of stream is an error, not a panic.
+
+Blocks that work on several types are registered once per type, with
+a suffix like GNU Radio's: `_c` for [Complex], `_f` for [Float], and
+`_b` for bytes (`u8`). Packets, like from `hdlc_deframer`, are
+[StreamKind::Pdu] streams.
+
+[DynGraph] builds and runs a graph of registry blocks, referring to
+streams by number. It's what language bindings are built on. Its
+topology and parameters can be saved as a [GraphSpec], to restore the
+same receiver setup later.
+
+```
+use rustradio::graph::Graph;
+use rustradio::registry::{Params, Registry};
+
+let reg = Registry::default();
+let mut g = Graph::new();
+let params: Params = serde_json::from_str(r#"{"data": [1.0, 2.0]}"#)?;
+let src = reg.create("vector_source_f", &[], &params)?;
+g.add(src.block);
+let sink = reg.create("null_sink_f", &src.outputs, &Params::new())?;
+g.add(sink.block);
+g.run()?;
+# Ok::<(), anyhow::Error>(())
+```
+*/
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::block::Block;
+use crate::blocks::*;
+use crate::control::Controls;
+use crate::file_sink::Mode;
+use crate::graph::{CancellationToken, Graph};
+use crate::stream::{NoCopyStreamp, Streamp};
+use crate::{Complex, Error, Float, Sample};
+
+/// Block parameters, by name.
+pub type Params = serde_json::Map<String, serde_json::Value>;
+
+/// Type of a stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamKind {
+    /// [Float] samples.
+    Float,
+
+    /// [Complex] samples.
+    Complex,
+
+    /// Bytes, usually bits.
+    U8,
+
+    /// Packets of bytes.
+    Pdu,
+}
+
+impl std::fmt::Display for StreamKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                StreamKind::Float => "float",
+                StreamKind::Complex => "complex",
+                StreamKind::U8 => "u8",
+                StreamKind::Pdu => "pdu",
+            }
+        )
+    }
+}
+
+/// Stream of any type the registry supports.
+#[derive(Clone)]
+pub enum AnyStream {
+    /// [Float] stream.
+    Float(Streamp<Float>),
+
+    /// [Complex] stream.
+    Complex(Streamp<Complex>),
+
+    /// Byte stream.
+    U8(Streamp<u8>),
+
+    /// Packet stream.
+    Pdu(NoCopyStreamp<Vec<u8>>),
+}
+
+impl AnyStream {
+    /// Type of the stream.
+    pub fn kind(&self) -> StreamKind {
+        match self {
+            AnyStream::Float(_) => StreamKind::Float,
+            AnyStream::Complex(_) => StreamKind::Complex,
+            AnyStream::U8(_) => StreamKind::U8,
+            AnyStream::Pdu(_) => StreamKind::Pdu,
+        }
+    }
+}
+
+/// Sample types that streams in the registry can have.
+pub trait StreamType:
+    Copy + Default + std::fmt::Debug + Sample<Type = Self> + Send + Sync + 'static
+{
+    /// The type of stream.
+    const KIND: StreamKind;
+
+    /// Suffix for block names.
+    const SUFFIX: &'static str;
+
+    /// Wrap a stream.
+    fn wrap(s: Streamp<Self>) -> AnyStream;
+
+    /// Unwrap a stream, if it's of this type.
+    fn unwrap(s: &AnyStream) -> Option<Streamp<Self>>;
+
+    /// Parse a parameter value.
+    fn from_value(v: &serde_json::Value) -> Option<Self>;
+}
+
+impl StreamType for Float {
+    const KIND: StreamKind = StreamKind::Float;
+    const SUFFIX: &'static str = "f";
+    fn wrap(s: Streamp<Self>) -> AnyStream {
+        AnyStream::Float(s)
+    }
+    fn unwrap(s: &AnyStream) -> Option<Streamp<Self>> {
+        match s {
+            AnyStream::Float(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+    fn from_value(v: &serde_json::Value) -> Option<Self> {
+        Some(v.as_f64()? as Float)
+    }
+}
+
+impl StreamType for Complex {
+    const KIND: StreamKind = StreamKind::Complex;
+    const SUFFIX: &'static str = "c";
+    fn wrap(s: Streamp<Self>) -> AnyStream {
+        AnyStream::Complex(s)
+    }
+    fn unwrap(s: &AnyStream) -> Option<Streamp<Self>> {
+        match s {
+            AnyStream::Complex(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+    // Either a number, or `[re, im]`.
+    fn from_value(v: &serde_json::Value) -> Option<Self> {
+        if let Some(re) = v.as_f64() {
+            return Some(Complex::new(re as Float, 0.0));
+        }
+        match v.as_array()?.as_slice() {
+            [re, im] => Some(Complex::new(re.as_f64()? as Float, im.as_f64()? as Float)),
+            _ => None,
+        }
+    }
+}
+
+impl StreamType for u8 {
+    const KIND: StreamKind = StreamKind::U8;
+    const SUFFIX: &'static str = "b";
+    fn wrap(s: Streamp<Self>) -> AnyStream {
+        AnyStream::U8(s)
+    }
+    fn unwrap(s: &AnyStream) -> Option<Streamp<Self>> {
+        match s {
+            AnyStream::U8(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+    fn from_value(v: &serde_json::Value) -> Option<Self> {
+        v.as_u64()?.try_into().ok()
+    }
+}
+
+/// Description of a registered block.
+#[derive(Clone, Debug, Serialize)]
+pub struct BlockInfo {
+    /// Name to create the block by.
+    pub name: String,
+
+    /// One line description.
+    pub description: String,
+
+    /// Types of input streams.
+    pub inputs: Vec<StreamKind>,
+
+    /// Types of output streams.
+    pub outputs: Vec<StreamKind>,
+
+    /// Names of parameters. Optional ones end with `?`.
+    pub params: Vec<String>,
+}
+
+/// A block created by the registry, and its output streams.
+pub struct Created {
+    /// The block, to add to a graph.
+    pub block: Box<dyn Block + Send>,
+
+    /// Output streams, as listed in [BlockInfo::outputs].
+    pub outputs: Vec<AnyStream>,
+}
+
+/// Function creating a block from input streams and parameters.
+///
+/// Inputs have already been checked against [BlockInfo::inputs].
+pub type Constructor = Box<dyn Fn(&[AnyStream], &Params) -> Result<Created, Error> + Send + Sync>;
+
+/// Registry of blocks that can be created by name.
+///
+/// [Registry::default] has the standard blocks. [Registry::new] is
+/// empty, for only registering your own.
+///
+/// [default_registry] is a shared default registry.
+pub struct Registry {
+    blocks: BTreeMap<String, (BlockInfo, Constructor)>,
+}
+
+impl Registry {
+    /// Create empty registry.
+    pub fn new() -> Self {
+        Self {
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    /// Register a block, replacing any block with the same name.
+    pub fn register(&mut self, info: BlockInfo, ctor: Constructor) {
+        self.blocks.insert(info.name.clone(), (info, ctor));
+    }
+
+    /// Names of all blocks, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        self.blocks.keys().map(|s| s.as_str()).collect()
+    }
+
+    /// Description of a block.
+    pub fn info(&self, name: &str) -> Option<&BlockInfo> {
+        self.blocks.get(name).map(|(i, _)| i)
+    }
+
+    /// Create a block.
+    pub fn create(
+        &self,
+        name: &str,
+        inputs: &[AnyStream],
+        params: &Params,
+    ) -> Result<Created, Error> {
+        let (info, ctor) = self
+            .blocks
+            .get(name)
+            .ok_or_else(|| Error::BadParameter(format!("unknown block {name}")))?;
+        let kinds: Vec<_> = inputs.iter().map(AnyStream::kind).collect();
+        if kinds != info.inputs {
+            return Err(Error::TypeMismatch(format!(
+                "{name} takes inputs {:?}, got {:?}",
+                info.inputs, kinds
+            )));
+        }
+        let known: Vec<_> = info
+            .params
+            .iter()
+            .map(|p| p.trim_end_matches('?'))
+            .collect();
+        if let Some(p) = params.keys().find(|p| !known.contains(&p.as_str())) {
+            return Err(Error::BadParameter(format!("{name} has no parameter {p}")));
+        }
+        ctor(inputs, params)
+    }
+
+    fn add<F>(&mut self, name: &str, description: &str, io: Io, params: &[&str], ctor: F)
+    where
+        F: Fn(&[AnyStream], &Params) -> Result<Created, Error> + Send + Sync + 'static,
+    {
+        self.register(
+            BlockInfo {
+                name: name.to_string(),
+                description: description.to_string(),
+                inputs: io.0.to_vec(),
+                outputs: io.1.to_vec(),
+                params: params.iter().map(|s| s.to_string()).collect(),
+            },
+            Box::new(ctor),
+        );
+    }
+
+    // Blocks registered once per stream type.
+    fn add_typed<T>(&mut self)
+    where
+        T: StreamType + std::ops::Add<Output = T> + std::ops::Mul<Output = T>,
+    {
+        let k = T::KIND;
+        let name = |n: &str| format!("{n}_{}", T::SUFFIX);
+        let input = |i: &[AnyStream]| T::unwrap(&i[0]).unwrap();
+        self.add(
+            &name("vector_source"),
+            "Stream the values in data",
+            (&[], &[k]),
+            &["data", "repeat?"],
+            |_, p| {
+                let data = p
+                    .get("data")
+                    .and_then(|v| v.as_array())
+                    .ok_or_else(|| Error::BadParameter("data must be a list".into()))?
+                    .iter()
+                    .map(|v| {
+                        T::from_value(v)
+                            .ok_or_else(|| Error::BadParameter(format!("bad sample {v}")))
+                    })
+                    .collect::<Result<Vec<T>, Error>>()?;
+                let mut b = VectorSourceBuilder::new(data);
+                if get_bool(p, "repeat", false)? {
+                    b = b.repeat_forever();
+                }
+                let b = b.build();
+                Ok(created(b.out(), b))
+            },
+        );
+        self.add(
+            &name("file_source"),
+            "Read raw samples from file",
+            (&[], &[k]),
+            &["filename", "repeat?"],
+            |_, p| {
+                let b =
+                    FileSource::<T>::new(&get_str(p, "filename")?, get_bool(p, "repeat", false)?)?;
+                Ok(created(b.out(), b))
+            },
+        );
+        self.add(
+            &name("file_sink"),
+            "Write raw samples to file",
+            (&[k], &[]),
+            &["filename", "mode?"],
+            move |i, p| {
+                let mode = match get_str(p, "mode").as_deref() {
+                    Err(_) | Ok("overwrite") => Mode::Overwrite,
+                    Ok("create") => Mode::Create,
+                    Ok("append") => Mode::Append,
+                    Ok(m) => return Err(Error::BadParameter(format!("bad file mode {m}"))),
+                };
+                let b = FileSink::new(input(i), get_str(p, "filename")?.into(), mode)?;
+                Ok(sink(b))
+            },
+        );
+        self.add(
+            &name("null_sink"),
+            "Discard samples",
+            (&[k], &[]),
+            &[],
+            move |i, _| Ok(sink(NullSink::new(input(i)))),
+        );
+        self.add(
+            &name("debug_sink"),
+            "Print samples",
+            (&[k], &[]),
+            &[],
+            move |i, _| Ok(sink(DebugSink::new(input(i)))),
+        );
+        self.add(
+            &name("add_const"),
+            "Add a constant",
+            (&[k], &[k]),
+            &["value"],
+            move |i, p| {
+                let b = AddConst::new(input(i), get::<T>(p, "value")?);
+                Ok(created(b.out(), b))
+            },
+        );
+        self.add(
+            &name("multiply_const"),
+            "Multiply by a constant",
+            (&[k], &[k]),
+            &["value"],
+            move |i, p| {
+                let b = MultiplyConst::new(input(i), get::<T>(p, "value")?);
+                Ok(created(b.out(), b))
+            },
+        );
+        self.add(
+            &name("rational_resampler"),
+            "Resample by interp/deci",
+            (&[k], &[k]),
+            &["interp", "deci"],
+            move |i, p| {
+                let b = RationalResampler::new(
+                    input(i),
+                    get_usize(p, "interp")?,
+                    get_usize(p, "deci")?,
+                )?;
+                Ok(created(b.out(), b))
+            },
+        );
+        self.add(
+            &name("delay"),
+            "Delay by a number of samples",
+            (&[k], &[k]),
+            &["delay"],
+            move |i, p| {
+                let b = Delay::new(input(i), get_usize(p, "delay")?);
+                Ok(created(b.out(), b))
+            },
+        );
+        self.add(
+            &name("skip"),
+            "Drop the first samples",
+            (&[k], &[k]),
+            &["skip"],
+            move |i, p| {
+                let b = Skip::new(input(i), get_usize(p, "skip")?);
+                Ok(created(b.out(), b))
+            },
+        );
+        self.add(
+            &name("tee"),
+            "Copy a stream to two outputs",
+            (&[k], &[k, k]),
+            &[],
+            move |i, _| {
+                let b = Tee::new(input(i));
+                let (a, c) = b.out();
+                Ok(Created {
+                    block: Box::new(b),
+                    outputs: vec![T::wrap(a), T::wrap(c)],
+                })
+            },
+        );
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        use StreamKind::{Complex as C, Float as F, Pdu as P, U8 as B};
+        let mut r = Self::new();
+        r.add_typed::<Float>();
+        r.add_typed::<Complex>();
+        r.add_typed::<u8>();
+        r.add(
+            "signal_source",
+            "Complex sine wave",
+            (&[], &[C]),
+            &["samp_rate", "freq", "amplitude?"],
+            |_, p| {
+                let b = SignalSourceComplex::new(
+                    get(p, "samp_rate")?,
+                    get(p, "freq")?,
+                    get_or(p, "amplitude", 1.0)?,
+                );
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add(
+            "quadrature_demod",
+            "FM demodulate",
+            (&[C], &[F]),
+            &["gain?", "fast_atan2?"],
+            |i, p| {
+                let b = QuadratureDemod::builder(
+                    Complex::unwrap(&i[0]).unwrap(),
+                    get_or(p, "gain", 1.0)?,
+                )
+                .fast_atan2(get_bool(p, "fast_atan2", false)?)
+                .build();
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add(
+            "dc_blocker_c",
+            "Remove DC offset",
+            (&[C], &[C]),
+            &["alpha"],
+            |i, p| {
+                let b = DcBlocker::new(Complex::unwrap(&i[0]).unwrap(), get(p, "alpha")?)?;
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add(
+            "dc_blocker_f",
+            "Remove DC offset",
+            (&[F], &[F]),
+            &["alpha"],
+            |i, p| {
+                let b = DcBlocker::new(Float::unwrap(&i[0]).unwrap(), get(p, "alpha")?)?;
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add(
+            "iq_balance",
+            "Correct I/Q imbalance",
+            (&[C], &[C]),
+            &["alpha"],
+            |i, p| {
+                let b = IqBalance::new(Complex::unwrap(&i[0]).unwrap(), get(p, "alpha")?)?;
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add(
+            "rotator",
+            "Frequency shift by freq Hz",
+            (&[C], &[C]),
+            &["samp_rate", "freq"],
+            |i, p| {
+                let b = Rotator::new(
+                    Complex::unwrap(&i[0]).unwrap(),
+                    get(p, "samp_rate")?,
+                    get(p, "freq")?,
+                );
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add(
+            "fast_fm",
+            "Fast, less exact, FM demodulate",
+            (&[C], &[F]),
+            &[],
+            |i, _| {
+                let b = FastFM::new(Complex::unwrap(&i[0]).unwrap());
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add(
+            "complex_to_mag2",
+            "Squared magnitude",
+            (&[C], &[F]),
+            &[],
+            |i, _| {
+                let b = ComplexToMag2::new(Complex::unwrap(&i[0]).unwrap());
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add("complex_to_mag", "Magnitude", (&[C], &[F]), &[], |i, _| {
+            let b = ComplexToMag::new(Complex::unwrap(&i[0]).unwrap());
+            Ok(created(b.out(), b))
+        });
+        r.add(
+            "complex_to_arg",
+            "Phase angle",
+            (&[C], &[F]),
+            &[],
+            |i, _| {
+                let b = ComplexToArg::new(Complex::unwrap(&i[0]).unwrap());
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add("complex_to_real", "Real part", (&[C], &[F]), &[], |i, _| {
+            let b = ComplexToReal::new(Complex::unwrap(&i[0]).unwrap());
+            Ok(created(b.out(), b))
+        });
+        r.add(
+            "complex_to_imag",
+            "Imaginary part",
+            (&[C], &[F]),
+            &[],
+            |i, _| {
+                let b = ComplexToImag::new(Complex::unwrap(&i[0]).unwrap());
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add(
+            "float_to_complex",
+            "Combine real and imaginary streams",
+            (&[F, F], &[C]),
+            &[],
+            |i, _| {
+                let b = FloatToComplex::new(
+                    Float::unwrap(&i[0]).unwrap(),
+                    Float::unwrap(&i[1]).unwrap(),
+                );
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add(
+            "hilbert",
+            "Hilbert transform, real to complex",
+            (&[F], &[C]),
+            &["ntaps"],
+            |i, p| {
+                let b = Hilbert::new(Float::unwrap(&i[0]).unwrap(), get_usize(p, "ntaps")?);
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add(
+            "binary_slicer",
+            "Positive to 1, else 0",
+            (&[F], &[B]),
+            &[],
+            |i, _| {
+                let b = BinarySlicer::new(Float::unwrap(&i[0]).unwrap());
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add(
+            "nrzi_decode",
+            "NRZI decode bits",
+            (&[B], &[B]),
+            &[],
+            |i, _| {
+                let b = NrziDecode::new(u8::unwrap(&i[0]).unwrap());
+                Ok(created(b.out(), b))
+            },
+        );
+        #[cfg(feature = "fft")]
+        r.add(
+            "fft_filter_c",
+            "FIR filter using FFT. Taps, or low pass if no taps",
+            (&[C], &[C]),
+            &["taps?", "samp_rate?", "cutoff?", "twidth?"],
+            |i, p| {
+                let taps = match p.get("taps") {
+                    Some(_) => get_vec(p, "taps")?,
+                    None => crate::fir::low_pass_complex(
+                        get(p, "samp_rate")?,
+                        get(p, "cutoff")?,
+                        get(p, "twidth")?,
+                    ),
+                };
+                let b = FftFilter::new(Complex::unwrap(&i[0]).unwrap(), &taps);
+                Ok(created(b.out(), b))
+            },
+        );
+        #[cfg(feature = "fft")]
+        r.add(
+            "fft_filter_f",
+            "FIR filter using FFT. Taps, or low pass if no taps",
+            (&[F], &[F]),
+            &["taps?", "samp_rate?", "cutoff?", "twidth?"],
+            |i, p| {
+                let taps = match p.get("taps") {
+                    Some(_) => get_vec(p, "taps")?,
+                    None => crate::fir::low_pass(
+                        get(p, "samp_rate")?,
+                        get(p, "cutoff")?,
+                        get(p, "twidth")?,
+                    ),
+                };
+                let b = FftFilterFloat::new(Float::unwrap(&i[0]).unwrap(), &taps);
+                Ok(created(b.out(), b))
+            },
+        );
+        r.add(
+            "hdlc_deframer",
+            "HDLC frames from bits, checksum checked and stripped",
+            (&[B], &[P]),
+            &["min_size?", "max_size?", "fix_bits?", "checksum?"],
+            |i, p| {
+                let b = HdlcDeframer::builder(u8::unwrap(&i[0]).unwrap())
+                    .min_size(get_usize_or(p, "min_size", 10)?)
+                    .max_size(get_usize_or(p, "max_size", 1500)?)
+                    .fix_bits(get_bool(p, "fix_bits", false)?)
+                    .fcs_check(get_bool(p, "checksum", true)?)
+                    .build();
+                Ok(Created {
+                    outputs: vec![AnyStream::Pdu(b.out())],
+                    block: Box::new(b),
+                })
+            },
+        );
+        r.add(
+            "il2p_deframer",
+            "IL2P frames from bits",
+            (&[B], &[P]),
+            &[],
+            |i, _| {
+                let b = Il2pDeframer::new(u8::unwrap(&i[0]).unwrap());
+                Ok(Created {
+                    outputs: vec![AnyStream::Pdu(b.out())],
+                    block: Box::new(b),
+                })
+            },
+        );
+        r.add(
+            "pdu_writer",
+            "Write each packet to a file in dir",
+            (&[P], &[]),
+            &["dir"],
+            |i, p| Ok(sink(PduWriter::new(pdu(&i[0]), get_str(p, "dir")?.into()))),
+        );
+        r.add(
+            "debug_sink_pdu",
+            "Print packets",
+            (&[P], &[]),
+            &[],
+            |i, _| Ok(sink(DebugSinkNoCopy::new(pdu(&i[0])))),
+        );
+        r
+    }
+}
+
+/// Shared [Registry::default].
+pub fn default_registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::default)
+}
+
+/** Saved [DynGraph]: its blocks, how they're connected, and their
+parameters.
+
+Stream numbers are the same as when the graph was built. As JSON:
+
+```text
+{"blocks": [
+  {"name": "file_source_c", "params": {"filename": "in.c32"}},
+  {"name": "quadrature_demod", "inputs": [0]},
+  {"name": "file_sink_f", "inputs": [1], "params": {"filename": "out.f32"}}
+]}
+```
+*/
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GraphSpec {
+    /// Blocks, in the order they were added.
+    pub blocks: Vec<BlockSpec>,
+}
+
+/// Block in a [GraphSpec].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockSpec {
+    /// Registry name.
+    pub name: String,
+
+    /// Numbers of the streams connected to the inputs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<usize>,
+
+    /// Parameters.
+    #[serde(default, skip_serializing_if = "Params::is_empty")]
+    pub params: Params,
+}
+
+impl GraphSpec {
+    /// Parse from JSON.
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|e| Error::BadParameter(format!("graph spec: {e}")))
+    }
+
+    /// Serialize to JSON.
+    pub fn to_json(&self) -> String {
+        // unwrap: can't fail for maps with string keys.
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Load from JSON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Save as JSON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        std::fs::write(path, self.to_json() + "\n")?;
+        Ok(())
+    }
+}
+
+/** Graph of registry blocks, for language bindings.
+
+Streams are referred to by number, counting from 0 in the order blocks
+create them. Each stream can be connected to only one block, so use a
+tee to split one.
+
+The graph runs in its own thread, and can only be run once.
+*/
+pub struct DynGraph {
+    registry: &'static Registry,
+    blocks: Vec<Box<dyn Block + Send>>,
+    // Streams, until connected to a block.
+    streams: Vec<Option<AnyStream>>,
+    kinds: Vec<StreamKind>,
+    spec: GraphSpec,
+    // Streams created by blocks from outside the registry.
+    foreign_streams: bool,
+    // Gathered as blocks are added, since they move to the graph
+    // thread on start.
+    controls: Controls,
+    started: bool,
+    running: Option<(CancellationToken, JoinHandle<Result<String, Error>>)>,
+}
+
+impl DynGraph {
+    /// Create new empty graph, with blocks from `registry`.
+    pub fn new(registry: &'static Registry) -> Self {
+        Self {
+            registry,
+            blocks: Vec::new(),
+            streams: Vec::new(),
+            kinds: Vec::new(),
+            spec: GraphSpec::default(),
+            foreign_streams: false,
+            controls: Controls::new(),
+            started: false,
+            running: None,
+        }
+    }
+
+    /// Create a block, connected to the `inputs` streams, and return
+    /// the numbers of its output streams.
+    pub fn add(
+        &mut self,
+        name: &str,
+        inputs: &[usize],
+        params: &Params,
+    ) -> Result<Vec<usize>, Error> {
+        self.check_not_started()?;
+        for (n, id) in inputs.iter().enumerate() {
+            if inputs[..n].contains(id) || !matches!(self.streams.get(*id), Some(Some(_))) {
+                return Err(Error::BadParameter(format!(
+                    "stream {id} doesn't exist or is already connected"
+                )));
+            }
+        }
+        let ins: Vec<AnyStream> = inputs
+            .iter()
+            .map(|id| self.streams[*id].clone().unwrap())
+            .collect();
+        let created = self.registry.create(name, &ins, params)?;
+        for id in inputs {
+            self.streams[*id] = None;
+        }
+        self.spec.blocks.push(BlockSpec {
+            name: name.to_string(),
+            inputs: inputs.to_vec(),
+            params: params.clone(),
+        });
+        Ok(self.insert(created))
+    }
+
+    /// Create graph from a saved spec.
+    pub fn from_spec(registry: &'static Registry, spec: &GraphSpec) -> Result<Self, Error> {
+        let mut g = Self::new(registry);
+        for (n, b) in spec.blocks.iter().enumerate() {
+            g.add(&b.name, &b.inputs, &b.params)
+                .map_err(|e| e.in_block(&b.name, n))?;
+        }
+        Ok(g)
+    }
+
+    /// Topology and parameters of the registry blocks, to save.
+    ///
+    /// Blocks added with [DynGraph::push] can't be saved, and are
+    /// left out. That's fine for sinks, but fails if they have output
+    /// streams, since that would renumber the streams.
+    pub fn spec(&self) -> Result<GraphSpec, Error> {
+        if self.foreign_streams {
+            return Err(Error::new(
+                "graph has blocks from outside the registry with outputs",
+            ));
+        }
+        Ok(self.spec.clone())
+    }
+
+    /// Take stream `id`, to connect to a block made outside the
+    /// registry, and added with [DynGraph::push].
+    pub fn take(&mut self, id: usize) -> Result<AnyStream, Error> {
+        self.check_not_started()?;
+        self.streams
+            .get_mut(id)
+            .and_then(Option::take)
+            .ok_or_else(|| {
+                Error::BadParameter(format!("stream {id} doesn't exist or is already connected"))
+            })
+    }
+
+    /// Add a block, and return the numbers of its output streams.
+    pub fn push(&mut self, created: Created) -> Vec<usize> {
+        self.foreign_streams |= !created.outputs.is_empty();
+        self.insert(created)
+    }
+
+    fn insert(&mut self, created: Created) -> Vec<usize> {
+        self.controls.add_block(&*created.block);
+        self.blocks.push(created.block);
+        created
+            .outputs
+            .into_iter()
+            .map(|s| {
+                self.kinds.push(s.kind());
+                self.streams.push(Some(s));
+                self.streams.len() - 1
+            })
+            .collect()
+    }
+
+    /// Type of stream `id`.
+    pub fn kind(&self, id: usize) -> Option<StreamKind> {
+        self.kinds.get(id).copied()
+    }
+
+    /// Return the [controls](crate::control) of all blocks. They can
+    /// be set from any thread, also while the graph is running.
+    pub fn controls(&self) -> Controls {
+        self.controls.clone()
+    }
+
+    /// Start running the graph in a thread. Returns a token that
+    /// cancels it.
+    pub fn start(&mut self) -> Result<CancellationToken, Error> {
+        self.check_not_started()?;
+        self.started = true;
+        let blocks = std::mem::take(&mut self.blocks);
+        // Graph isn't Send, so it's created in the thread.
+        let (tx, rx) = std::sync::mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            let mut g = Graph::new();
+            for b in blocks {
+                g.add(b);
+            }
+            tx.send(g.cancel_token()).unwrap();
+            let st = Instant::now();
+            g.run()?;
+            Ok(g.generate_stats(st.elapsed()))
+        });
+        let cancel = rx
+            .recv()
+            .map_err(|_| Error::new("graph thread failed to start"))?;
+        self.running = Some((cancel.clone(), thread));
+        Ok(cancel)
+    }
+
+    /// Ask a running graph to stop.
+    pub fn cancel(&self) {
+        if let Some((cancel, _)) = &self.running {
+            cancel.cancel();
+        }
+    }
+
+    /// True if the graph has been started.
+    pub fn started(&self) -> bool {
+        self.started
+    }
+
+    /// True if the graph was started, and has stopped running.
+    pub fn finished(&self) -> bool {
+        match &self.running {
+            Some((_, thread)) => thread.is_finished(),
+            None => self.started,
+        }
+    }
+
+    /// Wait for the graph to finish, and return block statistics.
+    pub fn wait(&mut self) -> Result<String, Error> {
+        join(self.take_thread()?)
+    }
+
+    /// Take the thread running the graph, to wait for it with [join]
+    /// without holding on to the graph.
+    pub fn take_thread(&mut self) -> Result<JoinHandle<Result<String, Error>>, Error> {
+        let (_, thread) = self
+            .running
+            .take()
+            .ok_or_else(|| Error::new("graph not running"))?;
+        Ok(thread)
+    }
+
+    fn check_not_started(&self) -> Result<(), Error> {
+        match self.started {
+            true => Err(Error::new("graph already started")),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Wait for a graph thread from [DynGraph::take_thread] to finish, and
+/// return block statistics.
+pub fn join(thread: JoinHandle<Result<String, Error>>) -> Result<String, Error> {
+    thread
+        .join()
+        .map_err(|_| Error::new("graph thread panicked"))?
+}
+
+// Input and output stream types.
+type Io<'a> = (&'a [StreamKind], &'a [StreamKind]);
+
+fn created<T: StreamType>(out: Streamp<T>, b: impl Block + Send + 'static) -> Created {
+    Created {
+        block: Box::new(b),
+        outputs: vec![T::wrap(out)],
+    }
+}
+
+fn sink(b: impl Block + Send + 'static) -> Created {
+    Created {
+        block: Box::new(b),
+        outputs: vec![],
+    }
+}
+
+fn get<T: StreamType>(p: &Params, name: &str) -> Result<T, Error> {
+    let v = p
+        .get(name)
+        .ok_or_else(|| Error::BadParameter(format!("missing parameter {name}")))?;
+    T::from_value(v).ok_or_else(|| Error::BadParameter(format!("bad value for {name}: {v}")))
+}
+
+fn get_or<T: StreamType>(p: &Params, name: &str, default: T) -> Result<T, Error> {
+    match p.get(name) {
+        None => Ok(default),
+        Some(_) => get(p, name),
+    }
+}
+
+#[cfg(feature = "fft")]
+fn get_vec<T: StreamType>(p: &Params, name: &str) -> Result<Vec<T>, Error> {
+    p.get(name)
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| Error::BadParameter(format!("{name} must be a list")))?
+        .iter()
+        .map(|v| {
+            T::from_value(v).ok_or_else(|| Error::BadParameter(format!("bad value in {name}: {v}")))
+        })
+        .collect()
+}
+
+fn pdu(s: &AnyStream) -> NoCopyStreamp<Vec<u8>> {
+    match s {
+        AnyStream::Pdu(s) => s.clone(),
+        _ => panic!("checked by Registry::create"),
+    }
+}
+
+fn get_usize_or(p: &Params, name: &str, default: usize) -> Result<usize, Error> {
+    match p.get(name) {
+        None => Ok(default),
+        Some(_) => get_usize(p, name),
+    }
+}
+
+fn get_usize(p: &Params, name: &str) -> Result<usize, Error> {
+    p.get(name)
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize)
+        .ok_or_else(|| Error::BadParameter(format!("{name} must be a non-negative integer")))
+}
+
+fn get_str(p: &Params, name: &str) -> Result<String, Error> {
+    p.get(name)
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .ok_or_else(|| Error::BadParameter(format!("{name} must be a string")))
+}
+
+fn get_bool(p: &Params, name: &str, default: bool) -> Result<bool, Error> {
+    match p.get(name) {
+        None => Ok(default),
+        Some(v) => v
+            .as_bool()
+            .ok_or_else(|| Error::BadParameter(format!("{name} must be a bool"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+
+    fn params(json: &str) -> Params {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn chain() -> Result<(), Error> {
+        let reg = Registry::default();
+        let mut g = Graph::new();
+        let src = reg.create(
+            "vector_source_c",
+            &[],
+            &params(r#"{"data": [[1.0, 2.0], 3.0]}"#),
+        )?;
+        g.add(src.block);
+        let b = reg.create(
+            "add_const_c",
+            &src.outputs,
+            &params(r#"{"value": [0.5, -1]}"#),
+        )?;
+        g.add(b.block);
+        let b = reg.create("complex_to_mag2", &b.outputs, &Params::new())?;
+        g.add(b.block);
+        let out = match &b.outputs[..] {
+            [AnyStream::Float(s)] => s.clone(),
+            _ => panic!("wrong outputs"),
+        };
+        g.run()?;
+        let (res, _) = out.read_buf()?;
+        assert_eq!(res.slice(), &[3.25, 13.25]);
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> Result<(), Error> {
+        let reg = Registry::default();
+        let src = reg.create("vector_source_f", &[], &params(r#"{"data": [1.0]}"#))?;
+        assert!(matches!(
+            reg.create("quadrature_demod", &src.outputs, &Params::new()),
+            Err(Error::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            reg.create("null_sink_f", &[], &Params::new()),
+            Err(Error::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            reg.create("no_such_block", &[], &Params::new()),
+            Err(Error::BadParameter(_))
+        ));
+        assert!(matches!(
+            reg.create("add_const_f", &src.outputs, &params(r#"{"value": "x"}"#)),
+            Err(Error::BadParameter(_))
+        ));
+        assert!(matches!(
+            reg.create("add_const_f", &src.outputs, &params(r#"{"valeu": 1}"#)),
+            Err(Error::BadParameter(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn dyn_graph() -> Result<(), Error> {
+        let mut g = DynGraph::new(default_registry());
+        let src = g.add("vector_source_f", &[], &params(r#"{"data": [1.0, -1.0]}"#))?;
+        assert_eq!(src, vec![0]);
+        let outs = g.add("tee_f", &src, &Params::new())?;
+        assert_eq!(outs, vec![1, 2]);
+        assert_eq!(g.kind(2), Some(StreamKind::Float));
+
+        // Streams can only be connected once.
+        assert!(g.add("null_sink_f", &src, &Params::new()).is_err());
+        assert!(g.add("null_sink_f", &[7], &Params::new()).is_err());
+        g.add("null_sink_f", &outs[..1], &Params::new())?;
+
+        // Blocks from outside the registry.
+        let bits = g.add("binary_slicer", &outs[1..], &Params::new())?;
+        let AnyStream::U8(s) = g.take(bits[0])? else {
+            panic!("wrong stream type");
+        };
+        assert!(g.take(bits[0]).is_err());
+        let got = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let got2 = got.clone();
+        let b = StreamToPdu::new(s, "x".into(), 10, 0);
+        let out = b.out();
+        g.push(sink(b));
+        g.push(sink(CallbackSink::new(out, move |v| {
+            got2.lock().unwrap().push(v)
+        })));
+
+        assert!(!g.finished());
+        g.start()?;
+        assert!(g.start().is_err());
+        assert!(g
+            .add("vector_source_f", &[], &params(r#"{"data": []}"#))
+            .is_err());
+        g.wait()?;
+        assert!(g.finished());
+        assert!(got.lock().unwrap().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn spec() -> Result<(), Error> {
+        let mut g = DynGraph::new(default_registry());
+        let src = g.add("vector_source_f", &[], &params(r#"{"data": [1.0]}"#))?;
+        let outs = g.add("tee_f", &src, &Params::new())?;
+        g.add("null_sink_f", &outs[1..], &Params::new())?;
+        let spec = g.spec()?;
+        let json = spec.to_json();
+        assert_eq!(GraphSpec::from_json(&json)?, spec);
+        assert_eq!(
+            serde_json::to_value(&spec).unwrap(),
+            serde_json::json!({"blocks": [
+                {"name": "vector_source_f", "params": {"data": [1.0]}},
+                {"name": "tee_f", "inputs": [0]},
+                {"name": "null_sink_f", "inputs": [2]},
+            ]})
+        );
+
+        // Restored graph has the same streams.
+        let mut g2 = DynGraph::from_spec(default_registry(), &spec)?;
+        assert_eq!(g2.spec()?, spec);
+        assert_eq!(g2.kind(1), Some(StreamKind::Float));
+        g2.add("null_sink_f", &[1], &Params::new())?;
+        g2.start()?;
+        g2.wait()?;
+
+        // Errors say which block.
+        let bad = GraphSpec::from_json(
+            r#"{"blocks": [{"name": "vector_source_f", "params": {"data": [1.0]}},
+                           {"name": "null_sink_c", "inputs": [0]}]}"#,
+        )?;
+        let e = DynGraph::from_spec(default_registry(), &bad).err().unwrap();
+        assert!(matches!(e, Error::Block { index: 1, .. }), "{e}");
+        assert!(GraphSpec::from_json("{").is_err());
+
+        // Blocks from outside the registry with outputs can't be saved.
+        let s = g.take(outs[0])?;
+        g.push(Created {
+            block: Box::new(NullSink::new(crate::stream::new_streamp::<Float>())),
+            outputs: vec![s],
+        });
+        assert!(g.spec().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn info() {
+        let reg = Registry::default();
+        let names = reg.names();
+        assert!(names.contains(&"add_const_f"));
+        assert!(names.contains(&"add_const_c"));
+        let i = reg.info("tee_b").unwrap();
+        assert_eq!(i.inputs, vec![StreamKind::U8]);
+        assert_eq!(i.outputs, vec![StreamKind::U8, StreamKind::U8]);
+        for n in names {
+            let i = reg.info(n).unwrap();
+            assert_eq!(i.name, n);
+            assert!(!i.description.is_empty());
+        }
+    }
+}
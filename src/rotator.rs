@@ -0,0 +1,246 @@
+/*! Frequency shift a complex stream.
+
+[Rotator] multiplies each sample by e^(jωn), where ω = 2π·freq/samp_rate.
+A positive frequency moves the signal up, so to re-center a signal
+that's 10kHz above center, rotate by -10kHz.
+
+The oscillator is a complex phasor, multiplied by a fixed step per
+sample. Rounding errors would slowly change its amplitude, so it's
+renormalized regularly. The frequency can be changed while running,
+through a [RotatorHandle] or the "freq" [control][crate::control],
+without a jump in phase.
+
+```
+use rustradio::blocks::{ConstantSource, NullSink, Rotator};
+use rustradio::Complex;
+let src = ConstantSource::new(Complex::new(1.0, 0.0));
+let rot = Rotator::new(src.out(), 1_024_000.0, -10_000.0);
+let handle = rot.handle();
+let sink = NullSink::new(rot.out());
+// Later, e.g. from a UI thread:
+handle.set_freq(-12_500.0);
+```
+*/
+use std::sync::{Arc, Mutex};
+
+use crate::block::{Block, BlockRet};
+use crate::control::Control;
+use crate::stream::{new_streamp, Streamp};
+use crate::{Complex, Error, Float};
+
+// Samples between renormalizing the oscillator.
+const RENORM_INTERVAL: usize = 512;
+
+/// Shared handle to change the frequency of a [Rotator].
+#[derive(Clone)]
+pub struct RotatorHandle {
+    inner: Arc<Mutex<Float>>,
+}
+
+impl RotatorHandle {
+    /// Current frequency shift, in Hz.
+    pub fn freq(&self) -> Float {
+        *self.inner.lock().unwrap()
+    }
+
+    /// Set frequency shift, in Hz. Used from the next `work()` call.
+    pub fn set_freq(&self, freq: Float) {
+        *self.inner.lock().unwrap() = freq;
+    }
+}
+
+/// Frequency shift a complex stream. See the [module
+/// docs][crate::rotator].
+pub struct Rotator {
+    src: Streamp<Complex>,
+    dst: Streamp<Complex>,
+    samp_rate: Float,
+    handle: RotatorHandle,
+    // Frequency that `step` was calculated for.
+    freq: Float,
+    phase: Complex,
+    step: Complex,
+    since_renorm: usize,
+}
+
+impl Rotator {
+    /// Create new Rotator, shifting by `freq` Hz.
+    pub fn new(src: Streamp<Complex>, samp_rate: Float, freq: Float) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            samp_rate,
+            handle: RotatorHandle {
+                inner: Arc::new(Mutex::new(freq)),
+            },
+            freq,
+            phase: Complex::new(1.0, 0.0),
+            step: step(freq, samp_rate),
+            since_renorm: 0,
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Complex> {
+        self.dst.clone()
+    }
+
+    /// Handle for changing the frequency while running.
+    pub fn handle(&self) -> RotatorHandle {
+        self.handle.clone()
+    }
+
+    /// Set frequency shift, in Hz.
+    pub fn set_freq(&mut self, freq: Float) {
+        self.handle.set_freq(freq);
+    }
+}
+
+fn step(freq: Float, samp_rate: Float) -> Complex {
+    Complex::from_polar(1.0, 2.0 * std::f64::consts::PI as Float * freq / samp_rate)
+}
+
+impl Block for Rotator {
+    fn block_name(&self) -> &str {
+        "Rotator"
+    }
+    fn controls(&self) -> Vec<Control> {
+        let (g, s) = (self.handle.clone(), self.handle.clone());
+        vec![Control::float(
+            "freq",
+            move || g.freq(),
+            move |f| {
+                s.set_freq(f);
+                Ok(())
+            },
+        )]
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let freq = self.handle.freq();
+        if freq != self.freq {
+            self.freq = freq;
+            self.step = step(freq, self.samp_rate);
+        }
+        let (i, tags) = self.src.read_buf()?;
+        let mut o = self.dst.write_buf()?;
+        let n = std::cmp::min(i.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        for (s, d) in i.iter().take(n).zip(o.slice().iter_mut()) {
+            *d = s * self.phase;
+            self.phase *= self.step;
+            self.since_renorm += 1;
+            if self.since_renorm == RENORM_INTERVAL {
+                self.phase /= self.phase.norm();
+                self.since_renorm = 0;
+            }
+        }
+        i.consume(n);
+        o.produce(n, &tags);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+    use crate::tests::assert_almost_equal_complex;
+
+    #[test]
+    fn shift() -> crate::Result<()> {
+        // Quarter turn per sample.
+        let src = streamp_from_slice(&[Complex::new(1.0, 0.0); 5]);
+        let mut b = Rotator::new(src, 4.0, 1.0);
+        assert_eq!(b.block_name(), "Rotator");
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        assert_almost_equal_complex(
+            res.slice(),
+            &[
+                Complex::new(1.0, 0.0),
+                Complex::new(0.0, 1.0),
+                Complex::new(-1.0, 0.0),
+                Complex::new(0.0, -1.0),
+                Complex::new(1.0, 0.0),
+            ],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn recenter() -> crate::Result<()> {
+        // A tone at +1kHz, shifted down to DC.
+        let samp_rate = 48000.0;
+        let w = 2.0 * std::f64::consts::PI as Float * 1000.0 / samp_rate;
+        let input: Vec<_> = (0..1000)
+            .map(|n| Complex::from_polar(1.0, w * n as Float))
+            .collect();
+        let mut b = Rotator::new(streamp_from_slice(&input), samp_rate, -1000.0);
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        for s in res.iter() {
+            assert!((s - Complex::new(1.0, 0.0)).norm() < 0.001, "{s}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn stable() -> crate::Result<()> {
+        let src = crate::stream::new_streamp();
+        let mut b = Rotator::new(src.clone(), 48000.0, 1234.5);
+        for _ in 0..100 {
+            {
+                let mut o = src.write_buf()?;
+                let n = std::cmp::min(o.len(), 10000);
+                o.fill_from_iter(std::iter::repeat_n(Complex::new(1.0, 0.0), n));
+                o.produce(n, &[]);
+            }
+            b.work()?;
+            let o = b.out();
+            let (res, _) = o.read_buf()?;
+            for s in res.iter() {
+                assert!((s.norm() - 1.0).abs() < 0.0001, "{s}");
+            }
+            let n = res.len();
+            res.consume(n);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn retune() -> crate::Result<()> {
+        let src = crate::stream::new_streamp();
+        let mut b = Rotator::new(src.clone(), 4.0, 1.0);
+        let handle = b.handle();
+        let push = |n: usize| -> crate::Result<()> {
+            let mut o = src.write_buf()?;
+            o.fill_from_iter(std::iter::repeat_n(Complex::new(1.0, 0.0), n));
+            o.produce(n, &[]);
+            Ok(())
+        };
+        push(2)?;
+        b.work()?;
+        // Half a turn per sample from here, continuing from where the
+        // phase got to.
+        handle.set_freq(2.0);
+        assert_eq!(handle.freq(), 2.0);
+        push(2)?;
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        assert_almost_equal_complex(
+            res.slice(),
+            &[
+                Complex::new(1.0, 0.0),
+                Complex::new(0.0, 1.0),
+                Complex::new(-1.0, 0.0),
+                Complex::new(1.0, 0.0),
+            ],
+        );
+        Ok(())
+    }
+}
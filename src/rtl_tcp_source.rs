@@ -0,0 +1,459 @@
+/*! rtl_tcp client source.
+
+[rtl_tcp] serves an RTL-SDR over the network. This lets the dongle sit
+on e.g. a Raspberry Pi near the antenna, while the DSP runs on a
+workstation, without the `rtlsdr` feature compiled in locally.
+
+The output is the same byte format as
+[RtlSdrSource][crate::rtlsdr_source::RtlSdrSource], so add an
+[RtlSdrDecode][crate::rtlsdr_decode::RtlSdrDecode] after it.
+
+```text
+  [ RtlTcpSource ] → [ RtlSdrDecode ] → …
+         \_ RtlTcpHandle → set_freq(), set_gain(), …
+```
+
+Frequency, gain, and AGC are also [controls][crate::control], named
+"freq", "gain", and "agc". Set "gain" to the string "auto" to let the
+tuner pick.
+
+```no_run
+use rustradio::blocks::{RtlSdrDecode, RtlTcpSource};
+let src = RtlTcpSource::builder("raspberrypi:1234", 100_000_000, 1_024_000)
+    .igain(30)
+    .build()?;
+let handle = src.handle();
+let dec = RtlSdrDecode::new(src.out());
+// Retune while running.
+handle.set_freq(101_000_000)?;
+# Ok::<(), rustradio::Error>(())
+```
+
+[rtl_tcp]: https://osmocom.org/projects/rtl-sdr/wiki
+*/
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::sync::mpsc::TryRecvError;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use log::{debug, info};
+
+use crate::block::{Block, BlockRet};
+use crate::control::Control;
+use crate::stream::{new_streamp, Streamp, TagValue};
+use crate::Error;
+
+const CHUNK_SIZE: usize = 16384;
+const MAX_CHUNKS_IN_FLIGHT: usize = 1000;
+
+const CMD_FREQ: u8 = 0x01;
+const CMD_SAMPLE_RATE: u8 = 0x02;
+const CMD_GAIN_MODE: u8 = 0x03;
+const CMD_GAIN: u8 = 0x04;
+const CMD_FREQ_CORRECTION: u8 = 0x05;
+const CMD_AGC_MODE: u8 = 0x08;
+const CMD_DIRECT_SAMPLING: u8 = 0x09;
+const CMD_BIAS_TEE: u8 = 0x0e;
+
+// Last settings sent to the server.
+#[derive(Default)]
+struct Settings {
+    freq: u64,
+    igain: Option<i32>,
+    agc: bool,
+}
+
+/// Shared handle for sending commands to the rtl_tcp server.
+#[derive(Clone)]
+pub struct RtlTcpHandle {
+    stream: Arc<Mutex<TcpStream>>,
+    settings: Arc<Mutex<Settings>>,
+}
+
+impl RtlTcpHandle {
+    fn command(&self, cmd: u8, param: u32) -> Result<(), Error> {
+        let mut buf = [0; 5];
+        buf[0] = cmd;
+        buf[1..].copy_from_slice(&param.to_be_bytes());
+        debug!("rtl_tcp: command {cmd:#04x} {param}");
+        self.stream.lock().unwrap().write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Set center frequency, in Hz.
+    pub fn set_freq(&self, freq: u64) -> Result<(), Error> {
+        let f = u32::try_from(freq)
+            .map_err(|_| Error::BadParameter(format!("rtl_tcp: frequency {freq} too high")))?;
+        self.command(CMD_FREQ, f)?;
+        self.settings.lock().unwrap().freq = freq;
+        Ok(())
+    }
+
+    /// Center frequency, in Hz.
+    pub fn freq(&self) -> u64 {
+        self.settings.lock().unwrap().freq
+    }
+
+    /// Set sample rate.
+    pub fn set_sample_rate(&self, samp_rate: u32) -> Result<(), Error> {
+        self.command(CMD_SAMPLE_RATE, samp_rate)
+    }
+
+    /// Set manual tuner gain, in dB.
+    pub fn set_gain(&self, igain: i32) -> Result<(), Error> {
+        self.command(CMD_GAIN_MODE, 1)?;
+        // Tenths of a dB, as the signed value.
+        self.command(CMD_GAIN, (10 * igain) as u32)?;
+        self.settings.lock().unwrap().igain = Some(igain);
+        Ok(())
+    }
+
+    /// Let the tuner pick its gain.
+    pub fn set_auto_gain(&self) -> Result<(), Error> {
+        self.command(CMD_GAIN_MODE, 0)?;
+        self.settings.lock().unwrap().igain = None;
+        Ok(())
+    }
+
+    /// Manual tuner gain, in dB, or None for automatic gain.
+    pub fn gain(&self) -> Option<i32> {
+        self.settings.lock().unwrap().igain
+    }
+
+    /// Set frequency correction, in PPM.
+    pub fn set_ppm(&self, ppm: i32) -> Result<(), Error> {
+        self.command(CMD_FREQ_CORRECTION, ppm as u32)
+    }
+
+    /// Turn the RTL2832 digital AGC on or off.
+    pub fn set_agc(&self, on: bool) -> Result<(), Error> {
+        self.command(CMD_AGC_MODE, on.into())?;
+        self.settings.lock().unwrap().agc = on;
+        Ok(())
+    }
+
+    /// True if the RTL2832 digital AGC is on.
+    pub fn agc(&self) -> bool {
+        self.settings.lock().unwrap().agc
+    }
+
+    /// Set direct sampling. 0 is off, 1 is the I branch, 2 is the Q
+    /// branch.
+    pub fn set_direct_sampling(&self, mode: u32) -> Result<(), Error> {
+        self.command(CMD_DIRECT_SAMPLING, mode)
+    }
+
+    /// Turn bias tee power on or off.
+    pub fn set_bias_tee(&self, on: bool) -> Result<(), Error> {
+        self.command(CMD_BIAS_TEE, on.into())
+    }
+}
+
+/// Builder for [RtlTcpSource].
+pub struct RtlTcpSourceBuilder {
+    addr: String,
+    freq: u64,
+    samp_rate: u32,
+    igain: Option<i32>,
+    ppm: i32,
+    agc: bool,
+    bias_tee: bool,
+}
+
+impl RtlTcpSourceBuilder {
+    /// Create new builder.
+    ///
+    /// * `addr`: Server address, e.g. `raspberrypi:1234`.
+    /// * `freq`: Center frequency, in Hz.
+    /// * `samp_rate`: samples per second. Equivalently, the bandwidth.
+    ///
+    /// Defaults to an input gain of 20, like
+    /// [RtlSdrSource][crate::rtlsdr_source::RtlSdrSource].
+    pub fn new(addr: &str, freq: u64, samp_rate: u32) -> Self {
+        Self {
+            addr: addr.to_string(),
+            freq,
+            samp_rate,
+            igain: Some(20),
+            ppm: 0,
+            agc: false,
+            bias_tee: false,
+        }
+    }
+
+    /// Set input gain, in dB.
+    pub fn igain(mut self, igain: i32) -> Self {
+        self.igain = Some(igain);
+        self
+    }
+
+    /// Let the tuner pick its gain.
+    pub fn auto_gain(mut self) -> Self {
+        self.igain = None;
+        self
+    }
+
+    /// Set frequency correction, in PPM.
+    pub fn ppm(mut self, ppm: i32) -> Self {
+        self.ppm = ppm;
+        self
+    }
+
+    /// Turn the RTL2832 digital AGC on or off. Default off.
+    pub fn agc(mut self, on: bool) -> Self {
+        self.agc = on;
+        self
+    }
+
+    /// Turn bias tee power on or off. Default off.
+    pub fn bias_tee(mut self, on: bool) -> Self {
+        self.bias_tee = on;
+        self
+    }
+
+    /// Connect to the server, configure it, and build the source block.
+    pub fn build(self) -> Result<RtlTcpSource, Error> {
+        let mut stream = TcpStream::connect(&self.addr)?;
+        stream.set_nodelay(true)?;
+
+        // "RTL0", tuner type, and number of gain steps.
+        let mut header = [0; 12];
+        stream.read_exact(&mut header)?;
+        if &header[..4] != b"RTL0" {
+            return Err(Error::Hardware {
+                msg: format!("{} is not an rtl_tcp server", self.addr),
+                source: None,
+            });
+        }
+        let tuner_type = u32::from_be_bytes(header[4..8].try_into().unwrap());
+        let gain_count = u32::from_be_bytes(header[8..12].try_into().unwrap());
+        info!(
+            "rtl_tcp: connected to {}, tuner type {tuner_type}, {gain_count} gains",
+            self.addr
+        );
+
+        let handle = RtlTcpHandle {
+            stream: Arc::new(Mutex::new(stream.try_clone()?)),
+            settings: Arc::new(Mutex::new(Settings::default())),
+        };
+        handle.set_sample_rate(self.samp_rate)?;
+        handle.set_freq(self.freq)?;
+        match self.igain {
+            Some(g) => handle.set_gain(g)?,
+            None => handle.set_auto_gain()?,
+        }
+        if self.ppm != 0 {
+            handle.set_ppm(self.ppm)?;
+        }
+        handle.set_agc(self.agc)?;
+        if self.bias_tee {
+            handle.set_bias_tee(true)?;
+        }
+
+        let (tx, rx) = mpsc::sync_channel(MAX_CHUNKS_IN_FLIGHT);
+        thread::Builder::new()
+            .name("RtlTcpSource-reader".to_string())
+            .spawn(move || loop {
+                let mut buf = vec![0; CHUNK_SIZE];
+                let res = match stream.read(&mut buf) {
+                    Ok(0) => return,
+                    Ok(n) => {
+                        buf.truncate(n);
+                        Ok(buf)
+                    }
+                    Err(e) => Err(e),
+                };
+                let stop = res.is_err();
+                if tx.send(res).is_err() || stop {
+                    return;
+                }
+            })?;
+        Ok(RtlTcpSource {
+            rx,
+            handle,
+            tuner_type,
+            dst: new_streamp(),
+            buf: Vec::new(),
+        })
+    }
+}
+
+/// rtl_tcp client source block.
+pub struct RtlTcpSource {
+    rx: mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    handle: RtlTcpHandle,
+    tuner_type: u32,
+    dst: Streamp<u8>,
+    buf: Vec<u8>,
+}
+
+impl RtlTcpSource {
+    /// Create new RtlTcpSource block.
+    ///
+    /// * `addr`: Server address, e.g. `raspberrypi:1234`.
+    /// * `freq`: Center frequency, in Hz.
+    /// * `samp_rate`: samples per second. Equivalently, the bandwidth.
+    /// * `igain`: Input gain. 20 is a good number to start with.
+    pub fn new(addr: &str, freq: u64, samp_rate: u32, igain: i32) -> Result<Self, Error> {
+        Self::builder(addr, freq, samp_rate).igain(igain).build()
+    }
+
+    /// Create a builder for RtlTcpSource.
+    pub fn builder(addr: &str, freq: u64, samp_rate: u32) -> RtlTcpSourceBuilder {
+        RtlTcpSourceBuilder::new(addr, freq, samp_rate)
+    }
+
+    /// Return a handle for changing settings while running.
+    pub fn handle(&self) -> RtlTcpHandle {
+        self.handle.clone()
+    }
+
+    /// Tuner type, as reported by the server. E.g. 5 is R820T.
+    pub fn tuner_type(&self) -> u32 {
+        self.tuner_type
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<u8> {
+        self.dst.clone()
+    }
+}
+
+impl Block for RtlTcpSource {
+    fn block_name(&self) -> &str {
+        "RtlTcpSource"
+    }
+    fn controls(&self) -> Vec<Control> {
+        let (g1, s1) = (self.handle.clone(), self.handle.clone());
+        let (g2, s2) = (self.handle.clone(), self.handle.clone());
+        let (g3, s3) = (self.handle.clone(), self.handle.clone());
+        vec![
+            Control::u64("freq", move || g1.freq(), move |f| s1.set_freq(f)),
+            Control::new(
+                "gain",
+                move || match g2.gain() {
+                    Some(g) => TagValue::Float(g as crate::Float),
+                    None => TagValue::String("auto".to_string()),
+                },
+                move |v| match v {
+                    TagValue::Float(g) => s2.set_gain(g.round() as i32),
+                    TagValue::U64(g) => s2.set_gain(*g as i32),
+                    TagValue::String(a) if a == "auto" => s2.set_auto_gain(),
+                    _ => Err(Error::TypeMismatch(format!(
+                        "control gain wants a number or \"auto\", got {v:?}"
+                    ))),
+                },
+            ),
+            Control::bool("agc", move || g3.agc(), move |on| s3.set_agc(on)),
+        ]
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let mut o = self.dst.write_buf()?;
+        if o.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        if self.buf.is_empty() {
+            match self.rx.try_recv() {
+                Err(TryRecvError::Empty) => return Ok(BlockRet::Pending),
+                Err(TryRecvError::Disconnected) => {
+                    info!("rtl_tcp: connection closed");
+                    return Ok(BlockRet::EOF);
+                }
+                Ok(buf) => self.buf = buf?,
+            }
+        }
+        let n = std::cmp::min(o.len(), self.buf.len());
+        o.fill_from_slice(&self.buf[..n]);
+        self.buf.drain(0..n);
+        o.produce(n, &[]);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol() -> Result<(), Error> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?.to_string();
+        let (cmd_tx, cmd_rx) = mpsc::channel();
+        let server = thread::spawn(move || -> std::io::Result<()> {
+            let (mut stream, _) = listener.accept()?;
+            stream.write_all(b"RTL0\0\0\0\x05\0\0\0\x1d")?;
+            // Initial setup, then one command from the handle.
+            let mut cmds = [0; 6 * 5];
+            stream.read_exact(&mut cmds)?;
+            stream.write_all(&[127, 128, 0, 255])?;
+            let mut cmd = [0; 5];
+            stream.read_exact(&mut cmd)?;
+            cmd_tx.send((cmds.to_vec(), cmd.to_vec())).unwrap();
+            Ok(())
+        });
+        let mut src = RtlTcpSource::builder(&addr, 144_800_000, 1_024_000)
+            .igain(30)
+            .ppm(-2)
+            .build()?;
+        assert_eq!(src.tuner_type(), 5);
+        src.handle().set_freq(145_000_000)?;
+        let (cmds, cmd) = cmd_rx.recv().unwrap();
+        let controls: Vec<_> = src.controls().iter().map(|c| c.get()).collect();
+        assert_eq!(
+            controls,
+            [
+                TagValue::U64(145_000_000),
+                TagValue::Float(30.0),
+                TagValue::Bool(false)
+            ]
+        );
+        server.join().unwrap()?;
+        assert_eq!(
+            cmds,
+            [
+                [0x02, 0x00, 0x0f, 0xa0, 0x00],
+                [0x01, 0x08, 0xa1, 0x79, 0x00],
+                [0x03, 0x00, 0x00, 0x00, 0x01],
+                [0x04, 0x00, 0x00, 0x01, 0x2c],
+                [0x05, 0xff, 0xff, 0xff, 0xfe],
+                [0x08, 0x00, 0x00, 0x00, 0x00],
+            ]
+            .concat()
+        );
+        assert_eq!(cmd, [0x01, 0x08, 0xa4, 0x86, 0x40]);
+
+        // All data, then EOF.
+        let mut got: Vec<u8> = Vec::new();
+        loop {
+            match src.work()? {
+                BlockRet::EOF => break,
+                BlockRet::Pending => thread::sleep(std::time::Duration::from_millis(1)),
+                _ => {}
+            }
+            let o = src.out();
+            let (b, _) = o.read_buf()?;
+            let n = b.len();
+            got.extend(b.iter());
+            b.consume(n);
+        }
+        assert_eq!(got, [127, 128, 0, 255]);
+        Ok(())
+    }
+
+    #[test]
+    fn not_rtl_tcp() -> Result<(), Error> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?.to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"HTTP/1.1 400").unwrap();
+        });
+        assert!(matches!(
+            RtlTcpSource::new(&addr, 100_000_000, 1_024_000, 20),
+            Err(Error::Hardware { .. })
+        ));
+        Ok(())
+    }
+}
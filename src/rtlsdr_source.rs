@@ -10,15 +10,21 @@ cases.
 The best places to get RTL SDRs are probably:
 * <https://www.rtl-sdr.com>
 * <https://www.nooelec.com/store/>
+
+Frequency and gain can be changed while running, through an
+[RtlSdrHandle], or the "freq" and "gain" [controls][crate::control].
+They take effect between reads from the device.
 */
 use std::sync::mpsc;
 use std::sync::mpsc::{RecvError, SendError, TryRecvError};
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 use anyhow::Result;
-use log::debug;
+use log::{debug, warn};
 
 use crate::block::{Block, BlockRet};
+use crate::control::Control;
 use crate::stream::{new_streamp, Streamp};
 use crate::Error;
 
@@ -27,7 +33,10 @@ const MAX_CHUNKS_IN_FLIGHT: usize = 1000;
 
 impl From<rtlsdr::RTLSDRError> for Error {
     fn from(e: rtlsdr::RTLSDRError) -> Self {
-        Error::new(&format!("RTL SDR Error: {}", e))
+        Error::Hardware {
+            msg: format!("RTL SDR: {e}"),
+            source: None,
+        }
     }
 }
 
@@ -48,38 +57,112 @@ impl<T> From<SendError<T>> for Error {
     }
 }
 
-/// RTL SDR Source block.
-pub struct RtlSdrSource {
-    rx: mpsc::Receiver<Vec<u8>>,
-    dst: Streamp<u8>,
-    buf: Vec<u8>,
+enum Command {
+    Freq(u32),
+    Gain(i32),
 }
 
-impl RtlSdrSource {
-    /// Create new RtlSdrSource block.
+struct Settings {
+    freq: u64,
+    igain: i32,
+}
+
+/// Shared handle for changing [RtlSdrSource] settings while running.
+#[derive(Clone)]
+pub struct RtlSdrHandle {
+    tx: mpsc::Sender<Command>,
+    settings: Arc<Mutex<Settings>>,
+}
+
+impl RtlSdrHandle {
+    /// Set center frequency, in Hz.
+    pub fn set_freq(&self, freq: u64) -> Result<(), Error> {
+        let f = u32::try_from(freq)
+            .map_err(|_| Error::BadParameter(format!("RTL SDR: frequency {freq} too high")))?;
+        self.tx.send(Command::Freq(f))?;
+        self.settings.lock().unwrap().freq = freq;
+        Ok(())
+    }
+
+    /// Center frequency, in Hz.
+    pub fn freq(&self) -> u64 {
+        self.settings.lock().unwrap().freq
+    }
+
+    /// Set input gain.
+    pub fn set_gain(&self, igain: i32) -> Result<(), Error> {
+        self.tx.send(Command::Gain(igain))?;
+        self.settings.lock().unwrap().igain = igain;
+        Ok(())
+    }
+
+    /// Input gain.
+    pub fn gain(&self) -> i32 {
+        self.settings.lock().unwrap().igain
+    }
+}
+
+/// Builder for [RtlSdrSource].
+pub struct RtlSdrSourceBuilder {
+    index: u32,
+    freq: u64,
+    samp_rate: u32,
+    igain: i32,
+}
+
+impl RtlSdrSourceBuilder {
+    /// Create new builder.
     ///
     /// * `freq`: Center frequency, in Hz.
     /// * `samp_rate`: samples per second. Equivalently, the bandwidth.
-    /// * `igain`: Input gain. 20 is a good number to start with.
     ///
-    /// If given frequency of 100Mhz, and sample rate of 1Msps, the
-    /// received spectrum is 99.5Mhz to 100.5Mhz.
-    pub fn new(freq: u64, samp_rate: u32, igain: i32) -> Result<Self, Error> {
-        let index = 0;
+    /// Defaults to the first device, and an input gain of 20.
+    pub fn new(freq: u64, samp_rate: u32) -> Self {
+        Self {
+            index: 0,
+            freq,
+            samp_rate,
+            igain: 20,
+        }
+    }
+
+    /// Set device index, for when more than one is plugged in.
+    pub fn index(mut self, index: u32) -> Self {
+        self.index = index;
+        self
+    }
+
+    /// Set input gain.
+    pub fn igain(mut self, igain: i32) -> Self {
+        self.igain = igain;
+        self
+    }
+
+    /// Open the device, and build the source block.
+    pub fn build(self) -> Result<RtlSdrSource, Error> {
+        let Self {
+            index,
+            freq,
+            samp_rate,
+            igain,
+        } = self;
         let found = rtlsdr::get_device_count();
-        if index >= found {
-            return Err(Error::new(&format!(
-                "RTL SDR index {} doesn't exist, found {}",
-                index, found
-            )));
+        if index >= found as u32 {
+            return Err(Error::Hardware {
+                msg: format!("RTL SDR index {} doesn't exist, found {}", index, found),
+                source: None,
+            });
         }
 
         let (tx, rx) = mpsc::sync_channel(MAX_CHUNKS_IN_FLIGHT);
+        let (cmd_tx, cmd_rx) = mpsc::channel();
         thread::Builder::new()
             .name("RtlSdrSource-reader".to_string())
             .spawn(move || -> Result<(), Error> {
-                let mut dev =
-                    rtlsdr::open(index).map_err(|e| Error::new(&format!("RTL SDR open: {e}")))?;
+                let mut dev = rtlsdr::open(index as i32).map_err(|e| Error::Hardware {
+                    msg: format!("RTL SDR open: {e}"),
+                    source: None,
+                })?;
                 debug!("Tuner type: {:?}", dev.get_tuner_type());
                 dev.set_center_freq(freq as u32)?;
                 debug!("Allowed tuner gains: {:?}", dev.get_tuner_gains()?);
@@ -94,28 +177,86 @@ impl RtlSdrSource {
                 dev.reset_buffer()?;
                 tx.send(vec![])?;
                 loop {
+                    while let Ok(cmd) = cmd_rx.try_recv() {
+                        let res = match cmd {
+                            Command::Freq(f) => dev.set_center_freq(f),
+                            Command::Gain(g) => dev.set_tuner_gain(10 * g),
+                        };
+                        if let Err(e) = res {
+                            warn!("RTL SDR: failed to change setting: {e}");
+                        }
+                    }
                     let buf = dev.read_sync(CHUNK_SIZE)?;
                     tx.send(buf)
                         .expect("Failed to send message from RTL-SDR read thread to the block");
                 }
             })?;
         assert_eq!(rx.recv()?, Vec::<u8>::new());
-        Ok(Self {
+        Ok(RtlSdrSource {
             rx,
+            handle: RtlSdrHandle {
+                tx: cmd_tx,
+                settings: Arc::new(Mutex::new(Settings { freq, igain })),
+            },
             dst: new_streamp(),
             buf: Vec::new(),
         })
     }
+}
+
+/// RTL SDR Source block.
+pub struct RtlSdrSource {
+    rx: mpsc::Receiver<Vec<u8>>,
+    handle: RtlSdrHandle,
+    dst: Streamp<u8>,
+    buf: Vec<u8>,
+}
+
+impl RtlSdrSource {
+    /// Create new RtlSdrSource block.
+    ///
+    /// * `freq`: Center frequency, in Hz.
+    /// * `samp_rate`: samples per second. Equivalently, the bandwidth.
+    /// * `igain`: Input gain. 20 is a good number to start with.
+    ///
+    /// If given frequency of 100Mhz, and sample rate of 1Msps, the
+    /// received spectrum is 99.5Mhz to 100.5Mhz.
+    pub fn new(freq: u64, samp_rate: u32, igain: i32) -> Result<Self, Error> {
+        Self::builder(freq, samp_rate).igain(igain).build()
+    }
+
+    /// Create a builder for RtlSdrSource.
+    pub fn builder(freq: u64, samp_rate: u32) -> RtlSdrSourceBuilder {
+        RtlSdrSourceBuilder::new(freq, samp_rate)
+    }
+
     /// Return the output stream.
     pub fn out(&self) -> Streamp<u8> {
         self.dst.clone()
     }
+
+    /// Return a handle for changing settings while running.
+    pub fn handle(&self) -> RtlSdrHandle {
+        self.handle.clone()
+    }
 }
 
 impl Block for RtlSdrSource {
     fn block_name(&self) -> &str {
         "RtlSdrSource"
     }
+    fn controls(&self) -> Vec<Control> {
+        let (g1, s1) = (self.handle.clone(), self.handle.clone());
+        let (g2, s2) = (self.handle.clone(), self.handle.clone());
+        vec![
+            Control::u64("freq", move || g1.freq(), move |f| s1.set_freq(f)),
+            Control::float(
+                "gain",
+                move || g2.gain() as crate::Float,
+                move |g| s2.set_gain(g.round() as i32),
+            ),
+        ]
+    }
     fn work(&mut self) -> Result<BlockRet, Error> {
         let mut o = self.dst.write_buf()?;
         if o.is_empty() {
@@ -0,0 +1,389 @@
+/*! RTTY (Baudot/ITA2) decoder.
+
+[RTTY][rtty] is asynchronous 2FSK, where each character is sent as one
+start bit (space), five data bits (LSB first), and 1.5 stop bits
+(mark).
+
+Amateur radio RTTY is usually 45.45 baud with 170Hz shift. On HF with
+an SSB receiver the "standard" audio tones are 2125Hz (mark) and
+2295Hz (space).
+
+The demodulation is done with the usual blocks (e.g. `Hilbert` and
+`QuadratureDemod`, or a filter bank), and this module provides the
+async framing and Baudot decoding part, turning the demodulated float
+stream into ASCII bytes.
+
+```text
+  [ audio ] → [ Hilbert ] → [ QuadratureDemod ] → [ FftFilterFloat ]
+    → [ AddConst to center ] → [ RttyDecode ] → ASCII bytes
+```
+
+See `examples/rtty-rx.rs` for a complete receiver.
+
+[rtty]: https://en.wikipedia.org/wiki/Radioteletype
+*/
+use log::{debug, info, trace};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, Streamp};
+use crate::{Error, Float};
+
+/// Common RTTY baud rates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Baud {
+    /// 45.45 baud, the amateur radio standard (60 WPM).
+    B45,
+
+    /// 50 baud, used by e.g. DWD weather broadcasts.
+    B50,
+
+    /// 75 baud (100 WPM).
+    B75,
+
+    /// Any other baud rate.
+    Other(Float),
+}
+
+impl Baud {
+    /// Symbols per second.
+    pub fn rate(&self) -> Float {
+        match self {
+            Baud::B45 => 1000.0 / 22.0,
+            Baud::B50 => 50.0,
+            Baud::B75 => 75.0,
+            Baud::Other(r) => *r,
+        }
+    }
+}
+
+/// Standard amateur radio RTTY shift, in Hz.
+pub const DEFAULT_SHIFT: Float = 170.0;
+
+const LTRS: u8 = 0x1f;
+const FIGS: u8 = 0x1b;
+
+// ITA2 letters case, indexed by code.
+const LETTERS: [u8; 32] = [
+    0, b'E', b'\n', b'A', b' ', b'S', b'I', b'U', b'\r', b'D', b'R', b'J', b'N', b'F', b'C', b'K',
+    b'T', b'Z', b'L', b'W', b'H', b'Y', b'P', b'Q', b'O', b'B', b'G', 0, b'M', b'X', b'V', 0,
+];
+
+// US TTY figures case, indexed by code. This is what's almost
+// universally used in amateur radio.
+const FIGURES: [u8; 32] = [
+    0, b'3', b'\n', b'-', b' ', 7, b'8', b'7', b'\r', b'$', b'4', b'\'', b',', b'!', b':', b'(',
+    b'5', b'"', b')', b'2', b'#', b'6', b'0', b'1', b'9', b'?', b'&', 0, b'.', b'/', b';', 0,
+];
+
+/// Baudot to ASCII decoder, keeping track of letters/figures shift.
+pub struct BaudotDecoder {
+    figures: bool,
+    unshift_on_space: bool,
+}
+
+impl BaudotDecoder {
+    /// Create new decoder, starting in letters shift.
+    pub fn new() -> Self {
+        Self {
+            figures: false,
+            unshift_on_space: true,
+        }
+    }
+
+    /// Set "unshift on space" (USOS).
+    ///
+    /// When enabled (the default), receiving a space switches back to
+    /// letters, limiting the damage of a lost LTRS shift.
+    pub fn set_unshift_on_space(&mut self, v: bool) {
+        self.unshift_on_space = v;
+    }
+
+    /// Decode one five bit Baudot code.
+    ///
+    /// Returns None for shift codes and NUL.
+    pub fn decode(&mut self, code: u8) -> Option<u8> {
+        let code = code & 0x1f;
+        match code {
+            LTRS => {
+                self.figures = false;
+                None
+            }
+            FIGS => {
+                self.figures = true;
+                None
+            }
+            _ => {
+                let ch = if self.figures {
+                    FIGURES[code as usize]
+                } else {
+                    LETTERS[code as usize]
+                };
+                if ch == b' ' && self.unshift_on_space {
+                    self.figures = false;
+                }
+                if ch == 0 {
+                    None
+                } else {
+                    Some(ch)
+                }
+            }
+        }
+    }
+}
+
+impl Default for BaudotDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Encode ASCII text into Baudot codes, inserting shifts as needed.
+///
+/// Characters not in the Baudot character set are skipped. Lower case
+/// is sent as upper case. The output starts with a LTRS, to put the
+/// receiver in a known state.
+pub fn baudot_encode(text: &str) -> Vec<u8> {
+    let mut figures = false;
+    let mut ret = vec![LTRS];
+    for ch in text.bytes().map(|b| b.to_ascii_uppercase()) {
+        let find = |table: &[u8; 32]| table.iter().position(|c| *c == ch && ch != 0);
+        match (find(&LETTERS), find(&FIGURES)) {
+            (Some(l), Some(_)) => {
+                // Space, CR, and LF are in both.
+                ret.push(l as u8);
+                if ch == b' ' {
+                    // Receivers may unshift on space.
+                    figures = false;
+                }
+            }
+            (Some(l), None) => {
+                if figures {
+                    ret.push(LTRS);
+                    figures = false;
+                }
+                ret.push(l as u8);
+            }
+            (None, Some(f)) => {
+                if !figures {
+                    ret.push(FIGS);
+                    figures = true;
+                }
+                ret.push(f as u8);
+            }
+            (None, None) => {
+                debug!("baudot_encode: skipping unencodable char {ch}");
+            }
+        }
+    }
+    ret
+}
+
+enum State {
+    /// Waiting for a mark to space transition.
+    Idle,
+
+    /// Inside a character, counting samples since start bit edge.
+    Receiving { count: Float, bits: Vec<bool> },
+}
+
+/** RTTY decoder block.
+
+Takes demodulated FSK as floats, positive for mark, negative for space
+(unless reversed), and outputs decoded ASCII bytes.
+
+The input sample rate should be at least a few samples per bit.
+*/
+pub struct RttyDecode {
+    src: Streamp<Float>,
+    dst: Streamp<u8>,
+    sps: Float,
+    reverse: bool,
+    last_mark: bool,
+    state: State,
+    decoder: BaudotDecoder,
+    decoded: usize,
+    framing_errors: usize,
+}
+
+impl Drop for RttyDecode {
+    fn drop(&mut self) {
+        info!(
+            "RttyDecode: Decoded {} characters, {} framing errors",
+            self.decoded, self.framing_errors
+        );
+    }
+}
+
+impl RttyDecode {
+    /// Create new RTTY decoder.
+    pub fn new(src: Streamp<Float>, samp_rate: Float, baud: Baud) -> Self {
+        let sps = samp_rate / baud.rate();
+        assert!(sps > 2.0, "RttyDecode needs more than 2 samples per bit");
+        Self {
+            src,
+            dst: new_streamp(),
+            sps,
+            reverse: false,
+            last_mark: true,
+            state: State::Idle,
+            decoder: BaudotDecoder::new(),
+            decoded: 0,
+            framing_errors: 0,
+        }
+    }
+
+    /// Swap mark and space.
+    pub fn set_reverse(&mut self, v: bool) {
+        self.reverse = v;
+    }
+
+    /// Set "unshift on space". See [BaudotDecoder::set_unshift_on_space].
+    pub fn set_unshift_on_space(&mut self, v: bool) {
+        self.decoder.set_unshift_on_space(v);
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<u8> {
+        self.dst.clone()
+    }
+
+    // Process one sample, returning a decoded character, if any.
+    fn process_one(&mut self, s: Float) -> Option<u8> {
+        let mark = (s > 0.0) != self.reverse;
+        let last_mark = self.last_mark;
+        self.last_mark = mark;
+        match &mut self.state {
+            State::Idle => {
+                if last_mark && !mark {
+                    trace!("RttyDecode: start bit edge");
+                    self.state = State::Receiving {
+                        count: 0.0,
+                        bits: Vec::with_capacity(7),
+                    };
+                }
+                None
+            }
+            State::Receiving { count, bits } => {
+                *count += 1.0;
+                // Sample in the middle of each bit.
+                let center = (bits.len() as Float + 0.5) * self.sps;
+                if *count < center {
+                    return None;
+                }
+                bits.push(mark);
+                match bits.len() {
+                    1 if mark => {
+                        // Start bit was a glitch.
+                        self.state = State::Idle;
+                        None
+                    }
+                    7 => {
+                        let stop = mark;
+                        let code = bits[1..6]
+                            .iter()
+                            .enumerate()
+                            .fold(0u8, |acc, (n, b)| acc | ((*b as u8) << n));
+                        self.state = State::Idle;
+                        if !stop {
+                            self.framing_errors += 1;
+                            trace!("RttyDecode: framing error for code {code:#x}");
+                            return None;
+                        }
+                        self.decoded += 1;
+                        self.decoder.decode(code)
+                    }
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+impl Block for RttyDecode {
+    fn block_name(&self) -> &str {
+        "RttyDecode"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let obind = self.dst.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let mut o = obind.write_buf()?;
+
+        // Each character is at least 7 bits, so one output per input
+        // is always enough.
+        let n = std::cmp::min(input.len(), o.len());
+        let mut out = Vec::new();
+        for s in input.iter().take(n) {
+            if let Some(ch) = self.process_one(*s) {
+                out.push(ch);
+            }
+        }
+        let produced = out.len();
+        o.fill_from_slice(&out);
+        o.produce(produced, &[]);
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    fn modulate(codes: &[u8], sps: usize) -> Vec<Float> {
+        let mut ret = vec![1.0; sps * 3];
+        let mut bit = |v: bool, len: usize| {
+            ret.extend(std::iter::repeat_n(if v { 1.0 } else { -1.0 }, len));
+        };
+        for code in codes {
+            bit(false, sps);
+            for n in 0..5 {
+                bit((code >> n) & 1 == 1, sps);
+            }
+            bit(true, sps * 3 / 2);
+        }
+        ret
+    }
+
+    #[test]
+    fn baudot_roundtrip() {
+        let mut d = BaudotDecoder::new();
+        let text = "RYRY CQ DE SM0ABC 599 TU/73?";
+        let got: Vec<u8> = baudot_encode(text)
+            .into_iter()
+            .filter_map(|c| d.decode(c))
+            .collect();
+        assert_eq!(String::from_utf8(got).unwrap(), text);
+    }
+
+    #[test]
+    fn unshift_on_space() {
+        let mut d = BaudotDecoder::new();
+        // FIGS, '3', space, 'E'(==3 in figures)
+        let codes = [FIGS, 0x01, 0x04, 0x01];
+        let got: Vec<u8> = codes.iter().filter_map(|c| d.decode(*c)).collect();
+        assert_eq!(got, b"3 E");
+
+        let mut d = BaudotDecoder::new();
+        d.set_unshift_on_space(false);
+        let got: Vec<u8> = codes.iter().filter_map(|c| d.decode(*c)).collect();
+        assert_eq!(got, b"3 3");
+    }
+
+    #[test]
+    fn decode_stream() -> crate::Result<()> {
+        let text = "THE QUICK BROWN FOX 1234";
+        let samples = modulate(&baudot_encode(text), 22);
+        let src = streamp_from_slice(&samples);
+        let mut b = RttyDecode::new(src, 1000.0, Baud::B45);
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        assert_eq!(String::from_utf8(res.slice().to_vec())?, text);
+        Ok(())
+    }
+}
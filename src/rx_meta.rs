@@ -0,0 +1,575 @@
+/*! Receive metadata for decoded frames.
+
+[SignalMeter] sits in the I/Q stream, passing it through unchanged,
+and keeps a short history of signal power and frequency offset. It
+also tags the first sample of each measurement block with its
+[SAMPLE_POS]. The tags follow the samples through the demodulator and
+clock recovery, and [HdlcDeframer][crate::hdlc_deframer::HdlcDeframer]
+tags each frame with the [SAMPLE_POS] and [SAMPLE_END] that it spans.
+[AnnotatePdu] then measures exactly those samples, so the metadata is
+right even if the meter has run far ahead of the decoder, or several
+stations were heard at about the same time.
+
+```text
+   Source -> SignalMeter -> demod -> … -> HdlcDeframer -> ToPdu -> AnnotatePdu -> sink
+                   \______________ MeterHandle ______________________/
+```
+
+PDUs from deframers that don't pass on the positions are annotated
+with the strongest signal within a lookback window instead.
+
+`examples/ax25-1200-rx.rs` shows this in use, for I/Q input.
+
+Metadata added:
+
+| Key                 | Type  | Meaning                                          |
+|---------------------|-------|--------------------------------------------------|
+| [LEVEL]             | Float | Signal power, in dBFS                            |
+| [NOISE]             | Float | Noise floor, in dBFS                             |
+| [SNR][crate::pdu::SNR] | Float | Difference between the two, in dB             |
+| [FREQ_OFFSET]       | Float | Frequency offset of the signal, in Hz            |
+| [SAMPLE_POS]        | U64   | Sample where the signal started                  |
+| [SAMPLE_END]        | U64   | Start of the last measurement block of the frame |
+| [TIME][crate::pdu::TIME] | U64 | Time of that sample, in µs since epoch        |
+
+The time is calculated from the sample position and sample rate, with
+the time of the first sample set by [SignalMeter::set_start_time],
+default the time the meter was created. When reading from a recording,
+setting the start time to when it was recorded gives timestamps
+accurate to the measurement block size.
+*/
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use log::trace;
+
+use crate::block::{Block, BlockRet};
+use crate::pdu::{Pdu, SNR, TIME};
+use crate::stream::{new_nocopy_streamp, new_streamp, NoCopyStreamp, Streamp, Tag, TagValue};
+use crate::{Complex, Error, Float};
+
+/// Metadata key for signal power, in dBFS (Float).
+pub const LEVEL: &str = "level";
+
+/// Metadata key for noise floor, in dBFS (Float).
+pub const NOISE: &str = "noise";
+
+/// Metadata key for frequency offset, in Hz (Float).
+pub const FREQ_OFFSET: &str = "freq_offset";
+
+/// Metadata and tag key for sample position (U64).
+pub const SAMPLE_POS: &str = "sample_pos";
+
+/// Metadata key for the position of the last measurement block of a
+/// frame (U64).
+pub const SAMPLE_END: &str = "sample_end";
+
+// Measurement block length.
+const BLOCK_SECONDS: Float = 0.001;
+
+// How much history to keep.
+const HISTORY_SECONDS: Float = 10.0;
+
+// Blocks within this of the strongest are part of the signal.
+const SIGNAL_DB: Float = 3.0;
+
+// Noise floor is this percentile of the history.
+const NOISE_PERCENTILE: usize = 10;
+
+#[derive(Debug, Clone, Copy)]
+struct Point {
+    pos: u64,
+    power: Float,
+    freq: Float,
+}
+
+#[derive(Debug)]
+struct History {
+    points: VecDeque<Point>,
+    samp_rate: Float,
+    start_us: u64,
+    max_points: usize,
+}
+
+/// Measurement of a signal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    /// Signal power, in dBFS.
+    pub level: Float,
+
+    /// Noise floor, in dBFS.
+    pub noise: Float,
+
+    /// Frequency offset, in Hz.
+    pub freq_offset: Float,
+
+    /// First sample of the signal.
+    pub sample_pos: u64,
+
+    /// Time of the first sample, in µs since epoch.
+    pub time: u64,
+}
+
+impl Measurement {
+    /// Signal to noise ratio, in dB.
+    pub fn snr(&self) -> Float {
+        self.level - self.noise
+    }
+}
+
+/// Shared handle to the measurements of a [SignalMeter].
+#[derive(Debug, Clone)]
+pub struct MeterHandle {
+    h: Arc<Mutex<History>>,
+}
+
+fn db(power: Float) -> Float {
+    10.0 * power.max(1e-20).log10()
+}
+
+impl MeterHandle {
+    /// Measure the strongest signal within `lookback` of the most
+    /// recent sample.
+    pub fn measure(&self, lookback: Duration) -> Option<Measurement> {
+        let h = self.h.lock().unwrap();
+        let last = h.points.back()?.pos;
+        let lookback = (lookback.as_secs_f64() as Float * h.samp_rate) as u64;
+        let recent: Vec<&Point> = h
+            .points
+            .iter()
+            .filter(|p| p.pos + lookback >= last)
+            .collect();
+        let peak = recent.iter().map(|p| p.power).fold(0.0, Float::max);
+        let threshold = peak / Float::powf(10.0, SIGNAL_DB / 10.0);
+        let signal: Vec<&Point> = recent
+            .into_iter()
+            .filter(|p| p.power >= threshold)
+            .collect();
+        h.measurement(&signal)
+    }
+
+    /// Measure the signal in the blocks starting from sample `start`
+    /// up to and including `end`, as tagged by [SAMPLE_POS].
+    pub fn measure_range(&self, start: u64, end: u64) -> Option<Measurement> {
+        let h = self.h.lock().unwrap();
+        let signal: Vec<&Point> = h
+            .points
+            .iter()
+            .filter(|p| (start..=end).contains(&p.pos))
+            .collect();
+        h.measurement(&signal)
+    }
+}
+
+impl History {
+    fn measurement(&self, signal: &[&Point]) -> Option<Measurement> {
+        let sample_pos = signal.first()?.pos;
+        let total: Float = signal.iter().map(|p| p.power).sum();
+        let freq = signal.iter().map(|p| p.freq * p.power).sum::<Float>() / total.max(1e-20);
+        let level = total / signal.len() as Float;
+
+        let mut powers: Vec<Float> = self.points.iter().map(|p| p.power).collect();
+        powers.sort_by(|a, b| a.total_cmp(b));
+        let noise = powers[powers.len() * NOISE_PERCENTILE / 100];
+        Some(Measurement {
+            level: db(level),
+            noise: db(noise),
+            freq_offset: freq,
+            sample_pos,
+            time: self.start_us + (sample_pos as f64 * 1e6 / self.samp_rate as f64) as u64,
+        })
+    }
+}
+
+/** Signal meter block.
+
+Passes the stream through as is, and measures power and frequency
+offset in blocks of 1ms. The first sample of each block is tagged with
+its [SAMPLE_POS].
+*/
+pub struct SignalMeter {
+    src: Streamp<Complex>,
+    dst: Streamp<Complex>,
+    handle: MeterHandle,
+    block: usize,
+    n: usize,
+    pos: u64,
+    power: Float,
+    corr: Complex,
+    prev: Complex,
+}
+
+impl SignalMeter {
+    /// Create new signal meter.
+    pub fn new(src: Streamp<Complex>, samp_rate: Float) -> Self {
+        let block = ((samp_rate * BLOCK_SECONDS) as usize).max(1);
+        let start_us = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_micros() as u64;
+        Self {
+            src,
+            dst: new_streamp(),
+            handle: MeterHandle {
+                h: Arc::new(Mutex::new(History {
+                    points: VecDeque::new(),
+                    samp_rate,
+                    start_us,
+                    max_points: (HISTORY_SECONDS / BLOCK_SECONDS) as usize,
+                })),
+            },
+            block,
+            n: 0,
+            pos: 0,
+            power: 0.0,
+            corr: Complex::new(0.0, 0.0),
+            prev: Complex::new(0.0, 0.0),
+        }
+    }
+
+    /// Set the time of the first sample.
+    pub fn set_start_time(&mut self, t: SystemTime) {
+        self.handle.h.lock().unwrap().start_us = t
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_micros() as u64;
+    }
+
+    /// Get a handle to the measurements.
+    pub fn handle(&self) -> MeterHandle {
+        self.handle.clone()
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Complex> {
+        self.dst.clone()
+    }
+
+    fn process_one(&mut self, x: Complex) {
+        self.power += x.norm_sqr();
+        self.corr += x * self.prev.conj();
+        self.prev = x;
+        self.n += 1;
+        if self.n < self.block {
+            return;
+        }
+        let mut h = self.handle.h.lock().unwrap();
+        let point = Point {
+            pos: self.pos + 1 - self.n as u64,
+            power: self.power / self.n as Float,
+            freq: self.corr.arg() * h.samp_rate / (2.0 * std::f64::consts::PI as Float),
+        };
+        trace!("SignalMeter: {point:?}");
+        h.points.push_back(point);
+        if h.points.len() > h.max_points {
+            h.points.pop_front();
+        }
+        drop(h);
+        self.n = 0;
+        self.power = 0.0;
+        self.corr = Complex::new(0.0, 0.0);
+    }
+}
+
+impl Block for SignalMeter {
+    fn block_name(&self) -> &str {
+        "SignalMeter"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let obind = self.dst.clone();
+        let mut o = obind.write_buf()?;
+        let n = std::cmp::min(input.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::Ok);
+        }
+        let mut tags: Vec<_> = tags.into_iter().filter(|t| t.pos() < n).collect();
+        for (i, x) in input.iter().take(n).enumerate() {
+            if self.n == 0 {
+                tags.push(Tag::new(i, SAMPLE_POS.into(), TagValue::U64(self.pos)));
+            }
+            self.process_one(*x);
+            self.pos += 1;
+        }
+        o.slice()[..n].copy_from_slice(&input.slice()[..n]);
+        o.produce(n, &tags);
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+/** Add receive metadata to PDUs.
+
+PDUs with [SAMPLE_POS] and [SAMPLE_END] metadata, e.g. from tags set
+by the deframer, are measured over exactly that range. Others get the
+strongest signal within the lookback.
+
+See the module documentation for the metadata added.
+*/
+pub struct AnnotatePdu {
+    src: NoCopyStreamp<Pdu>,
+    dst: NoCopyStreamp<Pdu>,
+    meter: MeterHandle,
+    lookback: Duration,
+}
+
+impl AnnotatePdu {
+    /// Create new block, looking back 1s for the signal.
+    pub fn new(src: NoCopyStreamp<Pdu>, meter: MeterHandle) -> Self {
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            meter,
+            lookback: Duration::from_secs(1),
+        }
+    }
+
+    /// Set how far back to look for the signal. Should be a bit longer
+    /// than the longest frame, plus the decode latency.
+    pub fn set_lookback(&mut self, lookback: Duration) {
+        self.lookback = lookback;
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<Pdu> {
+        self.dst.clone()
+    }
+}
+
+impl Block for AnnotatePdu {
+    fn block_name(&self) -> &str {
+        "AnnotatePdu"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let Some((mut pdu, tags)) = self.src.pop() else {
+            return Ok(BlockRet::Noop);
+        };
+        let m = match (pdu.get(SAMPLE_POS), pdu.get(SAMPLE_END)) {
+            (Some(TagValue::U64(start)), Some(TagValue::U64(end))) => {
+                self.meter.measure_range(*start, *end)
+            }
+            _ => self.meter.measure(self.lookback),
+        };
+        if let Some(m) = m {
+            pdu.set(LEVEL, TagValue::Float(m.level));
+            pdu.set(NOISE, TagValue::Float(m.noise));
+            pdu.set(SNR, TagValue::Float(m.snr()));
+            pdu.set(FREQ_OFFSET, TagValue::Float(m.freq_offset));
+            pdu.set(SAMPLE_POS, TagValue::U64(m.sample_pos));
+            pdu.set(TIME, TagValue::U64(m.time));
+        }
+        self.dst.push(pdu, &tags);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+    use std::ops::Range;
+
+    // Noise, with tones at (sample range, frequency, amplitude).
+    fn signal(
+        samp_rate: Float,
+        len: usize,
+        tones: &[(Range<usize>, Float, Float)],
+    ) -> Vec<Complex> {
+        let mut x: u32 = 1;
+        let mut noise = move || {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            ((x >> 16) % 1000) as Float / 1000.0 - 0.5
+        };
+        (0..len)
+            .map(|i| {
+                let mut s = Complex::new(noise(), noise()) * 0.01;
+                for (range, freq, amp) in tones {
+                    if range.contains(&i) {
+                        let ph =
+                            2.0 * std::f64::consts::PI as Float * freq * i as Float / samp_rate;
+                        s += Complex::new(ph.cos(), ph.sin()) * *amp;
+                    }
+                }
+                s
+            })
+            .collect()
+    }
+
+    fn float(pdu: &Pdu, k: &str) -> Float {
+        match pdu.get(k) {
+            Some(TagValue::Float(f)) => *f,
+            other => panic!("{k}: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn measure() -> anyhow::Result<()> {
+        let samp_rate = 10_000.0;
+        // 1s noise, 0.2s tone at +500Hz, 0.3s noise.
+        let signal = signal(samp_rate, 15_000, &[(10_000..12_000, 500.0, 0.5)]);
+        let mut meter = SignalMeter::new(streamp_from_slice(&signal), samp_rate);
+        meter.set_start_time(SystemTime::UNIX_EPOCH + Duration::from_secs(1000));
+        meter.work()?;
+        {
+            let out = meter.out();
+            let (o, _) = out.read_buf()?;
+            assert_eq!(o.slice(), &signal[..]);
+        }
+
+        let pdus = new_nocopy_streamp();
+        pdus.push(Pdu::new(vec![1]), &[]);
+        let mut b = AnnotatePdu::new(pdus, meter.handle());
+        b.work()?;
+        let (pdu, _) = b.out().pop().unwrap();
+        assert!((float(&pdu, LEVEL) - -6.0).abs() < 0.5, "{pdu:?}");
+        assert!((float(&pdu, NOISE) - -48.0).abs() < 3.0, "{pdu:?}");
+        assert!(float(&pdu, SNR) > 35.0, "{pdu:?}");
+        assert!((float(&pdu, FREQ_OFFSET) - 500.0).abs() < 5.0, "{pdu:?}");
+        assert_eq!(pdu.get(SAMPLE_POS), Some(&TagValue::U64(10_000)));
+        assert_eq!(pdu.time(), Some(1_001_000_000));
+        Ok(())
+    }
+
+    #[test]
+    fn measure_range() -> anyhow::Result<()> {
+        let samp_rate = 10_000.0;
+        // A strong station, then a weak one, both within the lookback.
+        let signal = signal(
+            samp_rate,
+            15_000,
+            &[
+                (10_000..11_000, 500.0, 0.5),
+                (12_000..13_000, -1000.0, 0.05),
+            ],
+        );
+        let mut meter = SignalMeter::new(streamp_from_slice(&signal), samp_rate);
+        meter.set_start_time(SystemTime::UNIX_EPOCH + Duration::from_secs(1000));
+        meter.work()?;
+        {
+            let out = meter.out();
+            let (_, tags) = out.read_buf()?;
+            let pos: Vec<_> = tags
+                .iter()
+                .filter(|t| t.key() == SAMPLE_POS)
+                .map(|t| (t.pos(), t.val().clone()))
+                .collect();
+            assert_eq!(pos.len(), 1500);
+            assert_eq!(pos[1], (10, TagValue::U64(10)));
+            assert_eq!(pos[1200], (12_000, TagValue::U64(12_000)));
+        }
+
+        // The weak one was decoded.
+        let pdus = new_nocopy_streamp();
+        pdus.push(
+            Pdu::new(vec![1])
+                .with(SAMPLE_POS, TagValue::U64(12_000))
+                .with(SAMPLE_END, TagValue::U64(12_990)),
+            &[],
+        );
+        let mut b = AnnotatePdu::new(pdus, meter.handle());
+        b.work()?;
+        let (pdu, _) = b.out().pop().unwrap();
+        assert!((float(&pdu, LEVEL) - -26.0).abs() < 0.5, "{pdu:?}");
+        assert!((float(&pdu, FREQ_OFFSET) - -1000.0).abs() < 10.0, "{pdu:?}");
+        assert_eq!(pdu.get(SAMPLE_POS), Some(&TagValue::U64(12_000)));
+        assert_eq!(pdu.time(), Some(1_001_200_000));
+        Ok(())
+    }
+
+    // The AX.25 1200 receive chain of examples/ax25-1200-rx, on a
+    // frame sent at +1kHz: the PDU out should be measured over the
+    // frame, not the noise around it.
+    #[cfg(feature = "fft")]
+    #[test]
+    fn ax25_chain() -> anyhow::Result<()> {
+        use crate::afsk::{BELL202_BAUD, BELL202_MARK, BELL202_SPACE};
+        use crate::blocks::*;
+        use crate::graph::Graph;
+        use crate::{add_block, flowgraph};
+
+        let samp_rate = 50_000.0;
+        let data = b"rustradio test frame".to_vec();
+
+        // Transmit.
+        let frames = new_nocopy_streamp();
+        frames.push(data.clone(), &[]);
+        let mut g = Graph::new();
+        let prev = frames;
+        let prev = flowgraph![g, prev =>
+            HdlcFramer::new(prev),
+            NrziEncode::new(prev),
+            AfskModulator::new(prev, samp_rate)?,
+            FrequencyModulator::new(prev, 2.0 * std::f64::consts::PI as Float * 3000.0 / samp_rate),
+        ];
+        let sink = VectorSink::new(prev);
+        let tx = sink.handle();
+        g.add(Box::new(sink));
+        g.run()?;
+        let tx = tx.data();
+
+        // Noise around it, and shift it up 1kHz.
+        let start = 20_000;
+        let mut input = signal(samp_rate, start + tx.len() + 20_000, &[]);
+        for (i, x) in tx.iter().enumerate() {
+            let ph = 2.0 * std::f64::consts::PI as Float * 1000.0 * i as Float / samp_rate;
+            input[start + i] += x * Complex::new(ph.cos(), ph.sin()) * 0.5;
+        }
+
+        // Receive.
+        let mut g = Graph::new();
+        let meter = SignalMeter::new(streamp_from_slice(&input), samp_rate);
+        let handle = meter.handle();
+        let prev = add_block![g, meter];
+        let taps = crate::fir::low_pass(samp_rate, 1100.0, 100.0);
+        let center = (BELL202_MARK + BELL202_SPACE) / 2.0;
+        let prev = flowgraph![g, prev =>
+            QuadratureDemod::new(prev, 1.0),
+            Hilbert::new(prev, 65),
+            QuadratureDemod::new(prev, 1.0),
+            FftFilterFloat::new(prev, &taps),
+            AddConst::new(prev, -center * 2.0 * std::f64::consts::PI as Float / samp_rate),
+            SymbolSync::new(
+                prev,
+                samp_rate / BELL202_BAUD,
+                0.5,
+                Box::new(crate::symbol_sync::TEDZeroCrossing::new()),
+                Box::new(crate::iir_filter::IIRFilter::new(&[0.5, 0.5])),
+            ),
+            BinarySlicer::new(prev),
+            NrziDecode::new(prev),
+            HdlcDeframer::new(prev, 10, 1500),
+            ToPdu::new(prev),
+            AnnotatePdu::new(prev, handle),
+        ];
+        g.run()?;
+
+        let (pdu, _) = prev.pop().expect("no frame decoded");
+        assert_eq!(pdu.data(), &data[..]);
+        assert!(prev.pop().is_none());
+        let Some(TagValue::U64(pos)) = pdu.get(SAMPLE_POS) else {
+            panic!("no sample pos: {pdu:?}");
+        };
+        let Some(TagValue::U64(end)) = pdu.get(SAMPLE_END) else {
+            panic!("positions not passed on by the chain: {pdu:?}");
+        };
+        // Positions lag by the filter delays, but not by much.
+        let (pos, end, stop) = (*pos as usize, *end as usize, start + tx.len());
+        assert!(
+            pos >= start && pos < end && end < stop + 1000,
+            "{stop} {pdu:?}"
+        );
+        assert!((float(&pdu, LEVEL) - -6.0).abs() < 1.0, "{pdu:?}");
+        assert!(float(&pdu, SNR) > 20.0, "{pdu:?}");
+        // Both tones are within ±deviation of the carrier.
+        assert!(
+            (float(&pdu, FREQ_OFFSET) - 1000.0).abs() < 3000.0,
+            "{pdu:?}"
+        );
+        Ok(())
+    }
+}
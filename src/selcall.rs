@@ -0,0 +1,325 @@
+/*! Selective calling (selcall) decoder.
+
+[Selcall] is used on analog PMR (private mobile radio) to address a
+radio by sending a sequence of tones, typically five, one per digit.
+A digit that's the same as the one before is sent as the repeat tone
+"E" instead, since two identical tones in a row can't be told apart.
+
+Supported standards:
+
+| Standard | Tone length | 0    | 1    | 9    | E (repeat) |
+|----------|-------------|------|------|------|------------|
+| CCIR     | 100ms       | 1981 | 1124 | 1860 | 2110       |
+| ZVEI     | 70ms        | 2400 | 1060 | 2200 | 2600       |
+| EEA      | 40ms        | 1981 | 1124 | 1860 | 2110       |
+
+[SelcallDecode] detects tones with a [ToneBank], with blocks a
+quarter of the tone length. A tone is accepted if it lasts at least
+half the nominal length. A sequence ends with a gap of at least half a
+tone, and is output as a [SelcallEvent] if it has at least
+[MIN_DIGITS] digits.
+
+[Selcall]: https://en.wikipedia.org/wiki/Selective_calling
+*/
+use anyhow::Result;
+use log::{debug, info, trace};
+
+use crate::block::{Block, BlockRet};
+use crate::goertzel::{ToneBank, ToneBlock};
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp, Streamp};
+use crate::{Error, Float};
+
+/// Minimum number of digits in a sequence.
+pub const MIN_DIGITS: usize = 3;
+
+// Blocks per nominal tone length.
+const BLOCKS_PER_TONE: usize = 4;
+
+// Minimum fraction of block energy in the strongest tone.
+const MIN_FRACTION: Float = 0.5;
+
+// Other tones must be this much weaker.
+const MIN_DOMINANCE: Float = 2.5;
+
+// Blocks with lower mean square are silence.
+const MIN_ENERGY: Float = 1e-6;
+
+const REPEAT: usize = 0xe;
+
+/// Selcall standard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Standard {
+    /// CCIR, 100ms tones.
+    #[default]
+    Ccir,
+
+    /// ZVEI-1, 70ms tones.
+    Zvei,
+
+    /// EEA, 40ms tones.
+    Eea,
+}
+
+impl Standard {
+    /// Tone frequencies, indexed by hex digit 0-F.
+    pub fn tones(&self) -> [Float; 16] {
+        match self {
+            Standard::Ccir => [
+                1981.0, 1124.0, 1197.0, 1275.0, 1358.0, 1446.0, 1540.0, 1640.0, 1747.0, 1860.0,
+                2400.0, 930.0, 2247.0, 991.0, 2110.0, 2600.0,
+            ],
+            Standard::Zvei => [
+                2400.0, 1060.0, 1160.0, 1270.0, 1400.0, 1530.0, 1670.0, 1830.0, 2000.0, 2200.0,
+                2800.0, 810.0, 970.0, 885.0, 2600.0, 680.0,
+            ],
+            Standard::Eea => [
+                1981.0, 1124.0, 1197.0, 1275.0, 1358.0, 1446.0, 1540.0, 1640.0, 1747.0, 1860.0,
+                1055.0, 930.0, 2247.0, 991.0, 2110.0, 2400.0,
+            ],
+        }
+    }
+
+    /// Nominal tone length, in seconds.
+    pub fn tone_length(&self) -> Float {
+        match self {
+            Standard::Ccir => 0.1,
+            Standard::Zvei => 0.07,
+            Standard::Eea => 0.04,
+        }
+    }
+
+    /// Encode digits into tone indices, inserting repeat tones.
+    pub fn encode(digits: &str) -> Option<Vec<usize>> {
+        let mut ret: Vec<usize> = Vec::new();
+        let mut prev = None;
+        for c in digits.chars() {
+            let d = c.to_digit(16)? as usize;
+            if d == REPEAT {
+                return None;
+            }
+            let t = if prev == Some(d) { REPEAT } else { d };
+            ret.push(t);
+            prev = Some(t);
+        }
+        Some(ret)
+    }
+}
+
+/// A received selcall sequence.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SelcallEvent {
+    /// Standard.
+    pub standard: Standard,
+
+    /// Digits, with repeat tones resolved. Digits above 9 are A-D and F.
+    pub digits: String,
+}
+
+/** Selcall decoder block.
+
+Input is audio. Output is one event per tone sequence.
+*/
+pub struct SelcallDecode {
+    src: Streamp<Float>,
+    dst: NoCopyStreamp<SelcallEvent>,
+    standard: Standard,
+    bank: ToneBank,
+    // Current tone and its length in blocks.
+    run: Option<(usize, usize)>,
+    silence: usize,
+    digits: Vec<usize>,
+    events: usize,
+}
+
+impl SelcallDecode {
+    /// Create new selcall decoder.
+    pub fn new(src: Streamp<Float>, samp_rate: Float, standard: Standard) -> Self {
+        let block = samp_rate * standard.tone_length() / BLOCKS_PER_TONE as Float;
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            standard,
+            bank: ToneBank::new(samp_rate, &standard.tones(), block.round() as usize),
+            run: None,
+            silence: 0,
+            digits: Vec::new(),
+            events: 0,
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<SelcallEvent> {
+        self.dst.clone()
+    }
+
+    fn detect(b: &ToneBlock) -> Option<usize> {
+        if b.energy < MIN_ENERGY {
+            return None;
+        }
+        let (n, p) = b.strongest(0..16);
+        (p >= MIN_FRACTION && b.dominates(0..16, n, MIN_DOMINANCE)).then_some(n)
+    }
+
+    // End the current tone, adding it if long enough.
+    fn end_tone(&mut self) {
+        let Some((tone, n)) = self.run.take() else {
+            return;
+        };
+        if n < BLOCKS_PER_TONE / 2 {
+            trace!("SelcallDecode: tone {tone:x} too short: {n}");
+            return;
+        }
+        let d = match (tone, self.digits.last()) {
+            (REPEAT, Some(prev)) => *prev,
+            (REPEAT, None) => return,
+            (d, _) => d,
+        };
+        self.digits.push(d);
+    }
+
+    fn process_block(&mut self, b: &ToneBlock) -> Option<SelcallEvent> {
+        let tone = Self::detect(b);
+        match (tone, self.run) {
+            (Some(t), Some((r, n))) if t == r => {
+                self.run = Some((r, n + 1));
+                self.silence = 0;
+            }
+            (Some(t), _) => {
+                self.end_tone();
+                self.run = Some((t, 1));
+                self.silence = 0;
+            }
+            (None, _) => {
+                self.silence += 1;
+                // Allow a one block glitch between tones.
+                if self.silence > 1 {
+                    self.end_tone();
+                }
+            }
+        }
+        if self.silence < BLOCKS_PER_TONE / 2 || self.digits.is_empty() {
+            return None;
+        }
+        let digits = std::mem::take(&mut self.digits);
+        if digits.len() < MIN_DIGITS {
+            trace!("SelcallDecode: too few digits: {digits:?}");
+            return None;
+        }
+        Some(SelcallEvent {
+            standard: self.standard,
+            digits: digits
+                .iter()
+                .map(|d| {
+                    std::char::from_digit(*d as u32, 16)
+                        .unwrap()
+                        .to_ascii_uppercase()
+                })
+                .collect(),
+        })
+    }
+}
+
+impl Drop for SelcallDecode {
+    fn drop(&mut self) {
+        info!("SelcallDecode: {} sequences", self.events);
+    }
+}
+
+impl Block for SelcallDecode {
+    fn block_name(&self) -> &str {
+        "SelcallDecode"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        for x in input.iter() {
+            let Some(b) = self.bank.push(*x) else {
+                continue;
+            };
+            if let Some(ev) = self.process_block(&b) {
+                debug!("SelcallDecode: {:?} {}", ev.standard, ev.digits);
+                self.events += 1;
+                self.dst.push(ev, &[]);
+            }
+        }
+        let n = input.len();
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    const SAMP_RATE: Float = 8000.0;
+
+    fn send(standard: Standard, digits: &str, stretch: Float) -> Vec<Float> {
+        let mut x: u32 = 1;
+        let mut noise = move || {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            ((x >> 16) % 1000) as Float / 1000.0 - 0.5
+        };
+        let len = (standard.tone_length() * stretch * SAMP_RATE) as usize;
+        let mut out: Vec<Float> = (0..1000).map(|_| 0.01 * noise()).collect();
+        let mut phase: Float = 0.0;
+        for t in Standard::encode(digits).unwrap() {
+            let f = standard.tones()[t];
+            for _ in 0..len {
+                phase += 2.0 * std::f64::consts::PI as Float * f / SAMP_RATE;
+                out.push(0.3 * phase.sin() + 0.01 * noise());
+            }
+        }
+        out.extend((0..4000).map(|_| 0.01 * noise()));
+        out
+    }
+
+    fn decode(standard: Standard, samples: &[Float]) -> Result<Vec<String>> {
+        let mut b = SelcallDecode::new(streamp_from_slice(samples), SAMP_RATE, standard);
+        b.work()?;
+        let out = b.out();
+        let mut ret = Vec::new();
+        while let Some((e, _)) = out.pop() {
+            assert_eq!(e.standard, standard);
+            ret.push(e.digits);
+        }
+        Ok(ret)
+    }
+
+    #[test]
+    fn encode() {
+        assert_eq!(Standard::encode("1223").unwrap(), vec![1, 2, 0xe, 3]);
+        assert_eq!(Standard::encode("222").unwrap(), vec![2, 0xe, 2]);
+        assert!(Standard::encode("12E").is_none());
+    }
+
+    #[test]
+    fn standards() -> Result<()> {
+        for standard in [Standard::Ccir, Standard::Zvei, Standard::Eea] {
+            for digits in ["12234", "90A5F", "77777"] {
+                let got = decode(standard, &send(standard, digits, 1.0))?;
+                assert_eq!(got, vec![digits.to_string()], "{standard:?}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn timing_tolerance() -> Result<()> {
+        for stretch in [0.7, 1.5] {
+            let got = decode(Standard::Zvei, &send(Standard::Zvei, "13579", stretch))?;
+            assert_eq!(got, vec!["13579".to_string()], "{stretch}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn too_short() -> Result<()> {
+        assert!(decode(Standard::Ccir, &send(Standard::Ccir, "12", 1.0))?.is_empty());
+        Ok(())
+    }
+}
@@ -2,19 +2,20 @@
 
 /*
  * TODO:
- * create sink block.
  * add sigmf archive (tar) support.
  */
+use std::collections::VecDeque;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::time::SystemTime;
+
 use anyhow::Result;
+use log::{debug, error, trace};
 use serde::{Deserialize, Serialize};
-use std::io::Write;
 
-const DATATYPE_CF32: &str = "cf32";
 const VERSION: &str = "1.1.0";
 
 use crate::block::{Block, BlockRet};
-use crate::file_source::FileSource;
-use crate::stream::Streamp;
+use crate::stream::{new_streamp, Streamp, Tag, TagValue, TAG_FREQUENCY, TAG_SAMP_RATE};
 use crate::{Complex, Error, Float, Sample};
 
 /// Capture segment.
@@ -152,11 +153,11 @@ pub struct SigMF {
     global: Global,
 
     /// Capture segments.
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     captures: Vec<Capture>,
 
     /// Annotations on the data.
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     annotations: Vec<Annotation>,
 }
 
@@ -173,7 +174,7 @@ pub fn write(fname: &str, samp_rate: f64, freq: f64) -> Result<()> {
     let data = SigMF {
         global: Global {
             core_version: VERSION.to_string(),
-            core_datatype: DATATYPE_CF32.to_string(),
+            core_datatype: Complex::type_string().to_string() + "_le",
             core_sample_rate: Some(samp_rate),
             ..Default::default()
         },
@@ -194,6 +195,30 @@ pub fn write(fname: &str, samp_rate: f64, freq: f64) -> Result<()> {
     Ok(())
 }
 
+// ISO 8601 UTC time, as `core:datetime` wants it.
+fn iso8601(t: SystemTime) -> String {
+    let d = t.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let secs = d.as_secs();
+    let rem = secs % 86400;
+    // Days to civil date, from Howard Hinnant's date algorithms.
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+        d.subsec_millis()
+    )
+}
+
 /// SigMF source builder.
 pub struct SigMFSourceBuilder<T: Copy + Type> {
     filename: String,
@@ -222,16 +247,48 @@ impl<T: Default + Copy + Type> SigMFSourceBuilder<T> {
 }
 
 /// SigMF file source.
+///
+/// Files of other data types are converted, see [DataType].
+///
+/// Header bytes of capture segments are skipped. The sample rate is
+/// tagged on the first sample, and the frequency of each capture
+/// segment on its first sample, as [TAG_SAMP_RATE] and
+/// [TAG_FREQUENCY].
 pub struct SigMFSource<T: Copy> {
-    // TODO: Can't continue to delegate reading the data, because tags.
-    file_source: FileSource<T>,
+    filename: String,
+    f: BufReader<std::fs::File>,
+    // Partial sample left over from last read.
+    buf: Vec<u8>,
+    // Capture segments not yet reached.
+    captures: VecDeque<Capture>,
+    // Samples read so far.
+    pos: u64,
     sample_rate: Option<f64>,
+    frequency: Option<f64>,
+    // Data type of the file, if it needs converting.
+    datatype: Option<DataType>,
+    dst: Streamp<T>,
 }
 
 /// Trait that needs implementing for all supported SigMF data types.
-pub trait Type {
+pub trait Type: Sized {
     /// Return full type, or endianness prefix of the type.
     fn type_string() -> &'static str;
+
+    /// Create from real and imaginary parts, for reading files of
+    /// other data types. Integer types are scaled to ±1.0.
+    ///
+    /// None if this type doesn't support conversion.
+    fn from_components(_c: [Float; 2]) -> Option<Self> {
+        None
+    }
+
+    /// Real and imaginary parts, for writing other data types.
+    ///
+    /// None if this type doesn't support conversion.
+    fn to_components(&self) -> Option<[Float; 2]> {
+        None
+    }
 }
 
 impl Type for i32 {
@@ -248,20 +305,248 @@ impl Type for num_complex::Complex<i32> {
 
 impl Type for Complex {
     fn type_string() -> &'static str {
-        // TODO: support Float being 64bit.
-        assert_eq![std::mem::size_of::<Float>(), 4];
-        "cf32"
+        if std::mem::size_of::<Float>() == 8 {
+            "cf64"
+        } else {
+            "cf32"
+        }
+    }
+    fn from_components(c: [Float; 2]) -> Option<Self> {
+        Some(Complex::new(c[0], c[1]))
+    }
+    fn to_components(&self) -> Option<[Float; 2]> {
+        Some([self.re, self.im])
     }
 }
 
 impl Type for Float {
     fn type_string() -> &'static str {
-        // TODO: support Float being 64bit.
-        assert_eq![std::mem::size_of::<Float>(), 4];
-        "rf32"
+        if std::mem::size_of::<Float>() == 8 {
+            "rf64"
+        } else {
+            "rf32"
+        }
+    }
+    fn from_components(c: [Float; 2]) -> Option<Self> {
+        Some(c[0])
+    }
+    fn to_components(&self) -> Option<[Float; 2]> {
+        Some([*self, 0.0])
+    }
+}
+
+/// Format of each component of a sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// 64 bit float.
+    F64,
+    /// 32 bit float.
+    F32,
+    /// Signed 32 bit integer.
+    I32,
+    /// Signed 16 bit integer.
+    I16,
+    /// Signed 8 bit integer.
+    I8,
+    /// Unsigned 32 bit integer.
+    U32,
+    /// Unsigned 16 bit integer.
+    U16,
+    /// Unsigned 8 bit integer.
+    U8,
+}
+
+impl Format {
+    const ALL: [(Format, &'static str); 8] = [
+        (Format::F64, "f64"),
+        (Format::F32, "f32"),
+        (Format::I32, "i32"),
+        (Format::I16, "i16"),
+        (Format::I8, "i8"),
+        (Format::U32, "u32"),
+        (Format::U16, "u16"),
+        (Format::U8, "u8"),
+    ];
+
+    fn name(self) -> &'static str {
+        Self::ALL.iter().find(|(f, _)| *f == self).unwrap().1
+    }
+
+    /// Size in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            Format::F64 => 8,
+            Format::F32 | Format::I32 | Format::U32 => 4,
+            Format::I16 | Format::U16 => 2,
+            Format::I8 | Format::U8 => 1,
+        }
+    }
+}
+
+/** SigMF data type, e.g. `ci16_le`.
+
+[SigMFSource] converts files of any data type to [Complex] or [Float],
+as long as both are complex or both are real. Integers are scaled to
+±1.0, with unsigned integers centered on the middle of their range.
+[SigMFSinkBuilder::datatype] converts the other way.
+
+```
+use rustradio::sigmf::{DataType, Format};
+let dt = DataType::parse("cu8")?;
+assert_eq!(dt, DataType::new(Format::U8, true, false));
+assert_eq!(dt.size(), 2);
+assert_eq!(DataType::new(Format::I16, true, false).to_string(), "ci16_le");
+# Ok::<(), rustradio::Error>(())
+```
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataType {
+    format: Format,
+    complex: bool,
+    big_endian: bool,
+}
+
+impl DataType {
+    /// Create new data type.
+    pub fn new(format: Format, complex: bool, big_endian: bool) -> Self {
+        Self {
+            format,
+            complex,
+            big_endian,
+        }
+    }
+
+    /// Parse a `core:datatype` string.
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        let bad = || Error::TypeMismatch(format!("unsupported SigMF datatype {s}"));
+        let complex = match s.get(..1) {
+            Some("c") => true,
+            Some("r") => false,
+            _ => return Err(bad()),
+        };
+        let (name, big_endian) = match s[1..].split_once('_') {
+            Some((name, "le")) => (name, false),
+            Some((name, "be")) => (name, true),
+            None => (&s[1..], false),
+            _ => return Err(bad()),
+        };
+        let format = Format::ALL
+            .iter()
+            .find(|(_, n)| *n == name)
+            .ok_or_else(bad)?
+            .0;
+        // Endianness is required for everything but bytes.
+        if format.size() > 1 && !s.contains('_') {
+            return Err(bad());
+        }
+        Ok(Self::new(format, complex, big_endian))
+    }
+
+    /// Data format of each component.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// True if complex.
+    pub fn is_complex(&self) -> bool {
+        self.complex
+    }
+
+    /// Size of a sample, in bytes.
+    pub fn size(&self) -> usize {
+        self.format.size() * if self.complex { 2 } else { 1 }
+    }
+
+    // Convert one sample of `size()` bytes. Imaginary part is zero
+    // for real types.
+    fn decode(&self, data: &[u8]) -> [Float; 2] {
+        let size = self.format.size();
+        let mut ret = [0.0; 2];
+        for (n, d) in data.chunks_exact(size).enumerate() {
+            let mut b = [0u8; 8];
+            b[..size].copy_from_slice(d);
+            if self.big_endian {
+                b[..size].reverse();
+            }
+            ret[n] = match self.format {
+                Format::F64 => f64::from_le_bytes(b) as Float,
+                Format::F32 => f32::from_le_bytes(b[..4].try_into().unwrap()) as Float,
+                Format::I32 => {
+                    (i32::from_le_bytes(b[..4].try_into().unwrap()) as f64 / 2147483648.0) as Float
+                }
+                Format::I16 => i16::from_le_bytes([b[0], b[1]]) as Float / 32768.0,
+                Format::I8 => b[0] as i8 as Float / 128.0,
+                Format::U32 => {
+                    ((u32::from_le_bytes(b[..4].try_into().unwrap()) as f64 - 2147483648.0)
+                        / 2147483648.0) as Float
+                }
+                Format::U16 => (u16::from_le_bytes([b[0], b[1]]) as Float - 32768.0) / 32768.0,
+                Format::U8 => (b[0] as Float - 128.0) / 128.0,
+            };
+        }
+        ret
+    }
+
+    // Append one sample, clamping integers to their range.
+    fn encode(&self, c: [Float; 2], out: &mut Vec<u8>) {
+        let parts = if self.complex { 2 } else { 1 };
+        for &v in &c[..parts] {
+            let v = v as f64;
+            let int = |scale: f64, offset: f64, min: f64, max: f64| {
+                (v * scale + offset).round().clamp(min, max)
+            };
+            let mut b: Vec<u8> = match self.format {
+                Format::F64 => v.to_le_bytes().into(),
+                Format::F32 => (v as f32).to_le_bytes().into(),
+                Format::I32 => (int(2147483648.0, 0.0, i32::MIN as f64, i32::MAX as f64) as i32)
+                    .to_le_bytes()
+                    .into(),
+                Format::I16 => (int(32768.0, 0.0, i16::MIN as f64, i16::MAX as f64) as i16)
+                    .to_le_bytes()
+                    .into(),
+                Format::I8 => vec![int(128.0, 0.0, -128.0, 127.0) as i8 as u8],
+                Format::U32 => (int(2147483648.0, 2147483648.0, 0.0, u32::MAX as f64) as u32)
+                    .to_le_bytes()
+                    .into(),
+                Format::U16 => (int(32768.0, 32768.0, 0.0, u16::MAX as f64) as u16)
+                    .to_le_bytes()
+                    .into(),
+                Format::U8 => vec![int(128.0, 128.0, 0.0, 255.0) as u8],
+            };
+            if self.big_endian {
+                b.reverse();
+            }
+            out.extend(b);
+        }
     }
 }
 
+impl std::fmt::Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let c = if self.complex { "c" } else { "r" };
+        write!(f, "{c}{}", self.format.name())?;
+        if self.format.size() > 1 {
+            write!(f, "{}", if self.big_endian { "_be" } else { "_le" })?;
+        }
+        Ok(())
+    }
+}
+
+// The data type of T, or None if T can't be converted to it.
+fn check_conversion<T: Type>(dt: DataType) -> Result<Option<DataType>, Error> {
+    let native = T::type_string().to_owned() + "_le";
+    if dt.to_string() == native {
+        return Ok(None);
+    }
+    let complex = T::type_string().starts_with('c');
+    if T::from_components([0.0; 2]).is_none() || dt.complex != complex {
+        return Err(Error::TypeMismatch(format!(
+            "can't convert between SigMF {dt} and {native}"
+        )));
+    }
+    Ok(Some(dt))
+}
+
 impl<T: Default + Copy + Type> SigMFSource<T> {
     /// Create a new SigMF source block.
     pub fn new(filename: &str, samp_rate: Option<f64>) -> Result<Self> {
@@ -269,7 +554,7 @@ impl<T: Default + Copy + Type> SigMFSource<T> {
         if let Some(samp_rate) = samp_rate {
             if let Some(t) = meta.global.core_sample_rate {
                 if t != samp_rate {
-                    return Err(Error::new(&format!(
+                    return Err(Error::BadParameter(format!(
                         "sigmf file {} sample rate ({}) is not the expected {}",
                         filename, t, samp_rate
                     ))
@@ -277,28 +562,42 @@ impl<T: Default + Copy + Type> SigMFSource<T> {
                 }
             }
         }
-        // TODO: support i8/u8 and _be.
-        let expected_type = T::type_string().to_owned() + "_le";
-        if meta.global.core_datatype != expected_type {
-            return Err(Error::new(&format!(
-                "sigmf file {} data type ({}) not the expected {}",
-                filename, meta.global.core_datatype, expected_type
-            ))
-            .into());
-        }
+        let datatype = match DataType::parse(&meta.global.core_datatype) {
+            // Natively supported types, e.g. ci32_le, don't need to parse.
+            Err(_) if meta.global.core_datatype == T::type_string().to_owned() + "_le" => None,
+            dt => check_conversion::<T>(dt?)
+                .map_err(|e| Error::TypeMismatch(format!("sigmf file {filename}: {e}")))?,
+        };
+        let mut captures: VecDeque<Capture> = meta.captures.into();
+        captures
+            .make_contiguous()
+            .sort_by_key(|c| c.core_sample_start);
+        let datafile = format!("{filename}-data");
+        debug!("Opening SigMF source {datafile}");
         Ok(Self {
+            filename: datafile.clone(),
+            f: BufReader::new(std::fs::File::open(datafile)?),
+            buf: Vec::new(),
+            frequency: captures.front().and_then(|c| c.core_frequency),
+            captures,
+            pos: 0,
             sample_rate: meta.global.core_sample_rate,
-            file_source: FileSource::new(&format!["{}-data", filename], false)?,
+            datatype,
+            dst: new_streamp(),
         })
     }
     /// Return the output stream.
     pub fn out(&self) -> Streamp<T> {
-        self.file_source.out()
+        self.dst.clone()
     }
     /// Get the sample rate from the meta file.
     pub fn sample_rate(&self) -> Option<f64> {
         self.sample_rate
     }
+    /// Get the center frequency of the first capture segment.
+    pub fn frequency(&self) -> Option<f64> {
+        self.frequency
+    }
 }
 
 impl<T> Block for SigMFSource<T>
@@ -309,6 +608,697 @@ where
         "SigMFSource"
     }
     fn work(&mut self) -> Result<BlockRet, Error> {
-        self.file_source.work()
+        let mut o = self.dst.write_buf()?;
+        if o.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let sample_size = self.datatype.map(|dt| dt.size()).unwrap_or(T::size());
+
+        // Enter capture segments starting here.
+        let mut tags = Vec::new();
+        if self.pos == 0 {
+            if let Some(rate) = self.sample_rate {
+                tags.push(Tag::new(
+                    0,
+                    TAG_SAMP_RATE.into(),
+                    TagValue::Float(rate as Float),
+                ));
+            }
+        }
+        while let Some(c) = self.captures.front() {
+            if c.core_sample_start > self.pos {
+                break;
+            }
+            if let Some(skip) = c.core_header_bytes {
+                trace!("SigMFSource: skipping {skip} header bytes");
+                std::io::copy(&mut (&mut self.f).take(skip), &mut std::io::sink())?;
+            }
+            if let Some(freq) = c.core_frequency {
+                tags.push(Tag::new(
+                    0,
+                    TAG_FREQUENCY.into(),
+                    TagValue::Float(freq as Float),
+                ));
+            }
+            self.captures.pop_front();
+        }
+
+        // Don't read past the start of the next segment, since its
+        // header bytes are in the way.
+        let mut want = o.len();
+        if let Some(c) = self.captures.front() {
+            want = want.min((c.core_sample_start - self.pos) as usize);
+        }
+        let have = self.buf.len();
+        self.buf.resize(want * sample_size, 0);
+        let n = self
+            .f
+            .read(&mut self.buf[have..])
+            .map_err(|e| -> anyhow::Error { e.into() })?;
+        self.buf.truncate(have + n);
+        if n == 0 {
+            debug!("EOF on {}", self.filename);
+            return Ok(BlockRet::EOF);
+        }
+        let got = self.buf.len() / sample_size;
+        o.fill_from_iter(
+            self.buf
+                .chunks_exact(sample_size)
+                .map(|d| match self.datatype {
+                    Some(dt) => Ok(T::from_components(dt.decode(d)).unwrap()),
+                    None => T::parse(d),
+                })
+                .collect::<Result<Vec<_>>>()?,
+        );
+        self.buf.drain(..got * sample_size);
+        self.pos += got as u64;
+        o.produce(got, &tags);
+        Ok(BlockRet::Ok)
+    }
+}
+
+/// SigMF sink builder.
+pub struct SigMFSinkBuilder<T: Copy> {
+    src: Streamp<T>,
+    filename: String,
+    sample_rate: Option<f64>,
+    frequency: Option<f64>,
+    hw: Option<String>,
+    author: Option<String>,
+    description: Option<String>,
+    datatype: Option<DataType>,
+    datetime: Option<SystemTime>,
+}
+
+impl<T: Copy + Type> SigMFSinkBuilder<T> {
+    /// Create new SigMF sink builder, writing `filename`-data and
+    /// `filename`-meta.
+    pub fn new(src: Streamp<T>, filename: &str) -> Self {
+        Self {
+            src,
+            filename: filename.to_string(),
+            sample_rate: None,
+            frequency: None,
+            hw: None,
+            author: None,
+            description: None,
+            datatype: None,
+            datetime: None,
+        }
+    }
+    /// Set sample rate.
+    pub fn sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = Some(rate);
+        self
+    }
+    /// Set center frequency of the first capture segment.
+    pub fn frequency(mut self, freq: f64) -> Self {
+        self.frequency = Some(freq);
+        self
+    }
+    /// Set hardware used.
+    pub fn hw(mut self, hw: &str) -> Self {
+        self.hw = Some(hw.to_string());
+        self
+    }
+    /// Set author.
+    pub fn author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+    /// Set description.
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+    /// Set time of the first sample.
+    pub fn datetime(mut self, t: SystemTime) -> Self {
+        self.datetime = Some(t);
+        self
+    }
+    /// Write samples as this data type, e.g. `ci16_le` to save space.
+    /// Default is the type of the stream.
+    pub fn datatype(mut self, dt: DataType) -> Self {
+        self.datatype = Some(dt);
+        self
+    }
+    /// Build a SigMFSink, creating the data file. Fails if it already
+    /// exists.
+    pub fn build(self) -> Result<SigMFSink<T>> {
+        let datatype = self
+            .datatype
+            .map(check_conversion::<T>)
+            .transpose()?
+            .flatten();
+        let datafile = format!("{}-data", self.filename);
+        debug!("Opening SigMF sink {datafile}");
+        let f = std::fs::File::options()
+            .write(true)
+            .create_new(true)
+            .open(&datafile)?;
+        Ok(SigMFSink {
+            src: self.src,
+            filename: self.filename,
+            f: Some(BufWriter::new(f)),
+            sha: Sha512::new(),
+            pos: 0,
+            datatype,
+            global: Global {
+                core_version: VERSION.to_string(),
+                core_datatype: match datatype {
+                    Some(dt) => dt.to_string(),
+                    None => T::type_string().to_string() + "_le",
+                },
+                core_sample_rate: self.sample_rate,
+                core_hw: self.hw,
+                core_author: self.author,
+                core_description: self.description,
+                core_recorder: Some("rustradio".to_string()),
+                ..Default::default()
+            },
+            captures: vec![Capture {
+                core_sample_start: 0,
+                core_frequency: self.frequency,
+                core_datetime: self.datetime.map(iso8601),
+                ..Default::default()
+            }],
+            annotations: Vec::new(),
+        })
+    }
+}
+
+/// SigMF file sink.
+///
+/// Writes the data file as samples arrive, and the meta file when
+/// [closed](SigMFSink::close) or dropped. A [TAG_FREQUENCY] tag
+/// starts a new capture segment with that frequency.
+pub struct SigMFSink<T: Copy> {
+    src: Streamp<T>,
+    filename: String,
+    // None once closed.
+    f: Option<BufWriter<std::fs::File>>,
+    sha: Sha512,
+    // Samples written so far.
+    pos: u64,
+    // Data type to convert to, if not the stream's own.
+    datatype: Option<DataType>,
+    global: Global,
+    captures: Vec<Capture>,
+    annotations: Vec<Annotation>,
+}
+
+impl<T: Copy + Type> SigMFSink<T> {
+    /// Create a builder.
+    pub fn builder(src: Streamp<T>, filename: &str) -> SigMFSinkBuilder<T> {
+        SigMFSinkBuilder::new(src, filename)
+    }
+}
+
+impl<T: Copy> SigMFSink<T> {
+    /// Samples written so far.
+    pub fn samples(&self) -> u64 {
+        self.pos
+    }
+
+    /// Annotate `count` samples from `start`, or just the one sample
+    /// if `count` is None. Written to the meta file on close.
+    pub fn annotate(&mut self, start: u64, count: Option<u64>, label: &str) {
+        self.annotations.push(Annotation {
+            core_sample_start: start,
+            core_sample_count: count,
+            core_generator: Some("rustradio".to_string()),
+            core_label: Some(label.to_string()),
+            core_comment: None,
+            core_freq_lower_edge: None,
+            core_freq_upper_edge: None,
+            core_uuid: None,
+        });
+    }
+}
+
+impl<T: Copy> SigMFSink<T> {
+    /// Flush the data file, and write the meta file. Further samples
+    /// are not written.
+    ///
+    /// Called on drop, if not called before.
+    pub fn close(&mut self) -> Result<()> {
+        let Some(mut f) = self.f.take() else {
+            return Ok(());
+        };
+        f.flush()?;
+        let sha = std::mem::replace(&mut self.sha, Sha512::new());
+        let meta = SigMF {
+            global: Global {
+                core_sha512: Some(sha.hex()),
+                ..std::mem::take(&mut self.global)
+            },
+            captures: std::mem::take(&mut self.captures),
+            annotations: std::mem::take(&mut self.annotations),
+        };
+        let fname = format!("{}-meta", self.filename);
+        std::fs::write(&fname, serde_json::to_string_pretty(&meta)?)?;
+        debug!("SigMFSink: wrote {fname}");
+        Ok(())
+    }
+}
+
+impl<T: Copy> Drop for SigMFSink<T> {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            error!("SigMFSink: failed to close {}: {e}", self.filename);
+        }
+    }
+}
+
+impl<T> Block for SigMFSink<T>
+where
+    T: Sample<Type = T> + Copy + std::fmt::Debug + Type,
+{
+    fn block_name(&self) -> &str {
+        "SigMFSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (i, tags) = self.src.read_buf()?;
+        let n = i.len();
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        let Some(f) = &mut self.f else {
+            // Closed. Discard.
+            i.consume(n);
+            return Ok(BlockRet::Ok);
+        };
+        for tag in &tags {
+            let (TAG_FREQUENCY, TagValue::Float(freq)) = (tag.key(), tag.val()) else {
+                continue;
+            };
+            let start = self.pos + tag.pos() as u64;
+            let freq = Some(*freq as f64);
+            match self.captures.last_mut() {
+                // Same sample, so replace.
+                Some(c) if c.core_sample_start == start => c.core_frequency = freq,
+                Some(c) if c.core_frequency == freq => {}
+                _ => self.captures.push(Capture {
+                    core_sample_start: start,
+                    core_frequency: freq,
+                    ..Default::default()
+                }),
+            }
+        }
+        let mut v = Vec::with_capacity(T::size() * n);
+        i.iter().for_each(|s: &T| match self.datatype {
+            Some(dt) => dt.encode(s.to_components().unwrap(), &mut v),
+            None => v.extend(&s.serialize()),
+        });
+        f.write_all(&v)?;
+        self.sha.update(&v);
+        self.pos += n as u64;
+        i.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+// SHA-512, for `core:sha512`.
+struct Sha512 {
+    h: [u64; 8],
+    // Partial block.
+    buf: Vec<u8>,
+    // Total bytes.
+    len: u128,
+}
+
+#[rustfmt::skip]
+const SHA512_K: [u64; 80] = [
+    0x428a2f98d728ae22, 0x7137449123ef65cd, 0xb5c0fbcfec4d3b2f, 0xe9b5dba58189dbbc,
+    0x3956c25bf348b538, 0x59f111f1b605d019, 0x923f82a4af194f9b, 0xab1c5ed5da6d8118,
+    0xd807aa98a3030242, 0x12835b0145706fbe, 0x243185be4ee4b28c, 0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f, 0x80deb1fe3b1696b1, 0x9bdc06a725c71235, 0xc19bf174cf692694,
+    0xe49b69c19ef14ad2, 0xefbe4786384f25e3, 0x0fc19dc68b8cd5b5, 0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275, 0x4a7484aa6ea6e483, 0x5cb0a9dcbd41fbd4, 0x76f988da831153b5,
+    0x983e5152ee66dfab, 0xa831c66d2db43210, 0xb00327c898fb213f, 0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2, 0xd5a79147930aa725, 0x06ca6351e003826f, 0x142929670a0e6e70,
+    0x27b70a8546d22ffc, 0x2e1b21385c26c926, 0x4d2c6dfc5ac42aed, 0x53380d139d95b3df,
+    0x650a73548baf63de, 0x766a0abb3c77b2a8, 0x81c2c92e47edaee6, 0x92722c851482353b,
+    0xa2bfe8a14cf10364, 0xa81a664bbc423001, 0xc24b8b70d0f89791, 0xc76c51a30654be30,
+    0xd192e819d6ef5218, 0xd69906245565a910, 0xf40e35855771202a, 0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8, 0x1e376c085141ab53, 0x2748774cdf8eeb99, 0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63, 0x4ed8aa4ae3418acb, 0x5b9cca4f7763e373, 0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc, 0x78a5636f43172f60, 0x84c87814a1f0ab72, 0x8cc702081a6439ec,
+    0x90befffa23631e28, 0xa4506cebde82bde9, 0xbef9a3f7b2c67915, 0xc67178f2e372532b,
+    0xca273eceea26619c, 0xd186b8c721c0c207, 0xeada7dd6cde0eb1e, 0xf57d4f7fee6ed178,
+    0x06f067aa72176fba, 0x0a637dc5a2c898a6, 0x113f9804bef90dae, 0x1b710b35131c471b,
+    0x28db77f523047d84, 0x32caab7b40c72493, 0x3c9ebe0a15c9bebc, 0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6, 0x597f299cfc657e2a, 0x5fcb6fab3ad6faec, 0x6c44198c4a475817,
+];
+
+impl Sha512 {
+    fn new() -> Self {
+        Self {
+            h: [
+                0x6a09e667f3bcc908,
+                0xbb67ae8584caa73b,
+                0x3c6ef372fe94f82b,
+                0xa54ff53a5f1d36f1,
+                0x510e527fade682d1,
+                0x9b05688c2b3e6c1f,
+                0x1f83d9abfb41bd6b,
+                0x5be0cd19137e2179,
+            ],
+            buf: Vec::with_capacity(128),
+            len: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u128;
+        if !self.buf.is_empty() {
+            let n = (128 - self.buf.len()).min(data.len());
+            self.buf.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.buf.len() < 128 {
+                return;
+            }
+            let block: [u8; 128] = self.buf[..].try_into().unwrap();
+            self.block(&block);
+            self.buf.clear();
+        }
+        let mut chunks = data.chunks_exact(128);
+        for block in &mut chunks {
+            self.block(block.try_into().unwrap());
+        }
+        self.buf.extend_from_slice(chunks.remainder());
+    }
+
+    fn block(&mut self, block: &[u8; 128]) {
+        let mut w = [0u64; 80];
+        for (i, b) in block.chunks_exact(8).enumerate() {
+            w[i] = u64::from_be_bytes(b.try_into().unwrap());
+        }
+        for i in 16..80 {
+            let s0 = w[i - 15].rotate_right(1) ^ w[i - 15].rotate_right(8) ^ (w[i - 15] >> 7);
+            let s1 = w[i - 2].rotate_right(19) ^ w[i - 2].rotate_right(61) ^ (w[i - 2] >> 6);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.h;
+        for i in 0..80 {
+            let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA512_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (h, v) in self.h.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    // Finish, returning the hash as lowercase hex.
+    fn hex(mut self) -> String {
+        let bits = self.len * 8;
+        let mut pad = vec![0x80u8];
+        pad.resize((239 - (self.len % 128) as usize) % 128 + 1, 0);
+        pad.extend(bits.to_be_bytes());
+        self.update(&pad);
+        assert!(self.buf.is_empty());
+        self.h.iter().map(|v| format!("{v:016x}")).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source() -> Result<()> {
+        let tmpd = tempfile::tempdir()?;
+        let base = tmpd.path().join("test.sigmf");
+        let base = base.to_string_lossy();
+
+        // Two segments, each with a header.
+        let meta = SigMF {
+            global: Global {
+                core_version: VERSION.to_string(),
+                core_datatype: Float::type_string().to_string() + "_le",
+                core_sample_rate: Some(1000.0),
+                ..Default::default()
+            },
+            captures: vec![
+                Capture {
+                    core_sample_start: 3,
+                    core_header_bytes: Some(2),
+                    core_frequency: Some(200.0),
+                    ..Default::default()
+                },
+                Capture {
+                    core_sample_start: 0,
+                    core_header_bytes: Some(5),
+                    core_frequency: Some(100.0),
+                    ..Default::default()
+                },
+            ],
+            annotations: Vec::new(),
+        };
+        std::fs::write(format!("{base}-meta"), serde_json::to_string(&meta)?)?;
+        let mut data = vec![0xff; 5];
+        for s in [1.0 as Float, 2.0, 3.0] {
+            data.extend(s.to_le_bytes());
+        }
+        data.extend([0xff; 2]);
+        for s in [4.0 as Float, 5.0] {
+            data.extend(s.to_le_bytes());
+        }
+        std::fs::write(format!("{base}-data"), data)?;
+
+        assert!(SigMFSource::<Float>::new(&base, Some(2000.0)).is_err());
+        assert!(SigMFSource::<Complex>::new(&base, None).is_err());
+
+        let mut src = SigMFSource::<Float>::new(&base, Some(1000.0))?;
+        assert_eq!(src.sample_rate(), Some(1000.0));
+        assert_eq!(src.frequency(), Some(100.0));
+        while !matches!(src.work()?, BlockRet::EOF) {}
+        let o = src.out();
+        let (res, tags) = o.read_buf()?;
+        assert_eq!(res.slice(), &[1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(
+            tags,
+            vec![
+                Tag::new(0, TAG_SAMP_RATE.into(), TagValue::Float(1000.0)),
+                Tag::new(0, TAG_FREQUENCY.into(), TagValue::Float(100.0)),
+                Tag::new(3, TAG_FREQUENCY.into(), TagValue::Float(200.0)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn datetime() {
+        let t = |s| SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(s);
+        assert_eq!(iso8601(t(0)), "1970-01-01T00:00:00.000Z");
+        assert_eq!(iso8601(t(951_782_400_000)), "2000-02-29T00:00:00.000Z");
+        assert_eq!(iso8601(t(1_700_000_000_123)), "2023-11-14T22:13:20.123Z");
+    }
+
+    #[test]
+    fn datatypes() -> Result<()> {
+        for t in [
+            "cf64_le", "cf32_be", "ci32_le", "ci16_le", "ci8", "cu32_be", "cu16_le", "cu8",
+            "rf32_le", "ri16_be", "ru8",
+        ] {
+            assert_eq!(DataType::parse(t)?.to_string(), t);
+        }
+        for t in ["cf32", "xf32_le", "ci16_xe", "cu8_be_le", "ci7_le", ""] {
+            assert!(DataType::parse(t).is_err(), "{t}");
+        }
+        assert_eq!(
+            DataType::parse("ru8_le")?,
+            DataType::new(Format::U8, false, false)
+        );
+        Ok(())
+    }
+
+    fn write_file(base: &str, datatype: &str, data: &[u8]) -> Result<()> {
+        let meta = SigMF {
+            global: Global {
+                core_version: VERSION.to_string(),
+                core_datatype: datatype.to_string(),
+                ..Default::default()
+            },
+            captures: Vec::new(),
+            annotations: Vec::new(),
+        };
+        std::fs::write(format!("{base}-meta"), serde_json::to_string(&meta)?)?;
+        std::fs::write(format!("{base}-data"), data)?;
+        Ok(())
+    }
+
+    fn read_file<T>(base: &str) -> Result<Vec<T>>
+    where
+        T: Sample<Type = T> + Copy + Default + std::fmt::Debug + Type,
+    {
+        let mut src = SigMFSource::<T>::new(base, None)?;
+        while !matches!(src.work()?, BlockRet::EOF) {}
+        let o = src.out();
+        let (res, _) = o.read_buf()?;
+        Ok(res.slice().to_vec())
+    }
+
+    #[test]
+    fn convert_source() -> Result<()> {
+        let tmpd = tempfile::tempdir()?;
+        let base = tmpd.path().join("test.sigmf");
+        let base = base.to_string_lossy();
+
+        // RTL-SDR style.
+        write_file(&base, "cu8", &[128, 0, 255, 192])?;
+        assert_eq!(
+            read_file::<Complex>(&base)?,
+            vec![Complex::new(0.0, -1.0), Complex::new(0.9921875, 0.5)]
+        );
+        assert!(SigMFSource::<Float>::new(&base, None).is_err());
+
+        let mut data = Vec::new();
+        for v in [16384i16, -32768, 0, 1] {
+            data.extend(v.to_le_bytes());
+        }
+        write_file(&base, "ci16_le", &data)?;
+        assert_eq!(
+            read_file::<Complex>(&base)?,
+            vec![Complex::new(0.5, -1.0), Complex::new(0.0, 1.0 / 32768.0)]
+        );
+
+        write_file(&base, "ri16_be", &[0x40, 0, 0xc0, 0])?;
+        assert_eq!(read_file::<Float>(&base)?, vec![0.5, -0.5]);
+
+        write_file(&base, "rf64_le", &(-0.25f64).to_le_bytes())?;
+        assert_eq!(read_file::<Float>(&base)?, vec![-0.25]);
+        Ok(())
+    }
+
+    #[test]
+    fn convert_sink() -> Result<()> {
+        let tmpd = tempfile::tempdir()?;
+        let base = tmpd.path().join("test.sigmf");
+        let base = base.to_string_lossy();
+
+        let input = [Complex::new(0.5, -1.0), Complex::new(2.0, -0.25)];
+        let src = crate::stream::streamp_from_slice(&input);
+        let mut sink = SigMFSink::builder(src, &base)
+            .datatype(DataType::parse("ci16_le")?)
+            .build()?;
+        sink.work()?;
+        drop(sink);
+        assert_eq!(parse_meta(&base)?.global.core_datatype, "ci16_le");
+        let mut want = Vec::new();
+        // Clamped to full scale.
+        for v in [16384i16, -32768, 32767, -8192] {
+            want.extend(v.to_le_bytes());
+        }
+        assert_eq!(std::fs::read(format!("{base}-data"))?, want);
+        assert_eq!(
+            read_file::<Complex>(&base)?,
+            vec![
+                Complex::new(0.5, -1.0),
+                Complex::new(32767.0 / 32768.0, -0.25)
+            ]
+        );
+
+        let src = crate::stream::streamp_from_slice(&input);
+        assert!(SigMFSink::builder(src, &(base.to_string() + "2"))
+            .datatype(DataType::parse("rf32_le")?)
+            .build()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sha512() {
+        let hash = |data: &[u8]| {
+            let mut sha = Sha512::new();
+            sha.update(data);
+            sha.hex()
+        };
+        assert_eq!(hash(b""), "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e");
+        assert_eq!(hash(b"abc"), "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f");
+
+        // Fed in uneven pieces.
+        let mut sha = Sha512::new();
+        let data: Vec<u8> = (0..10_000).map(|n| (n * 7) as u8).collect();
+        let mut rest = &data[..];
+        for n in 0.. {
+            let (piece, r) = rest.split_at(((n % 7) * 50 + 1).min(rest.len()));
+            sha.update(piece);
+            rest = r;
+            if rest.is_empty() {
+                break;
+            }
+        }
+        assert_eq!(sha.hex(), hash(&data));
+        assert_eq!(hash(&vec![b'a'; 1_000_000]), "e718483d0ce769644e2e42c7bc15b4638e1f98b13b2044285632a803afa973ebde0ff244877ea60a4cb0432ce577c31beb009c5c2c49aa2e4eadb217ad8cc09b");
+    }
+
+    #[test]
+    fn sink() -> Result<()> {
+        let tmpd = tempfile::tempdir()?;
+        let base = tmpd.path().join("test.sigmf");
+        let base = base.to_string_lossy();
+
+        let input: Vec<Complex> = (0..10).map(|n| Complex::new(n as Float, -1.0)).collect();
+        let src = crate::stream::new_streamp();
+        let mut w = src.write_buf()?;
+        w.fill_from_slice(&input);
+        w.produce(
+            input.len(),
+            &[Tag::new(4, TAG_FREQUENCY.into(), TagValue::Float(2000.0))],
+        );
+        let mut sink = SigMFSink::builder(src, &base)
+            .sample_rate(1000.0)
+            .frequency(1000.0)
+            .hw("test")
+            .author("me")
+            .build()?;
+        sink.work()?;
+        drop(sink);
+
+        // Data file is not overwritten.
+        let src = crate::stream::streamp_from_slice(&input);
+        assert!(SigMFSink::builder(src, &base).build().is_err());
+
+        let meta = parse_meta(&base)?;
+        assert_eq!(meta.global.core_hw.as_deref(), Some("test"));
+        assert_eq!(meta.global.core_author.as_deref(), Some("me"));
+        let mut sha = Sha512::new();
+        sha.update(&std::fs::read(format!("{base}-data"))?);
+        assert_eq!(meta.global.core_sha512, Some(sha.hex()));
+
+        let mut src = SigMFSource::<Complex>::new(&base, Some(1000.0))?;
+        while !matches!(src.work()?, BlockRet::EOF) {}
+        let o = src.out();
+        let (res, tags) = o.read_buf()?;
+        assert_eq!(res.slice(), input);
+        assert_eq!(
+            tags,
+            vec![
+                Tag::new(0, TAG_SAMP_RATE.into(), TagValue::Float(1000.0)),
+                Tag::new(0, TAG_FREQUENCY.into(), TagValue::Float(1000.0)),
+                Tag::new(4, TAG_FREQUENCY.into(), TagValue::Float(2000.0)),
+            ]
+        );
+        Ok(())
     }
 }
@@ -1,4 +1,26 @@
-//! Generate a pure signal.
+/*! Generate a pure signal.
+
+[SignalSource] generates sine, square, triangle, or constant signals,
+as [Float] or [Complex]. Useful for tests, and as a mixing oscillator.
+
+Complex signals are the waveform as the imaginary part, and the
+waveform a quarter period ahead as the real part. So a complex sine is
+`amplitude·e^(jωt)`, with positive frequencies turning
+counterclockwise.
+
+```
+use rustradio::blocks::SignalSource;
+use rustradio::signal_source::Waveform;
+use rustradio::{Complex, Float};
+// Mix down by 10kHz.
+let lo = SignalSource::<Complex>::new(1_000_000.0, -10_000.0, 1.0);
+// Audio test tone.
+let tone = SignalSource::<Float>::builder(48_000.0, 1000.0)
+    .waveform(Waveform::Square)
+    .amplitude(0.5)
+    .build();
+```
+*/
 use anyhow::Result;
 
 use crate::block::{Block, BlockRet};
@@ -60,3 +82,285 @@ impl Block for SignalSourceComplex {
         Ok(BlockRet::Ok)
     }
 }
+
+/// Waveform for [SignalSource].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Waveform {
+    /// Sine wave.
+    #[default]
+    Sine,
+
+    /// Square wave, high for the first half of each period.
+    Square,
+
+    /// Triangle wave, in phase with the sine wave.
+    Triangle,
+
+    /// Constant. Frequency and phase are ignored.
+    Constant,
+}
+
+impl Waveform {
+    /// Value at `phase` radians, between -1 and 1.
+    pub fn value(&self, phase: f64) -> Float {
+        use std::f64::consts::{FRAC_PI_2, TAU};
+        let phase = phase.rem_euclid(TAU);
+        (match self {
+            Waveform::Sine => phase.sin(),
+            Waveform::Square => {
+                if phase < TAU / 2.0 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => {
+                // Up from 0 to 1 in the first quarter, down to -1 by
+                // three quarters, and back up to 0.
+                let p = phase / FRAC_PI_2;
+                if p < 1.0 {
+                    p
+                } else if p < 3.0 {
+                    2.0 - p
+                } else {
+                    p - 4.0
+                }
+            }
+            Waveform::Constant => 1.0,
+        }) as Float
+    }
+}
+
+/// Sample types [SignalSource] can generate.
+pub trait SignalType: Copy {
+    /// Sample of `waveform` at `phase`.
+    fn generate(waveform: Waveform, phase: f64, amplitude: Float) -> Self;
+}
+
+impl SignalType for Float {
+    fn generate(waveform: Waveform, phase: f64, amplitude: Float) -> Self {
+        amplitude * waveform.value(phase)
+    }
+}
+
+impl SignalType for Complex {
+    fn generate(waveform: Waveform, phase: f64, amplitude: Float) -> Self {
+        if waveform == Waveform::Constant {
+            return Complex::new(amplitude, 0.0);
+        }
+        amplitude
+            * Complex::new(
+                waveform.value(phase + std::f64::consts::FRAC_PI_2),
+                waveform.value(phase),
+            )
+    }
+}
+
+/// Builder for [SignalSource].
+pub struct SignalSourceBuilder<T> {
+    samp_rate: Float,
+    freq: Float,
+    waveform: Waveform,
+    amplitude: Float,
+    phase: f64,
+    dummy: std::marker::PhantomData<T>,
+}
+
+impl<T: SignalType + Default> SignalSourceBuilder<T> {
+    /// Create new builder, for a sine wave of `freq` Hz, with
+    /// amplitude 1.0.
+    pub fn new(samp_rate: Float, freq: Float) -> Self {
+        Self {
+            samp_rate,
+            freq,
+            waveform: Waveform::default(),
+            amplitude: 1.0,
+            phase: 0.0,
+            dummy: std::marker::PhantomData,
+        }
+    }
+
+    /// Set waveform.
+    pub fn waveform(mut self, w: Waveform) -> Self {
+        self.waveform = w;
+        self
+    }
+
+    /// Set amplitude.
+    pub fn amplitude(mut self, a: Float) -> Self {
+        self.amplitude = a;
+        self
+    }
+
+    /// Set phase of the first sample, in radians.
+    pub fn phase(mut self, rad: Float) -> Self {
+        self.phase = rad as f64;
+        self
+    }
+
+    /// Build SignalSource block.
+    pub fn build(self) -> SignalSource<T> {
+        SignalSource {
+            dst: new_streamp(),
+            waveform: self.waveform,
+            amplitude: self.amplitude,
+            rad_per_sample: 2.0 * std::f64::consts::PI * self.freq as f64 / self.samp_rate as f64,
+            phase: self.phase.rem_euclid(2.0 * std::f64::consts::PI),
+        }
+    }
+}
+
+/// Signal generator block.
+///
+/// See the [module docs](crate::signal_source).
+pub struct SignalSource<T: Copy> {
+    dst: Streamp<T>,
+    waveform: Waveform,
+    amplitude: Float,
+    rad_per_sample: f64,
+    phase: f64,
+}
+
+impl<T: SignalType + Default> SignalSource<T> {
+    /// Create new sine wave source.
+    pub fn new(samp_rate: Float, freq: Float, amplitude: Float) -> Self {
+        Self::builder(samp_rate, freq).amplitude(amplitude).build()
+    }
+
+    /// Create a builder.
+    pub fn builder(samp_rate: Float, freq: Float) -> SignalSourceBuilder<T> {
+        SignalSourceBuilder::new(samp_rate, freq)
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<T> {
+        self.dst.clone()
+    }
+}
+
+impl<T: SignalType> Block for SignalSource<T> {
+    fn block_name(&self) -> &str {
+        "SignalSource"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let mut o = self.dst.write_buf()?;
+        let n = o.len();
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        for d in o.slice().iter_mut() {
+            *d = T::generate(self.waveform, self.phase, self.amplitude);
+            self.phase = (self.phase + self.rad_per_sample).rem_euclid(2.0 * std::f64::consts::PI);
+        }
+        o.produce(n, &[]);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{assert_almost_equal_complex, assert_almost_equal_float};
+
+    fn first<T: SignalType + Default>(mut b: SignalSource<T>, n: usize) -> Result<Vec<T>> {
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        Ok(res.slice()[..n].to_vec())
+    }
+
+    #[test]
+    fn waveforms() -> Result<()> {
+        // Four samples per period.
+        let b = |w| SignalSource::<Float>::builder(4.0, 1.0).waveform(w);
+        assert_almost_equal_float(
+            &first(b(Waveform::Sine).amplitude(2.0).build(), 5)?,
+            &[0.0, 2.0, 0.0, -2.0, 0.0],
+        );
+        assert_almost_equal_float(
+            &first(b(Waveform::Square).build(), 5)?,
+            &[1.0, 1.0, -1.0, -1.0, 1.0],
+        );
+        assert_almost_equal_float(
+            &first(b(Waveform::Triangle).build(), 5)?,
+            &[0.0, 1.0, 0.0, -1.0, 0.0],
+        );
+        assert_almost_equal_float(
+            &first(b(Waveform::Constant).amplitude(0.5).build(), 3)?,
+            &[0.5, 0.5, 0.5],
+        );
+
+        // Triangle between the points.
+        let tri = SignalSource::<Float>::builder(8.0, 1.0)
+            .waveform(Waveform::Triangle)
+            .build();
+        assert_almost_equal_float(
+            &first(tri, 8)?,
+            &[0.0, 0.5, 1.0, 0.5, 0.0, -0.5, -1.0, -0.5],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn phase() -> Result<()> {
+        let b = SignalSource::<Float>::builder(4.0, 1.0)
+            .phase(std::f64::consts::FRAC_PI_2 as Float)
+            .build();
+        assert_almost_equal_float(&first(b, 4)?, &[1.0, 0.0, -1.0, 0.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn complex() -> Result<()> {
+        assert_almost_equal_complex(
+            &first(SignalSource::<Complex>::new(4.0, 1.0, 1.0), 4)?,
+            &[
+                Complex::new(1.0, 0.0),
+                Complex::new(0.0, 1.0),
+                Complex::new(-1.0, 0.0),
+                Complex::new(0.0, -1.0),
+            ],
+        );
+        // Negative frequency turns the other way.
+        assert_almost_equal_complex(
+            &first(SignalSource::<Complex>::new(4.0, -1.0, 1.0), 2)?,
+            &[Complex::new(1.0, 0.0), Complex::new(0.0, -1.0)],
+        );
+        let sq = SignalSource::<Complex>::builder(4.0, 1.0)
+            .waveform(Waveform::Square)
+            .build();
+        assert_almost_equal_complex(
+            &first(sq, 4)?,
+            &[
+                Complex::new(1.0, 1.0),
+                Complex::new(-1.0, 1.0),
+                Complex::new(-1.0, -1.0),
+                Complex::new(1.0, -1.0),
+            ],
+        );
+        let c = SignalSource::<Complex>::builder(4.0, 1.0)
+            .waveform(Waveform::Constant)
+            .build();
+        assert_almost_equal_complex(&first(c, 2)?, &[Complex::new(1.0, 0.0); 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn continuous() -> Result<()> {
+        // Phase carries over between work() calls.
+        let mut b = SignalSource::<Float>::new(1000.0, 7.0, 1.0);
+        b.work()?;
+        let o = b.out();
+        let (res, _) = o.read_buf()?;
+        let n = res.len();
+        let last = res.slice()[n - 1];
+        res.consume(n);
+        b.work()?;
+        let (res, _) = o.read_buf()?;
+        let step = 2.0 * std::f64::consts::PI * 7.0 / 1000.0;
+        let want = ((n as f64) * step).sin() as Float;
+        assert_almost_equal_float(&[res.slice()[0]], &[want]);
+        assert!((res.slice()[0] - last).abs() < 0.05);
+        Ok(())
+    }
+}
@@ -86,23 +86,37 @@ mod tests {
     use super::*;
     use crate::block::Block;
     use crate::stream::streamp_from_slice;
+    use crate::tests::{assert_close, Tolerance};
+    use crate::vector_sink::VectorSink;
     use crate::{Complex, Error};
 
     #[test]
     fn iir_ff() -> Result<()> {
-        // TODO: create an actual test.
-        let src = streamp_from_slice(&[0.1, 0.2]);
+        let src = streamp_from_slice(&[0.1, 0.2, 0.0]);
         let mut iir = SinglePoleIIRFilter::new(src, 0.2).ok_or(Error::new("alpha out of range"))?;
+        let mut sink = VectorSink::new(iir.out());
         iir.work()?;
+        sink.work()?;
+        assert_close(
+            &sink.handle().data(),
+            &[0.02, 0.056, 0.0448],
+            Tolerance::abs(1e-6),
+        );
         Ok(())
     }
 
     #[test]
     fn iir_cc() -> Result<()> {
-        // TODO: create an actual test.
         let src = streamp_from_slice(&[Complex::new(1.0, 0.1), Complex::default()]);
         let mut iir = SinglePoleIIRFilter::new(src, 0.2).ok_or(Error::new("alpha out of range"))?;
+        let mut sink = VectorSink::new(iir.out());
         iir.work()?;
+        sink.work()?;
+        assert_close(
+            &sink.handle().data(),
+            &[Complex::new(0.2, 0.02), Complex::new(0.16, 0.016)],
+            Tolerance::abs(1e-6),
+        );
         Ok(())
     }
 
@@ -8,7 +8,7 @@ use crate::{Complex, Error};
 
 impl From<soapysdr::Error> for Error {
     fn from(e: soapysdr::Error) -> Self {
-        Error::new(&format!("Soapy SDR Error: {}", e))
+        Error::hardware(&format!("Soapy SDR: {e}"), e)
     }
 }
 
@@ -110,6 +110,11 @@ fn ai_string(ai: &soapysdr::ArgInfo) -> String {
 }
 
 impl SoapySdrSource {
+    /// Create a builder for SoapySdrSource.
+    pub fn builder(dev: String, freq: f64, samp_rate: f64) -> SoapySdrSourceBuilder {
+        SoapySdrSourceBuilder::new(dev, freq, samp_rate)
+    }
+
     /// Get output stream.
     pub fn out(&self) -> Streamp<Complex> {
         self.dst.clone()
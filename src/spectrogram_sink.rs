@@ -0,0 +1,437 @@
+/*! Write spectrograms as PNG images.
+
+For unattended band monitoring, [SpectrogramSink] writes a spectrogram
+image for every period of time, default one minute. Images can also be
+written on demand, with [SpectrogramHandle::trigger], or when a tag
+arrives in the stream, e.g. to attach a picture of the signal to a
+decoded event.
+
+Time goes downwards, and frequency increases to the right. The PNG
+files are named by the time of their first row, in microseconds since
+the epoch, like [SstvPngWriter][crate::sstv::SstvPngWriter], and have
+text chunks with the sample rate, FFT size and start time.
+
+The spectrum calculation is the same as the
+[WaterfallSink][crate::waterfall_sink::WaterfallSink], with rows
+averaged over [SpectrogramSink::set_row_rate].
+
+```text
+Source -> SpectrogramSink
+```
+*/
+use std::collections::VecDeque;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use log::info;
+
+use crate::block::{Block, BlockRet};
+use crate::spectrum_sink::SpectrumInput;
+use crate::stream::Streamp;
+use crate::waterfall_sink::{colormap, Row, WaterfallHandle, WaterfallSink};
+use crate::{Error, Float};
+
+const DEFAULT_PERIOD: Duration = Duration::from_secs(60);
+const DEFAULT_ROW_RATE: Float = 10.0;
+
+// How far above the noise floor the top of an auto range is, at least.
+const AUTO_MIN_SPAN: Float = 30.0;
+
+/// A spectrogram image.
+#[derive(Debug, Clone)]
+pub struct Spectrogram {
+    /// Width in pixels, one per FFT bin.
+    pub width: usize,
+
+    /// Height in pixels, one per row.
+    pub height: usize,
+
+    /// RGB pixel data, 3 bytes per pixel, row by row.
+    pub pixels: Vec<u8>,
+
+    /// Time of the first row.
+    pub start: SystemTime,
+
+    /// Range of the colormap, in dB.
+    pub db_range: (Float, Float),
+}
+
+impl Spectrogram {
+    /// Render rows. If `db_range` is None, it's picked from the data:
+    /// from the noise floor up to the strongest signal.
+    pub fn new(rows: &[Row], db_range: Option<(Float, Float)>) -> Option<Self> {
+        let first = rows.first()?;
+        let width = first.db.len();
+        let rows: Vec<&Row> = rows.iter().filter(|r| r.db.len() == width).collect();
+        let (min, max) = db_range.unwrap_or_else(|| auto_range(&rows));
+        let mut pixels = Vec::with_capacity(width * rows.len() * 3);
+        for row in &rows {
+            for db in &row.db {
+                pixels.extend(colormap((db - min) / (max - min)));
+            }
+        }
+        Some(Self {
+            width,
+            height: rows.len(),
+            pixels,
+            start: first.time,
+            db_range: (min, max),
+        })
+    }
+
+    /// Write image as PNG, with the given text chunks.
+    pub fn write_png(&self, path: &Path, text: &[(&str, String)]) -> Result<()> {
+        let f = std::fs::File::create(path)?;
+        let mut enc = png::Encoder::new(BufWriter::new(f), self.width as u32, self.height as u32);
+        enc.set_color(png::ColorType::Rgb);
+        enc.set_depth(png::BitDepth::Eight);
+        for (k, v) in text {
+            enc.add_text_chunk(k.to_string(), v.clone())?;
+        }
+        let mut w = enc.write_header()?;
+        w.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+}
+
+// Use the 10th percentile as the noise floor.
+fn auto_range(rows: &[&Row]) -> (Float, Float) {
+    let mut all: Vec<Float> = rows
+        .iter()
+        .flat_map(|r| r.db.iter().cloned())
+        .filter(|v| v.is_finite())
+        .collect();
+    if all.is_empty() {
+        return (-120.0, 0.0);
+    }
+    all.sort_by(|a, b| a.total_cmp(b));
+    let min = all[all.len() / 10];
+    let max = all[all.len() - 1].max(min + AUTO_MIN_SPAN);
+    (min, max)
+}
+
+/// Handle for triggering images from another thread.
+#[derive(Clone)]
+pub struct SpectrogramHandle {
+    trigger: Arc<AtomicBool>,
+}
+
+impl SpectrogramHandle {
+    /// Write an image of the trigger history as soon as possible.
+    pub fn trigger(&self) {
+        self.trigger.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Spectrogram PNG sink block.
+pub struct SpectrogramSink<T> {
+    src: Streamp<T>,
+    waterfall: WaterfallSink<T>,
+    wh: WaterfallHandle,
+    next: u64,
+    rows: VecDeque<Row>,
+    samp_rate: Float,
+    dir: PathBuf,
+    period: Option<Duration>,
+    period_start: Option<SystemTime>,
+    trigger_tag: Option<String>,
+    history: Duration,
+    db_range: Option<(Float, Float)>,
+    trigger: Arc<AtomicBool>,
+    written: usize,
+}
+
+impl<T: SpectrumInput> SpectrogramSink<T> {
+    /// Create new SpectrogramSink, writing to directory `dir`.
+    pub fn new(src: Streamp<T>, samp_rate: Float, fft_size: usize, dir: PathBuf) -> Self {
+        let waterfall = WaterfallSink::new(src.clone(), samp_rate, fft_size);
+        let wh = waterfall.handle();
+        wh.set_row_rate(DEFAULT_ROW_RATE);
+        Self {
+            src,
+            waterfall,
+            wh,
+            next: 0,
+            rows: VecDeque::new(),
+            samp_rate,
+            dir,
+            period: Some(DEFAULT_PERIOD),
+            period_start: None,
+            trigger_tag: None,
+            history: DEFAULT_PERIOD,
+            db_range: None,
+            trigger: Arc::new(AtomicBool::new(false)),
+            written: 0,
+        }
+    }
+
+    /// Set the number of rows per second. Default 10.
+    pub fn set_row_rate(&mut self, rate: Float) {
+        self.wh.set_row_rate(rate);
+    }
+
+    /// Set how much time each periodic image covers, or None to only
+    /// write images when triggered. Default one minute.
+    pub fn set_period(&mut self, period: Option<Duration>) {
+        self.period = period;
+    }
+
+    /// Write an image when a tag with this key arrives.
+    pub fn set_trigger_tag(&mut self, key: &str) {
+        self.trigger_tag = Some(key.to_string());
+    }
+
+    /// Set how much time triggered images go back. Default one minute.
+    pub fn set_trigger_history(&mut self, history: Duration) {
+        self.history = history;
+    }
+
+    /// Set a fixed colormap range, in dB. Default None, picking it per
+    /// image.
+    pub fn set_db_range(&mut self, range: Option<(Float, Float)>) {
+        self.db_range = range;
+    }
+
+    /// Set the time of the first sample. Default when the block was
+    /// created.
+    pub fn set_start_time(&mut self, t: SystemTime) {
+        self.waterfall.set_start_time(t);
+    }
+
+    /// Return a handle for triggering images.
+    pub fn handle(&self) -> SpectrogramHandle {
+        SpectrogramHandle {
+            trigger: self.trigger.clone(),
+        }
+    }
+
+    fn write(&mut self, rows: &[Row]) -> Result<()> {
+        let Some(img) = Spectrogram::new(rows, self.db_range) else {
+            return Ok(());
+        };
+        let start = img
+            .start
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let full = self.dir.join(format!("{}.png", start.as_micros()));
+        info!("SpectrogramSink: writing {} rows to {:?}", img.height, full);
+        img.write_png(
+            &full,
+            &[
+                (
+                    "Software",
+                    format!("rustradio {}", env!("CARGO_PKG_VERSION")),
+                ),
+                ("SampleRate", self.samp_rate.to_string()),
+                ("FftSize", self.wh.fft_size().to_string()),
+                ("StartTime", format!("{:.6}", start.as_secs_f64())),
+                ("DbRange", format!("{} {}", img.db_range.0, img.db_range.1)),
+            ],
+        )?;
+        self.written += 1;
+        Ok(())
+    }
+
+    // Write periodic images that are complete.
+    fn periodic(&mut self) -> Result<()> {
+        let Some(period) = self.period else {
+            return Ok(());
+        };
+        while let Some(start) = self.period_start {
+            let end = start + period;
+            if self.rows.back().map(|r| r.time < end).unwrap_or(true) {
+                break;
+            }
+            let rows: Vec<Row> = self
+                .rows
+                .iter()
+                .filter(|r| r.time >= start && r.time < end)
+                .cloned()
+                .collect();
+            self.write(&rows)?;
+            self.period_start = Some(end);
+        }
+        Ok(())
+    }
+
+    fn triggered(&mut self) -> Result<()> {
+        let Some(last) = self.rows.back() else {
+            return Ok(());
+        };
+        let from = last.time.checked_sub(self.history).unwrap_or(last.time);
+        let rows: Vec<Row> = self
+            .rows
+            .iter()
+            .filter(|r| r.time >= from)
+            .cloned()
+            .collect();
+        self.write(&rows)
+    }
+
+    // Drop rows that no image will need anymore.
+    fn trim(&mut self) {
+        let Some(last) = self.rows.back().map(|r| r.time) else {
+            return;
+        };
+        let keep_from = last.checked_sub(self.history).unwrap_or(last);
+        let keep_from = match self.period_start {
+            Some(p) if self.period.is_some() => keep_from.min(p),
+            _ => keep_from,
+        };
+        while self
+            .rows
+            .front()
+            .map(|r| r.time < keep_from)
+            .unwrap_or(false)
+        {
+            self.rows.pop_front();
+        }
+    }
+}
+
+impl<T> Drop for SpectrogramSink<T> {
+    fn drop(&mut self) {
+        info!("SpectrogramSink: wrote {} images", self.written);
+    }
+}
+
+impl<T: SpectrumInput> Block for SpectrogramSink<T> {
+    fn block_name(&self) -> &str {
+        "SpectrogramSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        if let Some(key) = &self.trigger_tag {
+            let ibind = self.src.clone();
+            let (_input, tags) = ibind.read_buf()?;
+            if tags.iter().any(|t| t.key() == key) {
+                self.trigger.store(true, Ordering::Relaxed);
+            }
+        }
+        let ret = self.waterfall.work()?;
+
+        let (rows, next) = self.wh.rows_since(self.next);
+        self.next = next;
+        if self.period_start.is_none() {
+            self.period_start = rows.first().map(|r| r.time);
+        }
+        self.rows.extend(rows);
+        self.periodic()?;
+        if !self.rows.is_empty() && self.trigger.swap(false, Ordering::Relaxed) {
+            self.triggered()?;
+        }
+        self.trim();
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::SignalSourceComplex;
+    use crate::stream::{Tag, TagValue};
+    use crate::Complex;
+
+    fn pngs(dir: &Path) -> Vec<PathBuf> {
+        let mut v: Vec<PathBuf> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        v.sort();
+        v
+    }
+
+    #[test]
+    fn periodic() -> Result<()> {
+        let tmpd = tempfile::tempdir()?;
+        let samp_rate = 1024.0;
+        let mut src = SignalSourceComplex::new(samp_rate, 256.0, 1.0);
+        let mut sink =
+            SpectrogramSink::<Complex>::new(src.out(), samp_rate, 64, tmpd.path().to_path_buf());
+        sink.set_start_time(SystemTime::UNIX_EPOCH + Duration::from_secs(1000));
+        sink.set_row_rate(8.0);
+        sink.set_period(Some(Duration::from_secs(1)));
+        while sink.written < 2 {
+            src.work()?;
+            sink.work()?;
+        }
+        let files = pngs(tmpd.path());
+        assert_eq!(
+            files[0].file_name().unwrap().to_str().unwrap(),
+            "1000000000.png"
+        );
+        let dec = png::Decoder::new(std::fs::File::open(&files[1])?);
+        let reader = dec.read_info()?;
+        let info = reader.info();
+        assert_eq!((info.width, info.height), (64, 8));
+        let text: Vec<_> = info
+            .uncompressed_latin1_text
+            .iter()
+            .map(|t| (t.keyword.as_str(), t.text.as_str()))
+            .collect();
+        assert!(text.contains(&("FftSize", "64")), "{text:?}");
+        assert!(text.contains(&("StartTime", "1001.000000")), "{text:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn triggered() -> Result<()> {
+        let tmpd = tempfile::tempdir()?;
+        let samp_rate = 1024.0;
+        let src = crate::stream::new_streamp::<Complex>();
+        let mut sink = SpectrogramSink::new(src.clone(), samp_rate, 64, tmpd.path().to_path_buf());
+        sink.set_period(None);
+        sink.set_row_rate(8.0);
+        sink.set_trigger_tag("burst");
+        sink.set_trigger_history(Duration::from_millis(500));
+        sink.set_db_range(Some((-100.0, 0.0)));
+
+        let push = |tag: bool| -> Result<()> {
+            let mut o = src.write_buf()?;
+            o.slice()[..128].fill(Complex::new(1.0, 0.0));
+            let tags = if tag {
+                vec![Tag::new(0, "burst".into(), TagValue::Bool(true))]
+            } else {
+                vec![]
+            };
+            o.produce(128, &tags);
+            Ok(())
+        };
+        for _ in 0..10 {
+            push(false)?;
+            sink.work()?;
+        }
+        assert!(pngs(tmpd.path()).is_empty());
+        assert_eq!(sink.rows.len(), 5);
+
+        push(true)?;
+        sink.work()?;
+        let files = pngs(tmpd.path());
+        assert_eq!(files.len(), 1);
+        let dec = png::Decoder::new(std::fs::File::open(&files[0])?);
+        let reader = dec.read_info()?;
+        assert_eq!(reader.info().height, 5);
+
+        sink.handle().trigger();
+        push(false)?;
+        sink.work()?;
+        assert_eq!(pngs(tmpd.path()).len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn auto() {
+        let rows: Vec<Row> = (0..10)
+            .map(|n| Row {
+                time: SystemTime::UNIX_EPOCH,
+                db: vec![-100.0 + n as Float, -10.0],
+            })
+            .collect();
+        let img = Spectrogram::new(&rows, None).unwrap();
+        assert_eq!(img.db_range, (-98.0, -10.0));
+        assert_eq!(&img.pixels[3..6], &colormap(1.0));
+    }
+}
@@ -0,0 +1,523 @@
+/*! Live spectrum display.
+
+[SpectrumSink] calculates the averaged power spectrum of a stream, and
+makes the latest one available through a [SpectrumHandle]. With the
+`egui` feature enabled, [run_window] opens a window showing it live,
+with averaging, peak hold, and click-to-read frequency and level.
+
+The window needs to run on the main thread, so the graph has to run in
+another thread. [MTGraph][crate::mtgraph::MTGraph] can be moved there:
+
+```no_run
+use rustradio::Complex;
+use rustradio::mtgraph::MTGraph;
+use rustradio::blocks::{FileSource, SpectrumSink};
+
+let samp_rate = 1_024_000.0;
+let mut g = MTGraph::new();
+let src = Box::new(FileSource::<Complex>::new("raw.c32", false)?);
+let sink = Box::new(SpectrumSink::new(src.out(), samp_rate, 1024));
+let handle = sink.handle();
+g.add(src);
+g.add(sink);
+std::thread::spawn(move || g.run());
+# #[cfg(feature = "egui")]
+rustradio::spectrum_sink::run_window(handle, "Spectrum")?;
+# Ok::<(), anyhow::Error>(())
+```
+
+Levels are in dBFS, where a complex full scale tone is 0dB. For real
+input only the positive frequencies are shown.
+*/
+use std::sync::{Arc, Mutex};
+
+use log::debug;
+use rustfft::{Fft, FftPlanner};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::Streamp;
+pub use crate::SpectrumInput;
+use crate::{Complex, Error, Float};
+
+/// A calculated spectrum.
+#[derive(Debug, Clone, Default)]
+pub struct Spectrum {
+    /// Averaged power per bin, in dB, lowest frequency first.
+    pub avg: Vec<Float>,
+
+    /// Peak power per bin, in dB. Empty if peak hold is off.
+    pub peak: Vec<Float>,
+
+    /// Sample rate.
+    pub samp_rate: Float,
+
+    /// True if only positive frequencies are included.
+    pub real: bool,
+
+    /// Number of FFTs calculated so far.
+    pub count: u64,
+}
+
+impl Spectrum {
+    /// Frequency, relative to the center, of a bin.
+    pub fn bin_freq(&self, bin: usize) -> Float {
+        let n = self.avg.len() as Float;
+        if self.real {
+            // Bins 0..=N/2.
+            bin as Float * self.samp_rate / 2.0 / (n - 1.0)
+        } else {
+            (bin as Float - n / 2.0) * self.samp_rate / n
+        }
+    }
+
+    /// Bin closest to a frequency, if within the spectrum.
+    pub fn freq_bin(&self, freq: Float) -> Option<usize> {
+        let n = self.avg.len() as Float;
+        let bin = if self.real {
+            freq * (n - 1.0) * 2.0 / self.samp_rate
+        } else {
+            freq * n / self.samp_rate + n / 2.0
+        }
+        .round();
+        (bin >= 0.0 && bin < n).then_some(bin as usize)
+    }
+
+    /// Lowest frequency shown.
+    pub fn min_freq(&self) -> Float {
+        if self.real {
+            0.0
+        } else {
+            -self.samp_rate / 2.0
+        }
+    }
+
+    /// Highest frequency shown.
+    pub fn max_freq(&self) -> Float {
+        self.samp_rate / 2.0
+    }
+}
+
+struct Shared {
+    spectrum: Spectrum,
+    alpha: Float,
+    peak_hold: bool,
+    reset_peak: bool,
+}
+
+/// Handle for reading the spectrum, and changing settings, from
+/// another thread.
+#[derive(Clone)]
+pub struct SpectrumHandle {
+    h: Arc<Mutex<Shared>>,
+}
+
+impl SpectrumHandle {
+    /// Get the latest spectrum.
+    pub fn spectrum(&self) -> Spectrum {
+        self.h.lock().unwrap().spectrum.clone()
+    }
+
+    /// Number of FFTs calculated so far. Cheaper than getting the
+    /// whole spectrum to see if it's changed.
+    pub fn count(&self) -> u64 {
+        self.h.lock().unwrap().spectrum.count
+    }
+
+    /// Get the averaging factor.
+    pub fn averaging(&self) -> Float {
+        self.h.lock().unwrap().alpha
+    }
+
+    /// Set averaging factor, between 0 and 1. Each new FFT gets this
+    /// weight, so 1.0 means no averaging.
+    pub fn set_averaging(&self, alpha: Float) {
+        self.h.lock().unwrap().alpha = alpha.clamp(0.001, 1.0);
+    }
+
+    /// Return true if peak hold is on.
+    pub fn peak_hold(&self) -> bool {
+        self.h.lock().unwrap().peak_hold
+    }
+
+    /// Turn peak hold on or off. Turning it off also resets the peaks.
+    pub fn set_peak_hold(&self, on: bool) {
+        let mut h = self.h.lock().unwrap();
+        h.peak_hold = on;
+        h.reset_peak = true;
+    }
+
+    /// Reset peak hold.
+    pub fn reset_peak(&self) {
+        self.h.lock().unwrap().reset_peak = true;
+    }
+}
+
+/// Windowed FFT, returning power per bin.
+pub(crate) struct PowerFft {
+    fft: Arc<dyn Fft<Float>>,
+    window: Vec<Float>,
+    scale: Float,
+    buf: Vec<Complex>,
+}
+
+impl PowerFft {
+    pub(crate) fn new(size: usize) -> Self {
+        let size = size.max(2);
+        // Hann window.
+        let window: Vec<Float> = (0..size)
+            .map(|n| {
+                let x = std::f64::consts::PI as Float * n as Float / size as Float;
+                x.sin().powi(2)
+            })
+            .collect();
+        let sum: Float = window.iter().sum();
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(size),
+            scale: 1.0 / (sum * sum),
+            window,
+            buf: vec![Complex::default(); size],
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.buf.len()
+    }
+
+    // Return power per bin, lowest frequency first.
+    pub(crate) fn power<T: SpectrumInput>(&mut self, input: &[T]) -> Vec<Float> {
+        for ((b, x), w) in self.buf.iter_mut().zip(input).zip(&self.window) {
+            *b = x.to_complex() * w;
+        }
+        self.fft.process(&mut self.buf);
+        let n = self.buf.len();
+        let p = |c: &Complex| c.norm_sqr() * self.scale;
+        if T::REAL {
+            // Fold the negative frequencies into the positive.
+            (0..=n / 2)
+                .map(|i| {
+                    if i == 0 || 2 * i == n {
+                        p(&self.buf[i])
+                    } else {
+                        p(&self.buf[i]) + p(&self.buf[n - i])
+                    }
+                })
+                .collect()
+        } else {
+            let (lo, hi) = self.buf.split_at(n - n / 2);
+            hi.iter().chain(lo).map(p).collect()
+        }
+    }
+}
+
+/// Format a frequency for axis labels and markers.
+pub fn format_freq(f: Float) -> String {
+    if f.abs() >= 1e6 {
+        format!("{:.3} MHz", f / 1e6)
+    } else if f.abs() >= 1e3 {
+        format!("{:.2} kHz", f / 1e3)
+    } else {
+        format!("{f:.0} Hz")
+    }
+}
+
+/// Convert power to dB.
+pub(crate) fn to_db(v: &[Float]) -> Vec<Float> {
+    v.iter().map(|p| 10.0 * (p + 1e-20).log10()).collect()
+}
+
+/// Spectrum sink block.
+pub struct SpectrumSink<T> {
+    src: Streamp<T>,
+    fft: PowerFft,
+    avg: Vec<Float>,
+    peak: Vec<Float>,
+    handle: SpectrumHandle,
+}
+
+impl<T: SpectrumInput> SpectrumSink<T> {
+    /// Create new SpectrumSink block.
+    pub fn new(src: Streamp<T>, samp_rate: Float, fft_size: usize) -> Self {
+        Self {
+            src,
+            fft: PowerFft::new(fft_size),
+            avg: Vec::new(),
+            peak: Vec::new(),
+            handle: SpectrumHandle {
+                h: Arc::new(Mutex::new(Shared {
+                    spectrum: Spectrum {
+                        samp_rate,
+                        real: T::REAL,
+                        ..Default::default()
+                    },
+                    alpha: 0.1,
+                    peak_hold: true,
+                    reset_peak: false,
+                })),
+            },
+        }
+    }
+
+    /// Return a handle for reading the spectrum.
+    pub fn handle(&self) -> SpectrumHandle {
+        self.handle.clone()
+    }
+
+    fn update(&mut self, power: Vec<Float>) {
+        let mut h = self.handle.h.lock().unwrap();
+        if self.avg.is_empty() {
+            self.avg = power.clone();
+        } else {
+            for (a, p) in self.avg.iter_mut().zip(&power) {
+                *a += h.alpha * (p - *a);
+            }
+        }
+        if std::mem::take(&mut h.reset_peak) || !h.peak_hold {
+            self.peak.clear();
+        }
+        if h.peak_hold {
+            if self.peak.is_empty() {
+                self.peak = power;
+            } else {
+                for (pk, p) in self.peak.iter_mut().zip(&power) {
+                    *pk = pk.max(*p);
+                }
+            }
+        }
+        h.spectrum.avg = to_db(&self.avg);
+        h.spectrum.peak = to_db(&self.peak);
+        h.spectrum.count += 1;
+    }
+}
+
+impl<T: SpectrumInput> Block for SpectrumSink<T> {
+    fn block_name(&self) -> &str {
+        "SpectrumSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let n = self.fft.size();
+        let ibind = self.src.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.len() < n {
+            return Ok(BlockRet::Noop);
+        }
+        let chunks = input.len() / n;
+        for chunk in input.slice()[..chunks * n].chunks_exact(n) {
+            let power = self.fft.power(chunk);
+            self.update(power);
+        }
+        debug!("SpectrumSink: processed {} samples", chunks * n);
+        input.consume(chunks * n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(feature = "egui")]
+mod view {
+    use super::*;
+    use crate::gui::{freq_grid, View, GRID_COLOR};
+    use eframe::egui;
+    use egui::{Color32, Pos2, Sense, Stroke};
+
+    const AVG_COLOR: Color32 = Color32::from_rgb(80, 200, 80);
+    const PEAK_COLOR: Color32 = Color32::from_rgb(220, 180, 40);
+
+    /// Spectrum display, for [crate::gui::run].
+    pub struct SpectrumView {
+        handle: SpectrumHandle,
+        min_db: Float,
+        max_db: Float,
+        marker: Option<Float>,
+    }
+
+    impl SpectrumView {
+        /// Create new spectrum view.
+        pub fn new(handle: SpectrumHandle) -> Self {
+            Self {
+                handle,
+                min_db: -120.0,
+                max_db: 0.0,
+                marker: None,
+            }
+        }
+
+        fn plot(&mut self, ui: &mut egui::Ui, s: &Spectrum) {
+            let (resp, painter) = ui.allocate_painter(ui.available_size(), Sense::click());
+            let rect = resp.rect;
+            let span = s.max_freq() - s.min_freq();
+            let x_of = |f: Float| rect.left() + rect.width() * ((f - s.min_freq()) / span) as f32;
+            let f_of = |x: f32| s.min_freq() + ((x - rect.left()) / rect.width()) as Float * span;
+            let y_of = |db: Float| {
+                let v = (db - self.min_db) / (self.max_db - self.min_db);
+                rect.bottom() - rect.height() * v.clamp(0.0, 1.0) as f32
+            };
+            painter.rect_filled(rect, 0.0, Color32::BLACK);
+
+            // Grid.
+            let mut db = (self.min_db / 10.0).ceil() * 10.0;
+            while db <= self.max_db {
+                let y = y_of(db);
+                painter.hline(rect.x_range(), y, Stroke::new(1.0, GRID_COLOR));
+                painter.text(
+                    Pos2::new(rect.left() + 2.0, y),
+                    egui::Align2::LEFT_BOTTOM,
+                    format!("{db} dB"),
+                    egui::FontId::monospace(10.0),
+                    Color32::GRAY,
+                );
+                db += 10.0;
+            }
+            freq_grid(&painter, rect, s.min_freq(), s.max_freq());
+
+            // Traces.
+            for (v, color) in [(&s.peak, PEAK_COLOR), (&s.avg, AVG_COLOR)] {
+                let points: Vec<Pos2> = v
+                    .iter()
+                    .enumerate()
+                    .map(|(bin, db)| Pos2::new(x_of(s.bin_freq(bin)), y_of(*db)))
+                    .collect();
+                painter.add(egui::Shape::line(points, Stroke::new(1.0, color)));
+            }
+
+            // Marker.
+            if let Some(pos) = resp.interact_pointer_pos() {
+                self.marker = Some(f_of(pos.x));
+            }
+            if let Some(bin) = self.marker.and_then(|f| s.freq_bin(f)) {
+                let f = s.bin_freq(bin);
+                let x = x_of(f);
+                painter.vline(x, rect.y_range(), Stroke::new(1.0, Color32::WHITE));
+                let mut text = format!("{}\n{:.1} dB", format_freq(f), s.avg[bin]);
+                if let Some(pk) = s.peak.get(bin) {
+                    text += &format!("\npeak {pk:.1} dB");
+                }
+                painter.text(
+                    Pos2::new(x + 4.0, rect.top() + 4.0),
+                    egui::Align2::LEFT_TOP,
+                    text,
+                    egui::FontId::monospace(12.0),
+                    Color32::WHITE,
+                );
+            }
+        }
+    }
+
+    impl View for SpectrumView {
+        fn name(&self) -> &str {
+            "Spectrum"
+        }
+
+        fn controls(&mut self, ui: &mut egui::Ui) {
+            let mut peak = self.handle.peak_hold();
+            if ui.checkbox(&mut peak, "Peak hold").changed() {
+                self.handle.set_peak_hold(peak);
+            }
+            if ui.button("Reset peak").clicked() {
+                self.handle.reset_peak();
+            }
+            let mut alpha = self.handle.averaging();
+            if ui
+                .add(
+                    egui::Slider::new(&mut alpha, 0.001..=1.0)
+                        .logarithmic(true)
+                        .text("Averaging"),
+                )
+                .changed()
+            {
+                self.handle.set_averaging(alpha);
+            }
+            ui.add(egui::Slider::new(&mut self.max_db, -100.0..=20.0).text("Max dB"));
+            ui.add(egui::Slider::new(&mut self.min_db, -200.0..=0.0).text("Min dB"));
+            if self.min_db >= self.max_db {
+                self.min_db = self.max_db - 10.0;
+            }
+        }
+
+        fn show(&mut self, ui: &mut egui::Ui) {
+            let s = self.handle.spectrum();
+            if s.avg.is_empty() {
+                ui.label("Waiting for data…");
+            } else {
+                self.plot(ui, &s);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "egui")]
+pub use view::SpectrumView;
+
+/// Open a window showing the spectrum, and run until it's closed.
+///
+/// Must be called from the main thread.
+#[cfg(feature = "egui")]
+pub fn run_window(handle: SpectrumHandle, title: &str) -> anyhow::Result<()> {
+    crate::gui::run(title, vec![Box::new(SpectrumView::new(handle))])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{SignalSourceComplex, VectorSource};
+    use crate::vector_source::Repeat;
+
+    fn peak_bin(v: &[Float]) -> usize {
+        v.iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn complex_tone() -> anyhow::Result<()> {
+        let samp_rate = 1024.0;
+        let mut src = SignalSourceComplex::new(samp_rate, -128.0, 1.0);
+        let mut sink = SpectrumSink::new(src.out(), samp_rate, 256);
+        let h = sink.handle();
+        src.work()?;
+        sink.work()?;
+        let s = h.spectrum();
+        assert_eq!(s.avg.len(), 256);
+        assert_eq!(s.min_freq(), -512.0);
+        let bin = peak_bin(&s.avg);
+        assert_eq!(s.bin_freq(bin), -128.0);
+        assert_eq!(s.freq_bin(-128.0), Some(bin));
+        assert!(s.avg[bin].abs() < 0.1, "{}", s.avg[bin]);
+        assert!(s.avg[bin + 20] < -60.0, "{}", s.avg[bin + 20]);
+        assert_eq!(s.freq_bin(600.0), None);
+        Ok(())
+    }
+
+    #[test]
+    fn real_peak_hold() -> anyhow::Result<()> {
+        let samp_rate = 1000.0;
+        let tone = |freq: Float| -> Vec<Float> {
+            (0..100)
+                .map(|n| {
+                    (2.0 * std::f64::consts::PI as Float * freq * n as Float / samp_rate).cos()
+                })
+                .collect()
+        };
+        let mut v = tone(100.0);
+        v.extend(tone(300.0));
+        let mut src = VectorSource::new(v);
+        src.set_repeat(Repeat::Finite(2));
+        let mut sink = SpectrumSink::new(src.out(), samp_rate, 100);
+        let h = sink.handle();
+        h.set_averaging(1.0);
+        src.work()?;
+        sink.work()?;
+        let s = h.spectrum();
+        assert_eq!(s.count, 2);
+        assert_eq!(s.avg.len(), 51);
+        assert_eq!(s.bin_freq(peak_bin(&s.avg)), 300.0);
+        assert!(s.peak[10] > -7.0 && s.peak[30] > -7.0, "{:?}", s.peak);
+        assert!(s.avg[10] < -60.0);
+
+        h.set_peak_hold(false);
+        src.work()?;
+        sink.work()?;
+        assert!(h.spectrum().peak.is_empty());
+        Ok(())
+    }
+}
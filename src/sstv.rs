@@ -0,0 +1,738 @@
+/*! Slow-scan television (SSTV) decoder.
+
+[SSTV][sstv] sends still images as audio, where the frequency encodes
+the brightness of the pixel being sent: 1500Hz is black, and 2300Hz is
+white. Sync pulses are 1200Hz.
+
+Each transmission starts with a VIS code, identifying the mode:
+
+* 300ms leader tone at 1900Hz.
+* 10ms break at 1200Hz.
+* 300ms leader tone at 1900Hz.
+* 30ms start bit at 1200Hz.
+* 7 data bits, LSB first, each 30ms. 1100Hz is 1, 1300Hz is 0.
+* 1 even parity bit.
+* 30ms stop bit at 1200Hz.
+
+Supported modes are Robot 36/72, Martin 1/2, and Scottie 1/2/DX.
+
+Input to the decoder block is the instantaneous frequency in Hz. E.g.
+from a `Hilbert` followed by a `QuadratureDemod` with gain
+`samp_rate/(2π)`. Each line is aligned on its sync pulse, so small
+sample rate errors don't slant the image.
+
+```text
+  [ audio ] → [ Hilbert ] → [ QuadratureDemod ] → [ FftFilterFloat ]
+    → [ SstvDecode ] → [ SstvPngWriter ]
+```
+
+See `examples/sstv-rx.rs`.
+
+[sstv]: https://en.wikipedia.org/wiki/Slow-scan_television
+*/
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Result;
+use log::{debug, info, trace};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp, Streamp};
+use crate::{Error, Float};
+
+const SYNC_FREQ: Float = 1200.0;
+const BLACK_FREQ: Float = 1500.0;
+const WHITE_FREQ: Float = 2300.0;
+const LEADER_FREQ: Float = 1900.0;
+const VIS_BIT_MS: f64 = 30.0;
+
+// Give up on an image after this many lines without a sync pulse.
+const MAX_MISSED_SYNCS: usize = 20;
+
+/// SSTV mode.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+    /// Robot 36, 320x240 YCrCb, 36 seconds.
+    Robot36,
+
+    /// Robot 72, 320x240 YCrCb, 72 seconds.
+    Robot72,
+
+    /// Martin 1, 320x256 GBR, 114 seconds.
+    Martin1,
+
+    /// Martin 2, 320x256 GBR, 58 seconds.
+    Martin2,
+
+    /// Scottie 1, 320x256 GBR, 110 seconds.
+    Scottie1,
+
+    /// Scottie 2, 320x256 GBR, 71 seconds.
+    Scottie2,
+
+    /// Scottie DX, 320x256 GBR, 269 seconds.
+    ScottieDX,
+}
+
+/// What a scan in a line contains.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Channel {
+    Red,
+    Green,
+    Blue,
+    Luma,
+    RedDiff,
+    BlueDiff,
+    // Robot 36 alternates R-Y on even lines, and B-Y on odd lines.
+    AltDiff,
+}
+
+impl Mode {
+    /// Look up mode from VIS code.
+    pub fn from_vis(code: u8) -> Option<Mode> {
+        Some(match code {
+            8 => Mode::Robot36,
+            12 => Mode::Robot72,
+            44 => Mode::Martin1,
+            40 => Mode::Martin2,
+            60 => Mode::Scottie1,
+            56 => Mode::Scottie2,
+            76 => Mode::ScottieDX,
+            _ => return None,
+        })
+    }
+
+    /// VIS code for mode.
+    pub fn vis(&self) -> u8 {
+        match self {
+            Mode::Robot36 => 8,
+            Mode::Robot72 => 12,
+            Mode::Martin1 => 44,
+            Mode::Martin2 => 40,
+            Mode::Scottie1 => 60,
+            Mode::Scottie2 => 56,
+            Mode::ScottieDX => 76,
+        }
+    }
+
+    /// Image width in pixels.
+    pub fn width(&self) -> usize {
+        320
+    }
+
+    /// Image height in pixels.
+    pub fn height(&self) -> usize {
+        match self {
+            Mode::Robot36 | Mode::Robot72 => 240,
+            _ => 256,
+        }
+    }
+
+    // Length of one color scan, in ms.
+    fn scan_ms(&self) -> f64 {
+        match self {
+            Mode::Robot36 => 88.0,
+            Mode::Robot72 => 138.0,
+            Mode::Martin1 => 146.432,
+            Mode::Martin2 => 73.216,
+            Mode::Scottie1 => 138.24,
+            Mode::Scottie2 => 88.064,
+            Mode::ScottieDX => 345.6,
+        }
+    }
+
+    // Sync pulse length in ms.
+    fn sync_ms(&self) -> f64 {
+        match self {
+            Mode::Martin1 | Mode::Martin2 => 4.862,
+            _ => 9.0,
+        }
+    }
+
+    // Offset of sync pulse from start of line, in ms.
+    fn sync_offset_ms(&self) -> f64 {
+        match self {
+            Mode::Scottie1 | Mode::Scottie2 | Mode::ScottieDX => 3.0 + 2.0 * self.scan_ms(),
+            _ => 0.0,
+        }
+    }
+
+    // Silence (well, sync) before the first line, in ms.
+    fn start_ms(&self) -> f64 {
+        match self {
+            // Scottie starts with a sync pulse, since the sync is
+            // otherwise in the middle of the line.
+            Mode::Scottie1 | Mode::Scottie2 | Mode::ScottieDX => 9.0,
+            _ => 0.0,
+        }
+    }
+
+    // Line length in ms.
+    fn line_ms(&self) -> f64 {
+        let s = self.scan_ms();
+        match self {
+            Mode::Robot36 => 150.0,
+            Mode::Robot72 => 300.0,
+            Mode::Martin1 | Mode::Martin2 => self.sync_ms() + 4.0 * 0.572 + 3.0 * s,
+            Mode::Scottie1 | Mode::Scottie2 | Mode::ScottieDX => 13.5 + 3.0 * s,
+        }
+    }
+
+    // Scans of a line, as (start ms, length ms, channel).
+    fn scans(&self) -> Vec<(f64, f64, Channel)> {
+        let s = self.scan_ms();
+        match self {
+            Mode::Robot36 => vec![(12.0, 88.0, Channel::Luma), (106.0, 44.0, Channel::AltDiff)],
+            Mode::Robot72 => vec![
+                (12.0, 138.0, Channel::Luma),
+                (156.0, 69.0, Channel::RedDiff),
+                (231.0, 69.0, Channel::BlueDiff),
+            ],
+            Mode::Martin1 | Mode::Martin2 => {
+                let p = 0.572;
+                let g = self.sync_ms() + p;
+                vec![
+                    (g, s, Channel::Green),
+                    (g + s + p, s, Channel::Blue),
+                    (g + 2.0 * (s + p), s, Channel::Red),
+                ]
+            }
+            Mode::Scottie1 | Mode::Scottie2 | Mode::ScottieDX => vec![
+                (1.5, s, Channel::Green),
+                (3.0 + s, s, Channel::Blue),
+                (13.5 + 2.0 * s, s, Channel::Red),
+            ],
+        }
+    }
+}
+
+/// A decoded SSTV image.
+#[derive(Clone, Debug)]
+pub struct SstvImage {
+    /// Mode the image was received in.
+    pub mode: Mode,
+
+    /// Width in pixels.
+    pub width: usize,
+
+    /// Height in pixels. May be less than the mode height, if the
+    /// signal was lost before the end.
+    pub height: usize,
+
+    /// RGB pixel data, 3 bytes per pixel, row by row.
+    pub pixels: Vec<u8>,
+}
+
+impl SstvImage {
+    /// Write image as PNG.
+    pub fn write_png(&self, path: &Path) -> Result<()> {
+        let f = std::fs::File::create(path)?;
+        let mut enc = png::Encoder::new(BufWriter::new(f), self.width as u32, self.height as u32);
+        enc.set_color(png::ColorType::Rgb);
+        enc.set_depth(png::BitDepth::Eight);
+        let mut w = enc.write_header()?;
+        w.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+}
+
+impl crate::Len for SstvImage {
+    fn len(&self) -> usize {
+        self.pixels.len()
+    }
+}
+
+fn freq_to_level(f: Float) -> u8 {
+    ((f - BLACK_FREQ) / (WHITE_FREQ - BLACK_FREQ) * 255.0).clamp(0.0, 255.0) as u8
+}
+
+fn ycrcb_to_rgb(y: u8, cr: u8, cb: u8) -> [u8; 3] {
+    let y = y as Float;
+    let cr = cr as Float - 128.0;
+    let cb = cb as Float - 128.0;
+    [
+        (y + 1.402 * cr).clamp(0.0, 255.0) as u8,
+        (y - 0.344136 * cb - 0.714136 * cr).clamp(0.0, 255.0) as u8,
+        (y + 1.772 * cb).clamp(0.0, 255.0) as u8,
+    ]
+}
+
+enum State {
+    /// Looking for the VIS leader tone. Count of leader samples seen.
+    Leader(usize),
+
+    /// Receiving VIS bits, starting at the start bit.
+    Vis(Vec<Float>),
+
+    /// Receiving image.
+    Image(ImageState),
+}
+
+struct ImageState {
+    mode: Mode,
+
+    // Frequency samples. `buf[0]` is absolute sample `base`.
+    buf: Vec<Float>,
+    base: usize,
+
+    // Absolute (fractional) sample position of start of next line.
+    line_start: f64,
+
+    // Decoded lines, per scan.
+    lines: Vec<Vec<Vec<u8>>>,
+    missed_syncs: usize,
+}
+
+impl ImageState {
+    fn new(mode: Mode, start: f64) -> Self {
+        Self {
+            mode,
+            buf: Vec::new(),
+            base: 0,
+            line_start: start,
+            lines: Vec::with_capacity(mode.height()),
+            missed_syncs: 0,
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.lines.len() >= self.mode.height() || self.missed_syncs > MAX_MISSED_SYNCS
+    }
+
+    // Decode as many lines as are available.
+    fn decode_lines(&mut self, spms: f64) {
+        let window = (self.mode.sync_ms() * spms) as usize;
+        let line_len = self.mode.line_ms() * spms;
+        while !self.done() {
+            let need = (self.line_start + line_len) as usize + 2 * window;
+            if need > self.base + self.buf.len() {
+                return;
+            }
+            self.align_sync(spms, window);
+            let scans = self.mode.scans();
+            let line = scans.iter().map(|(start, len, _)| {
+                let w = self.mode.width();
+                (0..w)
+                    .map(|x| {
+                        let a = self.line_start + (start + len * x as f64 / w as f64) * spms;
+                        let b = self.line_start + (start + len * (x + 1) as f64 / w as f64) * spms;
+                        let a = a as usize - self.base;
+                        let b = std::cmp::max(a + 1, b as usize - self.base);
+                        let sum: Float = self.buf[a..b].iter().sum();
+                        freq_to_level(sum / (b - a) as Float)
+                    })
+                    .collect::<Vec<_>>()
+            });
+            let line = line.collect();
+            self.lines.push(line);
+            trace!("SstvDecode: decoded line {}", self.lines.len());
+            self.line_start += line_len;
+
+            // Drop samples no longer needed.
+            let keep_from = (self.line_start as usize).saturating_sub(2 * window);
+            if keep_from > self.base {
+                self.buf.drain(..(keep_from - self.base));
+                self.base = keep_from;
+            }
+        }
+    }
+
+    // Find the sync pulse near where it's expected, and adjust the line
+    // start accordingly.
+    fn align_sync(&mut self, spms: f64, window: usize) {
+        let sync_len = std::cmp::max(1, (self.mode.sync_ms() * spms) as usize);
+        let expected = (self.line_start + self.mode.sync_offset_ms() * spms) as usize;
+        let first = expected.saturating_sub(window).max(self.base);
+        let last = expected + window;
+        let is_sync = |pos: usize| -> usize {
+            (self.buf[pos - self.base] < (SYNC_FREQ + BLACK_FREQ) / 2.0) as usize
+        };
+        let mut score: usize = (first..first + sync_len).map(is_sync).sum();
+        let mut best = (score, first);
+        for pos in first + 1..=last {
+            score = score + is_sync(pos + sync_len - 1) - is_sync(pos - 1);
+            // Prefer the position closest to expected, on ties.
+            let dist = |p: usize| (p as i64 - expected as i64).abs();
+            if score > best.0 || (score == best.0 && dist(pos) < dist(best.1)) {
+                best = (score, pos);
+            }
+        }
+        if best.0 * 10 >= sync_len * 6 {
+            self.missed_syncs = 0;
+            let diff = best.1 as f64 - expected as f64;
+            self.line_start += diff;
+        } else {
+            self.missed_syncs += 1;
+            debug!("SstvDecode: no sync found for line {}", self.lines.len());
+        }
+    }
+
+    fn to_image(&self) -> SstvImage {
+        let mode = self.mode;
+        let width = mode.width();
+        let height = std::cmp::min(self.lines.len(), mode.height());
+        let scans = mode.scans();
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            let line = &self.lines[y];
+            for x in 0..width {
+                let mut rgb = [0u8; 3];
+                let (mut luma, mut cr, mut cb) = (0u8, 128u8, 128u8);
+                for (n, (_, _, ch)) in scans.iter().enumerate() {
+                    let v = line[n][x];
+                    match ch {
+                        Channel::Red => rgb[0] = v,
+                        Channel::Green => rgb[1] = v,
+                        Channel::Blue => rgb[2] = v,
+                        Channel::Luma => luma = v,
+                        Channel::RedDiff => cr = v,
+                        Channel::BlueDiff => cb = v,
+                        Channel::AltDiff => {
+                            // Pair even line (R-Y) with odd line (B-Y).
+                            let (even, odd) = if y & 1 == 0 { (y, y + 1) } else { (y - 1, y) };
+                            cr = self.lines[even][n][x];
+                            cb = self.lines.get(odd).map(|l| l[n][x]).unwrap_or(128);
+                        }
+                    }
+                }
+                if scans.iter().any(|(_, _, ch)| *ch == Channel::Luma) {
+                    rgb = ycrcb_to_rgb(luma, cr, cb);
+                }
+                pixels.extend(rgb);
+            }
+        }
+        SstvImage {
+            mode,
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// SSTV decoder block.
+///
+/// Takes instantaneous frequency in Hz, and outputs decoded images.
+pub struct SstvDecode {
+    src: Streamp<Float>,
+    dst: NoCopyStreamp<SstvImage>,
+    spms: f64,
+    state: State,
+    decoded: usize,
+}
+
+impl Drop for SstvDecode {
+    fn drop(&mut self) {
+        info!("SstvDecode: Decoded {} images", self.decoded);
+    }
+}
+
+impl SstvDecode {
+    /// Create new SSTV decoder.
+    pub fn new(src: Streamp<Float>, samp_rate: Float) -> Self {
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            spms: samp_rate as f64 / 1000.0,
+            state: State::Leader(0),
+            decoded: 0,
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<SstvImage> {
+        self.dst.clone()
+    }
+
+    // Try to parse VIS bits. Returns Err(leader samples) if this
+    // turned out not to be a VIS code, Ok(None) if more samples are
+    // needed, and Ok(Some(code)) when complete.
+    fn parse_vis(&self, buf: &[Float]) -> std::result::Result<Option<u8>, usize> {
+        let bit_len = VIS_BIT_MS * self.spms;
+        let bit_mean = |n: usize| -> Option<Float> {
+            // Look at middle half of bit.
+            let a = ((n as f64 + 0.25) * bit_len) as usize;
+            let b = ((n as f64 + 0.75) * bit_len) as usize;
+            if b > buf.len() || b <= a {
+                return None;
+            }
+            Some(buf[a..b].iter().sum::<Float>() / (b - a) as Float)
+        };
+        let is_sync = |f: Float| (f - SYNC_FREQ).abs() < 60.0;
+        let fail = || {
+            buf.iter()
+                .rev()
+                .take_while(|f| (*f - LEADER_FREQ).abs() < 100.0)
+                .count()
+        };
+        if let Some(start) = bit_mean(0) {
+            if !is_sync(start) {
+                return Err(fail());
+            }
+        }
+        if buf.len() < (10.0 * bit_len) as usize {
+            return Ok(None);
+        }
+        let mut code = 0u8;
+        let mut ones = 0;
+        for n in 0..8 {
+            let bit = bit_mean(1 + n).unwrap() < SYNC_FREQ; // unwrap: length checked above.
+            ones += bit as usize;
+            if n < 7 {
+                code |= (bit as u8) << n;
+            }
+        }
+        if ones & 1 != 0 {
+            debug!("SstvDecode: VIS parity error for code {code}");
+            return Err(0);
+        }
+        if !is_sync(bit_mean(9).unwrap()) {
+            debug!("SstvDecode: VIS stop bit missing for code {code}");
+            return Err(0);
+        }
+        Ok(Some(code))
+    }
+
+    fn process_one(&mut self, f: Float) {
+        match &mut self.state {
+            State::Leader(n) => {
+                if (f - LEADER_FREQ).abs() < 100.0 {
+                    *n += 1;
+                } else if (*n as f64) > 200.0 * self.spms && f < (SYNC_FREQ + LEADER_FREQ) / 2.0 {
+                    trace!("SstvDecode: possible VIS start bit");
+                    self.state = State::Vis(vec![f]);
+                } else {
+                    *n = 0;
+                }
+            }
+            State::Vis(buf) => {
+                buf.push(f);
+                let buf = std::mem::take(buf);
+                self.state = match self.parse_vis(&buf) {
+                    Ok(None) => State::Vis(buf),
+                    Err(leader) => State::Leader(leader),
+                    Ok(Some(code)) => match Mode::from_vis(code) {
+                        None => {
+                            info!("SstvDecode: unsupported VIS code {code}");
+                            State::Leader(0)
+                        }
+                        Some(mode) => {
+                            info!("SstvDecode: receiving {:?} image", mode);
+                            State::Image(ImageState::new(mode, mode.start_ms() * self.spms))
+                        }
+                    },
+                };
+            }
+            State::Image(img) => {
+                img.buf.push(f);
+            }
+        }
+    }
+}
+
+impl Block for SstvDecode {
+    fn block_name(&self) -> &str {
+        "SstvDecode"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        for f in input.iter() {
+            self.process_one(*f);
+            if let State::Image(img) = &mut self.state {
+                // Decode in batches, to not search for syncs every
+                // sample.
+                if img.buf.len() > (img.mode.line_ms() * 2.0 * self.spms) as usize {
+                    img.decode_lines(self.spms);
+                }
+                if img.done() {
+                    let image = img.to_image();
+                    info!(
+                        "SstvDecode: {:?} image done, {} lines",
+                        image.mode, image.height
+                    );
+                    self.dst.push(image, &[]);
+                    self.decoded += 1;
+                    self.state = State::Leader(0);
+                }
+            }
+        }
+        let n = input.len();
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+/// Write SSTV images to a directory as PNG files, named by the time
+/// they were received.
+pub struct SstvPngWriter {
+    src: NoCopyStreamp<SstvImage>,
+    dir: PathBuf,
+}
+
+impl SstvPngWriter {
+    /// Create new SstvPngWriter.
+    pub fn new(src: NoCopyStreamp<SstvImage>, dir: PathBuf) -> Self {
+        Self { src, dir }
+    }
+}
+
+impl Block for SstvPngWriter {
+    fn block_name(&self) -> &str {
+        "SstvPngWriter"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let image = match self.src.pop() {
+            None => return Ok(BlockRet::Noop),
+            Some((x, _tags)) => x,
+        };
+        let name = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_micros();
+        let full = self.dir.join(format!("{name}.png"));
+        info!("Saving {:?} SSTV image to {:?}", image.mode, full);
+        image.write_png(&full)?;
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    fn tone(v: &mut Vec<Float>, spms: f64, freq: Float, ms: f64, t: &mut f64) {
+        let end = *t + ms * spms;
+        while (*t as usize) < end as usize {
+            v.push(freq);
+            *t += 1.0;
+        }
+        *t = end;
+    }
+
+    fn level(l: u8) -> Float {
+        BLACK_FREQ + (WHITE_FREQ - BLACK_FREQ) * l as Float / 255.0
+    }
+
+    // Generate frequency samples for a transmission where every pixel
+    // of each scan has value from `f(line, scan)`.
+    fn encode(mode: Mode, spms: f64, f: impl Fn(usize, usize) -> u8) -> Vec<Float> {
+        let mut v = Vec::new();
+        let mut t = 0.0;
+        tone(&mut v, spms, 0.0, 100.0, &mut t);
+        tone(&mut v, spms, LEADER_FREQ, 300.0, &mut t);
+        tone(&mut v, spms, SYNC_FREQ, 10.0, &mut t);
+        tone(&mut v, spms, LEADER_FREQ, 300.0, &mut t);
+        tone(&mut v, spms, SYNC_FREQ, 30.0, &mut t);
+        let code = mode.vis();
+        let mut parity = 0;
+        for n in 0..7 {
+            let bit = (code >> n) & 1;
+            parity ^= bit;
+            tone(&mut v, spms, [1300.0, 1100.0][bit as usize], 30.0, &mut t);
+        }
+        tone(
+            &mut v,
+            spms,
+            [1300.0, 1100.0][parity as usize],
+            30.0,
+            &mut t,
+        );
+        tone(&mut v, spms, SYNC_FREQ, 30.0, &mut t);
+        tone(&mut v, spms, SYNC_FREQ, mode.start_ms(), &mut t);
+        for line in 0..mode.height() {
+            let mut segs = vec![(mode.sync_offset_ms(), mode.sync_ms(), SYNC_FREQ)];
+            for (n, (start, len, _)) in mode.scans().iter().enumerate() {
+                segs.push((*start, *len, level(f(line, n))));
+            }
+            let end = t + mode.line_ms() * spms;
+            let mut k = v.len() as f64;
+            while k < end {
+                let ms = (k - t) / spms;
+                let freq = segs
+                    .iter()
+                    .find(|(s, l, _)| ms >= *s && ms < s + l)
+                    .map(|(_, _, f)| *f)
+                    .unwrap_or(BLACK_FREQ);
+                v.push(freq);
+                k += 1.0;
+            }
+            t = end;
+        }
+        tone(&mut v, spms, 0.0, 500.0, &mut t);
+        v
+    }
+
+    fn decode(samples: &[Float], samp_rate: Float) -> Result<Vec<SstvImage>> {
+        let mut dec = SstvDecode::new(crate::stream::new_streamp(), samp_rate);
+        for chunk in samples.chunks(10000) {
+            dec.src = streamp_from_slice(chunk);
+            dec.work()?;
+        }
+        let mut ret = Vec::new();
+        while let Some((img, _)) = dec.out().pop() {
+            ret.push(img);
+        }
+        Ok(ret)
+    }
+
+    #[test]
+    fn martin2() -> Result<()> {
+        let samp_rate = 11025.0;
+        let samples = encode(Mode::Martin2, samp_rate as f64 / 1000.0, |line, scan| {
+            // Green, blue, red.
+            [(line % 256) as u8, 200, 50][scan]
+        });
+        let imgs = decode(&samples, samp_rate)?;
+        assert_eq!(imgs.len(), 1);
+        let img = &imgs[0];
+        assert_eq!(img.mode, Mode::Martin2);
+        assert_eq!(img.height, 256);
+        for y in [0, 10, 100, 255] {
+            for x in [5, 160, 315] {
+                let p = &img.pixels[(y * 320 + x) * 3..][..3];
+                let want = [50, y as i32, 200];
+                for (got, want) in p.iter().zip(want) {
+                    assert!((*got as i32 - want).abs() <= 3, "{y},{x}: {p:?}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn scottie1_slant() -> Result<()> {
+        // Receiver sample rate 0.1% off.
+        let samples = encode(Mode::Scottie1, 8.0, |_, scan| [255, 0, 128][scan]);
+        let imgs = decode(&samples, 8008.0)?;
+        assert_eq!(imgs.len(), 1);
+        let img = &imgs[0];
+        assert_eq!(img.mode, Mode::Scottie1);
+        let p = &img.pixels[(250 * 320 + 300) * 3..][..3];
+        assert!((p[0] as i32 - 128).abs() < 10, "{p:?}");
+        assert!(p[1] > 245, "{p:?}");
+        assert!(p[2] < 10, "{p:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn robot36() -> Result<()> {
+        // Grey: Y=128, Cr=Cb=128.
+        let samples = encode(Mode::Robot36, 8.0, |_, _| 128);
+        let imgs = decode(&samples, 8000.0)?;
+        assert_eq!(imgs.len(), 1);
+        let img = &imgs[0];
+        assert_eq!((img.width, img.height), (320, 240));
+        for p in img.pixels.chunks(3).skip(1000).take(1000) {
+            for c in p {
+                assert!((*c as i32 - 128).abs() < 5, "{p:?}");
+            }
+        }
+        Ok(())
+    }
+}
@@ -2,16 +2,61 @@
 
 Blocks are connected with streams. A block can have zero or more input
 streams, and write to zero or more output streams.
+
+Streams carry their item type, so connecting blocks of mismatched
+types is a compile error:
+
+```compile_fail
+use rustradio::blocks::{ConstantSource, FloatToComplex};
+use rustradio::Complex;
+let src = ConstantSource::new(Complex::new(1.0, 0.0));
+// FloatToComplex takes a Float stream, not a Complex one.
+let conv = FloatToComplex::new(src.out(), src.out());
+```
+
+# Tags
+
+Samples can carry [Tag]s: key/value metadata attached to one sample,
+like where a burst starts, or that the frequency changed. A block
+gets the tags of its input from `read_buf()`, with positions relative
+to the start of the input slice, and passes tags on with `produce()`,
+with positions relative to what it's producing. Tags past the produced
+samples are dropped, so a block that only produced part of its input
+can pass on all the input tags, and the rest are passed on next time.
+
+Blocks made with the `map_block` macros pass tags through
+automatically. Blocks that change the sample rate move the tags to the
+corresponding output sample.
+
+Common keys are [TAG_SAMP_RATE], [TAG_FREQUENCY], [TAG_BURST_START],
+and [TAG_BURST_END].
 */
+use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
+use crate::block::{BlockRet, WorkHints};
 use crate::circular_buffer;
 use crate::{Error, Float, Len};
 
 /// Tag position in the current stream.
 pub type TagPos = usize;
 
+/// Tag key for the sample rate from this sample on, in Hz (Float).
+pub const TAG_SAMP_RATE: &str = "samp_rate";
+
+/// Tag key for the center frequency from this sample on, in Hz
+/// (Float). Same as the PDU metadata key
+/// [FREQUENCY](crate::pdu::FREQUENCY).
+pub const TAG_FREQUENCY: &str = "frequency";
+
+/// Tag key for the first sample of a burst (Bool true).
+pub const TAG_BURST_START: &str = "burst_start";
+
+/// Tag key for the last sample of a burst (Bool true).
+pub const TAG_BURST_END: &str = "burst_end";
+
 /// Enum of tag values.
 #[derive(Clone, Debug, PartialEq)]
 pub enum TagValue {
@@ -56,6 +101,12 @@ impl Tag {
     pub fn val(&self) -> &TagValue {
         &self.val
     }
+
+    /// Copy of the tag, moved to `pos`. For passing tags on to an
+    /// output that doesn't line up with the input.
+    pub fn with_pos(&self, pos: TagPos) -> Self {
+        Self::new(pos, self.key.clone(), self.val.clone())
+    }
 }
 
 /// A stream between blocks.
@@ -74,7 +125,7 @@ pub fn new_streamp<T>() -> Streamp<T> {
 
 /// A stream of noncopyable objects (e.g. Vec / PDUs).
 pub struct NoCopyStream<T> {
-    s: Mutex<VecDeque<T>>,
+    s: Mutex<VecDeque<(T, Vec<Tag>)>>,
 }
 
 /// Convenience type for a "pointer to a stream".
@@ -90,15 +141,51 @@ pub fn streamp_from_slice<T: Copy>(data: &[T]) -> Streamp<T> {
     Arc::new(Stream::from_slice(data))
 }
 
-const DEFAULT_STREAM_SIZE: usize = 409600;
+// In bytes. Scaled with the size of Float, so that streams hold the
+// same number of samples with the `f64` feature.
+const DEFAULT_STREAM_SIZE: usize = 409600 * std::mem::size_of::<Float>() / 4;
+
+static STREAM_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_STREAM_SIZE);
+
+/** Set the size in bytes of streams created from now on.
+
+Blocks create their own output streams, so this is the way to get
+bigger buffers between them, e.g. to ride out scheduling hiccups at
+high sample rates. Call it before creating the blocks.
+
+The default is 400KiB, or 800KiB with the `f64` feature.
+
+```
+use rustradio::stream::{new_streamp, set_default_size};
+set_default_size(16 << 20);
+let s = new_streamp::<rustradio::Complex>();
+assert_eq!(s.total_size(), (16 << 20) / std::mem::size_of::<rustradio::Complex>());
+```
+*/
+pub fn set_default_size(bytes: usize) {
+    STREAM_SIZE.store(bytes, Ordering::Relaxed);
+}
 
 impl<T> Stream<T> {
-    /// Create a new stream.
+    /// Create a new stream, of the [default size](set_default_size).
     pub fn new() -> Self {
         Self {
-            circ: circular_buffer::Buffer::new(DEFAULT_STREAM_SIZE).unwrap(),
+            circ: circular_buffer::Buffer::new(STREAM_SIZE.load(Ordering::Relaxed)).unwrap(),
         }
     }
+
+    /// Create a new stream with room for at least `items` items.
+    ///
+    /// The size is rounded up to whole memory pages, so the actual
+    /// capacity, given by [total_size](Self::total_size), may be a
+    /// bit bigger.
+    pub fn with_capacity(items: usize) -> Result<Self, Error> {
+        let bytes = items.saturating_mul(std::mem::size_of::<T>());
+        Ok(Self {
+            circ: circular_buffer::Buffer::new(bytes)
+                .map_err(|e| Error::new(&format!("creating stream buffer: {e}")))?,
+        })
+    }
 }
 
 impl<T> NoCopyStream<T> {
@@ -111,17 +198,29 @@ impl<T> NoCopyStream<T> {
 
     /// Push one sample, handing off ownership.
     /// Ideally this should only be NoCopy.
-    ///
-    /// TODO: Actually store the tags.
-    pub fn push(&self, val: T, _tags: &[Tag]) {
-        self.s.lock().unwrap().push_back(val);
+    pub fn push(&self, val: T, tags: &[Tag]) {
+        self.s.lock().unwrap().push_back((val, tags.to_vec()));
+        count(|c| c.items_out += 1);
     }
 
-    /// Pop one sample.
+    /// Pop one sample, with its tags.
     /// Ideally this should only be NoCopy.
     pub fn pop(&self) -> Option<(T, Vec<Tag>)> {
-        // TODO: attach tags.
-        self.s.lock().unwrap().pop_front().map(|v| (v, Vec::new()))
+        let ret = self.s.lock().unwrap().pop_front();
+        if ret.is_some() {
+            count(|c| c.items_in += 1);
+        }
+        ret
+    }
+
+    /// Number of items in the stream.
+    pub fn len(&self) -> usize {
+        self.s.lock().unwrap().len()
+    }
+
+    /// Return true if the stream is empty.
+    pub fn is_empty(&self) -> bool {
+        self.s.lock().unwrap().is_empty()
     }
 }
 
@@ -134,14 +233,20 @@ impl<T> Default for NoCopyStream<T> {
 impl<T: Len> NoCopyStream<T> {
     /// Get the size of the front packet.
     pub fn peek_size(&self) -> Option<usize> {
-        self.s.lock().unwrap().front().map(|e| e.len())
+        self.s.lock().unwrap().front().map(|(e, _)| e.len())
     }
 }
 
 impl<T: Copy> Stream<T> {
     /// Create a new stream with initial data in it.
+    ///
+    /// The stream is made bigger than the default if needed.
     pub fn from_slice(data: &[T]) -> Self {
-        let circ = circular_buffer::Buffer::new(DEFAULT_STREAM_SIZE).unwrap(); // TODO
+        let size = std::cmp::max(
+            STREAM_SIZE.load(Ordering::Relaxed),
+            std::mem::size_of_val(data),
+        );
+        let circ = circular_buffer::Buffer::new(size).unwrap(); // TODO
         let mut wb = circ.write_buf().unwrap();
         wb.fill_from_slice(data);
         wb.produce(data.len(), &[]);
@@ -149,7 +254,7 @@ impl<T: Copy> Stream<T> {
     }
 
     /// Return total length of underlying circular buffer (before the
-    /// mapping doubling).
+    /// mapping doubling), in items.
     pub fn total_size(&self) -> usize {
         self.circ.total_size()
     }
@@ -158,18 +263,164 @@ impl<T: Copy> Stream<T> {
     ///
     /// The only reason for returning error should be if there's
     /// already a write slice handed out.
-    pub fn write_buf(&self) -> Result<circular_buffer::BufferWriter<T>, Error> {
-        // TODO: not sure why I need to use both Ok and ?. Should it not be From'd?
-        Ok(self.circ.write_buf()?)
+    pub fn write_buf(&self) -> Result<circular_buffer::BufferWriter<'_, T>, Error> {
+        let mut w = self.circ.write_buf()?;
+        if let Some(max) = LIMITS.with_borrow(|l| l.as_ref().map(|l| l.max)) {
+            w.truncate(std::cmp::min(w.len(), max));
+        }
+        Ok(w)
     }
 
     /// Return a read slice and the tags within the slice.
     ///
     /// The only reason for returning error should be if there's
     /// already a read slice handed out.
-    pub fn read_buf(&self) -> Result<(circular_buffer::BufferReader<T>, Vec<Tag>), Error> {
-        // TODO: not sure why I need to use both Ok and ?. Should it not be From'd?
-        Ok(self.circ.read_buf()?)
+    pub fn read_buf(&self) -> Result<(circular_buffer::BufferReader<'_, T>, Vec<Tag>), Error> {
+        let (mut r, mut tags) = self.circ.read_buf()?;
+        let fill = r.len() as f64 / self.circ.total_size() as f64;
+        count(|c| {
+            c.fill += fill;
+            c.reads += 1;
+        });
+        if let Some(n) = LIMITS.with_borrow_mut(|l| l.as_mut().map(|l| l.read(r.len()))) {
+            r.truncate(n);
+            tags.retain(|t| t.pos() < n);
+        }
+        Ok((r, tags))
+    }
+}
+
+// Limits for the `work()` call in progress on this thread, if it was
+// started by a [WorkLimiter].
+struct Limits {
+    min: usize,
+    max: usize,
+
+    // Number of read_buf() calls so far in this work() call.
+    reads: usize,
+
+    // Items held back, per read_buf() call, in this and the previous
+    // work() call.
+    held: Vec<usize>,
+    prev_held: Vec<usize>,
+}
+
+impl Limits {
+    // Return how much of `len` available items to hand out.
+    fn read(&mut self, len: usize) -> usize {
+        let i = self.reads;
+        self.reads += 1;
+        self.held.resize(self.reads, 0);
+        // If the same stream had the same number of items held back
+        // last time, then it's not growing, so hand them out.
+        if len < self.min && self.prev_held.get(i) != Some(&len) {
+            self.held[i] = len;
+            return 0;
+        }
+        std::cmp::min(len, self.max)
+    }
+}
+
+/// What a block did, summed over its `work()` calls.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct WorkCounts {
+    pub(crate) calls: u64,
+    pub(crate) items_in: u64,
+    pub(crate) items_out: u64,
+
+    // Sum of input buffer fill ratios, over `reads` read_buf() calls.
+    pub(crate) fill: f64,
+    pub(crate) reads: u64,
+}
+
+impl WorkCounts {
+    fn add(&mut self, o: &WorkCounts) {
+        self.calls += o.calls;
+        self.items_in += o.items_in;
+        self.items_out += o.items_out;
+        self.fill += o.fill;
+        self.reads += o.reads;
+    }
+}
+
+thread_local! {
+    static LIMITS: RefCell<Option<Limits>> = const { RefCell::new(None) };
+
+    // Counts for the `work()` call in progress on this thread, if it
+    // was started by a [WorkLimiter].
+    static COUNTS: RefCell<Option<WorkCounts>> = const { RefCell::new(None) };
+}
+
+/// Update the counts of the `work()` call in progress, if any.
+pub(crate) fn count(f: impl FnOnce(&mut WorkCounts)) {
+    COUNTS.with_borrow_mut(|c| {
+        if let Some(c) = c {
+            f(c);
+        }
+    });
+}
+
+/// Applies a block's [WorkHints] to its `work()` calls, and counts
+/// what they do.
+///
+/// Used by the schedulers. Keeps state between calls, so there should
+/// be one per block.
+pub(crate) struct WorkLimiter {
+    hints: WorkHints,
+    prev_held: Vec<usize>,
+    counts: WorkCounts,
+}
+
+impl WorkLimiter {
+    pub(crate) fn new(hints: WorkHints) -> Self {
+        Self {
+            hints,
+            prev_held: Vec::new(),
+            counts: WorkCounts::default(),
+        }
+    }
+
+    /// Counts of all `work()` calls so far.
+    pub(crate) fn counts(&self) -> &WorkCounts {
+        &self.counts
+    }
+
+    /// Run `f`, which should call the block's `work()`, with stream
+    /// access limited by the hints.
+    pub(crate) fn work(
+        &mut self,
+        f: impl FnOnce() -> Result<BlockRet, Error>,
+    ) -> Result<BlockRet, Error> {
+        let prev = COUNTS.replace(Some(WorkCounts {
+            calls: 1,
+            ..Default::default()
+        }));
+        let ret = self.limited(f);
+        if let Some(c) = COUNTS.replace(prev) {
+            self.counts.add(&c);
+        }
+        ret
+    }
+
+    fn limited(&mut self, f: impl FnOnce() -> Result<BlockRet, Error>) -> Result<BlockRet, Error> {
+        if self.hints == WorkHints::default() {
+            return f();
+        }
+        LIMITS.set(Some(Limits {
+            min: self.hints.min_items.unwrap_or(0),
+            max: self.hints.max_items.unwrap_or(usize::MAX),
+            reads: 0,
+            held: Vec::new(),
+            prev_held: std::mem::take(&mut self.prev_held),
+        }));
+        let ret = f();
+        self.prev_held = LIMITS.take().map(|l| l.held).unwrap_or_default();
+        // Held back items still need processing, even if the block
+        // had nothing to do with what it was given.
+        Ok(match ret? {
+            BlockRet::Noop if self.prev_held.iter().any(|&n| n > 0) => BlockRet::Pending,
+            r => r,
+        })
     }
 }
 impl<T> Default for Stream<T> {
@@ -136,3 +136,90 @@ where
         Ok(BlockRet::Ok)
     }
 }
+
+/** Fixed length PDUs after a tag.
+
+Cuts out `len` samples starting right after any sample tagged with
+`tag`, regardless of the tag value. E.g. for a bit stream tagged by
+[CorrelateAccessCodeTag][crate::correlate_access_code::CorrelateAccessCodeTag]
+on the last bit of the sync word, this outputs the packet bits.
+
+Tags seen while a PDU is being collected are ignored.
+*/
+pub struct TagToPdu<T> {
+    src: Streamp<T>,
+    dst: NoCopyStreamp<Vec<T>>,
+    tag: String,
+    len: usize,
+    buf: Option<Vec<T>>,
+}
+
+impl<T> TagToPdu<T> {
+    /// Make new TagToPdu block.
+    pub fn new(src: Streamp<T>, tag: String, len: usize) -> Self {
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            tag,
+            len,
+            buf: None,
+        }
+    }
+    /// Get output PDU stream.
+    pub fn out(&self) -> NoCopyStreamp<Vec<T>> {
+        self.dst.clone()
+    }
+}
+
+impl<T: Copy> Block for TagToPdu<T> {
+    fn block_name(&self) -> &str {
+        "TagToPdu"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (input, tags) = self.src.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let starts: Vec<TagPos> = tags
+            .iter()
+            .filter(|t| t.key() == self.tag)
+            .map(|t| t.pos())
+            .collect();
+        for (i, sample) in input.iter().enumerate() {
+            if let Some(buf) = &mut self.buf {
+                buf.push(*sample);
+                if buf.len() == self.len {
+                    trace!("TagToPdu: got PDU");
+                    self.dst.push(self.buf.take().unwrap(), &[]);
+                }
+            } else if starts.contains(&i) && self.len > 0 {
+                self.buf = Some(Vec::with_capacity(self.len));
+            }
+        }
+        let n = input.len();
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::CorrelateAccessCodeTag;
+    use crate::stream::streamp_from_slice;
+
+    #[test]
+    fn tag_to_pdu() -> anyhow::Result<()> {
+        let bits = [0u8, 1, 1, 0, 1, 0, 0, 1, 1, 1, 0, 1, 1, 0, 1, 0];
+        let src = streamp_from_slice(&bits);
+        let mut cac = CorrelateAccessCodeTag::new(src, vec![1, 1, 0, 1], "sync".into(), 0);
+        cac.work()?;
+        let mut b = TagToPdu::new(cac.out(), "sync".into(), 5);
+        b.work()?;
+        let out = b.out();
+        assert_eq!(out.pop().unwrap().0, vec![0, 0, 1, 1, 1]);
+        // The second PDU is incomplete.
+        assert!(out.pop().is_none());
+        Ok(())
+    }
+}
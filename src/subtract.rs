@@ -0,0 +1,61 @@
+//! Subtract two streams.
+use crate::block::Block;
+use crate::stream::{new_streamp, Streamp};
+
+/// Subtracts one stream from another, sample by sample.
+///
+/// Output is `a - b`.
+#[derive(Block)]
+pub struct Subtract<T>
+where
+    T: Copy + std::ops::Sub<Output = T>,
+{
+    #[rustradio(input)]
+    a: Streamp<T>,
+    #[rustradio(input)]
+    b: Streamp<T>,
+    #[rustradio(output)]
+    dst: Streamp<T>,
+}
+
+impl<T> Subtract<T>
+where
+    T: Copy + std::ops::Sub<Output = T>,
+{
+    /// Create a new Subtract block.
+    pub fn new(a: Streamp<T>, b: Streamp<T>) -> Self {
+        Self {
+            a,
+            b,
+            dst: new_streamp(),
+        }
+    }
+
+    fn process_one(&self, a: T, b: T) -> T {
+        a - b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockRet;
+    use crate::stream::streamp_from_slice;
+
+    #[test]
+    fn subtract() -> crate::Result<()> {
+        let a = streamp_from_slice(&[10i32, 20, 30]);
+        let b = streamp_from_slice(&[1i32, 2]);
+        let mut sub = Subtract::new(a.clone(), b.clone());
+        assert_eq!(sub.block_name(), "Subtract");
+        sub.work()?;
+        assert!(matches!(sub.work()?, BlockRet::Noop));
+        let o = sub.out();
+        let (res, _) = o.read_buf()?;
+        assert_eq!(res.slice(), &[9, 18]);
+        // The extra sample on `a` is left for when `b` catches up.
+        assert_eq!(a.read_buf()?.0.slice(), &[30]);
+        assert!(b.read_buf()?.0.is_empty());
+        Ok(())
+    }
+}
@@ -9,7 +9,7 @@ use log::debug;
 
 use crate::block::{Block, BlockRet};
 use crate::iir_filter::CappedFilter;
-use crate::stream::{new_streamp, Streamp};
+use crate::stream::{new_streamp, Streamp, Tag};
 use crate::{Error, Float};
 
 /// Timing Error Detector.
@@ -38,6 +38,8 @@ impl TED for TEDZeroCrossing {}
 /** Pluggable clock recovery block.
 
 Under development.
+
+Input tags are moved to the next output symbol.
 */
 pub struct SymbolSync {
     sps: Float,
@@ -52,6 +54,8 @@ pub struct SymbolSync {
     src: Streamp<Float>,
     dst: Streamp<Float>,
     out_clock: Option<Streamp<Float>>,
+    // Input tags, waiting for the next output sample.
+    tags: Vec<Tag>,
 }
 
 impl SymbolSync {
@@ -82,6 +86,7 @@ impl SymbolSync {
             last_sym_boundary_pos: 0.0,
             next_sym_middle: 0.0,
             out_clock: None,
+            tags: Vec::new(),
         }
     }
 
@@ -101,7 +106,7 @@ impl Block for SymbolSync {
         "SymbolSync"
     }
     fn work(&mut self) -> Result<BlockRet, Error> {
-        let (input, _tags) = self.src.read_buf()?;
+        let (input, mut tags) = self.src.read_buf()?;
         if input.is_empty() {
             return Ok(BlockRet::Noop);
         }
@@ -111,6 +116,10 @@ impl Block for SymbolSync {
         }
         // TODO: get rid of unwrap.
         let mut out_clock = self.out_clock.as_mut().map(|x| x.write_buf().unwrap());
+        // Tags are moved to the next output sample.
+        tags.sort_by_key(|t| t.pos());
+        let mut tags = tags.into_iter().peekable();
+        let mut otags = Vec::new();
 
         let mut n = 0; // Samples consumed.
         let mut opos = 0; // Current output position.
@@ -118,12 +127,16 @@ impl Block for SymbolSync {
         let oslice = o.slice();
         for sample in input.iter() {
             n += 1;
+            while let Some(t) = tags.next_if(|t| t.pos() < n) {
+                self.tags.push(t);
+            }
             if self.stream_pos >= self.next_sym_middle {
                 // TODO: use more than center sample.
                 oslice[opos] = *sample;
                 if let Some(ref mut s) = out_clock {
                     s.slice()[opos] = self.clock;
                 }
+                otags.extend(self.tags.drain(..).map(|t| t.with_pos(opos)));
                 opos += 1;
                 self.next_sym_middle += self.clock;
                 if opos == olen {
@@ -188,7 +201,7 @@ impl Block for SymbolSync {
             }
         }
         input.consume(n);
-        o.produce(opos, &[]);
+        o.produce(opos, &otags);
         if let Some(s) = out_clock {
             s.produce(opos, &[]);
         }
@@ -0,0 +1,354 @@
+/*! TCP PDU transport.
+
+[TcpPduSource] listens for connections from external applications,
+e.g. one generating frames to transmit, and outputs each received frame
+as a [Pdu]. [TcpPduSink] listens for subscribers, and sends every PDU
+to all of them, e.g. to stream decodes to other software.
+
+Frames on the wire are either length prefixed (32 bit big endian), or
+[KISS][crate::kiss] data frames, which is what most packet radio
+software speaks.
+
+Neither block blocks the graph waiting for connections or data.
+*/
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{debug, info, warn};
+
+use crate::block::{Block, BlockRet};
+use crate::pdu::Pdu;
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp};
+use crate::{kiss, Error};
+
+// Largest frame accepted by the source.
+const MAX_FRAME: usize = 1 << 20;
+
+// A subscriber not accepting data for this long is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Framing on the TCP stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Framing {
+    /// 32 bit big endian length, followed by the data.
+    #[default]
+    LengthPrefixed,
+
+    /// KISS data frames. The source accepts any port, and the sink
+    /// sends on port 0.
+    Kiss,
+}
+
+impl Framing {
+    /// Encode a frame.
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Framing::LengthPrefixed => {
+                let mut v = Vec::with_capacity(data.len() + 4);
+                v.extend((data.len() as u32).to_be_bytes());
+                v.extend(data);
+                v
+            }
+            Framing::Kiss => kiss::encode(0, data),
+        }
+    }
+
+    /// Take complete frames from the front of `buf`.
+    ///
+    /// Returns error if the stream is corrupt beyond recovery.
+    pub fn decode(&self, buf: &mut Vec<u8>) -> Result<Vec<Vec<u8>>> {
+        let mut ret = Vec::new();
+        match self {
+            Framing::LengthPrefixed => {
+                while buf.len() >= 4 {
+                    let len = u32::from_be_bytes(buf[..4].try_into().unwrap()) as usize;
+                    if len > MAX_FRAME {
+                        return Err(Error::new(&format!("frame too long: {len}")).into());
+                    }
+                    if buf.len() < 4 + len {
+                        break;
+                    }
+                    ret.push(buf[4..4 + len].to_vec());
+                    buf.drain(..4 + len);
+                }
+            }
+            Framing::Kiss => loop {
+                let start = buf.iter().take_while(|b| **b == kiss::FEND).count();
+                buf.drain(..start);
+                let Some(end) = buf.iter().position(|b| *b == kiss::FEND) else {
+                    if buf.len() > MAX_FRAME {
+                        return Err(Error::new("KISS frame too long").into());
+                    }
+                    break;
+                };
+                match kiss::decode(&buf[..end]) {
+                    Some((cmd, data)) if cmd & 0x0f == kiss::CMD_DATA => ret.push(data),
+                    Some((cmd, _)) => debug!("Framing: ignoring KISS command {cmd:02x}"),
+                    None => warn!("Framing: bad KISS frame"),
+                }
+                buf.drain(..end);
+            },
+        }
+        Ok(ret)
+    }
+}
+
+fn listen<A: ToSocketAddrs>(addr: A) -> Result<TcpListener> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    debug!("Listening on {}", listener.local_addr()?);
+    Ok(listener)
+}
+
+// Accept all pending connections.
+fn accept(listener: &TcpListener) -> Result<Vec<TcpStream>> {
+    let mut ret = Vec::new();
+    loop {
+        match listener.accept() {
+            Ok((s, addr)) => {
+                info!("New connection from {addr}");
+                ret.push(s);
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(ret),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+struct Client {
+    stream: TcpStream,
+    buf: Vec<u8>,
+}
+
+/** TCP PDU source.
+
+Listens for connections, and outputs each frame received as a PDU.
+Any number of clients can be connected at the same time.
+*/
+pub struct TcpPduSource {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    framing: Framing,
+    dst: NoCopyStreamp<Pdu>,
+    received: usize,
+}
+
+impl TcpPduSource {
+    /// Create new source, listening on `addr`, e.g. `"[::]:8001"`.
+    pub fn new<A: ToSocketAddrs>(addr: A, framing: Framing) -> Result<Self> {
+        Ok(Self {
+            listener: listen(addr)?,
+            clients: Vec::new(),
+            framing,
+            dst: new_nocopy_streamp(),
+            received: 0,
+        })
+    }
+
+    /// Address being listened on.
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<Pdu> {
+        self.dst.clone()
+    }
+
+    // Read what's available from a client. Returns false if the client
+    // should be dropped.
+    fn read_client(&mut self, n: usize) -> bool {
+        let mut tmp = [0u8; 4096];
+        loop {
+            let c = &mut self.clients[n];
+            match c.stream.read(&mut tmp) {
+                Ok(0) => return false,
+                Ok(len) => c.buf.extend(&tmp[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("TcpPduSource: read error: {e}");
+                    return false;
+                }
+            }
+        }
+        match self.framing.decode(&mut self.clients[n].buf) {
+            Ok(frames) => {
+                for f in frames {
+                    self.received += 1;
+                    self.dst.push(Pdu::new(f), &[]);
+                }
+                true
+            }
+            Err(e) => {
+                warn!("TcpPduSource: dropping client: {e}");
+                false
+            }
+        }
+    }
+}
+
+impl Drop for TcpPduSource {
+    fn drop(&mut self) {
+        info!("TcpPduSource: received {}", self.received);
+    }
+}
+
+impl Block for TcpPduSource {
+    fn block_name(&self) -> &str {
+        "TcpPduSource"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        for stream in accept(&self.listener)? {
+            stream.set_nonblocking(true)?;
+            self.clients.push(Client {
+                stream,
+                buf: Vec::new(),
+            });
+        }
+        let before = self.received;
+        let mut n = 0;
+        while n < self.clients.len() {
+            if self.read_client(n) {
+                n += 1;
+            } else {
+                info!("TcpPduSource: client disconnected");
+                self.clients.swap_remove(n);
+            }
+        }
+        Ok(if self.received == before {
+            BlockRet::Noop
+        } else {
+            BlockRet::Ok
+        })
+    }
+}
+
+/** TCP PDU sink.
+
+Listens for subscribers, and sends each PDU to all of them. PDUs are
+dropped if there are no subscribers.
+*/
+pub struct TcpPduSink {
+    src: NoCopyStreamp<Pdu>,
+    listener: TcpListener,
+    clients: Vec<TcpStream>,
+    framing: Framing,
+    sent: usize,
+}
+
+impl TcpPduSink {
+    /// Create new sink, listening on `addr`, e.g. `"[::]:8001"`.
+    pub fn new<A: ToSocketAddrs>(
+        src: NoCopyStreamp<Pdu>,
+        addr: A,
+        framing: Framing,
+    ) -> Result<Self> {
+        Ok(Self {
+            src,
+            listener: listen(addr)?,
+            clients: Vec::new(),
+            framing,
+            sent: 0,
+        })
+    }
+
+    /// Address being listened on.
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+}
+
+impl Drop for TcpPduSink {
+    fn drop(&mut self) {
+        info!("TcpPduSink: sent {}", self.sent);
+    }
+}
+
+impl Block for TcpPduSink {
+    fn block_name(&self) -> &str {
+        "TcpPduSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        for stream in accept(&self.listener)? {
+            stream.set_nonblocking(false)?;
+            stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+            self.clients.push(stream);
+        }
+        let Some((pdu, _tags)) = self.src.pop() else {
+            return Ok(BlockRet::Noop);
+        };
+        let frame = self.framing.encode(pdu.data());
+        self.clients.retain_mut(|c| match c.write_all(&frame) {
+            Ok(()) => true,
+            Err(e) => {
+                info!("TcpPduSink: dropping subscriber: {e}");
+                false
+            }
+        });
+        self.sent += 1;
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framing() -> Result<()> {
+        for framing in [Framing::LengthPrefixed, Framing::Kiss] {
+            let mut buf = framing.encode(&[1, 2, kiss::FEND]);
+            buf.extend(framing.encode(&[3]));
+            let last = framing.encode(&[4, 5]);
+            buf.extend(&last[..3]);
+            let got = framing.decode(&mut buf)?;
+            assert_eq!(got, vec![vec![1, 2, kiss::FEND], vec![3]], "{framing:?}");
+            buf.extend(&last[3..]);
+            assert_eq!(framing.decode(&mut buf)?, vec![vec![4, 5]], "{framing:?}");
+        }
+        let mut buf = vec![0xff, 0xff, 0xff, 0xff];
+        assert!(Framing::LengthPrefixed.decode(&mut buf).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn source() -> Result<()> {
+        let mut src = TcpPduSource::new("127.0.0.1:0", Framing::Kiss)?;
+        let mut client = TcpStream::connect(src.local_addr()?)?;
+        client.write_all(&Framing::Kiss.encode(b"hello"))?;
+        let out = src.out();
+        for _ in 0..500 {
+            src.work()?;
+            if let Some((pdu, _)) = out.pop() {
+                assert_eq!(pdu.data(), b"hello");
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("no PDU received");
+    }
+
+    #[test]
+    fn sink() -> Result<()> {
+        let src = new_nocopy_streamp();
+        let mut sink = TcpPduSink::new(src.clone(), "127.0.0.1:0", Framing::LengthPrefixed)?;
+        let mut client = TcpStream::connect(sink.local_addr()?)?;
+        client.set_read_timeout(Some(Duration::from_secs(5)))?;
+        // Wait for the connection to be accepted.
+        for _ in 0..500 {
+            sink.work()?;
+            if !sink.clients.is_empty() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        src.push(Pdu::new(vec![1, 2, 3]), &[]);
+        sink.work()?;
+        let mut buf = [0u8; 7];
+        client.read_exact(&mut buf)?;
+        assert_eq!(buf, [0, 0, 0, 3, 1, 2, 3]);
+        Ok(())
+    }
+}
@@ -0,0 +1,532 @@
+/*! Longwave time signal decoder, for DCF77 and WWVB.
+
+Both stations send one bit per second, by reducing the carrier
+amplitude at the start of each second. The length of the reduction
+encodes the bit:
+
+| Station   | 0     | 1     | Marker                          |
+|-----------|-------|-------|---------------------------------|
+| [DCF77]   | 100ms | 200ms | No reduction in second 59       |
+| [WWVB]    | 200ms | 500ms | 800ms, in seconds 0, 9, 19, …59 |
+
+Input to [TimeSignalDecode] is the carrier amplitude, e.g. from
+`ComplexToMag2` on I/Q centered on the carrier, or on the output of a
+`Hilbert` when decoding audio from an SSB/CW receiver. A low sample
+rate, like 1kHz, is plenty.
+
+Only the amplitude modulation is decoded. The phase modulation added
+to both stations in recent years is not used.
+
+```text
+  [ audio ] → [ Hilbert ] → [ ComplexToMag2 ] → [ TimeSignalDecode ]
+```
+
+See `examples/time-signal.rs`.
+
+[DCF77]: https://en.wikipedia.org/wiki/DCF77
+[WWVB]: https://en.wikipedia.org/wiki/WWVB
+*/
+use anyhow::Result;
+use log::{debug, info, trace};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp, Streamp};
+use crate::{Error, Float};
+
+/// Time signal station.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Station {
+    /// DCF77, Germany, 77.5kHz. Transmits local German time.
+    #[default]
+    Dcf77,
+
+    /// WWVB, USA, 60kHz. Transmits UTC.
+    Wwvb,
+}
+
+/// One received second.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Symbol {
+    /// Zero bit.
+    Zero,
+    /// One bit.
+    One,
+    /// Frame marker.
+    Marker,
+}
+
+/// Decoded time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TimeCode {
+    /// Station this was received from.
+    pub station: Station,
+    /// Year.
+    pub year: u32,
+    /// Month, 1-12.
+    pub month: u32,
+    /// Day of month, 1-31.
+    pub day: u32,
+    /// Hour.
+    pub hour: u32,
+    /// Minute.
+    pub minute: u32,
+    /// Daylight saving time in effect.
+    pub dst: bool,
+    /// Leap second announced.
+    pub leap_second: bool,
+}
+
+impl crate::Len for TimeCode {
+    fn len(&self) -> usize {
+        1
+    }
+}
+
+// Sum of bits weighted by BCD digits.
+fn bcd(bits: &[Symbol], weights: &[u32]) -> u32 {
+    bits.iter()
+        .zip(weights.iter())
+        .filter(|(b, _)| **b == Symbol::One)
+        .map(|(_, w)| w)
+        .sum()
+}
+
+fn even_parity(bits: &[Symbol]) -> bool {
+    bits.iter()
+        .filter(|b| **b == Symbol::One)
+        .count()
+        .is_multiple_of(2)
+}
+
+/// Decode a DCF77 minute, 59 bits starting at second 0.
+///
+/// The time is that of the minute starting at the next minute marker.
+pub fn decode_dcf77(bits: &[Symbol]) -> Option<TimeCode> {
+    if bits.len() != 59 || bits.contains(&Symbol::Marker) {
+        return None;
+    }
+    if bits[0] != Symbol::Zero || bits[20] != Symbol::One {
+        trace!("DCF77: bad fixed bits");
+        return None;
+    }
+    if !even_parity(&bits[21..29]) || !even_parity(&bits[29..36]) || !even_parity(&bits[36..59]) {
+        debug!("DCF77: parity error");
+        return None;
+    }
+    let cest = bits[17] == Symbol::One;
+    let cet = bits[18] == Symbol::One;
+    if cest == cet {
+        return None;
+    }
+    let ret = TimeCode {
+        station: Station::Dcf77,
+        minute: bcd(&bits[21..28], &[1, 2, 4, 8, 10, 20, 40]),
+        hour: bcd(&bits[29..35], &[1, 2, 4, 8, 10, 20]),
+        day: bcd(&bits[36..42], &[1, 2, 4, 8, 10, 20]),
+        month: bcd(&bits[45..50], &[1, 2, 4, 8, 10]),
+        year: 2000 + bcd(&bits[50..58], &[1, 2, 4, 8, 10, 20, 40, 80]),
+        dst: cest,
+        leap_second: bits[19] == Symbol::One,
+    };
+    valid(ret)
+}
+
+/// Decode a WWVB frame, 60 symbols starting at second 0.
+///
+/// The time is that of the start of the frame.
+pub fn decode_wwvb(bits: &[Symbol]) -> Option<TimeCode> {
+    if bits.len() != 60 {
+        return None;
+    }
+    for (n, b) in bits.iter().enumerate() {
+        let marker = n == 0 || n % 10 == 9;
+        if marker != (*b == Symbol::Marker) {
+            trace!("WWVB: bad marker at {n}");
+            return None;
+        }
+    }
+    let minute = bcd(&bits[1..9], &[40, 20, 10, 0, 8, 4, 2, 1]);
+    let hour = bcd(&bits[12..19], &[20, 10, 0, 8, 4, 2, 1]);
+    let yday = bcd(&bits[22..34], &[200, 100, 0, 80, 40, 20, 10, 0, 8, 4, 2, 1]);
+    let year = 2000 + bcd(&bits[45..54], &[80, 40, 20, 10, 0, 8, 4, 2, 1]);
+    let leap_year = bits[55] == Symbol::One;
+    if leap_year != year.is_multiple_of(4) {
+        debug!("WWVB: leap year bit mismatch");
+        return None;
+    }
+    let mut days = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    if leap_year {
+        days[1] = 29;
+    }
+    let mut day = yday;
+    let mut month = 1;
+    for d in days {
+        if day <= d {
+            break;
+        }
+        day -= d;
+        month += 1;
+    }
+    let ret = TimeCode {
+        station: Station::Wwvb,
+        minute,
+        hour,
+        day,
+        month,
+        year,
+        dst: bits[58] == Symbol::One,
+        leap_second: bits[56] == Symbol::One,
+    };
+    valid(ret)
+}
+
+fn valid(t: TimeCode) -> Option<TimeCode> {
+    if t.minute > 59 || t.hour > 23 || !(1..=31).contains(&t.day) || !(1..=12).contains(&t.month) {
+        debug!("Time signal: invalid time {t:?}");
+        return None;
+    }
+    Some(t)
+}
+
+/** Time signal decoder block.
+
+Takes the carrier amplitude, and outputs a [TimeCode] for every
+successfully decoded minute.
+*/
+pub struct TimeSignalDecode {
+    src: Streamp<Float>,
+    dst: NoCopyStreamp<TimeCode>,
+    station: Station,
+    samp_rate: Float,
+
+    // Envelope and adaptive threshold.
+    alpha: Float,
+    level: Float,
+    hi: Float,
+    lo: Float,
+    decay: Float,
+    low: bool,
+
+    // Sample counters.
+    now: u64,
+    fall: Option<u64>,
+    bits: Vec<Symbol>,
+    decoded: usize,
+}
+
+impl TimeSignalDecode {
+    /// Create new time signal decoder.
+    pub fn new(src: Streamp<Float>, samp_rate: Float, station: Station) -> Self {
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            station,
+            samp_rate,
+            // About 10ms time constant.
+            alpha: 1.0 - (-1.0 / (0.01 * samp_rate)).exp(),
+            level: 0.0,
+            hi: 0.0,
+            lo: Float::MAX,
+            // Levels forget over about 10 seconds.
+            decay: (-1.0 / (10.0 * samp_rate)).exp(),
+            low: false,
+            now: 0,
+            fall: None,
+            bits: Vec::new(),
+            decoded: 0,
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<TimeCode> {
+        self.dst.clone()
+    }
+
+    fn ms(&self, samples: u64) -> Float {
+        samples as Float * 1000.0 / self.samp_rate
+    }
+
+    fn symbol(&self, ms: Float) -> Option<Symbol> {
+        match self.station {
+            Station::Dcf77 => match ms {
+                x if x < 40.0 => None,
+                x if x < 150.0 => Some(Symbol::Zero),
+                x if x < 300.0 => Some(Symbol::One),
+                _ => None,
+            },
+            Station::Wwvb => match ms {
+                x if x < 100.0 => None,
+                x if x < 350.0 => Some(Symbol::Zero),
+                x if x < 650.0 => Some(Symbol::One),
+                x if x < 950.0 => Some(Symbol::Marker),
+                _ => None,
+            },
+        }
+    }
+
+    // Carrier dropped. For DCF77, a missing second before this marks
+    // the start of a minute.
+    fn falling(&mut self) {
+        if let Some(fall) = self.fall {
+            let gap = self.ms(self.now - fall);
+            if gap > 2500.0 {
+                debug!("Time signal: lost signal for {gap}ms");
+                self.bits.clear();
+            } else if gap > 1500.0 && self.station == Station::Dcf77 {
+                if let Some(t) = decode_dcf77(&self.bits) {
+                    self.output(t);
+                } else {
+                    debug!("DCF77: minute marker after {} bits", self.bits.len());
+                }
+                self.bits.clear();
+            }
+        }
+        self.fall = Some(self.now);
+    }
+
+    // Carrier back. The length of the reduction is the symbol.
+    fn rising(&mut self) {
+        let Some(fall) = self.fall else {
+            return;
+        };
+        let ms = self.ms(self.now - fall);
+        let Some(sym) = self.symbol(ms) else {
+            trace!("Time signal: ignoring {ms}ms pulse");
+            return;
+        };
+        trace!("Time signal: {sym:?} ({ms}ms)");
+        self.bits.push(sym);
+        if self.station == Station::Wwvb {
+            let n = self.bits.len();
+            // Two markers in a row start a new frame.
+            if n >= 2 && self.bits[n - 2] == Symbol::Marker && sym == Symbol::Marker {
+                if n == 61 {
+                    if let Some(t) = decode_wwvb(&self.bits[..60]) {
+                        self.output(t);
+                    }
+                }
+                self.bits = vec![Symbol::Marker];
+            }
+        }
+        if self.bits.len() > 61 {
+            self.bits.clear();
+        }
+    }
+
+    fn output(&mut self, t: TimeCode) {
+        info!(
+            "Time signal: {:04}-{:02}-{:02} {:02}:{:02}",
+            t.year, t.month, t.day, t.hour, t.minute
+        );
+        self.decoded += 1;
+        self.dst.push(t, &[]);
+    }
+
+    fn process_one(&mut self, s: Float) {
+        self.now += 1;
+        self.level += self.alpha * (s - self.level);
+        self.hi = (self.hi * self.decay).max(self.level);
+        self.lo = (self.lo / self.decay).min(self.level);
+        let thresh = (self.hi + self.lo) / 2.0;
+        // Hysteresis of 10% of the range.
+        let hyst = (self.hi - self.lo) * 0.1;
+        if !self.low && self.level < thresh - hyst {
+            self.low = true;
+            self.falling();
+        } else if self.low && self.level > thresh + hyst {
+            self.low = false;
+            self.rising();
+        }
+    }
+}
+
+impl Drop for TimeSignalDecode {
+    fn drop(&mut self) {
+        info!(
+            "TimeSignalDecode: decoded {} {:?} minutes",
+            self.decoded, self.station
+        );
+    }
+}
+
+impl Block for TimeSignalDecode {
+    fn block_name(&self) -> &str {
+        "TimeSignalDecode"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        for s in input.iter() {
+            self.process_one(*s);
+        }
+        let n = input.len();
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    fn set_bcd(bits: &mut [u8], value: u32, weights: &[u32]) {
+        let mut v = value;
+        // Greedy from the largest weight works for BCD.
+        let mut order: Vec<_> = weights
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| **w > 0)
+            .collect();
+        order.sort_by(|a, b| b.1.cmp(a.1));
+        for (n, w) in order {
+            if v >= *w {
+                bits[n] = 1;
+                v -= w;
+            }
+        }
+    }
+
+    fn parity(bits: &mut [u8], range: std::ops::Range<usize>, at: usize) {
+        bits[at] = bits[range].iter().sum::<u8>() % 2;
+    }
+
+    fn dcf77_minute(year: u32, month: u32, day: u32, hour: u32, minute: u32) -> Vec<u8> {
+        let mut b = vec![0u8; 59];
+        b[18] = 1;
+        b[20] = 1;
+        set_bcd(&mut b[21..28], minute, &[1, 2, 4, 8, 10, 20, 40]);
+        parity(&mut b, 21..28, 28);
+        set_bcd(&mut b[29..35], hour, &[1, 2, 4, 8, 10, 20]);
+        parity(&mut b, 29..35, 35);
+        set_bcd(&mut b[36..42], day, &[1, 2, 4, 8, 10, 20]);
+        b[42] = 1;
+        set_bcd(&mut b[45..50], month, &[1, 2, 4, 8, 10]);
+        set_bcd(&mut b[50..58], year - 2000, &[1, 2, 4, 8, 10, 20, 40, 80]);
+        parity(&mut b, 36..58, 58);
+        b
+    }
+
+    fn wwvb_frame(year: u32, yday: u32, hour: u32, minute: u32) -> Vec<u8> {
+        // 2 means marker.
+        let mut b = vec![0u8; 60];
+        for n in [0, 9, 19, 29, 39, 49, 59] {
+            b[n] = 2;
+        }
+        set_bcd(&mut b[1..9], minute, &[40, 20, 10, 0, 8, 4, 2, 1]);
+        set_bcd(&mut b[12..19], hour, &[20, 10, 0, 8, 4, 2, 1]);
+        set_bcd(
+            &mut b[22..34],
+            yday,
+            &[200, 100, 0, 80, 40, 20, 10, 0, 8, 4, 2, 1],
+        );
+        set_bcd(
+            &mut b[45..54],
+            year - 2000,
+            &[80, 40, 20, 10, 0, 8, 4, 2, 1],
+        );
+        b[49] = 2;
+        b[55] = year.is_multiple_of(4) as u8;
+        b
+    }
+
+    // Generate amplitude at 1kHz, with the carrier reduced for the
+    // given number of ms each second. None means no reduction.
+    fn envelope(reductions: &[Option<usize>]) -> Vec<Float> {
+        let mut x: u32 = 1;
+        reductions
+            .iter()
+            .flat_map(|r| {
+                (0..1000).map(move |ms| match r {
+                    Some(len) if ms < *len => 0.15,
+                    _ => 1.0,
+                })
+            })
+            .map(|s| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                s + ((x >> 16) % 100) as Float / 1000.0
+            })
+            .collect()
+    }
+
+    fn run(env: &[Float], station: Station) -> Vec<TimeCode> {
+        // Minutes of signal don't fit in a stream, so feed it directly.
+        let mut dec = TimeSignalDecode::new(streamp_from_slice(&[0.0]), 1000.0, station);
+        for s in env {
+            dec.process_one(*s);
+        }
+        let out = dec.out();
+        let mut ret = Vec::new();
+        while let Some((t, _)) = out.pop() {
+            ret.push(t);
+        }
+        ret
+    }
+
+    #[test]
+    fn dcf77() {
+        let mut red = vec![Some(100); 10];
+        red.push(None);
+        for minute in [14, 15] {
+            let bits = dcf77_minute(2024, 7, 21, 9, minute);
+            red.extend(bits.iter().map(|b| Some(100 + 100 * *b as usize)));
+            red.push(None);
+        }
+        red.push(Some(100));
+        let got = run(&envelope(&red), Station::Dcf77);
+        assert_eq!(got.len(), 2);
+        assert_eq!(
+            got[1],
+            TimeCode {
+                station: Station::Dcf77,
+                year: 2024,
+                month: 7,
+                day: 21,
+                hour: 9,
+                minute: 15,
+                dst: false,
+                leap_second: false,
+            }
+        );
+    }
+
+    #[test]
+    fn wwvb() {
+        // Second 59 of the previous frame, to sync on.
+        let mut red = vec![Some(200); 5];
+        red.push(Some(800));
+        for minute in [30, 31] {
+            // 2024-03-01 is day 61 in a leap year.
+            let bits = wwvb_frame(2024, 61, 18, minute);
+            red.extend(bits.iter().map(|b| Some([200, 500, 800][*b as usize])));
+        }
+        red.push(Some(800));
+        let got = run(&envelope(&red), Station::Wwvb);
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].minute, 30);
+        assert_eq!(
+            (
+                got[1].year,
+                got[1].month,
+                got[1].day,
+                got[1].hour,
+                got[1].minute
+            ),
+            (2024, 3, 1, 18, 31)
+        );
+    }
+
+    #[test]
+    fn dcf77_parity() {
+        let mut bits: Vec<Symbol> = dcf77_minute(2024, 1, 2, 3, 4)
+            .iter()
+            .map(|b| if *b == 1 { Symbol::One } else { Symbol::Zero })
+            .collect();
+        assert!(decode_dcf77(&bits).is_some());
+        bits[22] = Symbol::One;
+        assert!(decode_dcf77(&bits).is_none());
+    }
+}
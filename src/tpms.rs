@@ -0,0 +1,426 @@
+/*! Tire pressure monitoring system (TPMS) sensor decoders.
+
+TPMS sensors sit inside car tires, and send pressure and temperature
+a few times a minute while driving, on 315MHz (US) or 433.92MHz
+(Europe). Most are FSK, some OOK, and the bits are usually Manchester
+or differential Manchester coded.
+
+These are [Device]s for the [ism](crate::ism) framework, and so are
+part of [crate::ism::devices]. The pulse trains are sliced as NRZ chips,
+and each decoder searches for its preamble before decoding the
+Manchester coding.
+
+All messages have "type" set to "TPMS", and "id", "pressure_kPa", and
+"temperature_C" fields.
+
+Protocol details are from [rtl_433].
+
+[rtl_433]: https://github.com/merbanan/rtl_433
+*/
+use crate::ism::{crc8, pack_bits, BitRows, Coding, Device, IsmMessage, Modulation, Slicing};
+
+// Most FSK sensors use about 19.2k chips per second.
+const FSK_CHIP_US: crate::Float = 52.0;
+
+fn fsk_slicing() -> Slicing {
+    Slicing {
+        coding: Coding::Nrz,
+        short_us: FSK_CHIP_US,
+        long_us: FSK_CHIP_US,
+        gap_limit_us: 10.0 * FSK_CHIP_US,
+    }
+}
+
+fn round1(x: f64) -> f64 {
+    (x * 10.0).round() / 10.0
+}
+
+fn message(model: &str, id: String, pressure_kpa: f64, temperature_c: f64) -> IsmMessage {
+    IsmMessage::new(model)
+        .with("type", "TPMS")
+        .with("id", id)
+        .with("pressure_kPa", round1(pressure_kpa))
+        .with("temperature_C", temperature_c)
+}
+
+// Find preamble, and decode `bytes` bytes after it, in any row.
+fn find<'a>(
+    bits: &'a BitRows,
+    preamble: &[u8],
+    preamble_bits: usize,
+    bytes: usize,
+    differential: bool,
+) -> impl Iterator<Item = Vec<u8>> + 'a {
+    let preamble = preamble.to_vec();
+    (0..bits.rows.len()).filter_map(move |r| {
+        let start = bits.search(r, 0, &preamble, preamble_bits)?;
+        let data = if differential {
+            bits.differential_manchester(r, start)
+        } else {
+            bits.manchester(r, start)
+        };
+        (data.len() >= bytes * 8).then(|| pack_bits(&data[..bytes * 8]))
+    })
+}
+
+/** Renault TPMS.
+
+FSK, Manchester, 9 bytes after the preamble:
+
+```text
+  FFFFFFPP PPPPPPPP TTTTTTTT IIIIIIII IIIIIIII IIIIIIII ???????? ???????? CCCCCCCC
+```
+
+* F: Flags.
+* P: Pressure in 0.75kPa.
+* T: Temperature, offset 30C.
+* I: ID, little endian.
+* C: CRC-8, polynomial 7.
+*/
+pub struct Renault {}
+
+impl Device for Renault {
+    fn name(&self) -> &str {
+        "Renault"
+    }
+    fn modulation(&self) -> Modulation {
+        Modulation::Fsk
+    }
+    fn slicing(&self) -> Slicing {
+        fsk_slicing()
+    }
+    fn decode(&self, bits: &BitRows) -> Option<IsmMessage> {
+        find(bits, &[0xaa, 0xa9], 16, 9, false).find_map(|b| {
+            if crc8(&b, 0x07, 0) != 0 {
+                return None;
+            }
+            let pressure = (((b[0] as u16 & 3) << 8) | b[1] as u16) as f64 * 0.75;
+            let id = u32::from_le_bytes([b[3], b[4], b[5], 0]);
+            Some(
+                message(
+                    self.name(),
+                    format!("{id:06x}"),
+                    pressure,
+                    b[2] as f64 - 30.0,
+                )
+                .with("flags", b[0] >> 2),
+            )
+        })
+    }
+}
+
+/** Citroen / Peugeot TPMS.
+
+FSK, Manchester, 10 bytes after the preamble:
+
+```text
+  SSSSSSSS IIIIIIII IIIIIIII IIIIIIII IIIIIIII FFFFFFFF RRRRRRRR PPPPPPPP TTTTTTTT BBBBBBBB XXXXXXXX
+```
+
+* S: State.
+* I: ID.
+* F: Flags.
+* R: Repeat counter.
+* P: Pressure in 1.364kPa.
+* T: Temperature, offset 50C.
+* B: Battery.
+* X: XOR of all bytes after the state is zero.
+*/
+pub struct Citroen {}
+
+impl Device for Citroen {
+    fn name(&self) -> &str {
+        "Citroen"
+    }
+    fn modulation(&self) -> Modulation {
+        Modulation::Fsk
+    }
+    fn slicing(&self) -> Slicing {
+        fsk_slicing()
+    }
+    fn decode(&self, bits: &BitRows) -> Option<IsmMessage> {
+        find(bits, &[0x55, 0x56], 16, 11, false).find_map(|b| {
+            let id = u32::from_be_bytes([b[1], b[2], b[3], b[4]]);
+            if id == 0 || b[1..].iter().fold(0, |a, x| a ^ x) != 0 {
+                return None;
+            }
+            Some(
+                message(
+                    self.name(),
+                    format!("{id:08x}"),
+                    b[7] as f64 * 1.364,
+                    b[8] as f64 - 50.0,
+                )
+                .with("state", b[0])
+                .with("flags", b[5])
+                .with("repeat", b[6])
+                .with("battery", b[9]),
+            )
+        })
+    }
+}
+
+/** Toyota TPMS, as made by Pacific Industries.
+
+FSK, differential Manchester, 9 bytes after the preamble:
+
+```text
+  IIIIIIII IIIIIIII IIIIIIII IIIIIIII PPPPPPPP TTTTTTTT SSSSSSSS pppppppp CCCCCCCC
+```
+
+* I: ID.
+* P: Pressure in 0.25PSI, offset 7PSI.
+* T: Temperature, offset 40C.
+* S: Status.
+* p: Pressure inverted.
+* C: CRC-8, polynomial 7, initial value 0x80.
+*/
+pub struct Toyota {}
+
+impl Device for Toyota {
+    fn name(&self) -> &str {
+        "Toyota"
+    }
+    fn modulation(&self) -> Modulation {
+        Modulation::Fsk
+    }
+    fn slicing(&self) -> Slicing {
+        fsk_slicing()
+    }
+    fn decode(&self, bits: &BitRows) -> Option<IsmMessage> {
+        find(bits, &[0xa9, 0xe0], 12, 9, true).find_map(|b| {
+            if crc8(&b[..8], 0x07, 0x80) != b[8] || b[7] != !b[4] {
+                return None;
+            }
+            let psi = b[4] as f64 * 0.25 - 7.0;
+            let id = u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+            Some(
+                message(
+                    self.name(),
+                    format!("{id:08x}"),
+                    psi * 6.894_757,
+                    b[5] as f64 - 40.0,
+                )
+                .with("status", b[6]),
+            )
+        })
+    }
+}
+
+/** Schrader TPMS, as used in many US cars.
+
+OOK, Manchester, with 120us chips. 8 bytes after the preamble:
+
+```text
+  FFFFFFFF IIIIIIII IIIIIIII IIIIIIII PPPPPPPP TTTTTTTT SSSSSSSS CCCCCCCC
+```
+
+* F: Flags.
+* I: ID.
+* P: Pressure in 2.5kPa.
+* T: Temperature, offset 50C.
+* S: Status.
+* C: Sum of the other bytes.
+*/
+pub struct Schrader {}
+
+impl Device for Schrader {
+    fn name(&self) -> &str {
+        "Schrader"
+    }
+    fn slicing(&self) -> Slicing {
+        Slicing {
+            coding: Coding::Nrz,
+            short_us: 120.0,
+            long_us: 120.0,
+            gap_limit_us: 3000.0,
+        }
+    }
+    fn decode(&self, bits: &BitRows) -> Option<IsmMessage> {
+        find(bits, &[0xff, 0xfe], 16, 8, false).find_map(|b| {
+            let sum = b[..7].iter().fold(0u8, |a, x| a.wrapping_add(*x));
+            if sum != b[7] {
+                return None;
+            }
+            let id = u32::from_be_bytes([0, b[1], b[2], b[3]]);
+            Some(
+                message(
+                    self.name(),
+                    format!("{id:06x}"),
+                    b[4] as f64 * 2.5,
+                    b[5] as f64 - 50.0,
+                )
+                .with("flags", b[0])
+                .with("status", b[6]),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ism::{slice, IsmDecode, Pulse, PulseSlicer, PulseTrain};
+    use crate::stream::{new_nocopy_streamp, new_streamp};
+    use crate::{Complex, Float};
+    use anyhow::Result;
+
+    fn bits(bytes: &[u8], n: usize) -> Vec<bool> {
+        (0..n)
+            .map(|i| (bytes[i / 8] >> (7 - i % 8)) & 1 == 1)
+            .collect()
+    }
+
+    fn manchester(data: &[u8]) -> Vec<bool> {
+        bits(data, data.len() * 8)
+            .into_iter()
+            .flat_map(|b| [!b, b])
+            .collect()
+    }
+
+    fn differential_manchester(mut prev: bool, data: &[u8]) -> Vec<bool> {
+        bits(data, data.len() * 8)
+            .into_iter()
+            .flat_map(|b| {
+                let a = if b { prev } else { !prev };
+                prev = !a;
+                [a, !a]
+            })
+            .collect()
+    }
+
+    // Preamble chips, then coded data, as a pulse train.
+    fn train(modulation: Modulation, chip_us: Float, chips: &[bool]) -> PulseTrain {
+        let mut pulses: Vec<Pulse> = Vec::new();
+        let mut runs = Vec::new();
+        for c in chips {
+            match runs.last_mut() {
+                Some((v, n)) if v == c => *n += 1,
+                _ => runs.push((*c, 1)),
+            }
+        }
+        assert!(runs[0].0, "must start with a pulse");
+        for (v, n) in runs {
+            let w = n as Float * chip_us;
+            if v {
+                pulses.push(Pulse {
+                    pulse_us: w,
+                    gap_us: 0.0,
+                });
+            } else {
+                pulses.last_mut().unwrap().gap_us = w;
+            }
+        }
+        pulses.last_mut().unwrap().gap_us = 20000.0;
+        PulseTrain { modulation, pulses }
+    }
+
+    fn with_preamble(preamble: &[u8], n: usize, data: Vec<bool>) -> Vec<bool> {
+        let mut chips = bits(&[0xaa, 0xaa], 16);
+        chips.extend(bits(preamble, n));
+        chips.extend(data);
+        chips
+    }
+
+    // Run through all devices, to also check for false positives.
+    fn decode_all(train: PulseTrain) -> Result<Vec<IsmMessage>> {
+        let src = new_nocopy_streamp();
+        src.push(train, &[]);
+        let mut dec = IsmDecode::new(src);
+        use crate::block::Block;
+        dec.work()?;
+        let out = dec.out();
+        let mut ret = Vec::new();
+        while let Some((m, _)) = out.pop() {
+            ret.push(m);
+        }
+        Ok(ret)
+    }
+
+    #[test]
+    fn renault() -> Result<()> {
+        let mut b = vec![0x15, 0x2c, 0x46, 0x56, 0x34, 0x12, 0, 0, 0];
+        b[8] = crc8(&b[..8], 0x07, 0);
+        let chips = with_preamble(&[0xaa, 0xa9], 16, manchester(&b));
+        let got = decode_all(train(Modulation::Fsk, FSK_CHIP_US, &chips))?;
+        assert_eq!(got.len(), 1);
+        assert_eq!(
+            got[0].to_json(),
+            r#"{"model":"Renault","flags":5,"id":"123456","pressure_kPa":225.0,"temperature_C":40.0,"type":"TPMS"}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn citroen() -> Result<()> {
+        let mut b = vec![
+            0x03, 0x8a, 0x2b, 0x3c, 0x4d, 0x01, 0x02, 0xa5, 0x46, 0x60, 0,
+        ];
+        b[10] = b[1..10].iter().fold(0, |a, x| a ^ x);
+        let chips = with_preamble(&[0x55, 0x56], 16, manchester(&b));
+        let got = decode_all(train(Modulation::Fsk, FSK_CHIP_US, &chips))?;
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].model, "Citroen");
+        assert_eq!(got[0].get("id").unwrap(), "8a2b3c4d");
+        assert_eq!(got[0].get("pressure_kPa").unwrap(), 225.1);
+        assert_eq!(got[0].get("temperature_C").unwrap(), 20.0);
+        Ok(())
+    }
+
+    #[test]
+    fn schrader() -> Result<()> {
+        let mut b = vec![0x01, 0xab, 0xcd, 0xef, 0x5a, 0x41, 0x00, 0];
+        b[7] = b[..7].iter().fold(0u8, |a, x| a.wrapping_add(*x));
+        let mut chips = bits(&[0xff, 0xfe], 16);
+        chips.extend(manchester(&b));
+        let got = decode_all(train(Modulation::Ook, 120.0, &chips))?;
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].model, "Schrader");
+        assert_eq!(got[0].get("id").unwrap(), "abcdef");
+        assert_eq!(got[0].get("pressure_kPa").unwrap(), 225.0);
+        assert_eq!(got[0].get("temperature_C").unwrap(), 15.0);
+        Ok(())
+    }
+
+    #[test]
+    fn toyota_from_iq() -> Result<()> {
+        let p = 158; // 32.5PSI
+        let mut b = vec![0xde, 0xad, 0xbe, 0xef, p, 65, 0x80, !p, 0];
+        b[8] = crc8(&b[..8], 0x07, 0x80);
+        let preamble = bits(&[0xa9, 0xe0], 12);
+        let data = differential_manchester(*preamble.last().unwrap(), &b);
+        let chips = with_preamble(&[0xa9, 0xe0], 12, data);
+
+        // Check the bit level first.
+        let t = train(Modulation::Fsk, FSK_CHIP_US, &chips);
+        let m = Toyota {}.decode(&slice(&t, &Toyota {}.slicing())).unwrap();
+        assert_eq!(m.get("id").unwrap(), "deadbeef");
+        assert_eq!(m.get("pressure_kPa").unwrap(), 224.1);
+        assert_eq!(m.get("temperature_C").unwrap(), 25.0);
+
+        // Then FSK modulate, at 250ksps and +-40kHz deviation.
+        let samp_rate = 250_000.0;
+        let spc = (samp_rate * FSK_CHIP_US / 1e6) as usize;
+        let mut samples = vec![Complex::default(); 500];
+        let mut phase: Float = 0.0;
+        for c in &chips {
+            let dev: Float = if *c { 40000.0 } else { -40000.0 };
+            for _ in 0..spc {
+                phase += 2.0 * std::f64::consts::PI as Float * dev / samp_rate;
+                samples.push(Complex::from_polar(1.0, phase));
+            }
+        }
+        samples.extend(vec![Complex::default(); 6000]);
+        // Doesn't fit in a stream, so feed it directly.
+        let mut slicer = PulseSlicer::new(new_streamp(), samp_rate, Modulation::Fsk);
+        let trains: Vec<PulseTrain> = samples
+            .into_iter()
+            .filter_map(|s| slicer.process_one(s))
+            .collect();
+        assert_eq!(trains.len(), 1);
+        let got = decode_all(trains.into_iter().next().unwrap())?;
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0], m);
+        Ok(())
+    }
+}
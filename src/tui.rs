@@ -0,0 +1,320 @@
+/*! Text mode spectrum and waterfall display.
+
+Only available with the `tui` feature.
+
+For headless receivers, like a Raspberry Pi accessed over SSH, [run]
+shows the output of a [SpectrumSink][crate::spectrum_sink::SpectrumSink]
+and/or a [WaterfallSink][crate::waterfall_sink::WaterfallSink] in the
+terminal. The spectrum is drawn with braille characters, and the
+waterfall with half blocks, so each line of text is two rows.
+
+The terminal needs to support 24 bit color.
+
+Keys:
+
+| Key       | Action                         |
+|-----------|--------------------------------|
+| `q`, Esc  | Quit                           |
+| `p`       | Toggle peak hold               |
+| `r`       | Reset peak hold                |
+| `a`       | Cycle averaging                |
+| `f`       | Cycle waterfall FFT size       |
+| `+`, `-`  | Raise/lower the top of the dB range    |
+| `]`, `[`  | Raise/lower the bottom of the dB range |
+*/
+use std::collections::VecDeque;
+use std::io::Stdout;
+use std::time::Duration;
+
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use crossterm::ExecutableCommand;
+use ratatui::backend::CrosstermBackend;
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::symbols::Marker;
+use ratatui::text::Span;
+use ratatui::widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph, Widget};
+use ratatui::{Frame, Terminal};
+
+use crate::spectrum_sink::{format_freq, SpectrumHandle};
+use crate::waterfall_sink::{colormap, format_time, Row, WaterfallHandle};
+use crate::Float;
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+const AVERAGING: [Float; 6] = [1.0, 0.5, 0.2, 0.1, 0.05, 0.01];
+const FFT_SIZES: [usize; 7] = [256, 512, 1024, 2048, 4096, 8192, 16384];
+
+/// Reduce `v` to `width` values, keeping the max of the values that
+/// end up in each, so narrow peaks stay visible.
+pub fn resample(v: &[Float], width: usize) -> Vec<Float> {
+    if v.is_empty() {
+        return vec![Float::NEG_INFINITY; width];
+    }
+    (0..width)
+        .map(|x| {
+            let a = x * v.len() / width;
+            let b = ((x + 1) * v.len() / width).max(a + 1);
+            v[a..b.min(v.len())]
+                .iter()
+                .cloned()
+                .fold(Float::NEG_INFINITY, Float::max)
+        })
+        .collect()
+}
+
+// Restores the terminal, even on error.
+struct TermGuard;
+
+impl TermGuard {
+    fn new() -> Result<Self> {
+        enable_raw_mode()?;
+        std::io::stdout().execute(EnterAlternateScreen)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for TermGuard {
+    fn drop(&mut self) {
+        let _ = std::io::stdout().execute(LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
+
+struct Waterfall<'a> {
+    rows: &'a VecDeque<Row>,
+    min_db: Float,
+    max_db: Float,
+}
+
+impl Widget for Waterfall<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let color = |db: Float| {
+            let [r, g, b] = colormap((db - self.min_db) / (self.max_db - self.min_db));
+            Color::Rgb(r, g, b)
+        };
+        let width = area.width as usize;
+        let mut rows = self.rows.iter().map(|r| resample(&r.db, width));
+        for y in area.top()..area.bottom() {
+            let top = rows.next();
+            let bottom = rows.next();
+            for x in 0..width {
+                let fg = top.as_ref().map(|r| color(r[x])).unwrap_or(Color::Black);
+                let bg = bottom.as_ref().map(|r| color(r[x])).unwrap_or(Color::Black);
+                buf.get_mut(area.left() + x as u16, y)
+                    .set_char('▀')
+                    .set_fg(fg)
+                    .set_bg(bg);
+            }
+        }
+    }
+}
+
+struct App {
+    spectrum: Option<SpectrumHandle>,
+    waterfall: Option<WaterfallHandle>,
+    rows: VecDeque<Row>,
+    next: u64,
+    min_db: Float,
+    max_db: Float,
+}
+
+impl App {
+    // Fetch new waterfall rows, newest first.
+    fn fetch(&mut self, keep: usize) {
+        let Some(w) = &self.waterfall else {
+            return;
+        };
+        let (rows, next) = w.rows_since(self.next);
+        self.next = next;
+        for row in rows {
+            if self.rows.front().map(|r| r.db.len()) != Some(row.db.len()) {
+                // FFT size changed.
+                self.rows.clear();
+            }
+            self.rows.push_front(row);
+        }
+        self.rows.truncate(keep);
+    }
+
+    // Handle a key. Return false to quit.
+    fn key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('p') => {
+                if let Some(s) = &self.spectrum {
+                    s.set_peak_hold(!s.peak_hold());
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Some(s) = &self.spectrum {
+                    s.reset_peak();
+                }
+            }
+            KeyCode::Char('a') => {
+                if let Some(s) = &self.spectrum {
+                    let cur = s.averaging();
+                    let i = AVERAGING.iter().position(|a| *a < cur - 1e-6).unwrap_or(0);
+                    s.set_averaging(AVERAGING[i]);
+                }
+            }
+            KeyCode::Char('f') => {
+                if let Some(w) = &self.waterfall {
+                    let cur = w.fft_size();
+                    let i = FFT_SIZES.iter().position(|n| *n > cur).unwrap_or(0);
+                    w.set_fft_size(FFT_SIZES[i]);
+                }
+            }
+            KeyCode::Char('+') => self.max_db += 5.0,
+            KeyCode::Char('-') => self.max_db = (self.max_db - 5.0).max(self.min_db + 5.0),
+            KeyCode::Char(']') => self.min_db = (self.min_db + 5.0).min(self.max_db - 5.0),
+            KeyCode::Char('[') => self.min_db -= 5.0,
+            _ => {}
+        }
+        true
+    }
+
+    fn draw_spectrum(&self, f: &mut Frame, area: Rect, h: &SpectrumHandle) {
+        let s = h.spectrum();
+        let points = |v: &[Float]| -> Vec<(f64, f64)> {
+            v.iter()
+                .enumerate()
+                .map(|(bin, db)| (s.bin_freq(bin) as f64, db.max(self.min_db) as f64))
+                .collect()
+        };
+        let (avg, peak) = (points(&s.avg), points(&s.peak));
+        let mut datasets = Vec::new();
+        if !peak.is_empty() {
+            datasets.push(
+                Dataset::default()
+                    .marker(Marker::Braille)
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(Color::Yellow))
+                    .data(&peak),
+            );
+        }
+        datasets.push(
+            Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Green))
+                .data(&avg),
+        );
+        let (lo, hi) = (s.min_freq(), s.max_freq());
+        let title = format!(
+            " Spectrum  peak hold: {}  averaging: {} ",
+            if h.peak_hold() { "on" } else { "off" },
+            h.averaging()
+        );
+        let chart = Chart::new(datasets)
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .x_axis(Axis::default().bounds([lo as f64, hi as f64]).labels(vec![
+                Span::raw(format_freq(lo)),
+                Span::raw(format_freq((lo + hi) / 2.0)),
+                Span::raw(format_freq(hi)),
+            ]))
+            .y_axis(
+                Axis::default()
+                    .bounds([self.min_db as f64, self.max_db as f64])
+                    .labels(vec![
+                        Span::raw(format!("{}", self.min_db)),
+                        Span::raw(format!("{}", self.max_db)),
+                    ]),
+            );
+        f.render_widget(chart, area);
+    }
+
+    fn draw_waterfall(&self, f: &mut Frame, area: Rect, h: &WaterfallHandle) {
+        let newest = self.rows.front().map(|r| format_time(r.time));
+        let title = format!(
+            " Waterfall  FFT size: {}  {} ",
+            h.fft_size(),
+            newest.unwrap_or_default()
+        );
+        let block = Block::default().title(title).borders(Borders::ALL);
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+        f.render_widget(
+            Waterfall {
+                rows: &self.rows,
+                min_db: self.min_db,
+                max_db: self.max_db,
+            },
+            inner,
+        );
+    }
+
+    fn draw(&self, f: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(f.size());
+        let parts = match (&self.spectrum, &self.waterfall) {
+            (Some(_), Some(_)) => Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .split(chunks[0])
+                .to_vec(),
+            _ => vec![chunks[0]],
+        };
+        let mut parts = parts.into_iter();
+        if let Some(s) = &self.spectrum {
+            self.draw_spectrum(f, parts.next().unwrap(), s);
+        }
+        if let Some(w) = &self.waterfall {
+            self.draw_waterfall(f, parts.next().unwrap(), w);
+        }
+        let help = format!(
+            "q quit  p peak  r reset  a avg  f fft  +/- max {} dB  ]/[ min {} dB",
+            self.max_db, self.min_db
+        );
+        f.render_widget(Paragraph::new(help), chunks[1]);
+    }
+}
+
+/// Show spectrum and/or waterfall in the terminal, and run until the
+/// user quits.
+pub fn run(spectrum: Option<SpectrumHandle>, waterfall: Option<WaterfallHandle>) -> Result<()> {
+    let _guard = TermGuard::new()?;
+    let mut term: Terminal<CrosstermBackend<Stdout>> =
+        Terminal::new(CrosstermBackend::new(std::io::stdout()))?;
+    let mut app = App {
+        spectrum,
+        waterfall,
+        rows: VecDeque::new(),
+        next: 0,
+        min_db: -120.0,
+        max_db: 0.0,
+    };
+    loop {
+        // Two rows per line.
+        let keep = term.size()?.height as usize * 2;
+        app.fetch(keep);
+        term.draw(|f| app.draw(f))?;
+        if event::poll(REDRAW_INTERVAL)? {
+            if let Event::Key(k) = event::read()? {
+                if k.kind == KeyEventKind::Press && !app.key(k.code) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resampling() {
+        let v = [1.0, 5.0, 2.0, 3.0, 0.0, 4.0];
+        assert_eq!(resample(&v, 3), vec![5.0, 3.0, 4.0]);
+        assert_eq!(resample(&v, 2), vec![5.0, 4.0]);
+        assert_eq!(resample(&v[..2], 4), vec![1.0, 1.0, 5.0, 5.0]);
+        assert_eq!(resample(&[], 1), vec![Float::NEG_INFINITY]);
+    }
+}
@@ -0,0 +1,135 @@
+/*! UDP sink.
+
+Sends each PDU as a UDP datagram, e.g. to feed decoded packets to
+aggregation software on another machine in real time.
+
+```text
+HdlcDeframer -> ToPdu -> UdpSink
+```
+*/
+use std::net::{ToSocketAddrs, UdpSocket};
+
+use anyhow::Result;
+use log::{debug, info, warn};
+
+use crate::block::{Block, BlockRet};
+use crate::pdu::Pdu;
+use crate::stream::NoCopyStreamp;
+use crate::{kiss, Error};
+
+/// Datagram format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// PDU data as is.
+    #[default]
+    Raw,
+
+    /// KISS data frame, on the given port.
+    Kiss(u8),
+
+    /// JSON object, as from [Pdu::to_json].
+    #[cfg(feature = "json")]
+    Json,
+}
+
+impl Format {
+    /// Encode a PDU into a datagram.
+    pub fn encode(&self, pdu: &Pdu) -> Vec<u8> {
+        match self {
+            Format::Raw => pdu.data().to_vec(),
+            Format::Kiss(port) => kiss::encode(*port, pdu.data()),
+            #[cfg(feature = "json")]
+            Format::Json => pdu.to_json().into_bytes(),
+        }
+    }
+}
+
+/** UDP sink block.
+
+Send errors, such as nobody listening on the other end, are logged and
+the PDU dropped, instead of stopping the graph.
+*/
+pub struct UdpSink {
+    src: NoCopyStreamp<Pdu>,
+    socket: UdpSocket,
+    format: Format,
+    sent: usize,
+    errors: usize,
+}
+
+impl UdpSink {
+    /// Create new UDP sink, sending to `addr`, e.g. `"10.0.0.1:8001"`.
+    pub fn new<A: ToSocketAddrs>(src: NoCopyStreamp<Pdu>, addr: A, format: Format) -> Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or(Error::new("UdpSink: address did not resolve"))?;
+        let bind = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(bind)?;
+        socket.connect(addr)?;
+        debug!("UdpSink: sending to {addr}");
+        Ok(Self {
+            src,
+            socket,
+            format,
+            sent: 0,
+            errors: 0,
+        })
+    }
+}
+
+impl Drop for UdpSink {
+    fn drop(&mut self) {
+        info!("UdpSink: sent {}, errors {}", self.sent, self.errors);
+    }
+}
+
+impl Block for UdpSink {
+    fn block_name(&self) -> &str {
+        "UdpSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let Some((pdu, _tags)) = self.src.pop() else {
+            return Ok(BlockRet::Noop);
+        };
+        match self.socket.send(&self.format.encode(&pdu)) {
+            Ok(_) => self.sent += 1,
+            Err(e) => {
+                warn!("UdpSink: failed to send: {e}");
+                self.errors += 1;
+            }
+        }
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::new_nocopy_streamp;
+
+    #[test]
+    fn send() -> Result<()> {
+        let rx = UdpSocket::bind("127.0.0.1:0")?;
+        rx.set_read_timeout(Some(std::time::Duration::from_secs(5)))?;
+        let mut buf = [0u8; 100];
+        for (format, want) in [
+            (Format::Raw, vec![1, 0xc0]),
+            (Format::Kiss(0), vec![0xc0, 0, 1, 0xdb, 0xdc, 0xc0]),
+            #[cfg(feature = "json")]
+            (Format::Json, br#"{"data":"01c0"}"#.to_vec()),
+        ] {
+            let src = new_nocopy_streamp();
+            src.push(Pdu::new(vec![1, 0xc0]), &[]);
+            let mut sink = UdpSink::new(src, rx.local_addr()?, format)?;
+            sink.work()?;
+            let n = rx.recv(&mut buf)?;
+            assert_eq!(&buf[..n], &want, "{format:?}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+//! Collect a stream into memory.
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{Streamp, Tag};
+use crate::Error;
+
+/// Handle for reading what a [VectorSink] has collected, from outside
+/// the graph.
+#[derive(Clone)]
+pub struct VectorSinkHandle<T> {
+    h: Arc<Mutex<(Vec<T>, Vec<Tag>)>>,
+}
+
+impl<T: Clone> VectorSinkHandle<T> {
+    /// Get a copy of the samples collected so far.
+    pub fn data(&self) -> Vec<T> {
+        self.h.lock().unwrap().0.clone()
+    }
+
+    /// Get a copy of the tags collected so far, with positions
+    /// relative to the start of the stream.
+    pub fn tags(&self) -> Vec<Tag> {
+        self.h.lock().unwrap().1.clone()
+    }
+}
+
+/** Collect all samples of a stream into memory.
+
+Mostly useful for tests, as the counterpart of
+[VectorSource](crate::vector_source::VectorSource).
+
+```
+use rustradio::graph::Graph;
+use rustradio::blocks::{VectorSink, VectorSource};
+
+let src = VectorSource::new(vec![1.0, 2.0]);
+let sink = VectorSink::new(src.out());
+let h = sink.handle();
+let mut g = Graph::new();
+g.add(Box::new(src));
+g.add(Box::new(sink));
+g.run()?;
+assert_eq!(h.data(), vec![1.0, 2.0]);
+# Ok::<(), anyhow::Error>(())
+```
+*/
+pub struct VectorSink<T: Copy> {
+    src: Streamp<T>,
+    handle: VectorSinkHandle<T>,
+}
+
+impl<T: Copy> VectorSink<T> {
+    /// Create new VectorSink block.
+    pub fn new(src: Streamp<T>) -> Self {
+        Self {
+            src,
+            handle: VectorSinkHandle {
+                h: Arc::new(Mutex::new((Vec::new(), Vec::new()))),
+            },
+        }
+    }
+
+    /// Get a handle to the collected data.
+    pub fn handle(&self) -> VectorSinkHandle<T> {
+        self.handle.clone()
+    }
+}
+
+impl<T: Copy> Block for VectorSink<T> {
+    fn block_name(&self) -> &str {
+        "VectorSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (i, tags) = self.src.read_buf()?;
+        let n = i.len();
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        {
+            let mut h = self.handle.h.lock().unwrap();
+            let (data, all_tags) = &mut *h;
+            let pos = data.len();
+            all_tags.extend(
+                tags.into_iter()
+                    .map(|t| Tag::new(t.pos() + pos, t.key().into(), t.val().clone())),
+            );
+            data.extend_from_slice(i.slice());
+        }
+        i.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::TagValue;
+    use crate::vector_source::VectorSource;
+
+    #[test]
+    fn collect() -> Result<()> {
+        let mut src = VectorSource::new(vec![1u8, 2, 3]);
+        let mut sink = VectorSink::new(src.out());
+        let h = sink.handle();
+        src.work()?;
+        sink.work()?;
+        assert!(matches!(sink.work()?, BlockRet::Noop));
+        assert_eq!(h.data(), vec![1, 2, 3]);
+        assert!(h.tags().contains(&Tag::new(
+            0,
+            "VectorSource::first".into(),
+            TagValue::Bool(true)
+        )));
+        Ok(())
+    }
+}
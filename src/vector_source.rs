@@ -1,4 +1,28 @@
-//! Generate values from a fixed vector.
+/*! Generate values from a fixed vector.
+
+Together with [VectorSink](crate::vector_sink::VectorSink), this lets
+block tests be written as graph runs:
+
+```
+use rustradio::blocks::{AddConst, VectorSink, VectorSource};
+use rustradio::graph::Graph;
+let src = VectorSource::builder(vec![1.0, 2.0]).repeat(2).build();
+let add = AddConst::new(src.out(), 10.0);
+let sink = VectorSink::new(add.out());
+let h = sink.handle();
+let mut g = Graph::new();
+g.add(Box::new(src));
+g.add(Box::new(add));
+g.add(Box::new(sink));
+g.run()?;
+assert_eq!(h.data(), vec![11.0, 12.0, 11.0, 12.0]);
+# Ok::<(), anyhow::Error>(())
+```
+
+Each repetition starts with the tags `VectorSource::start` and
+`VectorSource::repeat` (the repetition number), and the first also
+with `VectorSource::first`.
+*/
 use anyhow::Result;
 
 use crate::block::{Block, BlockRet};
@@ -68,6 +92,11 @@ impl<T: Copy> VectorSource<T> {
         }
     }
 
+    /// Create a builder.
+    pub fn builder(data: Vec<T>) -> VectorSourceBuilder<T> {
+        VectorSourceBuilder::new(data)
+    }
+
     /// Set repeat status.
     pub fn set_repeat(&mut self, r: Repeat) {
         self.repeat = r;
@@ -92,6 +121,10 @@ where
                 return Ok(BlockRet::EOF);
             }
         }
+        if self.data.is_empty() {
+            // Nothing to repeat.
+            return Ok(BlockRet::EOF);
+        }
         let mut tags = if self.pos == 0 {
             vec![
                 Tag::new(0, "VectorSource::start".to_string(), TagValue::Bool(true)),
@@ -113,6 +146,9 @@ where
         }
         let mut os = self.dst.write_buf()?;
         let n = std::cmp::min(os.len(), self.data.len() - self.pos);
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
         os.fill_from_slice(&self.data[self.pos..(self.pos + n)]);
         os.produce(n, &tags);
 
@@ -124,3 +160,73 @@ where
         Ok(BlockRet::Ok)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::VectorSink;
+    use crate::graph::Graph;
+
+    fn run(src: VectorSource<u8>) -> Result<(Vec<u8>, Vec<Tag>)> {
+        let sink = VectorSink::new(src.out());
+        let h = sink.handle();
+        let mut g = Graph::new();
+        g.add(Box::new(src));
+        g.add(Box::new(sink));
+        g.run()?;
+        Ok((h.data(), h.tags()))
+    }
+
+    #[test]
+    fn once() -> Result<()> {
+        let (data, tags) = run(VectorSource::new(vec![1, 2, 3]))?;
+        assert_eq!(data, vec![1, 2, 3]);
+        assert_eq!(
+            tags,
+            vec![
+                Tag::new(0, "VectorSource::start".into(), TagValue::Bool(true)),
+                Tag::new(0, "VectorSource::repeat".into(), TagValue::U64(0)),
+                Tag::new(0, "VectorSource::first".into(), TagValue::Bool(true)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn repeat() -> Result<()> {
+        let (data, tags) = run(VectorSource::builder(vec![1, 2]).repeat(3).build())?;
+        assert_eq!(data, vec![1, 2, 1, 2, 1, 2]);
+        assert!(tags.contains(&Tag::new(
+            4,
+            "VectorSource::repeat".into(),
+            TagValue::U64(2)
+        )));
+        assert_eq!(
+            run(VectorSource::builder(vec![1, 2]).repeat(0).build())?.0,
+            Vec::<u8>::new()
+        );
+        assert_eq!(run(VectorSource::new(vec![]))?.0, Vec::<u8>::new());
+        assert_eq!(
+            run(VectorSource::builder(vec![]).repeat_forever().build())?.0,
+            Vec::<u8>::new()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn forever() -> Result<()> {
+        let mut src = VectorSource::builder(vec![1u8, 2, 3])
+            .repeat_forever()
+            .build();
+        let o = src.out();
+        // Until the output buffer is full.
+        while matches!(src.work()?, BlockRet::Ok) {}
+        let (res, _) = o.read_buf()?;
+        assert!(res.len() > 1000);
+        assert!(res
+            .iter()
+            .enumerate()
+            .all(|(n, v)| *v as usize == n % 3 + 1));
+        Ok(())
+    }
+}
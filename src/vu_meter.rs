@@ -0,0 +1,341 @@
+/*! Audio level meter.
+
+[VuMeter] passes an audio stream through unchanged, and measures its
+level the way an audio meter would, so the demod gain or volume can be
+set to use the range without clipping.
+
+```text
+   … -> demod -> VuMeter -> AuEncode -> FileSink
+                    \_ VuHandle -> level()
+```
+
+Readings, all in dBFS where a sample of 1.0 is 0dB:
+
+| Reading                  | Ballistics                                       |
+|--------------------------|--------------------------------------------------|
+| [VuLevel::vu]            | RMS, reaching 99% of a step in 300ms, like a VU meter |
+| [VuLevel::peak]          | Quasi peak, 5ms attack, falling 20dB in 1.7s, like a PPM |
+| [VuLevel::peak_hold]     | Highest peak, held for 1.5s                      |
+
+Samples at or above the clip level, default 1.0 which is where PCM
+encoding clips, set [VuLevel::clipping] for the hold time, and are
+counted in [VuLevel::clips].
+
+[VuLevel::bar] draws a text meter, for showing on the terminal.
+*/
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, Streamp};
+use crate::{Error, Float};
+
+// VU time constant. 99% of a step after 4.6 time constants, 300ms.
+const VU_TAU: Float = 0.065;
+
+// PPM attack time constant.
+const PEAK_ATTACK: Float = 0.005;
+
+// PPM fall rate, in dB per second.
+const PEAK_FALL: Float = 20.0 / 1.7;
+
+const DEFAULT_HOLD: Duration = Duration::from_millis(1500);
+
+fn to_db(v: Float) -> Float {
+    20.0 * v.max(1e-10).log10()
+}
+
+/// Audio level reading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VuLevel {
+    /// RMS level with VU ballistics, in dBFS.
+    pub vu: Float,
+
+    /// Peak level with PPM ballistics, in dBFS.
+    pub peak: Float,
+
+    /// Highest recent peak, in dBFS.
+    pub peak_hold: Float,
+
+    /// True if a sample clipped within the hold time.
+    pub clipping: bool,
+
+    /// Number of clipped samples since the last reset.
+    pub clips: u64,
+}
+
+impl VuLevel {
+    /// Draw a text meter `width` characters wide, covering `min_db` to
+    /// 0dBFS.
+    ///
+    /// `#` is the VU level, `=` the peak beyond it, and `|` the peak
+    /// hold. Ends with `CLIP` if clipping.
+    pub fn bar(&self, width: usize, min_db: Float) -> String {
+        let pos = |db: Float| {
+            let f = ((db - min_db) / -min_db).clamp(0.0, 1.0);
+            (f * width as Float).round() as usize
+        };
+        let (vu, peak, hold) = (pos(self.vu), pos(self.peak), pos(self.peak_hold));
+        let mut s: Vec<char> = (0..width)
+            .map(|i| {
+                if i < vu {
+                    '#'
+                } else if i < peak {
+                    '='
+                } else {
+                    ' '
+                }
+            })
+            .collect();
+        if hold > 0 {
+            s[hold - 1] = '|';
+        }
+        format!(
+            "[{}] {:6.1} dB{}",
+            s.into_iter().collect::<String>(),
+            self.vu,
+            if self.clipping { " CLIP" } else { "" }
+        )
+    }
+}
+
+/// Shared handle to the readings of a [VuMeter].
+#[derive(Clone)]
+pub struct VuHandle {
+    inner: Arc<Mutex<VuLevel>>,
+}
+
+impl VuHandle {
+    /// Latest reading.
+    pub fn level(&self) -> VuLevel {
+        self.inner.lock().unwrap().clone()
+    }
+
+    /// Reset the clip count.
+    pub fn reset_clips(&self) {
+        self.inner.lock().unwrap().clips = 0;
+    }
+}
+
+/** Audio level meter block.
+
+Passes the stream through as is.
+*/
+pub struct VuMeter {
+    src: Streamp<Float>,
+    dst: Streamp<Float>,
+    samp_rate: Float,
+    handle: VuHandle,
+    clip_level: Float,
+    hold: u64,
+
+    // Per sample coefficients.
+    vu_alpha: Float,
+    attack_alpha: Float,
+    fall: Float,
+
+    pos: u64,
+    ms: Float,
+    peak: Float,
+    peak_hold: Float,
+    peak_hold_pos: u64,
+    last_clip: Option<u64>,
+}
+
+impl VuMeter {
+    /// Create new audio level meter.
+    pub fn new(src: Streamp<Float>, samp_rate: Float) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            samp_rate,
+            handle: VuHandle {
+                inner: Arc::new(Mutex::new(VuLevel {
+                    vu: to_db(0.0),
+                    peak: to_db(0.0),
+                    peak_hold: to_db(0.0),
+                    clipping: false,
+                    clips: 0,
+                })),
+            },
+            clip_level: 1.0,
+            hold: (DEFAULT_HOLD.as_secs_f64() as Float * samp_rate) as u64,
+            vu_alpha: 1.0 - (-1.0 / (VU_TAU * samp_rate)).exp(),
+            attack_alpha: 1.0 - (-1.0 / (PEAK_ATTACK * samp_rate)).exp(),
+            fall: Float::powf(10.0, -PEAK_FALL / 20.0 / samp_rate),
+            pos: 0,
+            ms: 0.0,
+            peak: 0.0,
+            peak_hold: 0.0,
+            peak_hold_pos: 0,
+            last_clip: None,
+        }
+    }
+
+    /// Set the level at or above which samples count as clipped.
+    /// Default 1.0.
+    pub fn set_clip_level(&mut self, level: Float) {
+        self.clip_level = level;
+    }
+
+    /// Set how long peak hold and clip indication are held. Default
+    /// 1.5s.
+    pub fn set_hold(&mut self, hold: Duration) {
+        self.hold = (hold.as_secs_f64() as Float * self.samp_rate) as u64;
+    }
+
+    /// Get a handle to the readings.
+    pub fn handle(&self) -> VuHandle {
+        self.handle.clone()
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Float> {
+        self.dst.clone()
+    }
+
+    // Process one sample, returning true if it clipped.
+    fn process_one(&mut self, x: Float) -> bool {
+        let a = x.abs();
+        self.ms += self.vu_alpha * (x * x - self.ms);
+        if a > self.peak {
+            self.peak += self.attack_alpha * (a - self.peak);
+        } else {
+            self.peak *= self.fall;
+        }
+        if self.peak >= self.peak_hold || self.pos - self.peak_hold_pos > self.hold {
+            self.peak_hold = self.peak;
+            self.peak_hold_pos = self.pos;
+        }
+        let clipped = a >= self.clip_level;
+        if clipped {
+            self.last_clip = Some(self.pos);
+        }
+        self.pos += 1;
+        clipped
+    }
+}
+
+impl Block for VuMeter {
+    fn block_name(&self) -> &str {
+        "VuMeter"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        let obind = self.dst.clone();
+        let mut o = obind.write_buf()?;
+        let n = std::cmp::min(input.len(), o.len());
+        if n == 0 {
+            return Ok(BlockRet::Ok);
+        }
+        let mut clips = 0;
+        for x in input.iter().take(n) {
+            if self.process_one(*x) {
+                clips += 1;
+            }
+        }
+        {
+            let mut l = self.handle.inner.lock().unwrap();
+            l.vu = to_db(self.ms.sqrt());
+            l.peak = to_db(self.peak);
+            l.peak_hold = to_db(self.peak_hold);
+            l.clipping = matches!(self.last_clip, Some(p) if self.pos - p <= self.hold);
+            l.clips += clips;
+        }
+        o.slice()[..n].copy_from_slice(&input.slice()[..n]);
+        let tags: Vec<_> = tags.into_iter().filter(|t| t.pos() < n).collect();
+        o.produce(n, &tags);
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::VectorSource;
+
+    const SAMP_RATE: Float = 8000.0;
+
+    fn sine(amplitude: Float, seconds: Float) -> Vec<Float> {
+        (0..(seconds * SAMP_RATE) as usize)
+            .map(|n| amplitude * (n as Float * 2.0 * std::f64::consts::PI as Float / 8.0).sin())
+            .collect()
+    }
+
+    fn run(data: Vec<Float>) -> Result<VuLevel, Error> {
+        let mut src = VectorSource::new(data);
+        let mut m = VuMeter::new(src.out(), SAMP_RATE);
+        while !matches!(src.work()?, BlockRet::EOF) {
+            m.work()?;
+        }
+        m.work()?;
+        Ok(m.handle().level())
+    }
+
+    #[test]
+    fn steady() -> Result<(), Error> {
+        let l = run(sine(0.5, 1.0))?;
+        // RMS of a sine is 3dB below its peak.
+        assert!((l.vu + 9.03).abs() < 0.1, "{l:?}");
+        assert!((l.peak + 6.02).abs() < 0.3, "{l:?}");
+        assert!((l.peak_hold + 6.02).abs() < 0.3, "{l:?}");
+        assert!(!l.clipping);
+        assert_eq!(l.clips, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn ballistics() -> Result<(), Error> {
+        // VU reaches 99% of the level after 300ms.
+        let l = run(sine(1.0, 0.3))?;
+        assert!((l.vu + 3.01).abs() < 0.1, "{l:?}");
+        let l = run(sine(1.0, 0.05))?;
+        assert!(l.vu < -5.0, "{l:?}");
+
+        // Peak falls 20dB in 1.7s, hold holds for 1.5s.
+        let mut data = sine(0.5, 0.1);
+        data.extend(vec![0.0; (1.7 * SAMP_RATE) as usize]);
+        let l = run(data)?;
+        assert!((l.peak + 26.02).abs() < 0.4, "{l:?}");
+        assert!(l.peak_hold < -20.0, "{l:?}");
+
+        let mut data = sine(0.5, 0.1);
+        data.extend(vec![0.0; SAMP_RATE as usize]);
+        let l = run(data)?;
+        assert!((l.peak_hold + 6.02).abs() < 0.3, "{l:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn clipping() -> Result<(), Error> {
+        let mut data = sine(0.5, 0.1);
+        data.extend([1.2, -1.0, 0.99]);
+        let l = run(data.clone())?;
+        assert!(l.clipping);
+        assert_eq!(l.clips, 2);
+        assert!(l.bar(20, -60.0).ends_with(" CLIP"));
+
+        data.extend(vec![0.0; 2 * SAMP_RATE as usize]);
+        let l = run(data)?;
+        assert!(!l.clipping);
+        assert_eq!(l.clips, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn bar() {
+        let l = VuLevel {
+            vu: -30.0,
+            peak: -20.0,
+            peak_hold: -10.0,
+            clipping: false,
+            clips: 0,
+        };
+        assert_eq!(l.bar(6, -60.0), "[###=| ]  -30.0 dB");
+    }
+}
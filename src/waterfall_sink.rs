@@ -0,0 +1,619 @@
+/*! Live waterfall display.
+
+[WaterfallSink] calculates a power spectrum a number of times per
+second, and keeps a history of them as rows for a scrolling waterfall.
+With the `egui` feature enabled, [WaterfallView] shows it, in the same
+window framework as [SpectrumView][crate::spectrum_sink::SpectrumView].
+With the `gpu` feature as well, it's drawn on the GPU, see
+[crate::gpu].
+
+The FFT size can be changed while running, from the view or with
+[WaterfallHandle::set_fft_size]. Each row is the average of all FFTs
+since the previous row.
+
+```no_run
+use rustradio::Complex;
+use rustradio::mtgraph::MTGraph;
+use rustradio::blocks::{FileSource, SpectrumSink, Tee, WaterfallSink};
+
+let samp_rate = 1_024_000.0;
+let mut g = MTGraph::new();
+let src = Box::new(FileSource::<Complex>::new("raw.c32", false)?);
+let tee = Box::new(Tee::new(src.out()));
+let (a, b) = tee.out();
+let spectrum = Box::new(SpectrumSink::new(a, samp_rate, 1024));
+let waterfall = Box::new(WaterfallSink::new(b, samp_rate, 1024));
+let (sh, wh) = (spectrum.handle(), waterfall.handle());
+g.add(src);
+g.add(tee);
+g.add(spectrum);
+g.add(waterfall);
+std::thread::spawn(move || g.run());
+# #[cfg(feature = "egui")]
+rustradio::gui::run(
+    "Spectrum",
+    vec![
+        Box::new(rustradio::spectrum_sink::SpectrumView::new(sh)),
+        Box::new(rustradio::waterfall_sink::WaterfallView::new(wh)),
+    ],
+)?;
+# Ok::<(), anyhow::Error>(())
+```
+
+Row times are calculated from the sample position and sample rate,
+starting at [WaterfallSink::set_start_time], default when the block
+was created.
+*/
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use log::debug;
+
+use crate::block::{Block, BlockRet};
+use crate::spectrum_sink::{to_db, PowerFft, SpectrumInput};
+use crate::stream::Streamp;
+use crate::{Error, Float};
+
+const DEFAULT_ROW_RATE: Float = 20.0;
+const DEFAULT_HISTORY: usize = 500;
+
+/// Map a value between 0 and 1 to a color, from black through blue,
+/// cyan, yellow and red to white. Values outside are clamped.
+pub fn colormap(v: Float) -> [u8; 3] {
+    const STOPS: [[Float; 3]; 6] = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 160.0],
+        [0.0, 200.0, 255.0],
+        [255.0, 255.0, 0.0],
+        [255.0, 0.0, 0.0],
+        [255.0, 255.0, 255.0],
+    ];
+    let v = if v.is_nan() { 0.0 } else { v.clamp(0.0, 1.0) };
+    let pos = v * (STOPS.len() - 1) as Float;
+    let i = (pos as usize).min(STOPS.len() - 2);
+    let frac = pos - i as Float;
+    let (a, b) = (STOPS[i], STOPS[i + 1]);
+    [0, 1, 2].map(|c| (a[c] + (b[c] - a[c]) * frac).round() as u8)
+}
+
+/// Format a time as HH:MM:SS UTC, for time markers.
+pub fn format_time(t: SystemTime) -> String {
+    let s = t
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        % 86400;
+    format!("{:02}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+}
+
+/// A waterfall row.
+#[derive(Debug, Clone)]
+pub struct Row {
+    /// Time of the first sample in the row.
+    pub time: SystemTime,
+
+    /// Power per bin, in dB, lowest frequency first.
+    pub db: Vec<Float>,
+}
+
+struct Shared {
+    rows: VecDeque<Row>,
+    // Sequence number of the first row in `rows`.
+    first: u64,
+    history: usize,
+    fft_size: usize,
+    row_rate: Float,
+    samp_rate: Float,
+    real: bool,
+}
+
+/// Handle for reading the waterfall, and changing settings, from
+/// another thread.
+#[derive(Clone)]
+pub struct WaterfallHandle {
+    h: Arc<Mutex<Shared>>,
+}
+
+impl WaterfallHandle {
+    /// Get rows added since sequence number `seq`, oldest first, and
+    /// the sequence number to use next time. Rows that have already
+    /// fallen out of the history are skipped.
+    pub fn rows_since(&self, seq: u64) -> (Vec<Row>, u64) {
+        let h = self.h.lock().unwrap();
+        let skip = seq.saturating_sub(h.first) as usize;
+        let rows = h.rows.iter().skip(skip).cloned().collect();
+        (rows, h.first + h.rows.len() as u64)
+    }
+
+    /// Sample rate.
+    pub fn samp_rate(&self) -> Float {
+        self.h.lock().unwrap().samp_rate
+    }
+
+    /// True if only positive frequencies are included.
+    pub fn real(&self) -> bool {
+        self.h.lock().unwrap().real
+    }
+
+    /// Get the FFT size.
+    pub fn fft_size(&self) -> usize {
+        self.h.lock().unwrap().fft_size
+    }
+
+    /// Set the FFT size. Takes effect from the next row.
+    pub fn set_fft_size(&self, n: usize) {
+        self.h.lock().unwrap().fft_size = n.max(2);
+    }
+
+    /// Get the number of rows per second.
+    pub fn row_rate(&self) -> Float {
+        self.h.lock().unwrap().row_rate
+    }
+
+    /// Set the number of rows per second. If this is more than the
+    /// number of FFTs per second, there's one row per FFT.
+    pub fn set_row_rate(&self, rate: Float) {
+        self.h.lock().unwrap().row_rate = rate.max(0.01);
+    }
+
+    /// Get the number of rows kept.
+    pub fn history(&self) -> usize {
+        self.h.lock().unwrap().history
+    }
+
+    /// Set the number of rows kept.
+    pub fn set_history(&self, rows: usize) {
+        let mut h = self.h.lock().unwrap();
+        h.history = rows.max(1);
+        while h.rows.len() > h.history {
+            h.rows.pop_front();
+            h.first += 1;
+        }
+    }
+}
+
+/// Waterfall sink block.
+pub struct WaterfallSink<T> {
+    src: Streamp<T>,
+    fft: PowerFft,
+    acc: Vec<Float>,
+    acc_n: usize,
+    row_start: u64,
+    pos: u64,
+    start_time: SystemTime,
+    handle: WaterfallHandle,
+}
+
+impl<T: SpectrumInput> WaterfallSink<T> {
+    /// Create new WaterfallSink block.
+    pub fn new(src: Streamp<T>, samp_rate: Float, fft_size: usize) -> Self {
+        let fft = PowerFft::new(fft_size);
+        Self {
+            src,
+            handle: WaterfallHandle {
+                h: Arc::new(Mutex::new(Shared {
+                    rows: VecDeque::new(),
+                    first: 0,
+                    history: DEFAULT_HISTORY,
+                    fft_size: fft.size(),
+                    row_rate: DEFAULT_ROW_RATE,
+                    samp_rate,
+                    real: T::REAL,
+                })),
+            },
+            fft,
+            acc: Vec::new(),
+            acc_n: 0,
+            row_start: 0,
+            pos: 0,
+            start_time: SystemTime::now(),
+        }
+    }
+
+    /// Set the time of the first sample.
+    pub fn set_start_time(&mut self, t: SystemTime) {
+        self.start_time = t;
+    }
+
+    /// Return a handle for reading the waterfall.
+    pub fn handle(&self) -> WaterfallHandle {
+        self.handle.clone()
+    }
+
+    fn time(&self, pos: u64, samp_rate: Float) -> SystemTime {
+        self.start_time + Duration::from_secs_f64(pos as f64 / samp_rate as f64)
+    }
+
+    fn add(&mut self, input: &[T]) {
+        let power = self.fft.power(input);
+        if self.acc_n == 0 {
+            self.acc = power;
+            self.row_start = self.pos;
+        } else {
+            for (a, p) in self.acc.iter_mut().zip(&power) {
+                *a += p;
+            }
+        }
+        self.acc_n += 1;
+        self.pos += input.len() as u64;
+
+        let mut h = self.handle.h.lock().unwrap();
+        let per_row = h.samp_rate / h.row_rate;
+        if ((self.pos - self.row_start) as Float) < per_row {
+            return;
+        }
+        let n = self.acc_n as Float;
+        let avg: Vec<Float> = self.acc.iter().map(|a| a / n).collect();
+        let row = Row {
+            time: self.time(self.row_start, h.samp_rate),
+            db: to_db(&avg),
+        };
+        h.rows.push_back(row);
+        while h.rows.len() > h.history {
+            h.rows.pop_front();
+            h.first += 1;
+        }
+        self.acc_n = 0;
+    }
+}
+
+impl<T: SpectrumInput> Block for WaterfallSink<T> {
+    fn block_name(&self) -> &str {
+        "WaterfallSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let want = self.handle.fft_size();
+        if want != self.fft.size() {
+            debug!("WaterfallSink: FFT size changed to {want}");
+            self.fft = PowerFft::new(want);
+            self.acc_n = 0;
+        }
+        let n = self.fft.size();
+        let ibind = self.src.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.len() < n {
+            return Ok(BlockRet::Noop);
+        }
+        let chunks = input.len() / n;
+        for chunk in input.slice()[..chunks * n].chunks_exact(n) {
+            self.add(chunk);
+        }
+        input.consume(chunks * n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(feature = "egui")]
+mod view {
+    use super::*;
+    use crate::gui::{freq_grid, View};
+    use crate::spectrum_sink::format_freq;
+    use eframe::egui;
+    use egui::{Color32, ColorImage, Pos2, Rect, Sense, Stroke, TextureHandle};
+
+    const FFT_SIZES: [usize; 7] = [256, 512, 1024, 2048, 4096, 8192, 16384];
+
+    // Marker intervals to choose from, in seconds.
+    const MARKER_INTERVALS: [u64; 12] = [1, 2, 5, 10, 15, 30, 60, 120, 300, 600, 1800, 3600];
+    const MARKER_SPACING: f32 = 40.0;
+
+    /// Waterfall display, for [crate::gui::run].
+    pub struct WaterfallView {
+        handle: WaterfallHandle,
+        rows: VecDeque<Row>,
+        next: u64,
+        min_db: Float,
+        max_db: Float,
+        texture: Option<TextureHandle>,
+        dirty: bool,
+        marker: Option<(Float, usize)>,
+        #[cfg(feature = "gpu")]
+        gpu: Option<crate::gpu::GpuWaterfall>,
+    }
+
+    impl WaterfallView {
+        /// Create new waterfall view.
+        ///
+        /// With the `gpu` feature, it's drawn on the GPU, unless that
+        /// fails or is turned off with [WaterfallView::set_gpu].
+        pub fn new(handle: WaterfallHandle) -> Self {
+            Self {
+                handle,
+                rows: VecDeque::new(),
+                next: 0,
+                min_db: -120.0,
+                max_db: 0.0,
+                texture: None,
+                dirty: true,
+                marker: None,
+                #[cfg(feature = "gpu")]
+                gpu: Some(crate::gpu::GpuWaterfall::new()),
+            }
+        }
+
+        /// Draw on the GPU, or not.
+        #[cfg(feature = "gpu")]
+        pub fn set_gpu(&mut self, on: bool) {
+            if on == self.gpu.is_some() {
+                return;
+            }
+            self.gpu = on.then(|| {
+                let g = crate::gpu::GpuWaterfall::new();
+                for row in self.rows.iter().rev() {
+                    g.push(&row.db);
+                }
+                g
+            });
+            self.dirty = true;
+        }
+
+        // True if drawing on the GPU.
+        fn on_gpu(&self) -> bool {
+            #[cfg(feature = "gpu")]
+            if let Some(g) = &self.gpu {
+                return !g.failed();
+            }
+            false
+        }
+
+        // Keep the GPU copy of the rows in sync. `None` clears it.
+        fn gpu_push(&self, _db: Option<&[Float]>) {
+            #[cfg(feature = "gpu")]
+            if let Some(g) = &self.gpu {
+                match _db {
+                    Some(db) => g.push(db),
+                    None => g.clear(),
+                }
+            }
+        }
+
+        // Fetch new rows, newest first.
+        fn fetch(&mut self) {
+            let (rows, next) = self.handle.rows_since(self.next);
+            self.next = next;
+            for row in rows {
+                if self.rows.front().map(|r| r.db.len()) != Some(row.db.len()) {
+                    // FFT size changed.
+                    self.rows.clear();
+                    self.gpu_push(None);
+                }
+                self.gpu_push(Some(&row.db));
+                self.rows.push_front(row);
+                self.dirty = true;
+            }
+            self.rows.truncate(self.handle.history());
+        }
+
+        fn image(&self, history: usize) -> ColorImage {
+            let width = self.rows.front().map(|r| r.db.len()).unwrap_or(1);
+            let mut img = ColorImage::new([width, history], Color32::BLACK);
+            let range = self.max_db - self.min_db;
+            for (y, row) in self.rows.iter().take(history).enumerate() {
+                for (x, db) in row.db.iter().enumerate() {
+                    let [r, g, b] = colormap((db - self.min_db) / range);
+                    img.pixels[y * width + x] = Color32::from_rgb(r, g, b);
+                }
+            }
+            img
+        }
+
+        fn time_markers(&self, painter: &egui::Painter, rect: Rect, history: usize) {
+            let px_per_row = rect.height() / history as f32;
+            let row_rate = self.handle.row_rate();
+            let interval = MARKER_INTERVALS
+                .into_iter()
+                .find(|i| *i as f32 * row_rate as f32 * px_per_row >= MARKER_SPACING)
+                .unwrap_or(3600);
+            let secs = |r: &Row| {
+                r.time
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+            };
+            for (y, pair) in self.rows.iter().collect::<Vec<_>>().windows(2).enumerate() {
+                let (newer, older) = (secs(pair[0]), secs(pair[1]));
+                if newer / interval == older / interval {
+                    continue;
+                }
+                let y = rect.top() + (y + 1) as f32 * px_per_row;
+                painter.hline(
+                    rect.x_range(),
+                    y,
+                    Stroke::new(1.0, Color32::from_white_alpha(80)),
+                );
+                painter.text(
+                    Pos2::new(rect.left() + 2.0, y),
+                    egui::Align2::LEFT_BOTTOM,
+                    format_time(
+                        SystemTime::UNIX_EPOCH + Duration::from_secs(newer / interval * interval),
+                    ),
+                    egui::FontId::monospace(10.0),
+                    Color32::WHITE,
+                );
+            }
+        }
+    }
+
+    impl View for WaterfallView {
+        fn name(&self) -> &str {
+            "Waterfall"
+        }
+
+        fn controls(&mut self, ui: &mut egui::Ui) {
+            let mut fft_size = self.handle.fft_size();
+            egui::ComboBox::from_label("FFT size")
+                .selected_text(fft_size.to_string())
+                .show_ui(ui, |ui| {
+                    for n in FFT_SIZES {
+                        ui.selectable_value(&mut fft_size, n, n.to_string());
+                    }
+                });
+            if fft_size != self.handle.fft_size() {
+                self.handle.set_fft_size(fft_size);
+            }
+            let mut rate = self.handle.row_rate();
+            if ui
+                .add(
+                    egui::Slider::new(&mut rate, 1.0..=100.0)
+                        .logarithmic(true)
+                        .text("Rows/s"),
+                )
+                .changed()
+            {
+                self.handle.set_row_rate(rate);
+            }
+            let (min, max) = (self.min_db, self.max_db);
+            ui.add(egui::Slider::new(&mut self.max_db, -100.0..=20.0).text("Max dB"));
+            ui.add(egui::Slider::new(&mut self.min_db, -200.0..=0.0).text("Min dB"));
+            if self.min_db >= self.max_db {
+                self.min_db = self.max_db - 10.0;
+            }
+            self.dirty |= (min, max) != (self.min_db, self.max_db);
+            #[cfg(feature = "gpu")]
+            {
+                let mut on = self.gpu.is_some();
+                let failed = self.gpu.as_ref().map(|g| g.failed()).unwrap_or(false);
+                ui.add_enabled(!failed, egui::Checkbox::new(&mut on, "GPU"));
+                self.set_gpu(on);
+            }
+        }
+
+        fn show(&mut self, ui: &mut egui::Ui) {
+            self.fetch();
+            if self.rows.is_empty() {
+                ui.label("Waiting for data…");
+                return;
+            }
+            let history = self.handle.history();
+            let (resp, painter) = ui.allocate_painter(ui.available_size(), Sense::click());
+            let rect = resp.rect;
+            if self.on_gpu() {
+                #[cfg(feature = "gpu")]
+                if let Some(g) = &self.gpu {
+                    let bins = self.rows.front().map(|r| r.db.len()).unwrap_or(1);
+                    g.paint(&painter, rect, bins, history, self.min_db, self.max_db);
+                }
+            } else if self.dirty || self.texture.is_none() {
+                let img = self.image(history);
+                match &mut self.texture {
+                    Some(t) => t.set(img, egui::TextureOptions::LINEAR),
+                    None => {
+                        self.texture = Some(ui.ctx().load_texture(
+                            "waterfall",
+                            img,
+                            egui::TextureOptions::LINEAR,
+                        ))
+                    }
+                }
+                self.dirty = false;
+            }
+            if !self.on_gpu() {
+                let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+                if let Some(t) = &self.texture {
+                    painter.image(t.id(), rect, uv, Color32::WHITE);
+                }
+            }
+
+            let samp_rate = self.handle.samp_rate();
+            let (min_freq, max_freq) = if self.handle.real() {
+                (0.0, samp_rate / 2.0)
+            } else {
+                (-samp_rate / 2.0, samp_rate / 2.0)
+            };
+            freq_grid(&painter, rect, min_freq, max_freq);
+            self.time_markers(&painter, rect, history);
+
+            // Marker.
+            if let Some(pos) = resp.interact_pointer_pos() {
+                let f = min_freq
+                    + ((pos.x - rect.left()) / rect.width()) as Float * (max_freq - min_freq);
+                let row = ((pos.y - rect.top()) / rect.height() * history as f32) as usize;
+                self.marker = Some((f, row));
+            }
+            if let Some((f, row)) = self.marker {
+                let x =
+                    rect.left() + rect.width() * ((f - min_freq) / (max_freq - min_freq)) as f32;
+                painter.vline(x, rect.y_range(), Stroke::new(1.0, Color32::WHITE));
+                let mut text = format_freq(f);
+                if let Some(r) = self.rows.get(row) {
+                    text += &format!("\n{}", format_time(r.time));
+                }
+                painter.text(
+                    Pos2::new(x + 4.0, rect.top() + 4.0),
+                    egui::Align2::LEFT_TOP,
+                    text,
+                    egui::FontId::monospace(12.0),
+                    Color32::WHITE,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(feature = "egui")]
+pub use view::WaterfallView;
+
+/// Open a window showing the waterfall, and run until it's closed.
+///
+/// Must be called from the main thread.
+#[cfg(feature = "egui")]
+pub fn run_window(handle: WaterfallHandle, title: &str) -> anyhow::Result<()> {
+    crate::gui::run(title, vec![Box::new(WaterfallView::new(handle))])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::SignalSourceComplex;
+    use crate::Complex;
+
+    #[test]
+    fn colors() {
+        assert_eq!(colormap(-1.0), [0, 0, 0]);
+        assert_eq!(colormap(0.2), [0, 0, 160]);
+        assert_eq!(colormap(0.5), [128, 228, 128]);
+        assert_eq!(colormap(1.0), [255, 255, 255]);
+        assert_eq!(colormap(Float::NAN), [0, 0, 0]);
+    }
+
+    #[test]
+    fn rows() -> anyhow::Result<()> {
+        let samp_rate = 1024.0;
+        let mut src = SignalSourceComplex::new(samp_rate, 256.0, 1.0);
+        let mut sink = WaterfallSink::<Complex>::new(src.out(), samp_rate, 64);
+        sink.set_start_time(SystemTime::UNIX_EPOCH);
+        let h = sink.handle();
+        h.set_row_rate(8.0);
+        h.set_history(3);
+
+        // 128 samples per row, so two FFTs each.
+        src.work()?;
+        sink.work()?;
+        let (rows, next) = h.rows_since(0);
+        assert_eq!(rows.len(), 3);
+        let row = &rows[2];
+        assert_eq!(row.db.len(), 64);
+        let peak = row
+            .db
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap()
+            .0;
+        assert_eq!(peak, 48);
+        assert!(row.db[peak].abs() < 0.1);
+        assert_eq!(
+            rows[1].time.duration_since(rows[0].time)?,
+            Duration::from_millis(125)
+        );
+        assert_eq!(h.rows_since(next).0.len(), 0);
+
+        h.set_fft_size(128);
+        src.work()?;
+        sink.work()?;
+        let (rows, _) = h.rows_since(next);
+        assert_eq!(rows.last().unwrap().db.len(), 128);
+        Ok(())
+    }
+}
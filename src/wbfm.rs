@@ -0,0 +1,345 @@
+/*! Broadcast FM.
+
+Broadcast FM has ±75kHz deviation, and the transmitter boosts the
+treble before modulating (pre-emphasis), to get better signal to noise
+ratio after the receiver cuts it back down (de-emphasis). The time
+constant is 75µs in the Americas and South Korea, and 50µs elsewhere.
+
+[WbfmDecode] does the whole mono receive chain, from I/Q centered on
+the station to audio:
+
+```text
+   [ I/Q, ~200kHz wide ] -> FM demod -> de-emphasis -> low pass -> decimate -> [ audio ]
+```
+
+The demodulated multiplex, before de-emphasis, is also available from
+[out_mpx](WbfmDecode::out_mpx), for decoding the stereo and RDS
+subcarriers with e.g. [RdsDecode](crate::rds::RdsDecode).
+
+[Deemph] is the de-emphasis alone, for building other chains.
+
+```
+use rustradio::blocks::{NullSink, WbfmDecode};
+use rustradio::stream::new_streamp;
+let src = new_streamp();
+// 240kHz in, 48kHz audio out.
+let fm = WbfmDecode::builder(src, 240_000.0).audio_decim(5).build()?;
+let sink = NullSink::new(fm.out());
+# Ok::<(), rustradio::Error>(())
+```
+*/
+use crate::block::{Block, BlockRet};
+use crate::fir::FIR;
+use crate::stream::{new_streamp, Streamp};
+use crate::{map_block_convert_macro, Complex, Error, Float};
+
+/// De-emphasis time constant in the Americas and South Korea.
+pub const TAU_US: Float = 75e-6;
+
+/// De-emphasis time constant in Europe and most other places.
+pub const TAU_EU: Float = 50e-6;
+
+/// Max deviation of broadcast FM, in Hz.
+pub const DEVIATION: Float = 75_000.0;
+
+const PI: Float = std::f64::consts::PI as Float;
+
+// Single pole low pass, from the RC filter by bilinear transform.
+// Unity gain at DC.
+struct DeemphFilter {
+    b0: Float,
+    p1: Float,
+    last_in: Float,
+    last_out: Float,
+}
+
+impl DeemphFilter {
+    fn new(samp_rate: Float, tau: Float) -> Self {
+        let wc = 1.0 / tau;
+        let wca = 2.0 * samp_rate * (wc / (2.0 * samp_rate)).tan();
+        let k = -wca / (2.0 * samp_rate);
+        Self {
+            b0: -k / (1.0 - k),
+            p1: (1.0 + k) / (1.0 - k),
+            last_in: 0.0,
+            last_out: 0.0,
+        }
+    }
+
+    fn filter(&mut self, x: Float) -> Float {
+        let y = self.b0 * (x + self.last_in) + self.p1 * self.last_out;
+        self.last_in = x;
+        self.last_out = y;
+        y
+    }
+}
+
+/// FM de-emphasis filter.
+pub struct Deemph {
+    src: Streamp<Float>,
+    dst: Streamp<Float>,
+    filter: DeemphFilter,
+}
+
+impl Deemph {
+    /// Create new de-emphasis block, with time constant `tau`, e.g.
+    /// [TAU_US] or [TAU_EU].
+    pub fn new(src: Streamp<Float>, samp_rate: Float, tau: Float) -> Self {
+        Self {
+            src,
+            dst: new_streamp(),
+            filter: DeemphFilter::new(samp_rate, tau),
+        }
+    }
+
+    fn process_one(&mut self, s: Float) -> Float {
+        self.filter.filter(s)
+    }
+}
+
+map_block_convert_macro![Deemph, Float];
+
+/// Builder for [WbfmDecode].
+pub struct WbfmDecodeBuilder {
+    src: Streamp<Complex>,
+    quad_rate: Float,
+    audio_decim: Option<usize>,
+    tau: Float,
+    deviation: Float,
+}
+
+impl WbfmDecodeBuilder {
+    /// Create new builder, for input at `quad_rate`.
+    pub fn new(src: Streamp<Complex>, quad_rate: Float) -> Self {
+        Self {
+            src,
+            quad_rate,
+            audio_decim: None,
+            tau: TAU_US,
+            deviation: DEVIATION,
+        }
+    }
+
+    /// Set decimation from input to audio. Default is whatever gets
+    /// closest to 48kHz.
+    pub fn audio_decim(mut self, decim: usize) -> Self {
+        self.audio_decim = Some(decim);
+        self
+    }
+
+    /// Set de-emphasis time constant. Default [TAU_US].
+    pub fn tau(mut self, tau: Float) -> Self {
+        self.tau = tau;
+        self
+    }
+
+    /// Set deviation giving full scale audio. Default [DEVIATION].
+    pub fn deviation(mut self, hz: Float) -> Self {
+        self.deviation = hz;
+        self
+    }
+
+    /// Build WbfmDecode block.
+    pub fn build(self) -> Result<WbfmDecode, Error> {
+        let decim = self
+            .audio_decim
+            .unwrap_or(((self.quad_rate / 48_000.0).round() as usize).max(1));
+        if decim == 0 || self.quad_rate <= 0.0 {
+            return Err(Error::BadParameter(format!(
+                "WbfmDecode needs a positive rate and decimation, got {} and {decim}",
+                self.quad_rate
+            )));
+        }
+        let audio_rate = self.quad_rate / decim as Float;
+        // Mono audio goes to 15kHz, with the stereo pilot at 19kHz.
+        let cutoff = (15_000.0 as Float).min(0.4 * audio_rate);
+        let taps = crate::fir::low_pass(self.quad_rate, cutoff, 0.1 * audio_rate);
+        Ok(WbfmDecode {
+            src: self.src,
+            dst: new_streamp(),
+            mpx: None,
+            gain: self.quad_rate / (2.0 * PI * self.deviation),
+            last: Complex::default(),
+            deemph: DeemphFilter::new(self.quad_rate, self.tau),
+            ntaps: taps.len(),
+            fir: FIR::new(&taps),
+            hist: Vec::new(),
+            decim,
+            audio_rate,
+        })
+    }
+}
+
+/// Broadcast FM mono receiver. See the [module docs](crate::wbfm).
+pub struct WbfmDecode {
+    src: Streamp<Complex>,
+    dst: Streamp<Float>,
+    mpx: Option<Streamp<Float>>,
+    gain: Float,
+    last: Complex,
+    deemph: DeemphFilter,
+    fir: FIR<Float>,
+    ntaps: usize,
+    // De-emphasized samples not yet decimated.
+    hist: Vec<Float>,
+    decim: usize,
+    audio_rate: Float,
+}
+
+impl WbfmDecode {
+    /// Create a builder.
+    pub fn builder(src: Streamp<Complex>, quad_rate: Float) -> WbfmDecodeBuilder {
+        WbfmDecodeBuilder::new(src, quad_rate)
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<Float> {
+        self.dst.clone()
+    }
+
+    /// Return the demodulated multiplex, at the input sample rate.
+    pub fn out_mpx(&mut self) -> Streamp<Float> {
+        self.mpx.get_or_insert(new_streamp()).clone()
+    }
+
+    /// Return the output sample rate.
+    pub fn audio_rate(&self) -> Float {
+        self.audio_rate
+    }
+}
+
+impl Block for WbfmDecode {
+    fn block_name(&self) -> &str {
+        "WbfmDecode"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (input, tags) = self.src.read_buf()?;
+        let mut o = self.dst.write_buf()?;
+        let mut mpx = self.mpx.as_ref().map(|m| m.write_buf()).transpose()?;
+        let n = input
+            .len()
+            .min(o.len() * self.decim)
+            .min(mpx.as_ref().map_or(usize::MAX, |m| m.len()));
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        let offset = self.hist.len();
+        for (i, s) in input.iter().take(n).enumerate() {
+            let d = (s * self.last.conj()).arg() * self.gain;
+            self.last = *s;
+            if let Some(m) = &mut mpx {
+                m.slice()[i] = d;
+            }
+            self.hist.push(self.deemph.filter(d));
+        }
+        if let Some(m) = mpx {
+            m.produce(n, &tags);
+        }
+        let mut produced = 0;
+        let mut pos = 0;
+        while pos + self.ntaps <= self.hist.len() && produced < o.len() {
+            o.slice()[produced] = self.fir.filter(&self.hist[pos..pos + self.ntaps]);
+            produced += 1;
+            pos += self.decim;
+        }
+        self.hist.drain(..pos);
+        let otags: Vec<_> = tags
+            .iter()
+            .map(|t| t.with_pos((t.pos() + offset) / self.decim))
+            .filter(|t| t.pos() < produced)
+            .collect();
+        o.produce(produced, &otags);
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    // Gain of the de-emphasis filter for a tone at `freq`.
+    fn deemph_gain(freq: Float, tau: Float) -> Float {
+        // High enough rate for the bilinear transform to not warp much.
+        let samp_rate = 192_000.0;
+        let input: Vec<Float> = (0..48_000)
+            .map(|n| (2.0 * PI * freq * n as Float / samp_rate).sin())
+            .collect();
+        let mut b = Deemph::new(streamp_from_slice(&input), samp_rate, tau);
+        b.work().unwrap();
+        let o = b.out();
+        let (out, _) = o.read_buf().unwrap();
+        let peak = out.slice()[24_000..]
+            .iter()
+            .fold(0.0 as Float, |a, s| a.max(s.abs()));
+        peak
+    }
+
+    #[test]
+    fn deemph() {
+        // Corner frequency is 1/(2πτ), where the gain is -3dB.
+        for tau in [TAU_US, TAU_EU] {
+            let corner = 1.0 / (2.0 * PI * tau);
+            let g = deemph_gain(corner, tau);
+            assert!((g - 0.5f32.sqrt() as Float).abs() < 0.01, "{tau}: {g}");
+            assert!((deemph_gain(50.0, tau) - 1.0).abs() < 0.01);
+            // Falling towards 6dB per octave above the corner.
+            let g = deemph_gain(2.0 * corner, tau);
+            assert!((g - 0.2f32.sqrt() as Float).abs() < 0.01, "{tau}: {g}");
+        }
+    }
+
+    #[test]
+    fn decode() -> Result<(), Error> {
+        // 1kHz tone at 75kHz deviation.
+        let quad_rate = 240_000.0;
+        let mut phase: Float = 0.0;
+        let input: Vec<Complex> = (0..24_000)
+            .map(|n| {
+                let audio = (2.0 * PI * 1000.0 * n as Float / quad_rate).sin();
+                phase += 2.0 * PI * DEVIATION * audio / quad_rate;
+                Complex::from_polar(1.0, phase)
+            })
+            .collect();
+        let mut b = WbfmDecode::builder(streamp_from_slice(&input), quad_rate).build()?;
+        assert_eq!(b.audio_rate(), 48_000.0);
+        b.work()?;
+        let o = b.out();
+        let (out, _) = o.read_buf()?;
+        let out = out.slice();
+        assert!(out.len() > 4700, "{}", out.len());
+
+        // Full scale, less de-emphasis at 1kHz.
+        let want = deemph_gain(1000.0, TAU_US);
+        let peak = out[1000..].iter().fold(0.0 as Float, |a, s| a.max(s.abs()));
+        assert!((peak - want).abs() < 0.02, "want {want} got {peak}");
+
+        // 48 samples per cycle.
+        let crossings = out[1000..]
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count();
+        let cycles = (out.len() - 1000) as Float / 48.0;
+        assert!((crossings as Float - cycles).abs() <= 1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn mpx() -> Result<(), Error> {
+        // 10kHz off center is a constant 10/75 of full scale.
+        let quad_rate = 240_000.0;
+        let input: Vec<Complex> = (0..1000)
+            .map(|n| Complex::from_polar(1.0, 2.0 * PI * 10_000.0 * n as Float / quad_rate))
+            .collect();
+        let mut b = WbfmDecode::builder(streamp_from_slice(&input), quad_rate).build()?;
+        let mpx = b.out_mpx();
+        b.work()?;
+        let (out, _) = mpx.read_buf()?;
+        assert_eq!(out.len(), input.len());
+        for s in &out.slice()[1..] {
+            assert!((s - 10.0 / 75.0).abs() < 0.001, "{s}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,556 @@
+/*! Wireless M-Bus (EN 13757-4) receiver.
+
+[Wireless M-Bus][wmbus] is used by utility meters (water, gas, heat, electricity)
+in the 868MHz band. This implements the three most common modes:
+
+| Mode | Frequency  | Chip rate | Coding                    |
+|------|------------|-----------|---------------------------|
+| S    | 868.3MHz   | 32.768k   | Manchester                |
+| T    | 868.95MHz  | 100k      | 3-of-6                    |
+| C    | 868.95MHz  | 100k      | NRZ, frame format A or B  |
+
+All are 2-FSK. [Mode] has the parameters needed to set up the
+demodulator, which is the same as for other FSK in this crate:
+
+```text
+  QuadratureDemod ─→ ZeroCrossing ─→ BinarySlicer ─→ WmbusDecode
+```
+
+[WmbusDecode] takes one chip per sample, finds the sync word, decodes
+the chips into bytes, checks the CRC of each block, and outputs a
+[WmbusFrame] for each frame with all CRCs correct.
+
+Application layer payloads are often encrypted, and are not parsed.
+
+[wmbus]: https://en.wikipedia.org/wiki/Meter-Bus
+*/
+use anyhow::Result;
+use log::{debug, info, trace};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_nocopy_streamp, NoCopyStreamp, Streamp};
+use crate::{Error, Float};
+
+/// Wireless M-Bus mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// Stationary mode.
+    S,
+
+    /// Frequent transmit mode.
+    #[default]
+    T,
+
+    /// Compact mode.
+    C,
+}
+
+impl Mode {
+    /// Center frequency, in Hz.
+    pub fn frequency(&self) -> f64 {
+        match self {
+            Mode::S => 868_300_000.0,
+            Mode::T | Mode::C => 868_950_000.0,
+        }
+    }
+
+    /// Chip rate, in chips per second.
+    pub fn chip_rate(&self) -> Float {
+        match self {
+            Mode::S => 32_768.0,
+            Mode::T | Mode::C => 100_000.0,
+        }
+    }
+
+    /// FSK deviation, in Hz.
+    pub fn deviation(&self) -> Float {
+        match self {
+            Mode::S | Mode::T => 50_000.0,
+            Mode::C => 45_000.0,
+        }
+    }
+
+    /// Number of chips per byte.
+    fn chips_per_byte(&self) -> usize {
+        match self {
+            Mode::S => 16,
+            Mode::T => 12,
+            Mode::C => 8,
+        }
+    }
+}
+
+/// Frame format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// Format A: CRC after the first 10 bytes, and then every 16 bytes.
+    #[default]
+    A,
+
+    /// Format B: CRC after at most 126 bytes, and then at the end.
+    /// Only used in mode C.
+    B,
+}
+
+/// 3-of-6 codes, indexed by nibble.
+const THREE_OF_SIX: [u8; 16] = [
+    0x16, 0x0d, 0x0e, 0x0b, 0x1c, 0x19, 0x1a, 0x13, 0x2c, 0x25, 0x26, 0x23, 0x34, 0x31, 0x32, 0x29,
+];
+
+/// Encode a byte as 12 chips of 3-of-6, high nibble first.
+pub fn encode_3of6(b: u8) -> u16 {
+    ((THREE_OF_SIX[(b >> 4) as usize] as u16) << 6) | THREE_OF_SIX[(b & 0xf) as usize] as u16
+}
+
+/// Decode 12 chips of 3-of-6 into a byte.
+pub fn decode_3of6(chips: u16) -> Option<u8> {
+    let nib = |c: u16| THREE_OF_SIX.iter().position(|x| *x as u16 == c);
+    let hi = nib((chips >> 6) & 0x3f)?;
+    let lo = nib(chips & 0x3f)?;
+    Some(((hi << 4) | lo) as u8)
+}
+
+/// Encode a byte as 16 chips of Manchester, where 0 is 10 and 1 is 01.
+pub fn encode_manchester(b: u8) -> u16 {
+    (0..8).fold(0, |acc, n| {
+        let bit = (b >> (7 - n)) & 1;
+        (acc << 2) | if bit == 1 { 0b01 } else { 0b10 }
+    })
+}
+
+/// Decode 16 chips of Manchester.
+pub fn decode_manchester(chips: u16) -> Option<u8> {
+    (0..8).try_fold(0, |acc, n| {
+        let bit = match (chips >> (14 - 2 * n)) & 3 {
+            0b01 => 1,
+            0b10 => 0,
+            _ => return None,
+        };
+        Some((acc << 1) | bit)
+    })
+}
+
+/// CRC used for all blocks: polynomial 0x3d65, inverted.
+pub fn crc(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for b in data {
+        crc ^= (*b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x3d65
+            } else {
+                crc << 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Total number of bytes on air for a frame, including L field and
+/// CRCs.
+pub fn frame_len(l: u8, format: Format) -> usize {
+    let l = l as usize;
+    match format {
+        Format::A => {
+            let blocks = 1 + l.saturating_sub(9).div_ceil(16);
+            1 + l + 2 * blocks
+        }
+        Format::B => 1 + l,
+    }
+}
+
+/// Check and strip CRCs. Returns data without CRCs.
+pub fn check_blocks(raw: &[u8], format: Format) -> Option<Vec<u8>> {
+    fn ok(block: &[u8]) -> Option<&[u8]> {
+        let (data, c) = block.split_at(block.len() - 2);
+        (crc(data) == u16::from_be_bytes([c[0], c[1]])).then_some(data)
+    }
+    let mut out = Vec::new();
+    match format {
+        Format::A => {
+            let (first, rest) = raw.split_at(12.min(raw.len()));
+            out.extend(ok(first)?);
+            for block in rest.chunks(18) {
+                if block.len() < 3 {
+                    return None;
+                }
+                out.extend(ok(block)?);
+            }
+        }
+        Format::B => {
+            // Block 1 and 2 share a CRC, covering at most 128 bytes.
+            let n = raw.len().min(128);
+            out.extend(ok(&raw[..n])?);
+            if raw.len() > n {
+                out.extend(ok(&raw[n..])?);
+            }
+        }
+    }
+    Some(out)
+}
+
+/// Decode the three letter manufacturer code.
+pub fn manufacturer(m: u16) -> String {
+    [10, 5, 0]
+        .iter()
+        .map(|s| (((m >> s) & 0x1f) as u8 + 64) as char)
+        .collect()
+}
+
+/// Return a name for a device type, if known.
+pub fn device_type_name(t: u8) -> Option<&'static str> {
+    Some(match t {
+        0x02 => "Electricity",
+        0x03 => "Gas",
+        0x04 => "Heat",
+        0x06 => "Warm water",
+        0x07 => "Water",
+        0x08 => "Heat cost allocator",
+        0x0a => "Cooling",
+        0x16 => "Cold water",
+        0x1a => "Smoke detector",
+        _ => return None,
+    })
+}
+
+/// Wireless M-Bus frame.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WmbusFrame {
+    /// Mode received in.
+    pub mode: Mode,
+
+    /// Frame format.
+    pub format: Format,
+
+    /// C field.
+    pub c: u8,
+
+    /// Manufacturer code, e.g. "KAM".
+    pub manufacturer: String,
+
+    /// Meter ID. Usually BCD, so print it as hex.
+    pub id: u32,
+
+    /// Version.
+    pub version: u8,
+
+    /// Device type. See [device_type_name].
+    pub device_type: u8,
+
+    /// CI field, telling what the payload is.
+    pub ci: u8,
+
+    /// Payload after the CI field, without CRCs.
+    pub payload: Vec<u8>,
+}
+
+impl WmbusFrame {
+    /// Parse frame data, with CRCs already removed.
+    pub fn parse(mode: Mode, format: Format, data: &[u8]) -> Option<Self> {
+        if data.len() < 11 {
+            return None;
+        }
+        Some(Self {
+            mode,
+            format,
+            c: data[1],
+            manufacturer: manufacturer(u16::from_le_bytes([data[2], data[3]])),
+            id: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+            version: data[8],
+            device_type: data[9],
+            ci: data[10],
+            payload: data[11..].to_vec(),
+        })
+    }
+}
+
+impl crate::Len for WmbusFrame {
+    fn len(&self) -> usize {
+        self.payload.len()
+    }
+}
+
+enum State {
+    Hunt,
+    Frame {
+        format: Format,
+        chips: u64,
+        nchips: usize,
+        bytes: Vec<u8>,
+    },
+}
+
+/** Wireless M-Bus decoder.
+
+Input is one chip per sample, as 0 or 1.
+*/
+pub struct WmbusDecode {
+    src: Streamp<u8>,
+    dst: NoCopyStreamp<WmbusFrame>,
+    mode: Mode,
+    shift: u64,
+    state: State,
+    frames: usize,
+    crc_errors: usize,
+}
+
+impl WmbusDecode {
+    /// Create new decoder.
+    pub fn new(src: Streamp<u8>, mode: Mode) -> Self {
+        Self {
+            src,
+            dst: new_nocopy_streamp(),
+            mode,
+            shift: 0,
+            state: State::Hunt,
+            frames: 0,
+            crc_errors: 0,
+        }
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> NoCopyStreamp<WmbusFrame> {
+        self.dst.clone()
+    }
+
+    fn sync(&self) -> Option<Format> {
+        match self.mode {
+            Mode::S if self.shift & 0xff_ffff == 0x54_7696 => Some(Format::A),
+            Mode::T if self.shift & 0xf_ffff == 0x5_543d => Some(Format::A),
+            Mode::C if self.shift & 0xffff_ffff == 0x543d_54cd => Some(Format::A),
+            Mode::C if self.shift & 0xffff_ffff == 0x543d_543d => Some(Format::B),
+            _ => None,
+        }
+    }
+
+    fn decode_byte(&self, chips: u64) -> Option<u8> {
+        match self.mode {
+            Mode::S => decode_manchester(chips as u16),
+            Mode::T => decode_3of6(chips as u16),
+            Mode::C => Some(chips as u8),
+        }
+    }
+
+    fn process_one(&mut self, chip: u8) -> Option<WmbusFrame> {
+        self.shift = (self.shift << 1) | (chip & 1) as u64;
+        let State::Frame {
+            format,
+            chips,
+            nchips,
+            ..
+        } = &mut self.state
+        else {
+            if let Some(format) = self.sync() {
+                trace!("WmbusDecode: sync, format {format:?}");
+                self.state = State::Frame {
+                    format,
+                    chips: 0,
+                    nchips: 0,
+                    bytes: Vec::new(),
+                };
+            }
+            return None;
+        };
+        *chips = (*chips << 1) | (chip & 1) as u64;
+        *nchips += 1;
+        if *nchips < self.mode.chips_per_byte() {
+            return None;
+        }
+        let (format, c) = (*format, *chips);
+        let Some(b) = self.decode_byte(c) else {
+            trace!("WmbusDecode: coding error");
+            self.state = State::Hunt;
+            return None;
+        };
+        let State::Frame {
+            chips,
+            nchips,
+            bytes,
+            ..
+        } = &mut self.state
+        else {
+            unreachable!()
+        };
+        *chips = 0;
+        *nchips = 0;
+        bytes.push(b);
+        if bytes.len() < 11 || bytes.len() < frame_len(bytes[0], format) {
+            return None;
+        }
+        let raw = std::mem::take(bytes);
+        self.state = State::Hunt;
+        let Some(data) = check_blocks(&raw, format) else {
+            debug!("WmbusDecode: CRC error");
+            self.crc_errors += 1;
+            return None;
+        };
+        WmbusFrame::parse(self.mode, format, &data)
+    }
+}
+
+impl Drop for WmbusDecode {
+    fn drop(&mut self) {
+        info!(
+            "WmbusDecode: {} frames, {} CRC errors",
+            self.frames, self.crc_errors
+        );
+    }
+}
+
+impl Block for WmbusDecode {
+    fn block_name(&self) -> &str {
+        "WmbusDecode"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let ibind = self.src.clone();
+        let (input, _tags) = ibind.read_buf()?;
+        if input.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        for chip in input.iter() {
+            if let Some(f) = self.process_one(*chip) {
+                debug!(
+                    "WmbusDecode: {} {:08x} type {:02x} CI {:02x}, {} bytes",
+                    f.manufacturer,
+                    f.id,
+                    f.device_type,
+                    f.ci,
+                    f.payload.len()
+                );
+                self.frames += 1;
+                self.dst.push(f, &[]);
+            }
+        }
+        let n = input.len();
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::streamp_from_slice;
+
+    // A frame from a Kamstrup water meter, ID 12345678, with
+    // `extra` bytes of dummy payload.
+    fn frame(extra: usize) -> Vec<u8> {
+        let mut f = vec![
+            0, 0x44, 0x2d, 0x2c, 0x78, 0x56, 0x34, 0x12, 0x1b, 0x07, 0x7a,
+        ];
+        f.extend((0..extra).map(|n| n as u8));
+        f[0] = (f.len() - 1) as u8;
+        f
+    }
+
+    fn add_crcs(data: &[u8], format: Format) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut add = |block: &[u8]| {
+            out.extend(block);
+            out.extend(crc(block).to_be_bytes());
+        };
+        match format {
+            Format::A => {
+                add(&data[..10]);
+                data[10..].chunks(16).for_each(add);
+            }
+            Format::B => {
+                let n = data.len().min(126);
+                add(&data[..n]);
+                if data.len() > n {
+                    add(&data[n..]);
+                }
+            }
+        }
+        out
+    }
+
+    fn chips(v: u64, n: usize) -> impl Iterator<Item = u8> {
+        (0..n).rev().map(move |i| ((v >> i) & 1) as u8)
+    }
+
+    fn modulate(mode: Mode, format: Format, data: &[u8]) -> Vec<u8> {
+        // L field counts the CRCs in format B.
+        let mut data = data.to_vec();
+        if format == Format::B {
+            data[0] += 2 * (1 + (data.len() > 126) as u8);
+        }
+        let raw = add_crcs(&data, format);
+        assert_eq!(raw.len(), frame_len(raw[0], format));
+        let mut out: Vec<u8> = chips(0x5555_5555_5555, 48).collect();
+        match mode {
+            Mode::S => out.extend(chips(0x547696, 24)),
+            Mode::T => out.extend(chips(0x543d, 16)),
+            Mode::C => {
+                out.extend(chips(0x543d, 16));
+                out.extend(chips(if format == Format::A { 0x54cd } else { 0x543d }, 16));
+            }
+        }
+        for b in raw {
+            let (v, n) = match mode {
+                Mode::S => (encode_manchester(b) as u64, 16),
+                Mode::T => (encode_3of6(b) as u64, 12),
+                Mode::C => (b as u64, 8),
+            };
+            out.extend(chips(v, n));
+        }
+        out.extend(chips(0x5555, 16));
+        out
+    }
+
+    fn decode(mode: Mode, chips: &[u8]) -> Result<Vec<WmbusFrame>> {
+        let mut b = WmbusDecode::new(streamp_from_slice(chips), mode);
+        b.work()?;
+        let out = b.out();
+        let mut ret = Vec::new();
+        while let Some((f, _)) = out.pop() {
+            ret.push(f);
+        }
+        Ok(ret)
+    }
+
+    #[test]
+    fn codings() {
+        for b in 0..=255 {
+            assert_eq!(decode_3of6(encode_3of6(b)), Some(b));
+            assert_eq!(decode_manchester(encode_manchester(b)), Some(b));
+            // All 3-of-6 codes have three ones.
+            assert_eq!(encode_3of6(b).count_ones(), 6);
+        }
+        assert_eq!(decode_3of6(0xfff), None);
+        assert_eq!(decode_manchester(0xffff), None);
+        assert_eq!(manufacturer(0x2c2d), "KAM");
+    }
+
+    #[test]
+    fn modes() -> Result<()> {
+        for (mode, format, extra) in [
+            (Mode::T, Format::A, 20),
+            (Mode::S, Format::A, 5),
+            (Mode::C, Format::A, 16),
+            (Mode::C, Format::B, 30),
+            (Mode::C, Format::B, 150),
+        ] {
+            let data = frame(extra);
+            let got = decode(mode, &modulate(mode, format, &data))?;
+            assert_eq!(got.len(), 1, "{mode:?} {format:?}");
+            let f = &got[0];
+            assert_eq!(f.format, format);
+            assert_eq!(f.manufacturer, "KAM");
+            assert_eq!(format!("{:08x}", f.id), "12345678");
+            assert_eq!(f.device_type, 0x07);
+            assert_eq!(f.ci, 0x7a);
+            assert_eq!(f.payload, data[11..], "{mode:?} {format:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn crc_error() -> Result<()> {
+        let mut chips = modulate(Mode::C, Format::A, &frame(10));
+        let n = chips.len() - 60;
+        chips[n] ^= 1;
+        assert!(decode(Mode::C, &chips)?.is_empty());
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@
 use anyhow::Result;
 
 use crate::block::{Block, BlockRet};
-use crate::stream::{new_streamp, Streamp};
+use crate::stream::{new_streamp, Streamp, Tag};
 use crate::{Error, Float};
 
 /** Very simple clock recovery by looking at zero crossings.
@@ -20,17 +20,21 @@ looping.
 
 But for now it's "good enough" to get simple 2FSK decoded pretty
 reliably.
+
+Input tags are moved to the next output symbol.
 */
 pub struct ZeroCrossing {
     sps: Float,
     max_deviation: Float,
     clock: Float,
     last_sign: bool,
-    last_cross: f32,
+    last_cross: Float,
     counter: u64,
     src: Streamp<Float>,
     dst: Streamp<Float>,
     out_clock: Option<Streamp<Float>>,
+    // Input tags, waiting for the next output sample.
+    tags: Vec<Tag>,
 }
 
 impl ZeroCrossing {
@@ -52,6 +56,7 @@ impl ZeroCrossing {
             last_cross: 0.0,
             counter: 0,
             out_clock: None,
+            tags: Vec::new(),
         }
     }
 
@@ -71,7 +76,7 @@ impl Block for ZeroCrossing {
         "ZeroCrossing"
     }
     fn work(&mut self) -> Result<BlockRet, Error> {
-        let (input, _tags) = self.src.read_buf()?;
+        let (input, mut tags) = self.src.read_buf()?;
         if input.is_empty() {
             return Ok(BlockRet::Noop);
         }
@@ -83,13 +88,21 @@ impl Block for ZeroCrossing {
         let mut opos = 0;
         // TODO: get rid of unwrap.
         let mut out_clock = self.out_clock.as_mut().map(|x| x.write_buf().unwrap());
+        // Tags are moved to the next output sample.
+        tags.sort_by_key(|t| t.pos());
+        let mut tags = tags.into_iter().peekable();
+        let mut otags = Vec::new();
         for sample in input.iter() {
             n += 1;
+            while let Some(t) = tags.next_if(|t| t.pos() < n) {
+                self.tags.push(t);
+            }
             if self.counter == (self.last_cross + (self.clock / 2.0)) as u64 {
                 o.slice()[opos] = *sample;
                 if let Some(ref mut s) = out_clock {
                     s.slice()[opos] = self.clock;
                 }
+                otags.extend(self.tags.drain(..).map(|t| t.with_pos(opos)));
                 opos += 1;
                 self.last_cross += self.clock;
                 if opos == o.len() {
@@ -99,7 +112,7 @@ impl Block for ZeroCrossing {
 
             let sign = *sample > 0.0;
             if sign != self.last_sign {
-                self.last_cross = self.counter as f32;
+                self.last_cross = self.counter as Float;
                 // TODO: adjust clock, within sps. Here just shut up the linter.
                 self.sps *= 1.0;
                 self.max_deviation *= 1.0;
@@ -110,14 +123,43 @@ impl Block for ZeroCrossing {
             let step_back = (10.0 * self.clock) as u64;
             if self.counter > step_back && self.last_cross as u64 > step_back {
                 self.counter -= step_back;
-                self.last_cross -= step_back as f32;
+                self.last_cross -= step_back as Float;
             }
         }
         input.consume(n);
-        o.produce(opos, &[]);
+        o.produce(opos, &otags);
         if let Some(s) = out_clock {
             s.produce(opos, &[]);
         }
         Ok(BlockRet::Ok)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::TagValue;
+
+    #[test]
+    fn tags() -> crate::Result<()> {
+        // Symbols of 4 samples, alternating.
+        let input: Vec<Float> = (0..40)
+            .map(|n| if (n / 4) % 2 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        let src = new_streamp();
+        {
+            let mut o = src.write_buf()?;
+            o.fill_from_slice(&input);
+            o.produce(input.len(), &[Tag::new(9, "x".into(), TagValue::U64(1))]);
+        }
+        let mut b = ZeroCrossing::new(src, 4.0, 0.1);
+        b.work()?;
+        let o = b.out();
+        let (res, tags) = o.read_buf()?;
+        assert_eq!(res.len(), 10);
+        // Symbols are sampled at 2, 6, 10, …, so the tag moves to the
+        // third one.
+        assert_eq!(tags, vec![Tag::new(2, "x".into(), TagValue::U64(1))]);
+        Ok(())
+    }
+}
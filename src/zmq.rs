@@ -0,0 +1,519 @@
+/*! ZeroMQ PUB/SUB blocks.
+
+Interoperate with GNU Radio's ZMQ PUB Sink and ZMQ SUB Source, so that
+rustradio can be one process in a multi-process GNU Radio pipeline.
+
+Messages are raw samples, as GNU Radio sends them with "pass tags"
+off. A message from a GNU Radio sink with a key set has the key as
+the first part, and the samples as the last part. Only the last part
+is used.
+
+Like in GNU Radio, [ZmqPubSink] binds and [ZmqSubSource] connects.
+Only `tcp://` endpoints are supported, with `*` as the host meaning
+any address. Only the parts of [ZMTP
+3.0] needed for PUB/SUB with the NULL mechanism are implemented.
+
+Unlike a real ZeroMQ PUB socket, a slow subscriber slows down the
+sink, instead of losing messages.
+
+Frames longer than a [maximum
+size](ZmqSubSourceBuilder::max_frame_size), by default 4MiB, are
+treated as an error, so that a broken peer can't make the process
+run out of memory.
+
+```no_run
+use rustradio::blocks::{ZmqPubSink, ZmqSubSource};
+use rustradio::Complex;
+// Receive from a GNU Radio ZMQ PUB Sink on port 5555.
+let src = ZmqSubSource::<Complex>::new("tcp://127.0.0.1:5555")?;
+// Publish for a GNU Radio ZMQ SUB Source connecting to port 5556.
+let sink = ZmqPubSink::new(src.out(), "tcp://0.0.0.0:5556")?;
+# Ok::<(), rustradio::Error>(())
+```
+
+[ZMTP 3.0]: https://rfc.zeromq.org/spec/23/
+*/
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::sync::mpsc::TryRecvError;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+
+use crate::block::{Block, BlockRet};
+use crate::stream::{new_streamp, Streamp};
+use crate::{Error, Sample};
+
+const MORE: u8 = 0x01;
+const LONG: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_MESSAGES_IN_FLIGHT: usize = 1000;
+const DEFAULT_MAX_FRAME_SIZE: usize = 4 << 20;
+
+// Turn a `tcp://host:port` endpoint into `host:port`, with `*` as
+// any address.
+fn parse_endpoint(endpoint: &str) -> Result<String, Error> {
+    let Some(addr) = endpoint.strip_prefix("tcp://") else {
+        return Err(Error::BadParameter(format!(
+            "ZMQ: only tcp:// endpoints are supported, got {endpoint}"
+        )));
+    };
+    Ok(match addr.strip_prefix("*:") {
+        Some(port) => format!("0.0.0.0:{port}"),
+        None => addr.to_string(),
+    })
+}
+
+fn frame(flags: u8, body: &[u8]) -> Vec<u8> {
+    let mut v = Vec::with_capacity(body.len() + 9);
+    if body.len() > 255 {
+        v.push(flags | LONG);
+        v.extend((body.len() as u64).to_be_bytes());
+    } else {
+        v.push(flags);
+        v.push(body.len() as u8);
+    }
+    v.extend(body);
+    v
+}
+
+// Read one frame of at most `max` bytes, returning flags and body.
+fn read_frame(r: &mut dyn Read, max: usize) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut flags = [0u8; 1];
+    r.read_exact(&mut flags)?;
+    let len = if flags[0] & LONG != 0 {
+        let mut b = [0u8; 8];
+        r.read_exact(&mut b)?;
+        u64::from_be_bytes(b)
+    } else {
+        let mut b = [0u8; 1];
+        r.read_exact(&mut b)?;
+        b[0].into()
+    };
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|&l| l <= max)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("ZMQ: frame of {len} bytes is longer than the max {max}"),
+            )
+        })?;
+    let mut body = vec![0u8; len];
+    r.read_exact(&mut body)?;
+    Ok((flags[0], body))
+}
+
+// Split a command body into name and data.
+fn command(body: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = *body.first()? as usize;
+    (body.len() > len).then(|| (&body[1..=len], &body[len + 1..]))
+}
+
+// Exchange greetings and READY commands.
+fn handshake(stream: &mut TcpStream, socket_type: &str, peer_type: &str) -> Result<(), Error> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut greeting = vec![0xff, 0, 0, 0, 0, 0, 0, 0, 1, 0x7f, 3, 0];
+    greeting.extend(b"NULL");
+    greeting.resize(64, 0);
+    stream.write_all(&greeting)?;
+    let mut peer = [0u8; 64];
+    stream.read_exact(&mut peer)?;
+    if peer[0] != 0xff || peer[9] != 0x7f || peer[10] < 3 {
+        return Err(Error::new("ZMQ: peer doesn't speak ZMTP 3"));
+    }
+    if !peer[12..32].starts_with(b"NULL\0") {
+        return Err(Error::new("ZMQ: only the NULL mechanism is supported"));
+    }
+
+    let mut ready = vec![5];
+    ready.extend(b"READY");
+    ready.push(11);
+    ready.extend(b"Socket-Type");
+    ready.extend((socket_type.len() as u32).to_be_bytes());
+    ready.extend(socket_type.as_bytes());
+    stream.write_all(&frame(COMMAND, &ready))?;
+
+    let (flags, body) = read_frame(stream, DEFAULT_MAX_FRAME_SIZE)?;
+    let Some((b"READY", mut props)) = command(&body).filter(|_| flags & COMMAND != 0) else {
+        return Err(Error::new("ZMQ: expected READY"));
+    };
+    // Properties are a one byte name length, the name, a four byte
+    // value length, and the value.
+    while let Some(&len) = props.first() {
+        let len = len as usize;
+        let value_start = 1 + len + 4;
+        if props.len() < value_start {
+            break;
+        }
+        let name = &props[1..=len];
+        let vlen = u32::from_be_bytes(props[len + 1..value_start].try_into().unwrap()) as usize;
+        let value = props.get(value_start..value_start + vlen).unwrap_or(&[]);
+        if name.eq_ignore_ascii_case(b"Socket-Type") && !peer_type.as_bytes().ends_with(value) {
+            return Err(Error::new(&format!(
+                "ZMQ: {socket_type} can't talk to {}",
+                String::from_utf8_lossy(value)
+            )));
+        }
+        props = props.get(value_start + vlen..).unwrap_or(&[]);
+    }
+    stream.set_read_timeout(None)?;
+    Ok(())
+}
+
+struct Subscriber {
+    stream: TcpStream,
+    peer: SocketAddr,
+    topics: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl Subscriber {
+    fn wants(&self, msg: &[u8]) -> bool {
+        self.topics
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|t| msg.starts_with(t))
+    }
+}
+
+// Read subscriptions from a subscriber, until it disconnects.
+fn read_subscriptions(mut stream: TcpStream, topics: Arc<Mutex<Vec<Vec<u8>>>>) {
+    loop {
+        let Ok((flags, body)) = read_frame(&mut stream, DEFAULT_MAX_FRAME_SIZE) else {
+            return;
+        };
+        let (subscribe, topic) = if flags & COMMAND != 0 {
+            match command(&body) {
+                Some((b"SUBSCRIBE", t)) => (true, t),
+                Some((b"CANCEL", t)) => (false, t),
+                _ => continue,
+            }
+        } else {
+            match body.split_first() {
+                Some((1, t)) => (true, t),
+                Some((0, t)) => (false, t),
+                _ => continue,
+            }
+        };
+        debug!("ZMQ: subscribe={subscribe} topic {topic:?}");
+        let mut topics = topics.lock().unwrap();
+        if subscribe {
+            topics.push(topic.to_vec());
+        } else if let Some(pos) = topics.iter().position(|t| t == topic) {
+            topics.remove(pos);
+        }
+    }
+}
+
+/// ZeroMQ PUB sink.
+pub struct ZmqPubSink<T: Copy> {
+    src: Streamp<T>,
+    addr: SocketAddr,
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl<T: Copy> ZmqPubSink<T> {
+    /// Create new ZMQ PUB sink, bound to e.g. `tcp://0.0.0.0:5555`.
+    pub fn new(src: Streamp<T>, endpoint: &str) -> Result<Self, Error> {
+        let listener = TcpListener::bind(parse_endpoint(endpoint)?)?;
+        let addr = listener.local_addr()?;
+        info!("ZMQ: publishing on {addr}");
+        let subscribers = Arc::new(Mutex::new(Vec::new()));
+        let subs = subscribers.clone();
+        thread::Builder::new()
+            .name("ZmqPubSink-listener".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let res = stream.map_err(Error::from).and_then(|mut stream| {
+                        let peer = stream.peer_addr()?;
+                        handshake(&mut stream, "PUB", "SUB")?;
+                        let topics = Arc::new(Mutex::new(Vec::new()));
+                        let reader = stream.try_clone()?;
+                        let t = topics.clone();
+                        thread::Builder::new()
+                            .name("ZmqPubSink-subscriber".to_string())
+                            .spawn(move || read_subscriptions(reader, t))?;
+                        info!("ZMQ: subscriber {peer} connected");
+                        subs.lock().unwrap().push(Subscriber {
+                            stream,
+                            peer,
+                            topics,
+                        });
+                        Ok(())
+                    });
+                    if let Err(e) = res {
+                        warn!("ZMQ: failed to accept subscriber: {e}");
+                    }
+                }
+            })?;
+        Ok(Self {
+            src,
+            addr,
+            subscribers,
+        })
+    }
+
+    /// Address bound to. Useful when binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl<T> Block for ZmqPubSink<T>
+where
+    T: Sample<Type = T> + Copy + std::fmt::Debug,
+{
+    fn block_name(&self) -> &str {
+        "ZmqPubSink"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let (input, _tags) = self.src.read_buf()?;
+        let n = input.len();
+        if n == 0 {
+            return Ok(BlockRet::Noop);
+        }
+        let msg: Vec<u8> = input.iter().flat_map(|s| s.serialize()).collect();
+        let framed = frame(0, &msg);
+        self.subscribers.lock().unwrap().retain_mut(|s| {
+            if !s.wants(&msg) {
+                return true;
+            }
+            match s.stream.write_all(&framed) {
+                Ok(()) => true,
+                Err(e) => {
+                    info!("ZMQ: subscriber {} gone: {e}", s.peer);
+                    false
+                }
+            }
+        });
+        input.consume(n);
+        Ok(BlockRet::Ok)
+    }
+}
+
+/// Builder for [ZmqSubSource].
+pub struct ZmqSubSourceBuilder<T> {
+    endpoint: String,
+    max_frame_size: usize,
+    _t: std::marker::PhantomData<T>,
+}
+
+impl<T: Copy + Default> ZmqSubSourceBuilder<T> {
+    /// Create new builder, connecting to e.g. `tcp://127.0.0.1:5555`.
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            _t: std::marker::PhantomData,
+        }
+    }
+
+    /// Max frame size in bytes. Longer frames are an error. Default
+    /// 4MiB.
+    pub fn max_frame_size(mut self, bytes: usize) -> Self {
+        self.max_frame_size = bytes;
+        self
+    }
+
+    /// Connect, and build the source block.
+    pub fn build(self) -> Result<ZmqSubSource<T>, Error> {
+        let Self {
+            endpoint,
+            max_frame_size,
+            ..
+        } = self;
+        let mut stream = TcpStream::connect(parse_endpoint(&endpoint)?)?;
+        handshake(&mut stream, "SUB", "PUB")?;
+        // Subscribe to all, as a ZMTP 3.0 subscription message.
+        stream.write_all(&frame(0, &[1]))?;
+        info!("ZMQ: subscribed to {endpoint}");
+
+        let (tx, rx) = mpsc::sync_channel(MAX_MESSAGES_IN_FLIGHT);
+        thread::Builder::new()
+            .name("ZmqSubSource-reader".to_string())
+            .spawn(move || {
+                loop {
+                    let (flags, body) = match read_frame(&mut stream, max_frame_size) {
+                        Ok(f) => f,
+                        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return,
+                        Err(e) => {
+                            let _ = tx.send(Err(e));
+                            return;
+                        }
+                    };
+                    if flags & COMMAND != 0 {
+                        continue;
+                    }
+                    // Earlier parts are the key, if any.
+                    if flags & MORE == 0 && tx.send(Ok(body)).is_err() {
+                        return;
+                    }
+                }
+            })?;
+        Ok(ZmqSubSource {
+            rx,
+            buf: Vec::new(),
+            dst: new_streamp(),
+        })
+    }
+}
+
+/// ZeroMQ SUB source, subscribing to everything.
+pub struct ZmqSubSource<T: Copy> {
+    rx: mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    buf: Vec<u8>,
+    dst: Streamp<T>,
+}
+
+impl<T: Copy + Default> ZmqSubSource<T> {
+    /// Create new ZMQ SUB source, connecting to e.g.
+    /// `tcp://127.0.0.1:5555`.
+    pub fn new(endpoint: &str) -> Result<Self, Error> {
+        ZmqSubSourceBuilder::new(endpoint).build()
+    }
+
+    /// Create a builder.
+    pub fn builder(endpoint: &str) -> ZmqSubSourceBuilder<T> {
+        ZmqSubSourceBuilder::new(endpoint)
+    }
+
+    /// Return the output stream.
+    pub fn out(&self) -> Streamp<T> {
+        self.dst.clone()
+    }
+}
+
+impl<T> Block for ZmqSubSource<T>
+where
+    T: Sample<Type = T> + Copy + std::fmt::Debug,
+{
+    fn block_name(&self) -> &str {
+        "ZmqSubSource"
+    }
+    fn work(&mut self) -> Result<BlockRet, Error> {
+        let size = T::size();
+        let mut o = self.dst.write_buf()?;
+        if o.is_empty() {
+            return Ok(BlockRet::Noop);
+        }
+        if self.buf.len() < size {
+            match self.rx.try_recv() {
+                Err(TryRecvError::Empty) => return Ok(BlockRet::Pending),
+                Err(TryRecvError::Disconnected) => {
+                    info!("ZMQ: publisher disconnected");
+                    return Ok(BlockRet::EOF);
+                }
+                Ok(msg) => self.buf.extend(msg?),
+            }
+        }
+        let n = std::cmp::min(o.len(), self.buf.len() / size);
+        if n == 0 {
+            return Ok(BlockRet::Ok);
+        }
+        let v = self.buf[..n * size]
+            .chunks_exact(size)
+            .map(T::parse)
+            .collect::<crate::Result<Vec<T>>>()?;
+        o.fill_from_iter(v);
+        o.produce(n, &[]);
+        self.buf.drain(..n * size);
+        Ok(BlockRet::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Float;
+
+    #[test]
+    fn endpoints() -> Result<(), Error> {
+        assert_eq!(parse_endpoint("tcp://*:5555")?, "0.0.0.0:5555");
+        assert_eq!(parse_endpoint("tcp://[::1]:5555")?, "[::1]:5555");
+        assert!(parse_endpoint("ipc:///tmp/foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn frames() -> Result<(), Error> {
+        assert_eq!(frame(0, b"hi"), [0, 2, b'h', b'i']);
+        let long = frame(MORE, &[7; 300]);
+        assert_eq!(long[..9], [MORE | LONG, 0, 0, 0, 0, 0, 0, 1, 44]);
+        let (flags, body) = read_frame(&mut &long[..], 300)?;
+        assert_eq!(flags, MORE | LONG);
+        assert_eq!(body, [7; 300]);
+        assert!(read_frame(&mut &long[..], 299).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn oversized_frame() {
+        // A length that can't be allocated, with no body behind it.
+        let mut huge = vec![LONG];
+        huge.extend(u64::MAX.to_be_bytes());
+        let e = read_frame(&mut &huge[..], DEFAULT_MAX_FRAME_SIZE).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        assert!(read_frame(&mut &frame(0, &[0; 10])[..], 9).is_err());
+    }
+
+    #[test]
+    fn oversized_message() -> Result<(), Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let server = thread::spawn(move || -> Result<(), Error> {
+            let (mut stream, _) = listener.accept()?;
+            handshake(&mut stream, "PUB", "SUB")?;
+            stream.write_all(&frame(0, &[0; 1001]))?;
+            // Wait for the subscriber to hang up.
+            let _ = stream.read(&mut [0; 16]);
+            Ok(())
+        });
+        let mut src = ZmqSubSource::<u8>::builder(&format!("tcp://{addr}"))
+            .max_frame_size(1000)
+            .build()?;
+        let mut res = src.work();
+        for _ in 0..1000 {
+            if !matches!(res, Ok(BlockRet::Pending)) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+            res = src.work();
+        }
+        assert!(res.is_err(), "{res:?}");
+        drop(src);
+        server.join().unwrap()
+    }
+
+    #[test]
+    fn pubsub() -> Result<(), Error> {
+        let data: Vec<Float> = vec![1.0, 2.0, 3.0];
+        let src = new_streamp();
+        let mut sink = ZmqPubSink::new(src.clone(), "tcp://127.0.0.1:0")?;
+        let mut source = ZmqSubSource::<Float>::new(&format!("tcp://{}", sink.local_addr()))?;
+        let out = source.out();
+        // Publish until the subscription has made it to the sink.
+        for _ in 0..1000 {
+            let mut w = src.write_buf()?;
+            w.fill_from_slice(&data);
+            w.produce(data.len(), &[]);
+            sink.work()?;
+            thread::sleep(Duration::from_millis(1));
+            source.work()?;
+            if !out.read_buf()?.0.is_empty() {
+                break;
+            }
+        }
+        source.work()?;
+        let (res, _) = out.read_buf()?;
+        assert!(res.len() >= 3);
+        assert_eq!(res.len() % 3, 0);
+        assert_eq!(res.slice()[..3], data);
+        Ok(())
+    }
+}